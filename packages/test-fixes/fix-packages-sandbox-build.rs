@@ -0,0 +1,6 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/sandbox.proto");
+    // Labels are kept sorted, as in the snapshot types
+    tonic_build::configure().btree_map(["."]).compile_protos(&["proto/sandbox.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,90 @@
+#ifndef SANDBOX_AGENT_H
+#define SANDBOX_AGENT_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Outcome of an FFI call
+ */
+typedef enum FfiStatus {
+  FFI_STATUS_OK = 0,
+  /**
+   * `sandbox_poll_event` found nothing queued
+   */
+  FFI_STATUS_NO_EVENT = 1,
+  /**
+   * A null pointer, non-UTF-8 string or invalid JSON was passed
+   */
+  FFI_STATUS_INVALID_ARGUMENT = 2,
+  /**
+   * The operation failed; the output string holds the error
+   */
+  FFI_STATUS_FAILED = 3,
+} FfiStatus;
+
+/**
+ * Opaque handle to a manager and the runtime it runs on, owned by the host
+ */
+typedef struct SandboxAgent SandboxAgent;
+
+/**
+ * Create a manager from a JSON `AutoPauseConfig`, or the defaults when `config_json`
+ * is null. Returns null if the config does not parse or the runtime cannot start.
+ *
+ * # Safety
+ * `config_json` must be null or a NUL-terminated string.
+ */
+struct SandboxAgent *sandbox_agent_new(const char *config_json);
+
+/**
+ * Shut the manager down and free it.
+ *
+ * # Safety
+ * `agent` must be null or a pointer from `sandbox_agent_new` not freed before.
+ */
+void sandbox_agent_free(struct SandboxAgent *agent);
+
+/**
+ * Pause a sandbox; `force` overrides its protection. On return `*out_json` holds the
+ * `PauseResult` as JSON, or the error message when the status is `Failed`.
+ *
+ * # Safety
+ * `agent` must come from `sandbox_agent_new`, `sandbox_id` must be a NUL-terminated
+ * string and `out_json` null or valid for writes.
+ */
+enum FfiStatus sandbox_pause(const struct SandboxAgent *agent, const char *sandbox_id, bool force, char **out_json);
+
+/**
+ * Resume a sandbox. On return `*out_json` holds the `ResumeReport` as JSON, or the
+ * error message when the status is `Failed`.
+ *
+ * # Safety
+ * Same as `sandbox_pause`.
+ */
+enum FfiStatus sandbox_resume(const struct SandboxAgent *agent, const char *sandbox_id, char **out_json);
+
+/**
+ * Take the next queued warning or alarm without blocking. Returns `NoEvent` when
+ * none is queued; otherwise `*out_json` holds the event tagged by `"type"`.
+ * Events raised before the manager was created, or dropped because the host polled
+ * too slowly, are not delivered.
+ *
+ * # Safety
+ * `agent` must come from `sandbox_agent_new` and `out_json` be null or valid for writes.
+ */
+enum FfiStatus sandbox_poll_event(const struct SandboxAgent *agent, char **out_json);
+
+/**
+ * Free a string returned through an `out_json` parameter.
+ *
+ * # Safety
+ * `value` must be null or a string from this library not freed before.
+ */
+void sandbox_string_free(char *value);
+
+#endif /* SANDBOX_AGENT_H */
@@ -0,0 +1,164 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+
+use crate::state_snapshot::PersistedProcess;
+
+/// Start times this close are taken to be the same process; /proc reports them in clock ticks
+const START_TIME_TOLERANCE_MS: i64 = 1000;
+
+/// Where survivors of a sandbox may be found: its cgroup and the login sessions its
+/// processes ran in. Processes outside both are never candidates.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxScope {
+    pub cgroup: Option<PathBuf>,
+    pub sessions: HashSet<i32>,
+}
+
+impl SandboxScope {
+    pub fn of(cgroup: &Path, persisted: &[PersistedProcess]) -> Self {
+        Self {
+            cgroup: Some(cgroup.to_path_buf()),
+            sessions: persisted.iter().filter_map(|p| p.session.as_ref().map(|session| session.session_id)).collect(),
+        }
+    }
+}
+
+/// A process of the sandbox, as seen when matching survivors
+#[derive(Debug, Clone)]
+pub struct LiveProcess {
+    pub pid: i32,
+    pub argv: Vec<String>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub uid: Option<u32>,
+}
+
+/// Processes in the scope's cgroup or sessions with a readable command line
+#[cfg(target_os = "linux")]
+pub fn live_processes(scope: &SandboxScope) -> Vec<LiveProcess> {
+    use crate::procfs;
+
+    let mut pids: BTreeSet<i32> = scope.cgroup.as_deref().and_then(procfs::cgroup_pids).unwrap_or_default().into_iter().collect();
+    if !scope.sessions.is_empty() {
+        if let Ok(entries) = std::fs::read_dir("/proc") {
+            pids.extend(
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().to_string_lossy().parse::<i32>().ok())
+                    .filter(|&pid| procfs::read_stat(pid).is_some_and(|stat| scope.sessions.contains(&stat.session))),
+            );
+        }
+    }
+    pids.into_iter()
+        .filter_map(|pid| {
+            let argv = procfs::read_argv(pid).filter(|argv| !argv.is_empty())?;
+            let start_time = procfs::read_stat(pid).and_then(|stat| procfs::start_time(&stat));
+            Some(LiveProcess { pid, argv, start_time, uid: procfs::read_uid(pid) })
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn live_processes(_scope: &SandboxScope) -> Vec<LiveProcess> {
+    Vec::new()
+}
+
+/// Real UID of a process, recorded so survivors can be checked against it
+#[cfg(target_os = "linux")]
+pub fn process_uid(pid: i32) -> Option<u32> {
+    crate::procfs::read_uid(pid)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_uid(_pid: i32) -> Option<u32> {
+    None
+}
+
+/// Map snapshot entries in `unmatched` (by recorded PID) to live processes that are the
+/// same program under a new PID, e.g. after a partial kill or a restart of the freezer.
+///
+/// A live process matches an entry when its argv and UID are the recorded ones and it
+/// started between the entry's recorded start and `snapshot_at`, so neither an older
+/// process nor anything launched since the pause is taken over. Entries without a
+/// recorded UID are never matched. When several match, the one whose start time is
+/// closest to the recorded start time wins. Live PIDs in `claimed` already belong to
+/// verified entries and each live process is adopted once.
+pub fn match_survivors(persisted: &[PersistedProcess], unmatched: &HashSet<i32>, snapshot_at: DateTime<Utc>, claimed: &HashSet<i32>, live: &[LiveProcess]) -> HashMap<i32, i32> {
+    let mut taken = claimed.clone();
+    let mut adopted = HashMap::new();
+    for entry in persisted.iter().filter(|p| unmatched.contains(&p.pid)) {
+        let Some(uid) = entry.uid else { continue };
+        let argv: Vec<String> = if entry.argv.is_empty() {
+            entry.cmd.split_whitespace().map(str::to_string).collect()
+        } else {
+            entry.argv.clone()
+        };
+        if argv.is_empty() {
+            continue;
+        }
+        let earliest = entry.start_time - chrono::Duration::milliseconds(START_TIME_TOLERANCE_MS);
+        let best = live
+            .iter()
+            .filter(|candidate| !taken.contains(&candidate.pid) && candidate.uid == Some(uid) && candidate.argv == argv)
+            .filter(|candidate| candidate.start_time.is_some_and(|started| started >= earliest && started <= snapshot_at))
+            .min_by_key(|candidate| {
+                let distance = candidate.start_time.map(|started| (started - entry.start_time).num_milliseconds().abs()).unwrap_or(i64::MAX);
+                // Within the tolerance every candidate is as good as the recorded start time
+                (distance.max(START_TIME_TOLERANCE_MS), candidate.pid)
+            });
+        if let Some(candidate) = best {
+            taken.insert(candidate.pid);
+            adopted.insert(entry.pid, candidate.pid);
+        }
+    }
+    adopted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use crate::test_support::persisted_process as entry;
+
+    fn live(pid: i32, argv: &[&str], started: DateTime<Utc>) -> LiveProcess {
+        LiveProcess {
+            pid,
+            argv: argv.iter().map(|a| a.to_string()).collect(),
+            start_time: Some(started),
+            uid: Some(1000),
+        }
+    }
+
+    #[test]
+    fn test_match_by_argv_and_start_time() {
+        let base = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let paused_at = base + Duration::hours(1);
+        let persisted = vec![
+            entry(10, &["python", "worker.py", "--queue", "a"], base),
+            entry(11, &["python", "worker.py", "--queue", "a"], base + Duration::minutes(5)),
+            entry(12, &["redis-server"], base),
+        ];
+        let unmatched: HashSet<i32> = [10, 11, 12].into();
+        let candidates = vec![
+            live(200, &["python", "worker.py", "--queue", "a"], base + Duration::minutes(5)),
+            live(201, &["python", "worker.py", "--queue", "a"], base),
+            // Started after the pause: a fresh process, not a survivor
+            live(202, &["redis-server"], paused_at + Duration::minutes(1)),
+            // Already owned by a verified entry
+            live(203, &["redis-server"], base),
+            // Same program, but another user's
+            LiveProcess { uid: Some(0), ..live(204, &["redis-server"], base) },
+            // Older than the entry it would replace
+            live(205, &["redis-server"], base - Duration::hours(1)),
+        ];
+        let claimed: HashSet<i32> = [203].into();
+
+        let adopted = match_survivors(&persisted, &unmatched, paused_at, &claimed, &candidates);
+        assert_eq!(adopted, HashMap::from([(10, 201), (11, 200)]));
+
+        // Without a recorded UID nothing can be verified, so nothing is adopted
+        let unknown_user = vec![PersistedProcess { uid: None, ..entry(13, &["sleeper"], base) }];
+        let sleeper = vec![live(206, &["sleeper"], base)];
+        assert!(match_survivors(&unknown_user, &[13].into(), paused_at, &HashSet::new(), &sleeper).is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::events::{EventBus, EventPayload};
+
+/// Operation phase an SLO applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmPhase {
+    Pause,
+    Resume,
+    SnapshotSave,
+}
+
+/// Upper bounds operators expect each phase to finish within; unset means no alarm
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SloThresholds {
+    pub pause_max_ms: Option<u64>,
+    pub resume_max_ms: Option<u64>,
+    pub snapshot_save_max_ms: Option<u64>,
+}
+
+impl SloThresholds {
+    fn limit(&self, phase: AlarmPhase) -> Option<Duration> {
+        let ms = match phase {
+            AlarmPhase::Pause => self.pause_max_ms,
+            AlarmPhase::Resume => self.resume_max_ms,
+            AlarmPhase::SnapshotSave => self.snapshot_save_max_ms,
+        };
+        ms.map(Duration::from_millis)
+    }
+}
+
+/// Durations of the individual steps of an operation, in milliseconds
+pub type PhaseTimings = BTreeMap<String, u64>;
+
+/// Raised when an operation breaches its configured threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alarm {
+    pub sandbox_id: String,
+    pub phase: AlarmPhase,
+    pub elapsed_ms: u64,
+    pub threshold_ms: u64,
+    pub phase_timings: PhaseTimings,
+    pub timestamp: DateTime<Utc>,
+    /// Raised by the watchdog while the operation is still running, rather than after it finished
+    #[serde(default)]
+    pub stuck: bool,
+    /// Set when the operations of every sandbox from this template regressed, rather than one operation
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// Compares operation durations against SLO thresholds and broadcasts alarms
+pub struct AlarmMonitor {
+    thresholds: SloThresholds,
+    sender: broadcast::Sender<Alarm>,
+    events: Option<EventBus>,
+}
+
+impl AlarmMonitor {
+    pub fn new(thresholds: SloThresholds) -> Self {
+        let (sender, _) = broadcast::channel(64);
+        Self { thresholds, sender, events: None }
+    }
+
+    /// Also publish every alarm as an event
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Receive every alarm raised from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Alarm> {
+        self.sender.subscribe()
+    }
+
+    /// Raise an alarm if `elapsed` exceeds the threshold for `phase`
+    pub fn check(&self, sandbox_id: &str, phase: AlarmPhase, elapsed: Duration, phase_timings: &PhaseTimings) -> Option<Alarm> {
+        let limit = self.thresholds.limit(phase)?;
+        if elapsed <= limit {
+            return None;
+        }
+
+        let alarm = Alarm {
+            sandbox_id: sandbox_id.to_string(),
+            phase,
+            elapsed_ms: elapsed.as_millis() as u64,
+            threshold_ms: limit.as_millis() as u64,
+            phase_timings: phase_timings.clone(),
+            timestamp: Utc::now(),
+            stuck: false,
+            template: None,
+        };
+        warn!(
+            "{:?} of sandbox {} took {}ms, over the {}ms threshold",
+            phase, sandbox_id, alarm.elapsed_ms, alarm.threshold_ms
+        );
+        self.raise(alarm.clone());
+        Some(alarm)
+    }
+
+    /// Publish an alarm raised outside `check`
+    pub fn raise(&self, alarm: Alarm) {
+        if let Some(events) = &self.events {
+            events.publish(&alarm.sandbox_id, EventPayload::Alarm(alarm.clone()));
+        }
+        // No subscribers is fine; callers log the alarm themselves
+        let _ = self.sender.send(alarm);
+    }
+}
+
+/// Forwards alarms to an HTTP endpoint as JSON
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Deliver alarms from `alarms` until the monitor is dropped
+    pub fn spawn(self, mut alarms: broadcast::Receiver<Alarm>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match alarms.recv().await {
+                    Ok(alarm) => self.deliver(&alarm).await,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Alarm webhook fell behind, dropped {} alarms", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    async fn deliver(&self, alarm: &Alarm) {
+        let result = self.client.post(&self.url).json(alarm).send().await;
+        match result.and_then(|res| res.error_for_status()) {
+            Ok(_) => {}
+            Err(e) => error!("Failed to deliver alarm for sandbox {} to {}: {}", alarm.sandbox_id, self.url, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alarm_raised_only_when_threshold_breached() {
+        let monitor = AlarmMonitor::new(SloThresholds {
+            pause_max_ms: Some(1000),
+            ..Default::default()
+        });
+        let mut alarms = monitor.subscribe();
+        let timings = PhaseTimings::from([("kill".to_string(), 1500)]);
+
+        assert!(monitor.check("sb", AlarmPhase::Pause, Duration::from_millis(900), &timings).is_none());
+        assert!(monitor.check("sb", AlarmPhase::Resume, Duration::from_secs(60), &timings).is_none());
+
+        monitor.check("sb", AlarmPhase::Pause, Duration::from_millis(1500), &timings).unwrap();
+        let alarm = alarms.try_recv().unwrap();
+        assert_eq!(alarm.phase, AlarmPhase::Pause);
+        assert_eq!(alarm.threshold_ms, 1000);
+        assert_eq!(alarm.phase_timings["kill"], 1500);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::sync::oneshot;
+
+use crate::error::SandboxError;
+use crate::operations::OperationId;
+use crate::registry::PriorityClass;
+
+/// Which force-kill pauses need a second person's approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    pub enabled: bool,
+    /// Priority classes whose kill pauses wait for approval (default: critical)
+    pub priorities: Vec<PriorityClass>,
+    /// How long a pause waits before it is given up (default: 300s)
+    pub timeout_secs: u64,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priorities: vec![PriorityClass::Critical],
+            timeout_secs: 300,
+        }
+    }
+}
+
+impl ApprovalConfig {
+    pub fn requires_approval(&self, priority: PriorityClass) -> bool {
+        self.enabled && self.priorities.contains(&priority)
+    }
+}
+
+/// Step of an approval, as published in events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    Requested,
+    Approved,
+    TimedOut,
+}
+
+/// An operation waiting for approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingApproval {
+    pub operation_id: OperationId,
+    pub sandbox_id: String,
+    /// Who asked for the operation, when known
+    #[serde(default)]
+    pub requested_by: Option<String>,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Operations held until someone approves them
+#[derive(Default)]
+pub struct ApprovalGate {
+    pending: Mutex<HashMap<OperationId, (PendingApproval, oneshot::Sender<String>)>>,
+}
+
+impl ApprovalGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `operation_id`; the receiver gets the approver's name once it is approved
+    pub fn request(&self, operation_id: OperationId, sandbox_id: &str, requested_by: Option<&str>) -> oneshot::Receiver<String> {
+        let (tx, rx) = oneshot::channel();
+        let pending = PendingApproval {
+            operation_id,
+            sandbox_id: sandbox_id.to_string(),
+            requested_by: requested_by.map(String::from),
+            requested_at: Utc::now(),
+        };
+        self.pending.lock().unwrap().insert(operation_id, (pending, tx));
+        rx
+    }
+
+    /// Release a held operation; None if it is not waiting for approval. The requester
+    /// approving their own operation is refused and leaves it waiting.
+    pub fn approve(&self, operation_id: OperationId, approver: &str) -> Result<Option<PendingApproval>, SandboxError> {
+        let mut all = self.pending.lock().unwrap();
+        let Some((pending, _)) = all.get(&operation_id) else {
+            return Ok(None);
+        };
+        if pending.requested_by.as_deref() == Some(approver) {
+            return Err(SandboxError::PermissionDenied(format!("{} requested operation {} and cannot approve it", approver, operation_id)));
+        }
+        let Some((pending, tx)) = all.remove(&operation_id) else {
+            return Ok(None);
+        };
+        // The operation gave up in the meantime when the receiver is gone
+        Ok(tx.send(approver.to_string()).ok().map(|()| pending))
+    }
+
+    /// Stop holding an operation that timed out or was cancelled
+    pub fn withdraw(&self, operation_id: OperationId) {
+        self.pending.lock().unwrap().remove(&operation_id);
+    }
+
+    /// Operations waiting for approval, oldest first
+    pub fn pending(&self) -> Vec<PendingApproval> {
+        let mut pending: Vec<PendingApproval> = self.pending.lock().unwrap().values().map(|(pending, _)| pending.clone()).collect();
+        pending.sort_by_key(|pending| pending.operation_id);
+        pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_approve_releases_operation_once() {
+        let gate = ApprovalGate::new();
+        let approved = gate.request(7, "sbx-prod", Some("carol"));
+        let abandoned = gate.request(8, "sbx-other", None);
+        assert_eq!(gate.pending().iter().map(|p| p.operation_id).collect::<Vec<_>>(), [7, 8]);
+
+        assert!(gate.approve(7, "carol").is_err());
+        assert_eq!(gate.approve(7, "alice").unwrap().map(|p| p.sandbox_id), Some("sbx-prod".to_string()));
+        assert_eq!(approved.await.unwrap(), "alice");
+        assert!(gate.approve(7, "bob").unwrap().is_none());
+
+        drop(abandoned);
+        assert!(gate.approve(8, "bob").unwrap().is_none());
+        assert!(gate.pending().is_empty());
+    }
+}
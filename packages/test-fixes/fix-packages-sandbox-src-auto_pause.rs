@@ -1,23 +1,202 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
-use std::time::Duration;
-use tokio::time::timeout;
-use nix::sys::signal::{self, Signal};
-use nix::unistd::Pid;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use serde::{Serialize, Deserialize};
-use log::{info, warn, error};
+use log::{error, info, warn};
 
-use crate::process::{ProcessInfo, ProcessManager};
-use crate::state_snapshot::{StateSnapshot, PersistedProcess};
-use crate::persistence::PersistenceManager;
+use crate::adoption::{self, SandboxScope};
+use crate::alarms::{Alarm, AlarmMonitor, AlarmPhase, PhaseTimings, SloThresholds};
+use crate::approval::{ApprovalConfig, ApprovalDecision, ApprovalGate, PendingApproval};
+use crate::backend::{self, GroupSignal, ProcessBackend};
+use crate::blocking;
+use crate::chaos::{Chaos, ChaosBackend, ChaosConfig};
+use crate::cgroup::{Cgroup, CgroupLimits, SoftPauseConfig};
+use crate::cost_estimate::{self, StrategyCost};
+use crate::crash_dump::CrashDump;
+use crate::diagnostics::{Diagnostics, LockDiagnostics, LockState, QueueDiagnostics, SandboxDiagnostics, TaskRegistry};
+use crate::error::SandboxError;
+use crate::event_sinks::{self, EventSinkConfig};
+use crate::events::{Event, EventBus, EventPayload, EventReplay, EventReplayConfig};
+use crate::feature_flags::{Feature, FeatureFlags, FeatureFlagsConfig};
+use crate::history::{HistoryConfig, HistoryStore};
+use crate::hooks::{HookErrorPolicy, HookFailure, HookPhase, HookRegistry, RegisteredHook, ScriptHookConfig};
+use crate::instance_lock::{InstanceConflict, InstanceMode, LockOwner};
+use crate::integrity::IntegrityReport;
+use crate::launch_hints::{self, LaunchContext};
+use crate::lifecycle::{LifecycleRecord, LifecycleState};
+use crate::notifications::{NotificationConfig, Notifier};
+use crate::object_store::S3Store;
+use crate::operations::{OperationGuard, OperationId, OperationInfo, OperationKind, OperationTracker};
+use crate::probes::{self, ProbeConfig, ProbeReport, ProbeStatus};
+use crate::process::{OomEvent, ProcessInfo, ProcessLimits, ProcessManager, ProcessState};
+use crate::state_snapshot::{FdSummary, FileLock, SnapshotCaps, StateSnapshot, PersistedProcess};
+use crate::persistence::{self, PersistenceManager, SnapshotLoad, StorageFullPolicy};
+use crate::policy::{PolicyAction, PolicyEngine, PolicyRule, ProcessRule};
+use crate::process_tree::{self, TreeFormat, TreeNode};
+use crate::protection;
+use crate::registry::SandboxRegistry;
+use crate::restore_checkpoint::{RestoreCheckpoint, RestoreStep};
+use crate::restore_order;
+use crate::resume_banner::{self, ResumeBanner, ResumeBannerConfig};
+use crate::runtime_metrics::RuntimeStats;
+use crate::sandbox_channel::SandboxChannel;
+use crate::sandbox_id::SandboxId;
+use crate::scheduled_jobs::{self, SandboxRoot, ScheduledJobsConfig, ScheduledJobsRestore};
+use crate::selector::Selector;
+use crate::sessions;
+use crate::startup_recovery::{StartupGate, StartupRecoveryConfig};
+use crate::stats::{StatsStore, TimelineEvent, TimelinePoint};
+use crate::store_migration::RemoteMigrationConfig;
+use crate::strategy_select::{self, AutoStrategyConfig, StrategyDecision, Workload};
+use crate::systemd_user::{self, SystemdUnitsRestore, SystemdUserConfig};
+use crate::template_anomaly::{TemplateAnomalyConfig, TemplateMetrics, TemplateMonitor};
+use crate::tenant_quotas::{QuotaPermit, TenantQuotas, TenantQuotasConfig};
+use crate::throttle::Throttle;
+use crate::usage_stats::{UsageStats, UsageStatsConfig};
+use crate::warnings::{Warning, WarningKind, WarningSink};
+use crate::wasm_plugins::{PluginHook, PluginHost, WasmPluginConfig};
+use crate::watchdog::WatchdogConfig;
+
+/// What pausing does to a sandbox's processes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseStrategy {
+    /// Terminate processes; nothing is restored on resume
+    Kill,
+    /// Leave processes to the VM snapshot and persist their state for verification on resume
+    Persist,
+    /// Keep processes running but clamp the sandbox cgroup's CPU (and IO) to a trickle
+    Throttle,
+    /// Pick one of the above per sandbox when its pause is prepared, from its workload
+    Auto,
+}
 
 /// Configuration for auto-pause behavior
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AutoPauseConfig {
-    /// Whether to kill processes on auto-pause (default: true)
+    /// Whether to kill processes on auto-pause (default: true); ignored when `strategy` is set
     pub kill_on_pause: bool,
     /// Timeout for graceful shutdown in seconds (default: 30)
     pub graceful_timeout_secs: u64,
+    /// Durations above which an alarm is raised (default: none)
+    #[serde(default)]
+    pub slo: SloThresholds,
+    /// Initial state of runtime feature flags (default: all off)
+    #[serde(default)]
+    pub features: FeatureFlagsConfig,
+    /// Bounds on tracked processes per sandbox
+    #[serde(default)]
+    pub process_limits: ProcessLimits,
+    /// Label-matched overrides for how individual processes are paused; superseded by `policies`
+    #[serde(default)]
+    pub process_rules: Vec<ProcessRule>,
+    /// Pause strategy; defaults to kill or persist according to `kill_on_pause`
+    #[serde(default)]
+    pub strategy: Option<PauseStrategy>,
+    /// Limits applied by the throttle strategy
+    #[serde(default)]
+    pub soft_pause: SoftPauseConfig,
+    /// Capture crontabs and at jobs at pause and restore them on resume
+    #[serde(default)]
+    pub scheduled_jobs: ScheduledJobsConfig,
+    /// Capture systemd user services at pause and start them again on resume
+    #[serde(default)]
+    pub systemd_user: SystemdUserConfig,
+    /// Health probes run after every resume
+    #[serde(default)]
+    pub resume_probes: Vec<ProbeConfig>,
+    /// Script hooks run at lifecycle phases, next to hooks registered in code
+    #[serde(default)]
+    pub hooks: Vec<ScriptHookConfig>,
+    /// WASM policy plugins consulted when choosing processes to spare and at every hook phase
+    #[serde(default)]
+    pub wasm_plugins: Vec<WasmPluginConfig>,
+    /// Declarative rules deciding per process whether a pause kills, spares, freezes or persists it
+    #[serde(default)]
+    pub policies: Vec<PolicyRule>,
+    /// Opt-in aggregate usage statistics
+    #[serde(default)]
+    pub usage_stats: UsageStatsConfig,
+    /// Fault injection for resilience testing; inert unless the `chaos` feature flag is on
+    #[serde(default)]
+    pub chaos: ChaosConfig,
+    /// Blocking jobs (/proc scans, filesystem checks) allowed at once; defaults to `blocking::DEFAULT_BUDGET`
+    #[serde(default)]
+    pub blocking_budget: Option<usize>,
+    /// Upper bound on any single snapshot or lifecycle storage call
+    #[serde(default)]
+    pub storage_timeout_ms: Option<u64>,
+    /// Snapshot store being migrated away from; while set, writes go to both stores and reads fall back to it
+    #[serde(default)]
+    pub storage_migration_source: Option<PathBuf>,
+    /// Bucket being migrated to or from; while set, writes go to both stores and reads
+    /// try the new store first
+    #[serde(default)]
+    pub storage_migration_remote: Option<RemoteMigrationConfig>,
+    /// How snapshot saves degrade when the snapshot filesystem is full or read-only
+    #[serde(default)]
+    pub storage_full: StorageFullPolicy,
+    /// Size caps applied to every snapshot before it is written
+    #[serde(default)]
+    pub snapshot_caps: SnapshotCaps,
+    /// Per-tenant caps replacing `snapshot_caps` for that tenant's sandboxes
+    #[serde(default)]
+    pub tenant_snapshot_caps: HashMap<String, SnapshotCaps>,
+    /// Note written inside the sandbox after each resume describing the pause
+    #[serde(default)]
+    pub resume_banner: ResumeBannerConfig,
+    /// Event history kept for subscribers that connect late or reconnect
+    #[serde(default)]
+    pub event_replay: EventReplayConfig,
+    /// Message buses (NATS, Kafka) every event is forwarded to
+    #[serde(default)]
+    pub event_sinks: Vec<EventSinkConfig>,
+    /// Slack and email notifications for failed operations, corrupted snapshots and SLO breaches
+    #[serde(default)]
+    pub notifications: Vec<NotificationConfig>,
+    /// Concurrency and rate limits on each tenant's pause/resume operations
+    #[serde(default)]
+    pub tenant_quotas: TenantQuotasConfig,
+    /// Heuristics used by the auto strategy
+    #[serde(default)]
+    pub auto_strategy: AutoStrategyConfig,
+    /// Operation timings and snapshot sizes kept on disk, for estimates that survive restarts
+    #[serde(default)]
+    pub history: HistoryConfig,
+    /// Alarms on templates whose pauses or resumes regressed; needs `history`
+    #[serde(default)]
+    pub template_anomaly: TemplateAnomalyConfig,
+    /// Second-person approval of force-kill pauses, e.g. of production sandboxes
+    #[serde(default)]
+    pub approval: ApprovalConfig,
+    /// Rolling half-finished operations forward or back before new work is taken
+    #[serde(default)]
+    pub startup_recovery: StartupRecoveryConfig,
+    /// What to do when another agent already owns the snapshot base dir
+    #[serde(default)]
+    pub instance_conflict: InstanceConflict,
+    /// Flags pauses and resumes that run far longer than expected; off when unset
+    #[serde(default)]
+    pub watchdog: Option<WatchdogConfig>,
+    /// Scan the snapshot store again this often after the startup scan; only at
+    /// startup when unset
+    #[serde(default)]
+    pub integrity_scan_interval_secs: Option<u64>,
+    /// Where to mount the live-state filesystem, e.g. `/run/e2b/state`; needs the
+    /// `fuse` feature. Not mounted when unset.
+    #[serde(default)]
+    pub state_fs: Option<PathBuf>,
+}
+
+impl AutoPauseConfig {
+    /// The configured strategy; `Auto` is resolved for each pause
+    pub fn strategy(&self) -> PauseStrategy {
+        self.strategy.unwrap_or(if self.kill_on_pause { PauseStrategy::Kill } else { PauseStrategy::Persist })
+    }
 }
 
 impl Default for AutoPauseConfig {
@@ -25,148 +204,2920 @@ impl Default for AutoPauseConfig {
         Self {
             kill_on_pause: true,
             graceful_timeout_secs: 30,
+            slo: SloThresholds::default(),
+            features: FeatureFlagsConfig::default(),
+            process_limits: ProcessLimits::default(),
+            process_rules: Vec::new(),
+            strategy: None,
+            soft_pause: SoftPauseConfig::default(),
+            scheduled_jobs: ScheduledJobsConfig::default(),
+            systemd_user: SystemdUserConfig::default(),
+            resume_probes: Vec::new(),
+            hooks: Vec::new(),
+            wasm_plugins: Vec::new(),
+            policies: Vec::new(),
+            usage_stats: UsageStatsConfig::default(),
+            chaos: ChaosConfig::default(),
+            blocking_budget: None,
+            storage_timeout_ms: None,
+            storage_migration_source: None,
+            storage_migration_remote: None,
+            storage_full: StorageFullPolicy::default(),
+            snapshot_caps: SnapshotCaps::default(),
+            tenant_snapshot_caps: HashMap::new(),
+            resume_banner: ResumeBannerConfig::default(),
+            event_replay: EventReplayConfig::default(),
+            event_sinks: Vec::new(),
+            notifications: Vec::new(),
+            tenant_quotas: TenantQuotasConfig::default(),
+            auto_strategy: AutoStrategyConfig::default(),
+            history: HistoryConfig::default(),
+            template_anomaly: TemplateAnomalyConfig::default(),
+            approval: ApprovalConfig::default(),
+            startup_recovery: StartupRecoveryConfig::default(),
+            instance_conflict: InstanceConflict::default(),
+            watchdog: None,
+            integrity_scan_interval_secs: None,
+            state_fs: None,
+        }
+    }
+}
+
+/// Point-in-time status of a sandbox as seen by the agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxStatus {
+    pub sandbox_id: String,
+    pub process_count: usize,
+    pub has_snapshot: bool,
+    /// Recent OOM kills, oldest first
+    pub oom_events: Vec<OomEvent>,
+}
+
+/// What a pause would do to a sandbox, without doing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunReport {
+    pub sandbox_id: String,
+    pub kill_on_pause: bool,
+    pub strategy: PauseStrategy,
+    pub processes: Vec<ProcessInfo>,
+    pub total_threads: u32,
+    /// Expected cost of each strategy, from this sandbox's and the fleet's past pauses
+    #[serde(default)]
+    pub costs: Vec<StrategyCost>,
+}
+
+/// Outcome of checking a restored process against the live system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RestoreStatus {
+    /// The recorded PID is alive and still belongs to the same program
+    Verified,
+    /// Nothing is running under the recorded PID anymore
+    Missing,
+    /// The recorded PID was reused by a different program
+    Replaced,
+    /// The recorded PID is gone but the same program survived under another PID, which is now tracked
+    Adopted,
+}
+
+/// A single snapshot entry and how it reconciled on resume
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoredProcess {
+    pub pid: i32,
+    pub name: String,
+    pub status: RestoreStatus,
+    /// Live PID the entry was matched to, when adopted
+    #[serde(default)]
+    pub adopted_pid: Option<i32>,
+}
+
+/// A resource held at pause time that could not be carried across the pause
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NonRestorableResource {
+    pub pid: i32,
+    pub name: String,
+    pub description: String,
+}
+
+/// A lock a restored process held at pause time that is now held by someone else
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockConflict {
+    pub pid: i32,
+    pub name: String,
+    pub lock: FileLock,
+    pub holder_pid: i32,
+}
+
+/// Time reserved for the SIGKILL phase when budgeting against a deadline
+const KILL_PHASE_RESERVE: Duration = Duration::from_secs(1);
+
+/// What asked for a pause; protected sandboxes only accept forced manual pauses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseTrigger {
+    /// An operator or API call
+    #[default]
+    Manual,
+    Idle,
+    Budget,
+    Scheduled,
+    CrashLoop,
+}
+
+impl PauseTrigger {
+    pub fn is_automatic(&self) -> bool {
+        *self != PauseTrigger::Manual
+    }
+}
+
+/// Per-call options for `prepare_pause_with`
+#[derive(Debug, Clone, Default)]
+pub struct PauseOptions {
+    /// Hard deadline set by the orchestrator (e.g. the VM snapshot window);
+    /// the graceful and forced phases are budgeted to finish before it
+    pub deadline: Option<tokio::time::Instant>,
+    /// Aborts the pause at its next checkpoint; take it from `operation_token`
+    /// so an agent shutdown cancels it too. Defaults to a fresh operation token.
+    pub cancel: Option<CancellationToken>,
+    pub trigger: PauseTrigger,
+    /// Pause a protected sandbox anyway; only honoured for manual pauses
+    pub force: bool,
+    /// Who asked for the pause; they cannot approve it themselves
+    pub requested_by: Option<String>,
+}
+
+/// Summary of what happened while pausing a sandbox
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PauseResult {
+    pub sandbox_id: String,
+    pub warnings: Vec<Warning>,
+    /// The deadline passed before every process was confirmed gone
+    pub deadline_exceeded: bool,
+    /// Processes still tracked when the pause gave up
+    pub remaining_pids: Vec<i32>,
+    /// Strategy the pause used, and why when it was picked automatically
+    #[serde(default)]
+    pub strategy: Option<StrategyDecision>,
+}
+
+/// A pause that has passed the prepare phase and is waiting to be committed
+/// or aborted; the orchestrator takes its VM-level snapshot in between
+#[derive(Debug)]
+pub struct PreparedPause {
+    sandbox_id: String,
+    options: PauseOptions,
+    /// Processes tracked when the pause was prepared
+    pub processes: Vec<ProcessInfo>,
+    prepare_elapsed: Duration,
+    operation: OperationGuard,
+    strategy: StrategyDecision,
+    /// Held from prepare until the pause is committed or aborted
+    _slot: OperationSlot,
+    pending: PendingPause,
+}
+
+type PendingPauses = Arc<Mutex<HashSet<SandboxId>>>;
+
+/// A sandbox's entry in `pending_pauses`, removed on `release` or on drop, so a
+/// prepare that fails or a prepared pause that is dropped does not block the next one.
+/// Once the sandbox is marked Pausing, dropping the entry unreleased also puts it back
+/// to Running, so startup recovery does not finish a pause nobody committed.
+#[derive(Debug)]
+struct PendingPause {
+    pending: PendingPauses,
+    sandbox_id: SandboxId,
+    released: bool,
+    rollback: Option<LifecycleRollback>,
+}
+
+impl PendingPause {
+    /// Enter `sandbox_id`, or None when a pause of it is already prepared
+    fn insert(pending: &PendingPauses, sandbox_id: &str) -> Option<Self> {
+        let sandbox_id = SandboxId::new(sandbox_id);
+        if !pending.lock().unwrap().insert(sandbox_id.clone()) {
+            return None;
+        }
+        Some(Self { pending: pending.clone(), sandbox_id, released: false, rollback: None })
+    }
+
+    /// Roll the sandbox back to Running if this entry is dropped before `release`
+    fn arm(&mut self, rollback: LifecycleRollback) {
+        self.rollback = Some(rollback);
+    }
+
+    fn release(&mut self) {
+        self.rollback = None;
+        if !std::mem::replace(&mut self.released, true) {
+            self.pending.lock().unwrap().remove(&self.sandbox_id);
+        }
+    }
+}
+
+impl Drop for PendingPause {
+    fn drop(&mut self) {
+        if let Some(rollback) = self.rollback.take() {
+            rollback.run(&self.sandbox_id);
         }
+        self.release();
+    }
+}
+
+/// What `set_lifecycle_state` writes to, held by a `PendingPause` so it can mark
+/// its sandbox Running again without the manager
+struct LifecycleRollback {
+    cache: Arc<Mutex<HashMap<SandboxId, LifecycleState>>>,
+    persistence: Arc<PersistenceManager>,
+    stats: Arc<StatsStore>,
+    events: EventBus,
+}
+
+impl std::fmt::Debug for LifecycleRollback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LifecycleRollback").finish_non_exhaustive()
+    }
+}
+
+impl LifecycleRollback {
+    /// The cache and subscribers see Running at once; the record is saved in the background
+    fn run(self, sandbox_id: &SandboxId) {
+        let state = LifecycleState::Running;
+        self.cache.lock().unwrap().insert(sandbox_id.clone(), state);
+        self.stats.record(sandbox_id, TimelineEvent::State { state });
+        self.events.publish(sandbox_id, EventPayload::Lifecycle { state });
+        warn!("Prepared pause of sandbox {} was dropped without commit or abort; marking it Running", sandbox_id);
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            warn!("No runtime to save the lifecycle state of sandbox {}; it stays Pausing on disk", sandbox_id);
+            return;
+        };
+        let sandbox_id = sandbox_id.clone();
+        runtime.spawn(async move {
+            if let Err(e) = self.persistence.save_lifecycle(&sandbox_id, state).await {
+                warn!("Failed to persist lifecycle state {:?} for sandbox {}: {}", state, sandbox_id, e);
+            }
+        });
+    }
+}
+
+/// A tenant quota permit and a pause/resume slot, released on drop
+pub(crate) struct OperationSlot {
+    _quota: Option<QuotaPermit>,
+    _slot: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
+impl std::fmt::Debug for OperationSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OperationSlot").field("quota", &self._quota.is_some()).field("slot", &self._slot.is_some()).finish()
+    }
+}
+
+impl PreparedPause {
+    pub fn sandbox_id(&self) -> &str {
+        &self.sandbox_id
+    }
+}
+
+/// Summary of what happened while resuming a sandbox
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResumeReport {
+    pub sandbox_id: String,
+    pub restored: Vec<RestoredProcess>,
+    pub non_restorable: Vec<NonRestorableResource>,
+    pub lock_conflicts: Vec<LockConflict>,
+    pub warnings: Vec<Warning>,
+    /// Crontabs and at jobs put back after the pause, if capture is enabled
+    pub scheduled_jobs: Option<ScheduledJobsRestore>,
+    /// systemd user services started again after the pause, if capture is enabled
+    pub systemd_units: Option<SystemdUnitsRestore>,
+    /// Outcome of the configured resume probes
+    pub health: Vec<ProbeReport>,
+    /// Banner left inside the sandbox, if enabled
+    pub banner: Option<ResumeBanner>,
+    /// Steps an interrupted earlier attempt had completed, which were not repeated
+    #[serde(default)]
+    pub resumed_steps: Vec<RestoreStep>,
+    /// Processes a Freeze policy stopped during the pause that were continued
+    #[serde(default)]
+    pub thawed: Vec<i32>,
+}
+
+impl ResumeReport {
+    /// Number of restored entries with the given status
+    pub fn count(&self, status: RestoreStatus) -> usize {
+        self.restored.iter().filter(|p| p.status == status).count()
     }
 }
 
+/// Summary of pausing or resuming a subset of a sandbox's processes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PartialPauseResult {
+    pub sandbox_id: String,
+    /// Processes that were killed, suspended or resumed
+    pub pids: Vec<i32>,
+    pub warnings: Vec<Warning>,
+}
+
 /// Manages auto-pause functionality for sandboxes
 pub struct AutoPauseManager {
     config: AutoPauseConfig,
     process_manager: ProcessManager,
-    persistence_manager: PersistenceManager,
+    persistence_manager: Arc<PersistenceManager>,
+    backend: Arc<dyn ProcessBackend>,
+    alarms: AlarmMonitor,
+    feature_flags: Arc<FeatureFlags>,
+    warnings: WarningSink,
+    pending_pauses: PendingPauses, // sandboxes between prepare and commit/abort
+    registry: Arc<SandboxRegistry>,
+    lifecycle: Arc<Mutex<HashMap<SandboxId, LifecycleState>>>, // cache of persisted lifecycle states
+    operation_throttle: Option<Arc<Throttle>>,
+    hooks: HookRegistry,
+    plugins: PluginHost,
+    policy: PolicyEngine,
+    usage_stats: Option<Arc<UsageStats>>,
+    stats: Arc<StatsStore>,
+    history: Option<Arc<HistoryStore>>,
+    templates: Option<TemplateMonitor>,
+    last_pause: Mutex<HashMap<SandboxId, PauseResult>>,
+    last_resume: Mutex<HashMap<SandboxId, ResumeReport>>,
+    policy_frozen: Mutex<HashMap<SandboxId, Vec<i32>>>, // stopped by a Freeze policy in the pause being committed
+    shutdown: CancellationToken, // parent of every operation token
+    integrity: Mutex<Option<IntegrityReport>>, // result of the latest scan
+    events: EventBus,
+    tenant_quotas: TenantQuotas,
+    operations: OperationTracker, // in-flight pauses and resumes
+    approvals: ApprovalGate, // force-kill pauses held for approval
+    startup: StartupGate, // closed until startup recovery ran
+    instance: Mutex<Option<InstanceMode>>, // ownership of the base dir, once claimed
+    tasks: TaskRegistry, // background tasks reported by dump_diagnostics
+    channels: Mutex<HashMap<SandboxId, Arc<SandboxChannel>>>, // in-sandbox channels being served
 }
 
 impl AutoPauseManager {
     pub fn new(config: AutoPauseConfig) -> Self {
+        Self::with_backend(config, backend::default_backend())
+    }
+
+    pub fn with_backend(config: AutoPauseConfig, backend: Box<dyn ProcessBackend>) -> Self {
+        if let Some(budget) = config.blocking_budget {
+            if !blocking::configure(budget) {
+                warn!("Blocking budget already set to {}, ignoring {}", blocking::pool().budget(), budget);
+            }
+        }
+        let hooks = HookRegistry::from_config(&config.hooks);
+        let plugins = PluginHost::load(&config.wasm_plugins);
+        for plugin in plugins.plugins() {
+            for phase in [HookPhase::PrePause, HookPhase::PostPause, HookPhase::PreResume, HookPhase::PostResume] {
+                let hook = RegisteredHook::new(format!("wasm:{}", plugin.name()), phase, Arc::new(PluginHook::new(plugin.clone())))
+                    .with_error_policy(HookErrorPolicy::Abort);
+                hooks.register(hook);
+            }
+        }
+        let feature_flags = Arc::new(FeatureFlags::new(config.features.clone()));
+        let mut events = EventBus::with_capacity(config.event_replay.capacity);
+        if let Some(dir) = &config.event_replay.journal_dir {
+            match events.clone().with_journal(dir.clone()) {
+                Ok(journaled) => events = journaled,
+                Err(e) => warn!("Event journal at {} unavailable, keeping history in memory only: {}", dir.display(), e),
+            }
+        }
+        let history = config.history.dir.as_ref().and_then(|dir| match HistoryStore::open(dir.clone(), config.history.clone()) {
+            Ok(history) => Some(Arc::new(history)),
+            Err(e) => {
+                warn!("History store at {} unavailable, keeping timings in memory only: {}", dir.display(), e);
+                None
+            }
+        });
+        let templates = history.as_ref().filter(|_| config.template_anomaly.enabled).map(|history| {
+            let monitor = TemplateMonitor::new(config.template_anomaly.clone());
+            monitor.seed(history);
+            monitor
+        });
+        let mut warnings = WarningSink::new().with_events(events.clone());
+        let mut persistence_manager = PersistenceManager::new().with_storage_full_policy(&config.storage_full);
+        if let Some(ms) = config.storage_timeout_ms {
+            persistence_manager = persistence_manager.with_storage_timeout(Duration::from_millis(ms));
+        }
+        if let Some(source) = &config.storage_migration_source {
+            persistence_manager = persistence_manager.with_migration_source(PersistenceManager::with_base_dir(source.clone()));
+        }
+        if let Some(remote) = &config.storage_migration_remote {
+            match S3Store::new(remote.s3.clone()) {
+                Ok(store) => persistence_manager = persistence_manager.with_remote_migration(Arc::new(store), remote.direction),
+                Err(e) => error!("Not migrating the snapshot store to or from bucket {}: {}", remote.s3.bucket, e),
+            }
+        }
+        let mut backend = backend;
+        if config.chaos.is_configured() {
+            let chaos = Arc::new(Chaos::new(config.chaos.clone(), feature_flags.clone()));
+            backend = Box::new(ChaosBackend::new(backend, chaos.clone()));
+            warnings = warnings.with_chaos(chaos.clone());
+            persistence_manager = persistence_manager.with_chaos(chaos);
+        }
         Self {
+            alarms: AlarmMonitor::new(config.slo.clone()).with_events(events.clone()),
+            feature_flags,
+            warnings,
+            pending_pauses: Arc::new(Mutex::new(HashSet::new())),
+            registry: Arc::new(SandboxRegistry::new()),
+            lifecycle: Arc::new(Mutex::new(HashMap::new())),
+            operation_throttle: None,
+            hooks,
+            plugins,
+            policy: PolicyEngine::from_config(&config.policies, &config.process_rules),
+            usage_stats: config.usage_stats.enabled.then(|| Arc::new(UsageStats::new())),
+            stats: Arc::new(StatsStore::new()),
+            history,
+            templates,
+            last_pause: Mutex::new(HashMap::new()),
+            last_resume: Mutex::new(HashMap::new()),
+            policy_frozen: Mutex::new(HashMap::new()),
+            shutdown: CancellationToken::new(),
+            integrity: Mutex::new(None),
+            process_manager: ProcessManager::with_limits(config.process_limits.clone()).with_events(events.clone()),
+            events,
+            tenant_quotas: TenantQuotas::new(config.tenant_quotas.clone()),
+            operations: OperationTracker::new(),
+            approvals: ApprovalGate::new(),
+            startup: StartupGate::new(!config.startup_recovery.enabled, Duration::from_secs(config.startup_recovery.gate_timeout_secs)),
+            instance: Mutex::new(None),
+            tasks: TaskRegistry::default(),
+            channels: Mutex::new(HashMap::new()),
             config,
-            process_manager: ProcessManager::new(),
-            persistence_manager: PersistenceManager::new(),
+            persistence_manager: Arc::new(persistence_manager),
+            backend: Arc::from(backend),
         }
     }
 
-    /// Prepare sandbox for auto-pause
-    pub async fn prepare_pause(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Preparing sandbox {} for auto-pause", sandbox_id);
-        
-        if self.config.kill_on_pause {
-            // Kill all user processes gracefully
-            self.kill_all_processes(sandbox_id).await?;
-        } else {
-            // Persist current process state for resume
-            self.persist_process_state(sandbox_id).await?;
-        }
-        
-        Ok(())
+    /// Bound concurrent pause/resume operations and snapshot I/O by load-driven throttles
+    pub fn with_throttles(mut self, operations: Arc<Throttle>, io: Arc<Throttle>) -> Self {
+        self.operation_throttle = Some(operations);
+        let persistence_manager = Arc::try_unwrap(self.persistence_manager).unwrap_or_else(|_| unreachable!("storage is not shared until the manager runs"));
+        self.persistence_manager = Arc::new(persistence_manager.with_io_throttle(io));
+        self
     }
 
-    /// Kill all user processes in the sandbox
-    async fn kill_all_processes(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let processes = self.process_manager.list_processes(sandbox_id).await?;
-        
-        // Send SIGTERM to all process groups first (graceful shutdown)
-        for process in &processes {
-            if let Ok(pid) = Pid::from_raw(process.pid) {
-                // Kill the entire process group
-                let pgid = -process.pid; // Negative PID kills process group
-                if let Err(e) = signal::killpg(Pid::from_raw(pgid), Signal::SIGTERM) {
-                    warn!("Failed to send SIGTERM to process group {}: {}", pgid, e);
+    /// Snapshot storage
+    pub fn persistence_manager(&self) -> &PersistenceManager {
+        &self.persistence_manager
+    }
+
+    /// A token for one operation; cancelled by the caller or by `shutdown`
+    pub fn operation_token(&self) -> CancellationToken {
+        self.shutdown.child_token()
+    }
+
+    /// Every in-flight pause and resume with its phase, elapsed time and deadline
+    pub fn list_operations(&self) -> Vec<OperationInfo> {
+        self.operations.list()
+    }
+
+    /// Cancel one in-flight operation at its next checkpoint; false if it already finished
+    pub fn cancel_operation(&self, id: OperationId) -> bool {
+        self.operations.cancel(id)
+    }
+
+    /// Let a force-kill pause held for approval proceed; false if it is not waiting.
+    /// Whoever requested the pause cannot approve it.
+    pub fn approve_operation(&self, id: OperationId, approver: &str) -> Result<bool, SandboxError> {
+        let Some(pending) = self.approvals.approve(id, approver)? else {
+            return Ok(false);
+        };
+        self.events.publish(&pending.sandbox_id, EventPayload::Approval { operation_id: id, decision: ApprovalDecision::Approved, approver: Some(approver.to_string()) });
+        info!(
+            target: "audit",
+            sandbox_id = pending.sandbox_id.as_str(),
+            operation = "approve",
+            operation_id = id,
+            approver = approver;
+            "{} approved the force-kill pause of sandbox {}", approver, pending.sandbox_id
+        );
+        Ok(true)
+    }
+
+    /// Force-kill pauses waiting for approval
+    pub fn pending_approvals(&self) -> Vec<PendingApproval> {
+        self.approvals.pending()
+    }
+
+    /// Cancel every in-flight and future operation at its next checkpoint
+    pub fn shutdown(&self) {
+        info!("Shutting down: cancelling in-flight operations");
+        self.shutdown.cancel();
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Holds pauses and resumes until `recover_on_startup` has run
+    pub fn startup_gate(&self) -> &StartupGate {
+        &self.startup
+    }
+
+    /// Sandboxes with a known lifecycle state
+    pub fn known_sandbox_ids(&self) -> Vec<SandboxId> {
+        self.lifecycle.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Whether a pause of the sandbox is prepared but not yet committed or aborted
+    pub fn is_pause_pending(&self, sandbox_id: &str) -> bool {
+        self.pending_pauses.lock().unwrap().contains(sandbox_id)
+    }
+
+    /// Per-sandbox metadata such as resume priority
+    pub fn registry(&self) -> &Arc<SandboxRegistry> {
+        &self.registry
+    }
+
+    /// Runtime feature flags, shared with the admin interfaces
+    pub fn feature_flags(&self) -> &Arc<FeatureFlags> {
+        &self.feature_flags
+    }
+
+    /// Whether `feature` is on for the sandbox, honouring its tenant's override
+    fn feature_enabled(&self, feature: Feature, sandbox_id: &str) -> bool {
+        self.feature_flags.is_enabled(feature, self.registry.tenant(sandbox_id).as_deref())
+    }
+
+    /// Receive structured warnings as they are raised by any operation
+    pub fn subscribe_warnings(&self) -> broadcast::Receiver<Warning> {
+        self.warnings.subscribe()
+    }
+
+    /// Receive alarms raised when an operation breaches its SLO
+    pub fn subscribe_alarms(&self) -> broadcast::Receiver<Alarm> {
+        self.alarms.subscribe()
+    }
+
+    /// Receive every lifecycle, process, audit, warning and alarm event in the versioned envelope
+    pub fn subscribe_events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Replay a sandbox's events from sequence number `from_seq` on, then follow live ones
+    pub fn subscribe_events_from(&self, sandbox_id: &str, from_seq: u64) -> EventReplay {
+        self.events.subscribe_from(sandbox_id, from_seq)
+    }
+
+    /// Start forwarding events to each configured sink. Sinks whose feature was not
+    /// compiled in are logged and skipped.
+    pub fn spawn_event_sinks(&self) -> Vec<tokio::task::JoinHandle<()>> {
+        let mut tasks = Vec::new();
+        for config in &self.config.event_sinks {
+            match event_sinks::build_sink(&config.target) {
+                Ok(sink) => {
+                    let task = event_sinks::spawn_sink(sink, config.delivery.clone(), self.events.subscribe());
+                    self.track_task(format!("event_sink:{:?}", config.target), &task);
+                    tasks.push(task);
                 }
+                Err(e) => error!("Not forwarding events: {}", e),
             }
         }
+        tasks
+    }
 
-        // Wait for graceful shutdown
-        let grace_period = Duration::from_secs(self.config.graceful_timeout_secs);
-        match timeout(grace_period, self.wait_for_processes_to_exit(sandbox_id)).await {
-            Ok(Ok(())) => {
-                info!("All processes exited gracefully");
-                return Ok(());
-            }
-            _ => {
-                warn!("Graceful shutdown timed out, forcing kill");
+    /// Start sending the configured notifications
+    pub fn spawn_notifiers(&self) -> Vec<tokio::task::JoinHandle<()>> {
+        self.config
+            .notifications
+            .iter()
+            .map(|config| {
+                let task = Notifier::new(config.clone()).spawn(self.events.subscribe());
+                self.track_task(format!("notifier:{:?}", config.channel), &task);
+                task
+            })
+            .collect()
+    }
+
+    /// Report whether a background task is still running in `dump_diagnostics`
+    pub fn track_task<T>(&self, name: impl Into<String>, task: &tokio::task::JoinHandle<T>) {
+        self.tasks.track(name, task.abort_handle());
+    }
+
+    /// In-flight state for a crash dump. Only tries each lock, since the panicking
+    /// thread may hold one of them.
+    pub fn crash_dump(&self, message: String, location: Option<String>) -> CrashDump {
+        let states = self
+            .lifecycle
+            .try_lock()
+            .ok()
+            .map(|states| states.iter().map(|(sandbox_id, state)| (sandbox_id.to_string(), *state)).collect());
+        let pending = self
+            .pending_pauses
+            .try_lock()
+            .ok()
+            .map(|pending| pending.iter().map(|sandbox_id| sandbox_id.to_string()).collect())
+            .unwrap_or_default();
+        CrashDump::new(message, location, states, self.operations.try_list(), pending)
+    }
+
+    /// Snapshot of lifecycle states, locks, queues, in-flight operations and background
+    /// tasks. Never waits for a lock, so it also works while the agent is wedged.
+    pub fn dump_diagnostics(&self) -> Diagnostics {
+        let listed = self.operations.try_list();
+        let registered = self.registry.try_list();
+        let tasks = self.tasks.health();
+        let operations = listed.clone().unwrap_or_default();
+        let states = self.lifecycle.try_lock().ok().map(|states| states.clone());
+        let pending = self.pending_pauses.try_lock().ok().map(|pending| pending.clone());
+        let process_counts = self.process_manager.try_process_counts();
+        let tenants: HashMap<String, Option<String>> = registered
+            .iter()
+            .flatten()
+            .map(|entry| (entry.sandbox_id.clone(), entry.tenant.clone()))
+            .collect();
+
+        let mut sandbox_ids: BTreeSet<String> = tenants.keys().cloned().collect();
+        sandbox_ids.extend(states.iter().flat_map(|states| states.keys().map(|id| id.to_string())));
+        sandbox_ids.extend(process_counts.iter().flat_map(|counts| counts.keys().map(|id| id.to_string())));
+        sandbox_ids.extend(operations.iter().map(|op| op.sandbox_id.clone()));
+        let sandboxes = sandbox_ids
+            .into_iter()
+            .map(|sandbox_id| SandboxDiagnostics {
+                state: states.as_ref().and_then(|states| states.get(sandbox_id.as_str()).copied()),
+                tenant: tenants.get(&sandbox_id).cloned().flatten(),
+                pause_pending: pending.as_ref().is_some_and(|pending| pending.contains(sandbox_id.as_str())),
+                tracked_processes: process_counts.as_ref().map(|counts| counts.get(sandbox_id.as_str()).copied().unwrap_or(0)),
+                operations: operations.iter().filter(|op| op.sandbox_id == sandbox_id).count(),
+                sandbox_id,
+            })
+            .collect();
+
+        let queued = operations.iter().filter(|op| op.phase == "queued").count();
+        let mut locks = vec![
+            LockDiagnostics::probe("lifecycle_cache", &*self.lifecycle),
+            LockDiagnostics::probe("pending_pauses", &self.pending_pauses),
+            LockDiagnostics::new("process_map", if process_counts.is_some() { LockState::Free } else { LockState::Held }),
+            LockDiagnostics::new("registry", if registered.is_some() { LockState::Free } else { LockState::Held }),
+            LockDiagnostics::new("operations", if listed.is_some() { LockState::Free } else { LockState::Held }),
+            LockDiagnostics::new("tasks", if tasks.is_some() { LockState::Free } else { LockState::Held }),
+        ];
+        if let Some(throttle) = &self.operation_throttle {
+            locks.push(LockDiagnostics::semaphore("operation_slots", throttle.limit(), throttle.available(), queued));
+        }
+        let pool = blocking::pool();
+        Diagnostics {
+            generated_at: chrono::Utc::now(),
+            shutting_down: self.is_shutting_down(),
+            sandboxes,
+            operations,
+            locks,
+            queues: QueueDiagnostics {
+                blocking_budget: pool.budget(),
+                blocking_available: pool.available(),
+                operations_queued: queued,
+                event_subscribers: self.events.subscriber_count(),
+                events_pending: self.events.pending(),
+            },
+            tasks: tasks.unwrap_or_default(),
+            runtime: RuntimeStats::sample(),
+        }
+    }
+
+    /// Bus for subsystems outside the manager to publish through
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Broadcast an alarm raised by a background monitor such as the watchdog
+    pub fn raise_alarm(&self, alarm: Alarm) {
+        self.alarms.raise(alarm);
+    }
+
+    /// Configuration the manager was built with
+    pub fn config(&self) -> &AutoPauseConfig {
+        &self.config
+    }
+
+    /// Report of the last pause of a sandbox run by this agent
+    pub fn last_pause_report(&self, sandbox_id: &str) -> Option<PauseResult> {
+        self.last_pause.lock().unwrap().get(sandbox_id).cloned()
+    }
+
+    /// Report of the last resume of a sandbox run by this agent
+    pub fn last_resume_report(&self, sandbox_id: &str) -> Option<ResumeReport> {
+        self.last_resume.lock().unwrap().get(sandbox_id).cloned()
+    }
+
+    /// Process hierarchy of a sandbox with states and resource usage: the live processes
+    /// while any are tracked, otherwise the ones in its snapshot
+    pub async fn render_process_tree(&self, sandbox_id: &str, format: TreeFormat) -> Result<String, Box<dyn std::error::Error>> {
+        let processes = self.process_manager.list_processes(sandbox_id).await?;
+        let nodes = if !processes.is_empty() {
+            blocking::run("process_tree", move || processes.iter().map(TreeNode::live).collect::<Vec<_>>()).await
+        } else {
+            match self.persistence_manager.load_snapshot(sandbox_id).await? {
+                Some(snapshot) => snapshot.processes.iter().map(TreeNode::persisted).collect(),
+                None => return Err(format!("Sandbox {} has no live processes and no snapshot", sandbox_id).into()),
             }
+        };
+        Ok(process_tree::render(&nodes, format))
+    }
+
+    /// SLO thresholds alarms are raised against
+    pub fn slo(&self) -> &SloThresholds {
+        &self.config.slo
+    }
+
+    /// Log and broadcast a warning raised by a background monitor
+    pub fn publish_warning(&self, warning: Warning) {
+        self.warnings.publish(warning);
+    }
+
+    /// Result of the latest integrity scan, for health reporting; None until one has run
+    pub fn integrity_report(&self) -> Option<IntegrityReport> {
+        self.integrity.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_integrity_report(&self, report: IntegrityReport) {
+        *self.integrity.lock().unwrap() = Some(report);
+    }
+
+    /// The agent owning the base dir when this one runs read-only
+    pub fn read_only_holder(&self) -> Option<LockOwner> {
+        match self.instance.lock().unwrap().as_ref() {
+            Some(InstanceMode::ReadOnly { holder }) => Some(holder.clone()),
+            _ => None,
+        }
+    }
+
+    /// Record how this agent runs against its base dir; read-only mode stops every
+    /// write to the snapshot store, the history store and the event journal
+    pub(crate) fn set_instance_mode(&self, mode: InstanceMode) {
+        let read_only = matches!(mode, InstanceMode::ReadOnly { .. });
+        self.persistence_manager.set_read_only(read_only);
+        if let Some(history) = &self.history {
+            history.set_read_only(read_only);
+        }
+        self.events.pause_journal(read_only);
+        *self.instance.lock().unwrap() = Some(mode);
+    }
+
+    /// Refuse work that would change the store while another agent owns it
+    fn check_writable(&self) -> Result<(), SandboxError> {
+        match self.read_only_holder() {
+            Some(holder) => Err(SandboxError::PermissionDenied(format!("agent is read-only, pid {} owns {}", holder.pid, self.persistence_manager.get_base_dir().display()))),
+            None => Ok(()),
         }
+    }
+
+    /// In-sandbox channels attached with `attach_channel`
+    pub(crate) fn channels(&self) -> &Mutex<HashMap<SandboxId, Arc<SandboxChannel>>> {
+        &self.channels
+    }
+
+    /// Lifecycle hooks; embedders register async hooks here
+    pub fn hooks(&self) -> &HookRegistry {
+        &self.hooks
+    }
+
+    /// Aggregate usage statistics when enabled; call `spawn` on them to produce summaries
+    pub fn usage_stats(&self) -> Option<&Arc<UsageStats>> {
+        self.usage_stats.as_ref()
+    }
+
+    /// Per-sandbox lifecycle timelines, served by `StatsServer`
+    pub fn stats(&self) -> &Arc<StatsStore> {
+        &self.stats
+    }
+
+    /// Operation history kept on disk, when configured
+    pub fn history(&self) -> Option<&Arc<HistoryStore>> {
+        self.history.as_ref()
+    }
+
+    /// Past pause and resume durations and snapshot sizes of a sandbox, oldest first.
+    /// Without an on-disk store only what this process has seen is returned.
+    pub async fn get_history(&self, sandbox_id: &str) -> Result<Vec<TimelinePoint>, Box<dyn std::error::Error>> {
+        let Some(history) = &self.history else {
+            let timeline = self.stats.sandbox(sandbox_id).map(|stats| stats.timeline).unwrap_or_default();
+            return Ok(timeline.into_iter().filter(|point| !matches!(point.event, TimelineEvent::State { .. })).collect());
+        };
+        Ok(history.get(sandbox_id)?)
+    }
 
-        // Force kill any remaining processes
-        let remaining_processes = self.process_manager.list_processes(sandbox_id).await?;
-        for process in &remaining_processes {
-            if let Ok(pid) = Pid::from_raw(process.pid) {
-                let pgid = -process.pid;
-                if let Err(e) = signal::killpg(Pid::from_raw(pgid), Signal::SIGKILL) {
-                    error!("Failed to send SIGKILL to process group {}: {}", pgid, e);
+    /// Pause and resume metrics aggregated by template, with the phases currently flagged
+    pub fn template_metrics(&self) -> Vec<TemplateMetrics> {
+        self.templates.as_ref().map(TemplateMonitor::metrics).unwrap_or_default()
+    }
+
+    /// Record a point on the sandbox's timeline; durations and sizes also go to the history store
+    fn record_timeline(&self, sandbox_id: &str, event: TimelineEvent) {
+        if let Some(history) = &self.history {
+            if !matches!(event, TimelineEvent::State { .. }) {
+                history.record(sandbox_id, TimelinePoint { at: chrono::Utc::now(), event: event.clone() });
+            }
+            if let (Some(monitor), Some(template)) = (&self.templates, history.template_of(sandbox_id)) {
+                if let Some(alarm) = monitor.observe(&template, sandbox_id, &event) {
+                    self.alarms.raise(alarm);
                 }
             }
         }
+        self.stats.record(sandbox_id, event);
+    }
 
-        Ok(())
+    /// Platform process control
+    pub fn backend(&self) -> &dyn ProcessBackend {
+        self.backend.as_ref()
     }
 
-    /// Wait for all processes to exit
-    async fn wait_for_processes_to_exit(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let check_interval = Duration::from_millis(500);
-        let max_checks = 60; // 30 seconds total
-        
-        for _ in 0..max_checks {
-            let processes = self.process_manager.list_processes(sandbox_id).await?;
-            if processes.is_empty() {
-                return Ok(());
+    /// Process tracking shared with background tasks such as the reaper
+    pub fn process_manager(&self) -> &ProcessManager {
+        &self.process_manager
+    }
+
+    /// Refuse the operation on a read-only agent, wait out startup recovery, admit it
+    /// against its tenant's quota, then wait for a pause/resume slot when operations are
+    /// throttled. Sandboxes without a tenant have no quota. Cancelling `cancel` ends
+    /// the wait.
+    async fn acquire_operation_slot(&self, sandbox_id: &str, cancel: &CancellationToken) -> Result<OperationSlot, SandboxError> {
+        self.check_writable()?;
+        let acquire = async {
+            self.startup.wait_open().await?;
+            let quota = match self.registry.tenant(sandbox_id) {
+                Some(tenant) => Some(self.tenant_quotas.try_acquire(&tenant)?),
+                None => None,
+            };
+            let slot = match &self.operation_throttle {
+                Some(throttle) => Some(throttle.acquire().await),
+                None => None,
+            };
+            Ok(OperationSlot { _quota: quota, _slot: slot })
+        };
+        tokio::select! {
+            slot = acquire => slot,
+            _ = cancel.cancelled() => {
+                info!("Queued operation on sandbox {} cancelled", sandbox_id);
+                Err(SandboxError::Cancelled(format!("queued operation on sandbox {}", sandbox_id)))
             }
-            tokio::time::sleep(check_interval).await;
         }
-        
-        Err("Timeout waiting for processes to exit".into())
     }
 
-    /// Persist current process state to disk
-    async fn persist_process_state(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let processes = self.process_manager.list_processes(sandbox_id).await?;
-        
-        let persisted_processes: Vec<PersistedProcess> = processes
-            .into_iter()
-            .map(|p| PersistedProcess {
-                pid: p.pid,
-                name: p.name,
-                cmd: p.cmd,
-                start_time: p.start_time,
-                state: "running".to_string(),
-            })
-            .collect();
+    /// Current lifecycle state of a sandbox; sandboxes never seen are Running
+    pub async fn lifecycle_state(&self, sandbox_id: &str) -> LifecycleState {
+        if let Some(state) = self.lifecycle.lock().unwrap().get(sandbox_id) {
+            return *state;
+        }
 
-        let snapshot = StateSnapshot {
-            sandbox_id: sandbox_id.to_string(),
-            timestamp: chrono::Utc::now(),
-            processes: persisted_processes,
+        let persisted = match self.persistence_manager.load_lifecycle(sandbox_id).await {
+            Ok(record) => record.map(|r| r.state),
+            Err(e) => {
+                warn!("Failed to load lifecycle state for sandbox {}: {}", sandbox_id, e);
+                None
+            }
         };
-
-        self.persistence_manager.save_snapshot(&snapshot).await?;
-        info!("Persisted {} processes for sandbox {}", snapshot.processes.len(), sandbox_id);
-        
-        Ok(())
+        let state = persisted.unwrap_or_default();
+        self.lifecycle.lock().unwrap().insert(SandboxId::new(sandbox_id), state);
+        state
     }
 
-    /// Restore sandbox after auto-resume
-    pub async fn after_resume(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        info!("Restoring sandbox {} after auto-resume", sandbox_id);
-        
-        if !self.config.kill_on_pause {
-            // Load persisted process state
-            self.restore_process_state(sandbox_id).await?;
+    /// Record a lifecycle transition in memory and on disk
+    pub(crate) async fn set_lifecycle_state(&self, sandbox_id: &str, state: LifecycleState) {
+        self.lifecycle.lock().unwrap().insert(SandboxId::new(sandbox_id), state);
+        self.stats.record(sandbox_id, TimelineEvent::State { state });
+        self.events.publish(sandbox_id, EventPayload::Lifecycle { state });
+        if let Err(e) = self.persistence_manager.save_lifecycle(sandbox_id, state).await {
+            warn!("Failed to persist lifecycle state {:?} for sandbox {}: {}", state, sandbox_id, e);
         }
-        
-        Ok(())
     }
 
-    /// Restore process state from persistence
-    async fn restore_process_state(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(snapshot) = self.persistence_manager.load_snapshot(sandbox_id).await? {
-            info!("Restoring {} processes for sandbox {}", snapshot.processes.len(), sandbox_id);
-            
-            // Update process manager with restored state
-            self.process_manager.restore_processes(sandbox_id, snapshot.processes).await?;
-        } else {
-            warn!("No persisted state found for sandbox {}", sandbox_id);
+    /// Prepare sandbox for auto-pause
+    pub async fn prepare_pause(&self, sandbox_id: &str) -> Result<PauseResult, Box<dyn std::error::Error>> {
+        self.prepare_pause_with(sandbox_id, PauseOptions::default()).await
+    }
+
+    /// Prepare sandbox for auto-pause with per-call options
+    pub async fn prepare_pause_with(&self, sandbox_id: &str, options: PauseOptions) -> Result<PauseResult, Box<dyn std::error::Error>> {
+        // A refused pause is not a failed one, so it is rejected before failures are published
+        let protected = self.check_protection(sandbox_id, &options).await?;
+        let outcome = match self.prepare_checked(sandbox_id, options, protected).await {
+            Ok(prepared) => self.commit(prepared).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &outcome {
+            if let Some(stats) = &self.usage_stats {
+                stats.record_pause_failure();
+            }
+            self.publish_failure(sandbox_id, "pause", e.as_ref());
         }
-        
-        Ok(())
+        outcome
     }
-}
\ No newline at end of file
+
+    /// First pause phase: validate and quiesce without touching any process.
+    /// Nothing is irreversible until `commit`; `abort` releases the sandbox. A force-kill
+    /// pause that needs approval waits for it here, before any hook runs and without
+    /// holding an operation slot.
+    pub async fn prepare(&self, sandbox_id: &str, options: PauseOptions) -> Result<PreparedPause, Box<dyn std::error::Error>> {
+        let protected = self.check_protection(sandbox_id, &options).await?;
+        self.prepare_checked(sandbox_id, options, protected).await
+    }
+
+    /// `prepare` once `check_protection` has let the pause through
+    async fn prepare_checked(&self, sandbox_id: &str, mut options: PauseOptions, protected: bool) -> Result<PreparedPause, Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        info!("Preparing sandbox {} for auto-pause", sandbox_id);
+        let started = Instant::now();
+        let cancel = options.cancel.get_or_insert_with(|| self.shutdown.child_token()).clone();
+        let operation = self.operations.begin(sandbox_id, OperationKind::Pause, cancel.clone(), options.deadline);
+        checkpoint(&cancel, "pause", sandbox_id)?;
+
+        if protected {
+            warn!(target: "audit", sandbox_id = sandbox_id, operation = "pause"; "Force-pausing protected sandbox {}", sandbox_id);
+        }
+        let strategy = self.decide_strategy(sandbox_id).await?;
+        if strategy.strategy == PauseStrategy::Kill && self.config.approval.requires_approval(self.registry.priority(sandbox_id)) {
+            operation.set_phase("pending_approval");
+            self.await_approval(sandbox_id, operation.id(), options.requested_by.as_deref(), &cancel).await?;
+            operation.set_phase("queued");
+        }
+        let slot = self.acquire_operation_slot(sandbox_id, &cancel).await?;
+        checkpoint(&cancel, "pause", sandbox_id)?;
+        let Some(mut pending) = PendingPause::insert(&self.pending_pauses, sandbox_id) else {
+            return Err(format!("A pause of sandbox {} is already prepared", sandbox_id).into());
+        };
+
+        // Every early return below drops `pending`, which releases the sandbox
+        operation.set_phase("pre_pause_hooks");
+        self.run_hooks(HookPhase::PrePause, sandbox_id, None).await?;
+
+        operation.set_phase("quiesce");
+        let processes = self.validate_and_quiesce(sandbox_id, strategy.strategy).await?;
+        checkpoint(&cancel, "pause", sandbox_id)?;
+        self.set_lifecycle_state(sandbox_id, LifecycleState::Pausing).await;
+        pending.arm(LifecycleRollback {
+            cache: self.lifecycle.clone(),
+            persistence: self.persistence_manager.clone(),
+            stats: self.stats.clone(),
+            events: self.events.clone(),
+        });
+        operation.set_phase("prepared");
+        Ok(PreparedPause {
+            sandbox_id: sandbox_id.to_string(),
+            options,
+            processes,
+            prepare_elapsed: started.elapsed(),
+            operation,
+            strategy,
+            _slot: slot,
+            pending,
+        })
+    }
+
+    async fn validate_and_quiesce(&self, sandbox_id: &str, strategy: PauseStrategy) -> Result<Vec<ProcessInfo>, Box<dyn std::error::Error>> {
+        let processes = self.process_manager.list_live_processes(sandbox_id).await?;
+
+        // Make sure the snapshot can be written before committing to a pause that needs it
+        if strategy != PauseStrategy::Kill {
+            self.persistence_manager.layout(sandbox_id).create_dirs().await?;
+        }
+
+        // Flush dirty pages so the VM-level snapshot sees consistent files
+        #[cfg(unix)]
+        tokio::task::spawn_blocking(nix::unistd::sync).await?;
+
+        Ok(processes)
+    }
+
+    /// Second pause phase: kill or persist the processes of a prepared sandbox. A
+    /// commit that fails before the sandbox is Paused leaves it Running.
+    pub async fn commit(&self, prepared: PreparedPause) -> Result<PauseResult, Box<dyn std::error::Error>> {
+        // Dropping a foreign pause rolls it back in the agent that prepared it
+        if !Arc::ptr_eq(&prepared.pending.pending, &self.pending_pauses) {
+            return Err(format!("Pause of sandbox {} was prepared by another agent", prepared.sandbox_id).into());
+        }
+        let sandbox_id = prepared.sandbox_id.clone();
+        let committed = self.commit_prepared(prepared).await;
+        if committed.is_err() && self.lifecycle_state(&sandbox_id).await == LifecycleState::Pausing {
+            self.set_lifecycle_state(&sandbox_id, LifecycleState::Running).await;
+        }
+        committed
+    }
+
+    async fn commit_prepared(&self, mut prepared: PreparedPause) -> Result<PauseResult, Box<dyn std::error::Error>> {
+        self.check_writable()?;
+        // Like every other entry point; a pause prepared by recovery passes
+        self.startup.wait_open().await?;
+        prepared.pending.release();
+        let sandbox_id = prepared.sandbox_id.as_str();
+
+        let options = prepared.options;
+        let operation = prepared.operation;
+        let strategy = prepared.strategy.strategy;
+        let cancel = options.cancel.clone().unwrap_or_else(|| self.shutdown.child_token());
+        let started = Instant::now();
+        let mut timings = PhaseTimings::from([("prepare".to_string(), prepared.prepare_elapsed.as_millis() as u64)]);
+        let mut result = PauseResult {
+            sandbox_id: sandbox_id.to_string(),
+            strategy: Some(prepared.strategy.clone()),
+            ..Default::default()
+        };
+        self.policy_frozen.lock().unwrap().remove(sandbox_id);
+        if self.config.strategy() == PauseStrategy::Auto {
+            // The resume has to undo what this pause does, even after an agent restart
+            self.persistence_manager.save_strategy_decision(sandbox_id, &prepared.strategy).await?;
+        }
+        
+        // Progress of an earlier, unfinished resume does not apply to this pause
+        if let Err(e) = self.persistence_manager.remove_restore_checkpoint(sandbox_id).await {
+            warn!("Failed to remove restore checkpoint of sandbox {}: {}", sandbox_id, e);
+        }
+
+        // Capture scheduled jobs while their daemons are still running
+        operation.set_phase("capture");
+        if self.config.scheduled_jobs.enabled {
+            self.capture_scheduled_jobs(sandbox_id).await;
+        }
+        if self.config.systemd_user.enabled {
+            self.capture_systemd_units(sandbox_id).await;
+        }
+
+        // Last checkpoint: past here processes are touched and the pause runs to completion
+        checkpoint(&cancel, "pause", sandbox_id)?;
+        for (feature, missing) in [(Feature::Criu, "checkpoint processes with CRIU"), (Feature::OverlayCapture, "capture the overlay upper layer")] {
+            if self.feature_enabled(feature, sandbox_id) {
+                let message = format!("Feature {:?} is on for sandbox {}, but this agent cannot {}; pausing without it", feature, sandbox_id, missing);
+                self.warnings.emit(Warning::new(WarningKind::FeatureUnavailable, sandbox_id, message), &mut result.warnings);
+            }
+        }
+
+        match strategy {
+            PauseStrategy::Kill => {
+                // Kill all user processes gracefully
+                operation.set_phase("kill");
+                self.kill_all_processes(sandbox_id, options.deadline, &mut result).await?;
+                timings.insert("kill".to_string(), started.elapsed().as_millis() as u64);
+            }
+            // Auto was resolved when the pause was prepared
+            PauseStrategy::Persist | PauseStrategy::Auto => {
+                operation.set_phase("persist");
+                self.apply_explicit_policies(sandbox_id, options.deadline, &mut result).await?;
+                // Persist current process state for resume
+                self.persist_before_deadline(sandbox_id, None, options.deadline).await?;
+                timings.insert("persist".to_string(), started.elapsed().as_millis() as u64);
+            }
+            PauseStrategy::Throttle => {
+                operation.set_phase("clamp");
+                self.apply_explicit_policies(sandbox_id, options.deadline, &mut result).await?;
+                let cgroup = Cgroup::for_sandbox(&self.config.soft_pause, sandbox_id);
+                let original = cgroup.clamp(&self.config.soft_pause).await?;
+                timings.insert("clamp".to_string(), started.elapsed().as_millis() as u64);
+                let failure = self
+                    .persist_before_deadline(sandbox_id, Some(original.clone()), options.deadline)
+                    .await
+                    .err()
+                    .map(|e| e.to_string());
+                if let Some(reason) = failure {
+                    // Without a record of the original limits resume could not undo the clamp
+                    cgroup.restore(&original, &self.config.soft_pause).await?;
+                    return Err(format!("Failed to persist soft-paused sandbox {}: {}", sandbox_id, reason).into());
+                }
+                timings.insert("persist".to_string(), started.elapsed().as_millis() as u64);
+            }
+        }
+        
+        // Recorded in the snapshot by now
+        self.policy_frozen.lock().unwrap().remove(sandbox_id);
+        self.set_lifecycle_state(sandbox_id, LifecycleState::Paused).await;
+        operation.set_phase("post_pause_hooks");
+        self.run_hooks(HookPhase::PostPause, sandbox_id, Some(&mut result.warnings)).await?;
+        let elapsed = started.elapsed() + prepared.prepare_elapsed;
+        self.alarms.check(sandbox_id, AlarmPhase::Pause, elapsed, &timings);
+        if let Some(stats) = &self.usage_stats {
+            stats.record_pause(strategy, elapsed);
+        }
+        self.record_timeline(sandbox_id, TimelineEvent::Pause { duration_ms: elapsed.as_millis() as u64, strategy });
+        self.publish_audit(sandbox_id, "pause", elapsed, result.warnings.len());
+        info!(
+            target: "audit",
+            sandbox_id = sandbox_id,
+            operation = "pause",
+            duration_ms = elapsed.as_millis() as u64,
+            warnings = result.warnings.len();
+            "Paused sandbox {}", sandbox_id
+        );
+        self.last_pause.lock().unwrap().insert(SandboxId::new(sandbox_id), result.clone());
+        Ok(result)
+    }
+
+    fn publish_audit(&self, sandbox_id: &str, operation: &str, elapsed: Duration, warnings: usize) {
+        let entry = self.registry.get(sandbox_id);
+        let payload = EventPayload::Audit {
+            operation: operation.to_string(),
+            duration_ms: elapsed.as_millis() as u64,
+            warnings,
+            owner: entry.owner,
+            team: entry.team,
+        };
+        self.events.publish(sandbox_id, payload);
+    }
+
+    fn publish_failure(&self, sandbox_id: &str, operation: &str, error: &dyn std::error::Error) {
+        let payload = EventPayload::OperationFailed {
+            operation: operation.to_string(),
+            error: error.to_string(),
+        };
+        self.events.publish(sandbox_id, payload);
+    }
+
+    /// Caps for a sandbox's snapshots: its tenant's if configured, else the default
+    fn snapshot_caps(&self, sandbox_id: &str) -> &SnapshotCaps {
+        self.registry
+            .tenant(sandbox_id)
+            .and_then(|tenant| self.config.tenant_snapshot_caps.get(&tenant))
+            .unwrap_or(&self.config.snapshot_caps)
+    }
+
+    async fn persist_before_deadline(&self, sandbox_id: &str, cgroup_limits: Option<CgroupLimits>, deadline: Option<tokio::time::Instant>) -> Result<(), Box<dyn std::error::Error>> {
+        match deadline {
+            // Storage calls fail with StorageTimeout at the deadline; the outer timeout covers the rest
+            Some(deadline) => tokio::time::timeout_at(deadline, persistence::with_storage_deadline(Some(deadline), self.persist_process_state(sandbox_id, cgroup_limits)))
+                .await
+                .map_err(|_| SandboxError::Timeout(format!("persisting sandbox {} before the pause deadline", sandbox_id)))?,
+            None => self.persist_process_state(sandbox_id, cgroup_limits).await,
+        }
+    }
+
+    /// Refuse pauses of a protected sandbox the options do not allow; true when the
+    /// sandbox is protected and the pause is forced
+    async fn check_protection(&self, sandbox_id: &str, options: &PauseOptions) -> Result<bool, Box<dyn std::error::Error>> {
+        let protection = self.persistence_manager.load_protection(sandbox_id).await?;
+        if let Some(refusal) = protection::refusal(sandbox_id, protection.as_ref(), options) {
+            info!("{}", refusal);
+            return Err(refusal.into());
+        }
+        Ok(protection.is_some())
+    }
+
+    /// Hold a force-kill pause until someone other than `requested_by` approves it; it
+    /// fails when the approval times out or the pause is cancelled first
+    async fn await_approval(&self, sandbox_id: &str, operation_id: OperationId, requested_by: Option<&str>, cancel: &CancellationToken) -> Result<(), Box<dyn std::error::Error>> {
+        let timeout = Duration::from_secs(self.config.approval.timeout_secs);
+        let approved = self.approvals.request(operation_id, sandbox_id, requested_by);
+        self.events.publish(sandbox_id, EventPayload::Approval { operation_id, decision: ApprovalDecision::Requested, approver: None });
+        info!(
+            target: "audit",
+            sandbox_id = sandbox_id,
+            operation = "pause",
+            operation_id = operation_id;
+            "Force-kill pause of sandbox {} is waiting up to {:?} for approval", sandbox_id, timeout
+        );
+
+        let approver = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                self.approvals.withdraw(operation_id);
+                return Err(format!("Pause of sandbox {} cancelled while waiting for approval", sandbox_id).into());
+            }
+            approver = tokio::time::timeout(timeout, approved) => approver,
+        };
+        if let Ok(Ok(_)) = approver {
+            return Ok(());
+        }
+        self.approvals.withdraw(operation_id);
+        self.events.publish(sandbox_id, EventPayload::Approval { operation_id, decision: ApprovalDecision::TimedOut, approver: None });
+        info!(
+            target: "audit",
+            sandbox_id = sandbox_id,
+            operation = "pause",
+            operation_id = operation_id;
+            "Force-kill pause of sandbox {} was not approved within {:?}", sandbox_id, timeout
+        );
+        Err(format!("Pause of sandbox {} was not approved within {:?}", sandbox_id, timeout).into())
+    }
+
+    /// Abandon a prepared pause; the sandbox keeps running untouched
+    pub async fn abort(&self, mut prepared: PreparedPause) {
+        prepared.pending.release();
+        self.set_lifecycle_state(&prepared.sandbox_id, LifecycleState::Running).await;
+        info!("Aborted prepared pause of sandbox {}", prepared.sandbox_id);
+    }
+
+    /// Describe what `prepare_pause` would affect without touching any process
+    pub async fn dry_run_pause(&self, sandbox_id: &str) -> Result<DryRunReport, Box<dyn std::error::Error>> {
+        let processes = self.process_manager.list_live_processes(sandbox_id).await?;
+        let strategy = self.decide_strategy(sandbox_id).await?.strategy;
+
+        Ok(DryRunReport {
+            sandbox_id: sandbox_id.to_string(),
+            kill_on_pause: strategy == PauseStrategy::Kill,
+            strategy,
+            total_threads: processes.iter().map(|p| p.thread_count).sum(),
+            processes,
+            costs: self.estimate_costs(sandbox_id).await,
+        })
+    }
+
+    /// Kill all user processes in the sandbox, finishing before `deadline` if one is set
+    async fn kill_all_processes(&self, sandbox_id: &str, deadline: Option<tokio::time::Instant>, result: &mut PauseResult) -> Result<(), Box<dyn std::error::Error>> {
+        let processes = self.process_manager.list_live_processes(sandbox_id).await?;
+        self.kill_processes(sandbox_id, processes, deadline, result).await.map(|_| ())
+    }
+
+    /// Kill and freeze the processes whose policy asks for it explicitly;
+    /// used by strategies that otherwise leave processes running
+    async fn apply_explicit_policies(&self, sandbox_id: &str, deadline: Option<tokio::time::Instant>, result: &mut PauseResult) -> Result<(), Box<dyn std::error::Error>> {
+        let default_grace = Duration::from_secs(self.config.graceful_timeout_secs);
+        let may_freeze = self.feature_enabled(Feature::Freezer, sandbox_id);
+        let mut to_kill = Vec::new();
+        let mut to_freeze = Vec::new();
+        for process in self.process_manager.list_live_processes(sandbox_id).await? {
+            match self.policy.evaluate(&process, default_grace).action {
+                Some(PolicyAction::Kill) if !self.plugins.spare(&process).await => to_kill.push(process),
+                Some(PolicyAction::Freeze) if may_freeze => to_freeze.push(process),
+                Some(PolicyAction::Freeze) => info!("Leaving process {} ({}) of sandbox {} running: the freezer feature is off", process.pid, process.name, sandbox_id),
+                _ => {}
+            }
+        }
+        if !to_freeze.is_empty() {
+            self.freeze_by_policy(sandbox_id, &to_freeze, &mut result.warnings).await?;
+        }
+        if !to_kill.is_empty() {
+            self.kill_processes(sandbox_id, to_kill, deadline, result).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop processes whose policy freezes them, recording them for the snapshot so
+    /// the resume continues them
+    async fn freeze_by_policy(&self, sandbox_id: &str, processes: &[ProcessInfo], warnings: &mut Vec<Warning>) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
+        let frozen = self.set_suspended(sandbox_id, processes, true, warnings).await?;
+        self.policy_frozen.lock().unwrap().entry(SandboxId::new(sandbox_id)).or_default().extend(frozen.iter().copied());
+        Ok(frozen)
+    }
+
+    /// Terminate then force-kill `processes`, each on its own grace period and signal.
+    /// Processes the policy freezes or persists are recorded in the snapshot instead.
+    /// Returns the PIDs that were signalled, frozen ones included; spared and
+    /// persisted processes, and those every signal failed for, are left out.
+    async fn kill_processes(&self, sandbox_id: &str, processes: Vec<ProcessInfo>, deadline: Option<tokio::time::Instant>, result: &mut PauseResult) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
+        let default_grace = Duration::from_secs(self.config.graceful_timeout_secs);
+        let may_freeze = self.feature_enabled(Feature::Freezer, sandbox_id);
+        let mut targets = Vec::new();
+        let mut to_freeze = Vec::new();
+        let mut to_persist = 0;
+        for process in processes {
+            let verdict = self.policy.evaluate(&process, default_grace);
+            let action = match verdict.action {
+                _ if self.plugins.spare(&process).await => PolicyAction::Spare,
+                Some(PolicyAction::Freeze) if !may_freeze => {
+                    info!("Killing process {} ({}) of sandbox {}: the freezer feature is off", process.pid, process.name, sandbox_id);
+                    PolicyAction::Kill
+                }
+                action => action.unwrap_or(PolicyAction::Kill),
+            };
+            match action {
+                PolicyAction::Kill => targets.push((process.pid, verdict.graceful_timeout, verdict.signal)),
+                PolicyAction::Spare => info!("Sparing protected process {} ({}) in sandbox {}", process.pid, process.name, sandbox_id),
+                PolicyAction::Freeze => to_freeze.push(process),
+                PolicyAction::Persist => to_persist += 1,
+            }
+        }
+        let mut signalled = BTreeSet::new();
+        if !to_freeze.is_empty() {
+            signalled.extend(self.freeze_by_policy(sandbox_id, &to_freeze, &mut result.warnings).await?);
+        }
+        if !to_freeze.is_empty() || to_persist > 0 {
+            self.persist_before_deadline(sandbox_id, None, deadline).await?;
+        }
+
+        // Leave room for the forced phase when the deadline is tighter than a grace period
+        if let Some(deadline) = deadline {
+            let available = deadline.saturating_duration_since(tokio::time::Instant::now());
+            let budget = available.saturating_sub(KILL_PHASE_RESERVE);
+            if budget.is_zero() {
+                warn!("No time left before the deadline for graceful shutdown of sandbox {}", sandbox_id);
+            }
+            for (_, grace_period, _) in targets.iter_mut() {
+                *grace_period = (*grace_period).min(budget);
+            }
+        }
+        targets.sort_by_key(|(_, grace_period, _)| *grace_period);
+        let pids: Vec<i32> = targets.iter().map(|(pid, _, _)| *pid).collect();
+
+        // Ask all process groups to shut down first (graceful shutdown)
+        let graceful: Vec<(i32, GroupSignal)> = targets
+            .iter()
+            .filter(|(_, grace_period, _)| !grace_period.is_zero())
+            .map(|(pid, _, signal)| (*pid, *signal))
+            .collect();
+        let sent = backend::signal_batch(Arc::clone(&self.backend), graceful.clone()).await;
+        for ((pid, _), outcome) in graceful.iter().zip(sent) {
+            match outcome {
+                Ok(()) => {
+                    signalled.insert(*pid);
+                }
+                Err(e) => {
+                    let message = format!("Failed to terminate process group {}: {}", pid, e);
+                    self.warnings.emit(Warning::new(WarningKind::SignalFailed, sandbox_id, message).with_pid(*pid), &mut result.warnings);
+                }
+            }
+        }
+
+        // Give each process its own grace period, shortest first, then force kill it
+        let started = tokio::time::Instant::now();
+        let mut forced = 0;
+        // One buffer for every poll below; mass pauses run this loop for hundreds of sandboxes at once
+        let mut live = Vec::with_capacity(pids.len());
+        for (pid, grace_period, _) in &targets {
+            if self.wait_for_exit_until(sandbox_id, *pid, started + *grace_period, &mut live).await {
+                continue;
+            }
+            forced += 1;
+            match self.backend.signal_group(*pid, GroupSignal::Kill) {
+                Ok(()) => {
+                    signalled.insert(*pid);
+                }
+                Err(e) => {
+                    let message = format!("Failed to kill process group {}: {}", pid, e);
+                    self.warnings.emit(Warning::new(WarningKind::SignalFailed, sandbox_id, message).with_pid(*pid), &mut result.warnings);
+                }
+            }
+        }
+        let signalled: Vec<i32> = signalled.into_iter().collect();
+        if forced == 0 {
+            info!("All processes exited gracefully");
+            return Ok(signalled);
+        }
+        warn!("Graceful shutdown timed out for {} processes in sandbox {}, forced kill", forced, sandbox_id);
+
+        // With a deadline, confirm the kill landed in time and report what is left otherwise
+        if let Some(deadline) = deadline {
+            let confirmed = tokio::time::timeout_at(deadline, self.wait_for_processes_to_exit(sandbox_id, &pids, &mut live)).await;
+            if confirmed.is_err() {
+                result.deadline_exceeded = true;
+                self.process_manager.remaining_pids(sandbox_id, &mut live).await;
+                live.retain(|pid| pids.contains(pid));
+                result.remaining_pids = live;
+                warn!(
+                    "Pause deadline for sandbox {} reached with {} processes remaining",
+                    sandbox_id,
+                    result.remaining_pids.len()
+                );
+            }
+        }
+
+        Ok(signalled)
+    }
+
+    /// Wait for `pid` to exit by `until`, on the backend's exit notification where it
+    /// has one and by polling otherwise; returns whether it exited
+    async fn wait_for_exit_until(&self, sandbox_id: &str, pid: i32, until: tokio::time::Instant, live: &mut Vec<i32>) -> bool {
+        if self.backend.notifies_exit() {
+            let backend = Arc::clone(&self.backend);
+            let wait = until.saturating_duration_since(tokio::time::Instant::now());
+            match tokio::task::spawn_blocking(move || backend.wait_for_exit(pid, wait)).await {
+                Ok(Ok(exited)) => return exited,
+                Ok(Err(e)) => warn!("Exit notification for process {} failed, polling instead: {}", pid, e),
+                Err(e) => warn!("Exit notification task for process {} failed, polling instead: {}", pid, e),
+            }
+        }
+        tokio::time::timeout_at(until, self.wait_for_processes_to_exit(sandbox_id, &[pid], live)).await.is_ok()
+    }
+
+    /// Wait until none of `pids` is tracked as live in the sandbox, polling into `live`.
+    /// Never gives up by itself: callers bound it by the grace period or the deadline.
+    async fn wait_for_processes_to_exit(&self, sandbox_id: &str, pids: &[i32], live: &mut Vec<i32>) {
+        let check_interval = Duration::from_millis(500);
+        loop {
+            self.process_manager.remaining_pids(sandbox_id, live).await;
+            if !live.iter().any(|pid| pids.contains(pid)) {
+                return;
+            }
+            tokio::time::sleep(check_interval).await;
+        }
+    }
+
+    async fn persist_process_state(&self, sandbox_id: &str, cgroup_limits: Option<CgroupLimits>) -> Result<(), Box<dyn std::error::Error>> {
+        let processes = self.process_manager.list_processes(sandbox_id).await?;
+        let sessions = sessions::summarize(&processes);
+        let (processes, mut fds, mut locks, mut launch, mut ppids, mut uids, cut_argv) = blocking::run("capture_fds_locks", move || {
+            let fds = capture_fds(&processes);
+            let locks = capture_locks(&processes);
+            let ppids: HashMap<i32, i32> = processes.iter().filter_map(|p| process_tree::parent_pid(p.pid).map(|ppid| (p.pid, ppid))).collect();
+            let uids: HashMap<i32, u32> = processes.iter().filter_map(|p| adoption::process_uid(p.pid).map(|uid| (p.pid, uid))).collect();
+            let launch: HashMap<i32, LaunchContext> = processes
+                .iter()
+                .filter(|p| p.state != ProcessState::Terminated)
+                .filter_map(|p| launch_hints::capture(p.pid).map(|context| (p.pid, context)))
+                .collect();
+            let cut_argv = capture_cut_argv(&processes);
+            (processes, fds, locks, launch, ppids, uids, cut_argv)
+        })
+        .await;
+        
+        let persisted_processes: Vec<PersistedProcess> = processes
+            .into_iter()
+            .map(|p| PersistedProcess {
+                pid: p.pid,
+                name: p.name,
+                cmd: p.cmd,
+                argv: p.argv,
+                start_time: p.start_time,
+                state: match p.state {
+                    ProcessState::Running => "running",
+                    ProcessState::Suspended => "suspended",
+                    ProcessState::Terminated => "terminated",
+                }
+                .to_string(),
+                thread_count: p.thread_count,
+                child_count: p.child_count,
+                ppid: ppids.remove(&p.pid),
+                fds: fds.remove(&p.pid),
+                locks: locks.remove(&p.pid).unwrap_or_default(),
+                exit: p.exit,
+                ended_at: p.ended_at,
+                labels: p.labels,
+                session: p.session,
+                launch: launch.remove(&p.pid),
+                uid: uids.remove(&p.pid),
+                argv_truncated: cut_argv.contains(&p.pid),
+            })
+            .collect();
+
+        let entry = self.registry.get(sandbox_id);
+        let mut snapshot = StateSnapshot {
+            sandbox_id: SandboxId::new(sandbox_id),
+            timestamp: chrono::Utc::now(),
+            processes: persisted_processes,
+            sessions,
+            cgroup_limits,
+            oom_events: self.process_manager.oom_events(sandbox_id).await,
+            truncations: Vec::new(),
+            owner: entry.owner,
+            team: entry.team,
+            frozen: self.policy_frozen.lock().unwrap().get(sandbox_id).cloned().unwrap_or_default(),
+        };
+        snapshot.apply_caps(self.snapshot_caps(sandbox_id));
+        if !snapshot.truncations.is_empty() {
+            warn!("Snapshot of sandbox {} exceeded its size caps, {} fields truncated", sandbox_id, snapshot.truncations.len());
+        }
+
+        let save_started = Instant::now();
+        self.persistence_manager.save_snapshot(&snapshot).await?;
+        let save_elapsed = save_started.elapsed();
+        let timings = PhaseTimings::from([("save".to_string(), save_elapsed.as_millis() as u64)]);
+        self.alarms.check(sandbox_id, AlarmPhase::SnapshotSave, save_elapsed, &timings);
+        if let Ok(metadata) = tokio::fs::metadata(self.persistence_manager.layout(sandbox_id).snapshot_file()).await {
+            self.record_timeline(sandbox_id, TimelineEvent::Snapshot { bytes: metadata.len() });
+        }
+        info!("Persisted {} processes for sandbox {}", snapshot.processes.len(), sandbox_id);
+        
+        Ok(())
+    }
+
+    /// Signal every live process in the sandbox matched by `selector`,
+    /// returning the PIDs that were signalled
+    pub async fn signal_selected(&self, sandbox_id: &str, selector: &Selector, signal: GroupSignal) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
+        let mut signalled = Vec::new();
+        for process in self.process_manager.select(sandbox_id, selector).await {
+            if process.state == ProcessState::Terminated {
+                continue;
+            }
+            match self.backend.signal_group(process.pid, signal) {
+                Ok(()) => signalled.push(process.pid),
+                Err(e) => warn!("Failed to signal process group {} in sandbox {}: {}", process.pid, sandbox_id, e),
+            }
+        }
+        info!("Sent {:?} to {} processes in sandbox {} matching '{}'", signal, signalled.len(), sandbox_id, selector);
+        Ok(signalled)
+    }
+
+    /// Pause only the processes matched by `selector` while the rest of the
+    /// sandbox keeps running: with the kill strategy they are killed, otherwise
+    /// they are suspended and the snapshot is updated to record it (a cgroup
+    /// clamp cannot target individual processes)
+    pub async fn pause_processes(&self, sandbox_id: &str, selector: &Selector) -> Result<PartialPauseResult, Box<dyn std::error::Error>> {
+        let cancel = self.operation_token();
+        let operation = self.operations.begin(sandbox_id, OperationKind::Pause, cancel.clone(), None);
+        let _slot = self.acquire_operation_slot(sandbox_id, &cancel).await?;
+        operation.set_phase("partial_pause");
+        let targets: Vec<ProcessInfo> = self
+            .process_manager
+            .select(sandbox_id, selector)
+            .await
+            .into_iter()
+            .filter(|p| p.state == ProcessState::Running)
+            .collect();
+        let mut result = PartialPauseResult {
+            sandbox_id: sandbox_id.to_string(),
+            pids: targets.iter().map(|p| p.pid).collect(),
+            ..Default::default()
+        };
+
+        if self.partial_strategy(sandbox_id).await?.strategy == PauseStrategy::Kill {
+            let mut kill_result = PauseResult::default();
+            result.pids = self.kill_processes(sandbox_id, targets, None, &mut kill_result).await?;
+            result.warnings = kill_result.warnings;
+        } else {
+            result.pids = self.set_suspended(sandbox_id, &targets, true, &mut result.warnings).await?;
+            self.persist_process_state(sandbox_id, None).await?;
+        }
+
+        info!("Paused {} processes in sandbox {} matching '{}'", result.pids.len(), sandbox_id, selector);
+        Ok(result)
+    }
+
+    /// Resume suspended processes matched by `selector`. Processes killed by
+    /// a partial pause are not relaunched.
+    pub async fn resume_processes(&self, sandbox_id: &str, selector: &Selector) -> Result<PartialPauseResult, Box<dyn std::error::Error>> {
+        let cancel = self.operation_token();
+        let operation = self.operations.begin(sandbox_id, OperationKind::Resume, cancel.clone(), None);
+        let _slot = self.acquire_operation_slot(sandbox_id, &cancel).await?;
+        operation.set_phase("partial_resume");
+        let targets: Vec<ProcessInfo> = self
+            .process_manager
+            .select(sandbox_id, selector)
+            .await
+            .into_iter()
+            .filter(|p| p.state == ProcessState::Suspended)
+            .collect();
+        let mut result = PartialPauseResult {
+            sandbox_id: sandbox_id.to_string(),
+            ..Default::default()
+        };
+
+        result.pids = self.set_suspended(sandbox_id, &targets, false, &mut result.warnings).await?;
+        if self.paused_strategy(sandbox_id).await != PauseStrategy::Kill {
+            self.persist_process_state(sandbox_id, None).await?;
+        }
+
+        info!("Resumed {} processes in sandbox {} matching '{}'", result.pids.len(), sandbox_id, selector);
+        Ok(result)
+    }
+
+    /// Suspend or resume each process, returning the PIDs that changed state
+    async fn set_suspended(&self, sandbox_id: &str, processes: &[ProcessInfo], suspend: bool, warnings: &mut Vec<Warning>) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
+        let mut changed = Vec::new();
+        for process in processes {
+            let (outcome, state) = if suspend {
+                (self.backend.suspend(process.pid), ProcessState::Suspended)
+            } else {
+                (self.backend.resume(process.pid), ProcessState::Running)
+            };
+            match outcome {
+                Ok(()) => {
+                    self.process_manager.update_process_state(sandbox_id, process.pid, state).await?;
+                    changed.push(process.pid);
+                }
+                Err(e) => {
+                    let message = format!("Failed to {} process {}: {}", if suspend { "suspend" } else { "resume" }, process.pid, e);
+                    self.warnings.emit(Warning::new(WarningKind::SignalFailed, sandbox_id, message).with_pid(process.pid), warnings);
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Forget a sandbox that was deleted: its tracked processes, registry entry, cached
+    /// lifecycle state, last reports and stored files. Its snapshot, if any, is left to
+    /// retention, which removes the sandbox's directory with it.
+    pub async fn remove_sandbox(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.process_manager.clear_sandbox(sandbox_id).await?;
+        if let Err(e) = self.persistence_manager.remove_sandbox_files(sandbox_id).await {
+            warn!("Failed to remove the stored files of sandbox {}: {}", sandbox_id, e);
+        }
+        self.registry.remove(sandbox_id);
+        self.lifecycle.lock().unwrap().remove(sandbox_id);
+        self.last_pause.lock().unwrap().remove(sandbox_id);
+        self.last_resume.lock().unwrap().remove(sandbox_id);
+        self.detach_channel(sandbox_id);
+        self.stats.remove(sandbox_id);
+        info!("Removed sandbox {}", sandbox_id);
+        Ok(())
+    }
+
+    /// Report the current status of a sandbox
+    pub async fn status(&self, sandbox_id: &str) -> Result<SandboxStatus, Box<dyn std::error::Error>> {
+        let processes = self.process_manager.list_live_processes(sandbox_id).await?;
+
+        Ok(SandboxStatus {
+            sandbox_id: sandbox_id.to_string(),
+            process_count: processes.len(),
+            has_snapshot: self.persistence_manager.snapshot_exists(sandbox_id),
+            oom_events: self.process_manager.oom_events(sandbox_id).await,
+        })
+    }
+
+    /// Restore sandbox after auto-resume
+    pub async fn after_resume(&self, sandbox_id: &str) -> Result<ResumeReport, Box<dyn std::error::Error>> {
+        self.after_resume_with(sandbox_id, self.operation_token()).await
+    }
+
+    /// Restore sandbox after auto-resume, stopping at the next checkpoint once `cancel` fires.
+    /// Before the restore the sandbox stays paused; after it, the optional phases are skipped.
+    pub async fn after_resume_with(&self, sandbox_id: &str, cancel: CancellationToken) -> Result<ResumeReport, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let operation = self.operations.begin(sandbox_id, OperationKind::Resume, cancel.clone(), None);
+        let outcome = self.resume_sandbox(sandbox_id, &cancel, &operation).await;
+        if let Some(stats) = &self.usage_stats {
+            match &outcome {
+                Ok(_) => stats.record_resume(started.elapsed()),
+                Err(_) => stats.record_resume_failure(),
+            }
+        }
+        if let Err(e) = &outcome {
+            self.publish_failure(sandbox_id, "resume", e.as_ref());
+        }
+        outcome
+    }
+
+    async fn resume_sandbox(&self, sandbox_id: &str, cancel: &CancellationToken, operation: &OperationGuard) -> Result<ResumeReport, Box<dyn std::error::Error>> {
+        info!("Restoring sandbox {} after auto-resume", sandbox_id);
+        let slot = self.acquire_operation_slot(sandbox_id, cancel).await?;
+        let started = Instant::now();
+        // The banner needs the pause time, which the Resuming record overwrites
+        let paused = if self.config.resume_banner.enabled {
+            self.persistence_manager.load_lifecycle(sandbox_id).await.ok().flatten()
+        } else {
+            None
+        };
+        self.set_lifecycle_state(sandbox_id, LifecycleState::Resuming).await;
+        
+        let mut report = ResumeReport {
+            sandbox_id: sandbox_id.to_string(),
+            ..Default::default()
+        };
+        let mut progress = match self.persistence_manager.load_restore_checkpoint(sandbox_id).await {
+            Ok(Some(progress)) => {
+                info!("Continuing interrupted resume of sandbox {} after {:?}", sandbox_id, progress.completed);
+                report.resumed_steps = progress.completed.iter().copied().collect();
+                progress
+            }
+            Ok(None) => RestoreCheckpoint::new(),
+            Err(e) => {
+                warn!("Failed to load restore checkpoint of sandbox {}, restoring from the start: {}", sandbox_id, e);
+                RestoreCheckpoint::new()
+            }
+        };
+        operation.set_phase("pre_resume_hooks");
+        if !progress.is_done(RestoreStep::PreResumeHooks) {
+            if let Err(e) = self.run_hooks(HookPhase::PreResume, sandbox_id, Some(&mut report.warnings)).await {
+                self.set_lifecycle_state(sandbox_id, LifecycleState::Paused).await;
+                return Err(e.into());
+            }
+            self.complete_restore_step(sandbox_id, &mut progress, RestoreStep::PreResumeHooks).await;
+        }
+        if let Err(e) = checkpoint(cancel, "resume", sandbox_id) {
+            self.set_lifecycle_state(sandbox_id, LifecycleState::Paused).await;
+            return Err(e.into());
+        }
+
+        operation.set_phase("restore");
+        let strategy = self.paused_strategy(sandbox_id).await;
+        match strategy {
+            PauseStrategy::Kill => self.thaw_frozen(sandbox_id, &mut report).await?,
+            // paused_strategy never returns Auto
+            PauseStrategy::Persist | PauseStrategy::Auto => {
+                // Load persisted process state
+                self.restore_process_state(sandbox_id, &mut report).await?;
+            }
+            PauseStrategy::Throttle => {
+                if !progress.is_done(RestoreStep::SoftPauseReleased) {
+                    self.release_soft_pause(sandbox_id).await?;
+                    self.complete_restore_step(sandbox_id, &mut progress, RestoreStep::SoftPauseReleased).await;
+                }
+                self.restore_process_state(sandbox_id, &mut report).await?;
+            }
+        }
+
+        // The processes are back; cancellation from here on only skips the optional phases.
+        // Health probes may wait out their thresholds, so they run after the slot is free
+        // for the next pause or resume.
+        drop(slot);
+        operation.set_phase("optional_phases");
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                let message = format!("Resume of sandbox {} cancelled after restore; skipped job, unit, probe and banner phases", sandbox_id);
+                self.warnings.emit(Warning::new(WarningKind::ResumeCancelled, sandbox_id, message), &mut report.warnings);
+            }
+            _ = self.resume_optional_phases(sandbox_id, strategy, paused.as_ref(), &mut report, &mut progress) => {}
+        }
+        
+        self.set_lifecycle_state(sandbox_id, LifecycleState::Running).await;
+        operation.set_phase("post_resume_hooks");
+        self.run_hooks(HookPhase::PostResume, sandbox_id, Some(&mut report.warnings)).await?;
+        if let Err(e) = self.persistence_manager.remove_restore_checkpoint(sandbox_id).await {
+            warn!("Failed to remove restore checkpoint of sandbox {}: {}", sandbox_id, e);
+        }
+        let timings = PhaseTimings::from([("restore".to_string(), started.elapsed().as_millis() as u64)]);
+        self.alarms.check(sandbox_id, AlarmPhase::Resume, started.elapsed(), &timings);
+        self.record_timeline(sandbox_id, TimelineEvent::Resume { duration_ms: started.elapsed().as_millis() as u64 });
+        self.publish_audit(sandbox_id, "resume", started.elapsed(), report.warnings.len());
+        info!(
+            target: "audit",
+            sandbox_id = sandbox_id,
+            operation = "resume",
+            duration_ms = started.elapsed().as_millis() as u64,
+            warnings = report.warnings.len();
+            "Resumed sandbox {}", sandbox_id
+        );
+        self.last_resume.lock().unwrap().insert(SandboxId::new(sandbox_id), report.clone());
+        Ok(report)
+    }
+
+    /// Scheduled jobs, systemd user units, resume probes and the banner, in that order,
+    /// skipping those an interrupted attempt already completed
+    async fn resume_optional_phases(&self, sandbox_id: &str, strategy: PauseStrategy, paused: Option<&LifecycleRecord>, report: &mut ResumeReport, progress: &mut RestoreCheckpoint) {
+        if self.config.scheduled_jobs.enabled && !progress.is_done(RestoreStep::ScheduledJobs) {
+            report.scheduled_jobs = self.restore_scheduled_jobs(sandbox_id, progress).await;
+            self.complete_restore_step(sandbox_id, progress, RestoreStep::ScheduledJobs).await;
+        }
+        if self.config.systemd_user.enabled && !progress.is_done(RestoreStep::SystemdUnits) {
+            report.systemd_units = self.restore_systemd_units(sandbox_id).await;
+            self.complete_restore_step(sandbox_id, progress, RestoreStep::SystemdUnits).await;
+        }
+        if !self.config.resume_probes.is_empty() {
+            report.health = probes::run_all(&self.config.resume_probes).await;
+            for probe in report.health.iter().filter(|p| p.status != ProbeStatus::Healthy) {
+                let message = format!("Probe {:?} is {:?} after resume: {}", probe.kind, probe.status, probe.last.message);
+                self.warnings.emit(Warning::new(WarningKind::ProbeFailed, sandbox_id, message), &mut report.warnings);
+            }
+        }
+        if self.config.resume_banner.enabled && !progress.is_done(RestoreStep::Banner) {
+            let banner = ResumeBanner::new(strategy, paused, report);
+            report.banner = Some(resume_banner::publish(&self.config.resume_banner, sandbox_id, banner).await);
+            self.complete_restore_step(sandbox_id, progress, RestoreStep::Banner).await;
+        }
+    }
+
+    /// Record a finished entry of a resume step, e.g. one missed job started, before the next
+    async fn complete_restore_entry(&self, sandbox_id: &str, progress: &mut RestoreCheckpoint, step: RestoreStep, entry: String) {
+        progress.entries.entry(step).or_default().insert(entry);
+        if let Err(e) = self.persistence_manager.save_restore_checkpoint(sandbox_id, progress).await {
+            warn!("Failed to save restore checkpoint of sandbox {} during {:?}: {}", sandbox_id, step, e);
+        }
+    }
+
+    /// Record a finished resume step; a failure to persist only risks repeating the step
+    async fn complete_restore_step(&self, sandbox_id: &str, progress: &mut RestoreCheckpoint, step: RestoreStep) {
+        progress.completed.insert(step);
+        if let Err(e) = self.persistence_manager.save_restore_checkpoint(sandbox_id, progress).await {
+            warn!("Failed to save restore checkpoint of sandbox {} after {:?}: {}", sandbox_id, step, e);
+        }
+    }
+
+    /// Run the hooks of a phase, raising warnings for failures; without a result
+    /// to attach them to, warnings are only broadcast
+    async fn run_hooks(&self, phase: HookPhase, sandbox_id: &str, mut warnings: Option<&mut Vec<Warning>>) -> Result<(), String> {
+        let failures: Vec<HookFailure> = self.hooks.run(phase, sandbox_id).await?;
+        for failure in failures {
+            let message = format!("{:?} hook {} failed: {}", phase, failure.name, failure.error);
+            let warning = Warning::new(WarningKind::HookFailed, sandbox_id, message);
+            match warnings.as_deref_mut() {
+                Some(collected) => self.warnings.emit(warning, collected),
+                None => self.warnings.publish(warning),
+            }
+        }
+        Ok(())
+    }
+
+    /// Strategy for pausing a sandbox now, resolving `auto` from its current workload
+    pub async fn decide_strategy(&self, sandbox_id: &str) -> Result<StrategyDecision, Box<dyn std::error::Error>> {
+        let configured = self.config.strategy();
+        if configured != PauseStrategy::Auto {
+            return Ok(StrategyDecision::configured(configured));
+        }
+        let processes = self.process_manager.list_live_processes(sandbox_id).await?;
+        let entry = self.registry.get(sandbox_id);
+        let priority = self.config.auto_strategy.priority(entry.priority, entry.tenant.as_deref());
+        let config = self.config.auto_strategy.clone();
+        let mut workload = blocking::run("inspect_workload", move || Workload::inspect(&config, &processes, priority)).await;
+        workload.costs = self.estimate_costs(sandbox_id).await;
+        let decision = strategy_select::select(&self.config.auto_strategy, &workload);
+        info!("Auto strategy for sandbox {}: {:?} ({})", sandbox_id, decision.strategy, decision.reason);
+        Ok(decision)
+    }
+
+    /// Strategy of a partial pause. While processes of an earlier partial pause are still
+    /// suspended its recorded decision is reused, so one sandbox is not half frozen and
+    /// half killed; otherwise a new decision is made and recorded for the resume.
+    async fn partial_strategy(&self, sandbox_id: &str) -> Result<StrategyDecision, Box<dyn std::error::Error>> {
+        if self.config.strategy() != PauseStrategy::Auto {
+            return self.decide_strategy(sandbox_id).await;
+        }
+        let suspended = self.process_manager.list_processes(sandbox_id).await?.iter().any(|p| p.state == ProcessState::Suspended);
+        if suspended {
+            match self.persistence_manager.load_strategy_decision(sandbox_id).await {
+                Ok(Some(decision)) => return Ok(decision),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to load strategy decision of sandbox {}, deciding again: {}", sandbox_id, e),
+            }
+        }
+        let decision = self.decide_strategy(sandbox_id).await?;
+        self.persistence_manager.save_strategy_decision(sandbox_id, &decision).await?;
+        Ok(decision)
+    }
+
+    /// Expected pause duration, snapshot size and resume time of each strategy for a sandbox
+    /// Drawn from the history store's index when configured, otherwise from the in-memory timelines.
+    pub async fn estimate_costs(&self, sandbox_id: &str) -> Vec<StrategyCost> {
+        let Some(history) = &self.history else {
+            let own = self.stats.sandbox(sandbox_id).map(|stats| stats.timeline).unwrap_or_default();
+            let fleet: Vec<Vec<TimelinePoint>> = self.stats.all().into_iter().filter(|stats| stats.sandbox_id != sandbox_id).map(|stats| stats.timeline).collect();
+            return cost_estimate::estimate(&own, &[], &fleet);
+        };
+        let own = match history.get(sandbox_id) {
+            Ok(own) => own,
+            Err(e) => {
+                warn!("Failed to read history of sandbox {}, estimating without it: {}", sandbox_id, e);
+                return cost_estimate::estimate(&[], &[], &[]);
+            }
+        };
+        let others = |timelines: Vec<(String, Vec<TimelinePoint>)>| -> Vec<Vec<TimelinePoint>> {
+            timelines.into_iter().filter(|(sandbox, _)| sandbox != sandbox_id).map(|(_, timeline)| timeline).collect()
+        };
+        let template = match history.template_of(sandbox_id) {
+            Some(template) => others(history.template_history(&template)),
+            None => Vec::new(),
+        };
+        cost_estimate::estimate(&own, &template, &others(history.all()))
+    }
+
+    /// Strategy the sandbox's last pause used. Without a record of an auto decision,
+    /// persist is assumed: verifying the processes is harmless if they were killed.
+    async fn paused_strategy(&self, sandbox_id: &str) -> PauseStrategy {
+        let configured = self.config.strategy();
+        if configured != PauseStrategy::Auto {
+            return configured;
+        }
+        match self.persistence_manager.load_strategy_decision(sandbox_id).await {
+            Ok(Some(decision)) => decision.strategy,
+            Ok(None) => PauseStrategy::Persist,
+            Err(e) => {
+                warn!("Failed to load the pause strategy of sandbox {}, assuming persist: {}", sandbox_id, e);
+                PauseStrategy::Persist
+            }
+        }
+    }
+
+    /// Root filesystem of a live process of the sandbox; spools outside one are the host's
+    async fn sandbox_root(&self, sandbox_id: &str) -> Option<SandboxRoot> {
+        let pids: Vec<i32> = self.process_manager.list_live_processes(sandbox_id).await.ok()?.iter().map(|process| process.pid).collect();
+        let root = blocking::run("sandbox_root", move || SandboxRoot::find(&pids)).await;
+        if root.is_none() {
+            warn!("No live process of sandbox {} runs under its own root, skipping its scheduled jobs", sandbox_id);
+        }
+        root
+    }
+
+    /// Best effort: a failed capture must not block the pause
+    async fn capture_scheduled_jobs(&self, sandbox_id: &str) {
+        let Some(root) = self.sandbox_root(sandbox_id).await else {
+            return;
+        };
+        let captured = match scheduled_jobs::capture(&self.config.scheduled_jobs, &root).await {
+            Ok(jobs) => self.persistence_manager.save_scheduled_jobs(sandbox_id, &jobs).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = captured {
+            warn!("Failed to capture scheduled jobs of sandbox {}: {}", sandbox_id, e);
+        }
+    }
+
+    /// Missed jobs are checkpointed one by one: a retried resume does not start any twice
+    async fn restore_scheduled_jobs(&self, sandbox_id: &str, progress: &mut RestoreCheckpoint) -> Option<ScheduledJobsRestore> {
+        let jobs = match self.persistence_manager.load_scheduled_jobs(sandbox_id).await {
+            Ok(Some(jobs)) => jobs,
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("Failed to load scheduled jobs of sandbox {}: {}", sandbox_id, e);
+                return None;
+            }
+        };
+        let root = self.sandbox_root(sandbox_id).await?;
+        let mut restored = ScheduledJobsRestore::default();
+        match scheduled_jobs::restore_spools(&jobs, &root).await {
+            Ok(rewritten) => restored.rewritten = rewritten,
+            Err(e) => {
+                warn!("Failed to restore scheduled jobs of sandbox {}: {}", sandbox_id, e);
+                return None;
+            }
+        }
+        for (mut job, run) in scheduled_jobs::missed_jobs(&jobs, &self.config.scheduled_jobs) {
+            let key = job.key();
+            if run && progress.is_entry_done(RestoreStep::ScheduledJobs, &key) {
+                info!("Missed job of {} in sandbox {} was started by the interrupted resume: {}", job.user, sandbox_id, job.command);
+                job.ran = true;
+            } else if run {
+                job.ran = scheduled_jobs::start_missed(&root, &job);
+                if job.ran {
+                    self.complete_restore_entry(sandbox_id, progress, RestoreStep::ScheduledJobs, key).await;
+                }
+            }
+            restored.missed.push(job);
+        }
+        info!(
+            "Restored scheduled jobs of sandbox {}: {} spool files rewritten, {} missed cron jobs",
+            sandbox_id,
+            restored.rewritten.len(),
+            restored.missed.len()
+        );
+        Some(restored)
+    }
+
+    /// Best effort, like scheduled jobs; also labels tracked processes with the unit they run under
+    async fn capture_systemd_units(&self, sandbox_id: &str) {
+        let mut pids = Vec::new();
+        self.process_manager.remaining_pids(sandbox_id, &mut pids).await;
+        let units = blocking::run("read_cgroups", move || {
+            pids.into_iter()
+                .filter_map(|pid| {
+                    let cgroup = std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).unwrap_or_default();
+                    systemd_user::user_unit_of(&cgroup).map(|unit| (pid, unit))
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+        for (pid, unit) in units {
+            self.process_manager.set_label(sandbox_id, pid, systemd_user::UNIT_LABEL, &unit).await;
+        }
+        let captured = match systemd_user::capture().await {
+            Ok(units) => self.persistence_manager.save_systemd_units(sandbox_id, &units).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = captured {
+            warn!("Failed to capture systemd user units of sandbox {}: {}", sandbox_id, e);
+        }
+    }
+
+    async fn restore_systemd_units(&self, sandbox_id: &str) -> Option<SystemdUnitsRestore> {
+        match self.persistence_manager.load_systemd_units(sandbox_id).await {
+            Ok(Some(units)) => Some(systemd_user::restore(&units).await),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Failed to load systemd user units of sandbox {}: {}", sandbox_id, e);
+                None
+            }
+        }
+    }
+
+    /// Put back the cgroup limits recorded when the sandbox was throttled
+    async fn release_soft_pause(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let original = match self.persistence_manager.load_snapshot(sandbox_id).await? {
+            Some(StateSnapshot { cgroup_limits: Some(limits), .. }) => limits,
+            _ => {
+                warn!("No recorded cgroup limits for sandbox {}, lifting the clamp entirely", sandbox_id);
+                CgroupLimits::default()
+            }
+        };
+        Cgroup::for_sandbox(&self.config.soft_pause, sandbox_id)
+            .restore(&original, &self.config.soft_pause)
+            .await?;
+        Ok(())
+    }
+
+    /// Restore process state from persistence
+    async fn restore_process_state(&self, sandbox_id: &str, report: &mut ResumeReport) -> Result<(), Box<dyn std::error::Error>> {
+        // Keep cleanup away from the snapshot while the resume reads it
+        let _pin = self.persistence_manager.pin_snapshot(sandbox_id);
+        let mut snapshot = match self.persistence_manager.load_snapshot_detailed(sandbox_id).await? {
+            SnapshotLoad::Loaded(snapshot) => snapshot,
+            SnapshotLoad::Missing => {
+                warn!("No persisted state found for sandbox {}", sandbox_id);
+                return Ok(());
+            }
+            SnapshotLoad::Stale => {
+                let message = format!("Snapshot for sandbox {} was stale and has been removed", sandbox_id);
+                self.warnings.emit(Warning::new(WarningKind::StaleSnapshotRemoved, sandbox_id, message), &mut report.warnings);
+                return Ok(());
+            }
+        };
+
+        info!("Restoring {} processes for sandbox {}", snapshot.processes.len(), sandbox_id);
+        snapshot.processes = restore_order::in_restore_order(sandbox_id, &snapshot.processes);
+        // Retained terminated entries are restored for inspection only
+        let live: Vec<PersistedProcess> = snapshot.processes.iter().filter(|p| p.state != "terminated").cloned().collect();
+        let backend = Arc::clone(&self.backend);
+        let snapshot_at = snapshot.timestamp;
+        let scope = SandboxScope::of(Cgroup::for_sandbox(&self.config.soft_pause, sandbox_id).path(), &live);
+        let (live, restored, lock_conflicts) = blocking::run("verify_restored", move || {
+            let mut restored = verify_restored(backend.as_ref(), &live).map_err(|e| e.to_string())?;
+            adopt_survivors(&live, snapshot_at, &scope, &mut restored);
+            let lock_conflicts = detect_lock_conflicts(&live, &restored);
+            Ok::<_, String>((live, restored, lock_conflicts))
+        })
+        .await?;
+        // Adopted entries are tracked under the PID they survived as
+        let adopted: HashMap<i32, i32> = restored.iter().filter_map(|p| Some((p.pid, p.adopted_pid?))).collect();
+        for process in snapshot.processes.iter_mut() {
+            if let Some(&pid) = adopted.get(&process.pid).filter(|_| process.state != "terminated") {
+                info!("Adopting surviving process {} ({}) of sandbox {} as {}", process.pid, process.name, sandbox_id, pid);
+                process.pid = pid;
+            }
+        }
+        report.restored = restored;
+        report.non_restorable = non_restorable_resources(&live);
+        report.lock_conflicts = lock_conflicts;
+        for conflict in &report.lock_conflicts {
+            let message = format!(
+                "Process {} ({}) cannot reacquire {} lock on {}: held by {}",
+                conflict.pid,
+                conflict.name,
+                conflict.lock.kind,
+                conflict.lock.path.as_deref().unwrap_or(&conflict.lock.dev_inode),
+                conflict.holder_pid
+            );
+            self.warnings.emit(Warning::new(WarningKind::LockConflict, sandbox_id, message).with_pid(conflict.pid), &mut report.warnings);
+        }
+
+        // Processes of systemd user services come back when their unit is started later
+        // in the resume, under new PIDs, so their old entries are not reported missing
+        let units: HashMap<i32, String> = if self.config.systemd_user.enabled {
+            snapshot.processes.iter().filter_map(|p| Some((p.pid, p.labels.get(systemd_user::UNIT_LABEL)?.clone()))).collect()
+        } else {
+            HashMap::new()
+        };
+
+        // Update process manager with restored state
+        self.process_manager.restore_processes(sandbox_id, snapshot.processes).await?;
+
+        // Entries that no longer match a live process are not running anymore
+        let stale_entries: Vec<RestoredProcess> = report
+            .restored
+            .iter()
+            .filter(|p| !matches!(p.status, RestoreStatus::Verified | RestoreStatus::Adopted))
+            .cloned()
+            .collect();
+        for entry in stale_entries {
+            if let Some(unit) = units.get(&entry.pid).filter(|_| entry.status == RestoreStatus::Missing) {
+                info!("Process {} ({}) of sandbox {} is gone; its user unit {} is started again", entry.pid, entry.name, sandbox_id, unit);
+                self.process_manager.update_process_state(sandbox_id, entry.pid, ProcessState::Terminated).await?;
+                continue;
+            }
+            let kind = match entry.status {
+                RestoreStatus::Replaced => WarningKind::ProcessReplaced,
+                _ => WarningKind::ProcessMissing,
+            };
+            let message = format!("Restored process {} ({}) is {:?}", entry.pid, entry.name, entry.status);
+            self.warnings.emit(Warning::new(kind, sandbox_id, message).with_pid(entry.pid), &mut report.warnings);
+            self.process_manager.update_process_state(sandbox_id, entry.pid, ProcessState::Terminated).await?;
+        }
+
+        let frozen: Vec<i32> = report
+            .restored
+            .iter()
+            .filter(|p| snapshot.frozen.contains(&p.pid) && matches!(p.status, RestoreStatus::Verified | RestoreStatus::Adopted))
+            .map(|p| p.adopted_pid.unwrap_or(p.pid))
+            .collect();
+        self.thaw(sandbox_id, &frozen, report).await;
+        Ok(())
+    }
+
+    /// Continue the processes a Freeze policy stopped during a kill pause. The rest of
+    /// the snapshot is not restored, so each is checked to still be the same process.
+    async fn thaw_frozen(&self, sandbox_id: &str, report: &mut ResumeReport) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = match self.persistence_manager.load_snapshot_detailed(sandbox_id).await? {
+            SnapshotLoad::Loaded(snapshot) => snapshot,
+            SnapshotLoad::Missing | SnapshotLoad::Stale => return Ok(()),
+        };
+        let frozen: Vec<PersistedProcess> = snapshot.processes.into_iter().filter(|p| snapshot.frozen.contains(&p.pid)).collect();
+        if frozen.is_empty() {
+            return Ok(());
+        }
+        let backend = Arc::clone(&self.backend);
+        let verified = blocking::run("verify_frozen", move || verify_restored(backend.as_ref(), &frozen).map_err(|e| e.to_string())).await?;
+        let pids: Vec<i32> = verified.iter().filter(|p| p.status == RestoreStatus::Verified).map(|p| p.pid).collect();
+        self.thaw(sandbox_id, &pids, report).await;
+        Ok(())
+    }
+
+    /// Send SIGCONT to processes stopped by a Freeze policy
+    async fn thaw(&self, sandbox_id: &str, pids: &[i32], report: &mut ResumeReport) {
+        for &pid in pids {
+            match self.backend.resume(pid) {
+                Ok(()) => {
+                    // Untracked after an agent restart under the kill strategy; nothing to update then
+                    let _ = self.process_manager.update_process_state(sandbox_id, pid, ProcessState::Running).await;
+                    report.thawed.push(pid);
+                }
+                Err(e) => {
+                    let message = format!("Failed to continue frozen process {}: {}", pid, e);
+                    self.warnings.emit(Warning::new(WarningKind::SignalFailed, sandbox_id, message).with_pid(pid), &mut report.warnings);
+                }
+            }
+        }
+        if !pids.is_empty() {
+            info!("Continued {} of {} processes frozen by policy in sandbox {}", report.thawed.len(), pids.len(), sandbox_id);
+        }
+    }
+}
+
+/// Fail with `SandboxError::Cancelled` once `cancel` has fired
+pub(crate) fn checkpoint(cancel: &CancellationToken, operation: &str, sandbox_id: &str) -> Result<(), SandboxError> {
+    if cancel.is_cancelled() {
+        info!("{} of sandbox {} cancelled", operation, sandbox_id);
+        return Err(SandboxError::Cancelled(format!("{} of sandbox {}", operation, sandbox_id)));
+    }
+    Ok(())
+}
+
+/// Check each snapshot entry against the processes currently alive on the host
+fn verify_restored(backend: &dyn ProcessBackend, persisted: &[PersistedProcess]) -> Result<Vec<RestoredProcess>, Box<dyn std::error::Error>> {
+    let live: HashSet<i32> = backend.list_pids()?.into_iter().collect();
+
+    Ok(persisted
+        .iter()
+        .map(|p| {
+            let status = if !live.contains(&p.pid) {
+                RestoreStatus::Missing
+            } else {
+                match backend.process_name(p.pid) {
+                    Some(name) if !names_match(&p.name, &name) => RestoreStatus::Replaced,
+                    _ => RestoreStatus::Verified,
+                }
+            };
+            RestoredProcess {
+                pid: p.pid,
+                name: p.name.clone(),
+                status,
+                adopted_pid: None,
+            }
+        })
+        .collect())
+}
+
+/// Adopt live processes of the sandbox that are the same program as a missing or replaced entry
+fn adopt_survivors(persisted: &[PersistedProcess], snapshot_at: chrono::DateTime<chrono::Utc>, scope: &SandboxScope, restored: &mut [RestoredProcess]) {
+    let unmatched: HashSet<i32> = restored.iter().filter(|p| p.status != RestoreStatus::Verified).map(|p| p.pid).collect();
+    if unmatched.is_empty() {
+        return;
+    }
+    let claimed: HashSet<i32> = restored.iter().filter(|p| p.status == RestoreStatus::Verified).map(|p| p.pid).collect();
+    let adopted = adoption::match_survivors(persisted, &unmatched, snapshot_at, &claimed, &adoption::live_processes(scope));
+    for entry in restored.iter_mut() {
+        if let Some(&pid) = adopted.get(&entry.pid) {
+            entry.status = RestoreStatus::Adopted;
+            entry.adopted_pid = Some(pid);
+        }
+    }
+}
+
+/// Inventory open file descriptors of the given processes
+#[cfg(target_os = "linux")]
+fn capture_fds(processes: &[ProcessInfo]) -> HashMap<i32, FdSummary> {
+    let listening = crate::procfs::listening_sockets();
+    processes
+        .iter()
+        .filter_map(|p| crate::procfs::fd_summary(p.pid, &listening).map(|fds| (p.pid, fds)))
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn capture_fds(_processes: &[ProcessInfo]) -> HashMap<i32, FdSummary> {
+    HashMap::new()
+}
+
+/// Record the file locks held by the given processes
+#[cfg(target_os = "linux")]
+fn capture_locks(processes: &[ProcessInfo]) -> HashMap<i32, Vec<FileLock>> {
+    let all_locks = crate::procfs::read_locks();
+    processes
+        .iter()
+        .map(|p| (p.pid, crate::procfs::locks_for(p.pid, &all_locks)))
+        .filter(|(_, locks)| !locks.is_empty())
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn capture_locks(_processes: &[ProcessInfo]) -> HashMap<i32, Vec<FileLock>> {
+    HashMap::new()
+}
+
+/// Live processes whose /proc command line the kernel cut, so their argv is incomplete
+#[cfg(target_os = "linux")]
+fn capture_cut_argv(processes: &[ProcessInfo]) -> HashSet<i32> {
+    processes
+        .iter()
+        .filter(|p| p.state != ProcessState::Terminated && crate::procfs::cmdline_truncated(p.pid))
+        .map(|p| p.pid)
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn capture_cut_argv(_processes: &[ProcessInfo]) -> HashSet<i32> {
+    HashSet::new()
+}
+
+/// Find locks recorded for processes that did not survive the pause which are
+/// now held by some other, leftover process
+#[cfg(target_os = "linux")]
+fn detect_lock_conflicts(persisted: &[PersistedProcess], restored: &[RestoredProcess]) -> Vec<LockConflict> {
+    let held = crate::procfs::read_locks();
+    let mut conflicts = Vec::new();
+
+    for p in persisted {
+        let survived = restored
+            .iter()
+            .any(|r| r.pid == p.pid && matches!(r.status, RestoreStatus::Verified | RestoreStatus::Adopted));
+        if survived {
+            continue;
+        }
+        for lock in &p.locks {
+            if let Some(holder) = held.iter().find(|h| h.lock.dev_inode == lock.dev_inode && h.pid != p.pid) {
+                conflicts.push(LockConflict {
+                    pid: p.pid,
+                    name: p.name.clone(),
+                    lock: lock.clone(),
+                    holder_pid: holder.pid,
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(not(target_os = "linux"))]
+fn detect_lock_conflicts(_persisted: &[PersistedProcess], _restored: &[RestoredProcess]) -> Vec<LockConflict> {
+    Vec::new()
+}
+
+/// Sockets and pipes recorded in the snapshot are gone once the process restarts
+fn non_restorable_resources(persisted: &[PersistedProcess]) -> Vec<NonRestorableResource> {
+    let mut resources = Vec::new();
+    for p in persisted {
+        let Some(fds) = p.fds.as_ref().filter(|fds| fds.has_non_restorable()) else {
+            continue;
+        };
+        for port in &fds.listening_ports {
+            resources.push(NonRestorableResource {
+                pid: p.pid,
+                name: p.name.clone(),
+                description: format!("listening socket on port {}", port),
+            });
+        }
+        let connections = fds.sockets as usize - fds.listening_ports.len().min(fds.sockets as usize);
+        if connections > 0 {
+            resources.push(NonRestorableResource {
+                pid: p.pid,
+                name: p.name.clone(),
+                description: format!("{} open socket connection(s)", connections),
+            });
+        }
+        if fds.pipes > 0 {
+            resources.push(NonRestorableResource {
+                pid: p.pid,
+                name: p.name.clone(),
+                description: format!("{} open pipe(s)", fds.pipes),
+            });
+        }
+    }
+    resources
+}
+
+/// Compare a recorded process name with the live one, allowing for the kernel
+/// truncating names to 15 characters
+fn names_match(expected: &str, live: &str) -> bool {
+    expected == live || (live.len() == 15 && expected.starts_with(live))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+    use crate::test_support::{self, process_info};
+
+    /// Processes live until killed; stops and continues are recorded in `stopped`
+    struct RecordingBackend {
+        alive: Mutex<HashMap<i32, String>>,
+        stopped: Arc<Mutex<HashSet<i32>>>,
+    }
+
+    impl RecordingBackend {
+        fn new(processes: &[(i32, &str)]) -> (Self, Arc<Mutex<HashSet<i32>>>) {
+            let stopped = Arc::new(Mutex::new(HashSet::new()));
+            let backend = Self {
+                alive: Mutex::new(processes.iter().map(|(pid, name)| (*pid, name.to_string())).collect()),
+                stopped: stopped.clone(),
+            };
+            (backend, stopped)
+        }
+    }
+
+    impl ProcessBackend for RecordingBackend {
+        fn signal_group(&self, pid: i32, signal: GroupSignal) -> Result<(), Box<dyn Error>> {
+            if signal == GroupSignal::Kill {
+                self.alive.lock().unwrap().remove(&pid);
+            }
+            Ok(())
+        }
+
+        fn suspend(&self, pid: i32) -> Result<(), Box<dyn Error>> {
+            self.stopped.lock().unwrap().insert(pid);
+            Ok(())
+        }
+
+        fn resume(&self, pid: i32) -> Result<(), Box<dyn Error>> {
+            self.stopped.lock().unwrap().remove(&pid);
+            Ok(())
+        }
+
+        fn list_pids(&self) -> Result<Vec<i32>, Box<dyn Error>> {
+            Ok(self.alive.lock().unwrap().keys().copied().collect())
+        }
+
+        fn process_name(&self, pid: i32) -> Option<String> {
+            self.alive.lock().unwrap().get(&pid).cloned()
+        }
+    }
+
+    // Beyond the default pid_max, so the /proc reads of a pause find nothing real
+    const WORKER: i32 = 4_200_001;
+    const WEB: i32 = 4_200_002;
+
+    #[tokio::test]
+    async fn test_policy_frozen_processes_continue_on_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, stopped) = RecordingBackend::new(&[(WORKER, "worker"), (WEB, "web")]);
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Persist),
+            policies: vec![PolicyRule {
+                match_labels: test_support::labels(&[("role", "worker")]),
+                action: Some(PolicyAction::Freeze),
+                ..PolicyRule::default()
+            }],
+            ..AutoPauseConfig::default()
+        };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.feature_flags().set(Feature::Freezer, true);
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        let worker = ProcessInfo { labels: test_support::labels(&[("role", "worker")]), ..process_info(WORKER, "worker") };
+        manager.process_manager().add_process("sbx", worker).await.unwrap();
+        manager.process_manager().add_process("sbx", process_info(WEB, "web")).await.unwrap();
+
+        manager.prepare_pause("sbx").await.unwrap();
+        assert_eq!(*stopped.lock().unwrap(), HashSet::from([WORKER]));
+        let snapshot = manager.persistence_manager().load_snapshot("sbx").await.unwrap().unwrap();
+        assert_eq!(snapshot.frozen, vec![WORKER]);
+
+        let report = manager.after_resume("sbx").await.unwrap();
+        assert_eq!(report.thawed, vec![WORKER]);
+        assert!(stopped.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_feature_flags_gate_their_code_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, stopped) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Persist),
+            policies: vec![PolicyRule { action: Some(PolicyAction::Freeze), ..PolicyRule::default() }],
+            ..AutoPauseConfig::default()
+        };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+        manager.feature_flags().set(Feature::Criu, true);
+
+        // The freezer is off, so the Freeze policy stops nothing
+        let result = manager.prepare_pause("sbx").await.unwrap();
+        assert!(stopped.lock().unwrap().is_empty());
+        let unavailable: Vec<&Warning> = result.warnings.iter().filter(|warning| warning.kind == WarningKind::FeatureUnavailable).collect();
+        assert_eq!(unavailable.len(), 1);
+        assert!(unavailable[0].message.contains("Criu"));
+    }
+
+    #[tokio::test]
+    async fn test_kill_pause_waits_for_someone_else_to_approve() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[]);
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Kill),
+            approval: ApprovalConfig { enabled: true, ..ApprovalConfig::default() },
+            ..AutoPauseConfig::default()
+        };
+        let slots = Arc::new(Throttle::new("operations", 1, 1));
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend)).with_throttles(slots.clone(), Arc::new(Throttle::new("io", 1, 1)));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.registry().set_priority("sbx", crate::registry::PriorityClass::Critical);
+
+        let options = PauseOptions { requested_by: Some("alice".to_string()), ..Default::default() };
+        let approve = async {
+            let pending = loop {
+                if let Some(pending) = manager.pending_approvals().pop() {
+                    break pending;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            };
+            assert_eq!(pending.requested_by.as_deref(), Some("alice"));
+            // Nothing ran yet, and the waiting pause holds no slot
+            assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Running);
+            assert_eq!(slots.available(), 1);
+
+            assert!(manager.approve_operation(pending.operation_id, "alice").is_err());
+            assert!(manager.approve_operation(pending.operation_id, "bob").unwrap());
+        };
+        let (paused, ()) = tokio::join!(manager.prepare_pause_with("sbx", options), approve);
+        paused.unwrap();
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Paused);
+    }
+
+    #[tokio::test]
+    async fn test_queued_pause_is_listed_and_cancellable() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let slots = Arc::new(Throttle::new("operations", 1, 1));
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend)).with_throttles(slots.clone(), Arc::new(Throttle::new("io", 1, 1)));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+        let held = slots.acquire().await;
+
+        let cancel = async {
+            let queued = loop {
+                if let Some(operation) = manager.list_operations().pop() {
+                    break operation;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            };
+            assert_eq!((queued.sandbox_id.as_str(), queued.phase.as_str()), ("sbx", "queued"));
+            assert!(manager.cancel_operation(queued.id));
+        };
+        let (paused, ()) = tokio::join!(manager.prepare_pause("sbx"), cancel);
+        let err = paused.unwrap_err();
+        assert!(matches!(err.downcast_ref::<SandboxError>(), Some(SandboxError::Cancelled(_))), "{}", err);
+        assert!(manager.list_operations().is_empty());
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Running);
+        drop(held);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_by_team_across_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let sandboxes = [("a", WORKER, "alice", "infra"), ("b", WEB, "bob", "infra"), ("c", WEB + 1, "carol", "web")];
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker"), (WEB, "web"), (WEB + 1, "web")]);
+        let manager = AutoPauseManager::with_backend(config.clone(), Box::new(backend));
+        for (sandbox_id, pid, owner, team) in sandboxes {
+            manager.persistence_manager().set_sandbox_base_dir(sandbox_id, dir.path().to_path_buf());
+            manager.registry().set_owner(sandbox_id, Some(owner.to_string()), Some(team.to_string()));
+            manager.process_manager().add_process(sandbox_id, process_info(pid, "web")).await.unwrap();
+        }
+        let infra: crate::registry::OwnerScope = "team:infra".parse().unwrap();
+
+        let report = manager.pause_scoped(&infra, 2).await;
+        assert_eq!(report.paused.len(), 2);
+        assert!(report.failed.is_empty());
+        assert_eq!(manager.lifecycle_state("a").await, LifecycleState::Paused);
+        assert_eq!(manager.lifecycle_state("b").await, LifecycleState::Paused);
+        assert_eq!(manager.lifecycle_state("c").await, LifecycleState::Running);
+        // Already paused: skipped the second time
+        assert_eq!(manager.pause_scoped(&infra, 2).await.skipped, ["a", "b"]);
+
+        // A restarted agent learns the owners back from the snapshots
+        let (backend, _) = RecordingBackend::new(&[]);
+        let restarted = AutoPauseManager::with_backend(config, Box::new(backend));
+        let ids: Vec<String> = sandboxes.iter().map(|(sandbox_id, ..)| sandbox_id.to_string()).collect();
+        for sandbox_id in &ids {
+            restarted.persistence_manager().set_sandbox_base_dir(sandbox_id, dir.path().to_path_buf());
+        }
+        assert_eq!(restarted.restore_owners_of(&ids).await, 2);
+        assert_eq!(restarted.registry().scoped(&infra), ["a", "b"]);
+        assert_eq!(restarted.registry().get("a").owner.as_deref(), Some("alice"));
+
+        let report = manager.resume_scoped(&infra, Default::default()).await;
+        assert_eq!(report.resumed.len(), 2);
+        assert!(report.failed.is_empty());
+        assert_eq!(manager.lifecycle_state("a").await, LifecycleState::Running);
+        assert_eq!(manager.lifecycle_state("c").await, LifecycleState::Running);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_grace_period_longer_than_thirty_seconds_is_honoured() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Kill),
+            policies: vec![PolicyRule { timeout_secs: Some(45), ..PolicyRule::default() }],
+            ..AutoPauseConfig::default()
+        };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        // The worker ignores SIGTERM, so it is killed once its grace period is over
+        let started = tokio::time::Instant::now();
+        manager.prepare_pause("sbx").await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(45));
+    }
+
+    /// Reports every process as exiting as soon as it is waited on
+    struct ExitNotifyingBackend {
+        inner: RecordingBackend,
+        waited: Arc<Mutex<Vec<i32>>>,
+    }
+
+    impl ProcessBackend for ExitNotifyingBackend {
+        fn signal_group(&self, pid: i32, signal: GroupSignal) -> Result<(), Box<dyn Error>> {
+            self.inner.signal_group(pid, signal)
+        }
+
+        fn suspend(&self, pid: i32) -> Result<(), Box<dyn Error>> {
+            self.inner.suspend(pid)
+        }
+
+        fn resume(&self, pid: i32) -> Result<(), Box<dyn Error>> {
+            self.inner.resume(pid)
+        }
+
+        fn list_pids(&self) -> Result<Vec<i32>, Box<dyn Error>> {
+            self.inner.list_pids()
+        }
+
+        fn notifies_exit(&self) -> bool {
+            true
+        }
+
+        fn wait_for_exit(&self, pid: i32, _wait: Duration) -> Result<bool, String> {
+            self.waited.lock().unwrap().push(pid);
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grace_period_ends_on_exit_notification() {
+        let dir = tempfile::tempdir().unwrap();
+        let waited = Arc::new(Mutex::new(Vec::new()));
+        let backend = ExitNotifyingBackend { inner: RecordingBackend::new(&[(WORKER, "worker")]).0, waited: waited.clone() };
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Kill), ..AutoPauseConfig::default() };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        // Well inside the 30s grace period, and without a forced kill
+        let started = std::time::Instant::now();
+        manager.prepare_pause("sbx").await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(*waited.lock().unwrap(), vec![WORKER]);
+        assert!(manager.backend().list_pids().unwrap().contains(&WORKER));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_resume_probes_run_without_the_operation_slot() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[]);
+        let probe = ProbeConfig::new(probes::ProbeKind::Exec { command: vec!["sleep".to_string(), "0.5".to_string()] });
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Kill),
+            resume_probes: vec![probe],
+            ..AutoPauseConfig::default()
+        };
+        let slots = Arc::new(Throttle::new("operations", 1, 1));
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend)).with_throttles(slots.clone(), Arc::new(Throttle::new("io", 1, 1)));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+
+        let probing = async {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            assert_eq!(slots.available(), 1);
+        };
+        let (report, ()) = tokio::join!(manager.after_resume("sbx"), probing);
+        assert_eq!(report.unwrap().health[0].status, ProbeStatus::Healthy);
+    }
+
+    #[tokio::test]
+    async fn test_partial_kill_reports_only_signalled_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker"), (WEB, "web")]);
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Kill),
+            graceful_timeout_secs: 0,
+            policies: vec![PolicyRule {
+                match_labels: test_support::labels(&[("role", "web")]),
+                action: Some(PolicyAction::Spare),
+                ..PolicyRule::default()
+            }],
+            ..AutoPauseConfig::default()
+        };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        let web = ProcessInfo { labels: test_support::labels(&[("role", "web")]), ..process_info(WEB, "web") };
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+        manager.process_manager().add_process("sbx", web).await.unwrap();
+
+        let result = manager.pause_processes("sbx", &Selector::all()).await.unwrap();
+        assert_eq!(result.pids, vec![WORKER]);
+    }
+
+    #[tokio::test]
+    async fn test_partial_pauses_reuse_the_recorded_strategy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, stopped) = RecordingBackend::new(&[(WORKER, "worker"), (WEB, "web")]);
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Auto), ..AutoPauseConfig::default() };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+        manager.process_manager().add_process("sbx", process_info(WEB, "web")).await.unwrap();
+
+        // Small enough to throttle, so the worker is suspended
+        manager.pause_processes("sbx", &"name=worker".parse().unwrap()).await.unwrap();
+        let decision = manager.persistence_manager().load_strategy_decision("sbx").await.unwrap().unwrap();
+        assert_eq!(decision.strategy, PauseStrategy::Throttle);
+
+        // A new decision would now kill, but the web process joins the worker instead
+        manager.registry().set_priority("sbx", crate::registry::PriorityClass::Low);
+        manager.pause_processes("sbx", &"name=web".parse().unwrap()).await.unwrap();
+        assert_eq!(*stopped.lock().unwrap(), HashSet::from([WORKER, WEB]));
+
+        manager.resume_processes("sbx", &Selector::all()).await.unwrap();
+        assert!(stopped.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restored_entries_are_checked_against_live_pids() {
+        const GONE: i32 = 4_200_003;
+        const LONG: i32 = 4_200_004;
+        // WEB's PID was reused by a shell; the kernel cuts LONG's name to 15 bytes
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker"), (WEB, "bash"), (LONG, "data-ingest-wor")]);
+        let now = chrono::Utc::now();
+        let persisted = [
+            test_support::persisted_process(WORKER, &["worker"], now),
+            test_support::persisted_process(WEB, &["web"], now),
+            test_support::persisted_process(GONE, &["cron"], now),
+            test_support::persisted_process(LONG, &["data-ingest-worker"], now),
+        ];
+
+        let restored = verify_restored(&backend, &persisted).unwrap();
+        let statuses: Vec<(i32, RestoreStatus)> = restored.iter().map(|p| (p.pid, p.status)).collect();
+        assert_eq!(
+            statuses,
+            [(WORKER, RestoreStatus::Verified), (WEB, RestoreStatus::Replaced), (GONE, RestoreStatus::Missing), (LONG, RestoreStatus::Verified)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_report_marks_unverified_processes_terminated() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker"), (WEB, "web")]);
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+        manager.process_manager().add_process("sbx", process_info(WEB, "web")).await.unwrap();
+        manager.prepare_pause("sbx").await.unwrap();
+        // WEB dies while the sandbox is paused
+        manager.backend().signal_group(WEB, GroupSignal::Kill).unwrap();
+
+        let report = manager.after_resume("sbx").await.unwrap();
+        assert_eq!((report.count(RestoreStatus::Verified), report.count(RestoreStatus::Missing)), (1, 1));
+        assert!(report.warnings.iter().any(|w| w.kind == WarningKind::ProcessMissing && w.pid == Some(WEB)));
+        let live: Vec<i32> = manager.process_manager().list_live_processes("sbx").await.unwrap().iter().map(|p| p.pid).collect();
+        assert_eq!(live, vec![WORKER]);
+    }
+
+    #[tokio::test]
+    async fn test_processes_of_user_units_are_not_reported_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        // Nothing is alive on resume: the unit's process and a plain one are both gone
+        let (backend, _) = RecordingBackend::new(&[]);
+        let config = AutoPauseConfig {
+            strategy: Some(PauseStrategy::Persist),
+            systemd_user: SystemdUserConfig { enabled: true },
+            ..AutoPauseConfig::default()
+        };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        let unit = ProcessInfo { labels: test_support::labels(&[(systemd_user::UNIT_LABEL, "app.service")]), ..process_info(WORKER, "worker") };
+        manager.process_manager().add_process("sbx", unit).await.unwrap();
+        manager.process_manager().add_process("sbx", process_info(WEB, "web")).await.unwrap();
+        manager.prepare_pause("sbx").await.unwrap();
+
+        let report = manager.after_resume("sbx").await.unwrap();
+        assert_eq!(report.count(RestoreStatus::Missing), 2);
+        let missing: Vec<Option<i32>> = report.warnings.iter().filter(|w| w.kind == WarningKind::ProcessMissing).map(|w| w.pid).collect();
+        assert_eq!(missing, vec![Some(WEB)]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_prepared_pause_releases_the_sandbox() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("a", dir.path().to_path_buf());
+        manager.process_manager().add_process("a", process_info(WORKER, "worker")).await.unwrap();
+
+        let prepared = manager.prepare("a", PauseOptions::default()).await.unwrap();
+        assert!(manager.is_pause_pending("a"));
+        assert!(manager.prepare("a", PauseOptions::default()).await.is_err());
+        drop(prepared);
+        assert!(!manager.is_pause_pending("a"));
+
+        let prepared = manager.prepare("a", PauseOptions::default()).await.unwrap();
+        manager.commit(prepared).await.unwrap();
+        assert!(!manager.is_pause_pending("a"));
+    }
+
+    fn is_cancelled(err: &dyn Error) -> bool {
+        matches!(err.downcast_ref::<SandboxError>(), Some(SandboxError::Cancelled(_)))
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_pause_touches_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Kill), graceful_timeout_secs: 0, ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        let cancel = manager.operation_token();
+        cancel.cancel();
+        let err = manager.prepare_pause_with("sbx", PauseOptions { cancel: Some(cancel), ..Default::default() }).await.unwrap_err();
+        assert!(is_cancelled(err.as_ref()), "{}", err);
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Running);
+        assert!(!manager.is_pause_pending("sbx"));
+        assert!(manager.backend().list_pids().unwrap().contains(&WORKER));
+        assert!(manager.list_operations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_commit_cancelled_after_prepare_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Kill), graceful_timeout_secs: 0, ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        let cancel = manager.operation_token();
+        let prepared = manager.prepare("sbx", PauseOptions { cancel: Some(cancel.clone()), ..Default::default() }).await.unwrap();
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Pausing);
+        cancel.cancel();
+        let err = manager.commit(prepared).await.unwrap_err();
+        assert!(is_cancelled(err.as_ref()), "{}", err);
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Running);
+        assert!(manager.backend().list_pids().unwrap().contains(&WORKER));
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_leaves_the_sandbox_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Auto), ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        let prepared = manager.prepare("sbx", PauseOptions::default()).await.unwrap();
+        // A directory where the strategy decision's temp file goes makes saving it fail
+        let root = manager.persistence_manager().layout("sbx").root().to_path_buf();
+        std::fs::create_dir_all(root.join("strategy.tmp").join("blocker")).unwrap();
+        manager.commit(prepared).await.unwrap_err();
+
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Running);
+        let record = manager.persistence_manager().load_lifecycle("sbx").await.unwrap().unwrap();
+        assert_eq!(record.state, LifecycleState::Running);
+        assert!(!manager.is_pause_pending("sbx"));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_prepared_pause_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        drop(manager.prepare("sbx", PauseOptions::default()).await.unwrap());
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Running);
+        assert!(!manager.is_pause_pending("sbx"));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let record = manager.persistence_manager().load_lifecycle("sbx").await.unwrap().unwrap();
+            if record.state == LifecycleState::Running {
+                break;
+            }
+            assert!(Instant::now() < deadline, "lifecycle record stayed {:?}", record.state);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_new_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+        manager.prepare_pause("sbx").await.unwrap();
+
+        manager.shutdown();
+        assert!(manager.operation_token().is_cancelled());
+        let err = manager.after_resume("sbx").await.unwrap_err();
+        assert!(is_cancelled(err.as_ref()), "{}", err);
+        // Cancelled before the restore, so the sandbox is still paused and resumable
+        assert_eq!(manager.lifecycle_state("sbx").await, LifecycleState::Paused);
+        let err = manager.prepare_pause("other").await.unwrap_err();
+        assert!(is_cancelled(err.as_ref()), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_restore_launches_nothing_more() {
+        struct CountingLauncher(std::sync::atomic::AtomicUsize);
+
+        impl crate::clone::ProcessLauncher for CountingLauncher {
+            fn launch<'a>(&'a self, _sandbox_id: &'a str, _process: &'a PersistedProcess) -> futures::future::BoxFuture<'a, Result<i32, String>> {
+                let launched = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Box::pin(async move { Ok(WEB + launched as i32) })
+            }
+        }
+
+        let (src, dst) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let (backend, _) = RecordingBackend::new(&[]);
+        let manager = AutoPauseManager::with_backend(AutoPauseConfig::default(), Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("src", src.path().to_path_buf());
+        manager.persistence_manager().set_sandbox_base_dir("dst", dst.path().to_path_buf());
+        let mut snapshot = StateSnapshot::new("src");
+        snapshot.processes = vec![
+            test_support::persisted_process(WORKER, &["worker"], chrono::Utc::now()),
+            test_support::persisted_process(WEB, &["web"], chrono::Utc::now()),
+        ];
+        manager.persistence_manager().save_snapshot(&snapshot).await.unwrap();
+
+        let launcher = CountingLauncher(Default::default());
+        let cancel = manager.operation_token();
+        cancel.cancel();
+        let report = manager.restore_into("src", "dst", &launcher, &cancel).await.unwrap();
+        assert!(report.cancelled);
+        assert_eq!(launcher.0.load(std::sync::atomic::Ordering::SeqCst), 0);
+        assert!(report.processes.iter().all(|p| p.pid.is_none() && p.error.as_deref() == Some("cancelled")));
+        assert_eq!(report.processes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_shortens_grace_and_reports_what_is_left() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Kill), graceful_timeout_secs: 30, ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        // Nothing reaps the process, so it stays tracked after the kill
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        let started = tokio::time::Instant::now();
+        let deadline = started + KILL_PHASE_RESERVE + Duration::from_millis(500);
+        let result = manager.prepare_pause_with("sbx", PauseOptions { deadline: Some(deadline), ..Default::default() }).await.unwrap();
+        // Well inside the 30s grace period
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(tokio::time::Instant::now() >= deadline);
+        assert!(result.deadline_exceeded);
+        assert_eq!(result.remaining_pids, vec![WORKER]);
+        assert!(!manager.backend().list_pids().unwrap().contains(&WORKER));
+    }
+
+    #[tokio::test]
+    async fn test_persist_past_the_deadline_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        manager.process_manager().add_process("sbx", process_info(WORKER, "worker")).await.unwrap();
+
+        let deadline = tokio::time::Instant::now();
+        let err = manager.prepare_pause_with("sbx", PauseOptions { deadline: Some(deadline), ..Default::default() }).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<SandboxError>(), Some(SandboxError::Timeout(_) | SandboxError::StorageTimeout(_))), "{}", err);
+        assert!(!manager.persistence_manager().snapshot_exists("sbx"));
+    }
+
+    #[tokio::test]
+    async fn test_stale_snapshot_is_reported_as_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _) = RecordingBackend::new(&[]);
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("sbx", dir.path().to_path_buf());
+        let mut snapshot = StateSnapshot::new("sbx");
+        snapshot.timestamp -= chrono::Duration::hours(25);
+        manager.persistence_manager().save_snapshot(&snapshot).await.unwrap();
+        manager.set_lifecycle_state("sbx", LifecycleState::Paused).await;
+        let mut warnings = manager.subscribe_warnings();
+
+        let report = manager.after_resume("sbx").await.unwrap();
+        let stale = |warning: &Warning| warning.kind == WarningKind::StaleSnapshotRemoved && warning.sandbox_id == "sbx";
+        assert!(report.warnings.iter().any(stale));
+        // Also broadcast, and published as an event
+        assert!(stale(&warnings.try_recv().unwrap()));
+        assert!(manager.events().recent("sbx").iter().any(|event| matches!(&event.payload, EventPayload::Warning(warning) if stale(warning))));
+        assert!(!manager.persistence_manager().snapshot_exists("sbx"));
+    }
+
+    #[tokio::test]
+    async fn test_removed_sandbox_is_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let (backend, _) = RecordingBackend::new(&[(WORKER, "worker")]);
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        manager.persistence_manager().set_sandbox_base_dir("a", dir.path().to_path_buf());
+        manager.process_manager().add_process("a", process_info(WORKER, "worker")).await.unwrap();
+        manager.prepare_pause_with("a", PauseOptions::default()).await.unwrap();
+        assert!(manager.last_pause_report("a").is_some());
+        assert!(manager.stats().sandbox("a").is_some());
+
+        manager.remove_sandbox("a").await.unwrap();
+        assert!(!manager.persistence_manager().layout("a").lifecycle_file().exists());
+        assert!(manager.last_pause_report("a").is_none());
+        assert!(manager.stats().sandbox("a").is_none());
+        assert!(manager.process_manager().list_processes("a").await.unwrap_or_default().is_empty());
+    }
+}
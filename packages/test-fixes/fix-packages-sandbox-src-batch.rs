@@ -0,0 +1,251 @@
+use std::time::Duration;
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::{AutoPauseManager, PauseResult, ResumeReport};
+use crate::host_load::{self, HostLoad};
+use crate::lifecycle::LifecycleState;
+use crate::registry::OwnerScope;
+use crate::resume_scheduler::{ResumeJob, ResumeScheduler, ResumeSchedulerConfig};
+
+/// How often to re-check host load while waiting for capacity
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options for `AutoPauseManager::resume_all`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchResumeOptions {
+    #[serde(default)]
+    pub scheduler: ResumeSchedulerConfig,
+    /// Hold back new resumes while the 1-minute load per CPU is above this
+    pub max_load_per_cpu: Option<f64>,
+    /// Hold back new resumes while I/O pressure (PSI avg10, %) is above this
+    pub max_io_pressure: Option<f64>,
+    /// Longest time a single resume waits for the host to calm down (default: 60s)
+    pub max_load_wait_secs: Option<u64>,
+}
+
+/// A sandbox whose resume failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchFailure {
+    pub sandbox_id: String,
+    pub error: String,
+}
+
+/// Aggregated outcome of a batch resume
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchResumeReport {
+    pub resumed: Vec<ResumeReport>,
+    pub failed: Vec<BatchFailure>,
+    /// Sandboxes that were already running, e.g. resumed before an interruption
+    pub skipped: Vec<String>,
+}
+
+/// Aggregated outcome of a batch pause
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchPauseReport {
+    pub paused: Vec<PauseResult>,
+    pub failed: Vec<BatchFailure>,
+    /// Sandboxes that were not running
+    pub skipped: Vec<String>,
+}
+
+impl AutoPauseManager {
+    /// Pause every running sandbox in `scope`, e.g. everything belonging to a team,
+    /// at most `concurrency` at a time
+    pub async fn pause_scoped(&self, scope: &OwnerScope, concurrency: usize) -> BatchPauseReport {
+        let mut report = BatchPauseReport::default();
+        let mut targets = Vec::new();
+        for sandbox_id in self.registry().scoped(scope) {
+            if self.lifecycle_state(&sandbox_id).await == LifecycleState::Running {
+                targets.push(sandbox_id);
+            } else {
+                report.skipped.push(sandbox_id);
+            }
+        }
+
+        info!("Pausing {} sandboxes of {:?} ({} not running)", targets.len(), scope, report.skipped.len());
+        let mut outcomes = stream::iter(targets)
+            .map(|sandbox_id| async move {
+                let outcome = self.prepare_pause(&sandbox_id).await.map_err(|e| e.to_string());
+                (sandbox_id, outcome)
+            })
+            .buffer_unordered(concurrency.max(1));
+        while let Some((sandbox_id, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(paused) => report.paused.push(paused),
+                Err(error) => {
+                    warn!("Pausing sandbox {} of {:?} failed: {}", sandbox_id, scope, error);
+                    report.failed.push(BatchFailure { sandbox_id, error });
+                }
+            }
+        }
+        report
+    }
+
+    /// Resume every sandbox in `scope`, like `resume_all`
+    pub async fn resume_scoped(&self, scope: &OwnerScope, options: BatchResumeOptions) -> BatchResumeReport {
+        let sandbox_ids = self.registry().scoped(scope);
+        self.resume_all(&sandbox_ids, options).await
+    }
+
+    /// Put the owner and team recorded in the snapshots under the base dir back in the
+    /// registry, so sandboxes paused before a restart stay in their scope. Returns how
+    /// many sandboxes were tagged.
+    pub async fn restore_owners(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let sandbox_ids = self.persistence_manager().list_sandbox_dirs().await?;
+        Ok(self.restore_owners_of(&sandbox_ids).await)
+    }
+
+    /// Sandboxes the orchestrator has tagged since the agent started keep their tags
+    pub(crate) async fn restore_owners_of(&self, sandbox_ids: &[String]) -> usize {
+        let mut restored = 0;
+        for sandbox_id in sandbox_ids {
+            let entry = self.registry().get(sandbox_id);
+            if entry.owner.is_some() || entry.team.is_some() {
+                continue;
+            }
+            match self.persistence_manager().load_snapshot(sandbox_id).await {
+                Ok(Some(snapshot)) if snapshot.owner.is_some() || snapshot.team.is_some() => {
+                    self.registry().set_owner(sandbox_id, snapshot.owner, snapshot.team);
+                    restored += 1;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to read the owner of sandbox {} from its snapshot: {}", sandbox_id, e),
+            }
+        }
+        restored
+    }
+
+    /// Resume many sandboxes in parallel, highest priority first, bounded by the
+    /// scheduler limits and host load. Sandboxes already back in Running state are
+    /// skipped, so re-running an interrupted batch only resumes what is left.
+    pub async fn resume_all(&self, sandbox_ids: &[String], options: BatchResumeOptions) -> BatchResumeReport {
+        let mut report = BatchResumeReport::default();
+        let mut jobs = Vec::new();
+
+        for sandbox_id in sandbox_ids {
+            if self.lifecycle_state(sandbox_id).await.needs_resume() {
+                jobs.push(ResumeJob {
+                    sandbox_id: sandbox_id.clone(),
+                    priority: self.registry().priority(sandbox_id),
+                });
+            } else {
+                report.skipped.push(sandbox_id.clone());
+            }
+        }
+
+        info!("Batch resuming {} sandboxes ({} already running)", jobs.len(), report.skipped.len());
+        let scheduler = ResumeScheduler::new(options.scheduler.clone());
+        let results = scheduler
+            .run(jobs, |sandbox_id| {
+                let options = &options;
+                async move {
+                    wait_for_host_capacity(options).await;
+                    self.after_resume(&sandbox_id).await.map_err(|e| e.to_string())
+                }
+            })
+            .await;
+
+        for (sandbox_id, result) in results {
+            match result {
+                Ok(resumed) => report.resumed.push(resumed),
+                Err(error) => {
+                    warn!("Batch resume of sandbox {} failed: {}", sandbox_id, error);
+                    report.failed.push(BatchFailure { sandbox_id, error });
+                }
+            }
+        }
+
+        info!(
+            "Batch resume finished: {} resumed, {} failed, {} skipped",
+            report.resumed.len(),
+            report.failed.len(),
+            report.skipped.len()
+        );
+        report
+    }
+}
+
+/// Wait until host load is under the configured thresholds, giving up after the max wait
+async fn wait_for_host_capacity(options: &BatchResumeOptions) {
+    if options.max_load_per_cpu.is_none() && options.max_io_pressure.is_none() {
+        return;
+    }
+
+    let max_wait = Duration::from_secs(options.max_load_wait_secs.unwrap_or(60));
+    let started = tokio::time::Instant::now();
+    while started.elapsed() < max_wait {
+        let Some(load) = host_load::sample() else {
+            return;
+        };
+        if has_capacity(&load, options) {
+            return;
+        }
+        tokio::time::sleep(LOAD_POLL_INTERVAL).await;
+    }
+    warn!("Host still loaded after {:?}, resuming anyway", max_wait);
+}
+
+/// Load under every configured threshold; I/O pressure the kernel does not report
+/// does not hold resumes back
+fn has_capacity(load: &HostLoad, options: &BatchResumeOptions) -> bool {
+    let cpu_ok = options.max_load_per_cpu.map_or(true, |max| load.load_per_cpu <= max);
+    let io_ok = match (options.max_io_pressure, load.io_pressure) {
+        (Some(max), Some(pressure)) => pressure <= max,
+        _ => true,
+    };
+    cpu_ok && io_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::{AutoPauseConfig, PauseStrategy};
+    use crate::bench::FakeBackend;
+    use crate::test_support::process_info;
+
+    #[test]
+    fn test_capacity_thresholds() {
+        let options = BatchResumeOptions {
+            max_load_per_cpu: Some(1.5),
+            max_io_pressure: Some(20.0),
+            ..Default::default()
+        };
+        let load = |load_per_cpu, io_pressure| HostLoad { load_per_cpu, io_pressure, ..Default::default() };
+        assert!(has_capacity(&load(1.5, Some(20.0)), &options));
+        assert!(!has_capacity(&load(2.0, Some(5.0)), &options));
+        assert!(!has_capacity(&load(0.5, Some(40.0)), &options));
+        assert!(has_capacity(&load(0.5, None), &options));
+        assert!(has_capacity(&load(64.0, Some(100.0)), &BatchResumeOptions::default()));
+    }
+
+    #[tokio::test]
+    async fn test_rerun_batch_resumes_only_what_is_left() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _exits) = FakeBackend::new([1, 2]);
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let manager = AutoPauseManager::with_backend(config, Box::new(backend));
+        for sandbox_id in ["a", "b", "c"] {
+            manager.persistence_manager().set_sandbox_base_dir(sandbox_id, dir.path().to_path_buf());
+        }
+        for (pid, sandbox_id) in [(1, "a"), (2, "b")] {
+            manager.process_manager().add_process(sandbox_id, process_info(pid, &format!("bench-{}", pid))).await.unwrap();
+            manager.prepare_pause(sandbox_id).await.unwrap();
+        }
+        // An earlier batch was interrupted after resuming "a"; "c" was never paused
+        manager.after_resume("a").await.unwrap();
+
+        let sandbox_ids = ["a", "b", "c"].map(String::from);
+        let report = manager.resume_all(&sandbox_ids, BatchResumeOptions::default()).await;
+        assert_eq!(report.skipped, ["a", "c"]);
+        assert!(report.failed.is_empty());
+        assert_eq!(report.resumed.iter().map(|r| r.sandbox_id.as_str()).collect::<Vec<_>>(), ["b"]);
+
+        // The state a restarted agent would read agrees
+        let record = manager.persistence_manager().load_lifecycle("b").await.unwrap().unwrap();
+        assert_eq!(record.state, LifecycleState::Running);
+        let report = manager.resume_all(&sandbox_ids, BatchResumeOptions::default()).await;
+        assert!(report.resumed.is_empty() && report.skipped.len() == 3);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+use tokio::sync::mpsc;
+
+use crate::auto_pause::{AutoPauseConfig, AutoPauseManager, PauseStrategy};
+use crate::backend::{GroupSignal, ProcessBackend};
+use crate::process::{ProcessExit, ProcessInfo, ProcessLimits, ProcessState};
+
+/// In-memory backend: processes exist until signalled. Signals are reported on a
+/// channel so the caller can play the reaper.
+pub struct FakeBackend {
+    alive: Mutex<HashSet<i32>>,
+    exits: mpsc::UnboundedSender<i32>,
+}
+
+impl FakeBackend {
+    pub fn new(pids: impl IntoIterator<Item = i32>) -> (Self, mpsc::UnboundedReceiver<i32>) {
+        let (exits, receiver) = mpsc::unbounded_channel();
+        let backend = Self {
+            alive: Mutex::new(pids.into_iter().collect()),
+            exits,
+        };
+        (backend, receiver)
+    }
+}
+
+impl ProcessBackend for FakeBackend {
+    fn signal_group(&self, pid: i32, _signal: GroupSignal) -> Result<(), Box<dyn Error>> {
+        if self.alive.lock().unwrap().remove(&pid) {
+            let _ = self.exits.send(pid);
+        }
+        Ok(())
+    }
+
+    fn suspend(&self, _pid: i32) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn resume(&self, _pid: i32) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn list_pids(&self) -> Result<Vec<i32>, Box<dyn Error>> {
+        Ok(self.alive.lock().unwrap().iter().copied().collect())
+    }
+
+    fn process_name(&self, pid: i32) -> Option<String> {
+        self.alive.lock().unwrap().contains(&pid).then(|| format!("bench-{}", pid))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub sandboxes: usize,
+    pub processes_per_sandbox: usize,
+    /// Persist exercises snapshot I/O; Kill exercises signalling and the exit-wait loop
+    pub strategy: PauseStrategy,
+    /// Pauses in flight at once
+    pub concurrency: usize,
+}
+
+/// Throughput and latency of one benchmark run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub sandboxes: usize,
+    pub processes: usize,
+    pub failures: usize,
+    pub elapsed_ms: u64,
+    pub pauses_per_sec: f64,
+    pub pause_p50_ms: u64,
+    pub pause_p99_ms: u64,
+    /// Total snapshot bytes written and the rate they were written at
+    pub snapshot_bytes: u64,
+    pub snapshot_mb_per_sec: f64,
+    /// Latency of a ProcessManager read taken while the pauses ran; a proxy for lock contention
+    pub tracker_read_p99_us: u64,
+}
+
+/// Pause `n_sandboxes` sandboxes of `procs_per_sandbox` processes each, persisting their state
+pub async fn run_pause_load(n_sandboxes: usize, procs_per_sandbox: usize) -> Result<BenchReport, Box<dyn Error>> {
+    run(BenchConfig {
+        sandboxes: n_sandboxes,
+        processes_per_sandbox: procs_per_sandbox,
+        strategy: PauseStrategy::Persist,
+        concurrency: 64,
+    })
+    .await
+}
+
+pub async fn run(config: BenchConfig) -> Result<BenchReport, Box<dyn Error>> {
+    let base_dir = tempfile::tempdir()?;
+    let total = config.sandboxes * config.processes_per_sandbox;
+    let (backend, mut exits) = FakeBackend::new(1..=total as i32);
+    let manager_config = AutoPauseConfig {
+        strategy: Some(config.strategy),
+        graceful_timeout_secs: 1,
+        process_limits: ProcessLimits {
+            max_per_sandbox: config.processes_per_sandbox.max(1),
+            ..Default::default()
+        },
+        ..AutoPauseConfig::default()
+    };
+    let manager = Arc::new(AutoPauseManager::with_backend(manager_config, Box::new(backend)));
+
+    let sandbox_ids: Vec<String> = (0..config.sandboxes).map(|i| format!("bench-{}", i)).collect();
+    let mut pid = 0;
+    for sandbox_id in &sandbox_ids {
+        manager.persistence_manager().set_sandbox_base_dir(sandbox_id, base_dir.path().to_path_buf());
+        for _ in 0..config.processes_per_sandbox {
+            pid += 1;
+            manager.process_manager().add_process(sandbox_id, bench_process(pid)).await?;
+        }
+    }
+
+    // Play the reaper for signalled processes
+    let reaper = {
+        let manager = manager.clone();
+        tokio::spawn(async move {
+            while let Some(pid) = exits.recv().await {
+                manager.process_manager().mark_exited(pid, Some(ProcessExit::Signal("SIGTERM".to_string()))).await;
+            }
+        })
+    };
+
+    // Sample tracker read latency while the load runs
+    let sampling = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let sampler = {
+        let manager = manager.clone();
+        let sampling = sampling.clone();
+        tokio::spawn(async move {
+            let mut samples = Vec::new();
+            while sampling.load(std::sync::atomic::Ordering::Relaxed) {
+                let started = Instant::now();
+                manager.process_manager().tracked_count().await;
+                samples.push(started.elapsed().as_micros() as u64);
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            samples
+        })
+    };
+
+    let started = Instant::now();
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(config.concurrency.max(1)));
+    let mut tasks = Vec::new();
+    for sandbox_id in sandbox_ids.iter().cloned() {
+        let manager = manager.clone();
+        let semaphore = semaphore.clone();
+        tasks.push(tokio::spawn(async move {
+            let _permit = semaphore.acquire_owned().await;
+            let pause_started = Instant::now();
+            let ok = manager.prepare_pause(&sandbox_id).await.is_ok();
+            (ok, pause_started.elapsed())
+        }));
+    }
+    let mut durations = Vec::new();
+    let mut failures = 0;
+    for task in tasks {
+        let (ok, elapsed) = task.await?;
+        if !ok {
+            failures += 1;
+        }
+        durations.push(elapsed.as_millis() as u64);
+    }
+    let elapsed = started.elapsed();
+
+    sampling.store(false, std::sync::atomic::Ordering::Relaxed);
+    let mut reads = sampler.await?;
+    reaper.abort();
+
+    let mut snapshot_bytes = 0;
+    for sandbox_id in &sandbox_ids {
+        if let Ok(metadata) = tokio::fs::metadata(manager.persistence_manager().layout(sandbox_id).snapshot_file()).await {
+            snapshot_bytes += metadata.len();
+        }
+    }
+
+    durations.sort_unstable();
+    reads.sort_unstable();
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    Ok(BenchReport {
+        sandboxes: config.sandboxes,
+        processes: total,
+        failures,
+        elapsed_ms: elapsed.as_millis() as u64,
+        pauses_per_sec: config.sandboxes as f64 / secs,
+        pause_p50_ms: percentile(&durations, 50),
+        pause_p99_ms: percentile(&durations, 99),
+        snapshot_bytes,
+        snapshot_mb_per_sec: snapshot_bytes as f64 / (1024.0 * 1024.0) / secs,
+        tracker_read_p99_us: percentile(&reads, 99),
+    })
+}
+
+fn bench_process(pid: i32) -> ProcessInfo {
+    ProcessInfo {
+        pid,
+        name: format!("bench-{}", pid),
+        cmd: format!("/usr/bin/bench-worker --id {}", pid),
+        argv: vec!["/usr/bin/bench-worker".to_string(), "--id".to_string(), pid.to_string()],
+        start_time: Utc::now(),
+        state: ProcessState::Running,
+        thread_count: 1,
+        child_count: 0,
+        exit: None,
+        ended_at: None,
+        labels: Default::default(),
+        session: None,
+    }
+}
+
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[((pct * sorted.len()).div_ceil(100)).max(1) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_small_pause_load() {
+        let report = run_pause_load(4, 8).await.unwrap();
+        assert_eq!(report.failures, 0);
+        assert_eq!(report.processes, 32);
+        assert!(report.snapshot_bytes > 0);
+    }
+}
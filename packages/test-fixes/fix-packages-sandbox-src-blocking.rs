@@ -0,0 +1,138 @@
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use log::{debug, warn};
+use tokio::sync::Semaphore;
+
+use crate::metrics;
+
+/// Blocking jobs allowed to run at once unless configured otherwise
+pub const DEFAULT_BUDGET: usize = 16;
+
+/// Jobs running longer than this are logged
+const SLOW_JOB: Duration = Duration::from_secs(1);
+
+/// Runs blocking syscalls (/proc scans, filesystem checks, archive work) on
+/// tokio's blocking threads, at most `budget` at a time. Work over budget
+/// queues here instead of piling onto the blocking threads, which keeps the
+/// async workers free for the control plane under load.
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+    budget: usize,
+}
+
+impl BlockingPool {
+    pub fn new(budget: usize) -> Self {
+        let budget = budget.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(budget)),
+            budget,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Jobs that could start right now without queueing
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    /// Run `job` off the async runtime; `op` labels the blocking-time metrics.
+    /// A panic in the job is resumed in the caller.
+    pub async fn run<T, F>(&self, op: &'static str, job: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let queued = Instant::now();
+        let permit = Arc::clone(&self.permits).acquire_owned().await.expect("blocking pool semaphore is never closed");
+        metrics::record_blocking_wait(op, queued.elapsed());
+
+        let handle = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let started = Instant::now();
+            let value = job();
+            (value, started.elapsed())
+        });
+        match handle.await {
+            Ok((value, elapsed)) => {
+                metrics::record_blocking(op, elapsed);
+                if elapsed > SLOW_JOB {
+                    warn!("Blocking job {} took {}ms", op, elapsed.as_millis());
+                } else {
+                    debug!("Blocking job {} took {}us", op, elapsed.as_micros());
+                }
+                value
+            }
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => panic!("blocking job {} did not complete: {}", op, e),
+        }
+    }
+}
+
+static POOL: OnceLock<BlockingPool> = OnceLock::new();
+
+/// Set the process-wide budget; only the first call (or first use) takes effect.
+/// Returns whether this call set it.
+pub fn configure(budget: usize) -> bool {
+    let mut configured = false;
+    POOL.get_or_init(|| {
+        configured = true;
+        BlockingPool::new(budget)
+    });
+    configured
+}
+
+/// The process-wide pool
+pub fn pool() -> &'static BlockingPool {
+    POOL.get_or_init(|| BlockingPool::new(DEFAULT_BUDGET))
+}
+
+/// Run `job` on the process-wide pool
+pub async fn run<T, F>(op: &'static str, job: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    pool().run(op, job).await
+}
+
+/// `Path::exists` without blocking the caller's worker
+pub async fn path_exists(path: &Path) -> bool {
+    let path = path.to_path_buf();
+    run("fs_exists", move || path.exists()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_budget_bounds_concurrent_jobs() {
+        let pool = Arc::new(BlockingPool::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut tasks = Vec::new();
+        for _ in 0..8 {
+            let (pool, running, peak) = (pool.clone(), running.clone(), peak.clone());
+            tasks.push(tokio::spawn(async move {
+                pool.run("test", move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+                .await
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(pool.available(), 2);
+    }
+}
@@ -0,0 +1,226 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+/// Resource clamps applied by the throttle (soft pause) strategy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoftPauseConfig {
+    /// Parent of the per-sandbox cgroup v2 directories; a sandbox's cgroup is `<root>/<sandbox_id>`
+    pub cgroup_root: PathBuf,
+    /// cpu.max while paused (default: "1000 100000", 1% of one CPU)
+    pub cpu_max: String,
+    /// io.max lines while paused, e.g. "8:0 rbps=1048576 wbps=1048576" (default: none)
+    pub io_max: Vec<String>,
+    /// memory.high while paused, in bytes, to push the kernel to reclaim (default: unchanged)
+    pub memory_high: Option<u64>,
+}
+
+impl Default for SoftPauseConfig {
+    fn default() -> Self {
+        Self {
+            cgroup_root: PathBuf::from("/sys/fs/cgroup/sandboxes"),
+            cpu_max: "1000 100000".to_string(),
+            io_max: Vec::new(),
+            memory_high: None,
+        }
+    }
+}
+
+/// Limits of a cgroup as found before a soft pause, so resume can put them back exactly
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CgroupLimits {
+    pub cpu_max: Option<String>,
+    /// Non-empty io.max lines, one per device
+    #[serde(default)]
+    pub io_max: Vec<String>,
+    /// memory.high before the clamp; only recorded when memory was clamped
+    #[serde(default)]
+    pub memory_high: Option<String>,
+}
+
+/// A cgroup v2 directory
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn for_sandbox(config: &SoftPauseConfig, sandbox_id: &str) -> Self {
+        Self::new(config.cgroup_root.join(sandbox_id))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn read(&self, file: &str) -> io::Result<Option<String>> {
+        match async_fs::read_to_string(self.path.join(file)).await {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn write(&self, file: &str, value: &str) -> io::Result<()> {
+        async_fs::write(self.path.join(file), value).await
+    }
+
+    /// Total OOM kills in the cgroup (`oom_kill` in memory.events); None without the memory controller
+    pub async fn oom_kills(&self) -> io::Result<Option<u64>> {
+        Ok(self.read("memory.events").await?.and_then(|events| parse_oom_kills(&events)))
+    }
+
+    /// Current CPU and IO limits; controllers that are not enabled read as unset
+    pub async fn limits(&self) -> io::Result<CgroupLimits> {
+        Ok(CgroupLimits {
+            cpu_max: self.read("cpu.max").await?.map(|v| v.trim().to_string()),
+            io_max: self
+                .read("io.max")
+                .await?
+                .map(|v| v.lines().map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect())
+                .unwrap_or_default(),
+            memory_high: None,
+        })
+    }
+
+    /// Clamp CPU (and IO and memory, if configured) to a trickle, returning the limits it
+    /// replaced. When a write fails, the limits already clamped are put back.
+    pub async fn clamp(&self, config: &SoftPauseConfig) -> io::Result<CgroupLimits> {
+        let mut original = self.limits().await?;
+        if config.memory_high.is_some() {
+            original.memory_high = Some(self.read("memory.high").await?.map_or_else(|| "max".to_string(), |v| v.trim().to_string()));
+        }
+        let mut clamped_io = Vec::new();
+        let mut clamped_memory = false;
+        let clamped = async {
+            self.write("cpu.max", &config.cpu_max).await?;
+            for line in &config.io_max {
+                self.write("io.max", line).await?;
+                clamped_io.push(line.clone());
+            }
+            if let Some(memory_high) = config.memory_high {
+                self.write("memory.high", &memory_high.to_string()).await?;
+                clamped_memory = true;
+            }
+            io::Result::Ok(())
+        }
+        .await;
+        if let Err(e) = clamped {
+            let applied = SoftPauseConfig { io_max: clamped_io, ..config.clone() };
+            let undo = CgroupLimits {
+                memory_high: original.memory_high.clone().filter(|_| clamped_memory),
+                ..original
+            };
+            if let Err(undo_error) = self.restore(&undo, &applied).await {
+                warn!("Failed to undo partial clamp of cgroup {}: {}", self.path.display(), undo_error);
+            }
+            return Err(e);
+        }
+        info!("Clamped cgroup {} to cpu.max {:?}", self.path.display(), config.cpu_max);
+        Ok(original)
+    }
+
+    /// Undo `clamp`. Devices clamped without a previous limit are reset to
+    /// unlimited; memory.high is restored whenever it was recorded.
+    pub async fn restore(&self, original: &CgroupLimits, config: &SoftPauseConfig) -> io::Result<()> {
+        self.write("cpu.max", original.cpu_max.as_deref().unwrap_or("max")).await?;
+        for line in &config.io_max {
+            let Some(device) = line.split_whitespace().next() else {
+                continue;
+            };
+            let previous = original
+                .io_max
+                .iter()
+                .find(|l| l.split_whitespace().next() == Some(device))
+                .cloned()
+                .unwrap_or_else(|| format!("{} rbps=max wbps=max riops=max wiops=max", device));
+            self.write("io.max", &previous).await?;
+        }
+        if let Some(memory_high) = &original.memory_high {
+            self.write("memory.high", memory_high).await?;
+        }
+        info!("Restored limits of cgroup {}", self.path.display());
+        Ok(())
+    }
+}
+
+fn parse_oom_kills(memory_events: &str) -> Option<u64> {
+    memory_events
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_clamp_and_restore_cpu_max() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("cpu.max"), "200000 100000\n").unwrap();
+        let cgroup = Cgroup::new(temp_dir.path().to_path_buf());
+        let config = SoftPauseConfig::default();
+
+        let original = cgroup.clamp(&config).await.unwrap();
+        assert_eq!(original.cpu_max.as_deref(), Some("200000 100000"));
+        assert_eq!(cgroup.limits().await.unwrap().cpu_max.as_deref(), Some("1000 100000"));
+
+        cgroup.restore(&original, &config).await.unwrap();
+        assert_eq!(cgroup.limits().await.unwrap(), original);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_clamp_is_undone() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("cpu.max"), "200000 100000\n").unwrap();
+        std::fs::write(temp_dir.path().join("io.max"), "").unwrap();
+        // Reads as missing but cannot be written, so the clamp fails after CPU and IO
+        std::os::unix::fs::symlink(temp_dir.path().join("missing/memory.high"), temp_dir.path().join("memory.high")).unwrap();
+        let cgroup = Cgroup::new(temp_dir.path().to_path_buf());
+        let config = SoftPauseConfig {
+            io_max: vec!["8:0 rbps=1048576".to_string()],
+            memory_high: Some(64 * 1024 * 1024),
+            ..SoftPauseConfig::default()
+        };
+
+        assert!(cgroup.clamp(&config).await.is_err());
+        assert_eq!(cgroup.limits().await.unwrap().cpu_max.as_deref(), Some("200000 100000"));
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("io.max")).unwrap(), "8:0 rbps=max wbps=max riops=max wiops=max");
+    }
+
+    #[test]
+    fn test_parse_oom_kills() {
+        let events = "low 0\nhigh 12\nmax 3\noom 2\noom_kill 2\noom_group_kill 0\n";
+        assert_eq!(parse_oom_kills(events), Some(2));
+        assert_eq!(parse_oom_kills("low 0\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_high_restored_from_record() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("cpu.max"), "max 100000\n").unwrap();
+        std::fs::write(temp_dir.path().join("memory.high"), "max\n").unwrap();
+        let cgroup = Cgroup::new(temp_dir.path().to_path_buf());
+        let config = SoftPauseConfig {
+            memory_high: Some(64 * 1024 * 1024),
+            ..SoftPauseConfig::default()
+        };
+
+        let original = cgroup.clamp(&config).await.unwrap();
+        assert_eq!(original.memory_high.as_deref(), Some("max"));
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("memory.high")).unwrap(), "67108864");
+
+        // Resume must not depend on the config still enabling the memory clamp
+        cgroup.restore(&original, &SoftPauseConfig::default()).await.unwrap();
+        assert_eq!(std::fs::read_to_string(temp_dir.path().join("memory.high")).unwrap(), "max");
+    }
+}
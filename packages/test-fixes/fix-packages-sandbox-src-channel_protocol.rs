@@ -0,0 +1,60 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+/// Bumped on incompatible changes to the messages below
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Where the agent listens for in-sandbox applications
+pub const DEFAULT_SOCKET_PATH: &str = "/run/sandbox-agent/channel.sock";
+
+/// Sent by in-sandbox applications, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// First message on every connection
+    Hello {
+        app: String,
+        protocol: u32,
+        /// Receive pause notices on this connection instead of making requests
+        #[serde(default)]
+        pause_handler: bool,
+    },
+    Heartbeat,
+    /// Keep the sandbox from being paused until released or `ttl_secs` pass
+    AcquireHold { reason: String, ttl_secs: u64 },
+    ReleaseHold { hold_id: u64 },
+    /// The most recent `limit` pauses
+    History { limit: usize },
+    /// The pause handler is done with a `PauseNotice`
+    PauseAck,
+    /// Answer a `GraceNotice`: hold the sandbox for the keep-alive period it offered
+    KeepAlive,
+}
+
+/// Sent by the agent, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentMessage {
+    Welcome { protocol: u32, sandbox_id: String },
+    Ok,
+    HoldGranted { hold_id: u64, expires_at: DateTime<Utc> },
+    History { pauses: Vec<PauseRecord> },
+    /// The sandbox is about to pause; handlers should ack within `ack_within_ms`
+    PauseNotice { ack_within_ms: u64 },
+    /// An idle or scheduled pause is coming at `pause_at`; `KeepAlive` holds the sandbox
+    /// for `keep_alive_secs`
+    GraceNotice { trigger: String, pause_at: DateTime<Utc>, keep_alive_secs: u64 },
+    Resumed { paused_at: Option<DateTime<Utc>> },
+    Error { message: String },
+}
+
+/// One pause of the sandbox, as told to in-sandbox applications
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PauseRecord {
+    pub paused_at: DateTime<Utc>,
+    /// Unset while the pause is the current one
+    pub resumed_at: Option<DateTime<Utc>>,
+    pub strategy: String,
+    /// Time the pause itself took
+    pub duration_ms: u64,
+}
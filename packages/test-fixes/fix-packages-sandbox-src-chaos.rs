@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use log::warn;
+use serde::{Serialize, Deserialize};
+
+use crate::backend::{GroupSignal, ProcessBackend};
+use crate::feature_flags::{Feature, FeatureFlags};
+
+/// Fault injection rates; nothing is injected unless the `chaos` feature flag is on
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    /// Share of group signals that fail, 0.0 to 1.0
+    pub kill_failure_rate: f64,
+    /// Share of snapshot reads and writes that are delayed
+    pub storage_delay_rate: f64,
+    pub storage_delay_ms: u64,
+    /// Share of broadcast warnings that are dropped
+    pub event_drop_rate: f64,
+    /// Fixed seed for reproducible runs
+    pub seed: Option<u64>,
+}
+
+impl ChaosConfig {
+    pub fn is_configured(&self) -> bool {
+        self.kill_failure_rate > 0.0 || self.storage_delay_rate > 0.0 || self.event_drop_rate > 0.0
+    }
+}
+
+/// Decides which operations fail, shared by the injection points
+pub struct Chaos {
+    config: ChaosConfig,
+    flags: Arc<FeatureFlags>,
+    state: Mutex<u64>,
+}
+
+impl Chaos {
+    pub fn new(config: ChaosConfig, flags: Arc<FeatureFlags>) -> Self {
+        let seed = config.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(1)
+        });
+        Self {
+            config,
+            flags,
+            // xorshift needs a non-zero state
+            state: Mutex::new(seed | 1),
+        }
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        if rate <= 0.0 || !self.flags.is_enabled(Feature::Chaos, None) {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state as f64 / u64::MAX as f64) < rate
+    }
+
+    pub fn fail_kill(&self) -> bool {
+        self.roll(self.config.kill_failure_rate)
+    }
+
+    pub fn storage_delay(&self) -> Option<Duration> {
+        self.roll(self.config.storage_delay_rate).then(|| Duration::from_millis(self.config.storage_delay_ms))
+    }
+
+    pub fn drop_event(&self) -> bool {
+        self.roll(self.config.event_drop_rate)
+    }
+}
+
+/// Backend whose group signals fail at the configured rate
+pub struct ChaosBackend {
+    inner: Box<dyn ProcessBackend>,
+    chaos: Arc<Chaos>,
+}
+
+impl ChaosBackend {
+    pub fn new(inner: Box<dyn ProcessBackend>, chaos: Arc<Chaos>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+impl ProcessBackend for ChaosBackend {
+    fn signal_group(&self, pid: i32, signal: GroupSignal) -> Result<(), Box<dyn Error>> {
+        if self.chaos.fail_kill() {
+            warn!("chaos: failing {:?} of process group {}", signal, pid);
+            return Err(format!("chaos: injected failure signalling {}", pid).into());
+        }
+        self.inner.signal_group(pid, signal)
+    }
+
+    fn suspend(&self, pid: i32) -> Result<(), Box<dyn Error>> {
+        self.inner.suspend(pid)
+    }
+
+    fn resume(&self, pid: i32) -> Result<(), Box<dyn Error>> {
+        self.inner.resume(pid)
+    }
+
+    fn list_pids(&self) -> Result<Vec<i32>, Box<dyn Error>> {
+        self.inner.list_pids()
+    }
+
+    fn process_name(&self, pid: i32) -> Option<String> {
+        self.inner.process_name(pid)
+    }
+
+    fn notifies_exit(&self) -> bool {
+        self.inner.notifies_exit()
+    }
+
+    fn wait_for_exit(&self, pid: i32, wait: Duration) -> Result<bool, String> {
+        self.inner.wait_for_exit(pid, wait)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature_flags::FeatureFlagsConfig;
+
+    #[test]
+    fn test_nothing_injected_without_feature_flag() {
+        let flags = Arc::new(FeatureFlags::new(FeatureFlagsConfig::default()));
+        let config = ChaosConfig {
+            kill_failure_rate: 1.0,
+            seed: Some(42),
+            ..ChaosConfig::default()
+        };
+        let chaos = Chaos::new(config, flags.clone());
+        assert!(!chaos.fail_kill());
+
+        flags.set(Feature::Chaos, true);
+        assert!(chaos.fail_kill());
+        assert!(!chaos.drop_event());
+    }
+
+    #[test]
+    fn test_rate_is_roughly_respected() {
+        let flags = Arc::new(FeatureFlags::new(FeatureFlagsConfig::default()));
+        flags.set(Feature::Chaos, true);
+        let config = ChaosConfig {
+            event_drop_rate: 0.25,
+            seed: Some(7),
+            ..ChaosConfig::default()
+        };
+        let chaos = Chaos::new(config, flags);
+        let dropped = (0..10_000).filter(|_| chaos.drop_event()).count();
+        assert!((2_000..3_000).contains(&dropped), "dropped {}", dropped);
+    }
+}
@@ -0,0 +1,165 @@
+#![cfg(feature = "cli")]
+
+//! `sandboxctl`: commands against the agent on this host, or a remote one with `--address`
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use clap::{Parser, Subcommand};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::AutoPauseManager;
+use crate::config_lint::{self, ConfigFormat};
+use crate::output::{self, OutputFormat, Tabular};
+use crate::process_tree::TreeFormat;
+use crate::remote::{self, AgentClient, RemoteConfig};
+
+#[derive(Debug, Parser)]
+#[command(name = "sandboxctl", about = "Inspect and drive the sandbox agent")]
+pub struct Cli {
+    /// gRPC address of a remote agent; overrides the config file
+    #[arg(long, global = true)]
+    pub address: Option<String>,
+    /// Bearer token of the remote agent; overrides the config file
+    #[arg(long, global = true)]
+    pub token: Option<String>,
+    /// json, yaml or table; json and yaml have the schema of the library types
+    #[arg(long, global = true, default_value = "table")]
+    pub output: OutputFormat,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// List the processes of a sandbox
+    Ps {
+        sandbox_id: String,
+        /// Draw the process hierarchy, from the snapshot when nothing is live. The
+        /// drawing is text, so it only goes with `--output table`
+        #[arg(long)]
+        tree: bool,
+        /// Tree format: ascii or dot
+        #[arg(long, default_value = "ascii", requires = "tree")]
+        format: TreeFormat,
+    },
+    /// Pause a sandbox
+    Pause {
+        sandbox_id: String,
+        /// Pause even if the sandbox is protected
+        #[arg(long)]
+        force: bool,
+    },
+    /// Resume a paused sandbox
+    Resume { sandbox_id: String },
+    /// Lifecycle state of every sandbox
+    State,
+    /// Pauses and resumes in flight
+    Ops,
+    /// Cancel a pause or resume in flight
+    Cancel { id: u64 },
+    /// Work with agent config files, without contacting an agent
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Live dashboard of sandboxes, operations and events
+    #[cfg(feature = "tui")]
+    Top {
+        /// Refresh interval in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u64,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Report unknown fields, contradictory rules and unreachable selectors in a
+    /// JSON or TOML config; fails when any diagnostic is an error
+    Lint { path: PathBuf },
+}
+
+/// Run `cli` against the agent it names, or `local` when it names none, printing
+/// the result to stdout
+pub async fn run(cli: Cli, config: &RemoteConfig, local: impl FnOnce() -> Arc<AutoPauseManager>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if let Command::Config { command: ConfigCommand::Lint { path } } = &cli.command {
+        return lint_config(path, cli.output).await;
+    }
+    let target = config.resolve(cli.address, cli.token)?;
+    let agent = remote::agent_client(target.as_ref(), local).await?;
+    print!("{}", execute(cli.command, agent.as_ref(), cli.output).await?);
+    Ok(())
+}
+
+/// Result of `sandboxctl cancel`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CancelResult {
+    pub id: u64,
+    pub cancelled: bool,
+}
+
+impl Tabular for CancelResult {
+    fn headers() -> &'static [&'static str] {
+        &["ID", "CANCELLED"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.id.to_string(), self.cancelled.to_string()]
+    }
+}
+
+async fn lint_config(path: &std::path::Path, format: OutputFormat) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let text = tokio::fs::read_to_string(path).await.map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let diagnostics = config_lint::lint(&text, ConfigFormat::of_path(path));
+    print!("{}", output::render(&diagnostics, format).map_err(|e| e.to_string())?);
+    if config_lint::has_errors(&diagnostics) {
+        return Err(format!("{} has errors", path.display()).into());
+    }
+    Ok(())
+}
+
+async fn execute(command: Command, agent: &dyn AgentClient, format: OutputFormat) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let rendered = match command {
+        Command::Ps { tree: true, .. } if format != OutputFormat::Table => return Err("--tree draws the hierarchy as text; drop --output or --tree".into()),
+        Command::Ps { sandbox_id, tree: true, format: tree_format } => Ok(agent.process_tree(&sandbox_id, tree_format).await?),
+        Command::Ps { sandbox_id, tree: false, .. } => output::render(&agent.list_processes(&sandbox_id).await?, format),
+        Command::Pause { sandbox_id, force } => output::render_one(&agent.pause(&sandbox_id, force).await?, format),
+        Command::Resume { sandbox_id } => output::render_one(&agent.resume(&sandbox_id).await?, format),
+        Command::State => output::render(&agent.export_state().await?.sandboxes, format),
+        Command::Ops => output::render(&agent.list_operations().await?, format),
+        Command::Cancel { id } => output::render_one(&CancelResult { id, cancelled: agent.cancel_operation(id).await? }, format),
+        Command::Config { .. } => unreachable!("config commands run without an agent"),
+        #[cfg(feature = "tui")]
+        Command::Top { .. } if format != OutputFormat::Table => return Err("top is interactive; drop --output".into()),
+        #[cfg(feature = "tui")]
+        Command::Top { interval_ms } => {
+            crate::tui::run_top(agent, std::time::Duration::from_millis(interval_ms)).await?;
+            Ok(String::new())
+        }
+    };
+    rendered.map_err(|e| e.to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ps_tree_parses() {
+        let cli = Cli::try_parse_from(["sandboxctl", "ps", "sbx-1", "--tree", "--format", "dot"]).unwrap();
+        assert!(matches!(cli.command, Command::Ps { tree: true, format: TreeFormat::Dot, .. }));
+
+        // A format only applies to the tree
+        assert!(Cli::try_parse_from(["sandboxctl", "ps", "sbx-1", "--format", "dot"]).is_err());
+    }
+
+    #[test]
+    fn test_output_applies_to_every_command() {
+        let cli = Cli::try_parse_from(["sandboxctl", "cancel", "7", "--output", "json"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert_eq!(Cli::try_parse_from(["sandboxctl", "state"]).unwrap().output, OutputFormat::Table);
+        assert!(Cli::try_parse_from(["sandboxctl", "--output", "xml", "state"]).is_err());
+
+        let json: serde_json::Value = serde_json::from_str(&output::render_one(&CancelResult { id: 7, cancelled: true }, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!({ "id": 7, "cancelled": true }));
+    }
+}
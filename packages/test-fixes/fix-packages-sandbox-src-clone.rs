@@ -0,0 +1,252 @@
+use std::process::Stdio;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::auto_pause::AutoPauseManager;
+use crate::blocking;
+use crate::launch_hints;
+use crate::persistence::SnapshotLoad;
+use crate::process::{ProcessInfo, ProcessState};
+use crate::registry::SandboxEntry;
+use crate::relaunch_markers::RelaunchMarker;
+use crate::restore_order;
+use crate::state_snapshot::{PersistedProcess, StateSnapshot};
+
+/// Starts a persisted process inside a sandbox and returns its new PID
+pub trait ProcessLauncher: Send + Sync {
+    fn launch<'a>(&'a self, sandbox_id: &'a str, process: &'a PersistedProcess) -> BoxFuture<'a, Result<i32, String>>;
+}
+
+/// Launches each process by exec'ing its captured argv in a new process group,
+/// so arguments with spaces or quotes survive the restore. Nothing goes through a
+/// shell: processes without an argv are not relaunched. The environment is only
+/// what the snapshot recorded; the captured executable, cwd, virtualenv and PATH
+/// are reapplied where they still exist.
+pub struct ExecLauncher;
+
+impl ProcessLauncher for ExecLauncher {
+    fn launch<'a>(&'a self, _sandbox_id: &'a str, process: &'a PersistedProcess) -> BoxFuture<'a, Result<i32, String>> {
+        Box::pin(async move {
+            if process.argv_truncated {
+                return Err(format!("process {} has an incomplete command line and cannot be relaunched", process.pid));
+            }
+            let plan = {
+                let process = process.clone();
+                blocking::run("relaunch_plan", move || launch_hints::relaunch_plan(&process)).await
+            };
+            let (program, args) = plan.argv.split_first().ok_or_else(|| format!("process {} has no captured argument vector to exec", process.pid))?;
+            let mut command = tokio::process::Command::new(program);
+            command.args(args).env_clear().envs(plan.env.iter().map(|(k, v)| (k, v))).stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+            if let Some(cwd) = &plan.cwd {
+                command.current_dir(cwd);
+            }
+            #[cfg(unix)]
+            command.process_group(0);
+            let child = command.spawn().map_err(|e| format!("exec {}: {}", program, e))?;
+            child.id().map(|pid| pid as i32).ok_or_else(|| format!("{} exited before its PID was read", program))
+        })
+    }
+}
+
+/// Outcome of relaunching one process from the source snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClonedProcess {
+    pub source_pid: i32,
+    pub name: String,
+    /// PID under the new sandbox, if the launch succeeded
+    pub pid: Option<i32>,
+    pub error: Option<String>,
+    /// Ways the relaunch may differ from the original (missing cwd, replaced executable, ...)
+    #[serde(default)]
+    pub caveats: Vec<String>,
+    /// Already relaunched by an interrupted earlier restore and taken over rather than started again
+    #[serde(default)]
+    pub adopted: bool,
+}
+
+/// Result of `restore_into`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloneReport {
+    pub source_sandbox_id: String,
+    pub sandbox_id: String,
+    /// Template the sandbox was pre-warmed from, if any
+    pub template: Option<String>,
+    pub processes: Vec<ClonedProcess>,
+    /// The restore was cancelled; processes not yet launched carry a "cancelled" error
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+impl CloneReport {
+    pub fn launched(&self) -> usize {
+        self.processes.iter().filter(|p| p.pid.is_some()).count()
+    }
+}
+
+impl AutoPauseManager {
+    /// Fork a paused sandbox: restore `source_sandbox_id`'s snapshot under
+    /// `new_sandbox_id` and relaunch its processes through `launcher`.
+    /// The source snapshot is left untouched. Once `cancel` fires no further
+    /// processes are launched; the ones already running are kept.
+    pub async fn restore_into(&self, source_sandbox_id: &str, new_sandbox_id: &str, launcher: &dyn ProcessLauncher, cancel: &CancellationToken) -> Result<CloneReport, Box<dyn std::error::Error>> {
+        if source_sandbox_id == new_sandbox_id {
+            return Err(format!("Cannot restore sandbox {} into itself", source_sandbox_id).into());
+        }
+        self.ensure_fresh(new_sandbox_id).await?;
+
+        let snapshot = {
+            let _pin = self.persistence_manager().pin_snapshot(source_sandbox_id);
+            match self.persistence_manager().load_snapshot_detailed(source_sandbox_id).await? {
+                SnapshotLoad::Loaded(snapshot) => snapshot,
+                SnapshotLoad::Missing | SnapshotLoad::Stale => {
+                    return Err(format!("No usable snapshot for sandbox {}", source_sandbox_id).into());
+                }
+            }
+        };
+
+        let source = self.registry().get(source_sandbox_id);
+        self.registry().upsert(SandboxEntry {
+            sandbox_id: new_sandbox_id.to_string(),
+            priority: source.priority,
+            tenant: source.tenant,
+            owner: source.owner,
+            team: source.team,
+        });
+        self.relaunch_into(&snapshot, new_sandbox_id, launcher, cancel).await
+    }
+
+    /// Pre-warm a fresh sandbox from template `name`. The template stays leased
+    /// until every process has been relaunched.
+    pub async fn restore_from_template(&self, name: &str, new_sandbox_id: &str, launcher: &dyn ProcessLauncher, cancel: &CancellationToken) -> Result<CloneReport, Box<dyn std::error::Error>> {
+        self.ensure_fresh(new_sandbox_id).await?;
+
+        let (_lease, snapshot) = self.persistence_manager().templates().acquire(name).await?;
+        let mut report = self.relaunch_into(&snapshot, new_sandbox_id, launcher, cancel).await?;
+        report.template = Some(name.to_string());
+        if let Some(history) = self.history().cloned() {
+            let (sandbox_id, template) = (new_sandbox_id.to_string(), name.to_string());
+            if let Err(e) = blocking::run("history_template", move || history.set_template(&sandbox_id, &template)).await {
+                warn!("Failed to record template of sandbox {}: {}", new_sandbox_id, e);
+            }
+        }
+        Ok(report)
+    }
+
+    async fn ensure_fresh(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if self.persistence_manager().snapshot_exists(sandbox_id)
+            || !self.process_manager().list_processes(sandbox_id).await?.is_empty()
+        {
+            return Err(format!("Sandbox {} already has state", sandbox_id).into());
+        }
+        Ok(())
+    }
+
+    /// Copy `source` for the new sandbox and relaunch each of its processes. Processes a
+    /// previous, interrupted attempt already relaunched are adopted if still running.
+    async fn relaunch_into(&self, source: &StateSnapshot, new_sandbox_id: &str, launcher: &dyn ProcessLauncher, cancel: &CancellationToken) -> Result<CloneReport, Box<dyn std::error::Error>> {
+        let mut snapshot = source.clone_for(new_sandbox_id);
+        info!("Restoring {} processes from sandbox {} into {}", snapshot.processes.len(), source.sandbox_id, new_sandbox_id);
+        snapshot.processes = restore_order::in_restore_order(new_sandbox_id, &snapshot.processes);
+
+        let mut report = CloneReport {
+            source_sandbox_id: source.sandbox_id.to_string(),
+            sandbox_id: new_sandbox_id.to_string(),
+            template: None,
+            processes: Vec::new(),
+            cancelled: false,
+        };
+        // Source PIDs whose relaunch is tracked by the process manager
+        let mut tracked = Vec::new();
+        let markers = self.persistence_manager().load_relaunch_markers(new_sandbox_id).await.unwrap_or_else(|e| {
+            warn!("Failed to read relaunch markers of sandbox {}, relaunching everything: {}", new_sandbox_id, e);
+            Default::default()
+        });
+        for process in &snapshot.processes {
+            if cancel.is_cancelled() {
+                report.cancelled = true;
+                report.processes.push(ClonedProcess {
+                    source_pid: process.pid,
+                    name: process.name.clone(),
+                    pid: None,
+                    error: Some("cancelled".to_string()),
+                    caveats: Vec::new(),
+                    adopted: false,
+                });
+                continue;
+            }
+            let plan = {
+                let process = process.clone();
+                blocking::run("relaunch_plan", move || launch_hints::relaunch_plan(&process)).await
+            };
+            if !plan.caveats.is_empty() {
+                warn!("Relaunch of process {} ({}) may differ from the original: {}", process.pid, process.name, plan.caveats.join("; "));
+            }
+            let adopt = match markers.get(&process.pid) {
+                Some(marker) if marker.is_running().await => Some(marker),
+                _ => None,
+            };
+            let outcome = match adopt {
+                Some(marker) => {
+                    info!("Adopting process {} already relaunched for {} ({}) in sandbox {}", marker.pid, process.pid, process.name, new_sandbox_id);
+                    Ok(marker.pid)
+                }
+                None => launcher.launch(new_sandbox_id, process).await,
+            };
+            let (pid, error) = match outcome {
+                Ok(pid) => {
+                    if adopt.is_none() {
+                        if let Err(e) = self.backend().contain(pid) {
+                            warn!("Failed to put relaunched process {} of sandbox {} under group control: {}", pid, new_sandbox_id, e);
+                        }
+                        let marker = RelaunchMarker::new(process.pid, &process.name, pid).await;
+                        if let Err(e) = self.persistence_manager().save_relaunch_marker(new_sandbox_id, &marker).await {
+                            warn!("Failed to record relaunch of process {} in sandbox {}; a retried restore may start it again: {}", process.pid, new_sandbox_id, e);
+                        }
+                    }
+                    self.process_manager()
+                        .add_process(new_sandbox_id, ProcessInfo {
+                            pid,
+                            name: process.name.clone(),
+                            cmd: process.cmd.clone(),
+                            argv: process.argv.clone(),
+                            start_time: adopt.map(|marker| marker.launched_at).unwrap_or_else(Utc::now),
+                            state: ProcessState::Running,
+                            thread_count: 0,
+                            child_count: 0,
+                            exit: None,
+                            ended_at: None,
+                            labels: process.labels.clone(),
+                            session: None,
+                        })
+                        .await?;
+                    tracked.push(process.pid);
+                    (Some(pid), None)
+                }
+                Err(e) => {
+                    warn!("Failed to relaunch process {} ({}) in sandbox {}: {}", process.pid, process.name, new_sandbox_id, e);
+                    (None, Some(e))
+                }
+            };
+            report.processes.push(ClonedProcess {
+                source_pid: process.pid,
+                name: process.name.clone(),
+                pid,
+                error,
+                caveats: plan.caveats,
+                adopted: adopt.is_some(),
+            });
+        }
+        if report.cancelled {
+            warn!("Restore into sandbox {} cancelled after launching {} processes", new_sandbox_id, report.launched());
+        }
+        // A process that was not launched or adopted this time keeps its marker
+        if let Err(e) = self.persistence_manager().remove_relaunch_markers(new_sandbox_id, &tracked).await {
+            warn!("Failed to remove relaunch markers of sandbox {}: {}", new_sandbox_id, e);
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::auto_pause::{AutoPauseConfig, PauseStrategy};
+use crate::hooks::{HookErrorPolicy, HookPhase, ScriptHookConfig};
+use crate::policy::{PolicyRule, ProcessRule};
+use crate::probes::ProbeKind;
+use crate::selector::{Selector, Term};
+use crate::wasm_plugins::WasmPluginConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// One problem found in a config, located by a path such as `policies[2].selector`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, path: path.into(), message: message.into() }
+    }
+
+    fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, path: path.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.path, self.message)
+    }
+}
+
+/// Whether any diagnostic should stop a deployment
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+/// How a config file is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// TOML for `.toml` files, JSON otherwise
+    pub fn of_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    /// Parse a config into its JSON shape, which both formats share
+    pub fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            ConfigFormat::Json => serde_json::from_str(text).map_err(|e| format!("invalid JSON: {}", e)),
+            ConfigFormat::Toml => {
+                let value: toml::Value = toml::from_str(text).map_err(|e| format!("invalid TOML: {}", e))?;
+                serde_json::to_value(value).map_err(|e| format!("invalid TOML: {}", e))
+            }
+        }
+    }
+}
+
+impl AutoPauseConfig {
+    /// Read a JSON or TOML config file, going by its extension
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        let value = ConfigFormat::of_path(path).parse(&text)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Lint a JSON config: unknown fields, then parse errors or semantic problems
+pub fn lint_json(json: &str) -> Vec<Diagnostic> {
+    lint(json, ConfigFormat::Json)
+}
+
+/// Lint a config in either format
+pub fn lint(text: &str, format: ConfigFormat) -> Vec<Diagnostic> {
+    let value = match format.parse(text) {
+        Ok(value) => value,
+        Err(e) => return vec![Diagnostic::error("$", e)],
+    };
+
+    let mut diagnostics = Vec::new();
+    unknown_fields(&value, &known_fields(), "", &mut diagnostics);
+    match serde_json::from_value::<AutoPauseConfig>(value) {
+        Ok(config) => diagnostics.extend(config.validate()),
+        Err(e) => diagnostics.push(Diagnostic::error("$", e.to_string())),
+    }
+    diagnostics
+}
+
+/// The shape of a config with every field present, arrays holding one example element
+fn known_fields() -> Value {
+    let mut known = serde_json::to_value(AutoPauseConfig::default()).unwrap_or(Value::Null);
+    let examples = [
+        ("policies", serde_json::to_value(PolicyRule::default())),
+        ("process_rules", serde_json::to_value(ProcessRule::default())),
+        ("hooks", serde_json::to_value(ScriptHookConfig {
+            phase: HookPhase::PrePause,
+            command: Vec::new(),
+            order: 0,
+            timeout_secs: 0,
+            on_error: HookErrorPolicy::default(),
+        })),
+        ("wasm_plugins", serde_json::to_value(WasmPluginConfig { path: Default::default(), fuel: 0, timeout_ms: 0 })),
+    ];
+    if let Value::Object(fields) = &mut known {
+        for (field, example) in examples {
+            if let Ok(example) = example {
+                fields.insert(field.to_string(), Value::Array(vec![example]));
+            }
+        }
+    }
+    known
+}
+
+fn unknown_fields(value: &Value, known: &Value, path: &str, out: &mut Vec<Diagnostic>) {
+    match (value, known) {
+        (Value::Object(fields), Value::Object(known_fields)) => {
+            for (key, child) in fields {
+                let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match known_fields.get(key) {
+                    Some(known_child) => unknown_fields(child, known_child, &child_path, out),
+                    None => out.push(Diagnostic::error(child_path, "unknown field")),
+                }
+            }
+        }
+        (Value::Array(items), Value::Array(example)) => {
+            if let Some(example) = example.first() {
+                for (i, item) in items.iter().enumerate() {
+                    unknown_fields(item, example, &format!("{}[{}]", path, i), out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+impl AutoPauseConfig {
+    /// Semantic checks beyond what deserialization enforces
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut out = Vec::new();
+
+        if self.graceful_timeout_secs == 0 {
+            out.push(Diagnostic::warning("graceful_timeout_secs", "0 force-kills every process without a graceful shutdown"));
+        }
+        if let Some(strategy) = self.strategy {
+            if (strategy == PauseStrategy::Kill) != self.kill_on_pause {
+                out.push(Diagnostic::warning("kill_on_pause", format!("ignored because strategy is {:?}", strategy)));
+            }
+        }
+        if matches!(self.strategy(), PauseStrategy::Throttle | PauseStrategy::Auto) {
+            let parts: Vec<&str> = self.soft_pause.cpu_max.split_whitespace().collect();
+            let valid = parts.len() == 2 && (parts[0] == "max" || parts[0].parse::<u64>().is_ok()) && parts[1].parse::<u64>().is_ok();
+            if !valid {
+                out.push(Diagnostic::error("soft_pause.cpu_max", "expected \"<quota|max> <period>\""));
+            }
+        }
+        if self.scheduled_jobs.enabled && self.scheduled_jobs.crontab_dirs.is_empty() && self.scheduled_jobs.at_dirs.is_empty() {
+            out.push(Diagnostic::warning("scheduled_jobs", "enabled without any spool directories"));
+        }
+
+        if let Some(watchdog) = &self.watchdog {
+            if watchdog.interval_ms == 0 {
+                out.push(Diagnostic::error("watchdog.interval_ms", "must be at least 1"));
+            }
+            if !(watchdog.multiplier > 0.0) {
+                out.push(Diagnostic::error("watchdog.multiplier", "must be greater than 0"));
+            }
+        }
+        if let Some(remote) = &self.storage_migration_remote {
+            if self.storage_migration_source.is_some() {
+                out.push(Diagnostic::error("storage_migration_remote", "only one migration can run at a time; drop storage_migration_source"));
+            }
+            if remote.s3.bucket.is_empty() || remote.s3.endpoint.is_empty() {
+                out.push(Diagnostic::error("storage_migration_remote.s3", "needs an endpoint and a bucket"));
+            }
+        }
+        if self.integrity_scan_interval_secs == Some(0) {
+            out.push(Diagnostic::error("integrity_scan_interval_secs", "must be at least 1"));
+        }
+
+        self.validate_rules(&mut out);
+
+        for (i, hook) in self.hooks.iter().enumerate() {
+            let path = format!("hooks[{}]", i);
+            if hook.command.is_empty() {
+                out.push(Diagnostic::error(format!("{}.command", path), "empty command"));
+            }
+            if hook.on_error == HookErrorPolicy::Abort && matches!(hook.phase, HookPhase::PostPause | HookPhase::PostResume) {
+                out.push(Diagnostic::warning(format!("{}.on_error", path), "abort has no effect after the operation; failures are only warned about"));
+            }
+        }
+
+        for (i, probe) in self.resume_probes.iter().enumerate() {
+            let path = format!("resume_probes[{}]", i);
+            if probe.period_secs == 0 {
+                out.push(Diagnostic::error(format!("{}.period_secs", path), "must be at least 1"));
+            }
+            if probe.success_threshold == 0 || probe.failure_threshold == 0 {
+                out.push(Diagnostic::warning(path.clone(), "thresholds of 0 are treated as 1"));
+            }
+            if matches!(&probe.kind, ProbeKind::Exec { command } if command.is_empty()) {
+                out.push(Diagnostic::error(format!("{}.command", path), "empty command"));
+            }
+        }
+
+        out
+    }
+
+    fn validate_rules(&self, out: &mut Vec<Diagnostic>) {
+        for (i, rule) in self.process_rules.iter().enumerate() {
+            let path = format!("process_rules[{}]", i);
+            if rule.never_kill && rule.graceful_timeout_secs.is_some() {
+                out.push(Diagnostic::warning(format!("{}.graceful_timeout_secs", path), "unused: never_kill processes are not signalled"));
+            }
+            if let Some(reason) = rule.selector.as_ref().and_then(never_matches) {
+                out.push(Diagnostic::error(format!("{}.selector", path), format!("can never match: {}", reason)));
+            }
+        }
+        if !self.process_rules.is_empty() {
+            out.push(Diagnostic::warning("process_rules", "deprecated; express these as policies"));
+        }
+
+        for (i, rule) in self.policies.iter().enumerate() {
+            let path = format!("policies[{}]", i);
+            if let Some(reason) = rule.selector.as_ref().and_then(never_matches) {
+                out.push(Diagnostic::error(format!("{}.selector", path), format!("can never match: {}", reason)));
+                continue;
+            }
+            if let Some(reason) = conflicting_labels(&rule.match_labels, rule.selector.as_ref()) {
+                out.push(Diagnostic::error(path.clone(), format!("can never match: {}", reason)));
+                continue;
+            }
+            if rule.action.is_none() && rule.timeout_secs.is_none() && rule.signal.is_none() {
+                out.push(Diagnostic::warning(path.clone(), "sets no action, timeout or signal"));
+                continue;
+            }
+
+            // Each setting comes from the first matching rule that sets it, so a rule is
+            // unreachable when every setting it makes is already made by a broader earlier rule
+            let shadowing: Vec<(usize, &PolicyRule)> = self.policies[..i].iter().enumerate().filter(|(_, earlier)| covers(earlier, rule)).collect();
+            let shadowed = |set: bool, earlier_sets: fn(&PolicyRule) -> bool| !set || shadowing.iter().any(|(_, earlier)| earlier_sets(earlier));
+            if shadowed(rule.action.is_some(), |r| r.action.is_some())
+                && shadowed(rule.timeout_secs.is_some(), |r| r.timeout_secs.is_some())
+                && shadowed(rule.signal.is_some(), |r| r.signal.is_some())
+            {
+                let by: Vec<String> = shadowing.iter().map(|(j, _)| format!("policies[{}]", j)).collect();
+                out.push(Diagnostic::warning(path.clone(), format!("unreachable: fully shadowed by {}", by.join(", "))));
+            }
+            if let (Some((j, earlier)), Some(action)) = (shadowing.iter().find(|(_, r)| r.action.is_some()), rule.action) {
+                if earlier.action != Some(action) && equivalent(earlier, rule) {
+                    out.push(Diagnostic::error(
+                        format!("{}.action", path),
+                        format!("contradicts policies[{}]: same conditions but {:?} instead of {:?}", j, action, earlier.action.unwrap_or(action)),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Whether every process matching `narrow` also matches `broad`
+fn covers(broad: &PolicyRule, narrow: &PolicyRule) -> bool {
+    let labels = broad.match_labels.iter().all(|(k, v)| narrow.match_labels.get(k) == Some(v));
+    let narrow_terms = narrow.selector.as_ref().map(|s| s.terms()).unwrap_or_default();
+    let terms = broad.selector.as_ref().map_or(true, |s| s.terms().iter().all(|t| narrow_terms.contains(t)));
+    labels && terms
+}
+
+fn equivalent(a: &PolicyRule, b: &PolicyRule) -> bool {
+    covers(a, b) && covers(b, a)
+}
+
+/// Why a selector can match no process, if it cannot
+fn never_matches(selector: &Selector) -> Option<String> {
+    let mut labels: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut state = None;
+    let mut older = None;
+    let mut younger = None;
+    for term in selector.terms() {
+        match term {
+            Term::LabelEq(key, value) => {
+                if let Some(other) = labels.insert(key, value) {
+                    if other != value {
+                        return Some(format!("{} is both {} and {}", key, other, value));
+                    }
+                }
+            }
+            Term::State(s) => {
+                if let Some(other) = state.replace(s) {
+                    if other != s {
+                        return Some(format!("state is both {:?} and {:?}", other, s));
+                    }
+                }
+            }
+            Term::OlderThan(d) => older = Some(older.map_or(*d, |o: std::time::Duration| o.max(*d))),
+            Term::YoungerThan(d) => younger = Some(younger.map_or(*d, |y: std::time::Duration| y.min(*d))),
+            _ => {}
+        }
+    }
+    for term in selector.terms() {
+        if let Term::LabelNe(key, value) = term {
+            if labels.get(key.as_str()) == Some(&value.as_str()) {
+                return Some(format!("{} is both {} and not {}", key, value, value));
+            }
+        }
+    }
+    match (older, younger) {
+        (Some(older), Some(younger)) if older >= younger => Some(format!("age>{:?} and age<{:?}", older, younger)),
+        _ => None,
+    }
+}
+
+/// Label conditions that contradict the selector of the same rule
+fn conflicting_labels(match_labels: &BTreeMap<String, String>, selector: Option<&Selector>) -> Option<String> {
+    for term in selector.map(|s| s.terms()).unwrap_or_default() {
+        match term {
+            Term::LabelEq(key, value) => match match_labels.get(key) {
+                Some(other) if other != value => return Some(format!("{} is both {} and {}", key, other, value)),
+                _ => {}
+            },
+            Term::LabelNe(key, value) if match_labels.get(key) == Some(value) => {
+                return Some(format!("{} is both {} and not {}", key, value, value));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_fields_are_reported() {
+        let diagnostics = lint_json(r#"{"kill_on_pause": true, "graceful_timeout_secs": 30, "kil_on_pause": false, "policies": [{"acton": "spare"}]}"#);
+        let paths: Vec<&str> = diagnostics.iter().filter(|d| d.message == "unknown field").map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["kil_on_pause", "policies[0].acton"]);
+    }
+
+    #[test]
+    fn test_contradictory_and_unreachable_policies() {
+        let json = r#"{
+            "kill_on_pause": true,
+            "graceful_timeout_secs": 30,
+            "policies": [
+                {"match_labels": {"role": "db"}, "action": "spare"},
+                {"match_labels": {"role": "db"}, "action": "kill"},
+                {"selector": "role=db,role=web", "action": "freeze"}
+            ]
+        }"#;
+        let diagnostics = lint_json(json);
+        assert!(diagnostics.iter().any(|d| d.path == "policies[1]" && d.message.starts_with("unreachable")));
+        assert!(diagnostics.iter().any(|d| d.path == "policies[1].action" && d.severity == Severity::Error));
+        assert!(diagnostics.iter().any(|d| d.path == "policies[2].selector" && d.message.starts_with("can never match")));
+        assert!(has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn test_toml_config_is_linted_like_json() {
+        let toml = r#"
+            kill_on_pause = true
+            graceful_timeout_secs = 30
+            kil_on_pause = false
+
+            [[policies]]
+            selector = "role=db,role=web"
+            action = "freeze"
+        "#;
+        let diagnostics = lint(toml, ConfigFormat::Toml);
+        assert!(diagnostics.iter().any(|d| d.path == "kil_on_pause" && d.message == "unknown field"));
+        assert!(diagnostics.iter().any(|d| d.path == "policies[0].selector" && d.message.starts_with("can never match")));
+        assert_eq!(lint("kill_on_pause = ", ConfigFormat::Toml)[0].path, "$");
+        assert_eq!(ConfigFormat::of_path(Path::new("/etc/sandbox-agent/config.toml")), ConfigFormat::Toml);
+    }
+
+    #[test]
+    fn test_default_config_is_clean() {
+        assert!(AutoPauseConfig::default().validate().is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::PauseStrategy;
+use crate::stats::{TimelineEvent, TimelinePoint};
+
+/// Strategies a pause can actually run with
+pub const STRATEGIES: [PauseStrategy; 3] = [PauseStrategy::Kill, PauseStrategy::Persist, PauseStrategy::Throttle];
+
+/// Where an estimate's samples came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostSource {
+    /// Earlier pauses of the same sandbox
+    Sandbox,
+    /// Pauses of sandboxes restored from the same template
+    Template,
+    /// Pauses of every sandbox the agent has seen, when neither of the above has any with this strategy
+    Fleet,
+    /// No pause with this strategy on record
+    None,
+}
+
+/// Expected cost of pausing with one strategy: median of the recorded history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StrategyCost {
+    pub strategy: PauseStrategy,
+    pub pause_ms: Option<u64>,
+    pub snapshot_bytes: Option<u64>,
+    pub resume_ms: Option<u64>,
+    /// Pauses the estimate is based on
+    pub samples: usize,
+    pub source: CostSource,
+}
+
+#[derive(Default)]
+struct Samples {
+    pause_ms: Vec<u64>,
+    snapshot_bytes: Vec<u64>,
+    resume_ms: Vec<u64>,
+}
+
+/// Split a timeline's durations and sizes by the strategy of the pause they belong to.
+/// A snapshot is written before its pause is recorded, and a resume follows its pause.
+fn collect(timeline: &[TimelinePoint], samples: &mut HashMap<PauseStrategy, Samples>) {
+    let mut snapshot = None;
+    let mut last_strategy = None;
+    for point in timeline {
+        match &point.event {
+            TimelineEvent::Snapshot { bytes } => snapshot = Some(*bytes),
+            TimelineEvent::Pause { duration_ms, strategy } => {
+                let entry = samples.entry(*strategy).or_default();
+                entry.pause_ms.push(*duration_ms);
+                if let Some(bytes) = snapshot.take() {
+                    entry.snapshot_bytes.push(bytes);
+                }
+                last_strategy = Some(*strategy);
+            }
+            TimelineEvent::Resume { duration_ms } => {
+                if let Some(strategy) = last_strategy.take() {
+                    samples.entry(strategy).or_default().resume_ms.push(*duration_ms);
+                }
+            }
+            TimelineEvent::State { .. } => {}
+        }
+    }
+}
+
+fn median(values: &mut [u64]) -> Option<u64> {
+    values.sort_unstable();
+    values.get(values.len() / 2).copied()
+}
+
+fn cost(strategy: PauseStrategy, samples: Option<&mut Samples>, source: CostSource) -> Option<StrategyCost> {
+    let samples = samples.filter(|s| !s.pause_ms.is_empty())?;
+    Some(StrategyCost {
+        strategy,
+        samples: samples.pause_ms.len(),
+        pause_ms: median(&mut samples.pause_ms),
+        snapshot_bytes: median(&mut samples.snapshot_bytes),
+        resume_ms: median(&mut samples.resume_ms),
+        source,
+    })
+}
+
+/// Estimate every strategy for a sandbox from its own timeline, falling back to the
+/// timelines of sandboxes from its template, then of all sandboxes, for strategies it
+/// was never paused with
+pub fn estimate(sandbox: &[TimelinePoint], template: &[Vec<TimelinePoint>], fleet: &[Vec<TimelinePoint>]) -> Vec<StrategyCost> {
+    let mut own = HashMap::new();
+    collect(sandbox, &mut own);
+    let mut siblings = HashMap::new();
+    for timeline in template {
+        collect(timeline, &mut siblings);
+    }
+    let mut all = HashMap::new();
+    for timeline in fleet {
+        collect(timeline, &mut all);
+    }
+    STRATEGIES
+        .iter()
+        .map(|&strategy| {
+            cost(strategy, own.get_mut(&strategy), CostSource::Sandbox)
+                .or_else(|| cost(strategy, siblings.get_mut(&strategy), CostSource::Template))
+                .or_else(|| cost(strategy, all.get_mut(&strategy), CostSource::Fleet))
+                .unwrap_or(StrategyCost {
+                    strategy,
+                    pause_ms: None,
+                    snapshot_bytes: None,
+                    resume_ms: None,
+                    samples: 0,
+                    source: CostSource::None,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::StatsStore;
+
+    #[test]
+    fn test_estimate_prefers_own_history() {
+        let store = StatsStore::new();
+        for (pause, bytes, resume) in [(100, 2048, 40), (300, 4096, 60), (200, 1024, 50)] {
+            store.record("a", TimelineEvent::Snapshot { bytes });
+            store.record("a", TimelineEvent::Pause { duration_ms: pause, strategy: PauseStrategy::Persist });
+            store.record("a", TimelineEvent::Resume { duration_ms: resume });
+        }
+        store.record("b", TimelineEvent::Pause { duration_ms: 10, strategy: PauseStrategy::Kill });
+        store.record("b", TimelineEvent::Pause { duration_ms: 900, strategy: PauseStrategy::Persist });
+
+        let fleet: Vec<Vec<TimelinePoint>> = store.all().into_iter().map(|stats| stats.timeline).collect();
+        let costs = estimate(&store.sandbox("a").unwrap().timeline, &[], &fleet);
+        let persist = &costs[1];
+        assert_eq!((persist.source, persist.samples), (CostSource::Sandbox, 3));
+        assert_eq!((persist.pause_ms, persist.snapshot_bytes, persist.resume_ms), (Some(200), Some(2048), Some(50)));
+
+        let kill = &costs[0];
+        assert_eq!((kill.source, kill.pause_ms, kill.resume_ms), (CostSource::Fleet, Some(10), None));
+        assert_eq!(costs[2].source, CostSource::None);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+use crate::auto_pause::AutoPauseManager;
+use crate::blocking;
+use crate::lifecycle::LifecycleState;
+use crate::operations::{OperationInfo, OperationKind};
+use crate::persistence::PersistenceManager;
+
+/// File under the snapshot base holding the dump of the last crash
+pub const CRASH_FILE: &str = ".crash.json";
+
+/// What the agent was doing when it panicked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashDump {
+    pub crashed_at: DateTime<Utc>,
+    pub message: String,
+    pub location: Option<String>,
+    pub thread: Option<String>,
+    /// Cached lifecycle states; None when the cache was locked at crash time
+    pub states: Option<BTreeMap<String, LifecycleState>>,
+    /// In-flight operations and their phase; None when the tracker was locked
+    pub operations: Option<Vec<OperationInfo>>,
+    /// Sandboxes that may have been left half paused or resumed: mid-operation,
+    /// prepared, or in a transitional lifecycle state
+    pub affected: Vec<String>,
+    /// Sandboxes with a pause between prepare and commit
+    #[serde(default)]
+    pub prepared: Vec<String>,
+    /// Messages of earlier panics since the dump was last cleared, oldest first
+    #[serde(default)]
+    pub earlier: Vec<String>,
+}
+
+impl CrashDump {
+    /// Dump of the given in-flight state; `affected` is derived from it
+    pub fn new(message: String, location: Option<String>, states: Option<BTreeMap<String, LifecycleState>>, operations: Option<Vec<OperationInfo>>, pending_pauses: Vec<String>) -> Self {
+        let mut affected = pending_pauses.clone();
+        for (sandbox_id, state) in states.iter().flatten() {
+            if matches!(state, LifecycleState::Pausing | LifecycleState::Resuming) {
+                affected.push(sandbox_id.clone());
+            }
+        }
+        affected.extend(operations.iter().flatten().map(|operation| operation.sandbox_id.clone()));
+        affected.sort();
+        affected.dedup();
+        Self {
+            crashed_at: Utc::now(),
+            message,
+            location,
+            thread: std::thread::current().name().map(String::from),
+            states,
+            operations,
+            affected,
+            prepared: pending_pauses,
+            earlier: Vec::new(),
+        }
+    }
+
+    /// Fold in the dump of an earlier panic that recovery has not handled yet, so a
+    /// sandbox it found mid-operation stays affected. This dump's state wins where
+    /// both recorded a sandbox.
+    pub fn merge(mut self, earlier: CrashDump) -> Self {
+        let mut messages = earlier.earlier;
+        messages.push(earlier.message);
+        messages.append(&mut self.earlier);
+        self.earlier = messages;
+        if let Some(states) = &mut self.states {
+            for (sandbox_id, state) in earlier.states.into_iter().flatten() {
+                states.entry(sandbox_id).or_insert(state);
+            }
+        } else {
+            self.states = earlier.states;
+        }
+        let operations = self.operations.get_or_insert_with(Vec::new);
+        for operation in earlier.operations.into_iter().flatten() {
+            if !operations.iter().any(|current| current.sandbox_id == operation.sandbox_id) {
+                operations.push(operation);
+            }
+        }
+        for (merged, earlier) in [(&mut self.affected, earlier.affected), (&mut self.prepared, earlier.prepared)] {
+            merged.extend(earlier);
+            merged.sort();
+            merged.dedup();
+        }
+        self
+    }
+
+    /// The transition `sandbox_id` was in at the crash, and the phase its operation
+    /// had reached when the tracker was readable. Also covers operations that had not
+    /// recorded Pausing or Resuming yet, such as a pause still running its hooks.
+    pub fn interrupted(&self, sandbox_id: &str) -> Option<(LifecycleState, Option<String>)> {
+        if let Some(operation) = self.operations.iter().flatten().find(|operation| operation.sandbox_id == sandbox_id) {
+            let state = match operation.kind {
+                OperationKind::Pause => LifecycleState::Pausing,
+                OperationKind::Resume => LifecycleState::Resuming,
+            };
+            return Some((state, Some(operation.phase.clone())));
+        }
+        if self.prepared.iter().any(|prepared| prepared == sandbox_id) {
+            return Some((LifecycleState::Pausing, None));
+        }
+        match self.states.as_ref()?.get(sandbox_id) {
+            Some(state @ (LifecycleState::Pausing | LifecycleState::Resuming)) => Some((*state, None)),
+            _ => None,
+        }
+    }
+}
+
+impl PersistenceManager {
+    pub fn crash_file(&self) -> PathBuf {
+        self.get_base_dir().join(CRASH_FILE)
+    }
+
+    /// Dump left by an agent that crashed, if any
+    pub async fn load_crash_dump(&self) -> Result<Option<CrashDump>, Box<dyn std::error::Error>> {
+        let path = self.crash_file();
+        if !blocking::path_exists(&path).await {
+            return Ok(None);
+        }
+        let json = async_fs::read_to_string(&path).await?;
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    /// Forget the crash once its sandboxes have been reconciled
+    pub async fn clear_crash_dump(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable("crash dump removal")?;
+        let path = self.crash_file();
+        if blocking::path_exists(&path).await {
+            async_fs::remove_file(&path).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `dump`, merged with a dump already there. Synchronous, so it can run from a
+/// panic hook with the runtime in any state. Returns what was written.
+pub fn write_crash_dump(path: &Path, dump: CrashDump) -> std::io::Result<CrashDump> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let dump = match fs::read(path) {
+        Ok(json) => match serde_json::from_slice::<CrashDump>(&json) {
+            Ok(earlier) => dump.merge(earlier),
+            Err(e) => {
+                eprintln!("Replacing unreadable crash dump {}: {}", path.display(), e);
+                dump
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => dump,
+        Err(e) => return Err(e),
+    };
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, serde_json::to_vec_pretty(&dump)?)?;
+    fs::rename(&temp, path)?;
+    Ok(dump)
+}
+
+impl AutoPauseManager {
+    /// Write a crash dump whenever a thread panics, then run the previously installed
+    /// hook. A panicking task does not stop the agent, but it may have left a sandbox
+    /// half changed, so it is dumped too, merged into any dump recovery has not cleared.
+    /// Nothing is written while the agent is read-only.
+    pub fn install_crash_handler(self: &Arc<Self>) {
+        let manager = Arc::downgrade(self);
+        let path = self.persistence_manager().crash_file();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(manager) = manager.upgrade().filter(|manager| !manager.persistence_manager().is_read_only()) {
+                let message = info
+                    .payload()
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| info.payload().downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "panic".to_string());
+                let dump = manager.crash_dump(message, info.location().map(|location| location.to_string()));
+                // Logging may be what panicked, so report straight to stderr
+                match write_crash_dump(&path, dump) {
+                    Ok(dump) => eprintln!("Crash dump with {} affected sandboxes written to {}", dump.affected.len(), path.display()),
+                    Err(e) => eprintln!("Failed to write crash dump to {}: {}", path.display(), e),
+                }
+            }
+            previous(info);
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dump_marks_transitional_sandboxes() {
+        let states = BTreeMap::from([
+            ("running".to_string(), LifecycleState::Running),
+            ("pausing".to_string(), LifecycleState::Pausing),
+            ("paused".to_string(), LifecycleState::Paused),
+        ]);
+        let dump = CrashDump::new("boom".to_string(), None, Some(states), None, vec!["prepared".to_string()]);
+        assert_eq!(dump.affected, ["pausing", "prepared"]);
+        assert_eq!(dump.interrupted("pausing"), Some((LifecycleState::Pausing, None)));
+        assert_eq!(dump.interrupted("prepared"), Some((LifecycleState::Pausing, None)));
+        assert_eq!(dump.interrupted("paused"), None);
+
+        // A resume still waiting for its slot has not recorded Resuming yet
+        let queued = OperationInfo {
+            id: 1,
+            sandbox_id: "paused".to_string(),
+            kind: OperationKind::Resume,
+            phase: "queued".to_string(),
+            started_at: Utc::now(),
+            elapsed_ms: 0,
+            last_progress_ms: 0,
+            deadline_in_ms: None,
+            cancelled: false,
+        };
+        let early = CrashDump::new("boom".to_string(), None, dump.states.clone(), Some(vec![queued]), Vec::new());
+        assert_eq!(early.affected, ["paused", "pausing"]);
+        assert_eq!(early.interrupted("paused"), Some((LifecycleState::Resuming, Some("queued".to_string()))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let persistence = PersistenceManager::with_base_dir(dir.path().to_path_buf());
+        assert!(persistence.load_crash_dump().await.unwrap().is_none());
+        write_crash_dump(&persistence.crash_file(), dump.clone()).unwrap();
+        assert_eq!(persistence.load_crash_dump().await.unwrap().unwrap().affected, dump.affected);
+
+        // A second panic before recovery keeps what the first one found
+        let later = CrashDump::new("again".to_string(), None, Some(BTreeMap::new()), Some(Vec::new()), Vec::new());
+        write_crash_dump(&persistence.crash_file(), later).unwrap();
+        let merged = persistence.load_crash_dump().await.unwrap().unwrap();
+        assert_eq!(merged.message, "again");
+        assert_eq!(merged.earlier, ["boom"]);
+        assert_eq!(merged.affected, ["pausing", "prepared"]);
+        assert_eq!(merged.interrupted("pausing"), Some((LifecycleState::Pausing, None)));
+        assert_eq!(merged.interrupted("prepared"), Some((LifecycleState::Pausing, None)));
+        persistence.clear_crash_dump().await.unwrap();
+        assert!(persistence.load_crash_dump().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinHandle;
+
+use crate::auto_pause::{AutoPauseManager, PauseOptions, PauseTrigger};
+use crate::metrics;
+use crate::process::ProcessExit;
+use crate::sandbox_id::SandboxId;
+use crate::warnings::{Warning, WarningKind};
+
+/// When repeated failures count as a crash loop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrashLoopConfig {
+    /// Failures of the same command within this window (default: 60)
+    pub window_secs: u64,
+    /// Failures within the window that make a crash loop (default: 5)
+    pub max_failures: usize,
+    /// Pause a sandbox once it is crash-looping (default: false)
+    pub auto_pause: bool,
+}
+
+impl Default for CrashLoopConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 60,
+            max_failures: 5,
+            auto_pause: false,
+        }
+    }
+}
+
+/// A command that keeps failing inside a sandbox
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashLoop {
+    pub sandbox_id: String,
+    pub name: String,
+    pub cmd: String,
+    pub failures: usize,
+}
+
+/// Counts failed exits per (sandbox, name, command) over a sliding window
+pub struct CrashLoopDetector {
+    config: CrashLoopConfig,
+    failures: HashMap<(String, String, String), VecDeque<DateTime<Utc>>>,
+}
+
+impl CrashLoopDetector {
+    pub fn new(config: CrashLoopConfig) -> Self {
+        Self {
+            config,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Record one failed exit; returns the crash loop it completes, if any.
+    /// The count restarts after a loop is reported so it is not raised on every exit.
+    pub fn observe(&mut self, sandbox_id: &str, name: &str, cmd: &str, at: DateTime<Utc>) -> Option<CrashLoop> {
+        let window = chrono::Duration::seconds(self.config.window_secs as i64);
+        let key = (sandbox_id.to_string(), name.to_string(), cmd.to_string());
+        let failures = self.failures.entry(key).or_default();
+        failures.push_back(at);
+        while failures.front().map_or(false, |first| at - *first > window) {
+            failures.pop_front();
+        }
+        if failures.len() < self.config.max_failures {
+            return None;
+        }
+
+        let count = failures.len();
+        failures.clear();
+        Some(CrashLoop {
+            sandbox_id: sandbox_id.to_string(),
+            name: name.to_string(),
+            cmd: cmd.to_string(),
+            failures: count,
+        })
+    }
+
+    /// Drop history of sandboxes that are no longer tracked
+    pub fn retain_sandboxes(&mut self, sandbox_ids: &[SandboxId]) {
+        self.failures.retain(|(sandbox_id, _, _), _| sandbox_ids.iter().any(|id| id == sandbox_id));
+    }
+}
+
+/// Scans recently terminated processes for crash loops, raising a warning
+/// and optionally pausing the sandbox
+pub struct CrashLoopMonitor {
+    manager: Arc<AutoPauseManager>,
+    detector: CrashLoopDetector,
+    auto_pause: bool,
+    interval: Duration,
+    /// End time of the newest exit already counted per sandbox
+    seen_until: HashMap<SandboxId, DateTime<Utc>>,
+}
+
+impl CrashLoopMonitor {
+    pub fn new(manager: Arc<AutoPauseManager>, config: CrashLoopConfig, interval: Duration) -> Self {
+        Self {
+            manager,
+            auto_pause: config.auto_pause,
+            detector: CrashLoopDetector::new(config),
+            interval,
+            seen_until: HashMap::new(),
+        }
+    }
+
+    /// Run the monitor in the background
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.check_once().await;
+            }
+        })
+    }
+
+    /// Count exits since the last scan; returns the crash loops detected
+    pub async fn check_once(&mut self) -> Vec<CrashLoop> {
+        let process_manager = self.manager.process_manager();
+        let sandbox_ids = process_manager.sandbox_ids().await;
+        self.detector.retain_sandboxes(&sandbox_ids);
+        self.seen_until.retain(|sandbox_id, _| sandbox_ids.contains(sandbox_id));
+
+        let mut loops = Vec::new();
+        for sandbox_id in &sandbox_ids {
+            let seen_until = self.seen_until.get(sandbox_id).copied();
+            let mut exits: Vec<_> = process_manager
+                .recently_terminated(sandbox_id)
+                .await
+                .into_iter()
+                .filter_map(|p| p.ended_at.map(|ended| (ended, p)))
+                .filter(|(ended, _)| seen_until.map_or(true, |seen| *ended > seen))
+                .collect();
+            exits.sort_by_key(|(ended, _)| *ended);
+            if let Some((newest, _)) = exits.last() {
+                self.seen_until.insert(sandbox_id.clone(), *newest);
+            }
+
+            for (ended, process) in exits {
+                // Clean exits are not crashes
+                if process.exit == Some(ProcessExit::Code(0)) {
+                    continue;
+                }
+                if let Some(crash_loop) = self.detector.observe(sandbox_id, &process.name, &process.cmd, ended) {
+                    loops.push(crash_loop);
+                }
+            }
+        }
+
+        for crash_loop in &loops {
+            self.report(crash_loop).await;
+        }
+        loops
+    }
+
+    async fn report(&self, crash_loop: &CrashLoop) {
+        metrics::record_crash_loop();
+        let message = format!(
+            "Process {} ({}) failed {} times within the crash-loop window",
+            crash_loop.name, crash_loop.cmd, crash_loop.failures
+        );
+        self.manager
+            .publish_warning(Warning::new(WarningKind::CrashLoop, &crash_loop.sandbox_id, message));
+
+        if self.auto_pause && !self.manager.is_pause_pending(&crash_loop.sandbox_id) {
+            info!("Pausing crash-looping sandbox {}", crash_loop.sandbox_id);
+            if let Err(e) = self.manager.prepare_pause_with(&crash_loop.sandbox_id, PauseOptions { trigger: PauseTrigger::CrashLoop, ..Default::default() }).await {
+                warn!("Failed to pause crash-looping sandbox {}: {}", crash_loop.sandbox_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_failures_in_window_are_a_crash_loop() {
+        let mut detector = CrashLoopDetector::new(CrashLoopConfig {
+            window_secs: 10,
+            max_failures: 3,
+            auto_pause: false,
+        });
+        let start = Utc::now();
+        let at = |secs| start + chrono::Duration::seconds(secs);
+
+        assert!(detector.observe("sbx", "worker", "worker --once", at(0)).is_none());
+        // Outside the window, so the first failure no longer counts
+        assert!(detector.observe("sbx", "worker", "worker --once", at(11)).is_none());
+        assert!(detector.observe("sbx", "worker", "worker --once", at(12)).is_none());
+        let crash_loop = detector.observe("sbx", "worker", "worker --once", at(13)).unwrap();
+        assert_eq!(crash_loop.failures, 3);
+
+        // The count restarts after a report
+        assert!(detector.observe("sbx", "worker", "worker --once", at(14)).is_none());
+        assert!(detector.observe("sbx", "other", "other", at(14)).is_none());
+    }
+}
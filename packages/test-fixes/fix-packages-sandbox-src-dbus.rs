@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use zbus::{connection, fdo, interface, Connection};
+use log::info;
+
+use crate::auto_pause::{AutoPauseManager, PauseOptions};
+use crate::backend::GroupSignal;
+use crate::feature_flags::Feature;
+use crate::logging::LogLevels;
+use crate::selector::Selector;
+
+/// Well-known bus name claimed by the agent
+pub const BUS_NAME: &str = "org.e2b.Sandbox1";
+
+/// Object path the sandbox interface is served at
+pub const OBJECT_PATH: &str = "/org/e2b/Sandbox1";
+
+/// D-Bus front-end for pause/resume/status so host tooling and systemd units
+/// can drive the agent without an HTTP/gRPC stack
+pub struct SandboxInterface {
+    manager: Arc<AutoPauseManager>,
+    log_levels: Option<LogLevels>,
+}
+
+impl SandboxInterface {
+    pub fn new(manager: Arc<AutoPauseManager>) -> Self {
+        Self {
+            manager,
+            log_levels: None,
+        }
+    }
+
+    /// Allow log levels to be changed over the bus
+    pub fn with_log_levels(mut self, log_levels: LogLevels) -> Self {
+        self.log_levels = Some(log_levels);
+        self
+    }
+}
+
+#[interface(name = "org.e2b.Sandbox1")]
+impl SandboxInterface {
+    /// Prepare the sandbox for pause; `force` overrides protection
+    async fn pause(&self, sandbox_id: &str, force: bool) -> fdo::Result<()> {
+        self.manager
+            .prepare_pause_with(sandbox_id, PauseOptions { force, ..Default::default() })
+            .await
+            .map(|_| ())
+            .map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    /// Restore the sandbox after resume
+    async fn resume(&self, sandbox_id: &str) -> fdo::Result<()> {
+        self.manager
+            .after_resume(sandbox_id)
+            .await
+            .map(|_| ())
+            .map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    /// Return (process_count, has_snapshot) for the sandbox
+    async fn status(&self, sandbox_id: &str) -> fdo::Result<(u32, bool)> {
+        let status = self
+            .manager
+            .status(sandbox_id)
+            .await
+            .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+
+        Ok((status.process_count as u32, status.has_snapshot))
+    }
+
+    /// Return (pid, name, state) of every process matched by the selector
+    async fn list_processes(&self, sandbox_id: &str, selector: &str) -> fdo::Result<Vec<(i32, String, String)>> {
+        let selector: Selector = selector.parse().map_err(fdo::Error::InvalidArgs)?;
+        Ok(self
+            .manager
+            .process_manager()
+            .select(sandbox_id, &selector)
+            .await
+            .into_iter()
+            .map(|p| (p.pid, p.name, format!("{:?}", p.state).to_lowercase()))
+            .collect())
+    }
+
+    /// Terminate (or with `force`, kill) every process matched by the selector;
+    /// returns how many were signalled
+    async fn kill(&self, sandbox_id: &str, selector: &str, force: bool) -> fdo::Result<u32> {
+        let selector: Selector = selector.parse().map_err(fdo::Error::InvalidArgs)?;
+        let signal = if force { GroupSignal::Kill } else { GroupSignal::Terminate };
+        self.manager
+            .signal_selected(sandbox_id, &selector, signal)
+            .await
+            .map(|pids| pids.len() as u32)
+            .map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+
+    /// Return (id, sandbox_id, kind, phase, elapsed_ms, deadline_in_ms) of every in-flight
+    /// pause and resume; `deadline_in_ms` is `i64::MAX` for operations without a deadline
+    async fn list_operations(&self) -> Vec<(u64, String, String, String, u64, i64)> {
+        self.manager
+            .list_operations()
+            .into_iter()
+            .map(|op| (op.id, op.sandbox_id, format!("{:?}", op.kind).to_lowercase(), op.phase, op.elapsed_ms, op.deadline_in_ms.unwrap_or(i64::MAX)))
+            .collect()
+    }
+
+    /// Redeem the token of a grace notice; returns the sandbox kept alive and the
+    /// RFC 3339 time its hold expires
+    async fn keep_alive(&self, token: &str) -> fdo::Result<(String, String)> {
+        self.manager
+            .redeem_keep_alive(token)
+            .map(|(sandbox_id, hold)| (sandbox_id, hold.expires_at.to_rfc3339()))
+            .ok_or_else(|| fdo::Error::InvalidArgs("unknown or expired keep-alive token".to_string()))
+    }
+
+    /// Cancel an in-flight operation at its next checkpoint
+    async fn cancel_operation(&self, id: u64) -> fdo::Result<()> {
+        if self.manager.cancel_operation(id) {
+            Ok(())
+        } else {
+            Err(fdo::Error::InvalidArgs(format!("no operation {} in flight", id)))
+        }
+    }
+
+    /// Internal state (lifecycle states, locks, queues, tasks) as JSON for bug reports
+    async fn dump_diagnostics(&self) -> String {
+        self.manager.dump_diagnostics().to_json()
+    }
+
+    /// Toggle a runtime feature flag; an empty tenant applies host-wide
+    async fn set_feature(&self, feature: &str, tenant: &str, enabled: bool) -> fdo::Result<()> {
+        let feature: Feature = feature.parse().map_err(fdo::Error::InvalidArgs)?;
+        let flags = self.manager.feature_flags();
+        if tenant.is_empty() {
+            flags.set(feature, enabled);
+        } else {
+            flags.set_for_tenant(tenant, feature, enabled);
+        }
+        Ok(())
+    }
+
+    /// Set the log level of one module; an empty level removes the override
+    async fn set_log_level(&self, module: &str, level: &str) -> fdo::Result<()> {
+        let levels = self
+            .log_levels
+            .as_ref()
+            .ok_or_else(|| fdo::Error::NotSupported("runtime log levels not enabled".to_string()))?;
+        if level.is_empty() {
+            levels.clear_module_level(module);
+        } else {
+            let level = level
+                .parse()
+                .map_err(|_| fdo::Error::InvalidArgs(format!("invalid log level: {}", level)))?;
+            levels.set_module_level(module, level);
+        }
+        Ok(())
+    }
+}
+
+/// Claim the bus name on the system bus and serve the interface
+pub async fn serve(interface: SandboxInterface) -> Result<Connection, Box<dyn std::error::Error>> {
+    let connection = connection::Builder::system()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, interface)?
+        .build()
+        .await?;
+
+    info!("Serving {} at {} on the system bus", BUS_NAME, OBJECT_PATH);
+    Ok(connection)
+}
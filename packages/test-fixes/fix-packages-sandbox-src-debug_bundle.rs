@@ -0,0 +1,266 @@
+use std::fs::{self, File};
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Serialize, Deserialize};
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::auto_pause::AutoPauseManager;
+use crate::blocking;
+use crate::persistence::SnapshotLoad;
+
+/// Keys whose values identify a sandbox or a person; replaced by a keyed hash
+const ID_KEYS: &[&str] = &["sandbox_id", "tenant", "owner", "team", "approver", "requested_by", "app"];
+/// Keys whose string values are states, kinds or other agent vocabulary and are kept
+/// as they are. Any other string is redacted unless listed in `TEXT_KEYS`.
+const KEPT_KEYS: &[&str] = &[
+    "state", "from", "to", "kind", "phase", "status", "strategy", "action", "operation", "trigger", "priority", "interpreter", "name", "signal", "step", "level",
+    "feature", "schedule", "timestamp", "start_time", "at",
+];
+/// Free text written by the agent; kept with IDs hashed and anything path- or
+/// credential-like removed
+const TEXT_KEYS: &[&str] = &["message", "error"];
+/// Objects whose `message` is output captured from inside the sandbox, such as a
+/// probe's last outcome; redacted whole
+const CAPTURED_KEYS: &[&str] = &["last"];
+/// Maps whose keys are user-chosen; the keys stay, every value is redacted
+const USER_MAPS: &[&str] = &["labels", "env"];
+const REDACTED: &str = "[redacted]";
+
+/// What a debug bundle holds, written into it as `manifest.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub created_at: DateTime<Utc>,
+    /// Hashed ID of the sandbox the bundle is about. The hash is keyed per bundle, so
+    /// it only matches IDs inside the same bundle.
+    pub sandbox: String,
+    pub files: Vec<String>,
+}
+
+/// Removes personal data and secrets from JSON before it leaves the host. Only
+/// allow-listed keys keep their strings: IDs are hashed with a key drawn fresh for
+/// each bundle, so the same sandbox reads the same across its files but cannot be
+/// matched against a guessed ID, commands keep only their program name, and every
+/// other string is redacted.
+pub struct Scrubber {
+    key: [u8; 32],
+    /// Raw IDs and their hashes, replaced inside free text such as error messages
+    ids: Vec<(String, String)>,
+}
+
+impl Scrubber {
+    pub fn new(known_ids: impl IntoIterator<Item = String>) -> Result<Self, getrandom::Error> {
+        let mut key = [0u8; 32];
+        getrandom::getrandom(&mut key)?;
+        Ok(Self::with_key(key, known_ids))
+    }
+
+    fn with_key(key: [u8; 32], known_ids: impl IntoIterator<Item = String>) -> Self {
+        let mut scrubber = Self { key, ids: Vec::new() };
+        let mut ids: Vec<(String, String)> = known_ids
+            .into_iter()
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                let hash = scrubber.hash_id(&id);
+                (id, hash)
+            })
+            .collect();
+        // Longest first, so an ID that contains another is replaced whole
+        ids.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then_with(|| a.0.cmp(&b.0)));
+        ids.dedup();
+        scrubber.ids = ids;
+        scrubber
+    }
+
+    /// Stable, non-reversible stand-in for an ID, valid within this bundle
+    pub fn hash_id(&self, id: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes keys of any length");
+        mac.update(id.as_bytes());
+        let digest: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("anon-{}", &digest[..12])
+    }
+
+    pub fn scrub(&self, value: Value) -> Value {
+        match value {
+            Value::Object(map) => Value::Object(
+                map.into_iter()
+                    .map(|(key, value)| {
+                        let value = self.scrub_field(&key, value);
+                        // Map keys can be IDs, e.g. of per-sandbox tables
+                        (self.scrub_text(&key), value)
+                    })
+                    .collect(),
+            ),
+            Value::Array(items) => Value::Array(items.into_iter().map(|item| self.scrub(item)).collect()),
+            // A string outside any allow-listed key
+            Value::String(_) => Value::String(REDACTED.to_string()),
+            other => other,
+        }
+    }
+
+    fn scrub_field(&self, key: &str, value: Value) -> Value {
+        match (key, value) {
+            (_, Value::Null) => Value::Null,
+            (key, Value::String(id)) if ID_KEYS.contains(&key) => Value::String(self.hash_id(&id)),
+            ("cmd", Value::String(cmd)) => Value::String(redact_cmd(&cmd)),
+            (key, Value::String(text)) if TEXT_KEYS.contains(&key) => Value::String(self.scrub_message(&text)),
+            (key, Value::String(text)) if KEPT_KEYS.contains(&key) || key.ends_with("_at") => Value::String(text),
+            (key, Value::Object(map)) if USER_MAPS.contains(&key) => Value::Object(map.into_iter().map(|(key, _)| (key, Value::String(REDACTED.to_string()))).collect()),
+            (key, Value::Object(mut captured)) if CAPTURED_KEYS.contains(&key) => {
+                if let Some(message) = captured.get_mut("message") {
+                    *message = Value::String(REDACTED.to_string());
+                }
+                self.scrub(Value::Object(captured))
+            }
+            (_, value) => self.scrub(value),
+        }
+    }
+
+    fn scrub_text(&self, text: &str) -> String {
+        self.ids.iter().fold(text.to_string(), |text, (id, hash)| text.replace(id.as_str(), hash))
+    }
+
+    /// IDs hashed, and words that look like paths, URLs, addresses or assignments
+    /// dropped, since messages quote commands and files
+    fn scrub_message(&self, text: &str) -> String {
+        let words: Vec<&str> = text
+            .split(' ')
+            .map(|word| if word.contains(['/', '\\', '@', '=']) { REDACTED } else { word })
+            .collect();
+        self.scrub_text(&words.join(" "))
+    }
+}
+
+/// Program name of a command line, without its arguments
+fn redact_cmd(cmd: &str) -> String {
+    let mut words = cmd.split_whitespace();
+    let program = words.next().map(|program| program.rsplit('/').next().unwrap_or(program)).unwrap_or_default();
+    match words.count() {
+        0 => program.to_string(),
+        args => format!("{} [{} args redacted]", program, args),
+    }
+}
+
+impl AutoPauseManager {
+    /// Package what a bug report about `sandbox_id` needs into a `.tar.gz` at `dest`:
+    /// its snapshot, last pause and resume reports, timeline, recent events and the
+    /// agent diagnostics narrowed to it, all scrubbed of IDs, commands and paths
+    pub async fn export_debug_bundle(&self, sandbox_id: &str, dest: &Path) -> Result<BundleManifest, Box<dyn std::error::Error>> {
+        let diagnostics = self.dump_diagnostics();
+        let mut known_ids: Vec<String> = diagnostics.sandboxes.iter().map(|sandbox| sandbox.sandbox_id.clone()).collect();
+        for entry in self.registry().list() {
+            known_ids.extend([Some(entry.sandbox_id), entry.tenant, entry.owner, entry.team].into_iter().flatten());
+        }
+        known_ids.push(sandbox_id.to_string());
+        let scrubber = Scrubber::new(known_ids)?;
+        let diagnostics = diagnostics.for_sandbox(sandbox_id);
+
+        let snapshot = match self.persistence_manager().load_snapshot_detailed(sandbox_id).await? {
+            SnapshotLoad::Loaded(snapshot) => Some(snapshot),
+            SnapshotLoad::Missing | SnapshotLoad::Stale => None,
+        };
+        let timeline = self.stats().sandbox(sandbox_id).map(|stats| stats.timeline).unwrap_or_default();
+        let files = [
+            ("snapshot.json", serde_json::to_value(snapshot)?),
+            ("last_pause.json", serde_json::to_value(self.last_pause_report(sandbox_id))?),
+            ("last_resume.json", serde_json::to_value(self.last_resume_report(sandbox_id))?),
+            ("timeline.json", serde_json::to_value(timeline)?),
+            ("events.json", serde_json::to_value(self.events().recent(sandbox_id))?),
+            ("diagnostics.json", serde_json::to_value(&diagnostics)?),
+        ];
+        let mut contents = Vec::new();
+        for (name, value) in files {
+            contents.push((name.to_string(), serde_json::to_vec_pretty(&scrubber.scrub(value))?));
+        }
+        let manifest = BundleManifest {
+            created_at: Utc::now(),
+            sandbox: scrubber.hash_id(sandbox_id),
+            files: contents.iter().map(|(name, _)| name.clone()).collect(),
+        };
+        contents.insert(0, ("manifest.json".to_string(), serde_json::to_vec_pretty(&manifest)?));
+
+        let dest = dest.to_path_buf();
+        let created_at = manifest.created_at;
+        blocking::run("debug_bundle_write", move || write_bundle(&dest, &contents, created_at)).await?;
+        Ok(manifest)
+    }
+}
+
+/// Write next to `dest` and rename, so a failed export leaves no partial archive
+fn write_bundle(dest: &Path, contents: &[(String, Vec<u8>)], created_at: DateTime<Utc>) -> Result<(), String> {
+    let temp = dest.with_extension("tmp");
+    let written = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = tar::Builder::new(GzEncoder::new(File::create(&temp)?, Compression::default()));
+        for (name, bytes) in contents {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(bytes.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(created_at.timestamp().max(0) as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, bytes.as_slice())?;
+        }
+        builder.into_inner()?.finish()?.sync_all()?;
+        fs::rename(&temp, dest)?;
+        Ok(())
+    })();
+    written.map_err(|e| {
+        let _ = fs::remove_file(&temp);
+        format!("writing {}: {}", dest.display(), e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_scrub_hashes_ids_and_redacts_commands() {
+        let scrubber = Scrubber::new(["sbx-alice-1".to_string()]).unwrap();
+        let scrubbed = scrubber.scrub(json!({
+            "sandbox_id": "sbx-alice-1",
+            "owner": null,
+            "state": "paused",
+            "processes": [{
+                "cmd": "/usr/bin/psql postgres://alice:hunter2@db/prod",
+                "labels": { "role": "db" },
+                "launch": { "cwd": "/home/alice", "interpreter": "python3" },
+            }],
+            "scheduled_jobs": {
+                "rewritten": ["/var/spool/cron/crontabs/alice"],
+                "missed": [{ "user": "alice", "command": "backup --to s3://alice", "critical": true }],
+            },
+            "health": [{ "kind": "http", "last": { "success": false, "message": "alice's dashboard: 500", "duration_ms": 12 } }],
+            "warnings": [{ "message": "Failed to kill process in sandbox sbx-alice-1 at /home/alice/run.sh" }],
+        }));
+
+        let hashed = scrubber.hash_id("sbx-alice-1");
+        assert_eq!(scrubbed["sandbox_id"], hashed.as_str());
+        assert_eq!(scrubbed["owner"], Value::Null);
+        assert_eq!(scrubbed["state"], "paused");
+        assert_eq!(scrubbed["processes"][0]["cmd"], "psql [1 args redacted]");
+        assert_eq!(scrubbed["processes"][0]["labels"]["role"], REDACTED);
+        assert_eq!(scrubbed["processes"][0]["launch"]["cwd"], REDACTED);
+        assert_eq!(scrubbed["processes"][0]["launch"]["interpreter"], "python3");
+        assert_eq!(scrubbed["scheduled_jobs"]["rewritten"][0], REDACTED);
+        assert_eq!(scrubbed["scheduled_jobs"]["missed"][0]["user"], REDACTED);
+        assert_eq!(scrubbed["scheduled_jobs"]["missed"][0]["command"], REDACTED);
+        assert_eq!(scrubbed["scheduled_jobs"]["missed"][0]["critical"], true);
+        assert_eq!(scrubbed["health"][0]["kind"], "http");
+        assert_eq!(scrubbed["health"][0]["last"]["message"], REDACTED);
+        assert_eq!(scrubbed["warnings"][0]["message"], format!("Failed to kill process in sandbox {} at {}", hashed, REDACTED));
+        assert!(!scrubbed.to_string().contains("alice"));
+    }
+
+    #[test]
+    fn test_id_hashes_are_keyed_per_bundle() {
+        let first = Scrubber::with_key([1; 32], []);
+        let second = Scrubber::with_key([2; 32], []);
+        assert_eq!(first.hash_id("sbx-1"), first.hash_id("sbx-1"));
+        assert_ne!(first.hash_id("sbx-1"), first.hash_id("sbx-2"));
+        assert_ne!(first.hash_id("sbx-1"), second.hash_id("sbx-1"));
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::{Mutex, TryLockError};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::task::AbortHandle;
+
+use crate::lifecycle::LifecycleState;
+use crate::operations::OperationInfo;
+use crate::runtime_metrics::RuntimeStats;
+
+/// Snapshot of the agent's internal state for attaching to bug reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostics {
+    pub generated_at: DateTime<Utc>,
+    pub shutting_down: bool,
+    pub sandboxes: Vec<SandboxDiagnostics>,
+    /// Empty when the operation tracker was locked at dump time, as `locks` shows
+    pub operations: Vec<OperationInfo>,
+    pub locks: Vec<LockDiagnostics>,
+    pub queues: QueueDiagnostics,
+    /// Empty when the task registry was locked at dump time
+    pub tasks: Vec<TaskHealth>,
+    /// Async runtime the dump was taken on
+    pub runtime: Option<RuntimeStats>,
+}
+
+impl Diagnostics {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The dump with sandboxes and operations other than `sandbox_id` left out;
+    /// agent-wide locks, queues and tasks are kept
+    pub fn for_sandbox(mut self, sandbox_id: &str) -> Self {
+        self.sandboxes.retain(|sandbox| sandbox.sandbox_id == sandbox_id);
+        self.operations.retain(|op| op.sandbox_id == sandbox_id);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxDiagnostics {
+    pub sandbox_id: String,
+    /// Cached lifecycle state; None when not cached or the cache was locked at dump time
+    pub state: Option<LifecycleState>,
+    /// None when the sandbox has no tenant or the registry was locked at dump time
+    pub tenant: Option<String>,
+    /// Prepared but not yet committed or aborted
+    pub pause_pending: bool,
+    /// Tracked process entries; None when the process map was locked at dump time
+    pub tracked_processes: Option<usize>,
+    /// In-flight operations holding the sandbox
+    pub operations: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockState {
+    Free,
+    /// Held by someone else at dump time; a lock held in every dump suggests a deadlock
+    Held,
+    /// A holder panicked
+    Poisoned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockDiagnostics {
+    pub name: String,
+    pub state: LockState,
+    /// Slots in use, for semaphores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holders: Option<usize>,
+    /// Operations waiting for a slot, for semaphores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub waiters: Option<usize>,
+}
+
+impl LockDiagnostics {
+    pub fn new(name: &str, state: LockState) -> Self {
+        Self {
+            name: name.to_string(),
+            state,
+            holders: None,
+            waiters: None,
+        }
+    }
+
+    /// Probe a mutex without waiting for it
+    pub fn probe<T>(name: &str, mutex: &Mutex<T>) -> Self {
+        let state = match mutex.try_lock() {
+            Ok(_) => LockState::Free,
+            Err(TryLockError::WouldBlock) => LockState::Held,
+            Err(TryLockError::Poisoned(_)) => LockState::Poisoned,
+        };
+        Self::new(name, state)
+    }
+
+    /// A semaphore with `limit` slots of which `available` are free
+    pub fn semaphore(name: &str, limit: usize, available: usize, waiters: usize) -> Self {
+        let holders = limit.saturating_sub(available);
+        Self {
+            name: name.to_string(),
+            state: if available == 0 { LockState::Held } else { LockState::Free },
+            holders: Some(holders),
+            waiters: Some(waiters),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueDiagnostics {
+    /// Blocking jobs allowed at once and slots currently free
+    pub blocking_budget: usize,
+    pub blocking_available: usize,
+    /// Operations waiting for a worker slot
+    pub operations_queued: usize,
+    pub event_subscribers: usize,
+    /// Events published but not yet received by the slowest subscriber
+    pub events_pending: usize,
+}
+
+/// Whether a background task is still running
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskHealth {
+    pub name: String,
+    pub running: bool,
+}
+
+/// Background tasks registered for health reporting
+#[derive(Default)]
+pub struct TaskRegistry {
+    tasks: Mutex<Vec<(String, AbortHandle)>>,
+}
+
+impl TaskRegistry {
+    pub fn track(&self, name: impl Into<String>, task: AbortHandle) {
+        self.tasks.lock().unwrap().push((name.into(), task));
+    }
+
+    /// None instead of waiting while a task is being registered
+    pub fn health(&self) -> Option<Vec<TaskHealth>> {
+        let tasks = self.tasks.try_lock().ok()?;
+        Some(
+            tasks
+                .iter()
+                .map(|(name, task)| TaskHealth {
+                    name: name.clone(),
+                    running: !task.is_finished(),
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lock_probe_and_task_health() {
+        let mutex = Mutex::new(());
+        assert_eq!(LockDiagnostics::probe("free", &mutex).state, LockState::Free);
+        let guard = mutex.lock().unwrap();
+        assert_eq!(LockDiagnostics::probe("held", &mutex).state, LockState::Held);
+        drop(guard);
+
+        let tasks = TaskRegistry::default();
+        let finished = tokio::spawn(async {});
+        let running = tokio::spawn(std::future::pending::<()>());
+        tasks.track("finished", finished.abort_handle());
+        tasks.track("running", running.abort_handle());
+        finished.await.unwrap();
+        let health = tasks.health().unwrap();
+        assert!(!health[0].running);
+        assert!(health[1].running);
+        running.abort();
+    }
+}
@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use serde::{Serialize, Deserialize};
+
+/// Stable, machine-readable error codes for the orchestrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    StorageTimeout,
+    StorageUnavailable,
+    StorageFull,
+    StorageReadOnly,
+    PermissionDenied,
+    CorruptedSnapshot,
+    /// A file or other resource the operation needs does not exist
+    NotFound,
+    ProcessNotFound,
+    OperationTimeout,
+    Cancelled,
+    QuotaExceeded,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::StorageTimeout => "storage_timeout",
+            ErrorCode::StorageUnavailable => "storage_unavailable",
+            ErrorCode::StorageFull => "storage_full",
+            ErrorCode::StorageReadOnly => "storage_read_only",
+            ErrorCode::PermissionDenied => "permission_denied",
+            ErrorCode::CorruptedSnapshot => "corrupted_snapshot",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::ProcessNotFound => "process_not_found",
+            ErrorCode::OperationTimeout => "operation_timeout",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::QuotaExceeded => "quota_exceeded",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    /// Whether retrying the same operation can reasonably succeed
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::StorageTimeout | ErrorCode::StorageUnavailable | ErrorCode::OperationTimeout | ErrorCode::QuotaExceeded
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Errors raised by the sandbox agent
+#[derive(Debug)]
+pub enum SandboxError {
+    /// The storage backend did not answer in time
+    StorageTimeout(String),
+    /// The storage backend failed with an I/O error
+    Storage(io::Error),
+    /// The snapshot filesystem has no space left
+    StorageFull(String),
+    /// The snapshot filesystem is mounted read-only
+    StorageReadOnly(String),
+    /// The agent is not allowed to touch a process or path
+    PermissionDenied(String),
+    /// A snapshot could not be parsed or failed validation
+    CorruptedSnapshot { sandbox_id: String, reason: String },
+    /// A process the operation relied on no longer exists
+    ProcessNotFound(i32),
+    /// An operation exceeded its time budget
+    Timeout(String),
+    /// An operation stopped at a checkpoint because its token was cancelled
+    Cancelled(String),
+    /// The sandbox's tenant has used up its concurrency or rate quota
+    QuotaExceeded { tenant: String, reason: String },
+}
+
+impl SandboxError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SandboxError::StorageTimeout(_) => ErrorCode::StorageTimeout,
+            SandboxError::Storage(e) => io_error_code(e),
+            SandboxError::StorageFull(_) => ErrorCode::StorageFull,
+            SandboxError::StorageReadOnly(_) => ErrorCode::StorageReadOnly,
+            SandboxError::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            SandboxError::CorruptedSnapshot { .. } => ErrorCode::CorruptedSnapshot,
+            SandboxError::ProcessNotFound(_) => ErrorCode::ProcessNotFound,
+            SandboxError::Timeout(_) => ErrorCode::OperationTimeout,
+            SandboxError::Cancelled(_) => ErrorCode::Cancelled,
+            SandboxError::QuotaExceeded { .. } => ErrorCode::QuotaExceeded,
+        }
+    }
+
+    pub fn is_retriable(&self) -> bool {
+        self.code().is_retriable()
+    }
+}
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::StorageTimeout(op) => write!(f, "storage timed out during {}", op),
+            SandboxError::Storage(e) => write!(f, "storage error: {}", e),
+            SandboxError::StorageFull(op) => write!(f, "storage full during {}", op),
+            SandboxError::StorageReadOnly(op) => write!(f, "storage read-only during {}", op),
+            SandboxError::PermissionDenied(what) => write!(f, "permission denied: {}", what),
+            SandboxError::CorruptedSnapshot { sandbox_id, reason } => {
+                write!(f, "corrupted snapshot for sandbox {}: {}", sandbox_id, reason)
+            }
+            SandboxError::ProcessNotFound(pid) => write!(f, "process {} not found", pid),
+            SandboxError::Timeout(op) => write!(f, "timed out: {}", op),
+            SandboxError::Cancelled(op) => write!(f, "cancelled: {}", op),
+            SandboxError::QuotaExceeded { tenant, reason } => write!(f, "tenant {} over quota: {}", tenant, reason),
+        }
+    }
+}
+
+impl Error for SandboxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SandboxError::Storage(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SandboxError {
+    fn from(e: io::Error) -> Self {
+        SandboxError::Storage(e)
+    }
+}
+
+fn io_error_code(e: &io::Error) -> ErrorCode {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => ErrorCode::StorageTimeout,
+        io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => ErrorCode::StorageFull,
+        io::ErrorKind::ReadOnlyFilesystem => ErrorCode::StorageReadOnly,
+        // Retrying does not bring a missing file back
+        io::ErrorKind::NotFound => ErrorCode::NotFound,
+        _ => ErrorCode::Internal,
+    }
+}
+
+/// Classify any boxed error returned by the agent's APIs
+pub fn classify(err: &(dyn Error + 'static)) -> ErrorCode {
+    if let Some(e) = err.downcast_ref::<SandboxError>() {
+        e.code()
+    } else if let Some(e) = err.downcast_ref::<io::Error>() {
+        io_error_code(e)
+    } else if err.downcast_ref::<serde_json::Error>().is_some() {
+        ErrorCode::CorruptedSnapshot
+    } else if err.downcast_ref::<tokio::time::error::Elapsed>().is_some() {
+        ErrorCode::OperationTimeout
+    } else {
+        ErrorCode::Internal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classification_of_boxed_errors() {
+        let timeout: Box<dyn Error> = Box::new(SandboxError::StorageTimeout("save".to_string()));
+        assert_eq!(classify(timeout.as_ref()), ErrorCode::StorageTimeout);
+        assert!(classify(timeout.as_ref()).is_retriable());
+
+        let denied: Box<dyn Error> = io::Error::from(io::ErrorKind::PermissionDenied).into();
+        assert_eq!(classify(denied.as_ref()), ErrorCode::PermissionDenied);
+        assert!(!classify(denied.as_ref()).is_retriable());
+
+        let missing: Box<dyn Error> = Box::new(SandboxError::Storage(io::Error::from(io::ErrorKind::NotFound)));
+        assert_eq!(classify(missing.as_ref()), ErrorCode::NotFound);
+        assert!(!classify(missing.as_ref()).is_retriable());
+
+        let full: Box<dyn Error> = io::Error::from(io::ErrorKind::StorageFull).into();
+        assert_eq!(classify(full.as_ref()), ErrorCode::StorageFull);
+        assert!(!classify(full.as_ref()).is_retriable());
+
+        let corrupted: Box<dyn Error> = serde_json::from_str::<u32>("{").unwrap_err().into();
+        assert_eq!(classify(corrupted.as_ref()), ErrorCode::CorruptedSnapshot);
+
+        let cancelled: Box<dyn Error> = Box::new(SandboxError::Cancelled("pause of sandbox sbx".to_string()));
+        assert_eq!(classify(cancelled.as_ref()), ErrorCode::Cancelled);
+        assert!(!classify(cancelled.as_ref()).is_retriable());
+
+        let other: Box<dyn Error> = "something odd".into();
+        assert_eq!(classify(other.as_ref()), ErrorCode::Internal);
+    }
+}
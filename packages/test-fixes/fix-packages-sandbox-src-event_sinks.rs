@@ -0,0 +1,287 @@
+use std::time::Duration;
+use futures::future::BoxFuture;
+use log::{debug, error, warn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::events::Event;
+
+/// A message bus events are forwarded to, with its batching and retry settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventSinkConfig {
+    #[serde(flatten)]
+    pub target: SinkTarget,
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+}
+
+/// Where a sink publishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SinkTarget {
+    /// Each event goes to `<subject_prefix>.<sandbox_id>.<event_type>`; needs the `nats` feature
+    Nats { url: String, subject_prefix: String },
+    /// Each event goes to `topic`, keyed by sandbox ID so a sandbox's events stay ordered
+    /// within a partition; needs the `kafka` feature
+    Kafka { brokers: String, topic: String },
+}
+
+/// How events are grouped and how hard a failed batch is retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliveryConfig {
+    /// Events sent together at most
+    pub max_batch: usize,
+    /// A partial batch is sent after waiting this long for it to fill
+    pub flush_interval_ms: u64,
+    /// Retries of a failed batch before it is dropped
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each further one
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            max_batch: 100,
+            flush_interval_ms: 1000,
+            max_retries: 5,
+            retry_backoff_ms: 200,
+        }
+    }
+}
+
+/// Publishes batches of events to an external system
+pub trait EventSink: Send + Sync {
+    fn name(&self) -> String;
+    fn send_batch<'a>(&'a self, events: &'a [Event]) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// Build the sink for `target`, failing if its feature was not compiled in
+pub fn build_sink(target: &SinkTarget) -> Result<Box<dyn EventSink>, String> {
+    match target {
+        #[cfg(feature = "nats")]
+        SinkTarget::Nats { url, subject_prefix } => Ok(Box::new(nats::NatsSink::new(url.clone(), subject_prefix.clone()))),
+        #[cfg(feature = "kafka")]
+        SinkTarget::Kafka { brokers, topic } => Ok(Box::new(kafka::KafkaSink::new(brokers, topic.clone())?)),
+        #[allow(unreachable_patterns)]
+        other => Err(format!("event sink {:?} is not available in this build", other)),
+    }
+}
+
+/// Forward events from `events` to `sink` until the bus is dropped
+pub fn spawn_sink(sink: Box<dyn EventSink>, delivery: DeliveryConfig, mut events: broadcast::Receiver<Event>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let max_batch = delivery.max_batch.max(1);
+        let flush_interval = Duration::from_millis(delivery.flush_interval_ms);
+        let mut batch = Vec::with_capacity(max_batch);
+        let mut closed = false;
+        while !closed {
+            // Wait for the first event, then give the batch until the flush interval to fill
+            let mut deadline = None;
+            while batch.len() < max_batch {
+                let received = match deadline {
+                    None => events.recv().await,
+                    Some(deadline) => match tokio::time::timeout_at(deadline, events.recv()).await {
+                        Ok(received) => received,
+                        Err(_) => break,
+                    },
+                };
+                match received {
+                    Ok(event) => {
+                        batch.push(event);
+                        deadline.get_or_insert_with(|| Instant::now() + flush_interval);
+                    }
+                    Err(RecvError::Lagged(missed)) => warn!("Event sink {} fell behind, dropped {} events", sink.name(), missed),
+                    Err(RecvError::Closed) => {
+                        closed = true;
+                        break;
+                    }
+                }
+            }
+            if !batch.is_empty() {
+                deliver(sink.as_ref(), &delivery, &batch).await;
+                batch.clear();
+            }
+        }
+    })
+}
+
+async fn deliver(sink: &dyn EventSink, delivery: &DeliveryConfig, batch: &[Event]) {
+    let mut backoff = Duration::from_millis(delivery.retry_backoff_ms);
+    for attempt in 0..=delivery.max_retries {
+        match sink.send_batch(batch).await {
+            Ok(()) => {
+                debug!("Delivered {} events to {}", batch.len(), sink.name());
+                return;
+            }
+            Err(e) if attempt < delivery.max_retries => {
+                warn!("Delivering {} events to {} failed (attempt {}), retrying: {}", batch.len(), sink.name(), attempt + 1, e);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => error!("Dropping {} events for {} after {} attempts: {}", batch.len(), sink.name(), attempt + 1, e),
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use futures::future::BoxFuture;
+    use tokio::sync::OnceCell;
+
+    use super::EventSink;
+    use crate::events::Event;
+
+    pub struct NatsSink {
+        url: String,
+        subject_prefix: String,
+        // Connected on first use, so an unreachable server is retried like any failed send
+        client: OnceCell<async_nats::Client>,
+    }
+
+    impl NatsSink {
+        pub fn new(url: String, subject_prefix: String) -> Self {
+            Self {
+                url,
+                subject_prefix,
+                client: OnceCell::new(),
+            }
+        }
+    }
+
+    impl EventSink for NatsSink {
+        fn name(&self) -> String {
+            format!("nats:{}", self.url)
+        }
+
+        fn send_batch<'a>(&'a self, events: &'a [Event]) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                let client = self
+                    .client
+                    .get_or_try_init(|| async_nats::connect(self.url.as_str()))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                for event in events {
+                    let subject = format!("{}.{}.{}", self.subject_prefix, event.sandbox_id, event.event_type());
+                    let payload = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+                    client.publish(subject, payload.into()).await.map_err(|e| e.to_string())?;
+                }
+                // Publishes are buffered by the client; the batch counts once it is flushed
+                client.flush().await.map_err(|e| e.to_string())
+            })
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::time::Duration;
+    use futures::future::{self, BoxFuture};
+    use rdkafka::config::ClientConfig;
+    use rdkafka::producer::{FutureProducer, FutureRecord};
+
+    use super::EventSink;
+    use crate::events::Event;
+
+    pub struct KafkaSink {
+        brokers: String,
+        topic: String,
+        producer: FutureProducer,
+    }
+
+    impl KafkaSink {
+        pub fn new(brokers: &str, topic: String) -> Result<Self, String> {
+            let producer = ClientConfig::new()
+                .set("bootstrap.servers", brokers)
+                .set("enable.idempotence", "true")
+                .create()
+                .map_err(|e| e.to_string())?;
+            Ok(Self {
+                brokers: brokers.to_string(),
+                topic,
+                producer,
+            })
+        }
+    }
+
+    impl EventSink for KafkaSink {
+        fn name(&self) -> String {
+            format!("kafka:{}/{}", self.brokers, self.topic)
+        }
+
+        fn send_batch<'a>(&'a self, events: &'a [Event]) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                let payloads = events
+                    .iter()
+                    .map(|event| serde_json::to_string(event).map_err(|e| e.to_string()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                // Queue the whole batch, then wait for every delivery report
+                let sends = events.iter().zip(&payloads).map(|(event, payload)| {
+                    let record = FutureRecord::to(&self.topic).key(&event.sandbox_id).payload(payload);
+                    self.producer.send(record, Duration::from_secs(30))
+                });
+                for result in future::join_all(sends).await {
+                    result.map_err(|(e, _)| e.to_string())?;
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use super::*;
+    use crate::events::{EventBus, EventPayload};
+
+    struct FlakySink {
+        failures_left: AtomicUsize,
+        batches: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl EventSink for FlakySink {
+        fn name(&self) -> String {
+            "flaky".to_string()
+        }
+
+        fn send_batch<'a>(&'a self, events: &'a [Event]) -> BoxFuture<'a, Result<(), String>> {
+            Box::pin(async move {
+                if self.failures_left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1)).is_ok() {
+                    return Err("broker unavailable".to_string());
+                }
+                self.batches.lock().unwrap().push(events.len());
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sink_batches_and_retries() {
+        let bus = EventBus::new();
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sink = FlakySink {
+            failures_left: AtomicUsize::new(2),
+            batches: batches.clone(),
+        };
+        let delivery = DeliveryConfig {
+            max_batch: 3,
+            flush_interval_ms: 50,
+            max_retries: 2,
+            retry_backoff_ms: 1,
+        };
+        let task = spawn_sink(Box::new(sink), delivery, bus.subscribe());
+        for pid in 0..5 {
+            bus.publish("sbx", EventPayload::ProcessExited { pid, exit: None });
+        }
+        drop(bus);
+        task.await.unwrap();
+        // The first batch succeeds on its third attempt; the rest are flushed when the bus closes
+        assert_eq!(*batches.lock().unwrap(), vec![3, 2]);
+    }
+}
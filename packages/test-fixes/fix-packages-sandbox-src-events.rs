@@ -0,0 +1,453 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::alarms::Alarm;
+use crate::approval::ApprovalDecision;
+use crate::auto_pause::PauseTrigger;
+use crate::lifecycle::LifecycleState;
+use crate::process::{OomEvent, ProcessExit};
+use crate::sandbox_id::check_path_safe;
+use crate::startup_recovery::RecoveryAction;
+use crate::warnings::Warning;
+
+/// Version of the event envelope and payloads. Bumped only for incompatible changes;
+/// new event types and new optional payload fields keep the version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Events kept per sandbox for late subscribers when nothing is configured
+pub const DEFAULT_REPLAY_CAPACITY: usize = 1000;
+
+/// How much event history is kept for replay, and where it survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventReplayConfig {
+    /// Most recent events kept per sandbox
+    pub capacity: usize,
+    /// Directory holding one `<sandbox>.jsonl` journal per sandbox; history is in memory only when unset
+    pub journal_dir: Option<PathBuf>,
+}
+
+impl Default for EventReplayConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_REPLAY_CAPACITY,
+            journal_dir: None,
+        }
+    }
+}
+
+/// What happened, serialized as `event_type` plus `payload`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event_type", content = "payload", rename_all = "snake_case")]
+pub enum EventPayload {
+    /// The sandbox entered a lifecycle state
+    Lifecycle { state: LifecycleState },
+    /// A tracked process exited
+    ProcessExited { pid: i32, exit: Option<ProcessExit> },
+    /// The kernel OOM-killed processes of the sandbox
+    OomKill(OomEvent),
+    /// A pause or resume finished
+    Audit {
+        operation: String,
+        duration_ms: u64,
+        warnings: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<String>,
+    },
+    /// A pause or resume failed
+    OperationFailed { operation: String, error: String },
+    /// A force-kill pause asked for, got or ran out of time waiting for approval
+    Approval { operation_id: u64, decision: ApprovalDecision, approver: Option<String> },
+    /// An automatic pause is coming; redeeming `keep_alive_token` holds the sandbox
+    GraceNotice { trigger: PauseTrigger, pause_at: DateTime<Utc>, keep_alive_token: String },
+    /// Startup recovery rolled a sandbox left mid-operation forward or back
+    Recovery { from: LifecycleState, action: RecoveryAction, error: Option<String> },
+    Warning(Warning),
+    Alarm(Alarm),
+}
+
+/// Envelope every event is published in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub schema_version: u32,
+    /// Position in the sandbox's event stream, starting at 1; 0 until the bus assigns it
+    #[serde(default)]
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub sandbox_id: String,
+    #[serde(flatten)]
+    pub payload: EventPayload,
+}
+
+impl Event {
+    pub fn new(sandbox_id: &str, payload: EventPayload) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            seq: 0,
+            timestamp: Utc::now(),
+            sandbox_id: sandbox_id.to_string(),
+            payload,
+        }
+    }
+
+    /// The `event_type` the payload is tagged with
+    pub fn event_type(&self) -> &'static str {
+        match self.payload {
+            EventPayload::Lifecycle { .. } => "lifecycle",
+            EventPayload::ProcessExited { .. } => "process_exited",
+            EventPayload::OomKill(_) => "oom_kill",
+            EventPayload::Audit { .. } => "audit",
+            EventPayload::OperationFailed { .. } => "operation_failed",
+            EventPayload::Approval { .. } => "approval",
+            EventPayload::GraceNotice { .. } => "grace_notice",
+            EventPayload::Recovery { .. } => "recovery",
+            EventPayload::Warning(_) => "warning",
+            EventPayload::Alarm(_) => "alarm",
+        }
+    }
+}
+
+/// Recent events of one sandbox
+#[derive(Default)]
+struct ReplayBuffer {
+    next_seq: u64,
+    events: VecDeque<Event>,
+}
+
+impl ReplayBuffer {
+    fn push(&mut self, event: Event, capacity: usize) {
+        self.next_seq = event.seq + 1;
+        if self.events.len() >= capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Buffered events from `from_seq` on, and how many before them were already evicted
+    fn since(&self, from_seq: u64) -> (VecDeque<Event>, u64) {
+        let oldest = self.events.front().map_or(self.next_seq, |event| event.seq);
+        let missed = oldest.saturating_sub(from_seq.max(1));
+        (self.events.iter().filter(|event| event.seq >= from_seq).cloned().collect(), missed)
+    }
+}
+
+/// Broadcasts events from every subsystem; clones share the same subscribers and history.
+///
+/// Each sandbox's events are numbered from 1 and the most recent `capacity` of them are
+/// kept, optionally journaled to disk, so subscribers that connect late can replay
+/// them with `subscribe_from`.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+    buffers: Arc<Mutex<HashMap<String, ReplayBuffer>>>,
+    capacity: usize,
+    journal: Option<mpsc::Sender<Event>>,
+    journal_paused: Arc<AtomicBool>, // set while another agent owns the journal
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_REPLAY_CAPACITY)
+    }
+
+    /// Keep the `capacity` most recent events of each sandbox for replay
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(1024);
+        Self {
+            sender,
+            buffers: Arc::new(Mutex::new(HashMap::new())),
+            capacity: capacity.max(1),
+            journal: None,
+            journal_paused: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Load the history journaled in `dir` and keep journaling there, so replay and
+    /// sequence numbers survive restarts. Appends happen on a background thread.
+    pub fn with_journal(mut self, dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut line_counts = HashMap::new();
+        {
+            let mut buffers = self.buffers.lock().unwrap();
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let Some(sandbox_id) = journal_sandbox_id(&path) else {
+                    continue;
+                };
+                let (events, lines) = read_journal(&path, self.capacity)?;
+                let buffer = buffers.entry(sandbox_id.clone()).or_default();
+                for event in events {
+                    buffer.push(event, self.capacity);
+                }
+                line_counts.insert(sandbox_id, lines);
+            }
+        }
+        let (sender, receiver) = mpsc::channel();
+        let capacity = self.capacity;
+        thread::Builder::new()
+            .name("event-journal".to_string())
+            .spawn(move || run_journal(dir, receiver, line_counts, capacity))?;
+        self.journal = Some(sender);
+        Ok(self)
+    }
+
+    /// Stop or restart journaling; events are still buffered and broadcast
+    pub fn pause_journal(&self, paused: bool) {
+        self.journal_paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Receive the sandbox's events from sequence number `from_seq` on: buffered ones
+    /// first, then live ones, with no gaps or duplicates in between. Pass the last seen
+    /// `seq + 1` to resume after a disconnect, or 0 for everything still buffered.
+    pub fn subscribe_from(&self, sandbox_id: &str, from_seq: u64) -> EventReplay {
+        // Holding the lock keeps publishes out between subscribing and copying the buffer
+        let buffers = self.buffers.lock().unwrap();
+        let live = self.sender.subscribe();
+        let (backlog, missed) = buffers.get(sandbox_id).map(|buffer| buffer.since(from_seq)).unwrap_or_default();
+        EventReplay {
+            bus: self.clone(),
+            sandbox_id: sandbox_id.to_string(),
+            next_seq: from_seq,
+            backlog,
+            live,
+            missed,
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Events not yet received by the slowest live subscriber
+    pub fn pending(&self) -> usize {
+        self.sender.len()
+    }
+
+    /// Sequence number of the last event published for the sandbox, 0 if none
+    pub fn last_seq(&self, sandbox_id: &str) -> u64 {
+        self.buffers.lock().unwrap().get(sandbox_id).map_or(0, |buffer| buffer.next_seq.saturating_sub(1))
+    }
+
+    /// Buffered events of a sandbox, oldest first
+    pub fn recent(&self, sandbox_id: &str) -> Vec<Event> {
+        self.buffers.lock().unwrap().get(sandbox_id).map(|buffer| buffer.events.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    pub fn publish(&self, sandbox_id: &str, payload: EventPayload) {
+        let mut event = Event::new(sandbox_id, payload);
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.entry(sandbox_id.to_string()).or_default();
+        event.seq = buffer.next_seq.max(1);
+        buffer.push(event.clone(), self.capacity);
+        if let Some(journal) = self.journal.as_ref().filter(|_| !self.journal_paused.load(Ordering::Relaxed)) {
+            let _ = journal.send(event.clone());
+        }
+        // Sent under the lock so `subscribe_from` sees each event in exactly one of
+        // the buffer or the live stream. No subscribers is fine.
+        let _ = self.sender.send(event);
+    }
+}
+
+/// A sandbox's event stream from a given sequence number, see `EventBus::subscribe_from`
+pub struct EventReplay {
+    bus: EventBus,
+    sandbox_id: String,
+    next_seq: u64,
+    backlog: VecDeque<Event>,
+    live: broadcast::Receiver<Event>,
+    /// Events that were evicted from the buffer before they could be replayed
+    pub missed: u64,
+}
+
+impl EventReplay {
+    /// The next event in sequence, or None once the bus is gone
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.backlog.pop_front() {
+                self.next_seq = event.seq + 1;
+                return Some(event);
+            }
+            match self.live.recv().await {
+                Ok(event) if event.sandbox_id == self.sandbox_id && event.seq >= self.next_seq => {
+                    self.next_seq = event.seq + 1;
+                    return Some(event);
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => {
+                    // Fell behind the live stream; pick the gap up from the buffer instead
+                    let buffers = self.bus.buffers.lock().unwrap();
+                    self.live = self.live.resubscribe();
+                    if let Some(buffer) = buffers.get(&self.sandbox_id) {
+                        let (backlog, missed) = buffer.since(self.next_seq);
+                        self.backlog = backlog;
+                        self.missed += missed;
+                    }
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+fn journal_sandbox_id(path: &Path) -> Option<String> {
+    if path.extension()? != "jsonl" {
+        return None;
+    }
+    Some(path.file_stem()?.to_str()?.to_string())
+}
+
+/// The last `capacity` events in a journal, and how many lines it has. Lines that do
+/// not parse, such as one torn by a crash mid-append, are skipped.
+fn read_journal(path: &Path, capacity: usize) -> io::Result<(Vec<Event>, usize)> {
+    let mut events = VecDeque::new();
+    let mut lines = 0;
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        lines += 1;
+        match serde_json::from_str::<Event>(&line) {
+            Ok(event) => {
+                if events.len() >= capacity {
+                    events.pop_front();
+                }
+                events.push_back(event);
+            }
+            Err(e) => warn!("Skipping unreadable event in {}: {}", path.display(), e),
+        }
+    }
+    Ok((events.into(), lines))
+}
+
+/// Append journaled events until the bus is dropped. A journal is rewritten down to
+/// its last `capacity` events once it has grown to twice that.
+fn run_journal(dir: PathBuf, receiver: mpsc::Receiver<Event>, mut line_counts: HashMap<String, usize>, capacity: usize) {
+    for event in receiver {
+        if let Err(e) = check_path_safe(&event.sandbox_id) {
+            warn!("Not journaling event {}: {}", event.seq, e);
+            continue;
+        }
+        let path = dir.join(format!("{}.jsonl", event.sandbox_id));
+        if let Err(e) = append_event(&path, &event) {
+            warn!("Failed to journal event {} of {}: {}", event.seq, event.sandbox_id, e);
+            continue;
+        }
+        let lines = line_counts.entry(event.sandbox_id.clone()).or_default();
+        *lines += 1;
+        if *lines >= capacity * 2 {
+            match compact_journal(&path, capacity) {
+                Ok(kept) => *lines = kept,
+                Err(e) => warn!("Failed to compact event journal {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+fn append_event(path: &Path, event: &Event) -> io::Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())
+}
+
+fn compact_journal(path: &Path, capacity: usize) -> io::Result<usize> {
+    let (events, _) = read_journal(path, capacity)?;
+    let mut contents = String::new();
+    for event in &events {
+        contents.push_str(&serde_json::to_string(event)?);
+        contents.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(events.len())
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_shape() {
+        let event = Event::new("sbx", EventPayload::Lifecycle { state: LifecycleState::Paused });
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["sandbox_id"], "sbx");
+        assert_eq!(json["event_type"], event.event_type());
+        assert_eq!(json["payload"]["state"], "paused");
+        assert!(json["timestamp"].is_string());
+
+        let parsed: Event = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed.payload, EventPayload::Lifecycle { state: LifecycleState::Paused }));
+    }
+
+    #[tokio::test]
+    async fn test_bus_delivers_to_subscribers() {
+        let bus = EventBus::new();
+        let mut events = bus.subscribe();
+        bus.clone().publish("sbx", EventPayload::ProcessExited { pid: 7, exit: None });
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event_type(), "process_exited");
+        assert_eq!(event.seq, 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay_is_gapless_and_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let bus = EventBus::with_capacity(3).with_journal(dir.path().to_path_buf()).unwrap();
+        for pid in 1..=5 {
+            bus.publish("sbx", EventPayload::ProcessExited { pid, exit: None });
+        }
+        bus.publish("other", EventPayload::ProcessExited { pid: 99, exit: None });
+
+        // Only the last three are buffered; the two before the requested seq are reported missed
+        let mut replay = bus.subscribe_from("sbx", 1);
+        assert_eq!(replay.missed, 2);
+        bus.publish("sbx", EventPayload::ProcessExited { pid: 6, exit: None });
+        let mut seqs = Vec::new();
+        for _ in 0..4 {
+            seqs.push(replay.recv().await.unwrap().seq);
+        }
+        assert_eq!(seqs, vec![3, 4, 5, 6]);
+
+        drop(replay);
+        drop(bus);
+        // The journal thread appends in the background; wait for it to catch up
+        let path = dir.path().join("sbx.jsonl");
+        for _ in 0..100 {
+            if read_journal(&path, 3).ok().and_then(|(events, _)| events.last().map(|event| event.seq)) == Some(6) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let bus = EventBus::with_capacity(3).with_journal(dir.path().to_path_buf()).unwrap();
+        assert_eq!(bus.last_seq("sbx"), 6);
+        assert_eq!(bus.last_seq("other"), 1);
+        bus.publish("sbx", EventPayload::ProcessExited { pid: 7, exit: None });
+        let mut replay = bus.subscribe_from("sbx", 6);
+        assert_eq!(replay.missed, 0);
+        assert_eq!(replay.recv().await.unwrap().seq, 6);
+        assert_eq!(replay.recv().await.unwrap().seq, 7);
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+use log::info;
+use serde::{Serialize, Deserialize};
+
+/// Risky behaviours that can be switched on per host or per tenant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Checkpoint/restore processes with CRIU instead of relaunching them. Not
+    /// available in this agent; pauses with it on carry a `FeatureUnavailable` warning
+    Criu,
+    /// Let `Freeze` policies stop processes; without it they are handled as if the
+    /// policy had no action
+    Freezer,
+    /// Capture the sandbox's overlay filesystem upper layer. Not available in this
+    /// agent; pauses with it on carry a `FeatureUnavailable` warning
+    OverlayCapture,
+    /// Inject faults at the rates in `ChaosConfig`; for resilience testing only
+    Chaos,
+}
+
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "criu" => Ok(Feature::Criu),
+            "freezer" => Ok(Feature::Freezer),
+            "overlay_capture" => Ok(Feature::OverlayCapture),
+            "chaos" => Ok(Feature::Chaos),
+            other => Err(format!("unknown feature flag: {}", other)),
+        }
+    }
+}
+
+/// Feature flag settings as loaded from config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeatureFlagsConfig {
+    /// Host-wide settings; features not listed are disabled
+    #[serde(default)]
+    pub enabled: HashMap<Feature, bool>,
+    /// Per-tenant overrides of the host-wide settings
+    #[serde(default)]
+    pub tenants: HashMap<String, HashMap<Feature, bool>>,
+}
+
+/// Runtime-toggleable feature flags
+pub struct FeatureFlags {
+    state: RwLock<FeatureFlagsConfig>,
+}
+
+impl FeatureFlags {
+    pub fn new(config: FeatureFlagsConfig) -> Self {
+        Self {
+            state: RwLock::new(config),
+        }
+    }
+
+    /// Whether a feature is on, preferring the tenant override when one exists
+    pub fn is_enabled(&self, feature: Feature, tenant: Option<&str>) -> bool {
+        let state = self.state.read().unwrap();
+        tenant
+            .and_then(|t| state.tenants.get(t))
+            .and_then(|overrides| overrides.get(&feature))
+            .or_else(|| state.enabled.get(&feature))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Toggle a feature host-wide
+    pub fn set(&self, feature: Feature, enabled: bool) {
+        self.state.write().unwrap().enabled.insert(feature, enabled);
+        info!("Feature {:?} {}", feature, if enabled { "enabled" } else { "disabled" });
+    }
+
+    /// Toggle a feature for a single tenant
+    pub fn set_for_tenant(&self, tenant: &str, feature: Feature, enabled: bool) {
+        self.state
+            .write()
+            .unwrap()
+            .tenants
+            .entry(tenant.to_string())
+            .or_default()
+            .insert(feature, enabled);
+        info!("Feature {:?} {} for tenant {}", feature, if enabled { "enabled" } else { "disabled" }, tenant);
+    }
+
+    /// Drop a tenant override so the host-wide setting applies again
+    pub fn clear_tenant_override(&self, tenant: &str, feature: Feature) {
+        if let Some(overrides) = self.state.write().unwrap().tenants.get_mut(tenant) {
+            overrides.remove(&feature);
+        }
+    }
+
+    /// Current settings, e.g. for an admin listing
+    pub fn snapshot(&self) -> FeatureFlagsConfig {
+        self.state.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_override_takes_precedence() {
+        let flags = FeatureFlags::new(FeatureFlagsConfig::default());
+        assert!(!flags.is_enabled(Feature::Freezer, None));
+
+        flags.set(Feature::Freezer, true);
+        flags.set_for_tenant("tenant-a", Feature::Freezer, false);
+
+        assert!(flags.is_enabled(Feature::Freezer, None));
+        assert!(flags.is_enabled(Feature::Freezer, Some("tenant-b")));
+        assert!(!flags.is_enabled(Feature::Freezer, Some("tenant-a")));
+
+        flags.clear_tenant_override("tenant-a", Feature::Freezer);
+        assert!(flags.is_enabled(Feature::Freezer, Some("tenant-a")));
+    }
+}
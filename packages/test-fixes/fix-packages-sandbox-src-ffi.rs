@@ -0,0 +1,223 @@
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use crate::alarms::Alarm;
+use crate::auto_pause::{AutoPauseConfig, AutoPauseManager, PauseOptions};
+use crate::state_fs::StateFsSession;
+use crate::warnings::Warning;
+
+/// Opaque handle to a manager and the runtime it runs on, owned by the host
+pub struct SandboxAgent {
+    _state_fs: Option<StateFsSession>, // unmounted first, while the runtime its reads block on is up
+    manager: Arc<AutoPauseManager>,
+    warnings: Mutex<broadcast::Receiver<Warning>>,
+    alarms: Mutex<broadcast::Receiver<Alarm>>,
+    runtime: Runtime, // dropped last, after everything that may still hold tasks on it
+}
+
+/// Outcome of an FFI call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FfiStatus {
+    Ok = 0,
+    /// `sandbox_poll_event` found nothing queued
+    NoEvent = 1,
+    /// A null pointer, non-UTF-8 string or invalid JSON was passed
+    InvalidArgument = 2,
+    /// The operation failed; the output string holds the error
+    Failed = 3,
+}
+
+/// Events handed out by `sandbox_poll_event`
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FfiEvent {
+    Warning(Warning),
+    Alarm(Alarm),
+}
+
+unsafe fn read_str<'a>(value: *const c_char) -> Option<&'a str> {
+    if value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(value).to_str().ok()
+}
+
+/// Hand a string to the host; it is released with `sandbox_string_free`
+unsafe fn write_out(out: *mut *mut c_char, value: String) {
+    if !out.is_null() {
+        // Interior NULs cannot cross the ABI; they only appear in process output
+        *out = CString::new(value.replace('\0', "")).map_or(ptr::null_mut(), CString::into_raw);
+    }
+}
+
+/// Run `body`, turning a panic into `Failed` instead of unwinding into the host
+fn guarded(out: *mut *mut c_char, body: impl FnOnce() -> Result<String, String>) -> FfiStatus {
+    let (status, text) = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(json)) => (FfiStatus::Ok, json),
+        Ok(Err(e)) => (FfiStatus::Failed, e),
+        Err(_) => (FfiStatus::Failed, "panic in sandbox agent".to_string()),
+    };
+    unsafe { write_out(out, text) };
+    status
+}
+
+/// Create a manager from a JSON `AutoPauseConfig`, or the defaults when `config_json`
+/// is null, claim its base dir and start the watchdog and state filesystem if
+/// configured. Returns null if
+/// the config does not parse or is invalid, the runtime cannot start or another agent
+/// owns the base dir and `instance_conflict` is not `read_only`.
+///
+/// # Safety
+/// `config_json` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_agent_new(config_json: *const c_char) -> *mut SandboxAgent {
+    let config = if config_json.is_null() {
+        AutoPauseConfig::default()
+    } else {
+        match read_str(config_json).and_then(|json| serde_json::from_str(json).ok()) {
+            Some(config) => config,
+            None => return ptr::null_mut(),
+        }
+    };
+    let created = panic::catch_unwind(AssertUnwindSafe(|| {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().ok()?;
+        let manager = {
+            let _entered = runtime.enter();
+            AutoPauseManager::new(config)
+        };
+        runtime.block_on(manager.claim_instance()).ok()?;
+        let manager = Arc::new(manager);
+        manager.install_crash_handler();
+        let state_fs = {
+            let _entered = runtime.enter();
+            manager.spawn_watchdog().ok()?;
+            manager.spawn_integrity_scan();
+            manager.mount_state_fs()
+        };
+        Some(SandboxAgent {
+            _state_fs: state_fs,
+            warnings: Mutex::new(manager.subscribe_warnings()),
+            alarms: Mutex::new(manager.subscribe_alarms()),
+            runtime,
+            manager,
+        })
+    }));
+    match created {
+        Ok(Some(agent)) => Box::into_raw(Box::new(agent)),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Shut the manager down and free it.
+///
+/// # Safety
+/// `agent` must be null or a pointer from `sandbox_agent_new` not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_agent_free(agent: *mut SandboxAgent) {
+    if !agent.is_null() {
+        let agent = Box::from_raw(agent);
+        agent.manager.shutdown();
+    }
+}
+
+/// Pause a sandbox; `force` overrides its protection. On return `*out_json` holds the
+/// `PauseResult` as JSON, or the error message when the status is `Failed`.
+///
+/// # Safety
+/// `agent` must come from `sandbox_agent_new`, `sandbox_id` must be a NUL-terminated
+/// string and `out_json` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_pause(agent: *const SandboxAgent, sandbox_id: *const c_char, force: bool, out_json: *mut *mut c_char) -> FfiStatus {
+    let (Some(agent), Some(sandbox_id)) = (agent.as_ref(), read_str(sandbox_id)) else {
+        return FfiStatus::InvalidArgument;
+    };
+    guarded(out_json, || {
+        let options = PauseOptions { force, ..Default::default() };
+        let result = agent.runtime.block_on(agent.manager.prepare_pause_with(sandbox_id, options)).map_err(|e| e.to_string())?;
+        serde_json::to_string(&result).map_err(|e| e.to_string())
+    })
+}
+
+/// Resume a sandbox. On return `*out_json` holds the `ResumeReport` as JSON, or the
+/// error message when the status is `Failed`.
+///
+/// # Safety
+/// Same as `sandbox_pause`.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_resume(agent: *const SandboxAgent, sandbox_id: *const c_char, out_json: *mut *mut c_char) -> FfiStatus {
+    let (Some(agent), Some(sandbox_id)) = (agent.as_ref(), read_str(sandbox_id)) else {
+        return FfiStatus::InvalidArgument;
+    };
+    guarded(out_json, || {
+        let report = agent.runtime.block_on(agent.manager.after_resume(sandbox_id)).map_err(|e| e.to_string())?;
+        serde_json::to_string(&report).map_err(|e| e.to_string())
+    })
+}
+
+/// Take the next queued warning or alarm without blocking. Returns `NoEvent` when
+/// none is queued; otherwise `*out_json` holds the event tagged by `"type"`.
+/// Events raised before the manager was created, or dropped because the host polled
+/// too slowly, are not delivered.
+///
+/// # Safety
+/// `agent` must come from `sandbox_agent_new` and `out_json` be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_poll_event(agent: *const SandboxAgent, out_json: *mut *mut c_char) -> FfiStatus {
+    let Some(agent) = agent.as_ref() else {
+        return FfiStatus::InvalidArgument;
+    };
+    let event = next_event(&agent.warnings, FfiEvent::Warning).or_else(|| next_event(&agent.alarms, FfiEvent::Alarm));
+    match event {
+        Some(event) => guarded(out_json, || serde_json::to_string(&event).map_err(|e| e.to_string())),
+        None => FfiStatus::NoEvent,
+    }
+}
+
+fn next_event<T: Clone>(receiver: &Mutex<broadcast::Receiver<T>>, wrap: fn(T) -> FfiEvent) -> Option<FfiEvent> {
+    let mut receiver = receiver.lock().unwrap();
+    loop {
+        match receiver.try_recv() {
+            Ok(event) => return Some(wrap(event)),
+            Err(TryRecvError::Lagged(_)) => continue,
+            Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Free a string returned through an `out_json` parameter.
+///
+/// # Safety
+/// `value` must be null or a string from this library not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_lifecycle_over_the_c_abi() {
+        unsafe {
+            let invalid = CString::new("{not json").unwrap();
+            assert!(sandbox_agent_new(invalid.as_ptr()).is_null());
+
+            let agent = sandbox_agent_new(ptr::null());
+            assert!(!agent.is_null());
+            let mut out: *mut c_char = ptr::null_mut();
+            assert_eq!(sandbox_poll_event(agent, &mut out), FfiStatus::NoEvent);
+            assert!(out.is_null());
+            assert_eq!(sandbox_pause(agent, ptr::null(), false, &mut out), FfiStatus::InvalidArgument);
+            sandbox_agent_free(agent);
+        }
+    }
+}
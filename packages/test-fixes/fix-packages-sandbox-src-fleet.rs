@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::PauseResult;
+use crate::operations::OperationInfo;
+use crate::remote::AgentClient;
+use crate::state_export::{SandboxStateView, StateExport};
+
+/// A request to one host that failed or timed out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostError {
+    pub host: String,
+    pub error: String,
+}
+
+/// Merged results of a fan-out: what each host answered, and the hosts that did not
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetResult<T> {
+    pub results: BTreeMap<String, T>,
+    pub errors: Vec<HostError>,
+}
+
+impl<T> FleetResult<T> {
+    /// Every host answered
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Talks to many agents at once, e.g. one `RemoteAgent` per host
+pub struct FleetClient {
+    agents: BTreeMap<String, Box<dyn AgentClient>>,
+    concurrency: usize,
+    timeout: Duration,
+    pause_timeout: Duration,
+}
+
+impl FleetClient {
+    pub fn new() -> Self {
+        Self {
+            agents: BTreeMap::new(),
+            concurrency: 32,
+            timeout: Duration::from_secs(10),
+            pause_timeout: Duration::from_secs(300),
+        }
+    }
+
+    pub fn with_agent(mut self, host: impl Into<String>, agent: Box<dyn AgentClient>) -> Self {
+        self.agents.insert(host.into(), agent);
+        self
+    }
+
+    /// Hosts queried at the same time (default: 32)
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Time each host has to answer before it counts as failed (default: 10s)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Time each pause has to finish; pauses write snapshots, so this is much longer
+    /// than the query timeout (default: 300s)
+    pub fn with_pause_timeout(mut self, timeout: Duration) -> Self {
+        self.pause_timeout = timeout;
+        self
+    }
+
+    pub fn hosts(&self) -> impl Iterator<Item = &str> {
+        self.agents.keys().map(String::as_str)
+    }
+
+    /// Sandboxes of every host
+    pub async fn list(&self) -> FleetResult<StateExport> {
+        self.fan_out(|agent| agent.export_state()).await
+    }
+
+    /// In-flight operations of every host
+    pub async fn operations(&self) -> FleetResult<Vec<OperationInfo>> {
+        self.fan_out(|agent| agent.list_operations()).await
+    }
+
+    /// State of the given sandboxes, by sandbox ID and wherever they run. Sandboxes no
+    /// host reported are absent; hosts that failed to answer are in `errors`.
+    pub async fn status(&self, sandbox_ids: &[String]) -> FleetResult<SandboxStateView> {
+        let listed = self.list().await;
+        let mut results = BTreeMap::new();
+        for state in listed.results.into_values() {
+            for sandbox in state.sandboxes {
+                if sandbox_ids.contains(&sandbox.sandbox_id) {
+                    results.insert(sandbox.sandbox_id.clone(), sandbox);
+                }
+            }
+        }
+        FleetResult { results, errors: listed.errors }
+    }
+
+    /// Pause each sandbox on the host that runs it, keyed by sandbox ID. Sandboxes that
+    /// no reachable host reports are errors with an empty host; sandboxes that several
+    /// hosts report are not paused, and are errors naming those hosts.
+    pub async fn pause(&self, sandbox_ids: &[String]) -> FleetResult<PauseResult> {
+        let listed = self.list().await;
+        let mut placement: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (host, state) in &listed.results {
+            for sandbox in &state.sandboxes {
+                placement.entry(sandbox.sandbox_id.clone()).or_default().push(host.clone());
+            }
+        }
+        let mut errors = listed.errors;
+        let mut targets = Vec::new();
+        for sandbox_id in sandbox_ids {
+            match placement.get(sandbox_id).map(Vec::as_slice) {
+                Some([host]) => targets.push((host.clone(), sandbox_id.clone())),
+                Some(hosts) => errors.push(HostError {
+                    host: hosts.join(","),
+                    error: format!("sandbox {} is reported by {} hosts, not pausing any", sandbox_id, hosts.len()),
+                }),
+                None => errors.push(HostError {
+                    host: String::new(),
+                    error: format!("sandbox {} not found on any reachable host", sandbox_id),
+                }),
+            }
+        }
+
+        let timeout = self.pause_timeout;
+        let mut results = BTreeMap::new();
+        let mut outcomes = stream::iter(targets)
+            .map(|(host, sandbox_id)| async move {
+                let agent = &self.agents[&host];
+                let outcome = match tokio::time::timeout(timeout, agent.pause(&sandbox_id, false)).await {
+                    Ok(Ok(result)) => Ok(result),
+                    Ok(Err(e)) => Err(format!("pausing {} failed: {}", sandbox_id, e)),
+                    Err(_) => Err(format!("pausing {} timed out after {:?}", sandbox_id, timeout)),
+                };
+                (host, sandbox_id, outcome)
+            })
+            .buffer_unordered(self.concurrency);
+        while let Some((host, sandbox_id, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(result) => {
+                    results.insert(sandbox_id, result);
+                }
+                Err(error) => errors.push(HostError { host, error }),
+            }
+        }
+        FleetResult { results, errors }
+    }
+
+    /// Run `request` against every host, at most `concurrency` at a time
+    async fn fan_out<T, F>(&self, request: F) -> FleetResult<T>
+    where
+        F: for<'a> Fn(&'a dyn AgentClient) -> BoxFuture<'a, Result<T, Box<dyn std::error::Error + Send + Sync>>>,
+    {
+        let timeout = self.timeout;
+        let request = &request;
+        let mut outcomes = stream::iter(&self.agents)
+            .map(|(host, agent)| async move {
+                let outcome = match tokio::time::timeout(timeout, request(agent.as_ref())).await {
+                    Ok(Ok(value)) => Ok(value),
+                    Ok(Err(e)) => Err(e.to_string()),
+                    Err(_) => Err(format!("no answer within {:?}", timeout)),
+                };
+                (host.clone(), outcome)
+            })
+            .buffer_unordered(self.concurrency);
+        let mut merged = FleetResult { results: BTreeMap::new(), errors: Vec::new() };
+        while let Some((host, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(value) => {
+                    merged.results.insert(host, value);
+                }
+                Err(error) => merged.errors.push(HostError { host, error }),
+            }
+        }
+        merged.errors.sort_by(|a, b| a.host.cmp(&b.host));
+        merged
+    }
+}
+
+impl Default for FleetClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::auto_pause::ResumeReport;
+    use crate::operations::OperationId;
+
+    struct FakeAgent {
+        sandboxes: Vec<&'static str>,
+        down: bool,
+    }
+
+    type Reply<'a, T> = BoxFuture<'a, Result<T, Box<dyn std::error::Error + Send + Sync>>>;
+
+    impl AgentClient for FakeAgent {
+        fn pause<'a>(&'a self, sandbox_id: &'a str, _force: bool) -> Reply<'a, PauseResult> {
+            Box::pin(async move { Ok(PauseResult { sandbox_id: sandbox_id.to_string(), ..Default::default() }) })
+        }
+
+        fn resume<'a>(&'a self, _sandbox_id: &'a str) -> Reply<'a, ResumeReport> {
+            Box::pin(async { Err("not used".into()) })
+        }
+
+        fn export_state(&self) -> Reply<'_, StateExport> {
+            Box::pin(async move {
+                if self.down {
+                    return Err("connection refused".into());
+                }
+                let sandboxes = self
+                    .sandboxes
+                    .iter()
+                    .map(|id| SandboxStateView {
+                        sandbox_id: id.to_string(),
+                        lifecycle: Default::default(),
+                        priority: Default::default(),
+                        process_count: 1,
+                        running_count: 1,
+                        last_snapshot_at: None,
+                        active_pins: 0,
+                        pause_pending: false,
+                    })
+                    .collect();
+                Ok(StateExport { generated_at: Utc::now(), sandboxes })
+            })
+        }
+
+        fn list_operations(&self) -> Reply<'_, Vec<OperationInfo>> {
+            Box::pin(async { Ok(Vec::new()) })
+        }
+
+        fn cancel_operation(&self, _id: OperationId) -> Reply<'_, bool> {
+            Box::pin(async { Ok(false) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_keeps_per_host_errors() {
+        let fleet = FleetClient::new()
+            .with_agent("host-a", Box::new(FakeAgent { sandboxes: vec!["a1", "a2"], down: false }))
+            .with_agent("host-b", Box::new(FakeAgent { sandboxes: vec!["b1", "a2"], down: false }))
+            .with_agent("host-c", Box::new(FakeAgent { sandboxes: vec!["c1"], down: true }));
+
+        let listed = fleet.list().await;
+        assert_eq!(listed.results.keys().collect::<Vec<_>>(), ["host-a", "host-b"]);
+        assert_eq!(listed.errors, vec![HostError { host: "host-c".to_string(), error: "connection refused".to_string() }]);
+
+        let paused = fleet.pause(&["a1".to_string(), "b1".to_string(), "c1".to_string()]).await;
+        assert_eq!(paused.results.keys().collect::<Vec<_>>(), ["a1", "b1"]);
+        // host-c could not be listed, so c1 is reported both as a host error and as not found
+        assert_eq!(paused.errors.len(), 2);
+        assert!(!paused.is_complete());
+
+        // Reported by two hosts: neither is asked to pause it
+        let ambiguous = fleet.pause(&["a2".to_string()]).await;
+        assert!(ambiguous.results.is_empty());
+        assert_eq!(ambiguous.errors.last().unwrap().host, "host-a,host-b");
+    }
+}
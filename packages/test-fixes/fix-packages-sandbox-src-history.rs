@@ -0,0 +1,409 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::sandbox_id;
+use crate::stats::TimelinePoint;
+
+const TEMPLATES_FILE: &str = "templates.json";
+
+/// How often the writer drops expired points from the ring files
+const PRUNE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+type Index = Mutex<HashMap<String, VecDeque<TimelinePoint>>>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Directory holding one `<sandbox>.jsonl` ring file per sandbox; history is not kept when unset
+    pub dir: Option<PathBuf>,
+    /// Points kept per sandbox (default: 1000)
+    pub max_points: usize,
+    /// Points older than this are pruned (default: 30)
+    pub max_age_days: u32,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            max_points: 1000,
+            max_age_days: 30,
+        }
+    }
+}
+
+/// Operation timings and snapshot sizes of every sandbox, kept on disk across restarts
+pub struct HistoryStore {
+    dir: PathBuf,
+    config: HistoryConfig,
+    writer: mpsc::Sender<(String, TimelinePoint)>,
+    /// The points in the ring files, plus those still queued for the writer, so
+    /// queries do not read the disk
+    index: Arc<Index>,
+    /// Template each sandbox was restored from
+    templates: Arc<Mutex<HashMap<String, String>>>,
+    /// Another agent owns the store; nothing is written
+    read_only: Arc<AtomicBool>,
+}
+
+impl HistoryStore {
+    /// Open the store in `dir`, pruning expired points, and start its writer thread,
+    /// which prunes them again every hour
+    pub fn open(dir: PathBuf, config: HistoryConfig) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let templates = match fs::read_to_string(dir.join(TEMPLATES_FILE)) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable template index in {}: {}", dir.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let templates = Arc::new(Mutex::new(templates));
+        let index: Arc<Index> = Arc::default();
+        let kept = prune(&dir, &config, &templates, &index)?;
+        index.lock().unwrap().extend(kept.into_iter().map(|(sandbox_id, points)| (sandbox_id, points.into())));
+        let (writer, receiver) = mpsc::channel();
+        let read_only = Arc::new(AtomicBool::new(false));
+        let ring = Ring {
+            dir: dir.clone(),
+            config: config.clone(),
+            templates: templates.clone(),
+            index: index.clone(),
+            read_only: read_only.clone(),
+        };
+        thread::Builder::new()
+            .name("history-writer".to_string())
+            .spawn(move || run_writer(ring, receiver))?;
+        Ok(Self {
+            dir,
+            config,
+            writer,
+            index,
+            templates,
+            read_only,
+        })
+    }
+
+    /// Append a point; written in the background
+    pub fn record(&self, sandbox_id: &str, point: TimelinePoint) {
+        if self.is_read_only() {
+            return;
+        }
+        if let Err(e) = sandbox_id::check_path_safe(sandbox_id) {
+            warn!("Not recording history: {}", e);
+            return;
+        }
+        let mut index = self.index.lock().unwrap();
+        let points = index.entry(sandbox_id.to_string()).or_default();
+        if points.len() >= self.config.max_points {
+            points.pop_front();
+        }
+        points.push_back(point.clone());
+        drop(index);
+        let _ = self.writer.send((sandbox_id.to_string(), point));
+    }
+
+    /// Stop writing, for an agent that does not own the store
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Every unexpired point of a sandbox, oldest first
+    pub fn get(&self, sandbox_id: &str) -> io::Result<Vec<TimelinePoint>> {
+        sandbox_id::check_path_safe(sandbox_id).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        Ok(self.indexed(sandbox_id))
+    }
+
+    fn indexed(&self, sandbox_id: &str) -> Vec<TimelinePoint> {
+        let cutoff = cutoff(&self.config);
+        let index = self.index.lock().unwrap();
+        index.get(sandbox_id).map(|points| points.iter().filter(|point| point.at >= cutoff).cloned().collect()).unwrap_or_default()
+    }
+
+    /// Remember that a sandbox was restored from `template`, so its history counts towards the template's
+    pub fn set_template(&self, sandbox_id: &str, template: &str) -> io::Result<()> {
+        if self.is_read_only() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("history store {} is read-only", self.dir.display())));
+        }
+        sandbox_id::check_path_safe(sandbox_id).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        let mut templates = self.templates.lock().unwrap();
+        templates.insert(sandbox_id.to_string(), template.to_string());
+        save_templates(&self.dir, &templates)
+    }
+
+    pub fn template_of(&self, sandbox_id: &str) -> Option<String> {
+        self.templates.lock().unwrap().get(sandbox_id).cloned()
+    }
+
+    /// Every template a sandbox on record was restored from
+    pub fn templates(&self) -> Vec<String> {
+        let mut templates: Vec<String> = self.templates.lock().unwrap().values().cloned().collect();
+        templates.sort();
+        templates.dedup();
+        templates
+    }
+
+    /// History of every sandbox restored from `template`, by sandbox
+    pub fn template_history(&self, template: &str) -> Vec<(String, Vec<TimelinePoint>)> {
+        let sandboxes: Vec<String> = self.templates.lock().unwrap().iter().filter(|(_, t)| *t == template).map(|(s, _)| s.clone()).collect();
+        sandboxes.into_iter().map(|sandbox_id| {
+            let points = self.indexed(&sandbox_id);
+            (sandbox_id, points)
+        }).collect()
+    }
+
+    /// History of every sandbox on record, by sandbox
+    pub fn all(&self) -> Vec<(String, Vec<TimelinePoint>)> {
+        let cutoff = cutoff(&self.config);
+        let index = self.index.lock().unwrap();
+        index
+            .iter()
+            .map(|(sandbox_id, points)| (sandbox_id.clone(), points.iter().filter(|point| point.at >= cutoff).cloned().collect::<Vec<_>>()))
+            .filter(|(_, points)| !points.is_empty())
+            .collect()
+    }
+}
+
+/// What the writer thread shares with the store
+struct Ring {
+    dir: PathBuf,
+    config: HistoryConfig,
+    templates: Arc<Mutex<HashMap<String, String>>>,
+    index: Arc<Index>,
+    read_only: Arc<AtomicBool>,
+}
+
+impl Ring {
+    fn path(&self, sandbox_id: &str) -> io::Result<PathBuf> {
+        sandbox_id::check_path_safe(sandbox_id).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        Ok(self.dir.join(format!("{}.jsonl", sandbox_id)))
+    }
+}
+
+fn cutoff(config: &HistoryConfig) -> chrono::DateTime<Utc> {
+    Utc::now() - Duration::days(config.max_age_days as i64)
+}
+
+fn save_templates(dir: &Path, templates: &HashMap<String, String>) -> io::Result<()> {
+    let tmp = dir.join(format!("{}.tmp", TEMPLATES_FILE));
+    fs::write(&tmp, serde_json::to_string(templates)?)?;
+    fs::rename(&tmp, dir.join(TEMPLATES_FILE))
+}
+
+/// Drop expired points from the ring files and the index, and the ring files, index
+/// entries and template entries left with none. Returns the points kept per ring file.
+/// Runs before the writer starts, then on the writer thread so no append races it.
+fn prune(dir: &Path, config: &HistoryConfig, templates: &Mutex<HashMap<String, String>>, index: &Index) -> io::Result<HashMap<String, Vec<TimelinePoint>>> {
+    let mut removed = 0;
+    let mut kept = HashMap::new();
+    let mut templates = templates.lock().unwrap();
+    let indexed = templates.len();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(sandbox_id) = ring_sandbox_id(&path) else {
+            continue;
+        };
+        let (points, lines) = read_ring(&path, config)?;
+        if points.is_empty() {
+            fs::remove_file(&path)?;
+            templates.remove(&sandbox_id);
+        } else {
+            if points.len() < lines {
+                rewrite_ring(&path, &points)?;
+            }
+            kept.insert(sandbox_id, points.clone());
+        }
+        removed += lines - points.len();
+    }
+    if templates.len() < indexed {
+        save_templates(dir, &templates)?;
+    }
+    let cutoff = cutoff(config);
+    index.lock().unwrap().retain(|_, points| {
+        points.retain(|point| point.at >= cutoff);
+        !points.is_empty()
+    });
+    if removed > 0 {
+        info!("Pruned {} history points", removed);
+    }
+    Ok(kept)
+}
+
+fn ring_sandbox_id(path: &Path) -> Option<String> {
+    if path.extension()? != "jsonl" {
+        return None;
+    }
+    Some(path.file_stem()?.to_str()?.to_string())
+}
+
+/// The last `max_points` unexpired points of a ring file, and how many lines it has.
+/// Lines that do not parse, such as one torn by a crash mid-append, are skipped.
+fn read_ring(path: &Path, config: &HistoryConfig) -> io::Result<(Vec<TimelinePoint>, usize)> {
+    let cutoff = cutoff(config);
+    let mut points = VecDeque::new();
+    let mut lines = 0;
+    for line in BufReader::new(fs::File::open(path)?).lines() {
+        let line = line?;
+        lines += 1;
+        match serde_json::from_str::<TimelinePoint>(&line) {
+            Ok(point) if point.at >= cutoff => {
+                if points.len() >= config.max_points {
+                    points.pop_front();
+                }
+                points.push_back(point);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Skipping unreadable history point in {}: {}", path.display(), e),
+        }
+    }
+    Ok((points.into(), lines))
+}
+
+fn rewrite_ring(path: &Path, points: &[TimelinePoint]) -> io::Result<()> {
+    let mut contents = String::new();
+    for point in points {
+        contents.push_str(&serde_json::to_string(point)?);
+        contents.push('\n');
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// Append points until the store is dropped. A ring file is rewritten down to its last
+/// `max_points` unexpired points once it has grown to twice that, and every ring file
+/// is pruned each `PRUNE_INTERVAL` while the store is writable.
+fn run_writer(ring: Ring, receiver: mpsc::Receiver<(String, TimelinePoint)>) {
+    let config = &ring.config;
+    let mut line_counts: HashMap<String, usize> = HashMap::new();
+    let mut next_prune = Instant::now() + PRUNE_INTERVAL;
+    loop {
+        let received = receiver.recv_timeout(next_prune.saturating_duration_since(Instant::now()));
+        if Instant::now() >= next_prune {
+            if !ring.read_only.load(Ordering::Relaxed) {
+                if let Err(e) = prune(&ring.dir, config, &ring.templates, &ring.index) {
+                    warn!("Failed to prune history in {}: {}", ring.dir.display(), e);
+                }
+            }
+            line_counts.clear();
+            next_prune = Instant::now() + PRUNE_INTERVAL;
+        }
+        let (sandbox_id, point) = match received {
+            Ok(received) => received,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let path = match ring.path(&sandbox_id) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Not recording history of {:?}: {}", sandbox_id, e);
+                continue;
+            }
+        };
+        let lines = match line_counts.get(&sandbox_id) {
+            Some(lines) => *lines,
+            None => read_ring(&path, config).map(|(_, lines)| lines).unwrap_or(0),
+        };
+        let appended = serde_json::to_string(&point).map_err(io::Error::from).and_then(|mut line| {
+            line.push('\n');
+            OpenOptions::new().create(true).append(true).open(&path)?.write_all(line.as_bytes())
+        });
+        if let Err(e) = appended {
+            warn!("Failed to record history of {}: {}", sandbox_id, e);
+            continue;
+        }
+        let mut lines = lines + 1;
+        if lines >= config.max_points * 2 {
+            match read_ring(&path, config).and_then(|(points, _)| rewrite_ring(&path, &points).map(|()| points.len())) {
+                Ok(kept) => lines = kept,
+                Err(e) => warn!("Failed to compact history of {}: {}", sandbox_id, e),
+            }
+        }
+        line_counts.insert(sandbox_id, lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::PauseStrategy;
+    use crate::stats::TimelineEvent;
+
+    #[test]
+    fn test_record_query_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = HistoryConfig { max_points: 2, ..Default::default() };
+        let store = HistoryStore::open(dir.path().to_path_buf(), config.clone()).unwrap();
+        let point = |at, duration_ms| TimelinePoint { at, event: TimelineEvent::Pause { duration_ms, strategy: PauseStrategy::Persist } };
+
+        store.record("a", point(Utc::now() - Duration::days(40), 1));
+        for ms in [10, 20, 30] {
+            store.record("a", point(Utc::now(), ms));
+        }
+        store.set_template("a", "python").unwrap();
+        // The writer appends in the background; wait for it to catch up
+        let durations = |points: Vec<TimelinePoint>| -> Vec<u64> {
+            points
+                .into_iter()
+                .filter_map(|p| match p.event {
+                    TimelineEvent::Pause { duration_ms, .. } => Some(duration_ms),
+                    _ => None,
+                })
+                .collect()
+        };
+        for _ in 0..100 {
+            if durations(store.get("a").unwrap()) == vec![20, 30] {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(durations(store.get("a").unwrap()), vec![20, 30]);
+        assert_eq!(store.template_history("python").len(), 1);
+
+        // A sandbox with only expired points is never returned, and is dropped on reopen
+        store.record("old", point(Utc::now() - Duration::days(40), 5));
+        store.set_template("old", "python").unwrap();
+        assert!(store.get("old").unwrap().is_empty());
+        assert!(store.all().iter().all(|(sandbox_id, _)| sandbox_id != "old"));
+        for _ in 0..100 {
+            if dir.path().join("old.jsonl").exists() {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(dir.path().join("old.jsonl").exists());
+        drop(store);
+
+        // Reopening keeps the template index and prunes what expired in between
+        let store = HistoryStore::open(dir.path().to_path_buf(), config).unwrap();
+        assert_eq!(store.template_of("a").as_deref(), Some("python"));
+        assert_eq!(durations(store.get("a").unwrap()), vec![20, 30]);
+        assert_eq!(store.template_of("old"), None);
+        assert!(!dir.path().join("old.jsonl").exists());
+    }
+
+    #[test]
+    fn test_unsafe_ids_are_not_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = HistoryStore::open(dir.path().join("history"), HistoryConfig::default()).unwrap();
+        assert_eq!(store.get("../escape").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(store.set_template("../escape", "python").is_err());
+        store.record("../escape", TimelinePoint { at: Utc::now(), event: crate::stats::TimelineEvent::Resume { duration_ms: 1 } });
+        assert!(store.all().is_empty());
+        assert!(!dir.path().join("escape.jsonl").exists());
+    }
+}
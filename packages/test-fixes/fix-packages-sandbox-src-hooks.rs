@@ -0,0 +1,292 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use futures::future::BoxFuture;
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+use tokio::process::Command;
+
+use crate::process;
+
+/// Point in the pause/resume lifecycle at which hooks run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookPhase {
+    /// Before any process is touched; an aborting failure cancels the pause
+    PrePause,
+    /// After the sandbox is paused
+    PostPause,
+    /// Before processes are restored; an aborting failure cancels the resume
+    PreResume,
+    /// After the resume finished
+    PostResume,
+}
+
+/// What a failing hook does to the operation it runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookErrorPolicy {
+    /// Log only
+    Ignore,
+    /// Raise a warning on the operation result
+    #[default]
+    Warn,
+    /// Fail the operation; only effective in the pre-phases
+    Abort,
+}
+
+/// What a hook is told about the operation
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub sandbox_id: String,
+    pub phase: HookPhase,
+}
+
+/// An embedder-supplied lifecycle hook
+pub trait LifecycleHook: Send + Sync {
+    fn run<'a>(&'a self, context: &'a HookContext) -> BoxFuture<'a, Result<(), String>>;
+}
+
+/// Wraps an async closure as a hook
+struct FnHook<F>(F);
+
+impl<F> LifecycleHook for FnHook<F>
+where
+    F: Fn(HookContext) -> BoxFuture<'static, Result<(), String>> + Send + Sync,
+{
+    fn run<'a>(&'a self, context: &'a HookContext) -> BoxFuture<'a, Result<(), String>> {
+        (self.0)(context.clone())
+    }
+}
+
+/// Build a hook from a closure returning a boxed future
+pub fn hook_fn<F>(f: F) -> Arc<dyn LifecycleHook>
+where
+    F: Fn(HookContext) -> BoxFuture<'static, Result<(), String>> + Send + Sync + 'static,
+{
+    Arc::new(FnHook(f))
+}
+
+/// A hook that runs a command with `SANDBOX_ID` and `HOOK_PHASE` in its environment
+pub struct ScriptHook {
+    command: Vec<String>,
+}
+
+impl ScriptHook {
+    pub fn new(command: Vec<String>) -> Self {
+        Self { command }
+    }
+}
+
+impl LifecycleHook for ScriptHook {
+    fn run<'a>(&'a self, context: &'a HookContext) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let (program, args) = self.command.split_first().ok_or("empty hook command")?;
+            let phase = serde_json::to_value(context.phase).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default();
+            let output = process::helper_output(
+                Command::new(program).args(args).env("SANDBOX_ID", &context.sandbox_id).env("HOOK_PHASE", phase).kill_on_drop(true),
+            )
+            .await
+            .map_err(|e| format!("{}: {}", program, e))?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(format!("{} exited with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()))
+            }
+        })
+    }
+}
+
+/// A script hook declared in the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptHookConfig {
+    pub phase: HookPhase,
+    pub command: Vec<String>,
+    /// Lower runs first (default: 0)
+    #[serde(default)]
+    pub order: i32,
+    /// Time allowed before the hook counts as failed (default: 30)
+    #[serde(default = "default_hook_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub on_error: HookErrorPolicy,
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    30
+}
+
+/// A hook and the rules it runs under
+#[derive(Clone)]
+pub struct RegisteredHook {
+    pub name: String,
+    pub phase: HookPhase,
+    pub order: i32,
+    pub timeout: Duration,
+    pub on_error: HookErrorPolicy,
+    hook: Arc<dyn LifecycleHook>,
+}
+
+impl RegisteredHook {
+    pub fn new(name: impl Into<String>, phase: HookPhase, hook: Arc<dyn LifecycleHook>) -> Self {
+        Self {
+            name: name.into(),
+            phase,
+            order: 0,
+            timeout: Duration::from_secs(default_hook_timeout_secs()),
+            on_error: HookErrorPolicy::default(),
+            hook,
+        }
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_error_policy(mut self, on_error: HookErrorPolicy) -> Self {
+        self.on_error = on_error;
+        self
+    }
+}
+
+/// A hook that failed under the `Warn` policy
+#[derive(Debug, Clone)]
+pub struct HookFailure {
+    pub name: String,
+    pub error: String,
+}
+
+/// Hooks per lifecycle phase, run in `order` then registration order
+#[derive(Default)]
+pub struct HookRegistry {
+    hooks: RwLock<Vec<RegisteredHook>>,
+}
+
+impl HookRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry holding the script hooks declared in the config
+    pub fn from_config(scripts: &[ScriptHookConfig]) -> Self {
+        let registry = Self::new();
+        for script in scripts {
+            let hook = RegisteredHook::new(script.command.join(" "), script.phase, Arc::new(ScriptHook::new(script.command.clone())))
+                .with_order(script.order)
+                .with_timeout(Duration::from_secs(script.timeout_secs))
+                .with_error_policy(script.on_error);
+            registry.register(hook);
+        }
+        registry
+    }
+
+    pub fn register(&self, hook: RegisteredHook) {
+        let mut hooks = self.hooks.write().unwrap();
+        hooks.push(hook);
+        // Stable, so equal orders keep registration order
+        hooks.sort_by_key(|h| h.order);
+    }
+
+    /// Remove every hook with the given name; returns how many were removed
+    pub fn unregister(&self, name: &str) -> usize {
+        let mut hooks = self.hooks.write().unwrap();
+        let before = hooks.len();
+        hooks.retain(|h| h.name != name);
+        before - hooks.len()
+    }
+
+    /// Run the hooks of a phase one after another.
+    /// Returns the failures to warn about, or the error of the first aborting failure.
+    pub async fn run(&self, phase: HookPhase, sandbox_id: &str) -> Result<Vec<HookFailure>, String> {
+        let hooks: Vec<RegisteredHook> = self.hooks.read().unwrap().iter().filter(|h| h.phase == phase).cloned().collect();
+        let context = HookContext {
+            sandbox_id: sandbox_id.to_string(),
+            phase,
+        };
+
+        let mut failures = Vec::new();
+        for hook in hooks {
+            debug!("Running {:?} hook {} for sandbox {}", phase, hook.name, sandbox_id);
+            let error = match tokio::time::timeout(hook.timeout, hook.hook.run(&context)).await {
+                Ok(Ok(())) => continue,
+                Ok(Err(e)) => e,
+                Err(_) => format!("timed out after {}s", hook.timeout.as_secs()),
+            };
+            match hook.on_error {
+                HookErrorPolicy::Ignore => debug!("Ignoring failed {:?} hook {}: {}", phase, hook.name, error),
+                HookErrorPolicy::Abort if matches!(phase, HookPhase::PrePause | HookPhase::PreResume) => {
+                    return Err(format!("{:?} hook {} failed: {}", phase, hook.name, error));
+                }
+                _ => {
+                    warn!("{:?} hook {} failed for sandbox {}: {}", phase, hook.name, sandbox_id, error);
+                    failures.push(HookFailure { name: hook.name, error });
+                }
+            }
+        }
+        Ok(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn recording(log: &Arc<Mutex<Vec<String>>>, label: &str, result: Result<(), String>) -> Arc<dyn LifecycleHook> {
+        let log = log.clone();
+        let label = label.to_string();
+        hook_fn(move |_| {
+            log.lock().unwrap().push(label.clone());
+            let result = result.clone();
+            Box::pin(async move { result })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_hooks_run_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let registry = HookRegistry::new();
+        registry.register(RegisteredHook::new("late", HookPhase::PrePause, recording(&log, "late", Ok(()))).with_order(10));
+        registry.register(RegisteredHook::new("early", HookPhase::PrePause, recording(&log, "early", Ok(()))).with_order(-1));
+        registry.register(RegisteredHook::new("other", HookPhase::PostResume, recording(&log, "other", Ok(()))));
+
+        assert!(registry.run(HookPhase::PrePause, "sbx").await.unwrap().is_empty());
+        assert_eq!(*log.lock().unwrap(), vec!["early", "late"]);
+    }
+
+    #[tokio::test]
+    async fn test_hook_error_policies() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let registry = HookRegistry::new();
+        registry.register(RegisteredHook::new("warns", HookPhase::PrePause, recording(&log, "warns", Err("boom".to_string()))));
+        registry.register(
+            RegisteredHook::new("aborts", HookPhase::PrePause, recording(&log, "aborts", Err("stop".to_string())))
+                .with_order(1)
+                .with_error_policy(HookErrorPolicy::Abort),
+        );
+        registry.register(RegisteredHook::new("skipped", HookPhase::PrePause, recording(&log, "skipped", Ok(()))).with_order(2));
+
+        let error = registry.run(HookPhase::PrePause, "sbx").await.unwrap_err();
+        assert!(error.contains("aborts"));
+        assert_eq!(*log.lock().unwrap(), vec!["warns", "aborts"]);
+    }
+
+    #[tokio::test]
+    async fn test_hook_timeout_counts_as_failure() {
+        let registry = HookRegistry::new();
+        let slow = hook_fn(|_| Box::pin(async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        }));
+        registry.register(RegisteredHook::new("slow", HookPhase::PostPause, slow).with_timeout(Duration::from_millis(10)));
+
+        let failures = registry.run(HookPhase::PostPause, "sbx").await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].error.contains("timed out"));
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+
+/// Host-wide load indicators used to throttle pause/resume work
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostLoad {
+    /// 1-minute load average divided by the number of CPUs
+    pub load_per_cpu: f64,
+    /// PSI "some" avg10 for CPU, in percent, when the kernel exposes it
+    pub cpu_pressure: Option<f64>,
+    /// PSI "some" avg10 for I/O, in percent, when the kernel exposes it
+    pub io_pressure: Option<f64>,
+}
+
+/// Sample the current host load; returns None where /proc is unavailable
+pub fn sample() -> Option<HostLoad> {
+    let loadavg = fs::read_to_string("/proc/loadavg").ok()?;
+    let load1: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as f64;
+
+    Some(HostLoad {
+        load_per_cpu: load1 / cpus,
+        cpu_pressure: read_pressure("/proc/pressure/cpu"),
+        io_pressure: read_pressure("/proc/pressure/io"),
+    })
+}
+
+fn read_pressure(path: &str) -> Option<f64> {
+    parse_pressure(&fs::read_to_string(path).ok()?)
+}
+
+/// Extract avg10 from the `some` line of a PSI file
+fn parse_pressure(contents: &str) -> Option<f64> {
+    contents
+        .lines()
+        .find(|line| line.starts_with("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pressure_some_avg10() {
+        let psi = "some avg10=12.50 avg60=3.00 avg300=1.00 total=123456\nfull avg10=4.00 avg60=1.00 avg300=0.50 total=6543";
+        assert_eq!(parse_pressure(psi), Some(12.5));
+        assert_eq!(parse_pressure(""), None);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::AutoPauseManager;
+use crate::blocking;
+
+/// File under the snapshot base naming the agent that owns it
+pub const LOCK_FILE: &str = ".agent.lock";
+
+/// What an agent does when another live instance owns its base dir
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceConflict {
+    /// Fail to start
+    #[default]
+    Refuse,
+    /// Start, but refuse pauses, resumes and every other write to the store
+    ReadOnly,
+}
+
+/// The agent holding a base dir, as it recorded itself in the lock file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub acquired_at: DateTime<Utc>,
+}
+
+impl LockOwner {
+    pub fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            acquired_at: Utc::now(),
+        }
+    }
+}
+
+/// Exclusive ownership of a base dir: an advisory lock on the open lock file, which
+/// the kernel drops when the agent exits however it exits. The file itself stays
+/// behind and only says who held it last.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+    owner: LockOwner,
+}
+
+impl InstanceLock {
+    /// Take the lock in `base_dir`; the holder is returned as the error while
+    /// another agent has it
+    pub fn acquire(base_dir: &Path) -> io::Result<Result<Self, LockOwner>> {
+        fs::create_dir_all(base_dir)?;
+        let path = base_dir.join(LOCK_FILE);
+        let mut file = open_lock_file(&path)?;
+        if !try_lock(&file)? {
+            return read_holder(&path).map(Err);
+        }
+        let owner = LockOwner::current();
+        file.set_len(0)?;
+        file.write_all(&serde_json::to_vec(&owner)?)?;
+        file.sync_all()?;
+        Ok(Ok(Self { _file: file, owner }))
+    }
+
+    pub fn owner(&self) -> &LockOwner {
+        &self.owner
+    }
+}
+
+#[cfg(unix)]
+fn open_lock_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    // flock, not fcntl: a POSIX lock would be dropped when any other descriptor of
+    // the file in this process is closed, e.g. by `read_holder`
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    match io::Error::last_os_error() {
+        e if e.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(false),
+        e => Err(e),
+    }
+}
+
+/// Without flock the file is opened for writing with only reads shared, which
+/// fails for a second writer while the holder has it open
+#[cfg(windows)]
+fn open_lock_file(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_SHARE_READ: u32 = 0x1;
+    OpenOptions::new().read(true).write(true).create(true).truncate(false).share_mode(FILE_SHARE_READ).open(path)
+}
+
+#[cfg(windows)]
+fn try_lock(_file: &File) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// The holder writes itself right after locking; an empty or half-written file is
+/// read again briefly before giving up
+fn read_holder(path: &Path) -> io::Result<LockOwner> {
+    for _ in 0..10 {
+        match fs::read(path) {
+            Ok(json) => {
+                if let Ok(holder) = serde_json::from_slice(&json) {
+                    return Ok(holder);
+                }
+            }
+            // Open for writing by the holder
+            #[cfg(windows)]
+            Err(e) if e.raw_os_error() == Some(32) => {}
+            Err(e) => return Err(e),
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    warn!("Agent lock {} is held but does not name its holder", path.display());
+    Err(io::Error::new(io::ErrorKind::WouldBlock, format!("{} is held by an agent that did not record itself", path.display())))
+}
+
+/// How this agent runs against its base dir
+#[derive(Debug)]
+pub enum InstanceMode {
+    Exclusive(InstanceLock),
+    /// Another instance owns the base dir; nothing is changed
+    ReadOnly { holder: LockOwner },
+}
+
+impl AutoPauseManager {
+    /// Take ownership of the snapshot base dir; call once at startup, before any other
+    /// work. When another live agent holds it, fails or, with `instance_conflict:
+    /// read_only`, leaves this agent read-only and returns the holder.
+    pub async fn claim_instance(&self) -> Result<Option<LockOwner>, Box<dyn std::error::Error>> {
+        let base_dir = self.persistence_manager().get_base_dir().to_path_buf();
+        let acquired = blocking::run("instance_lock", move || InstanceLock::acquire(&base_dir).map_err(|e| e.to_string())).await?;
+        let mode = match acquired {
+            Ok(lock) => {
+                info!("Agent pid {} owns {}", lock.owner().pid, self.persistence_manager().get_base_dir().display());
+                InstanceMode::Exclusive(lock)
+            }
+            Err(holder) if self.config().instance_conflict == InstanceConflict::ReadOnly => {
+                warn!("Agent pid {} owns {}; running read-only", holder.pid, self.persistence_manager().get_base_dir().display());
+                InstanceMode::ReadOnly { holder }
+            }
+            Err(holder) => {
+                return Err(format!("Agent pid {} already owns {} since {}", holder.pid, self.persistence_manager().get_base_dir().display(), holder.acquired_at).into());
+            }
+        };
+        let holder = match &mode {
+            InstanceMode::ReadOnly { holder } => Some(holder.clone()),
+            InstanceMode::Exclusive(_) => None,
+        };
+        self.set_instance_mode(mode);
+        Ok(holder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive_until_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = InstanceLock::acquire(dir.path()).unwrap().unwrap();
+        let holder = InstanceLock::acquire(dir.path()).unwrap().unwrap_err();
+        assert_eq!(&holder, lock.owner());
+        drop(lock);
+
+        // The file left behind names the last holder but does not block the next one
+        assert!(dir.path().join(LOCK_FILE).exists());
+        let lock = InstanceLock::acquire(dir.path()).unwrap().unwrap();
+        let recorded: LockOwner = serde_json::from_slice(&fs::read(dir.path().join(LOCK_FILE)).unwrap()).unwrap();
+        assert_eq!(&recorded, lock.owner());
+    }
+}
@@ -0,0 +1,202 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+use tokio::task::JoinHandle;
+
+use crate::auto_pause::AutoPauseManager;
+use crate::lifecycle::LifecycleRecord;
+use crate::persistence::PersistenceManager;
+use crate::state_snapshot::StateSnapshot;
+use crate::warnings::{Warning, WarningKind};
+
+/// What is wrong with a stored file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityProblem {
+    /// The file could not be read at all (permissions, I/O error, not UTF-8)
+    Unreadable,
+    /// The file is not valid JSON, e.g. truncated by a crash mid-write
+    Corrupted,
+    /// Valid JSON that does not match the format this agent reads
+    IncompatibleSchema,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntegrityIssue {
+    pub sandbox_id: String,
+    pub file: PathBuf,
+    pub problem: IntegrityProblem,
+    pub detail: String,
+}
+
+/// Outcome of scanning the snapshot store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub scanned_at: Option<DateTime<Utc>>,
+    /// Sandbox directories looked at
+    pub sandboxes: usize,
+    pub issues: Vec<IntegrityIssue>,
+    /// Sandboxes moved to quarantine because of an issue
+    pub quarantined: Vec<String>,
+}
+
+impl IntegrityReport {
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Check one JSON file parses as `T`; a missing file is fine
+async fn check_file<T: serde::de::DeserializeOwned>(sandbox_id: &str, path: &Path) -> Option<IntegrityIssue> {
+    let issue = |problem, detail: String| IntegrityIssue {
+        sandbox_id: sandbox_id.to_string(),
+        file: path.to_path_buf(),
+        problem,
+        detail,
+    };
+    let json = match async_fs::read_to_string(path).await {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => return Some(issue(IntegrityProblem::Unreadable, e.to_string())),
+    };
+    if let Err(e) = serde_json::from_str::<serde_json::Value>(&json) {
+        return Some(issue(IntegrityProblem::Corrupted, e.to_string()));
+    }
+    serde_json::from_str::<T>(&json)
+        .err()
+        .map(|e| issue(IntegrityProblem::IncompatibleSchema, e.to_string()))
+}
+
+impl PersistenceManager {
+    /// Check every sandbox's snapshot and lifecycle files. With `quarantine`, sandboxes
+    /// with a bad file are moved out of the live store unless their snapshot is pinned.
+    pub async fn scan_integrity(&self, quarantine: bool) -> Result<IntegrityReport, Box<dyn std::error::Error>> {
+        let mut report = IntegrityReport {
+            scanned_at: Some(Utc::now()),
+            ..IntegrityReport::default()
+        };
+
+        for sandbox_id in self.list_sandbox_dirs().await? {
+            report.sandboxes += 1;
+            let layout = self.layout(&sandbox_id);
+            let issues: Vec<IntegrityIssue> = [
+                check_file::<StateSnapshot>(&sandbox_id, &layout.snapshot_file()).await,
+                check_file::<LifecycleRecord>(&sandbox_id, &layout.lifecycle_file()).await,
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if issues.is_empty() {
+                continue;
+            }
+
+            if quarantine && !self.is_read_only() && !self.is_pinned(&sandbox_id) {
+                match self.quarantine_sandbox(&sandbox_id).await {
+                    Ok(_) => report.quarantined.push(sandbox_id.clone()),
+                    Err(e) => warn!("Failed to quarantine damaged sandbox {}: {}", sandbox_id, e),
+                }
+            }
+            report.issues.extend(issues);
+        }
+        Ok(report)
+    }
+}
+
+impl AutoPauseManager {
+    /// Scan the snapshot store, quarantining damaged sandboxes and raising a
+    /// `SnapshotCorrupted` warning per bad file. The report is kept for
+    /// `integrity_report`.
+    pub async fn integrity_scan(&self) -> Result<IntegrityReport, Box<dyn std::error::Error>> {
+        let report = self.persistence_manager().scan_integrity(true).await?;
+        for issue in &report.issues {
+            self.publish_warning(Warning::new(
+                WarningKind::SnapshotCorrupted,
+                &issue.sandbox_id,
+                format!("{:?} {}: {}", issue.problem, issue.file.display(), issue.detail),
+            ));
+        }
+        info!(
+            "Integrity scan checked {} sandboxes: {} issues, {} quarantined",
+            report.sandboxes,
+            report.issues.len(),
+            report.quarantined.len()
+        );
+        self.set_integrity_report(report.clone());
+        Ok(report)
+    }
+
+    /// Scan at startup, then every `integrity_scan_interval_secs` if set
+    pub fn spawn_integrity_scan(self: &Arc<Self>) -> JoinHandle<()> {
+        let manager = self.clone();
+        let interval = self.config().integrity_scan_interval_secs.map(|secs| Duration::from_secs(secs.max(1)));
+        let task = tokio::spawn(async move {
+            let mut ticker = interval.map(tokio::time::interval);
+            loop {
+                if let Some(ticker) = &mut ticker {
+                    ticker.tick().await;
+                }
+                if let Err(e) = manager.integrity_scan().await {
+                    warn!("Integrity scan of the snapshot store failed: {}", e);
+                }
+                if ticker.is_none() {
+                    break;
+                }
+            }
+        });
+        self.track_task("integrity_scan", &task);
+        task
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_scan_finds_and_quarantines_damaged_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        for id in ["good", "truncated", "foreign"] {
+            manager.save_snapshot(&StateSnapshot::new(id.to_string())).await.unwrap();
+        }
+        std::fs::write(manager.layout("truncated").snapshot_file(), r#"{"sandbox_id": "trunc"#).unwrap();
+        std::fs::write(manager.layout("foreign").snapshot_file(), r#"{"format": 2}"#).unwrap();
+
+        let report = manager.scan_integrity(true).await.unwrap();
+        assert_eq!(report.sandboxes, 3);
+        let mut problems: Vec<_> = report.issues.iter().map(|i| (i.sandbox_id.as_str(), i.problem)).collect();
+        problems.sort_by_key(|(id, _)| *id);
+        assert_eq!(problems, vec![("foreign", IntegrityProblem::IncompatibleSchema), ("truncated", IntegrityProblem::Corrupted)]);
+        assert_eq!(report.quarantined.len(), 2);
+        assert!(manager.snapshot_exists("good"));
+        assert!(!manager.snapshot_exists("truncated"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scan_runs_at_startup_and_every_interval() {
+        let once = Arc::new(AutoPauseManager::new(Default::default()));
+        once.spawn_integrity_scan().await.unwrap();
+        assert!(once.integrity_report().is_some());
+
+        let config = crate::auto_pause::AutoPauseConfig {
+            integrity_scan_interval_secs: Some(60),
+            ..Default::default()
+        };
+        let manager = Arc::new(AutoPauseManager::new(config));
+        let task = manager.spawn_integrity_scan();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let first = manager.integrity_report().unwrap().scanned_at;
+        assert!(first.is_some());
+
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(manager.integrity_report().unwrap().scanned_at > first);
+        assert!(!task.is_finished());
+        task.abort();
+    }
+}
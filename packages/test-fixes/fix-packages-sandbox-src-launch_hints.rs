@@ -0,0 +1,165 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::state_snapshot::PersistedProcess;
+
+/// Where and how a process was started, captured at snapshot time so a relaunch
+/// can reproduce it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LaunchContext {
+    /// Resolved executable (`/proc/<pid>/exe`)
+    pub exe: Option<String>,
+    pub cwd: Option<String>,
+    /// Interpreter family when the executable is one (python, node, ruby, ...)
+    pub interpreter: Option<String>,
+    /// Python virtualenv the process ran in
+    pub venv: Option<String>,
+    /// PATH from the process environment
+    pub path: Option<String>,
+}
+
+impl LaunchContext {
+    /// The same context with every path, and each PATH entry, passed through `rewrite`
+    pub fn rewrite_paths(&self, rewrite: impl Fn(&str) -> String) -> Self {
+        let rewrite_one = |value: &Option<String>| value.as_deref().map(&rewrite);
+        Self {
+            exe: rewrite_one(&self.exe),
+            cwd: rewrite_one(&self.cwd),
+            interpreter: self.interpreter.clone(),
+            venv: rewrite_one(&self.venv),
+            path: self.path.as_deref().map(|path| path.split(':').map(&rewrite).collect::<Vec<_>>().join(":")),
+        }
+    }
+}
+
+/// How to relaunch a persisted process, and what could not be reproduced. Only
+/// `env` is set for the relaunched process; the agent's environment is not inherited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RelaunchPlan {
+    pub argv: Vec<String>,
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+    /// Ways the relaunch may differ from the original process
+    pub caveats: Vec<String>,
+}
+
+const INTERPRETERS: &[&str] = &["python", "node", "ruby", "perl", "php", "java", "bash", "sh"];
+
+/// Interpreter family of an executable path, e.g. `/usr/bin/python3.11` -> `python`
+pub fn interpreter_of(exe: &str) -> Option<String> {
+    let name = Path::new(exe).file_name()?.to_str()?;
+    INTERPRETERS
+        .iter()
+        .find(|family| name.strip_prefix(*family).is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit() || c == '.')))
+        .map(|family| family.to_string())
+}
+
+/// Capture the launch context of a live process
+#[cfg(target_os = "linux")]
+pub fn capture(pid: i32) -> Option<LaunchContext> {
+    let link = |name: &str| std::fs::read_link(format!("/proc/{}/{}", pid, name)).ok().map(|p| p.to_string_lossy().into_owned());
+    let exe = link("exe")?;
+    let environ = std::fs::read(format!("/proc/{}/environ", pid)).unwrap_or_default();
+    let var = |key: &str| {
+        environ
+            .split(|&b| b == 0)
+            .filter_map(|entry| std::str::from_utf8(entry).ok())
+            .find_map(|entry| entry.strip_prefix(key).and_then(|rest| rest.strip_prefix('=')))
+            .map(String::from)
+    };
+    let interpreter = interpreter_of(&exe);
+    let venv = var("VIRTUAL_ENV").or_else(|| {
+        // A venv python run by absolute path has no VIRTUAL_ENV; its pyvenv.cfg gives it away
+        let root = Path::new(&exe).parent()?.parent()?;
+        (interpreter.as_deref() == Some("python") && root.join("pyvenv.cfg").exists()).then(|| root.to_string_lossy().into_owned())
+    });
+    Some(LaunchContext {
+        cwd: link("cwd"),
+        path: var("PATH"),
+        interpreter,
+        venv,
+        exe: Some(exe),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn capture(_pid: i32) -> Option<LaunchContext> {
+    None
+}
+
+/// Build the relaunch for a persisted process, checking the captured paths still exist.
+/// The resolved executable replaces a bare `argv[0]` so `python` means the same
+/// interpreter (and venv) it meant at snapshot time.
+pub fn relaunch_plan(process: &PersistedProcess) -> RelaunchPlan {
+    let mut plan = RelaunchPlan {
+        argv: process.argv.clone(),
+        ..RelaunchPlan::default()
+    };
+    let Some(launch) = &process.launch else {
+        plan.caveats.push("no launch context captured; using the agent's cwd and an empty environment".to_string());
+        return plan;
+    };
+
+    if let Some(exe) = &launch.exe {
+        if let Some(original) = exe.strip_suffix(" (deleted)") {
+            plan.caveats.push(format!("executable {} was deleted or replaced after the process started", original));
+        } else if !Path::new(exe).exists() {
+            plan.caveats.push(format!("executable {} no longer exists", exe));
+        } else if !process.argv.is_empty() && !plan.argv[0].contains('/') {
+            plan.argv[0] = exe.clone();
+        }
+    }
+    match &launch.cwd {
+        Some(cwd) if Path::new(cwd).is_dir() => plan.cwd = Some(cwd.clone()),
+        Some(cwd) => plan.caveats.push(format!("working directory {} no longer exists", cwd)),
+        None => plan.caveats.push("working directory unknown".to_string()),
+    }
+    if let Some(venv) = &launch.venv {
+        if Path::new(venv).is_dir() {
+            plan.env.push(("VIRTUAL_ENV".to_string(), venv.clone()));
+        } else {
+            plan.caveats.push(format!("virtualenv {} no longer exists", venv));
+        }
+    }
+    if let Some(path) = &launch.path {
+        plan.env.push(("PATH".to_string(), path.clone()));
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::test_support::persisted_process;
+
+    #[test]
+    fn test_interpreter_of() {
+        assert_eq!(interpreter_of("/usr/bin/python3.11").as_deref(), Some("python"));
+        assert_eq!(interpreter_of("/usr/local/bin/node").as_deref(), Some("node"));
+        assert_eq!(interpreter_of("/usr/bin/shellcheck"), None);
+    }
+
+    #[test]
+    fn test_plan_uses_resolved_exe_and_reports_missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("python3");
+        std::fs::write(&exe, b"").unwrap();
+        let process = PersistedProcess {
+            launch: Some(LaunchContext {
+                exe: Some(exe.to_string_lossy().into_owned()),
+                cwd: Some("/nonexistent/project".to_string()),
+                interpreter: Some("python".to_string()),
+                venv: None,
+                path: Some("/usr/bin".to_string()),
+            }),
+            ..persisted_process(7, &["python3", "app.py"], Utc::now())
+        };
+
+        let plan = relaunch_plan(&process);
+        assert_eq!(plan.argv, vec![exe.to_string_lossy().into_owned(), "app.py".to_string()]);
+        assert_eq!(plan.cwd, None);
+        assert_eq!(plan.env, vec![("PATH".to_string(), "/usr/bin".to_string())]);
+        assert_eq!(plan.caveats, vec!["working directory /nonexistent/project no longer exists".to_string()]);
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+/// Lifecycle state of a sandbox as driven by the agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleState {
+    #[default]
+    Running,
+    Pausing,
+    Paused,
+    Resuming,
+}
+
+impl LifecycleState {
+    /// Whether a resume still has work to do for a sandbox in this state
+    pub fn needs_resume(&self) -> bool {
+        matches!(self, LifecycleState::Paused | LifecycleState::Resuming)
+    }
+}
+
+/// Persisted lifecycle state, so an interrupted batch can pick up where it left off
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleRecord {
+    pub state: LifecycleState,
+    pub updated_at: DateTime<Utc>,
+    /// `monotonic_ms` when the record was written, to tell how far the wall clock
+    /// moved while the sandbox was not running
+    #[serde(default)]
+    pub monotonic_ms: Option<u64>,
+}
+
+impl LifecycleRecord {
+    pub fn new(state: LifecycleState) -> Self {
+        Self {
+            state,
+            updated_at: Utc::now(),
+            monotonic_ms: monotonic_ms(),
+        }
+    }
+}
+
+/// Milliseconds on CLOCK_MONOTONIC, which stands still while the VM is snapshotted
+#[cfg(unix)]
+pub fn monotonic_ms() -> Option<u64> {
+    let now = nix::time::clock_gettime(nix::time::ClockId::CLOCK_MONOTONIC).ok()?;
+    Some(now.tv_sec() as u64 * 1000 + now.tv_nsec() as u64 / 1_000_000)
+}
+
+#[cfg(not(unix))]
+pub fn monotonic_ms() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupted_resume_still_needs_resuming() {
+        assert!(LifecycleState::Paused.needs_resume());
+        assert!(LifecycleState::Resuming.needs_resume());
+        assert!(!LifecycleState::Running.needs_resume());
+        assert!(!LifecycleState::Pausing.needs_resume());
+
+        // Records written before the monotonic clock was kept still load
+        let record: LifecycleRecord = serde_json::from_str(r#"{"state":"resuming","updated_at":"2024-01-01T00:00:00Z"}"#).unwrap();
+        assert_eq!(record.state, LifecycleState::Resuming);
+        assert_eq!(record.monotonic_ms, None);
+    }
+}
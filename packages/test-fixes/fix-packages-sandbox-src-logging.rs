@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Serialize, Deserialize};
+
+/// Where log records are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSink {
+    /// Plain text on stderr
+    #[default]
+    Stderr,
+    /// systemd journal; key-value pairs such as `sandbox_id` and `operation`
+    /// become journal fields (SANDBOX_ID, OPERATION)
+    Journald,
+    /// Local syslog daemon over the unix socket, in RFC 5424 format; key-value pairs
+    /// become structured data parameters under the `e2b@32473` SD-ID
+    Syslog,
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingConfig {
+    #[serde(default)]
+    pub sink: LogSink,
+    /// Maximum level to record (default: info)
+    #[serde(default = "default_level")]
+    pub level: LevelFilter,
+    /// Identifier used by journald/syslog (default: e2b-sandbox-agent)
+    #[serde(default = "default_identifier")]
+    pub identifier: String,
+    /// Per-module overrides, e.g. `persistence = "debug"`
+    #[serde(default)]
+    pub modules: HashMap<String, LevelFilter>,
+}
+
+fn default_level() -> LevelFilter {
+    LevelFilter::Info
+}
+
+fn default_identifier() -> String {
+    "e2b-sandbox-agent".to_string()
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            sink: LogSink::default(),
+            level: default_level(),
+            identifier: default_identifier(),
+            modules: HashMap::new(),
+        }
+    }
+}
+
+/// Handle for changing log levels at runtime; clones share the same levels
+#[derive(Clone)]
+pub struct LogLevels {
+    default: Arc<RwLock<LevelFilter>>,
+    modules: Arc<RwLock<HashMap<String, LevelFilter>>>,
+}
+
+impl LogLevels {
+    pub fn new(default: LevelFilter, modules: HashMap<String, LevelFilter>) -> Self {
+        Self {
+            default: Arc::new(RwLock::new(default)),
+            modules: Arc::new(RwLock::new(modules)),
+        }
+    }
+
+    /// Set the level for a module (`persistence`, `process`, `auto_pause`, `audit`, ...)
+    pub fn set_module_level(&self, module: &str, level: LevelFilter) {
+        self.modules.write().unwrap().insert(module.to_string(), level);
+    }
+
+    /// Remove a module override so the default level applies again
+    pub fn clear_module_level(&self, module: &str) {
+        self.modules.write().unwrap().remove(module);
+    }
+
+    pub fn set_default_level(&self, level: LevelFilter) {
+        *self.default.write().unwrap() = level;
+    }
+
+    /// Current overrides
+    pub fn module_levels(&self) -> HashMap<String, LevelFilter> {
+        self.modules.read().unwrap().clone()
+    }
+
+    /// Effective level for a log target; the longest matching module wins
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        let modules = self.modules.read().unwrap();
+        modules
+            .iter()
+            .filter(|(module, _)| module_matches(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(*self.default.read().unwrap())
+    }
+}
+
+/// `module` matches a target that is exactly it, nested under it, or contains it as a path segment
+fn module_matches(target: &str, module: &str) -> bool {
+    target == module
+        || target.starts_with(&format!("{}::", module))
+        || target.split("::").any(|segment| segment == module)
+}
+
+/// Wraps the sink logger and applies per-module levels
+struct FilteredLogger {
+    inner: Box<dyn Log>,
+    levels: LogLevels,
+}
+
+impl Log for FilteredLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.levels.level_for(metadata.target()) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// SD-ID the key-value pairs of a record are sent under. 32473 is the private
+/// enterprise number RFC 5612 reserves for examples and private use.
+const STRUCTURED_DATA_ID: &str = "e2b@32473";
+
+type SyslogWriter = syslog::Logger<syslog::LoggerBackend, syslog::Formatter5424>;
+
+/// Syslog sink that forwards a record's key-value pairs as structured data
+struct StructuredSyslog {
+    writer: Mutex<SyslogWriter>,
+}
+
+impl Log for StructuredSyslog {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        let message = (0, structured_data(record), record.args().to_string());
+        let mut writer = self.writer.lock().unwrap();
+        // Nowhere left to report a failure to log
+        let _ = match record.level() {
+            Level::Error => writer.err(message),
+            Level::Warn => writer.warning(message),
+            Level::Info => writer.info(message),
+            Level::Debug | Level::Trace => writer.debug(message),
+        };
+    }
+
+    fn flush(&self) {}
+}
+
+/// Collects key-value pairs as structured data parameters
+struct Params(BTreeMap<String, String>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for Params {
+    fn visit_pair(&mut self, key: log::kv::Key<'kvs>, value: log::kv::Value<'kvs>) -> Result<(), log::kv::Error> {
+        // RFC 5424 PARAM-VALUE escapes '"', '\' and ']'
+        let value = value.to_string().replace('\\', "\\\\").replace('"', "\\\"").replace(']', "\\]");
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// The record's key-value pairs as RFC 5424 structured data; empty without any
+fn structured_data(record: &Record) -> syslog::StructuredData {
+    let mut params = Params(BTreeMap::new());
+    let _ = record.key_values().visit(&mut params);
+    let mut data = syslog::StructuredData::new();
+    if !params.0.is_empty() {
+        data.insert(STRUCTURED_DATA_ID.to_string(), params.0);
+    }
+    data
+}
+
+/// Install the global logger for the configured sink; call once at startup.
+/// The returned handle changes levels without a restart.
+pub fn init(config: &LoggingConfig) -> Result<LogLevels, Box<dyn std::error::Error>> {
+    let inner: Box<dyn Log> = match config.sink {
+        LogSink::Stderr => Box::new(env_logger::Builder::new().filter_level(LevelFilter::Trace).build()),
+        LogSink::Journald => Box::new(
+            systemd_journal_logger::JournalLog::new()?.with_syslog_identifier(config.identifier.clone()),
+        ),
+        LogSink::Syslog => {
+            let formatter = syslog::Formatter5424 {
+                facility: syslog::Facility::LOG_DAEMON,
+                hostname: None,
+                process: config.identifier.clone(),
+                pid: std::process::id(),
+            };
+            let writer = syslog::unix(formatter).map_err(|e| e.to_string())?;
+            Box::new(StructuredSyslog { writer: Mutex::new(writer) })
+        }
+    };
+
+    let levels = LogLevels::new(config.level, config.modules.clone());
+    log::set_boxed_logger(Box::new(FilteredLogger {
+        inner,
+        levels: levels.clone(),
+    }))?;
+    // Filtering happens per module in FilteredLogger, so let every record through here
+    log::set_max_level(LevelFilter::Trace);
+    Ok(levels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_specific_module_level_wins() {
+        let levels = LogLevels::new(LevelFilter::Info, HashMap::new());
+        levels.set_module_level("persistence", LevelFilter::Debug);
+        levels.set_module_level("sandbox::persistence::gc", LevelFilter::Trace);
+
+        assert_eq!(levels.level_for("sandbox::process"), LevelFilter::Info);
+        assert_eq!(levels.level_for("sandbox::persistence"), LevelFilter::Debug);
+        assert_eq!(levels.level_for("sandbox::persistence::gc"), LevelFilter::Trace);
+
+        levels.clear_module_level("persistence");
+        assert_eq!(levels.level_for("sandbox::persistence"), LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_key_values_become_structured_data() {
+        let fields = [("sandbox_id", "sbx-1"), ("operation", "pause"), ("reason", "quota \"hard\" [x]")];
+        let data = structured_data(&Record::builder().args(format_args!("Paused sandbox sbx-1")).key_values(&fields).build());
+        let params = &data[STRUCTURED_DATA_ID];
+        assert_eq!(params["sandbox_id"], "sbx-1");
+        assert_eq!(params["operation"], "pause");
+        assert_eq!(params["reason"], r#"quota \"hard\" [x\]"#);
+
+        assert!(structured_data(&Record::builder().args(format_args!("no fields")).build()).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit};
+
+use crate::runtime_metrics::RuntimeStats;
+
+pub const SNAPSHOT_SIZE_BYTES: &str = "sandbox_snapshot_size_bytes";
+pub const SNAPSHOT_COMPRESSION_RATIO: &str = "sandbox_snapshot_compression_ratio";
+pub const SNAPSHOT_SAVE_SECONDS: &str = "sandbox_snapshot_save_seconds";
+pub const SNAPSHOT_LOAD_SECONDS: &str = "sandbox_snapshot_load_seconds";
+pub const PERSISTENCE_ERRORS_TOTAL: &str = "sandbox_persistence_errors_total";
+pub const TRACKED_PROCESSES: &str = "sandbox_tracked_processes";
+pub const PROCESS_EVICTIONS_TOTAL: &str = "sandbox_process_evictions_total";
+pub const PROCESS_REJECTIONS_TOTAL: &str = "sandbox_process_rejections_total";
+pub const OOM_KILLS_TOTAL: &str = "sandbox_oom_kills_total";
+pub const CRASH_LOOPS_TOTAL: &str = "sandbox_crash_loops_total";
+pub const BLOCKING_SECONDS: &str = "sandbox_blocking_seconds";
+pub const BLOCKING_WAIT_SECONDS: &str = "sandbox_blocking_wait_seconds";
+pub const STORE_SNAPSHOTS: &str = "sandbox_store_snapshots";
+pub const STORE_BYTES: &str = "sandbox_store_bytes";
+pub const QUOTA_REJECTIONS_TOTAL: &str = "sandbox_quota_rejections_total";
+pub const RUNTIME_WORKERS: &str = "sandbox_runtime_workers";
+pub const RUNTIME_ALIVE_TASKS: &str = "sandbox_runtime_alive_tasks";
+pub const RUNTIME_GLOBAL_QUEUE_DEPTH: &str = "sandbox_runtime_global_queue_depth";
+pub const RUNTIME_WORKER_BUSY_RATIO: &str = "sandbox_runtime_worker_busy_ratio";
+pub const RUNTIME_WORKER_MEAN_POLL_SECONDS: &str = "sandbox_runtime_worker_mean_poll_seconds";
+pub const RUNTIME_BLOCKING_THREADS: &str = "sandbox_runtime_blocking_threads";
+pub const RUNTIME_IDLE_BLOCKING_THREADS: &str = "sandbox_runtime_idle_blocking_threads";
+pub const RUNTIME_BLOCKING_QUEUE_DEPTH: &str = "sandbox_runtime_blocking_queue_depth";
+
+/// Register descriptions with the installed recorder; call once at startup
+pub fn describe() {
+    describe_histogram!(SNAPSHOT_SIZE_BYTES, Unit::Bytes, "Serialized snapshot size");
+    describe_histogram!(SNAPSHOT_COMPRESSION_RATIO, "Serialized size divided by stored size");
+    describe_histogram!(SNAPSHOT_SAVE_SECONDS, Unit::Seconds, "Time to persist a snapshot");
+    describe_histogram!(SNAPSHOT_LOAD_SECONDS, Unit::Seconds, "Time to load a snapshot");
+    describe_counter!(PERSISTENCE_ERRORS_TOTAL, "Failed persistence operations");
+    describe_gauge!(TRACKED_PROCESSES, "Process entries tracked across all sandboxes");
+    describe_counter!(PROCESS_EVICTIONS_TOTAL, "Terminated process entries evicted to stay within limits");
+    describe_counter!(PROCESS_REJECTIONS_TOTAL, "Processes not tracked because a sandbox was at its limit");
+    describe_counter!(OOM_KILLS_TOTAL, "Processes killed by the OOM killer inside sandbox cgroups");
+    describe_counter!(CRASH_LOOPS_TOTAL, "Crash loops detected in sandbox processes");
+    describe_histogram!(BLOCKING_SECONDS, Unit::Seconds, "Time spent in blocking jobs moved off the async runtime");
+    describe_histogram!(BLOCKING_WAIT_SECONDS, Unit::Seconds, "Time blocking jobs queued for the blocking budget");
+    describe_gauge!(STORE_SNAPSHOTS, "Snapshots in the snapshot store");
+    describe_gauge!(STORE_BYTES, Unit::Bytes, "Bytes used by the snapshot store");
+    describe_counter!(QUOTA_REJECTIONS_TOTAL, "Operations rejected because their tenant was over quota");
+    describe_gauge!(RUNTIME_WORKERS, "Async runtime worker threads");
+    describe_gauge!(RUNTIME_ALIVE_TASKS, "Tasks alive on the async runtime");
+    describe_gauge!(RUNTIME_GLOBAL_QUEUE_DEPTH, "Tasks waiting in the runtime's shared queue");
+    describe_gauge!(RUNTIME_WORKER_BUSY_RATIO, "Share of the last sampling interval a worker spent busy");
+    describe_gauge!(RUNTIME_WORKER_MEAN_POLL_SECONDS, Unit::Seconds, "Mean task poll time per worker");
+    describe_gauge!(RUNTIME_BLOCKING_THREADS, "Threads in the runtime's blocking pool");
+    describe_gauge!(RUNTIME_IDLE_BLOCKING_THREADS, "Idle threads in the runtime's blocking pool");
+    describe_gauge!(RUNTIME_BLOCKING_QUEUE_DEPTH, "Blocking jobs waiting for a blocking pool thread");
+}
+
+/// Record a successful snapshot save
+pub fn record_save(backend: &'static str, serialized_bytes: usize, stored_bytes: usize, elapsed: Duration) {
+    histogram!(SNAPSHOT_SIZE_BYTES, "backend" => backend).record(serialized_bytes as f64);
+    if stored_bytes > 0 {
+        histogram!(SNAPSHOT_COMPRESSION_RATIO, "backend" => backend)
+            .record(serialized_bytes as f64 / stored_bytes as f64);
+    }
+    histogram!(SNAPSHOT_SAVE_SECONDS, "backend" => backend).record(elapsed.as_secs_f64());
+}
+
+/// Record a successful snapshot load
+pub fn record_load(backend: &'static str, elapsed: Duration) {
+    histogram!(SNAPSHOT_LOAD_SECONDS, "backend" => backend).record(elapsed.as_secs_f64());
+}
+
+/// Count a failed persistence operation
+pub fn record_error(backend: &'static str, operation: &'static str) {
+    counter!(PERSISTENCE_ERRORS_TOTAL, "backend" => backend, "operation" => operation).increment(1);
+}
+
+/// Publish the number of tracked process entries
+pub fn record_tracked_processes(count: usize) {
+    gauge!(TRACKED_PROCESSES).set(count as f64);
+}
+
+/// Count terminated entries evicted from the tracking map
+pub fn record_process_evictions(count: usize) {
+    counter!(PROCESS_EVICTIONS_TOTAL).increment(count as u64);
+}
+
+/// Count a process that could not be tracked
+pub fn record_process_rejected() {
+    counter!(PROCESS_REJECTIONS_TOTAL).increment(1);
+}
+
+/// Count OOM kills observed in a sandbox cgroup
+pub fn record_oom_kills(count: u64) {
+    counter!(OOM_KILLS_TOTAL).increment(count);
+}
+
+/// Count a detected crash loop
+pub fn record_crash_loop() {
+    counter!(CRASH_LOOPS_TOTAL).increment(1);
+}
+
+/// Record how long a blocking job ran
+pub fn record_blocking(op: &'static str, elapsed: Duration) {
+    histogram!(BLOCKING_SECONDS, "op" => op).record(elapsed.as_secs_f64());
+}
+
+/// Record how long a blocking job waited for the budget
+pub fn record_blocking_wait(op: &'static str, waited: Duration) {
+    histogram!(BLOCKING_WAIT_SECONDS, "op" => op).record(waited.as_secs_f64());
+}
+
+/// Publish the size of the snapshot store
+pub fn record_store_stats(backend: &'static str, snapshots: usize, bytes: u64) {
+    gauge!(STORE_SNAPSHOTS, "backend" => backend).set(snapshots as f64);
+    gauge!(STORE_BYTES, "backend" => backend).set(bytes as f64);
+}
+
+/// Count an operation rejected by a tenant quota
+pub fn record_quota_rejection(tenant: &str, quota: &'static str) {
+    counter!(QUOTA_REJECTIONS_TOTAL, "tenant" => tenant.to_string(), "quota" => quota).increment(1);
+}
+
+/// Publish a runtime sample and each worker's busy share of the last interval
+pub fn record_runtime(stats: &RuntimeStats, busy_ratios: &[f64]) {
+    gauge!(RUNTIME_WORKERS).set(stats.workers as f64);
+    gauge!(RUNTIME_ALIVE_TASKS).set(stats.alive_tasks as f64);
+    gauge!(RUNTIME_GLOBAL_QUEUE_DEPTH).set(stats.global_queue_depth as f64);
+    for (worker, ratio) in busy_ratios.iter().enumerate() {
+        gauge!(RUNTIME_WORKER_BUSY_RATIO, "worker" => worker.to_string()).set(*ratio);
+    }
+    for (worker, poll_us) in stats.worker_mean_poll_us.iter().flatten().enumerate() {
+        gauge!(RUNTIME_WORKER_MEAN_POLL_SECONDS, "worker" => worker.to_string()).set(*poll_us as f64 / 1e6);
+    }
+    if let Some(threads) = stats.blocking_threads {
+        gauge!(RUNTIME_BLOCKING_THREADS).set(threads as f64);
+    }
+    if let Some(idle) = stats.idle_blocking_threads {
+        gauge!(RUNTIME_IDLE_BLOCKING_THREADS).set(idle as f64);
+    }
+    if let Some(depth) = stats.blocking_queue_depth {
+        gauge!(RUNTIME_BLOCKING_QUEUE_DEPTH).set(depth as f64);
+    }
+}
@@ -0,0 +1,265 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{error, warn};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::approval::ApprovalDecision;
+use crate::events::{Event, EventPayload};
+use crate::warnings::WarningKind;
+
+/// Events worth waking someone up for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlarmClass {
+    PauseFailed,
+    ResumeFailed,
+    SnapshotCorrupted,
+    /// An operation breached its SLO threshold
+    SloBreached,
+    /// The watchdog found an operation running far longer than expected
+    StuckOperation,
+    /// Operations of sandboxes from one template became much slower
+    TemplateRegression,
+    /// A force-kill pause is waiting for someone to approve it
+    ApprovalRequested,
+    /// An idle or scheduled pause is coming, for the sandbox's users
+    GraceNotice,
+}
+
+impl AlarmClass {
+    /// The class of an event, or None for routine events
+    pub fn of(event: &Event) -> Option<Self> {
+        match &event.payload {
+            EventPayload::OperationFailed { operation, .. } if operation == "pause" => Some(AlarmClass::PauseFailed),
+            EventPayload::OperationFailed { operation, .. } if operation == "resume" => Some(AlarmClass::ResumeFailed),
+            EventPayload::Warning(warning) if warning.kind == WarningKind::SnapshotCorrupted => Some(AlarmClass::SnapshotCorrupted),
+            EventPayload::Alarm(alarm) if alarm.stuck => Some(AlarmClass::StuckOperation),
+            EventPayload::Alarm(alarm) if alarm.template.is_some() => Some(AlarmClass::TemplateRegression),
+            EventPayload::Alarm(_) => Some(AlarmClass::SloBreached),
+            EventPayload::Approval { decision: ApprovalDecision::Requested, .. } => Some(AlarmClass::ApprovalRequested),
+            EventPayload::GraceNotice { .. } => Some(AlarmClass::GraceNotice),
+            _ => None,
+        }
+    }
+
+    fn default_template(self) -> &'static str {
+        match self {
+            AlarmClass::PauseFailed => "Pausing sandbox {sandbox_id} failed at {timestamp}: {payload.error}",
+            AlarmClass::ResumeFailed => "Resuming sandbox {sandbox_id} failed at {timestamp}: {payload.error}",
+            AlarmClass::SnapshotCorrupted => "Snapshot of sandbox {sandbox_id} is corrupted: {payload.message}",
+            AlarmClass::SloBreached => "Sandbox {sandbox_id} {payload.phase} took {payload.elapsed_ms} ms, over the {payload.threshold_ms} ms SLO",
+            AlarmClass::StuckOperation => "Sandbox {sandbox_id} {payload.phase} has been running for {payload.elapsed_ms} ms and looks stuck",
+            AlarmClass::TemplateRegression => "Template {payload.template} {payload.phase} now takes {payload.elapsed_ms} ms, over {payload.threshold_ms} ms against its earlier runs",
+            AlarmClass::ApprovalRequested => "Force-kill pause of sandbox {sandbox_id} (operation {payload.operation_id}) is waiting for approval",
+            AlarmClass::GraceNotice => "Sandbox {sandbox_id} will be paused at {payload.pause_at} ({payload.trigger}); keep it running with token {payload.keep_alive_token}",
+        }
+    }
+}
+
+/// Where notifications are sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotificationChannel {
+    /// A Slack incoming webhook
+    Slack { webhook_url: String },
+    /// Any HTTP endpoint; gets the event envelope plus the rendered `text`
+    Webhook { url: String },
+    Smtp {
+        host: String,
+        #[serde(default)]
+        port: Option<u16>,
+        /// Use STARTTLS; implicit TLS is not supported
+        #[serde(default = "default_starttls")]
+        starttls: bool,
+        #[serde(default)]
+        username: Option<String>,
+        /// Environment variable holding the SMTP password, so it stays out of the config file
+        #[serde(default)]
+        password_env: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
+/// A notification channel and the alarm-class events it is told about.
+///
+/// Templates substitute `{field}` with the field of the event's JSON envelope, using dots
+/// for nested fields, e.g. `{sandbox_id}` or `{payload.error}`. Unknown fields are left as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    #[serde(flatten)]
+    pub channel: NotificationChannel,
+    /// Classes to notify about; all of them when empty
+    #[serde(default)]
+    pub classes: Vec<AlarmClass>,
+    /// Message body, replacing the built-in per-class text
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Email subject; ignored by Slack
+    #[serde(default = "default_subject")]
+    pub subject: String,
+}
+
+fn default_subject() -> String {
+    "[sandbox] {event_type} in {sandbox_id}".to_string()
+}
+
+/// Fill `{field}` placeholders from the event's JSON form
+pub fn render(template: &str, event: &Event) -> String {
+    let value = serde_json::to_value(event).unwrap_or(Value::Null);
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let placeholder = &rest[..=end];
+        let path = &placeholder[1..placeholder.len() - 1];
+        match path.split('.').try_fold(&value, |value, key| value.get(key)) {
+            Some(Value::String(text)) => out.push_str(text),
+            Some(Value::Null) | None => out.push_str(placeholder),
+            Some(other) => out.push_str(&other.to_string()),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Sends alarm-class events to Slack, a webhook or by email
+pub struct Notifier {
+    config: NotificationConfig,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Whether the event belongs to a class this notifier is configured for
+    pub fn wants(&self, event: &Event) -> Option<AlarmClass> {
+        AlarmClass::of(event).filter(|class| self.config.classes.is_empty() || self.config.classes.contains(class))
+    }
+
+    /// Notify about events from `events` until the bus is dropped
+    pub fn spawn(self, mut events: broadcast::Receiver<Event>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let Some(class) = self.wants(&event) {
+                            self.notify(class, &event).await;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => warn!("Notifier fell behind, dropped {} events", missed),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    async fn notify(&self, class: AlarmClass, event: &Event) {
+        let template = self.config.template.as_deref().unwrap_or(class.default_template());
+        let text = render(template, event);
+        let result = match &self.config.channel {
+            NotificationChannel::Slack { webhook_url } => self.send_slack(webhook_url, &text).await,
+            NotificationChannel::Webhook { url } => self.send_webhook(url, event, text).await,
+            NotificationChannel::Smtp { .. } => self.send_email(&render(&self.config.subject, event), text).await,
+        };
+        if let Err(e) = result {
+            error!("Failed to send {:?} notification for sandbox {}: {}", class, event.sandbox_id, e);
+        }
+    }
+
+    async fn send_slack(&self, webhook_url: &str, text: &str) -> Result<(), String> {
+        let body = serde_json::json!({ "text": text });
+        self.client
+            .post(webhook_url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn send_webhook(&self, url: &str, event: &Event, text: String) -> Result<(), String> {
+        let mut body = serde_json::to_value(event).map_err(|e| e.to_string())?;
+        body["text"] = Value::String(text);
+        self.client
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn send_email(&self, subject: &str, body: String) -> Result<(), String> {
+        let NotificationChannel::Smtp { host, port, starttls, username, password_env, from, to } = &self.config.channel else {
+            return Ok(());
+        };
+        let mut message = Message::builder().from(from.parse::<Mailbox>().map_err(|e| e.to_string())?).subject(subject);
+        for recipient in to {
+            message = message.to(recipient.parse::<Mailbox>().map_err(|e| e.to_string())?);
+        }
+        let message = message.body(body).map_err(|e| e.to_string())?;
+
+        let mut transport = if *starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host).map_err(|e| e.to_string())?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        if let Some(port) = port {
+            transport = transport.port(*port);
+        }
+        if let Some(username) = username {
+            let password = match password_env {
+                Some(var) => std::env::var(var).map_err(|_| format!("SMTP password variable {} is not set", var))?,
+                None => String::new(),
+            };
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+        transport.build().send(message).await.map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warnings::Warning;
+
+    #[test]
+    fn test_classes_and_templates() {
+        let failed = Event::new("sbx", EventPayload::OperationFailed { operation: "resume".to_string(), error: "no snapshot".to_string() });
+        assert_eq!(AlarmClass::of(&failed), Some(AlarmClass::ResumeFailed));
+        assert_eq!(render(AlarmClass::ResumeFailed.default_template(), &failed).split(" at ").next(), Some("Resuming sandbox sbx failed"));
+        assert_eq!(render("{payload.error} ({event_type}) {payload.missing} {", &failed), "no snapshot (operation_failed) {payload.missing} {");
+
+        let corrupted = Event::new("sbx", EventPayload::Warning(Warning::new(WarningKind::SnapshotCorrupted, "sbx", "bad checksum")));
+        assert_eq!(AlarmClass::of(&corrupted), Some(AlarmClass::SnapshotCorrupted));
+        let routine = Event::new("sbx", EventPayload::Warning(Warning::new(WarningKind::ProbeFailed, "sbx", "probe")));
+        assert_eq!(AlarmClass::of(&routine), None);
+
+        let config: NotificationConfig =
+            serde_json::from_value(serde_json::json!({ "kind": "slack", "webhook_url": "https://hooks.example/x", "classes": ["pause_failed"] })).unwrap();
+        let notifier = Notifier::new(config);
+        assert_eq!(notifier.wants(&failed), None);
+    }
+}
@@ -0,0 +1,253 @@
+//! Remote object storage for the snapshot store, used while migrating to or from a bucket
+
+use chrono::Utc;
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use reqwest::{Method, StatusCode};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+/// Flat key/value storage; keys are store paths such as `<sandbox_id>/lifecycle.json`
+pub trait ObjectStore: Send + Sync {
+    /// The object under `key`, or None if there is none
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, String>>;
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<(), String>>;
+    /// Removing a missing object succeeds
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), String>>;
+    /// Every key in the store
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, String>>;
+    /// Where the store lives, for logs
+    fn describe(&self) -> String;
+}
+
+/// An S3 or S3-compatible bucket, addressed path-style so MinIO and others work too
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Config {
+    /// e.g. `https://s3.eu-west-1.amazonaws.com`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    /// Key prefix the store lives under
+    #[serde(default)]
+    pub prefix: String,
+    /// Taken from `AWS_ACCESS_KEY_ID` when unset
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    /// Taken from `AWS_SECRET_ACCESS_KEY` when unset
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+}
+
+pub struct S3Store {
+    config: S3Config,
+    access_key_id: String,
+    secret_access_key: String,
+    client: reqwest::Client,
+}
+
+impl S3Store {
+    pub fn new(config: S3Config) -> Result<Self, String> {
+        let credential = |value: &Option<String>, var: &str| {
+            value.clone().or_else(|| std::env::var(var).ok()).ok_or_else(|| format!("no {} for bucket {}", var, config.bucket))
+        };
+        Ok(Self {
+            access_key_id: credential(&config.access_key_id, "AWS_ACCESS_KEY_ID")?,
+            secret_access_key: credential(&config.secret_access_key, "AWS_SECRET_ACCESS_KEY")?,
+            client: reqwest::Client::new(),
+            config,
+        })
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        let key = match self.config.prefix.trim_matches('/') {
+            "" => key.to_string(),
+            prefix => format!("{}/{}", prefix, key),
+        };
+        format!("/{}/{}", uri_encode(&self.config.bucket, false), uri_encode(&key, true))
+    }
+
+    /// Send a request signed with AWS Signature Version 4
+    async fn send(&self, method: Method, path: &str, query: &[(&str, &str)], body: Vec<u8>) -> Result<reqwest::Response, String> {
+        let endpoint = reqwest::Url::parse(&self.config.endpoint).map_err(|e| format!("bad S3 endpoint {}: {}", self.config.endpoint, e))?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(format!("S3 endpoint {} has no host", self.config.endpoint)),
+        };
+        let mut query: Vec<(String, String)> = query.iter().map(|(k, v)| (uri_encode(k, false), uri_encode(v, false))).collect();
+        query.sort();
+        let query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+
+        let now = Utc::now();
+        let (amz_date, date) = (now.format("%Y%m%dT%H%M%SZ").to_string(), now.format("%Y%m%d").to_string());
+        let payload_hash = format!("{:x}", Sha256::digest(&body));
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, host, payload_hash, amz_date, SIGNED_HEADERS, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{:x}", amz_date, scope, Sha256::digest(canonical_request.as_bytes()));
+        let signature = hex(&hmac(&signing_key(&self.secret_access_key, &date, &self.config.region, "s3"), string_to_sign.as_bytes()));
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key_id, scope, SIGNED_HEADERS, signature);
+
+        let url = match query.as_str() {
+            "" => format!("{}{}", self.config.endpoint.trim_end_matches('/'), path),
+            query => format!("{}{}?{}", self.config.endpoint.trim_end_matches('/'), path, query),
+        };
+        self.client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", amz_date)
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+impl ObjectStore for S3Store {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, String>> {
+        Box::pin(async move {
+            let res = self.send(Method::GET, &self.object_path(key), &[], Vec::new()).await?;
+            if res.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let res = res.error_for_status().map_err(|e| e.to_string())?;
+            Ok(Some(res.bytes().await.map_err(|e| e.to_string())?.to_vec()))
+        })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let res = self.send(Method::PUT, &self.object_path(key), &[], bytes).await?;
+            res.error_for_status().map(|_| ()).map_err(|e| e.to_string())
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let res = self.send(Method::DELETE, &self.object_path(key), &[], Vec::new()).await?;
+            match res.status() {
+                StatusCode::NOT_FOUND => Ok(()),
+                _ => res.error_for_status().map(|_| ()).map_err(|e| e.to_string()),
+            }
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, String>> {
+        Box::pin(async move {
+            let prefix = match self.config.prefix.trim_matches('/') {
+                "" => String::new(),
+                prefix => format!("{}/", prefix),
+            };
+            let bucket = format!("/{}", uri_encode(&self.config.bucket, false));
+            let (mut keys, mut token) = (Vec::new(), None::<String>);
+            loop {
+                let mut query = vec![("list-type", "2"), ("prefix", prefix.as_str())];
+                if let Some(token) = &token {
+                    query.push(("continuation-token", token.as_str()));
+                }
+                let res = self.send(Method::GET, &bucket, &query, Vec::new()).await?;
+                let xml = res.error_for_status().map_err(|e| e.to_string())?.text().await.map_err(|e| e.to_string())?;
+                let page = parse_list(&xml);
+                keys.extend(page.keys.into_iter().filter_map(|key| key.strip_prefix(&prefix).map(str::to_string)));
+                match page.next {
+                    Some(next) => token = Some(next),
+                    None => return Ok(keys),
+                }
+            }
+        })
+    }
+
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.config.bucket, self.config.prefix.trim_matches('/'))
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac(format!("AWS4{}", secret).as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    hmac(&key, b"aws4_request")
+}
+
+/// Percent-encode everything but the unreserved characters, and `/` in paths
+fn uri_encode(value: &str, path: bool) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            b'/' if path => "/".to_string(),
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+struct ListPage {
+    keys: Vec<String>,
+    next: Option<String>,
+}
+
+/// The keys and continuation token of a ListObjectsV2 response
+fn parse_list(xml: &str) -> ListPage {
+    let elements = |tag: &str| -> Vec<String> {
+        let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+        xml.split(open.as_str()).skip(1).filter_map(|rest| rest.split_once(close.as_str())).map(|(value, _)| xml_unescape(value)).collect()
+    };
+    let truncated = elements("IsTruncated").first().is_some_and(|value| value == "true");
+    ListPage {
+        keys: elements("Key"),
+        next: elements("NextContinuationToken").into_iter().next().filter(|_| truncated),
+    }
+}
+
+fn xml_unescape(value: &str) -> String {
+    value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signing_key_matches_aws_example() {
+        // From the AWS Signature Version 4 documentation
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex(&key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[test]
+    fn test_object_paths_and_listing() {
+        let store = S3Store::new(S3Config {
+            endpoint: "http://127.0.0.1:9000".to_string(),
+            bucket: "snapshots".to_string(),
+            region: "us-east-1".to_string(),
+            prefix: "/agents/host 1/".to_string(),
+            access_key_id: Some("key".to_string()),
+            secret_access_key: Some("secret".to_string()),
+        })
+        .unwrap();
+        assert_eq!(store.object_path("sbx-1/lifecycle.json"), "/snapshots/agents/host%201/sbx-1/lifecycle.json");
+
+        let page = parse_list(
+            "<ListBucketResult><IsTruncated>true</IsTruncated><Contents><Key>agents/a&amp;b/lifecycle.json</Key></Contents>\
+             <Contents><Key>agents/c/lifecycle.json</Key></Contents><NextContinuationToken>t1</NextContinuationToken></ListBucketResult>",
+        );
+        assert_eq!(page.keys, ["agents/a&b/lifecycle.json", "agents/c/lifecycle.json"]);
+        assert_eq!(page.next.as_deref(), Some("t1"));
+        assert!(parse_list("<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>").next.is_none());
+    }
+}
@@ -0,0 +1,236 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use chrono::Utc;
+use log::{debug, warn};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use tokio::io::unix::AsyncFd;
+use tokio::task::JoinHandle;
+
+use crate::cgroup::Cgroup;
+use crate::metrics;
+use crate::process::{OomEvent, OomVictim, ProcessExit, ProcessManager, ProcessState};
+use crate::sandbox_id::SandboxId;
+
+/// How far back a SIGKILL exit may lie and still be attributed to an OOM kill.
+/// The kernel notifies as it kills, so this only covers the reaper catching up.
+const CORRELATION_WINDOW: Duration = Duration::from_secs(5);
+
+/// Watches `memory.events` of each sandbox cgroup (`<cgroup_root>/<sandbox_id>`)
+/// and records OOM kills in the process manager. The kernel signals a change of the
+/// file through inotify; `interval` only picks up new sandboxes and, where inotify
+/// is unavailable, polls instead.
+pub struct OomMonitor {
+    process_manager: ProcessManager,
+    cgroup_root: PathBuf,
+    interval: Duration,
+    /// Last `oom_kill` count seen per sandbox
+    seen: HashMap<SandboxId, u64>,
+    watches: HashMap<SandboxId, WatchDescriptor>,
+}
+
+impl OomMonitor {
+    pub fn new(process_manager: ProcessManager, cgroup_root: PathBuf, interval: Duration) -> Self {
+        Self {
+            process_manager,
+            cgroup_root,
+            interval,
+            seen: HashMap::new(),
+            watches: HashMap::new(),
+        }
+    }
+
+    /// Run the monitor in the background
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let notify = match Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC).map_err(std::io::Error::from).and_then(AsyncFd::new) {
+                Ok(notify) => Some(notify),
+                Err(e) => {
+                    warn!("inotify unavailable, polling memory.events every {:?}: {}", self.interval, e);
+                    None
+                }
+            };
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                let Some(notify) = &notify else {
+                    ticker.tick().await;
+                    self.check_once().await;
+                    continue;
+                };
+                tokio::select! {
+                    _ = ticker.tick() => {
+                        self.watch(notify.get_ref()).await;
+                        self.check_once().await;
+                    }
+                    ready = notify.readable() => {
+                        let mut ready = match ready {
+                            Ok(ready) => ready,
+                            Err(e) => {
+                                warn!("Failed to wait for memory.events changes: {}", e);
+                                continue;
+                            }
+                        };
+                        let changed = match ready.get_inner().read_events() {
+                            Ok(events) => events.into_iter().map(|event| event.wd).collect(),
+                            Err(Errno::EAGAIN) => {
+                                ready.clear_ready();
+                                continue;
+                            }
+                            Err(e) => {
+                                warn!("Failed to read memory.events changes: {}", e);
+                                Vec::new()
+                            }
+                        };
+                        for sandbox_id in self.changed_sandboxes(&changed) {
+                            self.check_sandbox(sandbox_id).await;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Watch the memory.events of every tracked sandbox, dropping watches of sandboxes
+    /// no longer tracked
+    async fn watch(&mut self, notify: &Inotify) {
+        let sandbox_ids = self.process_manager.sandbox_ids().await;
+        self.watches.retain(|sandbox_id, wd| {
+            let keep = sandbox_ids.contains(sandbox_id);
+            if !keep {
+                // The watch is already gone if the cgroup was removed
+                let _ = notify.rm_watch(*wd);
+            }
+            keep
+        });
+        for sandbox_id in sandbox_ids {
+            if self.watches.contains_key(&sandbox_id) {
+                continue;
+            }
+            let path = self.cgroup_root.join(sandbox_id.as_str()).join("memory.events");
+            match notify.add_watch(&path, AddWatchFlags::IN_MODIFY) {
+                Ok(wd) => {
+                    self.watches.insert(sandbox_id, wd);
+                }
+                Err(e) => debug!("Not watching {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    fn changed_sandboxes(&self, changed: &[WatchDescriptor]) -> Vec<SandboxId> {
+        self.watches.iter().filter(|(_, wd)| changed.contains(wd)).map(|(sandbox_id, _)| sandbox_id.clone()).collect()
+    }
+
+    /// Compare every sandbox's OOM kill count with the last observation;
+    /// returns the sandboxes that saw new kills
+    pub async fn check_once(&mut self) -> Vec<SandboxId> {
+        let mut affected = Vec::new();
+        let sandbox_ids = self.process_manager.sandbox_ids().await;
+        self.seen.retain(|sandbox_id, _| sandbox_ids.contains(sandbox_id));
+
+        for sandbox_id in sandbox_ids {
+            if self.check_sandbox(sandbox_id.clone()).await {
+                affected.push(sandbox_id);
+            }
+        }
+        affected
+    }
+
+    /// Compare one sandbox's OOM kill count with the last observation; true when
+    /// there were new kills
+    async fn check_sandbox(&mut self, sandbox_id: SandboxId) -> bool {
+        let cgroup = Cgroup::new(self.cgroup_root.join(sandbox_id.as_str()));
+        let total = match cgroup.oom_kills().await {
+            Ok(Some(total)) => total,
+            Ok(None) => return false,
+            Err(e) => {
+                warn!("Failed to read memory.events of sandbox {}: {}", sandbox_id, e);
+                return false;
+            }
+        };
+        // The first observation only establishes a baseline
+        let Some(previous) = self.seen.insert(sandbox_id.clone(), total) else {
+            return false;
+        };
+        if total <= previous {
+            return false;
+        }
+
+        let kills = total - previous;
+        let victims = self.correlate(&sandbox_id).await;
+        warn!("OOM killer hit sandbox {} {} time(s), correlated processes: {:?}", sandbox_id, kills, victims);
+        metrics::record_oom_kills(kills);
+        self.process_manager
+            .record_oom(&sandbox_id, OomEvent { at: Utc::now(), kills, victims })
+            .await;
+        true
+    }
+
+    /// Processes of the sandbox reaped after a SIGKILL within the correlation window
+    /// and not yet attributed to an earlier OOM kill
+    async fn correlate(&self, sandbox_id: &str) -> Vec<OomVictim> {
+        correlate(self.process_manager.list_processes(sandbox_id).await.unwrap_or_default(), Utc::now())
+    }
+}
+
+fn correlate(processes: Vec<crate::process::ProcessInfo>, now: chrono::DateTime<Utc>) -> Vec<OomVictim> {
+    let cutoff = now - chrono::Duration::from_std(CORRELATION_WINDOW).unwrap_or_else(|_| chrono::Duration::zero());
+    let sigkill = ProcessExit::Signal("SIGKILL".to_string());
+    processes
+        .into_iter()
+        .filter(|p| p.state == ProcessState::Terminated && p.exit.as_ref() == Some(&sigkill))
+        .filter(|p| p.ended_at.is_some_and(|ended| ended >= cutoff))
+        .map(|p| OomVictim { pid: p.pid, start_time: p.start_time })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::process_info;
+
+    #[tokio::test]
+    async fn test_notified_kill_is_recorded() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("sbx")).unwrap();
+        let events = root.path().join("sbx/memory.events");
+        std::fs::write(&events, "oom 0\noom_kill 0\n").unwrap();
+        let manager = ProcessManager::new();
+        manager.add_process("sbx", process_info(7, "hog")).await.unwrap();
+
+        // Hours between polls, so only the notification can pick the kill up
+        let monitor = OomMonitor::new(manager.clone(), root.path().to_path_buf(), Duration::from_secs(3600)).spawn();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        manager.mark_exited(7, Some(ProcessExit::Signal("SIGKILL".to_string()))).await;
+        std::fs::write(&events, "oom 1\noom_kill 1\n").unwrap();
+
+        let recorded = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(event) = manager.oom_events("sbx").await.pop() {
+                    break event;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!((recorded.kills, recorded.pids()), (1, vec![7]));
+        monitor.abort();
+    }
+
+    #[test]
+    fn test_correlates_only_recent_sigkills() {
+        let now = Utc::now();
+        let exited = |pid, exit: ProcessExit, ago| crate::process::ProcessInfo {
+            state: ProcessState::Terminated,
+            exit: Some(exit),
+            ended_at: Some(now - chrono::Duration::seconds(ago)),
+            ..process_info(pid, "p")
+        };
+        let sigkill = || ProcessExit::Signal("SIGKILL".to_string());
+        let processes = vec![exited(1, sigkill(), 1), exited(2, sigkill(), 60), exited(3, ProcessExit::Code(0), 1), exited(4, ProcessExit::OomKilled, 1), process_info(5, "live")];
+        assert_eq!(correlate(processes, now).iter().map(|victim| victim.pid).collect::<Vec<_>>(), vec![1]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+pub type OperationId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Pause,
+    Resume,
+}
+
+/// An in-flight operation as shown to operators
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationInfo {
+    pub id: OperationId,
+    pub sandbox_id: String,
+    pub kind: OperationKind,
+    /// Step the operation is in, e.g. `queued`, `pre_pause_hooks` or `restore`
+    pub phase: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    /// Time since the operation entered its current phase
+    pub last_progress_ms: u64,
+    /// Time left until the operation's deadline; negative once it has passed
+    pub deadline_in_ms: Option<i64>,
+    /// Cancellation was requested and takes effect at the next checkpoint
+    pub cancelled: bool,
+}
+
+#[derive(Debug)]
+struct Entry {
+    sandbox_id: String,
+    kind: OperationKind,
+    phase: &'static str,
+    phase_started: Instant,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    deadline: Option<Instant>,
+    cancel: CancellationToken,
+}
+
+type Entries = Arc<Mutex<HashMap<OperationId, Entry>>>;
+
+/// Registry of in-flight pause and resume operations
+#[derive(Default)]
+pub struct OperationTracker {
+    next_id: AtomicU64,
+    entries: Entries,
+}
+
+/// Keeps an operation listed until dropped
+#[derive(Debug)]
+pub struct OperationGuard {
+    id: OperationId,
+    entries: Entries,
+}
+
+impl OperationGuard {
+    pub fn id(&self) -> OperationId {
+        self.id
+    }
+
+    /// Record the step the operation has reached
+    pub fn set_phase(&self, phase: &'static str) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(&self.id) {
+            entry.phase = phase;
+            entry.phase_started = Instant::now();
+        }
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        self.entries.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl OperationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List an operation until the returned guard is dropped; `cancel` is what
+    /// `cancel` fires for it
+    pub fn begin(&self, sandbox_id: &str, kind: OperationKind, cancel: CancellationToken, deadline: Option<Instant>) -> OperationGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let entry = Entry {
+            sandbox_id: sandbox_id.to_string(),
+            kind,
+            phase: "queued",
+            phase_started: Instant::now(),
+            started_at: Utc::now(),
+            started: Instant::now(),
+            deadline,
+            cancel,
+        };
+        self.entries.lock().unwrap().insert(id, entry);
+        OperationGuard {
+            id,
+            entries: Arc::clone(&self.entries),
+        }
+    }
+
+    /// Every in-flight operation, oldest first
+    pub fn list(&self) -> Vec<OperationInfo> {
+        describe(&self.entries.lock().unwrap())
+    }
+
+    /// Like `list`, but None instead of waiting when the tracker is locked
+    pub fn try_list(&self) -> Option<Vec<OperationInfo>> {
+        self.entries.try_lock().ok().map(|entries| describe(&entries))
+    }
+
+    /// Cancel an operation at its next checkpoint; false if it is not in flight
+    pub fn cancel(&self, id: OperationId) -> bool {
+        let entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get(&id) else {
+            return false;
+        };
+        info!("Cancelling {:?} of sandbox {} (operation {}) in phase {}", entry.kind, entry.sandbox_id, id, entry.phase);
+        entry.cancel.cancel();
+        true
+    }
+}
+
+/// Operators' view of the tracked entries, oldest first
+fn describe(entries: &HashMap<OperationId, Entry>) -> Vec<OperationInfo> {
+    let now = Instant::now();
+    let mut operations: Vec<OperationInfo> = entries
+        .iter()
+        .map(|(id, entry)| OperationInfo {
+            id: *id,
+            sandbox_id: entry.sandbox_id.clone(),
+            kind: entry.kind,
+            phase: entry.phase.to_string(),
+            started_at: entry.started_at,
+            elapsed_ms: now.duration_since(entry.started).as_millis() as u64,
+            last_progress_ms: now.duration_since(entry.phase_started).as_millis() as u64,
+            deadline_in_ms: entry.deadline.map(|deadline| {
+                if deadline >= now {
+                    deadline.duration_since(now).as_millis() as i64
+                } else {
+                    -(now.duration_since(deadline).as_millis() as i64)
+                }
+            }),
+            cancelled: entry.cancel.is_cancelled(),
+        })
+        .collect();
+    operations.sort_by_key(|operation| operation.id);
+    operations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_and_cancel() {
+        let tracker = OperationTracker::new();
+        let token = CancellationToken::new();
+        let pause = tracker.begin("a", OperationKind::Pause, token.clone(), None);
+        let resume = tracker.begin("b", OperationKind::Resume, CancellationToken::new(), None);
+        pause.set_phase("persist");
+
+        let listed = tracker.list();
+        assert_eq!(listed.iter().map(|op| (op.sandbox_id.as_str(), op.phase.as_str())).collect::<Vec<_>>(), vec![("a", "persist"), ("b", "queued")]);
+
+        assert!(tracker.cancel(pause.id()));
+        assert!(token.is_cancelled());
+        assert!(tracker.list()[0].cancelled);
+
+        drop(resume);
+        let id = pause.id();
+        drop(pause);
+        assert!(tracker.list().is_empty());
+        assert!(!tracker.cancel(id));
+    }
+}
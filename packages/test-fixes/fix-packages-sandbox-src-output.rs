@@ -0,0 +1,229 @@
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::{PauseResult, ResumeReport};
+use crate::config_lint::Diagnostic;
+use crate::cost_estimate::StrategyCost;
+use crate::operations::OperationInfo;
+use crate::process::ProcessInfo;
+use crate::state_export::SandboxStateView;
+use crate::template_anomaly::TemplateMetrics;
+
+/// How a command prints its result. `json` and `yaml` serialize the library type as-is,
+/// so their schema is that of the type; `table` is for people and may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    Json,
+    Yaml,
+    #[default]
+    Table,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "table" => Ok(OutputFormat::Table),
+            other => Err(format!("unknown output format '{}', expected json, yaml or table", other)),
+        }
+    }
+}
+
+/// A type that can be printed as rows of a table
+pub trait Tabular {
+    fn headers() -> &'static [&'static str];
+    fn row(&self) -> Vec<String>;
+}
+
+/// Render a list of results. Lists are serialized as arrays, even when empty.
+pub fn render<T: Serialize + Tabular>(items: &[T], format: OutputFormat) -> Result<String, Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(items)? + "\n"),
+        OutputFormat::Yaml => Ok(serde_yaml::to_string(items)?),
+        OutputFormat::Table => Ok(table(T::headers(), items.iter().map(Tabular::row).collect())),
+    }
+}
+
+/// Render a single result; as a table it is one row
+pub fn render_one<T: Serialize + Tabular>(item: &T, format: OutputFormat) -> Result<String, Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(item)? + "\n"),
+        OutputFormat::Yaml => Ok(serde_yaml::to_string(item)?),
+        OutputFormat::Table => Ok(table(T::headers(), vec![item.row()])),
+    }
+}
+
+/// Columns padded to their widest cell; the last column is not padded
+fn table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let last = row.len().saturating_sub(1);
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| if i == last { cell.clone() } else { format!("{:<width$}", cell, width = widths[i]) })
+            .collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// A value as it appears in JSON, without quotes, so enums read the same in every format
+fn cell<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(serde_json::Value::Null) => "-".to_string(),
+        Ok(other) => other.to_string(),
+        Err(_) => "?".to_string(),
+    }
+}
+
+impl Tabular for ProcessInfo {
+    fn headers() -> &'static [&'static str] {
+        &["PID", "STATE", "THREADS", "STARTED", "COMMAND"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.pid.to_string(), cell(&self.state), self.thread_count.to_string(), self.start_time.to_rfc3339(), self.cmd.clone()]
+    }
+}
+
+impl Tabular for PauseResult {
+    fn headers() -> &'static [&'static str] {
+        &["SANDBOX", "STRATEGY", "DEADLINE EXCEEDED", "REMAINING", "WARNINGS"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.sandbox_id.clone(),
+            self.strategy.as_ref().map(|decision| cell(&decision.strategy)).unwrap_or_else(|| "-".to_string()),
+            self.deadline_exceeded.to_string(),
+            self.remaining_pids.len().to_string(),
+            self.warnings.len().to_string(),
+        ]
+    }
+}
+
+impl Tabular for ResumeReport {
+    fn headers() -> &'static [&'static str] {
+        &["SANDBOX", "RESTORED", "NOT RESTORABLE", "LOCK CONFLICTS", "WARNINGS"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.sandbox_id.clone(),
+            self.restored.len().to_string(),
+            self.non_restorable.len().to_string(),
+            self.lock_conflicts.len().to_string(),
+            self.warnings.len().to_string(),
+        ]
+    }
+}
+
+impl Tabular for Diagnostic {
+    fn headers() -> &'static [&'static str] {
+        &["SEVERITY", "PATH", "MESSAGE"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![cell(&self.severity), self.path.clone(), self.message.clone()]
+    }
+}
+
+impl Tabular for SandboxStateView {
+    fn headers() -> &'static [&'static str] {
+        &["SANDBOX", "STATE", "PRIORITY", "PROCESSES", "RUNNING", "LAST SNAPSHOT"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.sandbox_id.clone(),
+            cell(&self.lifecycle),
+            cell(&self.priority),
+            self.process_count.to_string(),
+            self.running_count.to_string(),
+            self.last_snapshot_at.map(|at| at.to_rfc3339()).unwrap_or_else(|| "-".to_string()),
+        ]
+    }
+}
+
+impl Tabular for OperationInfo {
+    fn headers() -> &'static [&'static str] {
+        &["ID", "SANDBOX", "KIND", "PHASE", "ELAPSED MS", "DEADLINE IN MS"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.id.to_string(), self.sandbox_id.clone(), cell(&self.kind), self.phase.clone(), self.elapsed_ms.to_string(), cell(&self.deadline_in_ms)]
+    }
+}
+
+impl Tabular for StrategyCost {
+    fn headers() -> &'static [&'static str] {
+        &["STRATEGY", "PAUSE MS", "SNAPSHOT BYTES", "RESUME MS", "SAMPLES", "SOURCE"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![cell(&self.strategy), cell(&self.pause_ms), cell(&self.snapshot_bytes), cell(&self.resume_ms), self.samples.to_string(), cell(&self.source)]
+    }
+}
+
+impl Tabular for TemplateMetrics {
+    fn headers() -> &'static [&'static str] {
+        &["TEMPLATE", "PAUSES", "PAUSE MEDIAN MS", "RESUMES", "RESUME MEDIAN MS", "ANOMALOUS"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let anomalous: Vec<String> = self.anomalous.iter().map(cell).collect();
+        vec![
+            self.template.clone(),
+            self.pauses.to_string(),
+            cell(&self.pause_median_ms),
+            self.resumes.to_string(),
+            cell(&self.resume_median_ms),
+            if anomalous.is_empty() { "-".to_string() } else { anomalous.join(",") },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecycleState;
+    use crate::registry::PriorityClass;
+
+    #[test]
+    fn test_formats_share_field_names() {
+        let view = SandboxStateView {
+            sandbox_id: "sbx-1".to_string(),
+            lifecycle: LifecycleState::Paused,
+            priority: PriorityClass::default(),
+            process_count: 3,
+            running_count: 0,
+            last_snapshot_at: None,
+            active_pins: 0,
+            pause_pending: false,
+        };
+        let json: serde_json::Value = serde_json::from_str(&render(std::slice::from_ref(&view), OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[0]["lifecycle"], "paused");
+        let yaml: serde_json::Value = serde_yaml::from_str(&render_one(&view, OutputFormat::Yaml).unwrap()).unwrap();
+        assert_eq!(yaml["process_count"], 3);
+
+        let table = render(&[view], OutputFormat::Table).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("SANDBOX  STATE   PRIORITY"));
+        assert!(lines[1].starts_with("sbx-1    paused"));
+        assert!(lines[1].ends_with("0        -"));
+    }
+}
@@ -1,112 +1,1001 @@
+use std::collections::{BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use tokio::fs as async_fs;
 use log::{info, warn, error};
 use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use tokio_util::sync::CancellationToken;
 
+use crate::blocking;
+use crate::chaos::Chaos;
+use crate::error::{classify, ErrorCode, SandboxError};
+use crate::lifecycle::{LifecycleRecord, LifecycleState};
+use crate::metrics;
+use crate::object_store::ObjectStore;
+use crate::sandbox_id::{self, SandboxId};
 use crate::state_snapshot::StateSnapshot;
+use crate::store_migration::{MigrationDirection, StoreMigration};
+use crate::templates::{TemplateStore, TEMPLATES_DIR};
+use crate::throttle::Throttle;
+
+/// Label identifying this storage backend in metrics
+pub(crate) const BACKEND: &str = "local";
+
+/// File name of the current snapshot inside a sandbox's snapshot directory
+const SNAPSHOT_FILE: &str = "snapshot.json";
+/// Held in a sandbox's snapshot directory by the cleanup removing its snapshot
+const COLLECT_CLAIM_FILE: &str = "snapshot.json.collecting";
+/// A claim this old was left by a cleanup that died and is taken over
+const COLLECT_CLAIM_TIMEOUT: Duration = Duration::from_secs(600);
+
+tokio::task_local! {
+    static STORAGE_DEADLINE: Option<tokio::time::Instant>;
+}
+
+/// Run `fut` with every storage call it makes bounded by `deadline`; a call still
+/// running at the deadline fails with `SandboxError::StorageTimeout`. Nested scopes
+/// keep the earlier deadline.
+pub async fn with_storage_deadline<F: Future>(deadline: Option<tokio::time::Instant>, fut: F) -> F::Output {
+    let deadline = earliest(deadline, current_deadline());
+    STORAGE_DEADLINE.scope(deadline, fut).await
+}
+
+fn current_deadline() -> Option<tokio::time::Instant> {
+    STORAGE_DEADLINE.try_with(|deadline| *deadline).ok().flatten()
+}
+
+fn earliest(a: Option<tokio::time::Instant>, b: Option<tokio::time::Instant>) -> Option<tokio::time::Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// On-disk layout for a single sandbox: `<base>/<sandbox_id>/{snapshots,logs,wal,payloads}`
+#[derive(Debug, Clone)]
+pub struct SandboxLayout {
+    root: PathBuf,
+}
+
+impl SandboxLayout {
+    pub fn new(base_dir: &Path, sandbox_id: &str) -> Self {
+        Self {
+            root: base_dir.join(sandbox_id),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn snapshots_dir(&self) -> PathBuf {
+        self.root.join("snapshots")
+    }
+
+    pub fn logs_dir(&self) -> PathBuf {
+        self.root.join("logs")
+    }
+
+    pub fn wal_dir(&self) -> PathBuf {
+        self.root.join("wal")
+    }
+
+    pub fn payloads_dir(&self) -> PathBuf {
+        self.root.join("payloads")
+    }
+
+    pub fn snapshot_file(&self) -> PathBuf {
+        self.snapshots_dir().join(SNAPSHOT_FILE)
+    }
+
+    pub fn lifecycle_file(&self) -> PathBuf {
+        self.root.join("lifecycle.json")
+    }
+
+    /// Remove the layout's empty directories, then its root if nothing is left in it;
+    /// returns whether the root was removed
+    pub async fn prune(&self) -> bool {
+        for dir in [self.snapshots_dir(), self.logs_dir(), self.wal_dir(), self.payloads_dir()] {
+            // Fails on directories with something in them, which stay
+            let _ = async_fs::remove_dir(dir).await;
+        }
+        async_fs::remove_dir(&self.root).await.is_ok()
+    }
+
+    /// Create every directory of the layout
+    pub async fn create_dirs(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for dir in [self.snapshots_dir(), self.logs_dir(), self.wal_dir(), self.payloads_dir()] {
+            async_fs::create_dir_all(dir).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Marks a sandbox's snapshot as in use; garbage collection skips it until
+/// every pin for that sandbox has been dropped
+pub struct SnapshotPin {
+    sandbox_id: SandboxId,
+    pins: Arc<Mutex<HashMap<SandboxId, usize>>>,
+}
+
+impl Drop for SnapshotPin {
+    fn drop(&mut self) {
+        let mut pins = self.pins.lock().unwrap();
+        if let Some(count) = pins.get_mut(&self.sandbox_id) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.sandbox_id);
+            }
+        }
+    }
+}
+
+/// Result of looking up a sandbox's snapshot
+#[derive(Debug)]
+pub enum SnapshotLoad {
+    Loaded(StateSnapshot),
+    Missing,
+    /// The snapshot was too old to use and has been removed
+    Stale,
+}
+
+/// What a snapshot save does when the store's filesystem is full or read-only
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageFullPolicy {
+    /// On a full disk, purge quarantine and stale snapshots and retry once
+    pub emergency_cleanup: bool,
+    /// Store directory on another filesystem to save to when the primary cannot take the write
+    pub fallback_dir: Option<PathBuf>,
+}
 
 /// Manages persistence of sandbox state
 pub struct PersistenceManager {
     base_dir: PathBuf,
+    sandbox_bases: RwLock<HashMap<SandboxId, PathBuf>>, // sandbox_id -> custom base dir
+    pins: Arc<Mutex<HashMap<SandboxId, usize>>>, // sandbox_id -> in-flight operations
+    io_throttle: Option<Arc<Throttle>>,
+    templates: TemplateStore,
+    chaos: Option<Arc<Chaos>>,
+    storage_timeout: Option<Duration>,
+    store_lock: tokio::sync::RwLock<()>, // shared by storage calls, exclusive for backup/restore
+    migration: Option<StoreMigration>, // other store kept in sync while migrating
+    emergency_cleanup: bool,
+    secondary: Option<Box<PersistenceManager>>, // takes snapshot saves the primary cannot
+    read_only: AtomicBool, // another agent owns the store
 }
 
 impl PersistenceManager {
     pub fn new() -> Self {
+        Self::with_base_dir(PathBuf::from("/var/lib/e2b/snapshots"))
+    }
+
+    pub fn with_base_dir(base_dir: PathBuf) -> Self {
         Self {
-            base_dir: PathBuf::from("/var/lib/e2b/snapshots"),
+            templates: TemplateStore::new(base_dir.join(TEMPLATES_DIR)),
+            base_dir,
+            sandbox_bases: RwLock::new(HashMap::new()),
+            pins: Arc::new(Mutex::new(HashMap::new())),
+            io_throttle: None,
+            chaos: None,
+            storage_timeout: None,
+            store_lock: tokio::sync::RwLock::new(()),
+            migration: None,
+            emergency_cleanup: false,
+            secondary: None,
+            read_only: AtomicBool::new(false),
         }
     }
 
-    pub fn with_base_dir(base_dir: PathBuf) -> Self {
-        Self { base_dir }
+    /// Bound concurrent snapshot I/O by a load-driven throttle
+    pub fn with_io_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.io_throttle = Some(throttle);
+        self
+    }
+
+    /// Delay snapshot reads and writes at the configured chaos rate
+    pub fn with_chaos(mut self, chaos: Arc<Chaos>) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Bound every storage call by `timeout`, on top of any caller deadline
+    pub fn with_storage_timeout(mut self, timeout: Duration) -> Self {
+        self.storage_timeout = Some(timeout);
+        self
+    }
+
+    /// Run one storage call against the earlier of the scoped deadline and the
+    /// configured storage timeout
+    pub(crate) async fn bounded<T>(&self, op: &'static str, sandbox_id: &str, fut: impl Future<Output = Result<T, Box<dyn std::error::Error>>>) -> Result<T, Box<dyn std::error::Error>> {
+        // Every per-sandbox storage call lands here before touching the sandbox's paths
+        sandbox_id::check_path_safe(sandbox_id)?;
+        let timeout = self.storage_timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+        // Waiting out a backup counts against the deadline
+        let fut = async {
+            let _store = self.store_lock.read().await;
+            fut.await
+        };
+        let Some(deadline) = earliest(current_deadline(), timeout) else {
+            return fut.await;
+        };
+        match tokio::time::timeout_at(deadline, fut).await {
+            Ok(result) => result,
+            Err(_) => {
+                metrics::record_error(BACKEND, "timeout");
+                warn!("Storage {} for sandbox {} missed its deadline", op, sandbox_id);
+                Err(SandboxError::StorageTimeout(format!("{} of sandbox {}", op, sandbox_id)).into())
+            }
+        }
+    }
+
+    /// Migration mode: writes go to this store and to `source`, and reads that find
+    /// nothing here fall back to `source`. `migrate_store` backfills before cutover.
+    pub fn with_migration_source(mut self, source: PersistenceManager) -> Self {
+        self.migration = Some(StoreMigration::Local(Box::new(source)));
+        self
+    }
+
+    /// Migration mode against a bucket: snapshots and lifecycle records are written
+    /// to both stores, and reads try the new store first, falling back to the old one.
+    /// `direction` says which of the two is the new store.
+    pub fn with_remote_migration(mut self, store: Arc<dyn ObjectStore>, direction: MigrationDirection) -> Self {
+        self.migration = Some(StoreMigration::Remote { store, direction });
+        self
+    }
+
+    /// The other store, if in migration mode
+    pub fn migration(&self) -> Option<&StoreMigration> {
+        self.migration.as_ref()
+    }
+
+    /// Degrade snapshot saves on a full or read-only filesystem as `policy` says
+    pub fn with_storage_full_policy(mut self, policy: &StorageFullPolicy) -> Self {
+        self.emergency_cleanup = policy.emergency_cleanup;
+        self.secondary = policy.fallback_dir.clone().map(|dir| Box::new(PersistenceManager::with_base_dir(dir)));
+        self
+    }
+
+    /// The old store is only a rollback target, so failing to mirror a write to it
+    /// is reported but does not fail the operation
+    fn mirror_failed(&self, op: &str, sandbox_id: &str, e: Box<dyn std::error::Error>) {
+        metrics::record_error(BACKEND, "dual_write");
+        warn!("Failed to mirror {} of sandbox {} to the old store: {}", op, sandbox_id, e);
+    }
+
+    /// A write the bucket did not take fails the call while the bucket is the new
+    /// store, and is only reported while it is the old one
+    fn remote_mirrored(&self, op: &str, sandbox_id: &str, direction: MigrationDirection, result: Result<(), String>) -> Result<(), Box<dyn std::error::Error>> {
+        match (result, direction) {
+            (Ok(()), _) => Ok(()),
+            (Err(e), MigrationDirection::ToRemote) => {
+                metrics::record_error(BACKEND, "dual_write");
+                Err(format!("{} of sandbox {} failed in the new store: {}", op, sandbox_id, e).into())
+            }
+            (Err(e), MigrationDirection::FromRemote) => {
+                self.mirror_failed(op, sandbox_id, e.into());
+                Ok(())
+            }
+        }
+    }
+
+    /// Hold off every storage call until the guard is dropped
+    pub(crate) async fn lock_store(&self) -> tokio::sync::RwLockWriteGuard<'_, ()> {
+        self.store_lock.write().await
+    }
+
+    async fn inject_storage_delay(&self) {
+        if let Some(delay) = self.chaos.as_ref().and_then(|chaos| chaos.storage_delay()) {
+            warn!("chaos: delaying snapshot I/O by {}ms", delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Reusable template snapshots
+    pub fn templates(&self) -> &TemplateStore {
+        &self.templates
+    }
+
+    /// Protect a sandbox's snapshot from garbage collection for the lifetime
+    /// of the returned pin (in-flight resume, replication, export)
+    pub fn pin_snapshot(&self, sandbox_id: &str) -> SnapshotPin {
+        let sandbox_id = SandboxId::new(sandbox_id);
+        *self.pins.lock().unwrap().entry(sandbox_id.clone()).or_insert(0) += 1;
+        SnapshotPin {
+            sandbox_id,
+            pins: Arc::clone(&self.pins),
+        }
+    }
+
+    /// Whether any operation currently holds a pin on the sandbox's snapshot
+    pub fn is_pinned(&self, sandbox_id: &str) -> bool {
+        self.pins.lock().unwrap().contains_key(sandbox_id)
+    }
+
+    /// Number of operations currently holding a pin on the sandbox's snapshot
+    pub fn pin_count(&self, sandbox_id: &str) -> usize {
+        self.pins.lock().unwrap().get(sandbox_id).copied().unwrap_or(0)
+    }
+
+    /// Refuse every write from now on, for an agent that does not own the store
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Fail with `PermissionDenied` on a read-only store; every write entry point checks this
+    pub(crate) fn check_writable(&self, op: &str) -> Result<(), SandboxError> {
+        if self.is_read_only() {
+            return Err(SandboxError::PermissionDenied(format!("{} refused: {} is read-only", op, self.base_dir.display())));
+        }
+        Ok(())
+    }
+
+    /// `bounded` for calls that change the store
+    pub(crate) async fn bounded_write<T>(&self, op: &'static str, sandbox_id: &str, fut: impl Future<Output = Result<T, Box<dyn std::error::Error>>>) -> Result<T, Box<dyn std::error::Error>> {
+        self.check_writable(op)?;
+        self.bounded(op, sandbox_id, fut).await
+    }
+
+    /// Register a custom base directory for one sandbox
+    pub fn set_sandbox_base_dir(&self, sandbox_id: &str, base_dir: PathBuf) {
+        self.sandbox_bases.write().unwrap().insert(SandboxId::new(sandbox_id), base_dir);
+    }
+
+    /// Layout for a sandbox, honouring any registered custom base directory
+    pub fn layout(&self, sandbox_id: &str) -> SandboxLayout {
+        let bases = self.sandbox_bases.read().unwrap();
+        let base_dir = bases.get(sandbox_id).unwrap_or(&self.base_dir);
+        SandboxLayout::new(base_dir, sandbox_id)
+    }
+
+    /// IDs of every sandbox with a directory in the store, under the base dir or a
+    /// custom one
+    pub async fn stored_sandbox_ids(&self) -> Result<BTreeSet<String>, Box<dyn std::error::Error>> {
+        let mut sandbox_ids: BTreeSet<String> = self.list_sandbox_dirs().await?.into_iter().collect();
+        let custom: Vec<String> = self.sandbox_bases.read().unwrap().keys().map(|id| id.to_string()).collect();
+        for sandbox_id in custom {
+            if blocking::path_exists(self.layout(&sandbox_id).root()).await {
+                sandbox_ids.insert(sandbox_id);
+            }
+        }
+        Ok(sandbox_ids)
     }
 
     /// Save a state snapshot to disk
     pub async fn save_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), Box<dyn std::error::Error>> {
-        // Ensure directory exists
-        async_fs::create_dir_all(&self.base_dir).await?;
+        self.check_writable("snapshot save")?;
+        let started = Instant::now();
+        let write = async {
+            let bytes = self.write_snapshot(snapshot).await?;
+            self.mirror_snapshot(snapshot).await?;
+            Ok(bytes)
+        };
+        let problem = match self.bounded("snapshot save", &snapshot.sandbox_id, write).await {
+            Ok(bytes) => {
+                // Snapshots are stored uncompressed
+                metrics::record_save(BACKEND, bytes, bytes, started.elapsed());
+                return Ok(());
+            }
+            Err(e) => match classify(e.as_ref()) {
+                code @ (ErrorCode::StorageFull | ErrorCode::StorageReadOnly) => (code, e.to_string()),
+                _ => {
+                    metrics::record_error(BACKEND, "save");
+                    return Err(e);
+                }
+            },
+        };
+        self.save_degraded(snapshot, problem, started).await
+    }
+
+    /// The primary filesystem is full or read-only: clean up and retry, or save to the
+    /// secondary store, as configured; otherwise fail with a specific error
+    async fn save_degraded(&self, snapshot: &StateSnapshot, (code, detail): (ErrorCode, String), started: Instant) -> Result<(), Box<dyn std::error::Error>> {
+        let sandbox_id = &snapshot.sandbox_id;
+        metrics::record_error(BACKEND, code.as_str());
+        warn!("Snapshot save for sandbox {} failed, {}: {}", sandbox_id, code, detail);
+
+        if code == ErrorCode::StorageFull && self.emergency_cleanup {
+            self.emergency_cleanup().await;
+            match self.bounded("snapshot save", sandbox_id, self.write_snapshot(snapshot)).await {
+                Ok(bytes) => {
+                    info!("Saved snapshot for sandbox {} after emergency cleanup", sandbox_id);
+                    metrics::record_save(BACKEND, bytes, bytes, started.elapsed());
+                    return Ok(());
+                }
+                Err(e) => warn!("Snapshot save for sandbox {} still failing after emergency cleanup: {}", sandbox_id, e),
+            }
+        }
+
+        if let Some(secondary) = &self.secondary {
+            match secondary.write_snapshot(snapshot).await {
+                Ok(bytes) => {
+                    warn!("Saved snapshot for sandbox {} to fallback store {}", sandbox_id, secondary.get_base_dir().display());
+                    metrics::record_save(BACKEND, bytes, bytes, started.elapsed());
+                    return Ok(());
+                }
+                Err(e) => error!("Fallback snapshot save for sandbox {} failed: {}", sandbox_id, e),
+            }
+        }
+
+        let op = format!("snapshot save of sandbox {}: {}", sandbox_id, detail);
+        Err(match code {
+            ErrorCode::StorageFull => SandboxError::StorageFull(op),
+            _ => SandboxError::StorageReadOnly(op),
+        }
+        .into())
+    }
+
+    /// Free space by purging all of quarantine and every stale snapshot
+    async fn emergency_cleanup(&self) {
+        warn!("Snapshot store is full, running emergency cleanup");
+        match self.cleanup_quarantine(Duration::ZERO).await {
+            Ok(removed) => info!("Emergency cleanup purged {} quarantined sandboxes", removed),
+            Err(e) => error!("Emergency quarantine purge failed: {}", e),
+        }
+        if let Err(e) = self.cleanup_old_snapshots().await {
+            error!("Emergency snapshot cleanup failed: {}", e);
+        }
+    }
+
+    /// Write the snapshot atomically, returning the number of bytes written
+    async fn write_snapshot(&self, snapshot: &StateSnapshot) -> Result<usize, Box<dyn std::error::Error>> {
+        let _permit = match &self.io_throttle {
+            Some(throttle) => Some(throttle.acquire().await),
+            None => None,
+        };
+        self.inject_storage_delay().await;
+
+        // Ensure directories exist
+        let layout = self.layout(&snapshot.sandbox_id);
+        layout.create_dirs().await?;
         
-        let file_path = snapshot.get_snapshot_path(&self.base_dir);
+        let file_path = layout.snapshot_file();
         let json = snapshot.to_json()?;
+        let bytes = json.len();
+        let json = json.into_bytes();
         
         // Write atomically by writing to temp file then renaming
         let temp_path = file_path.with_extension("tmp");
-        async_fs::write(&temp_path, json).await?;
+        async_fs::write(&temp_path, &json).await?;
         
         // Atomic rename
         async_fs::rename(&temp_path, &file_path).await?;
+
+        // Written after the snapshot; until it is, the previous checksum names an older
+        // timestamp and is ignored
+        let checksum_path = checksum_file(&file_path);
+        let temp_path = checksum_path.with_extension("tmp");
+        async_fs::write(&temp_path, snapshot_checksum(&json, snapshot.timestamp)).await?;
+        async_fs::rename(&temp_path, &checksum_path).await?;
         
         info!("Saved state snapshot for sandbox {} to {}", snapshot.sandbox_id, file_path.display());
+        Ok(bytes)
+    }
+
+    /// Write the snapshot to the other store of a migration
+    async fn mirror_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), Box<dyn std::error::Error>> {
+        let sandbox_id = snapshot.sandbox_id.as_str();
+        match &self.migration {
+            None => {}
+            Some(StoreMigration::Local(source)) => {
+                if let Err(e) = source.write_snapshot(snapshot).await {
+                    self.mirror_failed("snapshot save", sandbox_id, e);
+                }
+            }
+            Some(StoreMigration::Remote { store, direction }) => {
+                let file_path = self.layout(sandbox_id).snapshot_file();
+                let json = snapshot.to_json()?.into_bytes();
+                let checksum = snapshot_checksum(&json, snapshot.timestamp);
+                // Checksum last, as on disk
+                let put = async {
+                    store.put(&self.object_key(sandbox_id, &file_path), json).await?;
+                    store.put(&self.object_key(sandbox_id, &checksum_file(&file_path)), checksum.into_bytes()).await
+                };
+                self.remote_mirrored("snapshot save", sandbox_id, *direction, put.await)?;
+            }
+        }
         Ok(())
     }
 
+    /// Key of a store file in a bucket: its path under the sandbox's base dir
+    pub(crate) fn object_key(&self, sandbox_id: &str, path: &Path) -> String {
+        let layout = self.layout(sandbox_id);
+        let base = layout.root().parent().unwrap_or(layout.root());
+        let relative = path.strip_prefix(base).unwrap_or(path);
+        relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+    }
+
     /// Load a state snapshot from disk
     pub async fn load_snapshot(&self, sandbox_id: &str) -> Result<Option<StateSnapshot>, Box<dyn std::error::Error>> {
-        let file_path = self.base_dir.join(format!("{}.snapshot.json", sandbox_id));
+        match self.load_snapshot_detailed(sandbox_id).await? {
+            SnapshotLoad::Loaded(snapshot) => Ok(Some(snapshot)),
+            SnapshotLoad::Missing | SnapshotLoad::Stale => Ok(None),
+        }
+    }
+
+    /// Load a state snapshot, telling apart a missing snapshot from a stale one
+    pub async fn load_snapshot_detailed(&self, sandbox_id: &str) -> Result<SnapshotLoad, Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        let read = async {
+            let mut outcome = self.read_snapshot_migrating(sandbox_id).await;
+            // Saves the primary could not take went to the fallback store, so its copy may
+            // be the newer one
+            if let Some(secondary) = &self.secondary {
+                outcome = newer_copy(sandbox_id, outcome, secondary.read_snapshot(sandbox_id).await);
+            }
+            outcome
+        };
+        match self.bounded("snapshot load", sandbox_id, read).await {
+            Ok(outcome) => {
+                if let SnapshotLoad::Loaded(_) = outcome {
+                    metrics::record_load(BACKEND, started.elapsed());
+                }
+                Ok(outcome)
+            }
+            Err(e) => {
+                metrics::record_error(BACKEND, "load");
+                Err(e)
+            }
+        }
+    }
+
+    /// Read from the new store of a migration, falling back to the old one, which may
+    /// hold older copies
+    async fn read_snapshot_migrating(&self, sandbox_id: &str) -> Result<SnapshotLoad, Box<dyn std::error::Error>> {
+        match &self.migration {
+            None => self.read_snapshot(sandbox_id).await,
+            Some(StoreMigration::Local(source)) => match self.read_snapshot(sandbox_id).await {
+                Ok(SnapshotLoad::Missing) => source.read_snapshot(sandbox_id).await,
+                outcome => outcome,
+            },
+            Some(StoreMigration::Remote { store, direction: MigrationDirection::ToRemote }) => match self.read_remote_snapshot(store.as_ref(), sandbox_id).await {
+                Ok(SnapshotLoad::Missing) => self.read_snapshot(sandbox_id).await,
+                outcome => outcome,
+            },
+            Some(StoreMigration::Remote { store, direction: MigrationDirection::FromRemote }) => match self.read_snapshot(sandbox_id).await {
+                Ok(SnapshotLoad::Missing) => self.read_remote_snapshot(store.as_ref(), sandbox_id).await,
+                outcome => outcome,
+            },
+        }
+    }
+
+    async fn read_remote_snapshot(&self, store: &dyn ObjectStore, sandbox_id: &str) -> Result<SnapshotLoad, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).snapshot_file();
+        let Some(json) = store.get(&self.object_key(sandbox_id, &file_path)).await? else {
+            return Ok(SnapshotLoad::Missing);
+        };
+        let corrupted = |reason: String| SandboxError::CorruptedSnapshot {
+            sandbox_id: sandbox_id.to_string(),
+            reason,
+        };
+        let json = String::from_utf8(json).map_err(|e| corrupted(e.to_string()))?;
+        let snapshot = StateSnapshot::from_json(&json).map_err(|e| corrupted(e.to_string()))?;
+        if let Some(recorded) = store.get(&self.object_key(sandbox_id, &checksum_file(&file_path))).await? {
+            verify_checksum(sandbox_id, json.as_bytes(), &snapshot, &String::from_utf8_lossy(&recorded))?;
+        }
+        if snapshot.is_stale() {
+            if !self.is_read_only() {
+                warn!("Found stale snapshot for sandbox {} in {}, removing", sandbox_id, store.describe());
+                self.delete_remote_snapshot(store, sandbox_id).await?;
+            }
+            return Ok(SnapshotLoad::Stale);
+        }
+        info!("Loaded state snapshot for sandbox {} from {}", sandbox_id, store.describe());
+        Ok(SnapshotLoad::Loaded(snapshot))
+    }
+
+    async fn delete_remote_snapshot(&self, store: &dyn ObjectStore, sandbox_id: &str) -> Result<(), String> {
+        let file_path = self.layout(sandbox_id).snapshot_file();
+        store.delete(&self.object_key(sandbox_id, &file_path)).await?;
+        store.delete(&self.object_key(sandbox_id, &checksum_file(&file_path))).await
+    }
+
+    async fn read_snapshot(&self, sandbox_id: &str) -> Result<SnapshotLoad, Box<dyn std::error::Error>> {
+        let _permit = match &self.io_throttle {
+            Some(throttle) => Some(throttle.acquire().await),
+            None => None,
+        };
+        self.inject_storage_delay().await;
+        self.migrate_legacy_snapshot(sandbox_id).await?;
+        let file_path = self.layout(sandbox_id).snapshot_file();
         
-        if !file_path.exists() {
-            return Ok(None);
+        if !blocking::path_exists(&file_path).await {
+            return Ok(SnapshotLoad::Missing);
         }
         
         let json = async_fs::read_to_string(&file_path).await?;
-        let snapshot = StateSnapshot::from_json(&json)?;
+        let snapshot = StateSnapshot::from_json(&json).map_err(|e| SandboxError::CorruptedSnapshot {
+            sandbox_id: sandbox_id.to_string(),
+            reason: e.to_string(),
+        })?;
+        // Snapshots saved before checksums were kept have none
+        match async_fs::read_to_string(checksum_file(&file_path)).await {
+            Ok(recorded) => verify_checksum(sandbox_id, json.as_bytes(), &snapshot, &recorded)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
         
-        // Check if snapshot is stale
+        // Check if snapshot is stale; the owning agent removes it
         if snapshot.is_stale() {
-            warn!("Found stale snapshot for sandbox {}, removing", sandbox_id);
-            self.remove_snapshot(sandbox_id).await?;
-            return Ok(None);
+            if !self.is_read_only() {
+                warn!("Found stale snapshot for sandbox {}, removing", sandbox_id);
+                self.delete_snapshot_file(sandbox_id).await?;
+            }
+            return Ok(SnapshotLoad::Stale);
         }
         
         info!("Loaded state snapshot for sandbox {} from {}", sandbox_id, file_path.display());
-        Ok(Some(snapshot))
+        Ok(SnapshotLoad::Loaded(snapshot))
     }
 
     /// Remove a state snapshot
     pub async fn remove_snapshot(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let file_path = self.base_dir.join(format!("{}.snapshot.json", sandbox_id));
-        
-        if file_path.exists() {
+        self.bounded_write("snapshot removal", sandbox_id, async {
+            self.delete_snapshot_file(sandbox_id).await?;
+            match &self.migration {
+                None => {}
+                Some(StoreMigration::Local(source)) => {
+                    if let Err(e) = source.delete_snapshot_file(sandbox_id).await {
+                        self.mirror_failed("snapshot removal", sandbox_id, e);
+                    }
+                }
+                Some(StoreMigration::Remote { store, direction }) => {
+                    let deleted = self.delete_remote_snapshot(store.as_ref(), sandbox_id).await;
+                    self.remote_mirrored("snapshot removal", sandbox_id, *direction, deleted)?;
+                }
+            }
+            if let Some(secondary) = &self.secondary {
+                secondary.delete_snapshot_file(sandbox_id).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    // Not bounded itself so it can run inside another storage call
+    async fn delete_snapshot_file(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).snapshot_file();
+        if blocking::path_exists(&file_path).await {
             async_fs::remove_file(&file_path).await?;
             info!("Removed state snapshot for sandbox {}", sandbox_id);
         }
-        
+        let checksum_path = checksum_file(&file_path);
+        if blocking::path_exists(&checksum_path).await {
+            async_fs::remove_file(&checksum_path).await?;
+        }
+        Ok(())
+    }
+
+    /// Persist a sandbox's lifecycle state
+    pub async fn save_lifecycle(&self, sandbox_id: &str, state: LifecycleState) -> Result<(), Box<dyn std::error::Error>> {
+        let json = serde_json::to_string(&LifecycleRecord::new(state))?;
+        self.bounded_write("lifecycle save", sandbox_id, async {
+            self.write_lifecycle(sandbox_id, &json).await?;
+            match &self.migration {
+                None => {}
+                Some(StoreMigration::Local(source)) => {
+                    if let Err(e) = source.write_lifecycle(sandbox_id, &json).await {
+                        self.mirror_failed("lifecycle save", sandbox_id, e);
+                    }
+                }
+                Some(StoreMigration::Remote { store, direction }) => {
+                    let key = self.object_key(sandbox_id, &self.layout(sandbox_id).lifecycle_file());
+                    let put = store.put(&key, json.clone().into_bytes()).await;
+                    self.remote_mirrored("lifecycle save", sandbox_id, *direction, put)?;
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn write_lifecycle(&self, sandbox_id: &str, json: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let layout = self.layout(sandbox_id);
+        async_fs::create_dir_all(layout.root()).await?;
+        let file_path = layout.lifecycle_file();
+        let temp_path = file_path.with_extension("tmp");
+        async_fs::write(&temp_path, &json).await?;
+        async_fs::rename(&temp_path, &file_path).await?;
         Ok(())
     }
 
+    /// Load a sandbox's persisted lifecycle state, if any
+    pub async fn load_lifecycle(&self, sandbox_id: &str) -> Result<Option<LifecycleRecord>, Box<dyn std::error::Error>> {
+        self.bounded("lifecycle load", sandbox_id, async {
+            match &self.migration {
+                None => self.read_lifecycle(sandbox_id).await,
+                Some(StoreMigration::Local(source)) => match self.read_lifecycle(sandbox_id).await? {
+                    None => source.read_lifecycle(sandbox_id).await,
+                    record => Ok(record),
+                },
+                Some(StoreMigration::Remote { store, direction: MigrationDirection::ToRemote }) => match self.read_remote_lifecycle(store.as_ref(), sandbox_id).await? {
+                    None => self.read_lifecycle(sandbox_id).await,
+                    record => Ok(record),
+                },
+                Some(StoreMigration::Remote { store, direction: MigrationDirection::FromRemote }) => match self.read_lifecycle(sandbox_id).await? {
+                    None => self.read_remote_lifecycle(store.as_ref(), sandbox_id).await,
+                    record => Ok(record),
+                },
+            }
+        })
+        .await
+    }
+
+    async fn read_lifecycle(&self, sandbox_id: &str) -> Result<Option<LifecycleRecord>, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).lifecycle_file();
+        if !blocking::path_exists(&file_path).await {
+            return Ok(None);
+        }
+        let json = async_fs::read_to_string(&file_path).await?;
+        Ok(Some(serde_json::from_str(&json)?))
+    }
+
+    async fn read_remote_lifecycle(&self, store: &dyn ObjectStore, sandbox_id: &str) -> Result<Option<LifecycleRecord>, Box<dyn std::error::Error>> {
+        match store.get(&self.object_key(sandbox_id, &self.layout(sandbox_id).lifecycle_file())).await? {
+            Some(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Check whether a snapshot file exists for a sandbox
+    pub fn snapshot_exists(&self, sandbox_id: &str) -> bool {
+        self.layout(sandbox_id).snapshot_file().exists() || self.legacy_snapshot_path(sandbox_id).exists()
+    }
+
+    /// When the sandbox's snapshot was last written
+    pub fn snapshot_modified_at(&self, sandbox_id: &str) -> Option<DateTime<Utc>> {
+        let modified = fs::metadata(self.layout(sandbox_id).snapshot_file()).ok()?.modified().ok()?;
+        Some(DateTime::<Utc>::from(modified))
+    }
+
+    /// Path a snapshot had in the old flat layout
+    fn legacy_snapshot_path(&self, sandbox_id: &str) -> PathBuf {
+        self.base_dir.join(format!("{}.snapshot.json", sandbox_id))
+    }
+
+    /// Move a flat-layout snapshot for one sandbox into its per-sandbox directory
+    async fn migrate_legacy_snapshot(&self, sandbox_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let legacy = self.legacy_snapshot_path(sandbox_id);
+        if self.is_read_only() || !blocking::path_exists(&legacy).await {
+            return Ok(false);
+        }
+
+        let layout = self.layout(sandbox_id);
+        layout.create_dirs().await?;
+        if blocking::path_exists(&layout.snapshot_file()).await {
+            // The new layout wins; the flat file is an older leftover
+            warn!("Discarding legacy snapshot for sandbox {} superseded by {}", sandbox_id, layout.snapshot_file().display());
+            async_fs::remove_file(&legacy).await?;
+        } else {
+            async_fs::rename(&legacy, layout.snapshot_file()).await?;
+            info!("Migrated snapshot for sandbox {} to {}", sandbox_id, layout.root().display());
+        }
+        Ok(true)
+    }
+
+    /// Migrate every snapshot left in the flat layout; returns how many were moved
+    pub async fn migrate_flat_layout(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        self.check_writable("flat layout migration")?;
+        if !blocking::path_exists(&self.base_dir).await {
+            return Ok(0);
+        }
+
+        let mut migrated = 0;
+        let mut entries = async_fs::read_dir(&self.base_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(sandbox_id) = file_name.strip_suffix(".snapshot.json") {
+                if self.migrate_legacy_snapshot(sandbox_id).await? {
+                    migrated += 1;
+                }
+            }
+        }
+        Ok(migrated)
+    }
+
     /// Clean up old snapshots (older than 24 hours)
     pub async fn cleanup_old_snapshots(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut entries = async_fs::read_dir(&self.base_dir).await?;
+        self.cleanup_old_snapshots_with(&CancellationToken::new()).await
+    }
+
+    /// Like `cleanup_old_snapshots`, stopping between sandboxes once `cancel` fires.
+    /// Sandboxes under a custom base dir are covered too, and a sandbox directory left
+    /// with nothing in it is removed along with its custom base registration.
+    pub async fn cleanup_old_snapshots_with(&self, cancel: &CancellationToken) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable("snapshot cleanup")?;
+        let _store = self.store_lock.read().await;
+        let mut sandbox_ids: BTreeSet<String> = self.list_sandbox_dirs().await?.into_iter().collect();
+        sandbox_ids.extend(self.sandbox_bases.read().unwrap().keys().map(|id| id.to_string()));
         
-        while let Some(entry) = entries.next().await {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(json) = async_fs::read_to_string(&path).await {
-                    if let Ok(snapshot) = StateSnapshot::from_json(&json) {
-                        if snapshot.is_stale() {
-                            if let Err(e) = async_fs::remove_file(&path).await {
-                                error!("Failed to remove stale snapshot {}: {}", path.display(), e);
-                            } else {
-                                info!("Removed stale snapshot for sandbox {}", snapshot.sandbox_id);
-                            }
-                        }
-                    }
-                }
+        for sandbox_id in sandbox_ids {
+            if cancel.is_cancelled() {
+                info!("Snapshot cleanup cancelled");
+                break;
+            }
+            let layout = self.layout(&sandbox_id);
+            let collect = {
+                let (layout, sandbox_id, pins) = (layout.clone(), sandbox_id.clone(), Arc::clone(&self.pins));
+                move || collect_stale_snapshot(&layout, &sandbox_id, &pins)
+            };
+            match blocking::run("snapshot_collect", collect).await {
+                Ok(Collection::Removed) => info!("Removed stale snapshot for sandbox {}", sandbox_id),
+                Ok(Collection::InUse) => info!("Skipping stale snapshot for sandbox {}: in use", sandbox_id),
+                Ok(Collection::Claimed) => info!("Skipping stale snapshot for sandbox {}: another cleanup is removing it", sandbox_id),
+                Ok(Collection::Kept) => {}
+                Err(e) => error!("Failed to collect the snapshot of sandbox {}: {}", sandbox_id, e),
+            }
+            // A live sandbox always has its lifecycle record, so only a removed one empties out
+            if !self.is_pinned(&sandbox_id) && layout.prune().await {
+                self.sandbox_bases.write().unwrap().remove(sandbox_id.as_str());
+                info!("Removed the empty directory of sandbox {}", sandbox_id);
             }
         }
         
         Ok(())
     }
 
+    /// Delete what a removed sandbox leaves besides its snapshot and payloads, which
+    /// retention takes; the directory goes once they are gone too
+    pub async fn remove_sandbox_files(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.bounded_write("sandbox removal", sandbox_id, async {
+            let layout = self.layout(sandbox_id);
+            match async_fs::remove_file(layout.lifecycle_file()).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+            for dir in [layout.logs_dir(), layout.wal_dir()] {
+                match async_fs::remove_dir_all(&dir).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            if layout.prune().await {
+                self.sandbox_bases.write().unwrap().remove(sandbox_id);
+            }
+            Ok(())
+        })
+        .await
+    }
+
     /// Get the base directory for snapshots
     pub fn get_base_dir(&self) -> &Path {
         &self.base_dir
     }
 }
 
+/// What cleanup did with one sandbox's snapshot
+enum Collection {
+    Removed,
+    /// Stale, but pinned by an operation
+    InUse,
+    /// Stale, but another cleanup holds the claim on it
+    Claimed,
+    /// Missing, unreadable or not stale
+    Kept,
+}
+
+/// Claim on removing a sandbox's snapshot, released on drop
+struct CollectClaim(PathBuf);
+
+impl CollectClaim {
+    /// Create the claim file with O_EXCL, so of cleanups racing for one snapshot
+    /// exactly one gets it; None if another holds it
+    fn take(snapshots_dir: &Path) -> std::io::Result<Option<Self>> {
+        let path = snapshots_dir.join(COLLECT_CLAIM_FILE);
+        for _ in 0..2 {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Some(Self(path))),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(&path).and_then(|meta| meta.modified()).map(|modified| modified.elapsed().unwrap_or_default());
+                    match age {
+                        Ok(age) if age > COLLECT_CLAIM_TIMEOUT => {
+                            warn!("Taking over abandoned cleanup claim {}", path.display());
+                            let _ = fs::remove_file(&path);
+                        }
+                        // Released in between; try again
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        _ => return Ok(None),
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Drop for CollectClaim {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Remove the snapshot of `sandbox_id` if it is stale and unpinned. Blocking.
+fn collect_stale_snapshot(layout: &SandboxLayout, sandbox_id: &str, pins: &Mutex<HashMap<SandboxId, usize>>) -> std::io::Result<Collection> {
+    let path = layout.snapshot_file();
+    // Opened directly: a snapshot removed after an existence check would be an error
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Collection::Kept),
+        Err(e) => return Err(e),
+    };
+    if !StateSnapshot::from_json(&json).is_ok_and(|snapshot| snapshot.is_stale()) {
+        return Ok(Collection::Kept);
+    }
+    let Some(_claim) = CollectClaim::take(&layout.snapshots_dir())? else {
+        return Ok(Collection::Claimed);
+    };
+    // Held across the removal, so no operation pins the snapshot between the check and the unlink
+    let pins = pins.lock().unwrap();
+    if pins.contains_key(sandbox_id) {
+        return Ok(Collection::InUse);
+    }
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        // Taken by a cleanup that finished just before our claim
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Collection::Kept),
+        Err(e) => return Err(e),
+    }
+    let _ = fs::remove_file(checksum_file(&path));
+    Ok(Collection::Removed)
+}
+
+/// Checksum kept next to a snapshot file
+fn checksum_file(snapshot_file: &Path) -> PathBuf {
+    snapshot_file.with_extension("sha256")
+}
+
+/// SHA-256 of a snapshot file, followed by the timestamp of the snapshot it covers
+fn snapshot_checksum(json: &[u8], timestamp: DateTime<Utc>) -> String {
+    format!("{:x} {}\n", Sha256::digest(json), timestamp.to_rfc3339())
+}
+
+/// A checksum of another snapshot is left from an agent that stopped between writing
+/// a snapshot and its checksum, and says nothing about this one
+fn verify_checksum(sandbox_id: &str, json: &[u8], snapshot: &StateSnapshot, recorded: &str) -> Result<(), SandboxError> {
+    let mut fields = recorded.split_whitespace();
+    let (Some(digest), Some(covers)) = (fields.next(), fields.next().and_then(|covers| DateTime::parse_from_rfc3339(covers).ok())) else {
+        warn!("Ignoring unreadable snapshot checksum of sandbox {}", sandbox_id);
+        return Ok(());
+    };
+    if covers != snapshot.timestamp {
+        warn!("Snapshot checksum of sandbox {} is for the snapshot of {}, not {}; ignoring it", sandbox_id, covers, snapshot.timestamp);
+        return Ok(());
+    }
+    if format!("{:x}", Sha256::digest(json)) != digest {
+        return Err(SandboxError::CorruptedSnapshot {
+            sandbox_id: sandbox_id.to_string(),
+            reason: "checksum mismatch".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// The newer of the primary and fallback copies of a snapshot. A copy that failed to
+/// read, e.g. on a checksum mismatch, loses to one that loaded.
+fn newer_copy(sandbox_id: &str, primary: Result<SnapshotLoad, Box<dyn std::error::Error>>, fallback: Result<SnapshotLoad, Box<dyn std::error::Error>>) -> Result<SnapshotLoad, Box<dyn std::error::Error>> {
+    match (primary, fallback) {
+        (Ok(SnapshotLoad::Loaded(primary)), Ok(SnapshotLoad::Loaded(fallback))) => Ok(SnapshotLoad::Loaded(if fallback.timestamp > primary.timestamp { fallback } else { primary })),
+        (Ok(SnapshotLoad::Loaded(snapshot)), Err(e)) | (Err(e), Ok(SnapshotLoad::Loaded(snapshot))) => {
+            warn!("Using the one readable copy of the snapshot of sandbox {}, the other failed: {}", sandbox_id, e);
+            Ok(SnapshotLoad::Loaded(snapshot))
+        }
+        (Ok(SnapshotLoad::Missing | SnapshotLoad::Stale), Ok(SnapshotLoad::Loaded(snapshot))) => Ok(SnapshotLoad::Loaded(snapshot)),
+        (Ok(SnapshotLoad::Missing), fallback) => fallback,
+        (primary, _) => primary,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -119,13 +1008,7 @@ mod tests {
         let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
         
         let mut snapshot = StateSnapshot::new("test-sandbox".to_string());
-        let process = crate::state_snapshot::PersistedProcess {
-            pid: 1234,
-            name: "test-process".to_string(),
-            cmd: "test-command".to_string(),
-            start_time: Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap(),
-            state: "running".to_string(),
-        };
+        let process = crate::test_support::persisted_process(1234, &["test-command"], Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap());
         snapshot.add_process(process);
         
         // Save snapshot
@@ -140,4 +1023,135 @@ mod tests {
         manager.remove_snapshot("test-sandbox").await.unwrap();
         assert!(manager.load_snapshot("test-sandbox").await.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn test_read_only_store_refuses_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        manager.save_snapshot(&StateSnapshot::new("owned")).await.unwrap();
+
+        manager.set_read_only(true);
+        let refused = manager.save_snapshot(&StateSnapshot::new("other")).await.unwrap_err();
+        assert_eq!(classify(refused.as_ref()), ErrorCode::PermissionDenied);
+        assert!(manager.save_lifecycle("owned", LifecycleState::Paused).await.is_err());
+        assert!(manager.remove_snapshot("owned").await.is_err());
+        assert!(manager.save_restore_checkpoint("owned", &Default::default()).await.is_err());
+        assert!(manager.cleanup_old_snapshots().await.is_err());
+        // Reads still work
+        assert!(manager.load_snapshot("owned").await.unwrap().is_some());
+        assert!(manager.load_snapshot("other").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_skips_pinned_snapshots() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+
+        let mut snapshot = StateSnapshot::new("busy-sandbox".to_string());
+        snapshot.timestamp = Utc::now() - chrono::Duration::hours(25);
+        manager.save_snapshot(&snapshot).await.unwrap();
+
+        let pin = manager.pin_snapshot("busy-sandbox");
+        manager.cleanup_old_snapshots().await.unwrap();
+        assert!(manager.snapshot_exists("busy-sandbox"));
+
+        // Another cleanup is mid-removal
+        drop(pin);
+        let claim = CollectClaim::take(&manager.layout("busy-sandbox").snapshots_dir()).unwrap().unwrap();
+        assert!(CollectClaim::take(&manager.layout("busy-sandbox").snapshots_dir()).unwrap().is_none());
+        manager.cleanup_old_snapshots().await.unwrap();
+        assert!(manager.snapshot_exists("busy-sandbox"));
+
+        drop(claim);
+        manager.cleanup_old_snapshots().await.unwrap();
+        assert!(!manager.snapshot_exists("busy-sandbox"));
+        // Nothing else was left, so the directory went with the snapshot
+        assert!(!manager.layout("busy-sandbox").root().exists());
+    }
+
+    #[tokio::test]
+    async fn test_removed_sandbox_directories_are_collected() {
+        let temp_dir = TempDir::new().unwrap();
+        let custom = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        manager.set_sandbox_base_dir("gone", custom.path().to_path_buf());
+        for id in ["gone", "live"] {
+            let mut snapshot = StateSnapshot::new(id.to_string());
+            snapshot.timestamp = Utc::now() - chrono::Duration::hours(25);
+            manager.save_snapshot(&snapshot).await.unwrap();
+            manager.save_lifecycle(id, LifecycleState::Paused).await.unwrap();
+        }
+        let gone = manager.layout("gone");
+        fs::write(gone.logs_dir().join("agent.log"), "bye").unwrap();
+
+        manager.remove_sandbox_files("gone").await.unwrap();
+        assert!(!gone.lifecycle_file().exists());
+        assert!(!gone.logs_dir().exists());
+        assert!(gone.snapshot_file().exists());
+
+        // Retention takes both stale snapshots; only the live sandbox keeps its directory
+        manager.cleanup_old_snapshots().await.unwrap();
+        assert!(!gone.root().exists());
+        assert_eq!(manager.layout("gone").root(), temp_dir.path().join("gone"));
+        assert!(manager.layout("live").lifecycle_file().exists());
+        assert!(!manager.layout("live").snapshots_dir().exists());
+    }
+
+    #[tokio::test]
+    async fn test_flat_layout_migration() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+
+        let snapshot = StateSnapshot::new("legacy-sandbox".to_string());
+        let legacy_path = snapshot.get_snapshot_path(&temp_dir.path().to_path_buf());
+        fs::write(&legacy_path, snapshot.to_json().unwrap()).unwrap();
+
+        assert_eq!(manager.migrate_flat_layout().await.unwrap(), 1);
+        assert!(!legacy_path.exists());
+        assert!(manager.layout("legacy-sandbox").snapshot_file().exists());
+        assert!(manager.load_snapshot("legacy-sandbox").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_storage_deadline_times_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        let snapshot = StateSnapshot::new("slow-sandbox".to_string());
+
+        let passed = tokio::time::Instant::now();
+        let err = with_storage_deadline(Some(passed), manager.save_snapshot(&snapshot)).await.unwrap_err();
+        assert_eq!(crate::error::classify(err.as_ref()), crate::error::ErrorCode::StorageTimeout);
+
+        // The earlier deadline wins when scopes nest
+        let later = tokio::time::Instant::now() + Duration::from_secs(60);
+        let nested = with_storage_deadline(Some(passed), with_storage_deadline(Some(later), manager.load_snapshot("slow-sandbox"))).await;
+        assert!(nested.is_err());
+
+        with_storage_deadline(Some(later), manager.save_snapshot(&snapshot)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_newer_fallback_copy_wins_and_checksums_are_verified() {
+        let primary_dir = TempDir::new().unwrap();
+        let fallback_dir = TempDir::new().unwrap();
+        let policy = StorageFullPolicy { fallback_dir: Some(fallback_dir.path().to_path_buf()), ..Default::default() };
+        let manager = PersistenceManager::with_base_dir(primary_dir.path().to_path_buf()).with_storage_full_policy(&policy);
+        let fallback = PersistenceManager::with_base_dir(fallback_dir.path().to_path_buf());
+
+        let mut old = StateSnapshot::new("sbx");
+        old.timestamp = Utc::now() - chrono::Duration::hours(1);
+        manager.save_snapshot(&old).await.unwrap();
+        // A later save that only the fallback store took
+        let mut new = StateSnapshot::new("sbx");
+        new.add_process(crate::test_support::persisted_process(7, &["worker"], Utc::now()));
+        fallback.save_snapshot(&new).await.unwrap();
+        assert_eq!(manager.load_snapshot("sbx").await.unwrap().unwrap().processes.len(), 1);
+
+        // A damaged fallback copy gives way to the intact primary one
+        let path = fallback.layout("sbx").snapshot_file();
+        let damaged = fs::read_to_string(&path).unwrap().replace("worker", "wørker");
+        fs::write(&path, damaged).unwrap();
+        assert!(fallback.load_snapshot("sbx").await.is_err());
+        assert!(manager.load_snapshot("sbx").await.unwrap().unwrap().processes.is_empty());
+    }
 }
\ No newline at end of file
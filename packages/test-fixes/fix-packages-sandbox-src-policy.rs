@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+
+use crate::backend::GroupSignal;
+use crate::process::ProcessInfo;
+use crate::selector::Selector;
+
+/// What a pause does to a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Signal, wait out the grace period, then force-kill
+    Kill,
+    /// Leave it running untouched
+    Spare,
+    /// Stop it from being scheduled and record it in the snapshot
+    Freeze,
+    /// Leave it running and record it in the snapshot
+    Persist,
+}
+
+/// A declarative policy rule: processes matching all of its conditions get its settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Shown in logs when the rule decides a process
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Labels a process must carry, all with equal values
+    #[serde(default)]
+    pub match_labels: BTreeMap<String, String>,
+    /// Name, state, label or age terms, e.g. `postgres*,age>1h`
+    #[serde(default)]
+    pub selector: Option<Selector>,
+    #[serde(default)]
+    pub action: Option<PolicyAction>,
+    /// Grace period before a killed process is forced
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Signal that asks the process to shut down (default: terminate)
+    #[serde(default)]
+    pub signal: Option<GroupSignal>,
+}
+
+impl PolicyRule {
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        self.match_labels
+            .iter()
+            .all(|(key, value)| process.labels.get(key) == Some(value))
+            && self.selector.as_ref().map_or(true, |selector| selector.matches(process))
+    }
+}
+
+impl From<&ProcessRule> for PolicyRule {
+    fn from(rule: &ProcessRule) -> Self {
+        Self {
+            name: None,
+            match_labels: rule.match_labels.clone(),
+            selector: rule.selector.clone(),
+            action: rule.never_kill.then_some(PolicyAction::Spare),
+            timeout_secs: rule.graceful_timeout_secs,
+            signal: None,
+        }
+    }
+}
+
+/// The settings the rules resolve to for one process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyVerdict {
+    /// None when no rule sets an action; the pause strategy decides
+    pub action: Option<PolicyAction>,
+    pub graceful_timeout: Duration,
+    pub signal: GroupSignal,
+    /// Name of the rule that set the action
+    pub rule: Option<String>,
+}
+
+/// Evaluates policy rules in order. Each setting (action, timeout, signal) comes
+/// from the first matching rule that sets it, so general rules can follow specific ones.
+#[derive(Debug, Clone, Default)]
+pub struct PolicyEngine {
+    rules: Vec<PolicyRule>,
+}
+
+impl PolicyEngine {
+    pub fn new(rules: Vec<PolicyRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Engine over `rules` followed by the older per-process rules. Protecting
+    /// legacy rules go first, as any of them used to win regardless of order.
+    pub fn from_config(rules: &[PolicyRule], legacy: &[ProcessRule]) -> Self {
+        let mut all = rules.to_vec();
+        all.extend(legacy.iter().filter(|rule| rule.never_kill).map(PolicyRule::from));
+        all.extend(legacy.iter().filter(|rule| !rule.never_kill).map(PolicyRule::from));
+        Self::new(all)
+    }
+
+    pub fn rules(&self) -> &[PolicyRule] {
+        &self.rules
+    }
+
+    pub fn evaluate(&self, process: &ProcessInfo, default_timeout: Duration) -> PolicyVerdict {
+        let matching: Vec<&PolicyRule> = self.rules.iter().filter(|rule| rule.matches(process)).collect();
+        let deciding = matching.iter().find(|rule| rule.action.is_some());
+        PolicyVerdict {
+            action: deciding.and_then(|rule| rule.action),
+            graceful_timeout: matching
+                .iter()
+                .find_map(|rule| rule.timeout_secs)
+                .map(Duration::from_secs)
+                .unwrap_or(default_timeout),
+            signal: matching.iter().find_map(|rule| rule.signal).unwrap_or(GroupSignal::Terminate),
+            rule: deciding.and_then(|rule| rule.name.clone()),
+        }
+    }
+}
+
+/// Per-process pause behaviour for processes whose labels match.
+/// Superseded by [`PolicyRule`]; these are evaluated by the same engine.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessRule {
+    /// Labels a process must carry, all with equal values, for the rule to apply
+    #[serde(default)]
+    pub match_labels: BTreeMap<String, String>,
+    /// Further restrict the rule by name, state, label or age, e.g. `postgres*,age>1h`
+    #[serde(default)]
+    pub selector: Option<Selector>,
+    /// Never signal matching processes on pause. Signals go to process groups,
+    /// so a protected process must lead its own group to be fully spared.
+    #[serde(default)]
+    pub never_kill: bool,
+    /// Grace period before a matching process is force-killed
+    #[serde(default)]
+    pub graceful_timeout_secs: Option<u64>,
+}
+
+/// What the rules decide for one process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessDecision {
+    pub never_kill: bool,
+    pub graceful_timeout: Duration,
+}
+
+/// Resolve the rules for `process`. Any matching `never_kill` rule protects it;
+/// the first matching rule with a timeout sets its grace period.
+pub fn decide(rules: &[ProcessRule], process: &ProcessInfo, default_timeout: Duration) -> ProcessDecision {
+    let verdict = PolicyEngine::from_config(&[], rules).evaluate(process, default_timeout);
+    ProcessDecision {
+        never_kill: verdict.action == Some(PolicyAction::Spare),
+        graceful_timeout: verdict.graceful_timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn labelled(labels: &[(&str, &str)]) -> ProcessInfo {
+        ProcessInfo {
+            labels: test_support::labels(labels),
+            ..test_support::process_info(1, "svc")
+        }
+    }
+
+    #[test]
+    fn test_rules_match_on_labels() {
+        let rules = vec![
+            ProcessRule {
+                match_labels: BTreeMap::from([("service".to_string(), "db".to_string())]),
+                graceful_timeout_secs: Some(120),
+                ..ProcessRule::default()
+            },
+            ProcessRule {
+                match_labels: BTreeMap::from([("restart".to_string(), "never".to_string())]),
+                never_kill: true,
+                ..ProcessRule::default()
+            },
+        ];
+        let default = Duration::from_secs(30);
+
+        let db = decide(&rules, &labelled(&[("service", "db")]), default);
+        assert_eq!(db, ProcessDecision { never_kill: false, graceful_timeout: Duration::from_secs(120) });
+
+        let pinned = decide(&rules, &labelled(&[("service", "web"), ("restart", "never")]), default);
+        assert!(pinned.never_kill);
+        assert_eq!(pinned.graceful_timeout, default);
+    }
+
+    #[test]
+    fn test_engine_takes_each_setting_from_first_matching_rule() {
+        let engine = PolicyEngine::new(vec![
+            PolicyRule {
+                name: Some("freeze-workers".to_string()),
+                match_labels: BTreeMap::from([("role".to_string(), "worker".to_string())]),
+                action: Some(PolicyAction::Freeze),
+                ..PolicyRule::default()
+            },
+            PolicyRule {
+                match_labels: BTreeMap::from([("role".to_string(), "worker".to_string())]),
+                action: Some(PolicyAction::Kill),
+                signal: Some(GroupSignal::Interrupt),
+                ..PolicyRule::default()
+            },
+            PolicyRule {
+                timeout_secs: Some(5),
+                ..PolicyRule::default()
+            },
+        ]);
+        let default = Duration::from_secs(30);
+
+        let worker = engine.evaluate(&labelled(&[("role", "worker")]), default);
+        assert_eq!(worker.action, Some(PolicyAction::Freeze));
+        assert_eq!(worker.rule.as_deref(), Some("freeze-workers"));
+        assert_eq!(worker.signal, GroupSignal::Interrupt);
+        assert_eq!(worker.graceful_timeout, Duration::from_secs(5));
+
+        let other = engine.evaluate(&labelled(&[]), default);
+        assert_eq!(other.action, None);
+        assert_eq!(other.signal, GroupSignal::Terminate);
+    }
+}
@@ -0,0 +1,321 @@
+use std::time::{Duration, Instant};
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::time::{sleep, timeout};
+
+use crate::process;
+
+/// What a probe checks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ProbeKind {
+    /// Succeeds when the command exits with status 0
+    Exec { command: Vec<String> },
+    /// Succeeds when a TCP connection can be opened
+    Tcp { host: String, port: u16 },
+    /// Succeeds on a 2xx/3xx response
+    Http { url: String },
+    /// Succeeds when the gRPC health service reports SERVING; runs `grpc_health_probe`
+    Grpc {
+        address: String,
+        #[serde(default)]
+        service: Option<String>,
+    },
+}
+
+/// A probe with Kubernetes-style timing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeConfig {
+    #[serde(flatten)]
+    pub kind: ProbeKind,
+    /// Wait before the first check (default: 0)
+    #[serde(default)]
+    pub initial_delay_secs: u64,
+    /// Time between checks (default: 10)
+    #[serde(default = "default_period_secs")]
+    pub period_secs: u64,
+    /// Time allowed for one check (default: 1)
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Consecutive successes to become healthy (default: 1)
+    #[serde(default = "default_success_threshold")]
+    pub success_threshold: u32,
+    /// Consecutive failures to become unhealthy (default: 3)
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Up to this much is added to each period so probes do not fire in lockstep (default: 0)
+    #[serde(default)]
+    pub jitter_ms: u64,
+}
+
+fn default_period_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    1
+}
+
+fn default_success_threshold() -> u32 {
+    1
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+impl ProbeConfig {
+    pub fn new(kind: ProbeKind) -> Self {
+        Self {
+            kind,
+            initial_delay_secs: 0,
+            period_secs: default_period_secs(),
+            timeout_secs: default_timeout_secs(),
+            success_threshold: default_success_threshold(),
+            failure_threshold: default_failure_threshold(),
+            jitter_ms: 0,
+        }
+    }
+
+    /// Longest a probe can take to reach a verdict, assuming no jitter
+    pub fn settle_time(&self) -> Duration {
+        let checks = self.success_threshold.max(self.failure_threshold).max(1) as u64;
+        Duration::from_secs(self.initial_delay_secs + checks * (self.period_secs + self.timeout_secs.max(1)))
+    }
+
+    /// The period plus a pseudo-random share of the jitter
+    fn next_period(&self) -> Duration {
+        let period = Duration::from_secs(self.period_secs);
+        if self.jitter_ms == 0 {
+            return period;
+        }
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u64)
+            .unwrap_or(0);
+        period + Duration::from_millis(nanos % self.jitter_ms)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeStatus {
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+/// The result of a single check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeOutcome {
+    pub success: bool,
+    pub message: String,
+    pub duration_ms: u64,
+}
+
+/// Verdict of a probe run to completion
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeReport {
+    pub kind: ProbeKind,
+    pub status: ProbeStatus,
+    pub last: ProbeOutcome,
+}
+
+/// Applies success/failure thresholds to a series of outcomes
+#[derive(Debug, Clone)]
+pub struct ProbeTracker {
+    success_threshold: u32,
+    failure_threshold: u32,
+    successes: u32,
+    failures: u32,
+    status: ProbeStatus,
+}
+
+impl ProbeTracker {
+    pub fn new(config: &ProbeConfig) -> Self {
+        Self {
+            success_threshold: config.success_threshold.max(1),
+            failure_threshold: config.failure_threshold.max(1),
+            successes: 0,
+            failures: 0,
+            status: ProbeStatus::Unknown,
+        }
+    }
+
+    pub fn status(&self) -> ProbeStatus {
+        self.status
+    }
+
+    /// Record one outcome; returns the new status when it changed
+    pub fn record(&mut self, success: bool) -> Option<ProbeStatus> {
+        if success {
+            self.successes += 1;
+            self.failures = 0;
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+        }
+
+        let next = if self.successes >= self.success_threshold {
+            ProbeStatus::Healthy
+        } else if self.failures >= self.failure_threshold {
+            ProbeStatus::Unhealthy
+        } else {
+            self.status
+        };
+        if next == self.status {
+            return None;
+        }
+        self.status = next;
+        Some(next)
+    }
+}
+
+pub struct Probe {
+    config: ProbeConfig,
+    client: reqwest::Client,
+}
+
+impl Probe {
+    pub fn new(config: ProbeConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ProbeConfig {
+        &self.config
+    }
+
+    /// Run one check, bounded by the probe timeout
+    pub async fn check_once(&self) -> ProbeOutcome {
+        let started = Instant::now();
+        let limit = Duration::from_secs(self.config.timeout_secs.max(1));
+        let result = match timeout(limit, self.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("timed out after {}s", limit.as_secs())),
+        };
+        let (success, message) = match result {
+            Ok(message) => (true, message),
+            Err(message) => (false, message),
+        };
+        ProbeOutcome {
+            success,
+            message,
+            duration_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+
+    async fn check(&self) -> Result<String, String> {
+        match &self.config.kind {
+            ProbeKind::Exec { command } => {
+                let (program, args) = command.split_first().ok_or("empty exec probe command")?;
+                run_command(program, args).await
+            }
+            ProbeKind::Tcp { host, port } => TcpStream::connect((host.as_str(), *port))
+                .await
+                .map(|_| format!("connected to {}:{}", host, port))
+                .map_err(|e| e.to_string()),
+            ProbeKind::Http { url } => {
+                let response = self.client.get(url).send().await.map_err(|e| e.to_string())?;
+                let status = response.status();
+                if status.is_success() || status.is_redirection() {
+                    Ok(format!("HTTP {}", status.as_u16()))
+                } else {
+                    Err(format!("HTTP {}", status.as_u16()))
+                }
+            }
+            ProbeKind::Grpc { address, service } => {
+                let mut args = vec![format!("-addr={}", address)];
+                if let Some(service) = service {
+                    args.push(format!("-service={}", service));
+                }
+                run_command("grpc_health_probe", &args).await
+            }
+        }
+    }
+
+    /// Check until the probe turns healthy or unhealthy, or `deadline` passes
+    pub async fn wait_for_status(&self, deadline: Duration) -> (ProbeStatus, ProbeOutcome) {
+        let started = Instant::now();
+        let mut tracker = ProbeTracker::new(&self.config);
+        sleep(Duration::from_secs(self.config.initial_delay_secs)).await;
+        loop {
+            let outcome = self.check_once().await;
+            debug!("Probe {:?}: success={} {}", self.config.kind, outcome.success, outcome.message);
+            if tracker.record(outcome.success).is_some() {
+                return (tracker.status(), outcome);
+            }
+            let period = self.config.next_period();
+            if started.elapsed() + period > deadline {
+                warn!("Probe {:?} did not settle within {}s", self.config.kind, deadline.as_secs());
+                return (tracker.status(), outcome);
+            }
+            sleep(period).await;
+        }
+    }
+}
+
+/// Run every probe until it settles, concurrently
+pub async fn run_all(configs: &[ProbeConfig]) -> Vec<ProbeReport> {
+    let runs = configs.iter().map(|config| async move {
+        let probe = Probe::new(config.clone());
+        let (status, last) = probe.wait_for_status(config.settle_time()).await;
+        ProbeReport {
+            kind: config.kind.clone(),
+            status,
+            last,
+        }
+    });
+    futures::future::join_all(runs).await
+}
+
+async fn run_command<S: AsRef<std::ffi::OsStr>>(program: &str, args: &[S]) -> Result<String, String> {
+    let output = process::helper_output(Command::new(program).args(args).kill_on_drop(true))
+        .await
+        .map_err(|e| format!("{}: {}", program, e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(format!("{} exited with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_applies_thresholds() {
+        let mut config = ProbeConfig::new(ProbeKind::Tcp { host: "localhost".to_string(), port: 80 });
+        config.success_threshold = 2;
+        config.failure_threshold = 2;
+        let mut tracker = ProbeTracker::new(&config);
+
+        assert_eq!(tracker.record(true), None);
+        assert_eq!(tracker.record(true), Some(ProbeStatus::Healthy));
+        assert_eq!(tracker.record(false), None);
+        assert_eq!(tracker.record(true), None);
+        assert_eq!(tracker.record(false), None);
+        assert_eq!(tracker.record(false), Some(ProbeStatus::Unhealthy));
+    }
+
+    #[test]
+    fn test_probe_config_defaults() {
+        let config: ProbeConfig = serde_json::from_str(r#"{"type": "http", "url": "http://localhost:8080/healthz"}"#).unwrap();
+        assert_eq!(config.kind, ProbeKind::Http { url: "http://localhost:8080/healthz".to_string() });
+        assert_eq!(config.period_secs, 10);
+        assert_eq!(config.failure_threshold, 3);
+    }
+
+    #[tokio::test]
+    async fn test_exec_probe() {
+        let probe = Probe::new(ProbeConfig::new(ProbeKind::Exec { command: vec!["true".to_string()] }));
+        assert!(probe.check_once().await.success);
+        let probe = Probe::new(ProbeConfig::new(ProbeKind::Exec { command: vec!["false".to_string()] }));
+        assert!(!probe.check_once().await.success);
+    }
+}
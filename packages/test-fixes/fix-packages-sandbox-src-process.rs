@@ -1,37 +1,193 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::process::Stdio;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
-use log::{info, debug};
+use log::{info, debug, warn};
+
+use crate::events::{EventBus, EventPayload};
+use crate::metrics;
+use crate::sandbox_id::SandboxId;
+use crate::selector::Selector;
 
 /// Information about a running process
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessInfo {
     pub pid: i32,
     pub name: String,
+    /// Command line for display; arguments joined by spaces
     pub cmd: String,
+    /// Argument vector as passed to exec, when known
+    #[serde(default)]
+    pub argv: Vec<String>,
     pub start_time: DateTime<Utc>,
     pub state: ProcessState,
+    /// Live thread count, refreshed by the sampler
+    #[serde(default)]
+    pub thread_count: u32,
+    /// Number of direct children, refreshed by the sampler
+    #[serde(default)]
+    pub child_count: u32,
+    /// How the process ended, once terminated and reaped
+    #[serde(default)]
+    pub exit: Option<ProcessExit>,
+    /// When the process was seen to terminate
+    #[serde(default)]
+    pub ended_at: Option<DateTime<Utc>>,
+    /// User-defined metadata (service name, owner, restart policy, ...)
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Login session the process belongs to, once sampled
+    #[serde(default)]
+    pub session: Option<SessionRef>,
+}
+
+impl ProcessInfo {
+    /// The live entry for `pid`; a terminated entry is an earlier user of the PID
+    fn is_live(&self, pid: i32) -> bool {
+        self.pid == pid && self.state != ProcessState::Terminated
+    }
+}
+
+/// Identifies the login session (e.g. an ssh login) a process runs in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRef {
+    /// Session ID (the PID of the session leader)
+    pub session_id: i32,
+    /// Audit login UID; None for sessions not started by a login
+    pub login_uid: Option<u32>,
+    /// Controlling terminal device, e.g. /dev/pts/1
+    pub tty: Option<String>,
+}
+
+/// How a terminated process ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessExit {
+    /// Exited normally with this status code
+    Code(i32),
+    /// Killed by this signal (e.g. "SIGKILL")
+    Signal(String),
+    /// Killed by the kernel OOM killer
+    OomKilled,
 }
 
+/// OOM kills recorded per sandbox
+const MAX_OOM_EVENTS: usize = 32;
+
+/// A process by PID and start time, which tell it apart from a later one reusing the PID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OomVictim {
+    pub pid: i32,
+    pub start_time: DateTime<Utc>,
+}
+
+/// One or more OOM kills observed in a sandbox's cgroup
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OomEvent {
+    pub at: DateTime<Utc>,
+    /// Kills since the previous observation
+    pub kills: u64,
+    /// Processes that died by SIGKILL around the same time
+    #[serde(default)]
+    pub victims: Vec<OomVictim>,
+}
+
+impl OomEvent {
+    pub fn pids(&self) -> Vec<i32> {
+        self.victims.iter().map(|victim| victim.pid).collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProcessState {
     Running,
     Suspended,
     Terminated,
 }
 
+/// Bounds on how many processes are tracked per sandbox and for how long
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessLimits {
+    /// Tracked entries per sandbox; terminated entries are evicted least
+    /// recently updated first to make room (default: 4096)
+    pub max_per_sandbox: usize,
+    /// How long terminated processes stay queryable (default: 300)
+    pub terminated_retention_secs: u64,
+}
+
+impl Default for ProcessLimits {
+    fn default() -> Self {
+        Self {
+            max_per_sandbox: 4096,
+            terminated_retention_secs: 300,
+        }
+    }
+}
+
 /// Process manager for tracking sandbox processes
+///
+/// Clones share the same tracking map. Each sandbox's list is kept in
+/// least-recently-updated order, which drives eviction.
+#[derive(Clone)]
 pub struct ProcessManager {
-    processes: Arc<RwLock<HashMap<String, Vec<ProcessInfo>>>>, // sandbox_id -> processes
+    processes: Arc<RwLock<HashMap<SandboxId, Vec<ProcessInfo>>>>, // sandbox_id -> processes
+    oom_events: Arc<RwLock<HashMap<SandboxId, VecDeque<OomEvent>>>>, // sandbox_id -> most recent last
+    limits: ProcessLimits,
+    events: Option<EventBus>,
 }
 
 impl ProcessManager {
     pub fn new() -> Self {
+        Self::with_limits(ProcessLimits::default())
+    }
+
+    pub fn with_limits(limits: ProcessLimits) -> Self {
         Self {
             processes: Arc::new(RwLock::new(HashMap::new())),
+            oom_events: Arc::new(RwLock::new(HashMap::new())),
+            limits,
+            events: None,
+        }
+    }
+
+    /// Publish process exits and OOM kills as events
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Total number of tracked entries across all sandboxes
+    pub async fn tracked_count(&self) -> usize {
+        self.processes.read().await.values().map(Vec::len).sum()
+    }
+
+    /// Tracked entries per sandbox without waiting for the map; None while it is write-locked
+    pub fn try_process_counts(&self) -> Option<HashMap<SandboxId, usize>> {
+        let processes = self.processes.try_read().ok()?;
+        Some(processes.iter().map(|(sandbox_id, list)| (sandbox_id.clone(), list.len())).collect())
+    }
+
+    /// Evict terminated entries, oldest first, until the sandbox is within
+    /// its cap with `reserve` slots free. Returns how many were evicted.
+    fn evict_terminated(&self, sandbox_id: &str, sandbox_processes: &mut Vec<ProcessInfo>, reserve: usize) -> usize {
+        let mut evicted = 0;
+        while sandbox_processes.len() + reserve > self.limits.max_per_sandbox {
+            match sandbox_processes.iter().position(|p| p.state == ProcessState::Terminated) {
+                Some(idx) => {
+                    sandbox_processes.remove(idx);
+                    evicted += 1;
+                }
+                None => break,
+            }
         }
+        if evicted > 0 {
+            debug!("Evicted {} terminated processes from sandbox {}", evicted, sandbox_id);
+            metrics::record_process_evictions(evicted);
+        }
+        evicted
     }
 
     /// List all processes in a sandbox
@@ -40,17 +196,58 @@ impl ProcessManager {
         Ok(processes.get(sandbox_id).cloned().unwrap_or_default())
     }
 
+    /// List the processes in a sandbox that have not terminated
+    pub async fn list_live_processes(&self, sandbox_id: &str) -> Result<Vec<ProcessInfo>, Box<dyn std::error::Error>> {
+        let processes = self.processes.read().await;
+        Ok(processes
+            .get(sandbox_id)
+            .map(|sandbox_processes| {
+                sandbox_processes
+                    .iter()
+                    .filter(|p| p.state != ProcessState::Terminated)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Replace the contents of `out` with the PIDs of the sandbox's live processes.
+    /// Reusing `out` across calls keeps polling loops free of allocations.
+    pub async fn remaining_pids(&self, sandbox_id: &str, out: &mut Vec<i32>) {
+        out.clear();
+        let processes = self.processes.read().await;
+        if let Some(sandbox_processes) = processes.get(sandbox_id) {
+            out.extend(sandbox_processes.iter().filter(|p| p.state != ProcessState::Terminated).map(|p| p.pid));
+        }
+    }
+
+    /// List the processes in a sandbox matched by `selector`
+    pub async fn select(&self, sandbox_id: &str, selector: &Selector) -> Vec<ProcessInfo> {
+        let processes = self.processes.read().await;
+        processes
+            .get(sandbox_id)
+            .map(|sandbox_processes| sandbox_processes.iter().filter(|p| selector.matches(p)).cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Add a process to tracking
     pub async fn add_process(&self, sandbox_id: &str, process: ProcessInfo) -> Result<(), Box<dyn std::error::Error>> {
         let mut processes = self.processes.write().await;
-        let sandbox_processes = processes.entry(sandbox_id.to_string()).or_default();
+        let sandbox_processes = processes.entry(SandboxId::new(sandbox_id)).or_default();
         
-        // Check if process already exists
-        if !sandbox_processes.iter().any(|p| p.pid == process.pid) {
-            sandbox_processes.push(process);
+        // Check if process already exists; a terminated entry is an earlier user of the PID
+        if !sandbox_processes.iter().any(|p| p.is_live(process.pid)) {
+            self.evict_terminated(sandbox_id, sandbox_processes, 1);
+            if sandbox_processes.len() >= self.limits.max_per_sandbox {
+                warn!("Sandbox {} is tracking {} live processes, not tracking {}", sandbox_id, sandbox_processes.len(), process.pid);
+                metrics::record_process_rejected();
+                return Err(format!("Process tracking limit reached for sandbox {}", sandbox_id).into());
+            }
             debug!("Added process {} to sandbox {}", process.pid, sandbox_id);
+            sandbox_processes.push(process);
         }
         
+        metrics::record_tracked_processes(processes.values().map(Vec::len).sum());
         Ok(())
     }
 
@@ -58,19 +255,201 @@ impl ProcessManager {
     pub async fn remove_process(&self, sandbox_id: &str, pid: i32) -> Result<(), Box<dyn std::error::Error>> {
         let mut processes = self.processes.write().await;
         if let Some(sandbox_processes) = processes.get_mut(sandbox_id) {
-            sandbox_processes.retain(|p| p.pid != pid);
+            sandbox_processes.retain(|p| !p.is_live(pid));
             debug!("Removed process {} from sandbox {}", pid, sandbox_id);
         }
         
         Ok(())
     }
 
+    /// Stop tracking a process wherever it is, returning the sandbox it belonged to
+    pub async fn remove_pid(&self, pid: i32) -> Option<SandboxId> {
+        let mut processes = self.processes.write().await;
+        for (sandbox_id, sandbox_processes) in processes.iter_mut() {
+            if let Some(idx) = sandbox_processes.iter().position(|p| p.is_live(pid)) {
+                sandbox_processes.remove(idx);
+                debug!("Removed process {} from sandbox {}", pid, sandbox_id);
+                return Some(sandbox_id.clone());
+            }
+        }
+        None
+    }
+
+    /// Mark a tracked process as terminated wherever it is, keeping it for the
+    /// retention window. Returns the sandbox it belonged to.
+    pub async fn mark_exited(&self, pid: i32, exit: Option<ProcessExit>) -> Option<SandboxId> {
+        let mut processes = self.processes.write().await;
+        for (sandbox_id, sandbox_processes) in processes.iter_mut() {
+            if let Some(idx) = sandbox_processes.iter().position(|p| p.is_live(pid)) {
+                let mut process = sandbox_processes.remove(idx);
+                process.state = ProcessState::Terminated;
+                if let Some(events) = &self.events {
+                    events.publish(sandbox_id, EventPayload::ProcessExited { pid, exit: exit.clone() });
+                }
+                process.exit = exit;
+                process.ended_at = Some(Utc::now());
+                sandbox_processes.push(process);
+                debug!("Process {} in sandbox {} marked terminated", pid, sandbox_id);
+                return Some(sandbox_id.clone());
+            }
+        }
+        None
+    }
+
+    /// Terminated processes of a sandbox still inside the retention window, most recent last
+    pub async fn recently_terminated(&self, sandbox_id: &str) -> Vec<ProcessInfo> {
+        let cutoff = Utc::now() - self.retention();
+        let processes = self.processes.read().await;
+        processes
+            .get(sandbox_id)
+            .map(|sandbox_processes| {
+                sandbox_processes
+                    .iter()
+                    .filter(|p| p.state == ProcessState::Terminated && p.ended_at.map_or(false, |t| t >= cutoff))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drop terminated processes that ended before the retention window; returns how many were dropped
+    pub async fn prune_terminated(&self) -> usize {
+        let cutoff = Utc::now() - self.retention();
+        let mut processes = self.processes.write().await;
+        let mut pruned = 0;
+        for sandbox_processes in processes.values_mut() {
+            let before = sandbox_processes.len();
+            sandbox_processes.retain(|p| p.state != ProcessState::Terminated || p.ended_at.map_or(false, |t| t >= cutoff));
+            pruned += before - sandbox_processes.len();
+        }
+        if pruned > 0 {
+            debug!("Pruned {} terminated processes past retention", pruned);
+            metrics::record_tracked_processes(processes.values().map(Vec::len).sum());
+        }
+        pruned
+    }
+
+    fn retention(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.limits.terminated_retention_secs as i64)
+    }
+
+    /// Record the login session of a tracked process
+    pub async fn set_session(&self, sandbox_id: &str, pid: i32, session: SessionRef) {
+        let mut processes = self.processes.write().await;
+        if let Some(process) = processes
+            .get_mut(sandbox_id)
+            .and_then(|sandbox_processes| sandbox_processes.iter_mut().find(|p| p.is_live(pid)))
+        {
+            process.session = Some(session);
+        }
+    }
+
+    /// Set or replace one label on a tracked process; returns false if it is not tracked
+    pub async fn set_label(&self, sandbox_id: &str, pid: i32, key: &str, value: &str) -> bool {
+        let mut processes = self.processes.write().await;
+        match processes
+            .get_mut(sandbox_id)
+            .and_then(|sandbox_processes| sandbox_processes.iter_mut().find(|p| p.is_live(pid)))
+        {
+            Some(process) => {
+                process.labels.insert(key.to_string(), value.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a label from a tracked process, returning its previous value
+    pub async fn remove_label(&self, sandbox_id: &str, pid: i32, key: &str) -> Option<String> {
+        let mut processes = self.processes.write().await;
+        processes
+            .get_mut(sandbox_id)
+            .and_then(|sandbox_processes| sandbox_processes.iter_mut().find(|p| p.is_live(pid)))
+            .and_then(|process| process.labels.remove(key))
+    }
+
+    /// Record an OOM kill and mark the correlated processes as OOM-killed
+    pub async fn record_oom(&self, sandbox_id: &str, event: OomEvent) {
+        {
+            let mut processes = self.processes.write().await;
+            if let Some(sandbox_processes) = processes.get_mut(sandbox_id) {
+                let victim = |p: &ProcessInfo| event.victims.iter().any(|victim| victim.pid == p.pid && victim.start_time == p.start_time);
+                for process in sandbox_processes.iter_mut().filter(|p| p.state == ProcessState::Terminated && victim(p)) {
+                    process.exit = Some(ProcessExit::OomKilled);
+                }
+            }
+        }
+
+        if let Some(events) = &self.events {
+            events.publish(sandbox_id, EventPayload::OomKill(event.clone()));
+        }
+        let mut oom_events = self.oom_events.write().await;
+        let events = oom_events.entry(SandboxId::new(sandbox_id)).or_default();
+        if events.len() == MAX_OOM_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// OOM kills recorded for a sandbox, oldest first
+    pub async fn oom_events(&self, sandbox_id: &str) -> Vec<OomEvent> {
+        self.oom_events
+            .read()
+            .await
+            .get(sandbox_id)
+            .map(|events| events.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// PIDs of the live processes of every sandbox
+    pub async fn live_pids(&self) -> Vec<i32> {
+        let processes = self.processes.read().await;
+        processes
+            .values()
+            .flatten()
+            .filter(|p| p.state != ProcessState::Terminated)
+            .map(|p| p.pid)
+            .collect()
+    }
+
+    /// Find the sandbox a tracked process belongs to
+    pub async fn find_sandbox(&self, pid: i32) -> Option<SandboxId> {
+        let processes = self.processes.read().await;
+        processes
+            .iter()
+            .find(|(_, sandbox_processes)| sandbox_processes.iter().any(|p| p.is_live(pid)))
+            .map(|(sandbox_id, _)| sandbox_id.clone())
+    }
+
+    /// IDs of all sandboxes with tracked processes
+    pub async fn sandbox_ids(&self) -> Vec<SandboxId> {
+        self.processes.read().await.keys().cloned().collect()
+    }
+
+    /// Record freshly sampled thread and child counts for a process
+    pub async fn update_process_counts(&self, sandbox_id: &str, pid: i32, thread_count: u32, child_count: u32) {
+        let mut processes = self.processes.write().await;
+        if let Some(process) = processes
+            .get_mut(sandbox_id)
+            .and_then(|sandbox_processes| sandbox_processes.iter_mut().find(|p| p.is_live(pid)))
+        {
+            process.thread_count = thread_count;
+            process.child_count = child_count;
+        }
+    }
+
     /// Update process state
     pub async fn update_process_state(&self, sandbox_id: &str, pid: i32, state: ProcessState) -> Result<(), Box<dyn std::error::Error>> {
         let mut processes = self.processes.write().await;
         if let Some(sandbox_processes) = processes.get_mut(sandbox_id) {
-            if let Some(process) = sandbox_processes.iter_mut().find(|p| p.pid == pid) {
+            if let Some(idx) = sandbox_processes.iter().position(|p| p.is_live(pid)) {
+                // Move to the back so eviction sees it as most recently updated
+                let mut process = sandbox_processes.remove(idx);
+                if state == ProcessState::Terminated && process.state != ProcessState::Terminated {
+                    process.ended_at = Some(Utc::now());
+                }
                 process.state = state;
+                sandbox_processes.push(process);
                 debug!("Updated process {} state to {:?}", pid, state);
             }
         }
@@ -81,26 +460,40 @@ impl ProcessManager {
     /// Restore processes from persisted state
     pub async fn restore_processes(&self, sandbox_id: &str, persisted: Vec<crate::state_snapshot::PersistedProcess>) -> Result<(), Box<dyn std::error::Error>> {
         let mut processes = self.processes.write().await;
-        let sandbox_processes = processes.entry(sandbox_id.to_string()).or_default();
+        let sandbox_processes = processes.entry(SandboxId::new(sandbox_id)).or_default();
         
         // Clear existing processes
         sandbox_processes.clear();
         
         // Restore from persisted state
         for persisted_proc in persisted {
+            let state = match persisted_proc.state.as_str() {
+                "running" => ProcessState::Running,
+                "suspended" => ProcessState::Suspended,
+                _ => ProcessState::Terminated,
+            };
+            // Snapshots from before end times were recorded start the retention window now
+            let ended_at = match state {
+                ProcessState::Terminated => persisted_proc.ended_at.or_else(|| Some(Utc::now())),
+                _ => None,
+            };
             let process_info = ProcessInfo {
                 pid: persisted_proc.pid,
                 name: persisted_proc.name,
                 cmd: persisted_proc.cmd,
+                argv: persisted_proc.argv,
                 start_time: persisted_proc.start_time,
-                state: match persisted_proc.state.as_str() {
-                    "running" => ProcessState::Running,
-                    "suspended" => ProcessState::Suspended,
-                    _ => ProcessState::Terminated,
-                },
+                state,
+                thread_count: persisted_proc.thread_count,
+                child_count: persisted_proc.child_count,
+                exit: persisted_proc.exit,
+                ended_at,
+                labels: persisted_proc.labels,
+                session: persisted_proc.session,
             };
             sandbox_processes.push(process_info);
         }
+        self.evict_terminated(sandbox_id, sandbox_processes, 0);
         
         info!("Restored {} processes for sandbox {}", sandbox_processes.len(), sandbox_id);
         Ok(())
@@ -110,7 +503,168 @@ impl ProcessManager {
     pub async fn clear_sandbox(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
         let mut processes = self.processes.write().await;
         processes.remove(sandbox_id);
+        metrics::record_tracked_processes(processes.values().map(Vec::len).sum());
+        self.oom_events.write().await.remove(sandbox_id);
         info!("Cleared all processes for sandbox {}", sandbox_id);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// PIDs of commands the agent runs for itself (hooks, probes, tools), from spawn
+/// until they are waited for
+static HELPERS: std::sync::Mutex<BTreeSet<i32>> = std::sync::Mutex::new(BTreeSet::new());
+
+/// A registered helper command, unregistered on drop
+pub struct HelperPid(Option<i32>);
+
+impl Drop for HelperPid {
+    fn drop(&mut self) {
+        if let Some(pid) = self.0 {
+            HELPERS.lock().unwrap().remove(&pid);
+        }
+    }
+}
+
+/// Spawn a command the agent runs for itself. The spawn and the registration happen
+/// under one lock, which the reaper holds while it waits for unknown children, so the
+/// reaper never takes a helper's exit status from the code that spawned it.
+pub fn spawn_helper(command: &mut tokio::process::Command) -> std::io::Result<(tokio::process::Child, HelperPid)> {
+    let mut helpers = HELPERS.lock().unwrap();
+    let child = command.spawn()?;
+    let pid = child.id().map(|pid| pid as i32);
+    if let Some(pid) = pid {
+        helpers.insert(pid);
+    }
+    Ok((child, HelperPid(pid)))
+}
+
+/// `Command::output` for a helper command
+pub async fn helper_output(command: &mut tokio::process::Command) -> std::io::Result<std::process::Output> {
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let (child, _helper) = spawn_helper(command)?;
+    child.wait_with_output().await
+}
+
+/// `Command::status` for a helper command
+pub async fn helper_status(command: &mut tokio::process::Command) -> std::io::Result<std::process::ExitStatus> {
+    let (mut child, _helper) = spawn_helper(command)?;
+    child.wait().await
+}
+
+/// PIDs of the running helper commands; no helper is spawned while this is held
+pub fn helper_pids() -> std::sync::MutexGuard<'static, BTreeSet<i32>> {
+    HELPERS.lock().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn process(pid: i32) -> ProcessInfo {
+        ProcessInfo {
+            cmd: "sleep 1".to_string(),
+            ..test_support::process_info(pid, &format!("proc-{}", pid))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_terminated_entries_are_evicted_at_cap() {
+        let manager = ProcessManager::with_limits(ProcessLimits { max_per_sandbox: 3, ..ProcessLimits::default() });
+        for pid in 1..=3 {
+            manager.add_process("sbx", process(pid)).await.unwrap();
+        }
+        manager.update_process_state("sbx", 2, ProcessState::Terminated).await.unwrap();
+        manager.update_process_state("sbx", 1, ProcessState::Terminated).await.unwrap();
+
+        // Process 2 was terminated first, so it is the least recently updated
+        manager.add_process("sbx", process(4)).await.unwrap();
+        let pids: Vec<i32> = manager.list_processes("sbx").await.unwrap().iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![3, 1, 4]);
+
+        manager.add_process("sbx", process(5)).await.unwrap();
+        assert!(manager.add_process("sbx", process(6)).await.is_err());
+        assert_eq!(manager.tracked_count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_oom_kill_is_recorded_against_the_process_not_its_pid() {
+        let manager = ProcessManager::new();
+        let killed = process(7);
+        manager.add_process("sbx", killed.clone()).await.unwrap();
+        manager.mark_exited(7, Some(ProcessExit::Signal("SIGKILL".to_string()))).await;
+        // The PID is reused before the OOM kill is noticed
+        let reused = ProcessInfo { start_time: killed.start_time + chrono::Duration::seconds(1), ..process(7) };
+        manager.add_process("sbx", reused).await.unwrap();
+
+        let victims = vec![OomVictim { pid: 7, start_time: killed.start_time }];
+        manager.record_oom("sbx", OomEvent { at: Utc::now(), kills: 1, victims }).await;
+        let exits: Vec<_> = manager.list_processes("sbx").await.unwrap().into_iter().map(|p| (p.state, p.exit)).collect();
+        assert_eq!(exits, vec![(ProcessState::Terminated, Some(ProcessExit::OomKilled)), (ProcessState::Running, None)]);
+
+        // The exit of the new process is not recorded against the old entry either
+        manager.mark_exited(7, Some(ProcessExit::Code(0))).await;
+        let exits: Vec<_> = manager.list_processes("sbx").await.unwrap().into_iter().map(|p| p.exit).collect();
+        assert_eq!(exits, vec![Some(ProcessExit::OomKilled), Some(ProcessExit::Code(0))]);
+    }
+
+    #[tokio::test]
+    async fn test_lookups_skip_the_terminated_user_of_a_reused_pid() {
+        let manager = ProcessManager::new();
+        manager.add_process("sbx", process(7)).await.unwrap();
+        manager.mark_exited(7, Some(ProcessExit::Code(0))).await;
+        manager.add_process("sbx", process(7)).await.unwrap();
+
+        manager.update_process_state("sbx", 7, ProcessState::Suspended).await.unwrap();
+        assert!(manager.set_label("sbx", 7, "service", "api").await);
+        manager.update_process_counts("sbx", 7, 4, 2).await;
+        let entries: Vec<_> = manager.list_processes("sbx").await.unwrap().into_iter().map(|p| (p.state, p.labels.len(), p.thread_count)).collect();
+        assert_eq!(entries, vec![(ProcessState::Terminated, 0, 1), (ProcessState::Suspended, 1, 4)]);
+        assert_eq!(manager.find_sandbox(7).await.as_deref(), Some("sbx"));
+
+        // Removing the live process keeps the record of the earlier one
+        manager.remove_process("sbx", 7).await.unwrap();
+        let states: Vec<_> = manager.list_processes("sbx").await.unwrap().into_iter().map(|p| p.state).collect();
+        assert_eq!(states, vec![ProcessState::Terminated]);
+        assert_eq!(manager.find_sandbox(7).await, None);
+        assert_eq!(manager.remove_pid(7).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_exited_processes_are_retained_for_window() {
+        let manager = ProcessManager::new();
+        manager.add_process("sbx", process(1)).await.unwrap();
+        manager.add_process("sbx", process(2)).await.unwrap();
+
+        assert_eq!(manager.mark_exited(2, Some(ProcessExit::Code(1))).await.as_deref(), Some("sbx"));
+        assert_eq!(manager.prune_terminated().await, 0);
+
+        let terminated = manager.recently_terminated("sbx").await;
+        assert_eq!(terminated.len(), 1);
+        assert_eq!(terminated[0].exit, Some(ProcessExit::Code(1)));
+        assert!(terminated[0].ended_at.is_some());
+
+        let expired = ProcessManager::with_limits(ProcessLimits { terminated_retention_secs: 0, ..ProcessLimits::default() });
+        expired.add_process("sbx", process(3)).await.unwrap();
+        expired.mark_exited(3, None).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        assert_eq!(expired.prune_terminated().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_remaining_pids_reuses_buffer() {
+        let manager = ProcessManager::new();
+        for pid in 1..=3 {
+            manager.add_process("sbx", process(pid)).await.unwrap();
+        }
+        manager.update_process_state("sbx", 2, ProcessState::Terminated).await.unwrap();
+
+        let mut remaining = vec![99; 8];
+        manager.remaining_pids("sbx", &mut remaining).await;
+        assert_eq!(remaining, vec![1, 3]);
+        assert!(remaining.capacity() >= 8);
+
+        manager.remaining_pids("other", &mut remaining).await;
+        assert!(remaining.is_empty());
+    }
+}
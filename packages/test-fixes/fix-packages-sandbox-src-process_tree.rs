@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+use crate::process::{ProcessInfo, ProcessState};
+use crate::state_snapshot::PersistedProcess;
+
+/// Output format of a rendered process tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeFormat {
+    /// Indented tree with box-drawing characters, like `pstree`
+    Ascii,
+    /// Graphviz digraph
+    Dot,
+}
+
+impl FromStr for TreeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" | "tree" => Ok(TreeFormat::Ascii),
+            "dot" => Ok(TreeFormat::Dot),
+            other => Err(format!("unknown tree format '{}', expected ascii or dot", other)),
+        }
+    }
+}
+
+/// One process of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    pub pid: i32,
+    /// Parent PID; processes whose parent is not in the tree are drawn as roots
+    pub ppid: Option<i32>,
+    pub name: String,
+    pub state: String,
+    pub threads: u32,
+    pub rss_bytes: Option<u64>,
+}
+
+impl TreeNode {
+    /// A tracked live process; parent and memory are read from /proc
+    pub fn live(process: &ProcessInfo) -> Self {
+        let running = process.state != ProcessState::Terminated;
+        Self {
+            pid: process.pid,
+            ppid: if running { parent_pid(process.pid) } else { None },
+            name: process.name.clone(),
+            state: format!("{:?}", process.state).to_lowercase(),
+            threads: process.thread_count,
+            rss_bytes: if running { rss_bytes(process.pid) } else { None },
+        }
+    }
+
+    /// A process as recorded in a snapshot; memory was not captured
+    pub fn persisted(process: &PersistedProcess) -> Self {
+        Self {
+            pid: process.pid,
+            ppid: process.ppid,
+            name: process.name.clone(),
+            state: process.state.clone(),
+            threads: process.thread_count,
+            rss_bytes: None,
+        }
+    }
+
+    fn label(&self) -> String {
+        let mut label = format!("{} ({}) {}, {} threads", self.name, self.pid, self.state, self.threads);
+        if let Some(rss) = self.rss_bytes {
+            let _ = write!(label, ", {} MiB", rss / (1024 * 1024));
+        }
+        label
+    }
+}
+
+/// Parent PID of a live process
+#[cfg(target_os = "linux")]
+pub fn parent_pid(pid: i32) -> Option<i32> {
+    crate::procfs::read_stat(pid).map(|stat| stat.ppid)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn parent_pid(_pid: i32) -> Option<i32> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn rss_bytes(pid: i32) -> Option<u64> {
+    crate::procfs::rss_bytes(pid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss_bytes(_pid: i32) -> Option<u64> {
+    None
+}
+
+/// Render the hierarchy of `nodes`, children ordered by PID
+pub fn render(nodes: &[TreeNode], format: TreeFormat) -> String {
+    let pids: HashSet<i32> = nodes.iter().map(|n| n.pid).collect();
+    let mut children: BTreeMap<Option<i32>, Vec<&TreeNode>> = BTreeMap::new();
+    for node in nodes {
+        let parent = node.ppid.filter(|ppid| pids.contains(ppid) && *ppid != node.pid);
+        children.entry(parent).or_default().push(node);
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by_key(|n| n.pid);
+    }
+    let mut out = String::new();
+    match format {
+        TreeFormat::Ascii => {
+            for root in children.get(&None).into_iter().flatten() {
+                let _ = writeln!(out, "{}", root.label());
+                ascii(&children, root.pid, "", &mut out);
+            }
+        }
+        TreeFormat::Dot => {
+            out.push_str("digraph processes {\n    node [shape=box];\n");
+            for node in nodes {
+                let _ = writeln!(out, "    p{} [label=\"{}\"];", node.pid, dot_escape(&node.label()));
+            }
+            for (parent, siblings) in &children {
+                if let Some(parent) = parent {
+                    for child in siblings {
+                        let _ = writeln!(out, "    p{} -> p{};", parent, child.pid);
+                    }
+                }
+            }
+            out.push_str("}\n");
+        }
+    }
+    out
+}
+
+/// Quote a label for a DOT string. Process names are chosen by whoever started the
+/// process, so a backslash or line break in one must not end the string or the statement.
+fn dot_escape(label: &str) -> String {
+    let mut escaped = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn ascii(children: &BTreeMap<Option<i32>, Vec<&TreeNode>>, pid: i32, prefix: &str, out: &mut String) {
+    let Some(siblings) = children.get(&Some(pid)) else {
+        return;
+    };
+    for (i, child) in siblings.iter().enumerate() {
+        let last = i + 1 == siblings.len();
+        let _ = writeln!(out, "{}{}{}", prefix, if last { "└── " } else { "├── " }, child.label());
+        ascii(children, child.pid, &format!("{}{}", prefix, if last { "    " } else { "│   " }), out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(pid: i32, ppid: i32, name: &str) -> TreeNode {
+        TreeNode {
+            pid,
+            ppid: Some(ppid),
+            name: name.to_string(),
+            state: "running".to_string(),
+            threads: 1,
+            rss_bytes: None,
+        }
+    }
+
+    #[test]
+    fn test_render_ascii_and_dot() {
+        let nodes = vec![node(30, 10, "worker"), node(10, 1, "supervisor"), node(20, 10, "web"), node(40, 20, "php-fpm")];
+        assert_eq!(
+            render(&nodes, TreeFormat::Ascii),
+            "supervisor (10) running, 1 threads\n\
+             ├── web (20) running, 1 threads\n\
+             │   └── php-fpm (40) running, 1 threads\n\
+             └── worker (30) running, 1 threads\n"
+        );
+        let dot = render(&nodes, TreeFormat::Dot);
+        assert!(dot.contains("    p10 -> p20;\n    p10 -> p30;\n    p20 -> p40;\n"));
+    }
+
+    #[test]
+    fn test_dot_labels_are_escaped() {
+        let nodes = vec![node(10, 1, "evil\\\"];\np1 -> p2")];
+        let dot = render(&nodes, TreeFormat::Dot);
+        assert!(dot.contains("    p10 [label=\"evil\\\\\\\"];\\np1 -> p2 (10) running, 1 threads\"];\n"));
+        assert_eq!(dot.lines().count(), 4);
+    }
+}
@@ -0,0 +1,329 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use chrono::{DateTime, Utc};
+
+use crate::state_snapshot::{FdSummary, FileLock};
+
+/// Cap on regular-file paths recorded per process
+const MAX_NOTABLE_PATHS: usize = 16;
+
+/// Fields of /proc/<pid>/stat used by the agent
+#[derive(Debug, Clone)]
+pub struct ProcStat {
+    pub comm: String,
+    pub ppid: i32,
+    pub pgid: i32,
+    pub session: i32,
+    /// Controlling terminal as an encoded device number; 0 if none
+    pub tty_nr: i32,
+    pub num_threads: u32,
+    /// Start time in clock ticks since boot; with the PID it identifies a process across PID reuse
+    pub start_ticks: u64,
+}
+
+/// Read and parse /proc/<pid>/stat
+pub fn read_stat(pid: i32) -> Option<ProcStat> {
+    parse_stat(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+/// Parse `pid (comm) state ppid pgrp ...`; comm may itself contain spaces and parens
+pub fn parse_stat(stat: &str) -> Option<ProcStat> {
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let comm = stat[open + 1..close].to_string();
+    let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+
+    // Indices are relative to the field after comm (state)
+    Some(ProcStat {
+        comm,
+        ppid: fields.get(1)?.parse().ok()?,
+        pgid: fields.get(2)?.parse().ok()?,
+        session: fields.get(3)?.parse().ok()?,
+        tty_nr: fields.get(4)?.parse().ok()?,
+        num_threads: fields.get(17)?.parse().ok()?,
+        start_ticks: fields.get(19)?.parse().ok()?,
+    })
+}
+
+/// Wall-clock start time of a process, from its start ticks and the boot time in /proc/stat
+pub fn start_time(stat: &ProcStat) -> Option<DateTime<Utc>> {
+    let boot_secs: i64 = fs::read_to_string("/proc/stat").ok()?.lines().find_map(|line| line.strip_prefix("btime "))?.trim().parse().ok()?;
+    let ticks_per_sec = nix::unistd::sysconf(nix::unistd::SysconfVar::CLK_TCK).ok()??;
+    let since_boot_ms = stat.start_ticks as i64 * 1000 / ticks_per_sec as i64;
+    DateTime::from_timestamp_millis(boot_secs * 1000 + since_boot_ms)
+}
+
+/// Read the full command line of a process with arguments joined by spaces
+pub fn read_cmdline(pid: i32) -> Option<String> {
+    read_argv(pid).map(|argv| argv.join(" "))
+}
+
+/// Read the argument vector of a process as passed to exec
+pub fn read_argv(pid: i32) -> Option<Vec<String>> {
+    let raw = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    Some(parse_argv(&raw))
+}
+
+/// Kernels before 4.2 return at most one page of /proc/<pid>/cmdline
+const CMDLINE_PAGE: usize = 4096;
+
+/// Whether the kernel cut /proc/<pid>/cmdline, so the argv read from it is incomplete
+pub fn cmdline_truncated(pid: i32) -> bool {
+    fs::read(format!("/proc/{}/cmdline", pid)).is_ok_and(|raw| is_cut(&raw))
+}
+
+/// A complete command line ends in NUL; one filling the whole page without it was cut
+fn is_cut(raw: &[u8]) -> bool {
+    raw.len() >= CMDLINE_PAGE && raw.last() != Some(&0)
+}
+
+fn parse_argv(raw: &[u8]) -> Vec<String> {
+    // Arguments are NUL-terminated; empty arguments in between are kept
+    let raw = raw.strip_suffix(b"\0").unwrap_or(raw);
+    if raw.is_empty() {
+        return Vec::new();
+    }
+    raw.split(|&b| b == 0).map(|arg| String::from_utf8_lossy(arg).into_owned()).collect()
+}
+
+/// Real UID of a process, from the `Uid:` line of /proc/<pid>/status
+pub fn read_uid(pid: i32) -> Option<u32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status.lines().find_map(|line| line.strip_prefix("Uid:"))?.split_whitespace().next()?.parse().ok()
+}
+
+/// PIDs in a cgroup v2 directory, from its cgroup.procs
+pub fn cgroup_pids(cgroup: &std::path::Path) -> Option<Vec<i32>> {
+    let procs = fs::read_to_string(cgroup.join("cgroup.procs")).ok()?;
+    Some(procs.lines().filter_map(|line| line.trim().parse().ok()).collect())
+}
+
+/// Audit login UID of a process; None if it was never set by a login
+pub fn read_loginuid(pid: i32) -> Option<u32> {
+    let uid: u32 = fs::read_to_string(format!("/proc/{}/loginuid", pid)).ok()?.trim().parse().ok()?;
+    (uid != u32::MAX).then_some(uid)
+}
+
+/// Device path of a pseudo-terminal from a stat `tty_nr`; other terminals are not resolved
+pub fn tty_path(tty_nr: i32) -> Option<String> {
+    let tty_nr = tty_nr as u32;
+    let major = (tty_nr >> 8) & 0xfff;
+    let minor = (tty_nr & 0xff) | ((tty_nr >> 12) & 0xfff00);
+    // Unix98 PTY slaves use majors 136-143
+    (136..=143)
+        .contains(&major)
+        .then(|| format!("/dev/pts/{}", (major - 136) * 256 + minor))
+}
+
+/// Resident memory of a process, from /proc/<pid>/statm
+pub fn rss_bytes(pid: i32) -> Option<u64> {
+    let statm = fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE).ok()??;
+    Some(pages * page_size as u64)
+}
+
+/// Whether a process has a GPU device (NVIDIA or DRM render node) open
+pub fn uses_gpu(pid: i32) -> bool {
+    let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+        return false;
+    };
+    fds.flatten().filter_map(|fd| fs::read_link(fd.path()).ok()).any(|target| {
+        let target = target.to_string_lossy();
+        target.starts_with("/dev/nvidia") || target.starts_with("/dev/dri/")
+    })
+}
+
+/// Count direct children of a process across all of its threads
+pub fn child_count(pid: i32) -> u32 {
+    let Ok(tasks) = fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return 0;
+    };
+
+    tasks
+        .flatten()
+        .filter_map(|task| fs::read_to_string(task.path().join("children")).ok())
+        .map(|children| children.split_whitespace().count() as u32)
+        .sum()
+}
+
+/// Map socket inode -> port for every TCP socket in LISTEN state
+pub fn listening_sockets() -> HashMap<u64, u16> {
+    let mut sockets = HashMap::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(contents) = fs::read_to_string(table) {
+            sockets.extend(contents.lines().skip(1).filter_map(parse_listening));
+        }
+    }
+    sockets
+}
+
+/// Parse one /proc/net/tcp row, returning (inode, port) if it is listening
+fn parse_listening(line: &str) -> Option<(u64, u16)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    // 0A is TCP_LISTEN
+    if fields.get(3)? != &"0A" {
+        return None;
+    }
+    let port = u16::from_str_radix(fields.get(1)?.rsplit(':').next()?, 16).ok()?;
+    let inode = fields.get(9)?.parse().ok()?;
+    Some((inode, port))
+}
+
+/// Summarize the open file descriptors of a process
+pub fn fd_summary(pid: i32, listening: &HashMap<u64, u16>) -> Option<FdSummary> {
+    let mut summary = FdSummary::default();
+
+    for fd in fs::read_dir(format!("/proc/{}/fd", pid)).ok()?.flatten() {
+        let Ok(target) = fs::read_link(fd.path()) else {
+            continue;
+        };
+        let target = target.to_string_lossy();
+
+        if let Some(inode) = target.strip_prefix("socket:[").and_then(|t| t.strip_suffix(']')) {
+            summary.sockets += 1;
+            if let Some(port) = inode.parse().ok().and_then(|inode: u64| listening.get(&inode)) {
+                summary.listening_ports.push(*port);
+            }
+        } else if target.starts_with("pipe:[") {
+            summary.pipes += 1;
+        } else if target.starts_with('/') {
+            summary.files += 1;
+            let notable = !target.starts_with("/dev/") && !target.starts_with("/proc/");
+            if notable && summary.notable_paths.len() < MAX_NOTABLE_PATHS {
+                summary.notable_paths.push(target.into_owned());
+            }
+        } else {
+            summary.other += 1;
+        }
+    }
+
+    summary.listening_ports.sort_unstable();
+    summary.listening_ports.dedup();
+    Some(summary)
+}
+
+/// A lock currently held, as listed in /proc/locks
+#[derive(Debug, Clone)]
+pub struct LockEntry {
+    pub pid: i32,
+    pub lock: FileLock,
+}
+
+/// Read every held (not waiting) lock on the host
+pub fn read_locks() -> Vec<LockEntry> {
+    fs::read_to_string("/proc/locks")
+        .map(|contents| contents.lines().filter_map(parse_lock_line).collect())
+        .unwrap_or_default()
+}
+
+/// Parse `1: POSIX  ADVISORY  WRITE 1234 08:01:5678 0 EOF`; blocked waiters (`->`) are skipped
+fn parse_lock_line(line: &str) -> Option<LockEntry> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.get(1) == Some(&"->") {
+        return None;
+    }
+
+    let kind = fields.get(1)?.to_string();
+    let access = fields.get(3)?.to_string();
+    let pid = fields.get(4)?.parse().ok()?;
+    let dev_inode = fields.get(5)?.to_string();
+    let inode = dev_inode.rsplit(':').next()?.parse().ok()?;
+
+    Some(LockEntry {
+        pid,
+        lock: FileLock {
+            kind,
+            access,
+            dev_inode,
+            inode,
+            path: None,
+        },
+    })
+}
+
+/// Locks held by a process, with paths resolved through its open fds
+pub fn locks_for(pid: i32, all_locks: &[LockEntry]) -> Vec<FileLock> {
+    let mut held: Vec<FileLock> = all_locks
+        .iter()
+        .filter(|entry| entry.pid == pid)
+        .map(|entry| entry.lock.clone())
+        .collect();
+    if held.is_empty() {
+        return held;
+    }
+
+    let mut paths = HashMap::new();
+    if let Ok(fds) = fs::read_dir(format!("/proc/{}/fd", pid)) {
+        for fd in fds.flatten() {
+            if let (Ok(meta), Ok(target)) = (fs::metadata(fd.path()), fs::read_link(fd.path())) {
+                paths.insert(meta.ino(), target.to_string_lossy().into_owned());
+            }
+        }
+    }
+    for lock in &mut held {
+        lock.path = paths.get(&lock.inode).cloned();
+    }
+    held
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat_with_spaces_in_comm() {
+        let stat = parse_stat("4242 (my (odd) app) S 100 4200 4200 0 -1 4194560 10 0 0 0 1 2 0 0 20 0 7 0 5000").unwrap();
+        assert_eq!(stat.comm, "my (odd) app");
+        assert_eq!(stat.ppid, 100);
+        assert_eq!(stat.pgid, 4200);
+        assert_eq!(stat.num_threads, 7);
+        assert_eq!(stat.start_ticks, 5000);
+    }
+
+    #[test]
+    fn test_parse_stat_session_and_tty() {
+        let stat = parse_stat("4242 (bash) S 100 4242 4000 34817 4242 4194560 10 0 0 0 1 2 0 0 20 0 1 0 5000").unwrap();
+        assert_eq!(stat.session, 4000);
+        assert_eq!(tty_path(stat.tty_nr).as_deref(), Some("/dev/pts/1"));
+        assert_eq!(tty_path(0), None);
+    }
+
+    #[test]
+    fn test_parse_listening_socket() {
+        let listen = "   0: 00000000:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 123456 1 0000000000000000 100 0 0 10 0";
+        let established = "   1: 0100007F:1F90 0100007F:C350 01 00000000:00000000 00:00000000 00000000  1000        0 654321 1 0000000000000000 20 4 30 10 -1";
+
+        assert_eq!(parse_listening(listen), Some((123456, 8080)));
+        assert_eq!(parse_listening(established), None);
+    }
+
+    #[test]
+    fn test_parse_lock_line() {
+        let entry = parse_lock_line("1: POSIX  ADVISORY  WRITE 1234 08:01:5678 0 EOF").unwrap();
+        assert_eq!(entry.pid, 1234);
+        assert_eq!(entry.lock.kind, "POSIX");
+        assert_eq!(entry.lock.access, "WRITE");
+        assert_eq!(entry.lock.inode, 5678);
+
+        assert!(parse_lock_line("1: -> POSIX  ADVISORY  WRITE 4321 08:01:5678 0 EOF").is_none());
+    }
+
+    #[test]
+    fn test_parse_argv_keeps_spaces_and_empty_args() {
+        assert_eq!(parse_argv(b"sh\0-c\0echo a  b\0\0"), vec!["sh", "-c", "echo a  b", ""]);
+        assert!(parse_argv(b"").is_empty());
+    }
+
+    #[test]
+    fn test_cmdline_cut_at_a_page() {
+        let mut raw = vec![b'a'; CMDLINE_PAGE];
+        assert!(is_cut(&raw));
+        raw[CMDLINE_PAGE - 1] = 0;
+        assert!(!is_cut(&raw));
+        assert!(!is_cut(b"short"));
+    }
+}
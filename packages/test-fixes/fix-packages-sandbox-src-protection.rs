@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+use crate::auto_pause::{AutoPauseManager, PauseOptions};
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+
+const PROTECTION_FILE: &str = "protection.json";
+
+/// Marks a sandbox that automatic triggers must not pause, e.g. a live demo
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Protection {
+    pub protected_at: DateTime<Utc>,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Why a pause of a protected sandbox is refused, or None when it may go ahead
+pub fn refusal(sandbox_id: &str, protection: Option<&Protection>, options: &PauseOptions) -> Option<String> {
+    let protection = protection?;
+    let reason = protection.reason.as_deref().map(|reason| format!(" ({})", reason)).unwrap_or_default();
+    if options.trigger.is_automatic() {
+        Some(format!("Sandbox {} is protected{}, skipping {:?} pause", sandbox_id, reason, options.trigger))
+    } else if !options.force {
+        Some(format!("Sandbox {} is protected{}; pause it with force to override", sandbox_id, reason))
+    } else {
+        None
+    }
+}
+
+impl PersistenceManager {
+    pub async fn save_protection(&self, sandbox_id: &str, protection: &Protection) -> Result<(), Box<dyn std::error::Error>> {
+        let root = self.layout(sandbox_id).root().to_path_buf();
+        let json = serde_json::to_string(protection)?;
+        self.bounded_write("protection save", sandbox_id, async {
+            async_fs::create_dir_all(&root).await?;
+            let file_path = root.join(PROTECTION_FILE);
+            let temp_path = file_path.with_extension("tmp");
+            async_fs::write(&temp_path, json).await?;
+            async_fs::rename(&temp_path, &file_path).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn load_protection(&self, sandbox_id: &str) -> Result<Option<Protection>, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join(PROTECTION_FILE);
+        self.bounded("protection load", sandbox_id, async {
+            if !blocking::path_exists(&file_path).await {
+                return Ok(None);
+            }
+            let json = async_fs::read_to_string(&file_path).await?;
+            Ok(Some(serde_json::from_str(&json)?))
+        })
+        .await
+    }
+
+    pub async fn remove_protection(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join(PROTECTION_FILE);
+        self.bounded_write("protection remove", sandbox_id, async {
+            if blocking::path_exists(&file_path).await {
+                async_fs::remove_file(&file_path).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl AutoPauseManager {
+    /// Protect a sandbox from automatic pauses; manual pauses then need `force`.
+    /// Protection is kept on disk, so it survives agent restarts.
+    pub async fn protect(&self, sandbox_id: &str, reason: Option<String>) -> Result<Protection, Box<dyn std::error::Error>> {
+        let protection = Protection { protected_at: Utc::now(), reason };
+        self.persistence_manager().save_protection(sandbox_id, &protection).await?;
+        info!(target: "audit", sandbox_id = sandbox_id, operation = "protect"; "Protected sandbox {} from auto-pause", sandbox_id);
+        Ok(protection)
+    }
+
+    pub async fn unprotect(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.persistence_manager().remove_protection(sandbox_id).await?;
+        info!(target: "audit", sandbox_id = sandbox_id, operation = "unprotect"; "Removed auto-pause protection of sandbox {}", sandbox_id);
+        Ok(())
+    }
+
+    /// The sandbox's protection, if it has one
+    pub async fn protection(&self, sandbox_id: &str) -> Result<Option<Protection>, Box<dyn std::error::Error>> {
+        self.persistence_manager().load_protection(sandbox_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::PauseTrigger;
+
+    #[test]
+    fn test_refusal_by_trigger_and_force() {
+        let protection = Protection { protected_at: Utc::now(), reason: Some("customer demo".to_string()) };
+        let idle = PauseOptions { trigger: PauseTrigger::Idle, force: true, ..Default::default() };
+        let manual = PauseOptions::default();
+        let forced = PauseOptions { force: true, ..Default::default() };
+
+        assert_eq!(refusal("sbx", Some(&protection), &idle).unwrap(), "Sandbox sbx is protected (customer demo), skipping Idle pause");
+        assert!(refusal("sbx", Some(&protection), &manual).unwrap().contains("with force"));
+        assert!(refusal("sbx", Some(&protection), &forced).is_none());
+        assert!(refusal("sbx", None, &idle).is_none());
+    }
+}
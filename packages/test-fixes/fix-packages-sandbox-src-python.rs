@@ -0,0 +1,204 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use serde::Serialize;
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use crate::auto_pause::{AutoPauseConfig, AutoPauseManager, PauseOptions, PauseStrategy};
+use crate::bench::{self, BenchConfig};
+use crate::persistence::PersistenceManager;
+use crate::state_fs::StateFsSession;
+use crate::warnings::Warning;
+use crate::wire;
+
+/// Results cross into Python as plain dicts and lists, via their JSON form
+fn to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(py.import_bound("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+fn runtime() -> PyResult<Runtime> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+/// `AutoPauseManager` for Python. Calls block the calling thread, with the GIL
+/// released, until the operation finishes.
+#[pyclass(name = "AutoPauseManager")]
+pub struct PyAutoPauseManager {
+    _state_fs: Option<StateFsSession>, // unmounted first, while the runtime its reads block on is up
+    manager: Arc<AutoPauseManager>,
+    warnings: Mutex<broadcast::Receiver<Warning>>,
+    runtime: Runtime, // dropped last, after everything that may still hold tasks on it
+}
+
+#[pymethods]
+impl PyAutoPauseManager {
+    /// Build a manager from a JSON `AutoPauseConfig`, or the defaults, claim its base dir
+    /// and start the watchdog and state filesystem if configured
+    #[new]
+    #[pyo3(signature = (config_json=None))]
+    fn new(config_json: Option<&str>) -> PyResult<Self> {
+        let config: AutoPauseConfig = match config_json {
+            Some(json) => serde_json::from_str(json).map_err(|e| PyValueError::new_err(format!("invalid config: {}", e)))?,
+            None => AutoPauseConfig::default(),
+        };
+        let runtime = runtime()?;
+        let manager = {
+            let _entered = runtime.enter();
+            AutoPauseManager::new(config)
+        };
+        runtime.block_on(manager.claim_instance()).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let manager = Arc::new(manager);
+        manager.install_crash_handler();
+        let state_fs = {
+            let _entered = runtime.enter();
+            manager.spawn_watchdog().map_err(|e| PyValueError::new_err(format!("invalid config: {}", e)))?;
+            manager.spawn_integrity_scan();
+            manager.mount_state_fs()
+        };
+        Ok(Self {
+            _state_fs: state_fs,
+            warnings: Mutex::new(manager.subscribe_warnings()),
+            manager,
+            runtime,
+        })
+    }
+
+    /// Pause a sandbox; `force` overrides its protection
+    #[pyo3(signature = (sandbox_id, force=false))]
+    fn pause(&self, py: Python<'_>, sandbox_id: &str, force: bool) -> PyResult<PyObject> {
+        let options = PauseOptions { force, ..Default::default() };
+        let result = py
+            .allow_threads(|| self.runtime.block_on(self.manager.prepare_pause_with(sandbox_id, options)).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)?;
+        to_py(py, &result)
+    }
+
+    fn resume(&self, py: Python<'_>, sandbox_id: &str) -> PyResult<PyObject> {
+        let report = py
+            .allow_threads(|| self.runtime.block_on(self.manager.after_resume(sandbox_id)).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)?;
+        to_py(py, &report)
+    }
+
+    /// What a pause would do, without touching any process
+    fn dry_run_pause(&self, py: Python<'_>, sandbox_id: &str) -> PyResult<PyObject> {
+        let report = py
+            .allow_threads(|| self.runtime.block_on(self.manager.dry_run_pause(sandbox_id)).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)?;
+        to_py(py, &report)
+    }
+
+    fn status(&self, py: Python<'_>, sandbox_id: &str) -> PyResult<PyObject> {
+        let status = py
+            .allow_threads(|| self.runtime.block_on(self.manager.status(sandbox_id)).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)?;
+        to_py(py, &status)
+    }
+
+    /// Warnings raised since the last call, oldest first
+    fn drain_warnings(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let mut drained = Vec::new();
+        let mut receiver = self.warnings.lock().unwrap();
+        loop {
+            match receiver.try_recv() {
+                Ok(warning) => drained.push(warning),
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+        to_py(py, &drained)
+    }
+
+    fn shutdown(&self) {
+        self.manager.shutdown();
+    }
+}
+
+/// Read the stored snapshot of a sandbox from a snapshot store, or None
+#[pyfunction]
+fn load_snapshot(py: Python<'_>, base_dir: PathBuf, sandbox_id: &str) -> PyResult<PyObject> {
+    let manager = PersistenceManager::with_base_dir(base_dir);
+    let snapshot = py
+        .allow_threads(|| runtime()?.block_on(manager.load_snapshot(sandbox_id)).map_err(|e| PyRuntimeError::new_err(e.to_string())))?;
+    to_py(py, &snapshot)
+}
+
+/// Decode a snapshot in the protobuf wire format
+#[pyfunction]
+fn decode_snapshot(py: Python<'_>, data: &[u8]) -> PyResult<PyObject> {
+    let snapshot = wire::decode_snapshot(data).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    to_py(py, &snapshot)
+}
+
+/// Pause simulated sandboxes against an in-memory process backend and report
+/// throughput and latency
+#[pyfunction]
+#[pyo3(signature = (sandboxes, processes_per_sandbox, strategy="persist", concurrency=64))]
+fn run_simulation(py: Python<'_>, sandboxes: usize, processes_per_sandbox: usize, strategy: &str, concurrency: usize) -> PyResult<PyObject> {
+    let strategy: PauseStrategy = serde_json::from_value(serde_json::Value::String(strategy.to_string()))
+        .map_err(|_| PyValueError::new_err(format!("unknown strategy: {}", strategy)))?;
+    let config = BenchConfig {
+        sandboxes,
+        processes_per_sandbox,
+        strategy,
+        concurrency,
+    };
+    let report = py.allow_threads(|| runtime()?.block_on(bench::run(config)).map_err(|e| PyRuntimeError::new_err(e.to_string())))?;
+    to_py(py, &report)
+}
+
+/// The `sandbox_agent` extension module, built with the `python` feature
+#[pymodule]
+fn sandbox_agent(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAutoPauseManager>()?;
+    m.add_function(wrap_pyfunction!(load_snapshot, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_snapshot, m)?)?;
+    m.add_function(wrap_pyfunction!(run_simulation, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_snapshot::StateSnapshot;
+
+    fn with_gil<T>(f: impl FnOnce(Python<'_>) -> T) -> T {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(f)
+    }
+
+    #[test]
+    fn test_snapshots_cross_as_dicts() {
+        with_gil(|py| {
+            let decoded = decode_snapshot(py, &wire::encode_snapshot(&StateSnapshot::new("sbx"))).unwrap();
+            assert_eq!(decoded.bind(py).get_item("sandbox_id").unwrap().extract::<String>().unwrap(), "sbx");
+            assert!(decode_snapshot(py, b"\xff\xff").unwrap_err().is_instance_of::<PyValueError>(py));
+
+            let dir = tempfile::tempdir().unwrap();
+            let store = PersistenceManager::with_base_dir(dir.path().to_path_buf());
+            runtime().unwrap().block_on(store.save_snapshot(&StateSnapshot::new("sbx"))).unwrap();
+            let loaded = load_snapshot(py, dir.path().to_path_buf(), "sbx").unwrap();
+            assert_eq!(loaded.bind(py).get_item("sandbox_id").unwrap().extract::<String>().unwrap(), "sbx");
+            assert!(load_snapshot(py, dir.path().to_path_buf(), "other").unwrap().is_none(py));
+        });
+    }
+
+    #[test]
+    fn test_simulation_and_config_arguments() {
+        with_gil(|py| {
+            let err = PyAutoPauseManager::new(Some("{not json")).err().unwrap();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let err = run_simulation(py, 1, 1, "hibernate", 1).unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+
+            let report = run_simulation(py, 2, 1, "persist", 2).unwrap();
+            assert_eq!(report.bind(py).get_item("sandboxes").unwrap().extract::<usize>().unwrap(), 2);
+        });
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::future::BoxFuture;
+use log::{error, info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+use tokio_util::sync::CancellationToken;
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+
+/// Directory under the snapshot base holding quarantined sandboxes
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+/// File in a quarantined sandbox's directory recording when it was quarantined
+pub const QUARANTINE_RECORD: &str = "quarantine.json";
+
+/// Suffix format of quarantined directory names
+const NAME_TIMESTAMP: &str = "%Y%m%dT%H%M%S";
+
+/// Written into each quarantined directory. Retention counts from `quarantined_at`,
+/// which copying or touching the directory does not change, unlike its mtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub sandbox_id: String,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// When a quarantined directory was quarantined: its record, else the timestamp in
+/// its name, else, for directories that have neither, its mtime
+async fn quarantined_at(dir: &Path) -> Result<DateTime<Utc>, Box<dyn std::error::Error>> {
+    match async_fs::read(dir.join(QUARANTINE_RECORD)).await {
+        Ok(json) => match serde_json::from_slice::<QuarantineRecord>(&json) {
+            Ok(record) => return Ok(record.quarantined_at),
+            Err(e) => warn!("Ignoring unreadable quarantine record in {}: {}", dir.display(), e),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let name = dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let named = name.rsplit_once('-').and_then(|(_, stamp)| NaiveDateTime::parse_from_str(stamp, NAME_TIMESTAMP).ok());
+    if let Some(named) = named {
+        return Ok(named.and_utc());
+    }
+    let modified: SystemTime = async_fs::metadata(dir).await?.modified()?;
+    Ok(modified.into())
+}
+
+/// Answers whether a sandbox still exists (its VM has not been deleted)
+pub trait SandboxExistence: Send + Sync {
+    /// Ok(false) only when the sandbox is known to be gone; errors keep the snapshot in place
+    fn exists<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<bool, String>>;
+}
+
+impl PersistenceManager {
+    /// Where quarantined sandbox directories are kept
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.get_base_dir().join(QUARANTINE_DIR)
+    }
+
+    /// IDs of all sandboxes with a directory under the base dir
+    pub async fn list_sandbox_dirs(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut sandbox_ids = Vec::new();
+        if !blocking::path_exists(&self.get_base_dir()).await {
+            return Ok(sandbox_ids);
+        }
+
+        let mut entries = async_fs::read_dir(self.get_base_dir()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().await?.is_dir() && !name.starts_with('.') {
+                sandbox_ids.push(name);
+            }
+        }
+        Ok(sandbox_ids)
+    }
+
+    /// Move a sandbox's directory out of the live store into quarantine
+    pub async fn quarantine_sandbox(&self, sandbox_id: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        self.check_writable("quarantine")?;
+        let source = self.layout(sandbox_id).root().to_path_buf();
+        let record = QuarantineRecord {
+            sandbox_id: sandbox_id.to_string(),
+            quarantined_at: Utc::now(),
+        };
+        let target = self
+            .quarantine_dir()
+            .join(format!("{}-{}", sandbox_id, record.quarantined_at.format(NAME_TIMESTAMP)));
+
+        async_fs::create_dir_all(self.quarantine_dir()).await?;
+        async_fs::rename(&source, &target).await?;
+        // Without the record retention falls back to the time in the directory name
+        let record_path = target.join(QUARANTINE_RECORD);
+        let temp_path = record_path.with_extension("tmp");
+        let written = async {
+            async_fs::write(&temp_path, serde_json::to_vec(&record)?).await?;
+            async_fs::rename(&temp_path, &record_path).await?;
+            Ok::<_, Box<dyn std::error::Error>>(())
+        };
+        if let Err(e) = written.await {
+            warn!("Failed to record when sandbox {} was quarantined: {}", sandbox_id, e);
+        }
+        info!("Quarantined snapshots of sandbox {} to {}", sandbox_id, target.display());
+        Ok(target)
+    }
+
+    /// Quarantine the stored state of every sandbox that no longer exists.
+    /// Sandboxes with pinned snapshots are left alone. Returns the quarantined IDs.
+    pub async fn quarantine_dead_sandboxes(&self, existence: &dyn SandboxExistence) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.check_writable("quarantine")?;
+        let mut quarantined = Vec::new();
+
+        for sandbox_id in self.list_sandbox_dirs().await? {
+            if self.is_pinned(&sandbox_id) {
+                continue;
+            }
+            match existence.exists(&sandbox_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.quarantine_sandbox(&sandbox_id).await?;
+                    quarantined.push(sandbox_id);
+                }
+                Err(e) => warn!("Could not verify sandbox {} exists, keeping its snapshots: {}", sandbox_id, e),
+            }
+        }
+
+        Ok(quarantined)
+    }
+
+    /// Delete quarantined entries quarantined at least `retention` ago; returns how
+    /// many were removed
+    pub async fn cleanup_quarantine(&self, retention: Duration) -> Result<usize, Box<dyn std::error::Error>> {
+        self.cleanup_quarantine_with(retention, &CancellationToken::new()).await
+    }
+
+    /// Like `cleanup_quarantine`, stopping between entries once `cancel` fires
+    pub async fn cleanup_quarantine_with(&self, retention: Duration, cancel: &CancellationToken) -> Result<usize, Box<dyn std::error::Error>> {
+        self.check_writable("quarantine cleanup")?;
+        let dir = self.quarantine_dir();
+        if !blocking::path_exists(&dir).await {
+            return Ok(0);
+        }
+
+        let mut removed = 0;
+        let mut entries = async_fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if cancel.is_cancelled() {
+                info!("Quarantine cleanup cancelled after removing {} entries", removed);
+                break;
+            }
+            let age = (Utc::now() - quarantined_at(&entry.path()).await?).to_std().unwrap_or_default();
+            if age < retention {
+                continue;
+            }
+            match async_fs::remove_dir_all(entry.path()).await {
+                Ok(()) => removed += 1,
+                Err(e) => error!("Failed to remove quarantined {}: {}", entry.path().display(), e),
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_snapshot::StateSnapshot;
+    use tempfile::TempDir;
+
+    struct OnlyAlive(&'static str);
+
+    impl SandboxExistence for OnlyAlive {
+        fn exists<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<bool, String>> {
+            Box::pin(async move { Ok(sandbox_id == self.0) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dead_sandboxes_are_quarantined() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        for id in ["alive", "dead", "busy"] {
+            manager.save_snapshot(&StateSnapshot::new(id.to_string())).await.unwrap();
+        }
+
+        let _pin = manager.pin_snapshot("busy");
+        let quarantined = manager.quarantine_dead_sandboxes(&OnlyAlive("alive")).await.unwrap();
+
+        assert_eq!(quarantined, vec!["dead".to_string()]);
+        assert!(manager.snapshot_exists("alive"));
+        assert!(manager.snapshot_exists("busy"));
+        assert!(!manager.snapshot_exists("dead"));
+        assert_eq!(manager.list_sandbox_dirs().await.unwrap().len(), 2);
+
+        assert_eq!(manager.cleanup_quarantine(Duration::ZERO).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retention_counts_from_the_recorded_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        let day = Duration::from_secs(24 * 3600);
+        let mut quarantined = Vec::new();
+        for id in ["old", "new"] {
+            manager.save_snapshot(&StateSnapshot::new(id.to_string())).await.unwrap();
+            quarantined.push(manager.quarantine_sandbox(id).await.unwrap());
+        }
+        // "old" was quarantined a week ago but, like a copied directory, has a fresh mtime
+        let record = QuarantineRecord {
+            sandbox_id: "old".to_string(),
+            quarantined_at: Utc::now() - chrono::Duration::days(7),
+        };
+        std::fs::write(quarantined[0].join(QUARANTINE_RECORD), serde_json::to_vec(&record).unwrap()).unwrap();
+
+        assert_eq!(manager.cleanup_quarantine(day).await.unwrap(), 1);
+        assert!(!quarantined[0].exists());
+        assert!(quarantined[1].exists());
+
+        // Without a record, the time in the directory name counts
+        std::fs::remove_file(quarantined[1].join(QUARANTINE_RECORD)).unwrap();
+        let renamed = manager.quarantine_dir().join("new-20200101T000000");
+        std::fs::rename(&quarantined[1], &renamed).unwrap();
+        assert_eq!(manager.cleanup_quarantine(day).await.unwrap(), 1);
+        assert!(!renamed.exists());
+    }
+}
@@ -0,0 +1,221 @@
+#![cfg(target_os = "linux")]
+
+use std::collections::HashSet;
+use std::time::Duration;
+use chrono::Utc;
+use log::{debug, info, warn};
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+use crate::blocking;
+use crate::process::{self, ProcessExit, ProcessInfo, ProcessManager, ProcessState, SessionRef};
+use crate::procfs;
+
+/// How often to scan for orphans re-parented to the agent
+const ORPHAN_SCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Reaps exited children so they don't linger as zombies and adopts orphans
+/// re-parented to the agent into the sandbox their process group belongs to
+pub struct Reaper {
+    process_manager: ProcessManager,
+}
+
+impl Reaper {
+    pub fn new(process_manager: ProcessManager) -> Self {
+        Self { process_manager }
+    }
+
+    /// Make the agent the subreaper for its descendants so orphans are
+    /// re-parented to it instead of init
+    pub fn become_subreaper() -> Result<(), Box<dyn std::error::Error>> {
+        nix::sys::prctl::set_child_subreaper(true)?;
+        Ok(())
+    }
+
+    /// Run the reaper loop in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            if let Err(e) = self.run().await {
+                warn!("Reaper stopped: {}", e);
+            }
+        })
+    }
+
+    async fn run(&self) -> Result<(), std::io::Error> {
+        let mut sigchld = signal(SignalKind::child())?;
+        let mut orphan_scan = tokio::time::interval(ORPHAN_SCAN_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = sigchld.recv() => {
+                    self.reap_exited().await;
+                }
+                _ = orphan_scan.tick() => {
+                    self.adopt_orphans().await;
+                    self.process_manager.prune_terminated().await;
+                }
+            }
+        }
+    }
+
+    /// Collect every exited child tracked by the process manager and mark it
+    /// terminated with its exit status, then every other exited child, such as a
+    /// daemon that double-forked or called setsid and was re-parented to the agent;
+    /// returns the reaped PIDs. The agent's own helper commands (hooks, probes, tools)
+    /// are left alone, so their exit statuses stay with the code that spawned them.
+    pub async fn reap_exited(&self) -> Vec<i32> {
+        let tracked = self.process_manager.live_pids().await;
+        let mut reaped: Vec<(i32, ProcessExit)> = tracked.iter().filter_map(|&pid| wait_nohang(pid).map(|exit| (pid, exit))).collect();
+
+        for (pid, exit) in &reaped {
+            if let Some(sandbox_id) = self.process_manager.mark_exited(*pid, Some(exit.clone())).await {
+                info!("Process {} in sandbox {} exited ({:?})", pid, sandbox_id, exit);
+            }
+        }
+
+        match blocking::run("orphan_scan", agent_children).await {
+            Ok(children) => {
+                let tracked: HashSet<i32> = tracked.into_iter().collect();
+                let helpers = process::helper_pids();
+                for (_, child) in children {
+                    if tracked.contains(&child.pid) || helpers.contains(&child.pid) {
+                        continue;
+                    }
+                    if let Some(exit) = wait_nohang(child.pid) {
+                        debug!("Reaped untracked process {} ({})", child.pid, child.name);
+                        reaped.push((child.pid, exit));
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to scan /proc for untracked children: {}", e),
+        }
+
+        reaped.into_iter().map(|(pid, _)| pid).collect()
+    }
+
+    /// Track children of the agent that are not known to the process manager
+    /// but share a process group with a tracked process; returns how many were adopted
+    pub async fn adopt_orphans(&self) -> usize {
+        let children = match blocking::run("orphan_scan", agent_children).await {
+            Ok(children) => children,
+            Err(e) => {
+                warn!("Failed to scan /proc for orphans: {}", e);
+                return 0;
+            }
+        };
+
+        let mut adopted = 0;
+        for (pgid, process) in children {
+            let pid = process.pid;
+            if self.process_manager.find_sandbox(pid).await.is_some() {
+                continue;
+            }
+            let Some(sandbox_id) = self.process_manager.find_sandbox(pgid).await else {
+                continue;
+            };
+            if self.process_manager.add_process(&sandbox_id, process).await.is_ok() {
+                info!("Adopted orphan process {} into sandbox {}", pid, sandbox_id);
+                adopted += 1;
+            }
+        }
+
+        adopted
+    }
+}
+
+/// Exit of `pid` if it is a child of the agent that has exited, which reaps it
+fn wait_nohang(pid: i32) -> Option<ProcessExit> {
+    match waitpid(Pid::from_raw(pid), Some(WaitPidFlag::WNOHANG)) {
+        Ok(WaitStatus::Exited(_, code)) => {
+            debug!("Reaped process {} (exit code {})", pid, code);
+            Some(ProcessExit::Code(code))
+        }
+        Ok(WaitStatus::Signaled(_, sig, _)) => {
+            debug!("Reaped process {} (killed by {})", pid, sig);
+            Some(ProcessExit::Signal(sig.as_str().to_string()))
+        }
+        // Still running, or not a child of the agent
+        Ok(_) | Err(Errno::ECHILD) => None,
+        Err(e) => {
+            warn!("waitpid({}) failed: {}", pid, e);
+            None
+        }
+    }
+}
+
+/// Every child of the agent with its process group, read from /proc
+fn agent_children() -> Result<Vec<(i32, ProcessInfo)>, String> {
+    let agent_pid = std::process::id() as i32;
+    let entries = std::fs::read_dir("/proc").map_err(|e| e.to_string())?;
+
+    let mut children = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<i32>() else {
+            continue;
+        };
+        let Some(stat) = procfs::read_stat(pid) else {
+            continue;
+        };
+        if stat.ppid != agent_pid {
+            continue;
+        }
+        let argv = procfs::read_argv(pid).unwrap_or_default();
+        let start_time = procfs::start_time(&stat).unwrap_or_else(Utc::now);
+        let process = ProcessInfo {
+            pid,
+            name: stat.comm,
+            cmd: argv.join(" "),
+            argv,
+            start_time,
+            state: ProcessState::Running,
+            thread_count: stat.num_threads,
+            child_count: procfs::child_count(pid),
+            exit: None,
+            ended_at: None,
+            labels: Default::default(),
+            session: Some(SessionRef {
+                session_id: stat.session,
+                login_uid: procfs::read_loginuid(pid),
+                tty: procfs::tty_path(stat.tty_nr),
+            }),
+        };
+        children.push((stat.pgid, process));
+    }
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::process_info;
+
+    #[tokio::test]
+    async fn test_reaps_every_child_but_helpers() {
+        let process_manager = ProcessManager::new();
+        let reaper = Reaper::new(process_manager.clone());
+        let mut tracked = std::process::Command::new("true").spawn().unwrap();
+        // Stands in for a daemon re-parented to the agent that nobody tracks
+        let mut orphan = std::process::Command::new("true").spawn().unwrap();
+        let (mut helper, _registered) = process::spawn_helper(&mut tokio::process::Command::new("true")).unwrap();
+        let (tracked_pid, orphan_pid) = (tracked.id() as i32, orphan.id() as i32);
+        process_manager.add_process("sbx", process_info(tracked_pid, "true")).await.unwrap();
+
+        let mut reaped = Vec::new();
+        for _ in 0..100 {
+            reaped.extend(reaper.reap_exited().await);
+            if reaped.contains(&tracked_pid) && reaped.contains(&orphan_pid) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(reaped.contains(&tracked_pid) && reaped.contains(&orphan_pid), "reaped {:?}", reaped);
+        assert_eq!(process_manager.live_pids().await, Vec::<i32>::new());
+        // The helper's status is still there for the code that spawned it
+        assert!(helper.wait().await.unwrap().success());
+        assert!(tracked.try_wait().is_err());
+        assert!(orphan.try_wait().is_err());
+    }
+}
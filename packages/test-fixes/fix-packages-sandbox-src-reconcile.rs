@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::auto_pause::AutoPauseManager;
+use crate::lifecycle::LifecycleState;
+
+/// State the orchestrator wants a sandbox to be in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DesiredState {
+    Running,
+    Paused,
+}
+
+/// Desired state for every sandbox the orchestrator cares about; sandboxes
+/// not listed are left alone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DesiredStateDocument {
+    pub sandboxes: HashMap<String, DesiredState>,
+}
+
+/// What the reconciler did to one drifted sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileAction {
+    pub sandbox_id: String,
+    pub from: LifecycleState,
+    pub to: DesiredState,
+    /// Error message if the corrective action failed
+    pub error: Option<String>,
+}
+
+/// Outcome of one reconciliation pass
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconcileReport {
+    pub started_at: Option<DateTime<Utc>>,
+    pub actions: Vec<ReconcileAction>,
+    pub in_sync: usize,
+    /// Sandboxes mid-pause or mid-resume, revisited on the next pass
+    pub in_progress: Vec<String>,
+}
+
+impl AutoPauseManager {
+    /// Pause or resume every sandbox whose state differs from `desired`
+    pub async fn reconcile(&self, desired: &DesiredStateDocument) -> ReconcileReport {
+        let mut report = ReconcileReport {
+            started_at: Some(Utc::now()),
+            ..Default::default()
+        };
+
+        for (sandbox_id, target) in &desired.sandboxes {
+            let current = self.lifecycle_state(sandbox_id).await;
+            let result = match (current, target) {
+                (LifecycleState::Running, DesiredState::Paused) => {
+                    self.prepare_pause(sandbox_id).await.map(|_| ()).map_err(|e| e.to_string())
+                }
+                (LifecycleState::Paused, DesiredState::Running) => {
+                    self.after_resume(sandbox_id).await.map(|_| ()).map_err(|e| e.to_string())
+                }
+                (LifecycleState::Pausing | LifecycleState::Resuming, _) => {
+                    report.in_progress.push(sandbox_id.clone());
+                    continue;
+                }
+                _ => {
+                    report.in_sync += 1;
+                    continue;
+                }
+            };
+
+            if let Err(e) = &result {
+                warn!("Failed to reconcile sandbox {} from {:?} to {:?}: {}", sandbox_id, current, target, e);
+            } else {
+                info!("Reconciled sandbox {} from {:?} to {:?}", sandbox_id, current, target);
+            }
+            report.actions.push(ReconcileAction {
+                sandbox_id: sandbox_id.clone(),
+                from: current,
+                to: *target,
+                error: result.err(),
+            });
+        }
+
+        report
+    }
+}
+
+/// Runs `reconcile` periodically and whenever a new desired-state document arrives
+pub struct Reconciler {
+    manager: Arc<AutoPauseManager>,
+    desired: watch::Receiver<DesiredStateDocument>,
+    interval: Duration,
+    reports: watch::Sender<ReconcileReport>,
+}
+
+impl Reconciler {
+    /// Returns the reconciler, a sender for desired-state updates and a
+    /// receiver for the latest pass report
+    pub fn new(
+        manager: Arc<AutoPauseManager>,
+        interval: Duration,
+    ) -> (Self, watch::Sender<DesiredStateDocument>, watch::Receiver<ReconcileReport>) {
+        let (desired_tx, desired_rx) = watch::channel(DesiredStateDocument::default());
+        let (report_tx, report_rx) = watch::channel(ReconcileReport::default());
+        let reconciler = Self {
+            manager,
+            desired: desired_rx,
+            interval,
+            reports: report_tx,
+        };
+        (reconciler, desired_tx, report_rx)
+    }
+
+    /// Run the loop in the background until the desired-state sender is dropped
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    changed = self.desired.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                let desired = self.desired.borrow_and_update().clone();
+                let report = self.manager.reconcile(&desired).await;
+                if !report.actions.is_empty() {
+                    info!("Reconciliation pass took {} actions", report.actions.len());
+                }
+                let _ = self.reports.send(report);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::{AutoPauseConfig, PauseStrategy};
+    use crate::bench::FakeBackend;
+    use crate::test_support::process_info;
+
+    /// "running" and "paused" are where they should be, "drifted" is running and "stopped"
+    /// is paused, against a document that wants them the other way round
+    async fn manager(dir: &std::path::Path) -> (Arc<AutoPauseManager>, DesiredStateDocument) {
+        let (backend, _exits) = FakeBackend::new([1, 2, 3, 4]);
+        let config = AutoPauseConfig { strategy: Some(PauseStrategy::Persist), ..AutoPauseConfig::default() };
+        let manager = Arc::new(AutoPauseManager::with_backend(config, Box::new(backend)));
+        for (pid, sandbox_id) in [(1, "running"), (2, "paused"), (3, "drifted"), (4, "stopped")] {
+            manager.persistence_manager().set_sandbox_base_dir(sandbox_id, dir.to_path_buf());
+            manager.process_manager().add_process(sandbox_id, process_info(pid, &format!("bench-{}", pid))).await.unwrap();
+        }
+        manager.prepare_pause("paused").await.unwrap();
+        manager.prepare_pause("stopped").await.unwrap();
+        let desired = DesiredStateDocument {
+            sandboxes: HashMap::from([
+                ("running".to_string(), DesiredState::Running),
+                ("paused".to_string(), DesiredState::Paused),
+                ("drifted".to_string(), DesiredState::Paused),
+                ("stopped".to_string(), DesiredState::Running),
+            ]),
+        };
+        (manager, desired)
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_corrects_drift_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, mut desired) = manager(dir.path()).await;
+        manager.persistence_manager().set_sandbox_base_dir("busy", dir.path().to_path_buf());
+        manager.set_lifecycle_state("busy", LifecycleState::Resuming).await;
+        desired.sandboxes.insert("busy".to_string(), DesiredState::Running);
+
+        let mut report = manager.reconcile(&desired).await;
+        report.actions.sort_by(|a, b| a.sandbox_id.cmp(&b.sandbox_id));
+        assert_eq!(report.in_sync, 2);
+        assert_eq!(report.in_progress, ["busy"]);
+        let actions: Vec<_> = report.actions.iter().map(|a| (a.sandbox_id.as_str(), a.from, a.to, a.error.is_none())).collect();
+        assert_eq!(actions, [("drifted", LifecycleState::Running, DesiredState::Paused, true), ("stopped", LifecycleState::Paused, DesiredState::Running, true)]);
+        assert_eq!(manager.lifecycle_state("drifted").await, LifecycleState::Paused);
+        assert_eq!(manager.lifecycle_state("stopped").await, LifecycleState::Running);
+
+        // Nothing left to do
+        desired.sandboxes.remove("busy");
+        let report = manager.reconcile(&desired).await;
+        assert!(report.actions.is_empty());
+        assert_eq!(report.in_sync, 4);
+    }
+
+    #[tokio::test]
+    async fn test_reconciler_runs_on_new_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, desired) = manager(dir.path()).await;
+        let (reconciler, desired_tx, mut reports) = Reconciler::new(manager.clone(), Duration::from_secs(3600));
+        let task = reconciler.spawn();
+
+        desired_tx.send(desired).unwrap();
+        tokio::time::timeout(Duration::from_secs(10), reports.wait_for(|report| report.actions.len() == 2)).await.unwrap().unwrap();
+        assert_eq!(manager.lifecycle_state("drifted").await, LifecycleState::Paused);
+
+        // The loop ends with its document source
+        drop(desired_tx);
+        tokio::time::timeout(Duration::from_secs(10), task).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+use serde::{Serialize, Deserialize};
+
+/// Resume ordering class; higher classes are resumed first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityClass {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Paying customers, production templates
+    Critical,
+}
+
+/// Per-sandbox metadata known to the agent
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SandboxEntry {
+    pub sandbox_id: String,
+    #[serde(default)]
+    pub priority: PriorityClass,
+    /// Tenant owning the sandbox, when the orchestrator supplies one
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// User the sandbox belongs to
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Team the sandbox belongs to
+    #[serde(default)]
+    pub team: Option<String>,
+}
+
+/// Whose sandboxes a bulk operation applies to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnerScope {
+    Owner(String),
+    Team(String),
+}
+
+impl OwnerScope {
+    pub fn matches(&self, entry: &SandboxEntry) -> bool {
+        match self {
+            OwnerScope::Owner(owner) => entry.owner.as_ref() == Some(owner),
+            OwnerScope::Team(team) => entry.team.as_ref() == Some(team),
+        }
+    }
+}
+
+impl FromStr for OwnerScope {
+    type Err = String;
+
+    /// `team:<name>` or `owner:<name>`; a bare name is an owner
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let scope = match s.split_once(':') {
+            Some(("team", team)) => OwnerScope::Team(team.to_string()),
+            Some(("owner", owner)) => OwnerScope::Owner(owner.to_string()),
+            Some((kind, _)) => return Err(format!("unknown owner scope '{}', expected owner or team", kind)),
+            None => OwnerScope::Owner(s.to_string()),
+        };
+        match &scope {
+            OwnerScope::Owner(name) | OwnerScope::Team(name) if name.is_empty() => Err("owner scope needs a name".to_string()),
+            _ => Ok(scope),
+        }
+    }
+}
+
+/// Registry of sandbox metadata supplied by the orchestrator
+pub struct SandboxRegistry {
+    entries: RwLock<HashMap<String, SandboxEntry>>,
+}
+
+impl SandboxRegistry {
+    pub fn new() -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Insert or replace a sandbox's metadata
+    pub fn upsert(&self, entry: SandboxEntry) {
+        self.entries.write().unwrap().insert(entry.sandbox_id.clone(), entry);
+    }
+
+    /// Metadata for a sandbox; unknown sandboxes get defaults
+    pub fn get(&self, sandbox_id: &str) -> SandboxEntry {
+        self.entries
+            .read()
+            .unwrap()
+            .get(sandbox_id)
+            .cloned()
+            .unwrap_or_else(|| SandboxEntry {
+                sandbox_id: sandbox_id.to_string(),
+                ..Default::default()
+            })
+    }
+
+    pub fn remove(&self, sandbox_id: &str) -> Option<SandboxEntry> {
+        self.entries.write().unwrap().remove(sandbox_id)
+    }
+
+    pub fn set_priority(&self, sandbox_id: &str, priority: PriorityClass) {
+        let mut entries = self.entries.write().unwrap();
+        let entry = entries.entry(sandbox_id.to_string()).or_insert_with(|| SandboxEntry {
+            sandbox_id: sandbox_id.to_string(),
+            ..Default::default()
+        });
+        entry.priority = priority;
+    }
+
+    pub fn priority(&self, sandbox_id: &str) -> PriorityClass {
+        self.get(sandbox_id).priority
+    }
+
+    pub fn tenant(&self, sandbox_id: &str) -> Option<String> {
+        self.entries.read().unwrap().get(sandbox_id).and_then(|entry| entry.tenant.clone())
+    }
+
+    /// Set who a sandbox belongs to, keeping its other metadata
+    pub fn set_owner(&self, sandbox_id: &str, owner: Option<String>, team: Option<String>) {
+        let mut entries = self.entries.write().unwrap();
+        let entry = entries.entry(sandbox_id.to_string()).or_insert_with(|| SandboxEntry {
+            sandbox_id: sandbox_id.to_string(),
+            ..Default::default()
+        });
+        entry.owner = owner;
+        entry.team = team;
+    }
+
+    /// IDs of the sandboxes in `scope`, sorted
+    pub fn scoped(&self, scope: &OwnerScope) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .entries
+            .read()
+            .unwrap()
+            .values()
+            .filter(|entry| scope.matches(entry))
+            .map(|entry| entry.sandbox_id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// All registered sandboxes
+    pub fn list(&self) -> Vec<SandboxEntry> {
+        self.entries.read().unwrap().values().cloned().collect()
+    }
+
+    /// Like `list`, but None instead of waiting while the registry is being written
+    pub fn try_list(&self) -> Option<Vec<SandboxEntry>> {
+        self.entries.try_read().ok().map(|entries| entries.values().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scoped_by_owner_or_team() {
+        let registry = SandboxRegistry::new();
+        registry.set_owner("a", Some("alice".to_string()), Some("infra".to_string()));
+        registry.set_owner("b", Some("bob".to_string()), Some("infra".to_string()));
+        registry.set_priority("c", PriorityClass::High);
+
+        assert_eq!(registry.scoped(&"team:infra".parse().unwrap()), ["a", "b"]);
+        assert_eq!(registry.scoped(&"bob".parse().unwrap()), ["b"]);
+        assert!(registry.scoped(&OwnerScope::Owner("carol".to_string())).is_empty());
+        assert!("group:x".parse::<OwnerScope>().is_err());
+        assert!("team:".parse::<OwnerScope>().is_err());
+    }
+
+    #[test]
+    fn test_try_list_does_not_wait_for_a_writer() {
+        let registry = SandboxRegistry::new();
+        registry.set_priority("a", PriorityClass::High);
+        let writer = registry.entries.write().unwrap();
+        assert!(registry.try_list().is_none());
+        drop(writer);
+        assert_eq!(registry.try_list().unwrap().len(), 1);
+    }
+}
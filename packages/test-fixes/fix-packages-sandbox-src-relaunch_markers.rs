@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+
+const MARKERS_DIR: &str = "relaunch";
+
+/// Written once a persisted process has been relaunched, so a retried restore can tell
+/// the relaunch is already running and adopt it instead of starting a duplicate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelaunchMarker {
+    /// PID of the process in the snapshot
+    pub source_pid: i32,
+    pub name: String,
+    /// PID of the relaunched process
+    pub pid: i32,
+    /// Start time of the relaunched process in clock ticks since boot, if it could be read
+    pub start_ticks: Option<u64>,
+    pub launched_at: DateTime<Utc>,
+}
+
+impl RelaunchMarker {
+    /// Marker for `pid`, just launched in place of `source_pid`
+    pub async fn new(source_pid: i32, name: &str, pid: i32) -> Self {
+        Self {
+            source_pid,
+            name: name.to_string(),
+            pid,
+            start_ticks: blocking::run("read_stat", move || start_ticks(pid)).await,
+            launched_at: Utc::now(),
+        }
+    }
+
+    /// Whether the relaunched process still runs. Without a recorded start time a
+    /// recycled PID cannot be told apart, so the marker never matches.
+    pub async fn is_running(&self) -> bool {
+        let pid = self.pid;
+        let current = blocking::run("read_stat", move || start_ticks(pid)).await;
+        self.start_ticks.is_some() && current == self.start_ticks
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn start_ticks(pid: i32) -> Option<u64> {
+    crate::procfs::read_stat(pid).map(|stat| stat.start_ticks)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_ticks(_pid: i32) -> Option<u64> {
+    None
+}
+
+impl PersistenceManager {
+    pub async fn save_relaunch_marker(&self, sandbox_id: &str, marker: &RelaunchMarker) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = self.layout(sandbox_id).root().join(MARKERS_DIR);
+        let json = serde_json::to_string(marker)?;
+        self.bounded_write("relaunch marker save", sandbox_id, async {
+            async_fs::create_dir_all(&dir).await?;
+            let file_path = dir.join(format!("{}.json", marker.source_pid));
+            let temp_path = file_path.with_extension("tmp");
+            async_fs::write(&temp_path, json).await?;
+            async_fs::rename(&temp_path, &file_path).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Markers left by an earlier restore into `sandbox_id`, by source PID. Unreadable
+    /// markers are skipped, so only their processes are relaunched again.
+    pub async fn load_relaunch_markers(&self, sandbox_id: &str) -> Result<HashMap<i32, RelaunchMarker>, Box<dyn std::error::Error>> {
+        let dir = self.layout(sandbox_id).root().join(MARKERS_DIR);
+        self.bounded("relaunch markers load", sandbox_id, async {
+            let mut markers = HashMap::new();
+            if !blocking::path_exists(&dir).await {
+                return Ok(markers);
+            }
+            let mut entries = async_fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.path().extension().and_then(|e| e.to_str()) != Some("json") {
+                    continue;
+                }
+                let json = async_fs::read_to_string(entry.path()).await?;
+                match serde_json::from_str::<RelaunchMarker>(&json) {
+                    Ok(marker) => {
+                        markers.insert(marker.source_pid, marker);
+                    }
+                    Err(e) => warn!("Skipping corrupt relaunch marker {}: {}", entry.path().display(), e),
+                }
+            }
+            Ok(markers)
+        })
+        .await
+    }
+
+    /// Drop the markers of `source_pids` once their relaunched processes are tracked.
+    /// Markers of other processes stay for the next attempt.
+    pub async fn remove_relaunch_markers(&self, sandbox_id: &str, source_pids: &[i32]) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = self.layout(sandbox_id).root().join(MARKERS_DIR);
+        self.bounded_write("relaunch markers remove", sandbox_id, async {
+            for source_pid in source_pids {
+                match async_fs::remove_file(dir.join(format!("{}.json", source_pid))).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+            // Fails while other markers are left, which is fine
+            let _ = async_fs::remove_dir(&dir).await;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_marker_matches_running_process_only() {
+        let dir = TempDir::new().unwrap();
+        let persistence = PersistenceManager::with_base_dir(dir.path().to_path_buf());
+
+        let own = RelaunchMarker::new(7, "agent", std::process::id() as i32).await;
+        assert!(own.is_running().await);
+        let recycled = RelaunchMarker { start_ticks: own.start_ticks.map(|t| t + 1), ..own.clone() };
+        assert!(!recycled.is_running().await);
+
+        persistence.save_relaunch_marker("sbx", &own).await.unwrap();
+        let markers = persistence.load_relaunch_markers("sbx").await.unwrap();
+        assert_eq!(markers.get(&7), Some(&own));
+
+        let other = RelaunchMarker { source_pid: 8, ..own.clone() };
+        persistence.save_relaunch_marker("sbx", &other).await.unwrap();
+        let dir = persistence.layout("sbx").root().join(MARKERS_DIR);
+        std::fs::write(dir.join("9.json"), "{\"source_pid\": 9").unwrap();
+        let markers = persistence.load_relaunch_markers("sbx").await.unwrap();
+        assert_eq!(markers.keys().copied().collect::<std::collections::BTreeSet<_>>(), [7, 8].into());
+
+        persistence.remove_relaunch_markers("sbx", &[7]).await.unwrap();
+        let markers = persistence.load_relaunch_markers("sbx").await.unwrap();
+        assert_eq!(markers.keys().copied().collect::<Vec<_>>(), vec![8]);
+    }
+}
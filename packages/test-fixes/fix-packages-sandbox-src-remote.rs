@@ -0,0 +1,393 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream, StreamExt};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::metadata::{Ascii, MetadataMap, MetadataValue};
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+
+use crate::auto_pause::{AutoPauseManager, PauseOptions, PauseResult, ResumeReport};
+use crate::events::Event;
+use crate::operations::{OperationId, OperationInfo};
+use crate::process::ProcessInfo;
+use crate::process_tree::TreeFormat;
+use crate::sandbox_id::check_path_safe;
+use crate::state_export::StateExport;
+use crate::wire::sandbox_agent_client::SandboxAgentClient;
+use crate::wire::sandbox_agent_server::{SandboxAgent, SandboxAgentServer};
+use crate::wire::{self, CancelOperationReply, CancelOperationRequest, Empty, OperationList, PauseRequest, ProcessList, ProcessTreeReply, ProcessTreeRequest, SandboxRequest, SubscribeRequest};
+
+/// Where commands go: the agent on this host unless an address is set.
+/// Flags override the config file field by field.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    /// gRPC address of the remote agent, e.g. `https://10.0.3.7:7070`
+    pub address: Option<String>,
+    /// PEM file of the CA that signed the agents' certificates; the system roots
+    /// are trusted when unset
+    pub ca_cert: Option<PathBuf>,
+    /// Bearer token; prefer `token_env` so it stays out of config files
+    pub token: Option<String>,
+    /// Environment variable holding the token
+    pub token_env: Option<String>,
+}
+
+impl RemoteConfig {
+    /// The remote agent to use given `--address` and `--token`, or None for the local one
+    pub fn resolve(&self, address: Option<String>, token: Option<String>) -> Result<Option<RemoteTarget>, String> {
+        let Some(address) = address.or_else(|| self.address.clone()) else {
+            return Ok(None);
+        };
+        let token = match token.or_else(|| self.token.clone()) {
+            Some(token) => token,
+            None => match &self.token_env {
+                Some(var) => std::env::var(var).map_err(|_| format!("environment variable {} holding the agent token is not set", var))?,
+                None => return Err(format!("no token given for the agent at {}", address)),
+            },
+        };
+        Ok(Some(RemoteTarget { address, token, ca_cert: self.ca_cert.clone() }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteTarget {
+    pub address: String,
+    pub token: String,
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// Events as the agent streams them; an item fails when one could not be decoded
+pub type EventStream = BoxStream<'static, Result<Event, Box<dyn std::error::Error + Send + Sync>>>;
+
+/// What the CLI can ask of an agent, wherever it runs. Results are the library types,
+/// so output is the same for local and remote agents.
+pub trait AgentClient: Send + Sync {
+    /// Pause `sandbox_id`; `force` pauses it even if it is protected
+    fn pause<'a>(&'a self, sandbox_id: &'a str, force: bool) -> BoxFuture<'a, Result<PauseResult, Box<dyn std::error::Error + Send + Sync>>>;
+    fn resume<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<ResumeReport, Box<dyn std::error::Error + Send + Sync>>>;
+    fn export_state(&self) -> BoxFuture<'_, Result<StateExport, Box<dyn std::error::Error + Send + Sync>>>;
+    fn list_operations(&self) -> BoxFuture<'_, Result<Vec<OperationInfo>, Box<dyn std::error::Error + Send + Sync>>>;
+    fn cancel_operation(&self, id: OperationId) -> BoxFuture<'_, Result<bool, Box<dyn std::error::Error + Send + Sync>>>;
+    /// Events of `sandbox_id` replayed from `from_seq`, or live events of every sandbox
+    /// when None. The stream ends when the agent goes away.
+    fn subscribe_events<'a>(&'a self, sandbox_id: Option<&'a str>, from_seq: u64) -> BoxFuture<'a, Result<EventStream, Box<dyn std::error::Error + Send + Sync>>>;
+    fn list_processes<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<Vec<ProcessInfo>, Box<dyn std::error::Error + Send + Sync>>>;
+    /// Process hierarchy of `sandbox_id` as `AutoPauseManager::render_process_tree` draws it
+    fn process_tree<'a>(&'a self, sandbox_id: &'a str, format: TreeFormat) -> BoxFuture<'a, Result<String, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+/// Events of one sandbox from `from_seq` on, or live events of every sandbox
+fn event_stream(manager: &AutoPauseManager, sandbox_id: Option<&str>, from_seq: u64) -> BoxStream<'static, Event> {
+    match sandbox_id {
+        Some(sandbox_id) => {
+            let replay = manager.subscribe_events_from(sandbox_id, from_seq);
+            stream::unfold(replay, |mut replay| async move { replay.recv().await.map(|event| (event, replay)) }).boxed()
+        }
+        None => stream::unfold(manager.subscribe_events(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((event, events)),
+                    Err(RecvError::Lagged(skipped)) => warn!("Event subscriber fell behind; {} events skipped", skipped),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed(),
+    }
+}
+
+/// The agent running in this process
+pub struct LocalAgent(pub Arc<AutoPauseManager>);
+
+impl AgentClient for LocalAgent {
+    fn pause<'a>(&'a self, sandbox_id: &'a str, force: bool) -> BoxFuture<'a, Result<PauseResult, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move {
+            let options = PauseOptions { force, ..Default::default() };
+            self.0.prepare_pause_with(sandbox_id, options).await.map_err(|e| e.to_string().into())
+        })
+    }
+
+    fn resume<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<ResumeReport, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { self.0.after_resume(sandbox_id).await.map_err(|e| e.to_string().into()) })
+    }
+
+    fn export_state(&self) -> BoxFuture<'_, Result<StateExport, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { Ok(self.0.export_state().await) })
+    }
+
+    fn list_operations(&self) -> BoxFuture<'_, Result<Vec<OperationInfo>, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { Ok(self.0.list_operations()) })
+    }
+
+    fn cancel_operation(&self, id: OperationId) -> BoxFuture<'_, Result<bool, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { Ok(self.0.cancel_operation(id)) })
+    }
+
+    fn subscribe_events<'a>(&'a self, sandbox_id: Option<&'a str>, from_seq: u64) -> BoxFuture<'a, Result<EventStream, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { Ok(event_stream(&self.0, sandbox_id, from_seq).map(Ok).boxed()) })
+    }
+
+    fn list_processes<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<Vec<ProcessInfo>, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { self.0.process_manager().list_processes(sandbox_id).await.map_err(|e| e.to_string().into()) })
+    }
+
+    fn process_tree<'a>(&'a self, sandbox_id: &'a str, format: TreeFormat) -> BoxFuture<'a, Result<String, Box<dyn std::error::Error + Send + Sync>>> {
+        Box::pin(async move { self.0.render_process_tree(sandbox_id, format).await.map_err(|e| e.to_string().into()) })
+    }
+}
+
+/// Adds the bearer token to every call of the generated client
+#[derive(Clone)]
+struct BearerToken(MetadataValue<Ascii>);
+
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        request.metadata_mut().insert("authorization", self.0.clone());
+        Ok(request)
+    }
+}
+
+/// An agent on another host, reached over gRPC with TLS
+pub struct RemoteAgent {
+    client: SandboxAgentClient<InterceptedService<Channel, BearerToken>>,
+}
+
+impl RemoteAgent {
+    pub async fn connect(target: &RemoteTarget) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        // The bearer token must never cross the network in the clear
+        if !target.address.starts_with("https://") {
+            return Err(format!("agent address {} must use https", target.address).into());
+        }
+        let bearer = format!("Bearer {}", target.token).parse().map_err(|_| "token is not a valid header value")?;
+        let tls = match &target.ca_cert {
+            Some(path) => ClientTlsConfig::new().ca_certificate(Certificate::from_pem(tokio::fs::read(path).await?)),
+            None => ClientTlsConfig::new().with_native_roots(),
+        };
+        let channel = Endpoint::from_shared(target.address.clone())?.tls_config(tls)?.connect().await?;
+        Ok(Self {
+            client: SandboxAgentClient::with_interceptor(channel, BearerToken(bearer)),
+        })
+    }
+}
+
+impl AgentClient for RemoteAgent {
+    fn pause<'a>(&'a self, sandbox_id: &'a str, force: bool) -> BoxFuture<'a, Result<PauseResult, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        let request = PauseRequest { sandbox_id: sandbox_id.to_string(), force };
+        Box::pin(async move { Ok(PauseResult::try_from(client.pause(request).await?.into_inner())?) })
+    }
+
+    fn resume<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<ResumeReport, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        let request = SandboxRequest { sandbox_id: sandbox_id.to_string() };
+        Box::pin(async move { Ok(ResumeReport::try_from(client.resume(request).await?.into_inner())?) })
+    }
+
+    fn export_state(&self) -> BoxFuture<'_, Result<StateExport, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        Box::pin(async move { Ok(StateExport::try_from(client.export_state(Empty {}).await?.into_inner())?) })
+    }
+
+    fn list_operations(&self) -> BoxFuture<'_, Result<Vec<OperationInfo>, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        Box::pin(async move { Ok(Vec::try_from(client.list_operations(Empty {}).await?.into_inner())?) })
+    }
+
+    fn cancel_operation(&self, id: OperationId) -> BoxFuture<'_, Result<bool, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        Box::pin(async move { Ok(client.cancel_operation(CancelOperationRequest { id }).await?.into_inner().cancelled) })
+    }
+
+    fn subscribe_events<'a>(&'a self, sandbox_id: Option<&'a str>, from_seq: u64) -> BoxFuture<'a, Result<EventStream, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        let request = SubscribeRequest { sandbox_id: sandbox_id.map(String::from), from_seq };
+        Box::pin(async move {
+            let events = client.subscribe_events(request).await?.into_inner();
+            let events = events.map(|event| -> Result<Event, Box<dyn std::error::Error + Send + Sync>> { Ok(Event::try_from(event?)?) });
+            Ok(events.boxed())
+        })
+    }
+
+    fn list_processes<'a>(&'a self, sandbox_id: &'a str) -> BoxFuture<'a, Result<Vec<ProcessInfo>, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        let request = SandboxRequest { sandbox_id: sandbox_id.to_string() };
+        Box::pin(async move { Ok(Vec::try_from(client.list_processes(request).await?.into_inner())?) })
+    }
+
+    fn process_tree<'a>(&'a self, sandbox_id: &'a str, format: TreeFormat) -> BoxFuture<'a, Result<String, Box<dyn std::error::Error + Send + Sync>>> {
+        let mut client = self.client.clone();
+        let request = ProcessTreeRequest { sandbox_id: sandbox_id.to_string(), format: wire::TreeFormat::from(format) as i32 };
+        Box::pin(async move { Ok(client.process_tree(request).await?.into_inner().tree) })
+    }
+}
+
+/// Connect to the agent named by `target`, or use `local` when there is none
+pub async fn agent_client(target: Option<&RemoteTarget>, local: impl FnOnce() -> Arc<AutoPauseManager>) -> Result<Box<dyn AgentClient>, Box<dyn std::error::Error + Send + Sync>> {
+    match target {
+        Some(target) => Ok(Box::new(RemoteAgent::connect(target).await?)),
+        None => Ok(Box::new(LocalAgent(local()))),
+    }
+}
+
+/// TLS identity of the agent API from PEM files of its certificate chain and key
+pub async fn server_tls(cert: &Path, key: &Path) -> std::io::Result<ServerTlsConfig> {
+    let identity = Identity::from_pem(tokio::fs::read(cert).await?, tokio::fs::read(key).await?);
+    Ok(ServerTlsConfig::new().identity(identity))
+}
+
+/// Serves `SandboxAgent` from proto/sandbox.proto for remote CLIs, over TLS. Every
+/// call must carry `authorization: Bearer <token>`.
+#[derive(Clone)]
+pub struct AgentServer {
+    manager: Arc<AutoPauseManager>,
+    token: Arc<str>,
+}
+
+impl AgentServer {
+    pub fn new(manager: Arc<AutoPauseManager>, token: String) -> Result<Self, String> {
+        if token.trim().is_empty() {
+            return Err("the agent API token must not be empty".to_string());
+        }
+        Ok(Self { manager, token: token.into() })
+    }
+
+    /// Serve on `addr` until the returned task is aborted
+    pub async fn spawn(self, addr: SocketAddr, tls: ServerTlsConfig) -> Result<tokio::task::JoinHandle<()>, Box<dyn std::error::Error>> {
+        let mut server = tonic::transport::Server::builder().tls_config(tls)?;
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        info!("Serving the agent API on {}", listener.local_addr()?);
+        let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+        let token = self.token.clone();
+        let service = SandboxAgentServer::with_interceptor(self, move |request: Request<()>| {
+            if authorized(request.metadata(), &token) {
+                Ok(request)
+            } else {
+                Err(Status::unauthenticated("missing or wrong bearer token"))
+            }
+        });
+        Ok(tokio::spawn(async move {
+            if let Err(e) = server.add_service(service).serve_with_incoming(incoming).await {
+                log::error!("Agent API server stopped: {}", e);
+            }
+        }))
+    }
+}
+
+fn authorized(metadata: &MetadataMap, token: &str) -> bool {
+    let presented = metadata.get("authorization").and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+    presented.is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes()))
+}
+
+/// Compares every byte of the longer input, so the time taken does not tell how
+/// much of a guessed token was right
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        diff |= usize::from(a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0));
+    }
+    diff == 0
+}
+
+#[tonic::async_trait]
+impl SandboxAgent for AgentServer {
+    async fn pause(&self, request: Request<PauseRequest>) -> Result<Response<wire::PauseResult>, Status> {
+        let request = request.into_inner();
+        let options = PauseOptions { force: request.force, ..Default::default() };
+        let result = self.manager.prepare_pause_with(&request.sandbox_id, options).await.map_err(|e| Status::failed_precondition(e.to_string()))?;
+        Ok(Response::new(wire::PauseResult::from(&result)))
+    }
+
+    async fn resume(&self, request: Request<SandboxRequest>) -> Result<Response<wire::ResumeReport>, Status> {
+        let report = self.manager.after_resume(&request.into_inner().sandbox_id).await.map_err(|e| Status::failed_precondition(e.to_string()))?;
+        Ok(Response::new(wire::ResumeReport::from(&report)))
+    }
+
+    async fn export_state(&self, _: Request<Empty>) -> Result<Response<wire::StateExport>, Status> {
+        Ok(Response::new(wire::StateExport::from(&self.manager.export_state().await)))
+    }
+
+    async fn list_operations(&self, _: Request<Empty>) -> Result<Response<OperationList>, Status> {
+        Ok(Response::new(OperationList::from(self.manager.list_operations().as_slice())))
+    }
+
+    async fn cancel_operation(&self, request: Request<CancelOperationRequest>) -> Result<Response<CancelOperationReply>, Status> {
+        let cancelled = self.manager.cancel_operation(request.into_inner().id);
+        Ok(Response::new(CancelOperationReply { cancelled }))
+    }
+
+    type SubscribeEventsStream = BoxStream<'static, Result<wire::Event, Status>>;
+
+    async fn subscribe_events(&self, request: Request<SubscribeRequest>) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        let request = request.into_inner();
+        if let Some(sandbox_id) = &request.sandbox_id {
+            check_path_safe(sandbox_id).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        }
+        let events = event_stream(&self.manager, request.sandbox_id.as_deref(), request.from_seq);
+        Ok(Response::new(events.map(|event| Ok(wire::Event::from(&event))).boxed()))
+    }
+
+    async fn list_processes(&self, request: Request<SandboxRequest>) -> Result<Response<ProcessList>, Status> {
+        let processes = self.manager.process_manager().list_processes(&request.into_inner().sandbox_id).await.map_err(|e| Status::internal(e.to_string()))?;
+        Ok(Response::new(ProcessList::from(processes.as_slice())))
+    }
+
+    async fn process_tree(&self, request: Request<ProcessTreeRequest>) -> Result<Response<ProcessTreeReply>, Status> {
+        let request = request.into_inner();
+        check_path_safe(&request.sandbox_id).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let format = TreeFormat::try_from(request.format).map_err(Status::invalid_argument)?;
+        let tree = self.manager.render_process_tree(&request.sandbox_id, format).await.map_err(|e| Status::not_found(e.to_string()))?;
+        Ok(Response::new(ProcessTreeReply { tree }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_override_config() {
+        let config = RemoteConfig {
+            address: Some("https://agent-a:7070".to_string()),
+            token: Some("from-config".to_string()),
+            ..Default::default()
+        };
+        let target = config.resolve(Some("https://agent-b:7070".to_string()), None).unwrap().unwrap();
+        assert_eq!((target.address.as_str(), target.token.as_str()), ("https://agent-b:7070", "from-config"));
+        assert!(RemoteConfig::default().resolve(None, None).unwrap().is_none());
+
+        let missing = RemoteConfig { token_env: Some("SANDBOX_AGENT_TOKEN_UNSET_IN_TESTS".to_string()), ..Default::default() };
+        assert!(missing.resolve(Some("https://agent-c:7070".to_string()), None).is_err());
+    }
+
+    #[test]
+    fn test_token_comparison() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret-and-more"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+
+    #[test]
+    fn test_empty_token_is_rejected() {
+        let manager = Arc::new(AutoPauseManager::new(Default::default()));
+        assert!(AgentServer::new(manager.clone(), " ".to_string()).is_err());
+        assert!(AgentServer::new(manager, "secret".to_string()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_address_is_refused() {
+        let target = RemoteTarget {
+            address: "http://agent-a:7070".to_string(),
+            token: "t".to_string(),
+            ca_cert: None,
+        };
+        assert!(RemoteAgent::connect(&target).await.is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::{BTreeMap, BTreeSet};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+
+const CHECKPOINT_FILE: &str = "restore_checkpoint.json";
+
+/// Resume steps with effects outside the agent, which must not be repeated when an
+/// interrupted resume is retried. Registering the snapshot's processes is not one of
+/// them: that state lives in memory and is rebuilt on every attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreStep {
+    PreResumeHooks,
+    /// cgroup limits put back after a soft pause
+    SoftPauseReleased,
+    /// Spool files rewritten and missed jobs run
+    ScheduledJobs,
+    /// Units still active are skipped on a retry regardless; this also covers those that have exited since
+    SystemdUnits,
+    Banner,
+}
+
+/// Progress of a resume, persisted after each step so a resume interrupted by an agent
+/// crash picks up where it stopped
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreCheckpoint {
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed: BTreeSet<RestoreStep>,
+    /// Entries already done within a step that is not complete yet, e.g. missed cron
+    /// jobs already started
+    #[serde(default)]
+    pub entries: BTreeMap<RestoreStep, BTreeSet<String>>,
+}
+
+impl RestoreCheckpoint {
+    pub fn new() -> Self {
+        Self {
+            started_at: Some(Utc::now()),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_done(&self, step: RestoreStep) -> bool {
+        self.completed.contains(&step)
+    }
+
+    pub fn is_entry_done(&self, step: RestoreStep, entry: &str) -> bool {
+        self.is_done(step) || self.entries.get(&step).is_some_and(|entries| entries.contains(entry))
+    }
+}
+
+impl PersistenceManager {
+    pub async fn save_restore_checkpoint(&self, sandbox_id: &str, checkpoint: &RestoreCheckpoint) -> Result<(), Box<dyn std::error::Error>> {
+        let root = self.layout(sandbox_id).root().to_path_buf();
+        let json = serde_json::to_string(checkpoint)?;
+        self.bounded_write("restore checkpoint save", sandbox_id, async {
+            async_fs::create_dir_all(&root).await?;
+            let file_path = root.join(CHECKPOINT_FILE);
+            let temp_path = file_path.with_extension("tmp");
+            async_fs::write(&temp_path, json).await?;
+            async_fs::rename(&temp_path, &file_path).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The checkpoint of an unfinished resume, if one was interrupted
+    pub async fn load_restore_checkpoint(&self, sandbox_id: &str) -> Result<Option<RestoreCheckpoint>, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join(CHECKPOINT_FILE);
+        self.bounded("restore checkpoint load", sandbox_id, async {
+            if !blocking::path_exists(&file_path).await {
+                return Ok(None);
+            }
+            let json = async_fs::read_to_string(&file_path).await?;
+            Ok(Some(serde_json::from_str(&json)?))
+        })
+        .await
+    }
+
+    /// Forget resume progress, once the resume finished or a new pause makes it stale
+    pub async fn remove_restore_checkpoint(&self, sandbox_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join(CHECKPOINT_FILE);
+        self.bounded_write("restore checkpoint remove", sandbox_id, async {
+            if blocking::path_exists(&file_path).await {
+                async_fs::remove_file(&file_path).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_checkpoint_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let persistence = PersistenceManager::with_base_dir(dir.path().to_path_buf());
+        assert!(persistence.load_restore_checkpoint("sbx").await.unwrap().is_none());
+
+        let mut checkpoint = RestoreCheckpoint::new();
+        checkpoint.completed.insert(RestoreStep::ScheduledJobs);
+        checkpoint.entries.entry(RestoreStep::SystemdUnits).or_default().insert("alice/app.service".to_string());
+        persistence.save_restore_checkpoint("sbx", &checkpoint).await.unwrap();
+
+        let loaded = persistence.load_restore_checkpoint("sbx").await.unwrap().unwrap();
+        assert!(loaded.is_done(RestoreStep::ScheduledJobs));
+        assert!(loaded.is_entry_done(RestoreStep::ScheduledJobs, "any job"));
+        assert!(!loaded.is_done(RestoreStep::SystemdUnits));
+        assert!(loaded.is_entry_done(RestoreStep::SystemdUnits, "alice/app.service"));
+        assert!(!loaded.is_entry_done(RestoreStep::SystemdUnits, "alice/db.service"));
+
+        persistence.remove_restore_checkpoint("sbx").await.unwrap();
+        assert!(persistence.load_restore_checkpoint("sbx").await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,142 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use chrono::{DateTime, Utc};
+use log::warn;
+
+use crate::state_snapshot::PersistedProcess;
+
+/// Label naming the processes (by name, comma-separated) that must be restored first
+pub const AFTER_LABEL: &str = "restore.after";
+
+/// Label holding a process's restore priority; higher goes first, default 0
+pub const PRIORITY_LABEL: &str = "restore.priority";
+
+/// The order snapshot entries are restored or relaunched in, and what could not be honoured
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreOrder {
+    /// Indices into the snapshot's process list, in restore order
+    pub order: Vec<usize>,
+    /// PIDs on a dependency cycle; they are restored last, ignoring the dependencies between them
+    pub cycles: Vec<i32>,
+}
+
+impl RestoreOrder {
+    /// The processes in restore order
+    pub fn apply<T: Clone>(&self, processes: &[T]) -> Vec<T> {
+        self.order.iter().map(|&index| processes[index].clone()).collect()
+    }
+}
+
+/// Tie-breakers among processes whose dependencies are all restored
+type Rank = (Reverse<i64>, DateTime<Utc>, i32, usize);
+
+fn rank(index: usize, process: &PersistedProcess) -> Rank {
+    let priority = process.labels.get(PRIORITY_LABEL).and_then(|p| p.trim().parse().ok()).unwrap_or(0);
+    (Reverse(priority), process.start_time, process.pid, index)
+}
+
+/// Order in which to restore `processes`, independent of their order in the snapshot:
+///
+/// 1. Dependencies first: a process comes after every process named in its
+///    `restore.after` label. Names not in the snapshot are ignored.
+/// 2. Among processes whose dependencies are done, higher `restore.priority` first.
+/// 3. Then earlier original `start_time`, then lower PID, so the order is total.
+///
+/// Processes on a dependency cycle, and those depending on them, follow all others in
+/// the same priority/start-time order and are listed in `cycles`.
+pub fn restore_order(processes: &[PersistedProcess]) -> RestoreOrder {
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, process) in processes.iter().enumerate() {
+        by_name.entry(process.name.as_str()).or_default().push(index);
+    }
+
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); processes.len()];
+    let mut pending: Vec<usize> = vec![0; processes.len()];
+    for (index, process) in processes.iter().enumerate() {
+        let names: BTreeSet<&str> = process.labels.get(AFTER_LABEL).map(|after| after.split(',').map(str::trim).filter(|n| !n.is_empty()).collect()).unwrap_or_default();
+        for dependency in names.into_iter().flat_map(|name| by_name.get(name).into_iter().flatten()) {
+            if *dependency != index {
+                dependents[*dependency].push(index);
+                pending[index] += 1;
+            }
+        }
+    }
+
+    // Kahn's algorithm, always taking the best-ranked ready process
+    let mut ready: BTreeSet<Rank> = (0..processes.len()).filter(|&i| pending[i] == 0).map(|i| rank(i, &processes[i])).collect();
+    let mut order = Vec::with_capacity(processes.len());
+    while let Some(next) = ready.pop_first() {
+        let index = next.3;
+        order.push(index);
+        for &dependent in &dependents[index] {
+            pending[dependent] -= 1;
+            if pending[dependent] == 0 {
+                ready.insert(rank(dependent, &processes[dependent]));
+            }
+        }
+    }
+
+    let mut stuck: Vec<Rank> = (0..processes.len()).filter(|&i| pending[i] > 0).map(|i| rank(i, &processes[i])).collect();
+    stuck.sort();
+    let cycles = stuck.iter().map(|r| r.2).collect();
+    order.extend(stuck.into_iter().map(|r| r.3));
+    RestoreOrder { order, cycles }
+}
+
+/// `processes` reordered for restoring into `sandbox_id`, warning about dependency cycles
+pub fn in_restore_order(sandbox_id: &str, processes: &[PersistedProcess]) -> Vec<PersistedProcess> {
+    let order = restore_order(processes);
+    if !order.cycles.is_empty() {
+        warn!("Restore dependencies of sandbox {} form a cycle; restoring processes {:?} last", sandbox_id, order.cycles);
+    }
+    order.apply(processes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::test_support::{self, persisted_process};
+
+    fn process(pid: i32, name: &str, started: i64, labels: &[(&str, &str)]) -> PersistedProcess {
+        PersistedProcess {
+            labels: test_support::labels(labels),
+            ..persisted_process(pid, &[name], Utc.timestamp_opt(started, 0).unwrap())
+        }
+    }
+
+    fn pids(processes: &[PersistedProcess]) -> Vec<i32> {
+        restore_order(processes).apply(processes).iter().map(|p| p.pid).collect()
+    }
+
+    #[test]
+    fn test_dependencies_then_priority_then_start_time() {
+        let processes = vec![
+            process(10, "web", 100, &[(AFTER_LABEL, "db, cache")]),
+            process(11, "worker", 50, &[]),
+            process(12, "db", 300, &[]),
+            process(13, "cache", 200, &[(PRIORITY_LABEL, "5")]),
+            process(14, "cron", 50, &[]),
+        ];
+        // cache outranks everything; worker and cron tie on start time and go by PID;
+        // web waits for db although it started earlier
+        assert_eq!(pids(&processes), vec![13, 11, 14, 12, 10]);
+
+        // The snapshot order does not matter
+        let mut reversed = processes.clone();
+        reversed.reverse();
+        assert_eq!(pids(&reversed), vec![13, 11, 14, 12, 10]);
+    }
+
+    #[test]
+    fn test_cycles_go_last() {
+        let processes = vec![
+            process(1, "a", 10, &[(AFTER_LABEL, "b")]),
+            process(2, "b", 20, &[(AFTER_LABEL, "a")]),
+            process(3, "c", 30, &[(AFTER_LABEL, "missing")]),
+        ];
+        let order = restore_order(&processes);
+        assert_eq!(order.apply(&processes).iter().map(|p| p.pid).collect::<Vec<_>>(), vec![3, 1, 2]);
+        assert_eq!(order.cycles, vec![1, 2]);
+    }
+}
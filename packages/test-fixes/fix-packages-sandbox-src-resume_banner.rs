@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::{PauseStrategy, ResumeReport, RestoreStatus};
+use crate::blocking;
+use crate::lifecycle::{self, LifecycleRecord, LifecycleState};
+use crate::process;
+
+/// A note left inside the sandbox after resume, so interactive users can tell why
+/// their shell history has a gap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumeBannerConfig {
+    pub enabled: bool,
+    /// Where the banner is written; the default is picked up by pam_motd at the next login
+    pub path: PathBuf,
+    /// Also broadcast the banner to logged-in terminals with `wall`
+    pub wall: bool,
+}
+
+impl Default for ResumeBannerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: PathBuf::from("/run/motd.d/sandbox-resume"),
+            wall: false,
+        }
+    }
+}
+
+/// What the banner says about the last pause
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResumeBanner {
+    /// When the sandbox was paused, from its lifecycle record
+    pub paused_at: Option<DateTime<Utc>>,
+    pub resumed_at: DateTime<Utc>,
+    pub strategy: PauseStrategy,
+    pub restored: usize,
+    /// Snapshot entries with nothing running under their PID anymore
+    pub missing: usize,
+    /// Snapshot entries whose PID now belongs to another program
+    pub replaced: usize,
+    /// Wall-clock time that passed while the monotonic clock stood still; unknown
+    /// when the pause was recorded by another boot
+    pub clock_skew_ms: Option<i64>,
+    /// File the banner was written to
+    pub path: Option<PathBuf>,
+}
+
+impl ResumeBanner {
+    /// Describe a resume; `paused` is the lifecycle record as it stood before the resume began
+    pub fn new(strategy: PauseStrategy, paused: Option<&LifecycleRecord>, report: &ResumeReport) -> Self {
+        let paused = paused.filter(|record| record.state == LifecycleState::Paused);
+        let resumed_at = Utc::now();
+        let clock_skew_ms = paused.and_then(|record| {
+            let (then, now) = (record.monotonic_ms?, lifecycle::monotonic_ms()?);
+            // A monotonic clock that went backwards means the guest rebooted
+            let monotonic = now.checked_sub(then)? as i64;
+            Some((resumed_at - record.updated_at).num_milliseconds() - monotonic)
+        });
+        Self {
+            paused_at: paused.map(|record| record.updated_at),
+            resumed_at,
+            strategy,
+            restored: report.count(RestoreStatus::Verified) + report.count(RestoreStatus::Adopted),
+            missing: report.count(RestoreStatus::Missing),
+            replaced: report.count(RestoreStatus::Replaced),
+            clock_skew_ms,
+            path: None,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = Vec::new();
+        match self.paused_at {
+            Some(paused_at) => lines.push(format!(
+                "This sandbox was paused from {} to {} ({}).",
+                paused_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                self.resumed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+                format_millis((self.resumed_at - paused_at).num_milliseconds())
+            )),
+            None => lines.push(format!("This sandbox was resumed at {}.", self.resumed_at.format("%Y-%m-%d %H:%M:%S UTC"))),
+        }
+        match self.strategy {
+            PauseStrategy::Kill => lines.push("Processes running at the pause were terminated and have not been restarted.".to_string()),
+            PauseStrategy::Persist | PauseStrategy::Throttle | PauseStrategy::Auto => lines.push(format!(
+                "{} processes came back; {} were no longer running and {} PIDs now belong to other programs.",
+                self.restored, self.missing, self.replaced
+            )),
+        }
+        if let Some(skew) = self.clock_skew_ms.filter(|skew| *skew >= 1000) {
+            lines.push(format!(
+                "The system clock jumped forward by {}; timers, logs and shell history show a gap.",
+                format_millis(skew)
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// `1h 2m 3s`, dropping leading zero units
+fn format_millis(millis: i64) -> String {
+    let secs = millis.max(0) / 1000;
+    let (hours, minutes, seconds) = (secs / 3600, secs % 3600 / 60, secs % 60);
+    match (hours, minutes) {
+        (0, 0) => format!("{}s", seconds),
+        (0, _) => format!("{}m {}s", minutes, seconds),
+        _ => format!("{}h {}m {}s", hours, minutes, seconds),
+    }
+}
+
+/// Write the banner, and broadcast it if configured. Best effort: the resume has
+/// already succeeded, so failures are only logged.
+pub async fn publish(config: &ResumeBannerConfig, sandbox_id: &str, mut banner: ResumeBanner) -> ResumeBanner {
+    let text = banner.render();
+    let (path, contents) = (config.path.clone(), text.clone());
+    let written = blocking::run("resume_banner", move || {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("creating {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(&path, contents).map_err(|e| format!("writing {}: {}", path.display(), e))
+    })
+    .await;
+    match written {
+        Ok(()) => {
+            info!("Wrote resume banner for sandbox {} to {}", sandbox_id, config.path.display());
+            banner.path = Some(config.path.clone());
+        }
+        Err(e) => warn!("Failed to write resume banner for sandbox {}: {}", sandbox_id, e),
+    }
+
+    if config.wall {
+        match process::helper_status(tokio::process::Command::new("wall").arg(&text)).await {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("wall exited with {} for sandbox {}", status, sandbox_id),
+            Err(e) => warn!("Failed to run wall for sandbox {}: {}", sandbox_id, e),
+        }
+    }
+    banner
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::RestoredProcess;
+    use chrono::Duration;
+
+    #[tokio::test]
+    async fn test_banner_describes_pause_window() {
+        let record = LifecycleRecord {
+            state: LifecycleState::Paused,
+            updated_at: Utc::now() - Duration::minutes(90),
+            monotonic_ms: None,
+        };
+        let report = ResumeReport {
+            restored: vec![
+                RestoredProcess { pid: 1, name: "bash".to_string(), status: RestoreStatus::Verified, adopted_pid: None },
+                RestoredProcess { pid: 2, name: "vim".to_string(), status: RestoreStatus::Missing, adopted_pid: None },
+            ],
+            ..ResumeReport::default()
+        };
+        let banner = ResumeBanner::new(PauseStrategy::Persist, Some(&record), &report);
+        assert_eq!((banner.restored, banner.missing, banner.replaced), (1, 1, 0));
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = ResumeBannerConfig {
+            enabled: true,
+            path: dir.path().join("motd.d/sandbox-resume"),
+            wall: false,
+        };
+        let banner = publish(&config, "sb", banner).await;
+        let text = std::fs::read_to_string(banner.path.unwrap()).unwrap();
+        assert!(text.contains("(1h 30m 0s)"));
+        assert!(text.contains("1 processes came back; 1 were no longer running"));
+        // Without a monotonic reading the jump is unknown rather than guessed
+        assert_eq!(banner.clock_skew_ms, None);
+        assert!(!text.contains("jumped"));
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::{Serialize, Deserialize};
+
+use crate::registry::PriorityClass;
+
+/// Concurrency limits for batch resumes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumeSchedulerConfig {
+    /// Resumes running at once across all classes (default: 16)
+    pub max_concurrent: usize,
+    /// Per-class caps; classes not listed are only bound by `max_concurrent`
+    pub class_limits: HashMap<PriorityClass, usize>,
+}
+
+impl Default for ResumeSchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 16,
+            class_limits: HashMap::new(),
+        }
+    }
+}
+
+/// A sandbox waiting to be resumed
+#[derive(Debug, Clone)]
+pub struct ResumeJob {
+    pub sandbox_id: String,
+    pub priority: PriorityClass,
+}
+
+/// Dispatches resumes highest class first, honouring global and per-class limits
+pub struct ResumeScheduler {
+    config: ResumeSchedulerConfig,
+}
+
+impl ResumeScheduler {
+    pub fn new(config: ResumeSchedulerConfig) -> Self {
+        Self { config }
+    }
+
+    fn class_limit(&self, priority: PriorityClass) -> usize {
+        self.config
+            .class_limits
+            .get(&priority)
+            .copied()
+            .unwrap_or(self.config.max_concurrent)
+            .max(1)
+    }
+
+    /// Run `resume` for every job. Whenever a slot frees up, the highest-priority
+    /// waiting job whose class is under its limit starts next; within a class,
+    /// jobs start in submission order. Results are returned in completion order.
+    pub async fn run<F, Fut, T>(&self, mut jobs: Vec<ResumeJob>, resume: F) -> Vec<(String, T)>
+    where
+        F: Fn(String) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        // Stable sort keeps submission order within a class
+        jobs.sort_by(|a, b| b.priority.cmp(&a.priority));
+        let mut pending: VecDeque<ResumeJob> = jobs.into();
+        let mut running: HashMap<PriorityClass, usize> = HashMap::new();
+        let mut in_flight = FuturesUnordered::new();
+        let mut results = Vec::new();
+        let max_concurrent = self.config.max_concurrent.max(1);
+
+        loop {
+            while in_flight.len() < max_concurrent {
+                let next = pending
+                    .iter()
+                    .position(|job| running.get(&job.priority).copied().unwrap_or(0) < self.class_limit(job.priority));
+                let Some(job) = next.and_then(|idx| pending.remove(idx)) else {
+                    break;
+                };
+
+                *running.entry(job.priority).or_default() += 1;
+                let fut = resume(job.sandbox_id.clone());
+                in_flight.push(async move { (job, fut.await) });
+            }
+
+            match in_flight.next().await {
+                Some((job, output)) => {
+                    if let Some(count) = running.get_mut(&job.priority) {
+                        *count -= 1;
+                    }
+                    results.push((job.sandbox_id, output));
+                }
+                None => break,
+            }
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_priority_order_and_class_limits() {
+        let scheduler = ResumeScheduler::new(ResumeSchedulerConfig {
+            max_concurrent: 2,
+            class_limits: HashMap::from([(PriorityClass::Low, 1)]),
+        });
+        let jobs = vec![
+            ResumeJob { sandbox_id: "low-1".to_string(), priority: PriorityClass::Low },
+            ResumeJob { sandbox_id: "low-2".to_string(), priority: PriorityClass::Low },
+            ResumeJob { sandbox_id: "crit".to_string(), priority: PriorityClass::Critical },
+            ResumeJob { sandbox_id: "normal".to_string(), priority: PriorityClass::Normal },
+        ];
+
+        let started = Mutex::new(Vec::new());
+        let low_running = AtomicUsize::new(0);
+        let max_low_running = AtomicUsize::new(0);
+
+        scheduler
+            .run(jobs, |id| {
+                started.lock().unwrap().push(id.clone());
+                let is_low = id.starts_with("low");
+                let (low_running, max_low_running) = (&low_running, &max_low_running);
+                async move {
+                    if is_low {
+                        let now = low_running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_low_running.fetch_max(now, Ordering::SeqCst);
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    if is_low {
+                        low_running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                }
+            })
+            .await;
+
+        let started = started.into_inner().unwrap();
+        assert_eq!(&started[..2], &["crit".to_string(), "normal".to_string()]);
+        assert_eq!(started.len(), 4);
+        assert_eq!(max_low_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_omitted_fields_take_their_defaults() {
+        let config: ResumeSchedulerConfig = serde_json::from_str(r#"{ "class_limits": { "low": 1 } }"#).unwrap();
+        assert_eq!(config.max_concurrent, 16);
+        assert_eq!(config.class_limits, HashMap::from([(PriorityClass::Low, 1)]));
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+use crate::metrics;
+
+/// A worker busy for at least this share of a sampling interval is likely blocked
+/// by synchronous work on the async runtime
+const BLOCKED_BUSY_RATIO: f64 = 0.99;
+
+/// Point-in-time view of the tokio runtime. Fields marked optional need the agent to
+/// be built with `RUSTFLAGS="--cfg tokio_unstable"`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuntimeStats {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks queued on the shared injection queue, not yet picked up by a worker
+    pub global_queue_depth: usize,
+    /// Total time each worker has spent busy since the runtime started
+    pub worker_busy_ms: Vec<u64>,
+    pub blocking_threads: Option<usize>,
+    pub idle_blocking_threads: Option<usize>,
+    pub blocking_queue_depth: Option<usize>,
+    /// Mean time each worker spends polling a task
+    pub worker_mean_poll_us: Option<Vec<u64>>,
+}
+
+impl RuntimeStats {
+    /// Sample the runtime the caller runs on, or None outside of one
+    pub fn sample() -> Option<Self> {
+        let metrics = Handle::try_current().ok()?.metrics();
+        let workers = metrics.num_workers();
+        #[allow(unused_mut)]
+        let mut stats = Self {
+            workers,
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            worker_busy_ms: (0..workers).map(|worker| metrics.worker_total_busy_duration(worker).as_millis() as u64).collect(),
+            ..Default::default()
+        };
+        #[cfg(tokio_unstable)]
+        {
+            stats.blocking_threads = Some(metrics.num_blocking_threads());
+            stats.idle_blocking_threads = Some(metrics.num_idle_blocking_threads());
+            stats.blocking_queue_depth = Some(metrics.blocking_queue_depth());
+            stats.worker_mean_poll_us = Some((0..workers).map(|worker| metrics.worker_mean_poll_time(worker).as_micros() as u64).collect());
+        }
+        Some(stats)
+    }
+}
+
+/// Share of `interval` each worker spent busy between two samples
+fn busy_ratios(previous: &RuntimeStats, current: &RuntimeStats, interval: Duration) -> Vec<f64> {
+    let interval_ms = interval.as_millis().max(1) as f64;
+    current
+        .worker_busy_ms
+        .iter()
+        .zip(&previous.worker_busy_ms)
+        .map(|(now, before)| (now.saturating_sub(*before) as f64 / interval_ms).min(1.0))
+        .collect()
+}
+
+/// Publishes runtime metrics periodically and warns about workers that look blocked
+pub struct RuntimeMetricsSampler {
+    interval: Duration,
+}
+
+impl RuntimeMetricsSampler {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+
+    /// Run the sampler in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            let mut previous: Option<(RuntimeStats, Instant)> = None;
+            loop {
+                ticker.tick().await;
+                let Some(stats) = RuntimeStats::sample() else {
+                    return;
+                };
+                let ratios = match &previous {
+                    Some((before, at)) => busy_ratios(before, &stats, at.elapsed()),
+                    None => Vec::new(),
+                };
+                for (worker, ratio) in ratios.iter().enumerate() {
+                    if *ratio >= BLOCKED_BUSY_RATIO {
+                        warn!("Runtime worker {} was busy for the whole {:?} interval; a task may be blocking it", worker, self.interval);
+                    }
+                }
+                metrics::record_runtime(&stats, &ratios);
+                previous = Some((stats, Instant::now()));
+            }
+        })
+    }
+}
+
+/// Serve task instrumentation to `tokio-console`, listening on the address in
+/// `TOKIO_CONSOLE_BIND` (default 127.0.0.1:6669). Needs the `console` feature and a
+/// `tokio_unstable` build; call once, from within the runtime, before spawning work.
+#[cfg(feature = "console")]
+pub fn init_console() {
+    use tracing_subscriber::prelude::*;
+
+    let layer = console_subscriber::ConsoleLayer::builder().with_default_env().spawn();
+    match tracing_subscriber::registry().with(layer).try_init() {
+        Ok(()) => info!("tokio-console instrumentation enabled"),
+        Err(e) => warn!("tokio-console instrumentation not enabled: {}", e),
+    }
+}
+
+/// Without the `console` feature there is nothing to serve
+#[cfg(not(feature = "console"))]
+pub fn init_console() {
+    info!("tokio-console instrumentation requested, but the agent was built without the console feature");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_sample_and_busy_ratios() {
+        let stats = RuntimeStats::sample().unwrap();
+        assert_eq!(stats.workers, 2);
+        assert_eq!(stats.worker_busy_ms.len(), 2);
+
+        let before = RuntimeStats { worker_busy_ms: vec![100, 100], ..Default::default() };
+        let after = RuntimeStats { worker_busy_ms: vec![600, 1200], ..Default::default() };
+        assert_eq!(busy_ratios(&before, &after, Duration::from_secs(1)), vec![0.5, 1.0]);
+    }
+}
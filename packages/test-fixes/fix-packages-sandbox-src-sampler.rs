@@ -0,0 +1,89 @@
+#![cfg(target_os = "linux")]
+
+use std::time::Duration;
+use log::debug;
+use tokio::task::JoinHandle;
+
+use crate::blocking;
+use crate::process::{ProcessManager, ProcessState, SessionRef};
+use crate::procfs;
+
+/// Fresh counters of one process; `session` is set only when it changed
+struct Sample {
+    pid: i32,
+    threads: u32,
+    children: u32,
+    session: Option<SessionRef>,
+}
+
+/// Periodically refreshes live per-process counters from /proc
+pub struct Sampler {
+    process_manager: ProcessManager,
+    interval: Duration,
+}
+
+impl Sampler {
+    pub fn new(process_manager: ProcessManager, interval: Duration) -> Self {
+        Self { process_manager, interval }
+    }
+
+    /// Run the sampler in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.interval);
+            loop {
+                ticker.tick().await;
+                self.sample_once().await;
+            }
+        })
+    }
+
+    /// Refresh thread and child counts and session membership for every tracked process
+    pub async fn sample_once(&self) {
+        for sandbox_id in self.process_manager.sandbox_ids().await {
+            let Ok(processes) = self.process_manager.list_processes(&sandbox_id).await else {
+                continue;
+            };
+
+            let known: Vec<(i32, Option<i32>)> = processes
+                .into_iter()
+                .filter(|p| p.state != ProcessState::Terminated)
+                .map(|p| (p.pid, p.session.as_ref().map(|s| s.session_id)))
+                .collect();
+            let log_id = sandbox_id.clone();
+            // One blocking job per sandbox for all of its /proc reads
+            let samples = blocking::run("sample_procfs", move || {
+                known
+                    .into_iter()
+                    .filter_map(|(pid, known_session)| {
+                        let Some(stat) = procfs::read_stat(pid) else {
+                            debug!("Process {} in sandbox {} vanished before sampling", pid, log_id);
+                            return None;
+                        };
+                        let session = (known_session != Some(stat.session)).then(|| SessionRef {
+                            session_id: stat.session,
+                            login_uid: procfs::read_loginuid(pid),
+                            tty: procfs::tty_path(stat.tty_nr),
+                        });
+                        Some(Sample {
+                            pid,
+                            threads: stat.num_threads,
+                            children: procfs::child_count(pid),
+                            session,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await;
+
+            for sample in samples {
+                self.process_manager
+                    .update_process_counts(&sandbox_id, sample.pid, sample.threads, sample.children)
+                    .await;
+                if let Some(session) = sample.session {
+                    self.process_manager.set_session(&sandbox_id, sample.pid, session).await;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,684 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::net::UnixListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::{AbortHandle, JoinHandle};
+
+use crate::auto_pause::{AutoPauseManager, PauseOptions, PauseTrigger};
+use crate::channel_protocol::{AgentMessage, ClientMessage, PauseRecord, PROTOCOL_VERSION};
+use crate::events::{EventBus, EventPayload};
+use crate::hooks::{hook_fn, HookErrorPolicy, HookPhase, HookRegistry, RegisteredHook};
+use crate::sandbox_id::SandboxId;
+use crate::stats::{StatsStore, TimelineEvent, TimelinePoint};
+
+/// Name the channel's lifecycle hooks are registered under
+pub const CHANNEL_HOOK: &str = "sandbox-channel";
+
+/// Longest hold reason kept; longer ones are cut
+const MAX_REASON_LEN: usize = 256;
+
+/// Most pauses returned for one history request
+const MAX_HISTORY: usize = 100;
+
+/// A pause hold taken by an in-sandbox application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseHoldInfo {
+    pub hold_id: u64,
+    pub app: String,
+    pub reason: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    holds: BTreeMap<u64, PauseHoldInfo>,
+    next_hold: u64,
+    heartbeats: HashMap<String, DateTime<Utc>>,
+    /// Connections registered as pause handlers
+    handlers: usize,
+    /// Keep-alive tokens of announced pauses, until the pause is due
+    keep_alive_tokens: HashMap<String, DateTime<Utc>>,
+    /// Keep-alives granted since the sandbox last paused
+    keep_alives: u32,
+}
+
+/// Agent side of the in-sandbox channel: serves `SandboxClient`s of one sandbox on
+/// a unix socket. Its hooks refuse pauses while a hold is active and give pause
+/// handlers a chance to ack before the pause goes ahead.
+pub struct SandboxChannel {
+    sandbox_id: String,
+    path: PathBuf,
+    stats: Arc<StatsStore>,
+    ack_timeout: Duration,
+    max_hold: Duration,
+    max_holds: usize,
+    #[cfg(feature = "vsock")]
+    vsock_port: Option<u32>,
+    grace_lead: Duration,
+    keep_alive: Duration,
+    max_keep_alives: u32,
+    events: Option<EventBus>,
+    state: Mutex<ChannelState>,
+    listeners: Mutex<Vec<AbortHandle>>,
+    notices: broadcast::Sender<AgentMessage>,
+    acks_tx: mpsc::UnboundedSender<String>,
+    acks: tokio::sync::Mutex<mpsc::UnboundedReceiver<String>>,
+}
+
+impl SandboxChannel {
+    pub fn new(sandbox_id: impl Into<String>, path: impl AsRef<Path>, stats: Arc<StatsStore>) -> Self {
+        let (acks_tx, acks) = mpsc::unbounded_channel();
+        Self {
+            sandbox_id: sandbox_id.into(),
+            path: path.as_ref().to_path_buf(),
+            stats,
+            ack_timeout: Duration::from_secs(5),
+            max_hold: Duration::from_secs(15 * 60),
+            max_holds: 16,
+            #[cfg(feature = "vsock")]
+            vsock_port: None,
+            grace_lead: Duration::from_secs(5 * 60),
+            keep_alive: Duration::from_secs(15 * 60),
+            max_keep_alives: 3,
+            events: None,
+            state: Mutex::new(ChannelState::default()),
+            listeners: Mutex::new(Vec::new()),
+            notices: broadcast::channel(16).0,
+            acks_tx,
+            acks: tokio::sync::Mutex::new(acks),
+        }
+    }
+
+    /// How long a pause waits for handlers to ack (default: 5s)
+    pub fn with_ack_timeout(mut self, timeout: Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    /// Longest hold granted, whatever the client asks for (default: 15 minutes)
+    pub fn with_max_hold(mut self, max_hold: Duration) -> Self {
+        self.max_hold = max_hold;
+        self
+    }
+
+    /// Most holds active at once across the sandbox's applications (default: 16)
+    pub fn with_max_holds(mut self, max_holds: usize) -> Self {
+        self.max_holds = max_holds;
+        self
+    }
+
+    /// Also accept connections on vsock `port`, from sandboxes running as VMs
+    #[cfg(feature = "vsock")]
+    pub fn with_vsock_port(mut self, port: u32) -> Self {
+        self.vsock_port = Some(port);
+        self
+    }
+
+    /// How long before an announced pause its grace notice goes out (default: 5 minutes)
+    pub fn with_grace_lead(mut self, lead: Duration) -> Self {
+        self.grace_lead = lead;
+        self
+    }
+
+    /// Hold granted by a keep-alive, within the max hold (default: 15 minutes)
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// Keep-alives granted between two pauses; once used up, the next pause goes
+    /// ahead (default: 3)
+    pub fn with_max_keep_alives(mut self, max_keep_alives: u32) -> Self {
+        self.max_keep_alives = max_keep_alives;
+        self
+    }
+
+    /// Also publish grace notices as events, for webhooks and notifications
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Tell the sandbox's users an automatic pause is coming at `pause_at`. Pause
+    /// handlers get a grace notice and can answer with a keep-alive; the event carries
+    /// a token that `keep_alive` redeems until the pause is due.
+    pub fn announce_pause(&self, trigger: PauseTrigger, pause_at: DateTime<Utc>) -> Result<String, getrandom::Error> {
+        let token = keep_alive_token()?;
+        {
+            let mut state = self.state.lock().unwrap();
+            let now = Utc::now();
+            state.keep_alive_tokens.retain(|_, due| *due > now);
+            state.keep_alive_tokens.insert(token.clone(), pause_at);
+        }
+        info!("Announcing {:?} pause of sandbox {} at {}", trigger, self.sandbox_id, pause_at);
+        let notice = AgentMessage::GraceNotice {
+            trigger: serde_json::to_value(trigger).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default(),
+            pause_at,
+            keep_alive_secs: self.keep_alive.min(self.max_hold).as_secs(),
+        };
+        let _ = self.notices.send(notice);
+        if let Some(events) = &self.events {
+            let payload = EventPayload::GraceNotice { trigger, pause_at, keep_alive_token: token.clone() };
+            events.publish(&self.sandbox_id, payload);
+        }
+        Ok(token)
+    }
+
+    /// Announce a pause due at `pause_at` once it is within the grace lead
+    pub fn schedule_grace_notice(self: &Arc<Self>, trigger: PauseTrigger, pause_at: DateTime<Utc>) -> JoinHandle<()> {
+        let channel = self.clone();
+        tokio::spawn(async move {
+            let lead = chrono::Duration::from_std(channel.grace_lead).unwrap_or_default();
+            if let Ok(wait) = (pause_at - lead - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+            if let Err(e) = channel.announce_pause(trigger, pause_at) {
+                warn!("Failed to announce the {:?} pause of sandbox {}: {}", trigger, channel.sandbox_id, e);
+            }
+        })
+    }
+
+    /// Redeem the token of a grace notice for a keep-alive hold; None if it is unknown,
+    /// its pause is already due or the sandbox used up its keep-alives
+    pub fn keep_alive(&self, token: &str) -> Option<PauseHoldInfo> {
+        let mut state = self.state.lock().unwrap();
+        let due = state.keep_alive_tokens.remove(token)?;
+        if due <= Utc::now() {
+            return None;
+        }
+        self.grant_keep_alive(&mut state, "keep-alive", "kept alive from a grace notice".to_string()).ok()
+    }
+
+    /// Holds that have not expired, oldest first
+    pub fn active_holds(&self) -> Vec<PauseHoldInfo> {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now();
+        state.holds.retain(|_, hold| hold.expires_at > now);
+        state.holds.values().cloned().collect()
+    }
+
+    /// Last heartbeat per application
+    pub fn heartbeats(&self) -> HashMap<String, DateTime<Utc>> {
+        self.state.lock().unwrap().heartbeats.clone()
+    }
+
+    /// Refuse pauses of this sandbox while a hold is active, notify pause handlers
+    /// before a pause and after a resume
+    pub fn register_hooks(self: &Arc<Self>, hooks: &HookRegistry) {
+        let channel = self.clone();
+        let before_pause = hook_fn(move |context| {
+            let channel = channel.clone();
+            Box::pin(async move {
+                if context.sandbox_id != channel.sandbox_id {
+                    return Ok(());
+                }
+                channel.before_pause().await
+            })
+        });
+        let channel = self.clone();
+        let after_resume = hook_fn(move |context| {
+            let channel = channel.clone();
+            Box::pin(async move {
+                if context.sandbox_id == channel.sandbox_id {
+                    channel.state.lock().unwrap().keep_alives = 0;
+                    let paused_at = channel.history(1).pop().map(|pause| pause.paused_at);
+                    let _ = channel.notices.send(AgentMessage::Resumed { paused_at });
+                }
+                Ok(())
+            })
+        });
+        hooks.register(
+            RegisteredHook::new(self.hook_name(), HookPhase::PrePause, before_pause)
+                .with_timeout(self.ack_timeout + Duration::from_secs(1))
+                .with_error_policy(HookErrorPolicy::Abort),
+        );
+        hooks.register(RegisteredHook::new(self.hook_name(), HookPhase::PostResume, after_resume));
+    }
+
+    /// Name of this channel's hooks, one per sandbox so each can be unregistered alone
+    pub fn hook_name(&self) -> String {
+        format!("{}:{}", CHANNEL_HOOK, self.sandbox_id)
+    }
+
+    /// Stop accepting connections; ones already open are served until they close
+    pub fn close(&self) {
+        for listener in self.listeners.lock().unwrap().drain(..) {
+            listener.abort();
+        }
+    }
+
+    /// Serve one connection in the background
+    fn serve<S>(self: &Arc<Self>, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let channel = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = channel.handle(stream).await {
+                debug!("Sandbox channel connection for {} failed: {}", channel.sandbox_id, e);
+            }
+        });
+    }
+
+    /// Listen on the socket, replacing a stale one left by a previous agent, and on
+    /// the vsock port if one is set. The returned task is the unix listener's.
+    pub async fn spawn(self: Arc<Self>) -> Result<JoinHandle<()>, Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        if let Err(e) = tokio::fs::remove_file(&self.path).await {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+        let listener = UnixListener::bind(&self.path)?;
+        #[cfg(feature = "vsock")]
+        if let Some(port) = self.vsock_port {
+            self.spawn_vsock(port)?;
+        }
+        info!("Serving sandbox channel for {} on {}", self.sandbox_id, self.path.display());
+        let channel = self.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => channel.serve(stream),
+                    Err(e) => {
+                        warn!("Failed to accept sandbox channel connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        });
+        self.listeners.lock().unwrap().push(task.abort_handle());
+        Ok(task)
+    }
+
+    #[cfg(feature = "vsock")]
+    fn spawn_vsock(self: &Arc<Self>, port: u32) -> io::Result<()> {
+        use tokio_vsock::{VsockAddr, VsockListener, VMADDR_CID_ANY};
+
+        let mut listener = VsockListener::bind(VsockAddr::new(VMADDR_CID_ANY, port))?;
+        info!("Serving sandbox channel for {} on vsock port {}", self.sandbox_id, port);
+        let channel = self.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => channel.serve(stream),
+                    Err(e) => {
+                        warn!("Failed to accept sandbox channel connection on vsock: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        });
+        self.listeners.lock().unwrap().push(task.abort_handle());
+        Ok(())
+    }
+
+    async fn handle<S: AsyncRead + AsyncWrite>(&self, stream: S) -> io::Result<()> {
+        let (read, mut write) = tokio::io::split(stream);
+        let mut lines = BufReader::new(read).lines();
+        let Some(first) = lines.next_line().await? else {
+            return Ok(());
+        };
+        let (app, pause_handler) = match serde_json::from_str::<ClientMessage>(&first) {
+            Ok(ClientMessage::Hello { app, protocol, pause_handler }) if protocol == PROTOCOL_VERSION => (app, pause_handler),
+            Ok(ClientMessage::Hello { protocol, .. }) => {
+                let message = format!("unsupported protocol {}, agent speaks {}", protocol, PROTOCOL_VERSION);
+                return send(&mut write, &AgentMessage::Error { message }).await;
+            }
+            _ => return send(&mut write, &AgentMessage::Error { message: "expected hello".to_string() }).await,
+        };
+        let welcome = AgentMessage::Welcome {
+            protocol: PROTOCOL_VERSION,
+            sandbox_id: self.sandbox_id.clone(),
+        };
+        send(&mut write, &welcome).await?;
+        debug!("{} connected to the sandbox channel of {}", app, self.sandbox_id);
+
+        if pause_handler {
+            return self.serve_handler(&app, lines, write).await;
+        }
+        while let Some(line) = lines.next_line().await? {
+            let reply = match serde_json::from_str::<ClientMessage>(&line) {
+                Ok(message) => self.reply(&app, message),
+                Err(e) => AgentMessage::Error { message: format!("invalid message: {}", e) },
+            };
+            send(&mut write, &reply).await?;
+        }
+        Ok(())
+    }
+
+    async fn serve_handler<R, W>(&self, app: &str, mut lines: Lines<R>, mut write: W) -> io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        self.state.lock().unwrap().handlers += 1;
+        let served = self.forward_notices(app, &mut lines, &mut write).await;
+        self.state.lock().unwrap().handlers -= 1;
+        served
+    }
+
+    /// Forward notices to a pause handler and collect its acks until it disconnects
+    async fn forward_notices<R, W>(&self, app: &str, lines: &mut Lines<R>, write: &mut W) -> io::Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut notices = self.notices.subscribe();
+        loop {
+            tokio::select! {
+                notice = notices.recv() => match notice {
+                    Ok(notice) => send(write, &notice).await?,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                line = lines.next_line() => match line?.map(|line| serde_json::from_str::<ClientMessage>(&line)) {
+                    Some(Ok(ClientMessage::PauseAck)) => {
+                        let _ = self.acks_tx.send(app.to_string());
+                    }
+                    Some(Ok(ClientMessage::Heartbeat)) => {
+                        self.state.lock().unwrap().heartbeats.insert(app.to_string(), Utc::now());
+                    }
+                    Some(_) => debug!("Ignoring unexpected message from pause handler {}", app),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    fn reply(&self, app: &str, message: ClientMessage) -> AgentMessage {
+        let mut state = self.state.lock().unwrap();
+        match message {
+            ClientMessage::Heartbeat => {
+                state.heartbeats.insert(app.to_string(), Utc::now());
+                AgentMessage::Ok
+            }
+            ClientMessage::AcquireHold { reason, ttl_secs } => match self.grant_hold(&mut state, app, reason, Duration::from_secs(ttl_secs)) {
+                Ok(hold) => AgentMessage::HoldGranted { hold_id: hold.hold_id, expires_at: hold.expires_at },
+                Err(message) => AgentMessage::Error { message },
+            },
+            ClientMessage::KeepAlive => match self.grant_keep_alive(&mut state, app, "keep alive".to_string()) {
+                Ok(hold) => AgentMessage::HoldGranted { hold_id: hold.hold_id, expires_at: hold.expires_at },
+                Err(message) => AgentMessage::Error { message },
+            },
+            ClientMessage::ReleaseHold { hold_id } => match state.holds.remove(&hold_id) {
+                Some(_) => AgentMessage::Ok,
+                None => AgentMessage::Error { message: format!("no hold {}", hold_id) },
+            },
+            ClientMessage::History { limit } => {
+                drop(state);
+                AgentMessage::History { pauses: self.history(limit.min(MAX_HISTORY)) }
+            }
+            ClientMessage::Hello { .. } | ClientMessage::PauseAck => AgentMessage::Error {
+                message: "unexpected message".to_string(),
+            },
+        }
+    }
+
+    /// Refused once `max_holds` holds are active; the ttl is capped at `max_hold`
+    fn grant_hold(&self, state: &mut ChannelState, app: &str, mut reason: String, ttl: Duration) -> Result<PauseHoldInfo, String> {
+        let now = Utc::now();
+        state.holds.retain(|_, hold| hold.expires_at > now);
+        if state.holds.len() >= self.max_holds {
+            return Err(format!("sandbox {} already has {} holds", self.sandbox_id, self.max_holds));
+        }
+        if let Some((cut, _)) = reason.char_indices().nth(MAX_REASON_LEN) {
+            reason.truncate(cut);
+        }
+        state.next_hold += 1;
+        let ttl = ttl.min(self.max_hold);
+        let hold = PauseHoldInfo {
+            hold_id: state.next_hold,
+            app: app.to_string(),
+            reason,
+            expires_at: now + chrono::Duration::from_std(ttl).unwrap_or_default(),
+        };
+        info!("{} holds sandbox {} until {}: {}", app, self.sandbox_id, hold.expires_at, hold.reason);
+        state.holds.insert(hold.hold_id, hold.clone());
+        Ok(hold)
+    }
+
+    fn grant_keep_alive(&self, state: &mut ChannelState, app: &str, reason: String) -> Result<PauseHoldInfo, String> {
+        if state.keep_alives >= self.max_keep_alives {
+            info!("{} asked to keep sandbox {} alive, but its {} keep-alives are used up", app, self.sandbox_id, self.max_keep_alives);
+            return Err(format!("all {} keep-alives used since the last pause", self.max_keep_alives));
+        }
+        let hold = self.grant_hold(state, app, reason, self.keep_alive)?;
+        state.keep_alives += 1;
+        Ok(hold)
+    }
+
+    fn history(&self, limit: usize) -> Vec<PauseRecord> {
+        let timeline = self.stats.sandbox(&self.sandbox_id).map(|stats| stats.timeline).unwrap_or_default();
+        pause_history(&timeline, limit)
+    }
+
+    async fn before_pause(&self) -> Result<(), String> {
+        if let Some(hold) = self.active_holds().first() {
+            return Err(format!("held by {} until {}: {}", hold.app, hold.expires_at, hold.reason));
+        }
+
+        // The pause goes ahead, so the next one may be put off again
+        self.state.lock().unwrap().keep_alives = 0;
+        let mut acks = self.acks.lock().await;
+        // Late acks from a previous pause
+        while acks.try_recv().is_ok() {}
+        let expected = self.state.lock().unwrap().handlers;
+        if expected == 0 {
+            return Ok(());
+        }
+        let ack_within_ms = self.ack_timeout.as_millis() as u64;
+        let _ = self.notices.send(AgentMessage::PauseNotice { ack_within_ms });
+        let deadline = tokio::time::Instant::now() + self.ack_timeout;
+        for acked in 0..expected {
+            if !matches!(tokio::time::timeout_at(deadline, acks.recv()).await, Ok(Some(_))) {
+                warn!("Only {} of {} pause handlers of sandbox {} acked in time", acked, expected, self.sandbox_id);
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AutoPauseManager {
+    /// Serve the in-sandbox channel of `sandbox_id` on `path`, replacing one already
+    /// attached; its holds and pause handlers take part in the sandbox's pauses
+    pub async fn attach_channel(&self, sandbox_id: &str, path: &Path) -> Result<Arc<SandboxChannel>, Box<dyn std::error::Error>> {
+        let channel = Arc::new(SandboxChannel::new(sandbox_id, path, self.stats().clone()).with_events(self.events().clone()));
+        self.detach_channel(sandbox_id);
+        let task = channel.clone().spawn().await?;
+        self.track_task(format!("sandbox_channel:{}", sandbox_id), &task);
+        channel.register_hooks(self.hooks());
+        self.channels().lock().unwrap().insert(SandboxId::from(sandbox_id), channel.clone());
+        Ok(channel)
+    }
+
+    /// Stop serving the sandbox's channel; false if none was attached
+    pub fn detach_channel(&self, sandbox_id: &str) -> bool {
+        let Some(channel) = self.channels().lock().unwrap().remove(sandbox_id) else {
+            return false;
+        };
+        channel.close();
+        self.hooks().unregister(&channel.hook_name());
+        true
+    }
+
+    /// The channel attached for the sandbox, if any
+    pub fn channel(&self, sandbox_id: &str) -> Option<Arc<SandboxChannel>> {
+        self.channels().lock().unwrap().get(sandbox_id).cloned()
+    }
+
+    /// Pause `sandbox_id` at `pause_at` for an automatic trigger. An attached channel
+    /// announces the pause a grace lead ahead; a keep-alive taken in the meantime
+    /// makes its pre-pause hook refuse the pause.
+    pub fn schedule_pause(self: &Arc<Self>, sandbox_id: &str, trigger: PauseTrigger, pause_at: DateTime<Utc>) -> JoinHandle<()> {
+        let notice = self.channel(sandbox_id).map(|channel| channel.schedule_grace_notice(trigger, pause_at));
+        let manager = self.clone();
+        let sandbox_id = sandbox_id.to_string();
+        tokio::spawn(async move {
+            if let Ok(wait) = (pause_at - Utc::now()).to_std() {
+                tokio::time::sleep(wait).await;
+            }
+            // Already announced unless the grace lead is zero
+            if let Some(notice) = notice {
+                let _ = notice.await;
+            }
+            let options = PauseOptions { trigger, ..Default::default() };
+            if let Err(e) = manager.prepare_pause_with(&sandbox_id, options).await {
+                warn!("Scheduled {:?} pause of sandbox {} did not go ahead: {}", trigger, sandbox_id, e);
+            }
+        })
+    }
+
+    /// Redeem a grace notice token for a keep-alive hold in whichever sandbox it was
+    /// issued for; None if no attached channel accepts it
+    pub fn redeem_keep_alive(&self, token: &str) -> Option<(String, PauseHoldInfo)> {
+        let channels: Vec<Arc<SandboxChannel>> = self.channels().lock().unwrap().values().cloned().collect();
+        let (channel, hold) = channels.iter().find_map(|channel| channel.keep_alive(token).map(|hold| (channel, hold)))?;
+        info!(target: "audit", sandbox_id = channel.sandbox_id.as_str(), operation = "keep_alive"; "Sandbox {} kept alive until {}", channel.sandbox_id, hold.expires_at);
+        Some((channel.sandbox_id.clone(), hold))
+    }
+}
+
+/// Token for keep-alive links: 128 bits from the OS random source
+fn keep_alive_token() -> Result<String, getrandom::Error> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+async fn send<W: AsyncWrite + Unpin>(write: &mut W, message: &AgentMessage) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    write.write_all(&line).await
+}
+
+/// Pauses in a sandbox timeline, each matched with the resume that followed it;
+/// the most recent `limit`, oldest first
+fn pause_history(timeline: &[TimelinePoint], limit: usize) -> Vec<PauseRecord> {
+    let mut pauses: Vec<PauseRecord> = Vec::new();
+    for point in timeline {
+        match &point.event {
+            TimelineEvent::Pause { duration_ms, strategy } => pauses.push(PauseRecord {
+                paused_at: point.at,
+                resumed_at: None,
+                strategy: serde_json::to_value(strategy).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default(),
+                duration_ms: *duration_ms,
+            }),
+            TimelineEvent::Resume { .. } => {
+                if let Some(last) = pauses.last_mut().filter(|pause| pause.resumed_at.is_none()) {
+                    last.resumed_at = Some(point.at);
+                }
+            }
+            _ => {}
+        }
+    }
+    let skip = pauses.len().saturating_sub(limit);
+    pauses.split_off(skip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::PauseStrategy;
+
+    #[cfg(feature = "sandbox-client")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_hold_blocks_pause_until_released() {
+        use crate::sandbox_client::SandboxClient;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("channel.sock");
+        let stats = Arc::new(StatsStore::new());
+        stats.record("sbx", TimelineEvent::Pause { duration_ms: 40, strategy: PauseStrategy::Kill });
+        stats.record("sbx", TimelineEvent::Resume { duration_ms: 30 });
+        let channel = Arc::new(SandboxChannel::new("sbx", &path, stats));
+        let hooks = HookRegistry::new();
+        channel.register_hooks(&hooks);
+        channel.clone().spawn().await.unwrap();
+
+        let (mut client, hold, history) = tokio::task::spawn_blocking(move || {
+            let mut client = SandboxClient::connect(&path, "db").unwrap();
+            let hold = client.acquire_hold("flushing wal", Duration::from_secs(60)).unwrap();
+            let history = client.pause_history(10).unwrap();
+            (client, hold, history)
+        })
+        .await
+        .unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].strategy, "kill");
+        assert!(history[0].resumed_at.is_some());
+
+        let refused = hooks.run(HookPhase::PrePause, "sbx").await.unwrap_err();
+        assert!(refused.contains("flushing wal"));
+        // Other sandboxes are not affected by this channel's holds
+        assert!(hooks.run(HookPhase::PrePause, "other").await.is_ok());
+
+        tokio::task::spawn_blocking(move || client.release_hold(hold)).await.unwrap().unwrap();
+        assert!(channel.active_holds().is_empty());
+        assert!(hooks.run(HookPhase::PrePause, "sbx").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_grace_notice_token_keeps_sandbox_alive() {
+        let events = EventBus::new();
+        let mut published = events.subscribe();
+        let channel = Arc::new(SandboxChannel::new("sbx", "/nonexistent/channel.sock", Arc::new(StatsStore::new())).with_events(events));
+        let hooks = HookRegistry::new();
+        channel.register_hooks(&hooks);
+
+        let token = channel.announce_pause(PauseTrigger::Idle, Utc::now() + chrono::Duration::minutes(5)).unwrap();
+        assert_eq!(token.len(), 32);
+        let event = published.try_recv().unwrap();
+        assert_eq!(event.event_type(), "grace_notice");
+        assert!(matches!(event.payload, EventPayload::GraceNotice { keep_alive_token, .. } if keep_alive_token == token));
+
+        assert!(channel.keep_alive("wrong").is_none());
+        let hold = channel.keep_alive(&token).unwrap();
+        assert_eq!(hold.app, "keep-alive");
+        assert!(hooks.run(HookPhase::PrePause, "sbx").await.is_err());
+        // Tokens are single use
+        assert!(channel.keep_alive(&token).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keep_alives_are_capped_between_pauses() {
+        let channel = Arc::new(SandboxChannel::new("sbx", "/nonexistent/channel.sock", Arc::new(StatsStore::new())).with_max_keep_alives(2));
+        let pause_at = Utc::now() + chrono::Duration::minutes(5);
+        for _ in 0..2 {
+            let token = channel.announce_pause(PauseTrigger::Idle, pause_at).unwrap();
+            assert!(channel.keep_alive(&token).is_some());
+        }
+        let token = channel.announce_pause(PauseTrigger::Idle, pause_at).unwrap();
+        assert!(channel.keep_alive(&token).is_none());
+        assert!(matches!(channel.reply("app", ClientMessage::KeepAlive), AgentMessage::Error { .. }));
+
+        // A pause that goes ahead gives the sandbox its keep-alives back
+        channel.state.lock().unwrap().holds.clear();
+        channel.before_pause().await.unwrap();
+        assert!(matches!(channel.reply("app", ClientMessage::KeepAlive), AgentMessage::HoldGranted { .. }));
+    }
+
+    #[test]
+    fn test_holds_are_bounded() {
+        let channel = SandboxChannel::new("sbx", "/nonexistent/channel.sock", Arc::new(StatsStore::new())).with_max_holds(2);
+        let acquire = |reason: String| channel.reply("app", ClientMessage::AcquireHold { reason, ttl_secs: u64::MAX });
+        assert!(matches!(acquire("x".repeat(10_000)), AgentMessage::HoldGranted { .. }));
+        assert!(matches!(acquire("second".to_string()), AgentMessage::HoldGranted { .. }));
+        assert!(matches!(acquire("third".to_string()), AgentMessage::Error { .. }));
+
+        let holds = channel.active_holds();
+        assert_eq!(holds[0].reason.len(), MAX_REASON_LEN);
+        // Capped at the max hold, not the requested ttl
+        assert!(holds[0].expires_at <= Utc::now() + chrono::Duration::minutes(15));
+    }
+}
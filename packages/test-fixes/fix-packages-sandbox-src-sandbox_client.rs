@@ -0,0 +1,210 @@
+#![cfg(feature = "sandbox-client")]
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+
+use crate::channel_protocol::{AgentMessage, ClientMessage, PauseRecord, PROTOCOL_VERSION};
+
+/// Client for applications running inside a sandbox, built with the `sandbox-client`
+/// feature. Blocking and free of host-side dependencies; talks to the agent's
+/// `SandboxChannel` over its unix socket or, with the `vsock` feature, over vsock
+/// from a VM.
+pub struct SandboxClient {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+    sandbox_id: String,
+}
+
+/// Where the agent's channel is reached
+#[derive(Debug, Clone, Copy)]
+enum Endpoint<'a> {
+    Unix(&'a Path),
+    #[cfg(feature = "vsock")]
+    Vsock { cid: u32, port: u32 },
+}
+
+/// A granted pause hold; pass it back to `release_hold`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauseHold {
+    pub id: u64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// What a pause handler is told
+#[derive(Debug, Clone, PartialEq)]
+pub enum PauseEvent {
+    /// The sandbox is about to pause; the agent waits for the handler to return,
+    /// at most `ack_within`
+    Pausing { ack_within: Duration },
+    Resumed { paused_at: Option<DateTime<Utc>> },
+    /// An idle or scheduled pause is coming at `pause_at`; `SandboxClient::keep_alive`
+    /// puts it off by `keep_alive`
+    GraceNotice { trigger: String, pause_at: DateTime<Utc>, keep_alive: Duration },
+}
+
+impl SandboxClient {
+    pub fn connect(path: impl AsRef<Path>, app: &str) -> io::Result<Self> {
+        Self::open(Endpoint::Unix(path.as_ref()), app, false)
+    }
+
+    /// Connect from a VM to the agent listening on vsock `port` of context `cid`,
+    /// usually the host's (`VMADDR_CID_HOST`, 2)
+    #[cfg(feature = "vsock")]
+    pub fn connect_vsock(cid: u32, port: u32, app: &str) -> io::Result<Self> {
+        Self::open(Endpoint::Vsock { cid, port }, app, false)
+    }
+
+    fn open(endpoint: Endpoint, app: &str, pause_handler: bool) -> io::Result<Self> {
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) = match endpoint {
+            Endpoint::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            }
+            #[cfg(feature = "vsock")]
+            Endpoint::Vsock { cid, port } => {
+                let stream = vsock::VsockStream::connect(&vsock::VsockAddr::new(cid, port))?;
+                (Box::new(stream.try_clone()?), Box::new(stream))
+            }
+        };
+        let mut client = Self {
+            reader: BufReader::new(reader),
+            writer,
+            sandbox_id: String::new(),
+        };
+        let hello = ClientMessage::Hello {
+            app: app.to_string(),
+            protocol: PROTOCOL_VERSION,
+            pause_handler,
+        };
+        match client.request(&hello)? {
+            AgentMessage::Welcome { sandbox_id, .. } => client.sandbox_id = sandbox_id,
+            other => return Err(unexpected(&other)),
+        }
+        Ok(client)
+    }
+
+    /// Sandbox the agent serves this socket for
+    pub fn sandbox_id(&self) -> &str {
+        &self.sandbox_id
+    }
+
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        match self.request(&ClientMessage::Heartbeat)? {
+            AgentMessage::Ok => Ok(()),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Keep the sandbox from pausing for up to `ttl`; the agent may cap the ttl
+    pub fn acquire_hold(&mut self, reason: &str, ttl: Duration) -> io::Result<PauseHold> {
+        let message = ClientMessage::AcquireHold {
+            reason: reason.to_string(),
+            ttl_secs: ttl.as_secs(),
+        };
+        match self.request(&message)? {
+            AgentMessage::HoldGranted { hold_id, expires_at } => Ok(PauseHold { id: hold_id, expires_at }),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Hold the sandbox for the keep-alive period of grace notices, e.g. when the user
+    /// clicks "keep alive" on one
+    pub fn keep_alive(&mut self) -> io::Result<PauseHold> {
+        match self.request(&ClientMessage::KeepAlive)? {
+            AgentMessage::HoldGranted { hold_id, expires_at } => Ok(PauseHold { id: hold_id, expires_at }),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    pub fn release_hold(&mut self, hold: PauseHold) -> io::Result<()> {
+        match self.request(&ClientMessage::ReleaseHold { hold_id: hold.id })? {
+            AgentMessage::Ok => Ok(()),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// The most recent `limit` pauses, oldest first
+    pub fn pause_history(&mut self, limit: usize) -> io::Result<Vec<PauseRecord>> {
+        match self.request(&ClientMessage::History { limit })? {
+            AgentMessage::History { pauses } => Ok(pauses),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)
+    }
+
+    /// Next message from the agent; `None` once it closed the connection
+    fn recv(&mut self) -> io::Result<Option<AgentMessage>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&line)?))
+    }
+
+    fn request(&mut self, message: &ClientMessage) -> io::Result<AgentMessage> {
+        self.send(message)?;
+        match self.recv()? {
+            Some(AgentMessage::Error { message }) => Err(io::Error::other(message)),
+            Some(reply) => Ok(reply),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "agent closed the connection")),
+        }
+    }
+}
+
+fn unexpected(message: &AgentMessage) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply from agent: {:?}", message))
+}
+
+/// Call `handler` on a background thread for every pause, resume and grace notice of the sandbox.
+/// The pause waits for the handler to return (up to the agent's ack timeout), so it
+/// can flush state first. The thread ends when the agent closes the connection.
+pub fn on_pause<F>(path: impl AsRef<Path>, app: &str, handler: F) -> io::Result<JoinHandle<io::Result<()>>>
+where
+    F: FnMut(PauseEvent) + Send + 'static,
+{
+    let client = SandboxClient::open(Endpoint::Unix(path.as_ref()), app, true)?;
+    Ok(spawn_handler(client, handler))
+}
+
+/// `on_pause` for a VM reaching the agent over vsock
+#[cfg(feature = "vsock")]
+pub fn on_pause_vsock<F>(cid: u32, port: u32, app: &str, handler: F) -> io::Result<JoinHandle<io::Result<()>>>
+where
+    F: FnMut(PauseEvent) + Send + 'static,
+{
+    let client = SandboxClient::open(Endpoint::Vsock { cid, port }, app, true)?;
+    Ok(spawn_handler(client, handler))
+}
+
+fn spawn_handler<F>(mut client: SandboxClient, mut handler: F) -> JoinHandle<io::Result<()>>
+where
+    F: FnMut(PauseEvent) + Send + 'static,
+{
+    thread::spawn(move || {
+        while let Some(message) = client.recv()? {
+            match message {
+                AgentMessage::PauseNotice { ack_within_ms } => {
+                    handler(PauseEvent::Pausing { ack_within: Duration::from_millis(ack_within_ms) });
+                    client.send(&ClientMessage::PauseAck)?;
+                }
+                AgentMessage::Resumed { paused_at } => handler(PauseEvent::Resumed { paused_at }),
+                AgentMessage::GraceNotice { trigger, pause_at, keep_alive_secs } => handler(PauseEvent::GraceNotice {
+                    trigger,
+                    pause_at,
+                    keep_alive: Duration::from_secs(keep_alive_secs),
+                }),
+                _ => {}
+            }
+        }
+        Ok(())
+    })
+}
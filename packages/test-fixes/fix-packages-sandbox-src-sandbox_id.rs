@@ -0,0 +1,243 @@
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock, RwLock};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::SandboxError;
+
+/// A shard's entries are pruned once it has grown this much since its last prune
+const PRUNE_THRESHOLD: usize = 128;
+
+/// Independently locked parts of the intern table, so lookups of different IDs
+/// rarely wait on each other
+const SHARDS: usize = 16;
+
+#[derive(Default)]
+struct Shard {
+    ids: HashSet<Arc<str>>,
+    pruned_at: usize,
+}
+
+struct Interner {
+    hasher: RandomState,
+    shards: [RwLock<Shard>; SHARDS],
+}
+
+impl Interner {
+    fn shard(&self, id: &str) -> &RwLock<Shard> {
+        &self.shards[self.hasher.hash_one(id) as usize % SHARDS]
+    }
+}
+
+fn interner() -> &'static Interner {
+    static INTERNER: OnceLock<Interner> = OnceLock::new();
+    INTERNER.get_or_init(|| Interner {
+        hasher: RandomState::new(),
+        shards: Default::default(),
+    })
+}
+
+/// A sandbox ID shared by every map key, event and snapshot that refers to the
+/// sandbox. Clones are a reference count bump, and IDs interned from the same
+/// string share one allocation, so equality is usually a pointer comparison.
+///
+/// Maps keyed by `SandboxId` can be queried with a plain `&str`.
+#[derive(Clone, PartialOrd, Ord)]
+pub struct SandboxId(Arc<str>);
+
+impl SandboxId {
+    /// Intern `id`, reusing the existing allocation if the ID is already known
+    pub fn new(id: &str) -> Self {
+        let shard = interner().shard(id);
+        // Known IDs only need a shared lock
+        if let Some(existing) = shard.read().unwrap().ids.get(id) {
+            return Self(Arc::clone(existing));
+        }
+        let mut shard = shard.write().unwrap();
+        if let Some(existing) = shard.ids.get(id) {
+            return Self(Arc::clone(existing));
+        }
+        // Drop IDs nobody refers to anymore before growing the shard further
+        if shard.ids.len() >= shard.pruned_at + PRUNE_THRESHOLD {
+            shard.ids.retain(|id| Arc::strong_count(id) > 1);
+            shard.pruned_at = shard.ids.len();
+        }
+        let id: Arc<str> = Arc::from(id);
+        shard.ids.insert(Arc::clone(&id));
+        Self(id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for SandboxId {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for SandboxId {}
+
+// Must hash like `str` so lookups through `Borrow<str>` find the entry
+impl std::hash::Hash for SandboxId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for SandboxId {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SandboxId {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for SandboxId {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl Deref for SandboxId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SandboxId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for SandboxId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for SandboxId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<&String> for SandboxId {
+    fn from(id: &String) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for SandboxId {
+    fn from(id: String) -> Self {
+        Self::new(&id)
+    }
+}
+
+impl From<SandboxId> for String {
+    fn from(id: SandboxId) -> Self {
+        id.as_str().to_string()
+    }
+}
+
+impl fmt::Display for SandboxId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for SandboxId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+/// Check that `id` can name a file or directory of its own: not empty, not hidden
+/// (which also rules out `.` and `..`) and without path separators or NUL. Every
+/// path built from a sandbox ID is checked here first.
+pub fn check_path_safe(id: &str) -> Result<&str, SandboxError> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\', '\0']) {
+        return Err(SandboxError::PermissionDenied(format!("sandbox ID {:?} cannot be used in a path", id)));
+    }
+    Ok(id)
+}
+
+impl Default for SandboxId {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Serialize for SandboxId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for SandboxId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Ok(Self::new(&id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_interned_ids_share_allocation() {
+        let a = SandboxId::new("sbx-interned");
+        let b = SandboxId::from("sbx-interned".to_string());
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, b);
+        assert_eq!(a, "sbx-interned");
+
+        let mut map = HashMap::new();
+        map.insert(a, 1);
+        assert_eq!(map.get("sbx-interned"), Some(&1));
+    }
+
+    #[test]
+    fn test_concurrent_interning_shares_allocation() {
+        let ids: Vec<Vec<SandboxId>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (0..500).map(|i| SandboxId::new(&format!("sbx-concurrent-{}", i))).collect()))
+                .collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+        });
+        for other in &ids[1..] {
+            assert!(ids[0].iter().zip(other).all(|(a, b)| Arc::ptr_eq(&a.0, &b.0)));
+        }
+    }
+
+    #[test]
+    fn test_serializes_as_plain_string() {
+        let id = SandboxId::new("sbx-serde");
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(json, r#""sbx-serde""#);
+        let back: SandboxId = serde_json::from_str(&json).unwrap();
+        assert!(Arc::ptr_eq(&id.0, &back.0));
+    }
+
+    #[test]
+    fn test_path_safe_ids() {
+        assert_eq!(check_path_safe("sbx-1_a.b").unwrap(), "sbx-1_a.b");
+        for id in ["", ".", "..", "../etc", "a/../../b", "a\\b", ".templates", "nul\0"] {
+            assert!(check_path_safe(id).is_err(), "{:?}", id);
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+
+/// Longest pause for which missed cron runs are searched
+const MAX_MISSED_SEARCH_MINUTES: i64 = 7 * 24 * 60;
+
+/// What to do with cron jobs whose schedule passed while the sandbox was paused
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedJobPolicy {
+    /// Only report missed jobs
+    #[default]
+    Report,
+    /// Run missed jobs carrying the critical marker once
+    RunCritical,
+    /// Run every missed job once
+    RunAll,
+}
+
+/// Capture and restore of crontabs and at queues across a pause
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScheduledJobsConfig {
+    pub enabled: bool,
+    /// Per-user crontab spools inside the sandbox's root; the file name is the user name
+    pub crontab_dirs: Vec<PathBuf>,
+    /// at job spools inside the sandbox's root
+    pub at_dirs: Vec<PathBuf>,
+    pub missed: MissedJobPolicy,
+    /// Trailing comment marking a cron entry as critical, e.g. `0 * * * * backup.sh # e2b:critical`
+    pub critical_marker: String,
+}
+
+impl Default for ScheduledJobsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            crontab_dirs: vec![PathBuf::from("/var/spool/cron/crontabs"), PathBuf::from("/var/spool/cron")],
+            at_dirs: vec![PathBuf::from("/var/spool/cron/atjobs"), PathBuf::from("/var/spool/at")],
+            missed: MissedJobPolicy::Report,
+            critical_marker: "# e2b:critical".to_string(),
+        }
+    }
+}
+
+/// A spool file with the ownership and mode the daemon expects
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpoolFile {
+    /// Path as the sandbox sees it
+    pub path: PathBuf,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub content: String,
+}
+
+/// Scheduled jobs as found at pause time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJobs {
+    pub captured_at: DateTime<Utc>,
+    pub crontabs: Vec<SpoolFile>,
+    pub at_jobs: Vec<SpoolFile>,
+}
+
+/// A cron entry whose schedule fired at least once during the pause
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissedJob {
+    pub user: String,
+    pub schedule: String,
+    pub command: String,
+    pub critical: bool,
+    /// The job was started after resume
+    pub ran: bool,
+}
+
+impl MissedJob {
+    /// Identifies the job in the restore checkpoint, so a retried resume does not start it twice
+    pub fn key(&self) -> String {
+        format!("{}\t{}\t{}", self.user, self.schedule, self.command)
+    }
+}
+
+/// What restoring scheduled jobs did
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScheduledJobsRestore {
+    /// Spool files that were missing or changed and have been written back
+    pub rewritten: Vec<PathBuf>,
+    pub missed: Vec<MissedJob>,
+}
+
+/// The root filesystem a sandbox process sees, reached through `/proc/<pid>/root`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SandboxRoot {
+    /// Process whose root and namespaces are used
+    pub pid: i32,
+    pub path: PathBuf,
+}
+
+impl SandboxRoot {
+    /// The root of the first of `pids` that runs under its own root filesystem.
+    /// Processes sharing the agent's root are skipped: their spools are the host's.
+    pub fn find(pids: &[i32]) -> Option<Self> {
+        pids.iter().find_map(|&pid| {
+            let path = PathBuf::from(format!("/proc/{}/root", pid));
+            own_root(&path).then_some(Self { pid, path })
+        })
+    }
+
+    /// `path` as seen inside the sandbox, resolved from the agent
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.path.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+#[cfg(unix)]
+fn own_root(root: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(root), std::fs::metadata("/")) {
+        (Ok(root), Ok(agent)) => (root.dev(), root.ino()) != (agent.dev(), agent.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn own_root(_root: &Path) -> bool {
+    false
+}
+
+/// Read every crontab and at job from the configured spools under the sandbox's root
+pub async fn capture(config: &ScheduledJobsConfig, root: &SandboxRoot) -> Result<ScheduledJobs, Box<dyn std::error::Error>> {
+    let mut crontabs = Vec::new();
+    for dir in &config.crontab_dirs {
+        crontabs.extend(read_spool(root, dir).await?);
+    }
+    let mut at_jobs = Vec::new();
+    for dir in &config.at_dirs {
+        at_jobs.extend(read_spool(root, dir).await?);
+    }
+    Ok(ScheduledJobs {
+        captured_at: Utc::now(),
+        crontabs,
+        at_jobs,
+    })
+}
+
+async fn read_spool(root: &SandboxRoot, dir: &Path) -> Result<Vec<SpoolFile>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    let resolved = root.resolve(dir);
+    if !blocking::path_exists(&resolved).await {
+        return Ok(files);
+    }
+
+    let mut entries = async_fs::read_dir(&resolved).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        // Skip lock files, sequence files and nested spools
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !metadata.is_file() || name.starts_with('.') {
+            continue;
+        }
+        let Ok(content) = async_fs::read_to_string(entry.path()).await else {
+            continue;
+        };
+        let (uid, gid, mode) = ownership(&metadata);
+        files.push(SpoolFile { path: dir.join(entry.file_name()), uid, gid, mode, content });
+    }
+    Ok(files)
+}
+
+#[cfg(unix)]
+fn ownership(metadata: &std::fs::Metadata) -> (u32, u32, u32) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.uid(), metadata.gid(), metadata.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn ownership(_metadata: &std::fs::Metadata) -> (u32, u32, u32) {
+    (0, 0, 0o600)
+}
+
+/// Write back spool files that disappeared or changed during the pause under the
+/// sandbox's root; returns their paths. Unchanged files are left alone, so this is
+/// safe to repeat.
+pub async fn restore_spools(jobs: &ScheduledJobs, root: &SandboxRoot) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut rewritten = Vec::new();
+    for file in jobs.crontabs.iter().chain(&jobs.at_jobs) {
+        let resolved = root.resolve(&file.path);
+        let current = async_fs::read_to_string(&resolved).await.ok();
+        if current.as_deref() == Some(file.content.as_str()) {
+            continue;
+        }
+        write_spool_file(&resolved, file).await?;
+        rewritten.push(file.path.clone());
+    }
+    Ok(rewritten)
+}
+
+/// Cron runs that were missed since the capture, each with whether the policy runs it;
+/// none has run yet
+pub fn missed_jobs(jobs: &ScheduledJobs, config: &ScheduledJobsConfig) -> Vec<(MissedJob, bool)> {
+    let now = Utc::now();
+    let mut missed = Vec::new();
+    for crontab in &jobs.crontabs {
+        let Some(user) = crontab.path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        for (schedule, command) in parse_crontab(&crontab.content) {
+            if !schedule.fired_between(jobs.captured_at, now) {
+                continue;
+            }
+            let critical = command.contains(&config.critical_marker);
+            let run = match config.missed {
+                MissedJobPolicy::Report => false,
+                MissedJobPolicy::RunCritical => critical,
+                MissedJobPolicy::RunAll => true,
+            };
+            let job = MissedJob {
+                user: user.clone(),
+                schedule: schedule.source.clone(),
+                command,
+                critical,
+                ran: false,
+            };
+            missed.push((job, run));
+        }
+    }
+    missed
+}
+
+/// Start a missed job inside the sandbox; returns whether it was started
+pub fn start_missed(root: &SandboxRoot, job: &MissedJob) -> bool {
+    run_as(root, &job.user, &job.command)
+}
+
+async fn write_spool_file(path: &Path, file: &SpoolFile) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent() {
+        async_fs::create_dir_all(parent).await?;
+    }
+    async_fs::write(path, &file.content).await?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        async_fs::set_permissions(path, std::fs::Permissions::from_mode(file.mode)).await?;
+        nix::unistd::chown(
+            path,
+            Some(nix::unistd::Uid::from_raw(file.uid)),
+            Some(nix::unistd::Gid::from_raw(file.gid)),
+        )?;
+    }
+    Ok(())
+}
+
+/// Start a missed job in the background as `user`, in the namespaces of the sandbox
+/// process the root belongs to; returns whether it was started
+fn run_as(root: &SandboxRoot, user: &str, command: &str) -> bool {
+    match tokio::process::Command::new("nsenter")
+        .args(["--target", &root.pid.to_string(), "--all", "--", "runuser", "-u", user, "--", "/bin/sh", "-c", command])
+        .spawn()
+    {
+        Ok(_) => {
+            info!("Started missed cron job for {}: {}", user, command);
+            true
+        }
+        Err(e) => {
+            warn!("Failed to start missed cron job for {}: {}", user, e);
+            false
+        }
+    }
+}
+
+impl PersistenceManager {
+    /// Store the scheduled jobs captured at pause next to the sandbox's other state
+    pub async fn save_scheduled_jobs(&self, sandbox_id: &str, jobs: &ScheduledJobs) -> Result<(), Box<dyn std::error::Error>> {
+        let root = self.layout(sandbox_id).root().to_path_buf();
+        let json = serde_json::to_string(jobs)?;
+        self.bounded_write("scheduled jobs save", sandbox_id, async {
+            async_fs::create_dir_all(&root).await?;
+            let file_path = root.join("scheduled_jobs.json");
+            let temp_path = file_path.with_extension("tmp");
+            async_fs::write(&temp_path, json).await?;
+            async_fs::rename(&temp_path, &file_path).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn load_scheduled_jobs(&self, sandbox_id: &str) -> Result<Option<ScheduledJobs>, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join("scheduled_jobs.json");
+        self.bounded("scheduled jobs load", sandbox_id, async {
+            if !blocking::path_exists(&file_path).await {
+                return Ok(None);
+            }
+            let json = async_fs::read_to_string(&file_path).await?;
+            Ok(Some(serde_json::from_str(&json)?))
+        })
+        .await
+    }
+}
+
+/// A five-field cron schedule
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    source: String,
+    minutes: BTreeSet<u32>,
+    hours: BTreeSet<u32>,
+    days_of_month: BTreeSet<u32>,
+    months: BTreeSet<u32>,
+    days_of_week: BTreeSet<u32>,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl CronSchedule {
+    /// Parse `min hour dom month dow` or an `@hourly`-style alias; `@reboot` has no schedule
+    pub fn parse(spec: &str) -> Option<Self> {
+        let expanded = match spec {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let mut days_of_week = parse_field(fields[4], 0, 7)?;
+        // Both 0 and 7 mean Sunday
+        if days_of_week.remove(&7) {
+            days_of_week.insert(0);
+        }
+        Some(Self {
+            source: spec.to_string(),
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days_of_month: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            days_of_week,
+            dom_restricted: fields[2] != "*",
+            dow_restricted: fields[4] != "*",
+        })
+    }
+
+    /// Whether the schedule matches this local minute
+    pub fn matches(&self, at: DateTime<Local>) -> bool {
+        let dom = self.days_of_month.contains(&at.day());
+        let dow = self.days_of_week.contains(&at.weekday().num_days_from_sunday());
+        // cron fires when either day field matches if both are restricted
+        let day = match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        };
+        day && self.minutes.contains(&at.minute()) && self.hours.contains(&at.hour()) && self.months.contains(&at.month())
+    }
+
+    /// Whether the schedule fired at any minute in `(from, to]`
+    pub fn fired_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> bool {
+        let minutes = (to - from).num_minutes().min(MAX_MISSED_SEARCH_MINUTES);
+        let start = from.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(from);
+        (1..=minutes).any(|i| self.matches((start + chrono::Duration::minutes(i)).with_timezone(&Local)))
+    }
+}
+
+/// Parse one field: `*`, `*/n`, `a`, `a-b`, `a-b/n` and comma-separated lists
+fn parse_field(field: &str, min: u32, max: u32) -> Option<BTreeSet<u32>> {
+    let mut values = BTreeSet::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().ok()?, b.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    Some(values)
+}
+
+/// Schedules and commands of a user crontab, skipping comments, variables and `@reboot`
+fn parse_crontab(content: &str) -> Vec<(CronSchedule, String)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (spec, command) = if line.starts_with('@') {
+                line.split_once(char::is_whitespace)?
+            } else {
+                let mut end = 0;
+                for _ in 0..5 {
+                    let rest = &line[end..];
+                    let field_start = end + rest.len() - rest.trim_start().len();
+                    end = field_start + line[field_start..].find(char::is_whitespace)?;
+                }
+                (&line[..end], &line[end..])
+            };
+            // Environment assignments have no schedule and fail to parse here
+            Some((CronSchedule::parse(spec.trim())?, command.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_crontab_entries() {
+        let entries = parse_crontab("SHELL=/bin/bash\n# nightly\n*/15 2-4 * * 1-5 /usr/bin/backup --full # e2b:critical\n@reboot start.sh\n@hourly rotate.sh\n");
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].1, "/usr/bin/backup --full # e2b:critical");
+        assert_eq!(entries[1].0.source, "@hourly");
+    }
+
+    #[test]
+    fn test_schedule_fired_between() {
+        let schedule = CronSchedule::parse("30 * * * *").unwrap();
+        let from = Local.with_ymd_and_hms(2024, 5, 6, 10, 0, 0).unwrap().with_timezone(&Utc);
+
+        assert!(!schedule.fired_between(from, from + chrono::Duration::minutes(29)));
+        assert!(schedule.fired_between(from, from + chrono::Duration::minutes(30)));
+        assert!(CronSchedule::parse("61 * * * *").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_spools_are_read_and_restored_under_the_sandbox_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = SandboxRoot { pid: 0, path: dir.path().to_path_buf() };
+        let spool = dir.path().join("var/spool/cron/crontabs");
+        std::fs::create_dir_all(&spool).unwrap();
+        std::fs::write(spool.join("alice"), "0 3 * * * backup.sh\n").unwrap();
+        let config = ScheduledJobsConfig::default();
+
+        let jobs = capture(&config, &root).await.unwrap();
+        assert_eq!(jobs.crontabs.len(), 1);
+        assert_eq!(jobs.crontabs[0].path, Path::new("/var/spool/cron/crontabs/alice"));
+
+        std::fs::remove_file(spool.join("alice")).unwrap();
+        let rewritten = restore_spools(&jobs, &root).await.unwrap();
+        assert_eq!(rewritten, [PathBuf::from("/var/spool/cron/crontabs/alice")]);
+        assert!(restore_spools(&jobs, &root).await.unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(spool.join("alice")).unwrap(), "0 3 * * * backup.sh\n");
+        // The agent's own root is never a sandbox root
+        assert_eq!(SandboxRoot::find(&[std::process::id() as i32]), None);
+    }
+}
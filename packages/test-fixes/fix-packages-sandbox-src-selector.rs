@@ -0,0 +1,248 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::process::{ProcessInfo, ProcessState};
+
+/// One condition of a selector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// `name=<glob>` or a bare glob: `*` matches any run, `?` one character
+    Name(String),
+    /// `name!=<glob>`
+    NameNot(String),
+    /// `<key>=<value>`
+    LabelEq(String, String),
+    /// `<key>!=<value>`; also matches processes without the label
+    LabelNe(String, String),
+    /// `state=running|suspended|terminated`
+    State(ProcessState),
+    /// `state!=running|suspended|terminated`
+    StateNot(ProcessState),
+    /// `age>30s`
+    OlderThan(Duration),
+    /// `age<5m`
+    YoungerThan(Duration),
+}
+
+/// Comma-separated terms that must all match, e.g. `python*,service=api,age>10m`.
+/// The empty selector matches every process.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Selector {
+    terms: Vec<Term>,
+}
+
+impl Selector {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        self.terms.iter().all(|term| term_matches(term, process))
+    }
+}
+
+fn term_matches(term: &Term, process: &ProcessInfo) -> bool {
+    let age = || (Utc::now() - process.start_time).to_std().unwrap_or_default();
+    match term {
+        Term::Name(pattern) => glob_match(pattern, &process.name),
+        Term::NameNot(pattern) => !glob_match(pattern, &process.name),
+        Term::LabelEq(key, value) => process.labels.get(key) == Some(value),
+        Term::LabelNe(key, value) => process.labels.get(key) != Some(value),
+        Term::State(state) => process.state == *state,
+        Term::StateNot(state) => process.state != *state,
+        Term::OlderThan(duration) => age() > *duration,
+        Term::YoungerThan(duration) => age() < *duration,
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let terms = s
+            .split(',')
+            .map(str::trim)
+            .filter(|term| !term.is_empty())
+            .map(parse_term)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { terms })
+    }
+}
+
+fn parse_term(term: &str) -> Result<Term, String> {
+    if let Some(value) = term.strip_prefix("age>") {
+        return Ok(Term::OlderThan(parse_duration(value.trim())?));
+    }
+    if let Some(value) = term.strip_prefix("age<") {
+        return Ok(Term::YoungerThan(parse_duration(value.trim())?));
+    }
+    // Checked before `=`, and the reserved keys before labels, so `name!=x` is not a label
+    if let Some((key, value)) = term.split_once("!=") {
+        return match key.trim() {
+            "name" => Ok(Term::NameNot(value.trim().to_string())),
+            "state" => Ok(Term::StateNot(parse_state(value)?)),
+            "age" => Err(format!("age takes > or <, not !=: {}", term)),
+            key => Ok(Term::LabelNe(key.to_string(), value.trim().to_string())),
+        };
+    }
+    match term.split_once('=') {
+        Some(("name", pattern)) => Ok(Term::Name(pattern.trim().to_string())),
+        Some(("state", state)) => Ok(Term::State(parse_state(state)?)),
+        Some((key, value)) => Ok(Term::LabelEq(key.trim().to_string(), value.trim().to_string())),
+        None => Ok(Term::Name(term.to_string())),
+    }
+}
+
+fn parse_state(state: &str) -> Result<ProcessState, String> {
+    match state.trim() {
+        "running" => Ok(ProcessState::Running),
+        "suspended" => Ok(ProcessState::Suspended),
+        "terminated" => Ok(ProcessState::Terminated),
+        other => Err(format!("unknown process state: {}", other)),
+    }
+}
+
+/// Parse `<n>[s|m|h|d]`; a bare number is seconds
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration: {}", value))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("invalid duration unit: {}", value)),
+    };
+    let seconds = number.checked_mul(scale).ok_or_else(|| format!("duration too long: {}", value))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+fn format_duration(duration: &Duration) -> String {
+    format!("{}s", duration.as_secs())
+}
+
+/// Match `text` against a glob supporting `*` and `?`
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<String> = self
+            .terms
+            .iter()
+            .map(|term| match term {
+                Term::Name(pattern) => format!("name={}", pattern),
+                Term::NameNot(pattern) => format!("name!={}", pattern),
+                Term::LabelEq(key, value) => format!("{}={}", key, value),
+                Term::LabelNe(key, value) => format!("{}!={}", key, value),
+                Term::State(state) => format!("state={}", format!("{:?}", state).to_lowercase()),
+                Term::StateNot(state) => format!("state!={}", format!("{:?}", state).to_lowercase()),
+                Term::OlderThan(duration) => format!("age>{}", format_duration(duration)),
+                Term::YoungerThan(duration) => format!("age<{}", format_duration(duration)),
+            })
+            .collect();
+        f.write_str(&terms.join(","))
+    }
+}
+
+impl TryFrom<String> for Selector {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Selector> for String {
+    fn from(selector: Selector) -> Self {
+        selector.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn process(name: &str, labels: &[(&str, &str)], age_secs: i64) -> ProcessInfo {
+        ProcessInfo {
+            start_time: Utc::now() - chrono::Duration::seconds(age_secs),
+            labels: test_support::labels(labels),
+            ..test_support::process_info(1, name)
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("python*", "python3"));
+        assert!(glob_match("*worker?", "celery-worker1"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("node", "nodejs"));
+        assert!(!glob_match("a*b", "acbd"));
+    }
+
+    #[test]
+    fn test_selector_terms_combine() {
+        let selector: Selector = "python*, service=api, age>1m, state=running".parse().unwrap();
+        assert!(selector.matches(&process("python3", &[("service", "api")], 120)));
+        assert!(!selector.matches(&process("python3", &[("service", "api")], 10)));
+        assert!(!selector.matches(&process("node", &[("service", "api")], 120)));
+        assert!(!selector.matches(&process("python3", &[("service", "db")], 120)));
+
+        let reparsed: Selector = selector.to_string().parse().unwrap();
+        assert_eq!(reparsed, selector);
+        assert!(Selector::all().matches(&process("anything", &[], 0)));
+        assert!("age>5x".parse::<Selector>().is_err());
+        assert!(format!("age>{}d", u64::MAX / 86400 + 1).parse::<Selector>().is_err());
+        assert!(format!("age<{}s", u64::MAX).parse::<Selector>().is_ok());
+        assert_eq!("agent=x".parse::<Selector>().unwrap().terms(), &[Term::LabelEq("agent".to_string(), "x".to_string())]);
+    }
+
+    #[test]
+    fn test_reserved_keys_negate() {
+        let selector: Selector = "name!=postgres".parse().unwrap();
+        assert_eq!(selector.terms(), &[Term::NameNot("postgres".to_string())]);
+        assert!(!selector.matches(&process("postgres", &[], 0)));
+        assert!(selector.matches(&process("python3", &[], 0)));
+
+        let selector: Selector = "state!=running".parse().unwrap();
+        assert_eq!(selector.terms(), &[Term::StateNot(ProcessState::Running)]);
+        assert!(!selector.matches(&process("python3", &[], 0)));
+        assert!(selector.matches(&ProcessInfo { state: ProcessState::Suspended, ..process("python3", &[], 0) }));
+        assert_eq!(selector.to_string().parse::<Selector>().unwrap(), selector);
+
+        assert!("state!=sleeping".parse::<Selector>().is_err());
+        assert!("age!=5m".parse::<Selector>().is_err());
+    }
+}
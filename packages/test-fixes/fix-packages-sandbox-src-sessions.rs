@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::AutoPauseManager;
+use crate::process::{ProcessInfo, ProcessState};
+
+/// A login session inside a sandbox and the state of its processes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: i32,
+    pub login_uid: Option<u32>,
+    pub tty: Option<String>,
+    pub pids: Vec<i32>,
+    /// Time since the session's terminal was last read from; None without a terminal
+    pub idle_secs: Option<u64>,
+    /// Every live process of the session is suspended
+    pub paused: bool,
+}
+
+/// Group live processes by login session. Processes whose session has not
+/// been sampled yet are left out.
+pub fn summarize(processes: &[ProcessInfo]) -> Vec<SessionSummary> {
+    let mut sessions: BTreeMap<i32, SessionSummary> = BTreeMap::new();
+    for process in processes.iter().filter(|p| p.state != ProcessState::Terminated) {
+        let Some(session) = &process.session else {
+            continue;
+        };
+        let summary = sessions.entry(session.session_id).or_insert_with(|| SessionSummary {
+            session_id: session.session_id,
+            login_uid: session.login_uid,
+            tty: session.tty.clone(),
+            pids: Vec::new(),
+            idle_secs: session.tty.as_deref().and_then(tty_idle).map(|idle| idle.as_secs()),
+            paused: true,
+        });
+        summary.pids.push(process.pid);
+        summary.paused &= process.state == ProcessState::Suspended;
+    }
+    sessions.into_values().collect()
+}
+
+/// Time since a terminal was last read from, the same idle measure `w` reports
+fn tty_idle(tty: &str) -> Option<Duration> {
+    let accessed = std::fs::metadata(tty).ok()?.accessed().ok()?;
+    SystemTime::now().duration_since(accessed).ok()
+}
+
+impl AutoPauseManager {
+    /// Login sessions currently running in the sandbox
+    pub async fn sessions(&self, sandbox_id: &str) -> Result<Vec<SessionSummary>, Box<dyn std::error::Error>> {
+        Ok(summarize(&self.process_manager().list_live_processes(sandbox_id).await?))
+    }
+
+    /// Suspend the processes of every terminal session idle for at least
+    /// `idle_threshold`, leaving active sessions and sessions without a
+    /// terminal running. Returns the IDs of the sessions that were paused.
+    pub async fn pause_idle_sessions(&self, sandbox_id: &str, idle_threshold: Duration) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
+        let mut paused = Vec::new();
+        for session in self.sessions(sandbox_id).await? {
+            let idle = session.idle_secs.map_or(false, |idle| idle >= idle_threshold.as_secs());
+            if session.paused || !idle {
+                continue;
+            }
+            for pid in &session.pids {
+                match self.backend().suspend(*pid) {
+                    Ok(()) => self.process_manager().update_process_state(sandbox_id, *pid, ProcessState::Suspended).await?,
+                    Err(e) => warn!("Failed to suspend process {} of session {}: {}", pid, session.session_id, e),
+                }
+            }
+            info!("Paused idle session {} in sandbox {} ({} processes)", session.session_id, sandbox_id, session.pids.len());
+            paused.push(session.session_id);
+        }
+        Ok(paused)
+    }
+
+    /// Resume the suspended processes of one session
+    pub async fn resume_session(&self, sandbox_id: &str, session_id: i32) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut resumed = 0;
+        for process in self.process_manager().list_live_processes(sandbox_id).await? {
+            let in_session = process.session.as_ref().map_or(false, |s| s.session_id == session_id);
+            if !in_session || process.state != ProcessState::Suspended {
+                continue;
+            }
+            match self.backend().resume(process.pid) {
+                Ok(()) => {
+                    self.process_manager().update_process_state(sandbox_id, process.pid, ProcessState::Running).await?;
+                    resumed += 1;
+                }
+                Err(e) => warn!("Failed to resume process {} of session {}: {}", process.pid, session_id, e),
+            }
+        }
+        info!("Resumed {} processes of session {} in sandbox {}", resumed, session_id, sandbox_id);
+        Ok(resumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::SessionRef;
+    use crate::test_support;
+
+    fn process(pid: i32, session_id: i32, state: ProcessState) -> ProcessInfo {
+        ProcessInfo {
+            state,
+            session: Some(SessionRef { session_id, login_uid: Some(1000), tty: None }),
+            ..test_support::process_info(pid, "bash")
+        }
+    }
+
+    #[test]
+    fn test_summarize_groups_by_session() {
+        let sessions = summarize(&[
+            process(10, 10, ProcessState::Suspended),
+            process(11, 10, ProcessState::Suspended),
+            process(20, 20, ProcessState::Running),
+            process(21, 20, ProcessState::Terminated),
+        ]);
+
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].pids, vec![10, 11]);
+        assert!(sessions[0].paused);
+        assert_eq!(sessions[1].pids, vec![20]);
+        assert!(!sessions[1].paused);
+        assert_eq!(sessions[1].idle_secs, None);
+    }
+}
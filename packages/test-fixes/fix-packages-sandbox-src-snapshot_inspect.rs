@@ -0,0 +1,230 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use tokio::process::Command;
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+use crate::sandbox_id;
+use crate::process;
+use crate::process_tree::{self, TreeFormat, TreeNode};
+use crate::state_snapshot::StateSnapshot;
+
+/// A snapshot laid out as plain files for browsing:
+///
+/// ```text
+/// <dir>/snapshot.json            the snapshot as saved
+/// <dir>/lifecycle.json           last lifecycle state, if recorded
+/// <dir>/processes/<pid>-<name>.json
+/// <dir>/process_tree.txt         hierarchy, also as process_tree.dot
+/// <dir>/payloads/...             captured files, if any
+/// ```
+///
+/// Everything is made read-only. When mounted, `bindfs` serves `dir` read-only at the
+/// mountpoint as well, so nothing can be written back through it.
+#[derive(Debug)]
+pub struct InspectionDir {
+    pub sandbox_id: String,
+    pub dir: PathBuf,
+    pub mountpoint: Option<PathBuf>,
+}
+
+impl InspectionDir {
+    /// Where to browse: the mountpoint when mounted, the directory otherwise
+    pub fn path(&self) -> &Path {
+        self.mountpoint.as_deref().unwrap_or(&self.dir)
+    }
+
+    /// Unmount and delete the inspection directory
+    pub async fn remove(self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(mountpoint) = &self.mountpoint {
+            let status = process::helper_status(Command::new("fusermount").arg("-u").arg(mountpoint)).await?;
+            if !status.success() {
+                return Err(format!("fusermount -u {} failed with {}", mountpoint.display(), status).into());
+            }
+        }
+        let dir = self.dir.clone();
+        blocking::run("inspection_remove", move || {
+            set_read_only(&dir, false)?;
+            fs::remove_dir_all(&dir)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+impl PersistenceManager {
+    /// Write the snapshot of `sandbox_id` and its payloads under `dest`, read-only, and
+    /// FUSE-mount it at `mountpoint` if given. The snapshot is read as-is: stale
+    /// snapshots are materialized too, and nothing in the store is changed. On failure,
+    /// including a failed mount, nothing is left under `dest`.
+    pub async fn inspect_snapshot(&self, sandbox_id: &str, dest: &Path, mountpoint: Option<&Path>) -> Result<InspectionDir, Box<dyn std::error::Error>> {
+        sandbox_id::check_path_safe(sandbox_id)?;
+        let layout = self.layout(sandbox_id);
+        let snapshot_file = layout.snapshot_file();
+        if !blocking::path_exists(&snapshot_file).await {
+            return Err(format!("Sandbox {} has no snapshot", sandbox_id).into());
+        }
+        let dir = dest.join(sandbox_id);
+        if blocking::path_exists(&dir).await {
+            return Err(format!("{} already exists", dir.display()).into());
+        }
+        let target = dir.clone();
+        let written = self
+            .bounded("snapshot inspect", sandbox_id, async move {
+                blocking::run("inspection_write", move || materialize(&snapshot_file, &layout.lifecycle_file(), &layout.payloads_dir(), &target)).await?;
+                Ok(())
+            })
+            .await;
+        if let Err(e) = written {
+            discard(&dir).await;
+            return Err(e);
+        }
+
+        let mut inspection = InspectionDir {
+            sandbox_id: sandbox_id.to_string(),
+            dir,
+            mountpoint: None,
+        };
+        if let Some(mountpoint) = mountpoint {
+            let status = process::helper_status(Command::new("bindfs").arg("-r").arg(&inspection.dir).arg(mountpoint)).await;
+            let error = match status {
+                Ok(status) if status.success() => None,
+                Ok(status) => Some(format!("bindfs exited with {}", status)),
+                Err(e) => Some(format!("could not run bindfs: {}", e)),
+            };
+            if let Some(error) = error {
+                discard(&inspection.dir).await;
+                return Err(format!("Failed to mount snapshot of {} at {}: {}", sandbox_id, mountpoint.display(), error).into());
+            }
+            inspection.mountpoint = Some(mountpoint.to_path_buf());
+        }
+        info!("Snapshot of sandbox {} ready for inspection at {}", sandbox_id, inspection.path().display());
+        Ok(inspection)
+    }
+}
+
+/// Delete a partly written inspection directory
+async fn discard(dir: &Path) {
+    let target = dir.to_path_buf();
+    let removed = blocking::run("inspection_remove", move || match set_read_only(&target, false) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        _ => fs::remove_dir_all(&target),
+    })
+    .await;
+    if let Err(e) = removed {
+        warn!("Failed to remove partial inspection directory {}: {}", dir.display(), e);
+    }
+}
+
+fn materialize(snapshot_file: &Path, lifecycle_file: &Path, payloads_dir: &Path, dir: &Path) -> io::Result<()> {
+    let json = fs::read_to_string(snapshot_file)?;
+    let snapshot = StateSnapshot::from_json(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let processes = dir.join("processes");
+    fs::create_dir_all(&processes)?;
+    fs::write(dir.join("snapshot.json"), serde_json::to_string_pretty(&snapshot)?)?;
+    for process in &snapshot.processes {
+        let name: String = process.name.chars().map(|c| if c == '/' { '_' } else { c }).collect();
+        fs::write(processes.join(format!("{}-{}.json", process.pid, name)), serde_json::to_string_pretty(process)?)?;
+    }
+    let nodes: Vec<TreeNode> = snapshot.processes.iter().map(TreeNode::persisted).collect();
+    fs::write(dir.join("process_tree.txt"), process_tree::render(&nodes, TreeFormat::Ascii))?;
+    fs::write(dir.join("process_tree.dot"), process_tree::render(&nodes, TreeFormat::Dot))?;
+    if lifecycle_file.exists() {
+        fs::copy(lifecycle_file, dir.join("lifecycle.json"))?;
+    }
+    if payloads_dir.exists() {
+        copy_tree(payloads_dir, &dir.join("payloads"))?;
+    }
+    set_read_only(dir, true)
+}
+
+/// Copy files and directories; symlinks are recreated rather than followed
+fn copy_tree(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Toggle write permission on a tree; directories are made writable before their contents
+fn set_read_only(path: &Path, read_only: bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    let mut permissions = metadata.permissions();
+    permissions.set_readonly(read_only);
+    if metadata.is_dir() {
+        if !read_only {
+            fs::set_permissions(path, permissions.clone())?;
+        }
+        for entry in fs::read_dir(path)? {
+            set_read_only(&entry?.path(), read_only)?;
+        }
+        if read_only {
+            fs::set_permissions(path, permissions)?;
+        }
+        return Ok(());
+    }
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_inspect_snapshot_read_only() {
+        let store = TempDir::new().unwrap();
+        let inspect = TempDir::new().unwrap();
+        let persistence = PersistenceManager::with_base_dir(store.path().to_path_buf());
+        persistence.save_snapshot(&StateSnapshot::new("sbx".to_string())).await.unwrap();
+        let payloads = persistence.layout("sbx").payloads_dir();
+        fs::create_dir_all(payloads.join("home")).unwrap();
+        fs::write(payloads.join("home/notes.txt"), "hello").unwrap();
+
+        let inspection = persistence.inspect_snapshot("sbx", inspect.path(), None).await.unwrap();
+        let notes = inspection.path().join("payloads/home/notes.txt");
+        assert_eq!(fs::read_to_string(&notes).unwrap(), "hello");
+        assert!(fs::metadata(&notes).unwrap().permissions().readonly());
+        assert!(inspection.path().join("snapshot.json").exists());
+        // The store itself is left alone
+        assert!(persistence.snapshot_exists("sbx"));
+
+        let dir = inspection.dir.clone();
+        inspection.remove().await.unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_inspection_leaves_nothing_behind() {
+        let store = TempDir::new().unwrap();
+        let inspect = TempDir::new().unwrap();
+        let persistence = PersistenceManager::with_base_dir(store.path().to_path_buf());
+        assert!(persistence.inspect_snapshot("../sbx", inspect.path(), None).await.is_err());
+
+        // No bindfs, or nothing to mount on: the mount fails and the directory goes
+        persistence.save_snapshot(&StateSnapshot::new("sbx".to_string())).await.unwrap();
+        let missing = inspect.path().join("missing/mountpoint");
+        assert!(persistence.inspect_snapshot("sbx", inspect.path(), Some(&missing)).await.is_err());
+        assert!(!inspect.path().join("sbx").exists());
+
+        fs::write(persistence.layout("sbx").snapshot_file(), "not a snapshot").unwrap();
+        assert!(persistence.inspect_snapshot("sbx", inspect.path(), None).await.is_err());
+        assert!(!inspect.path().join("sbx").exists());
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::sync::watch;
+
+use crate::auto_pause::{AutoPauseManager, PauseOptions};
+use crate::crash_dump::CrashDump;
+use crate::error::SandboxError;
+use crate::events::EventPayload;
+use crate::lifecycle::LifecycleState;
+use crate::persistence::SnapshotLoad;
+use crate::restore_checkpoint::RestoreStep;
+
+tokio::task_local! {
+    static RECOVERING: ();
+}
+
+/// How a sandbox left mid-pause or mid-resume by the previous agent is finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    /// Run the operation again to completion
+    RollForward,
+    /// Put the sandbox back in the state it had before the operation
+    RollBack,
+}
+
+/// Reconciliation of half-finished operations before the agent takes new work
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartupRecoveryConfig {
+    /// Hold pauses and resumes until `recover_on_startup` has run
+    pub enabled: bool,
+    /// Sandboxes stuck in Pausing. Rolling back marks them Running without undoing
+    /// kills or cgroup clamps a crashed commit may have done (default: roll forward)
+    pub pausing: RecoveryAction,
+    /// Sandboxes stuck in Resuming. Rolling back marks them Paused; the next resume
+    /// continues from the restore checkpoint (default: roll forward)
+    pub resuming: RecoveryAction,
+    /// How long a pause or resume waits for recovery before failing, so an agent
+    /// that never calls `recover_on_startup` does not hang its callers (default: 300)
+    pub gate_timeout_secs: u64,
+}
+
+impl Default for StartupRecoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pausing: RecoveryAction::RollForward,
+            resuming: RecoveryAction::RollForward,
+            gate_timeout_secs: 300,
+        }
+    }
+}
+
+impl StartupRecoveryConfig {
+    pub fn action_for(&self, state: LifecycleState) -> Option<RecoveryAction> {
+        match state {
+            LifecycleState::Pausing => Some(self.pausing),
+            LifecycleState::Resuming => Some(self.resuming),
+            _ => None,
+        }
+    }
+}
+
+/// What was done about one sandbox found mid-operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryDecision {
+    pub sandbox_id: String,
+    pub from: LifecycleState,
+    /// Phase the interrupted operation was in, when the crash dump recorded it
+    pub phase: Option<String>,
+    pub action: RecoveryAction,
+    /// The crash dump lists the sandbox as affected
+    pub crashed: bool,
+    /// Resume steps the interrupted resume had completed
+    pub resume_progress: BTreeSet<RestoreStep>,
+    /// Error message if the action failed
+    pub error: Option<String>,
+}
+
+/// Outcome of startup recovery
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    pub started_at: Option<DateTime<Utc>>,
+    /// Sandbox directories whose lifecycle record was read
+    pub scanned: usize,
+    /// Dump left by the previous agent, if it crashed
+    pub crash: Option<CrashDump>,
+    pub decisions: Vec<RecoveryDecision>,
+}
+
+/// Holds new pauses and resumes until startup recovery is done. Operations the
+/// recovery runs itself pass; others fail once they have waited `timeout`.
+pub struct StartupGate {
+    open: watch::Sender<bool>,
+    timeout: Duration,
+}
+
+impl StartupGate {
+    pub fn new(open: bool, timeout: Duration) -> Self {
+        let (open, _) = watch::channel(open);
+        Self { open, timeout }
+    }
+
+    pub fn is_open(&self) -> bool {
+        *self.open.borrow()
+    }
+
+    pub fn open(&self) {
+        self.open.send_replace(true);
+    }
+
+    pub async fn wait_open(&self) -> Result<(), SandboxError> {
+        if RECOVERING.try_with(|_| ()).is_ok() {
+            return Ok(());
+        }
+        let mut open = self.open.subscribe();
+        // The sender lives as long as the gate, so this only returns once open
+        match tokio::time::timeout(self.timeout, open.wait_for(|open| *open)).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(SandboxError::Timeout(format!("waiting {:?} for startup recovery, which has not run", self.timeout))),
+        }
+    }
+}
+
+/// Run `fut` past a closed `StartupGate`
+async fn recovering<F: Future>(fut: F) -> F::Output {
+    RECOVERING.scope((), fut).await
+}
+
+impl AutoPauseManager {
+    /// Finish what the previous agent left half done, then open the startup gate.
+    /// Every sandbox whose persisted lifecycle state is Pausing or Resuming, or that the
+    /// crash dump lists as mid-operation, is rolled forward or back as `startup_recovery`
+    /// says, with a `Recovery` event per decision. The crash dump, if any, is reported
+    /// and cleared. Owners and teams recorded in snapshots go back in the registry first.
+    pub async fn recover_on_startup(&self) -> Result<RecoveryReport, Box<dyn std::error::Error>> {
+        match self.restore_owners().await {
+            Ok(restored) if restored > 0 => info!("Restored the owner of {} sandboxes from their snapshots", restored),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to restore sandbox owners from snapshots: {}", e),
+        }
+        let recovered = self.recover().await;
+        self.startup_gate().open();
+        recovered
+    }
+
+    async fn recover(&self) -> Result<RecoveryReport, Box<dyn std::error::Error>> {
+        let persistence = self.persistence_manager();
+        let mut report = RecoveryReport {
+            started_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        if let Some(holder) = self.read_only_holder() {
+            info!("Skipping startup recovery: the agent with pid {} owns the base dir", holder.pid);
+            return Ok(report);
+        }
+        report.crash = match persistence.load_crash_dump().await {
+            Ok(crash) => crash,
+            Err(e) => {
+                warn!("Failed to read crash dump, recovering from lifecycle records only: {}", e);
+                None
+            }
+        };
+        if let Some(crash) = &report.crash {
+            warn!("Previous agent crashed at {}: {}", crash.crashed_at, crash.message);
+        }
+
+        let mut sandbox_ids: BTreeSet<String> = persistence.list_sandbox_dirs().await?.into_iter().collect();
+        sandbox_ids.extend(report.crash.iter().flat_map(|crash| crash.affected.iter().cloned()));
+        for sandbox_id in sandbox_ids {
+            let record = match persistence.load_lifecycle(&sandbox_id).await {
+                Ok(record) => record,
+                Err(e) => {
+                    warn!("Failed to load lifecycle state of sandbox {}, leaving it alone: {}", sandbox_id, e);
+                    continue;
+                }
+            };
+            report.scanned += 1;
+            // An operation that crashed before recording Pausing or Resuming is known
+            // only from the dump; it is finished like one that had
+            let interrupted = report.crash.as_ref().and_then(|crash| crash.interrupted(&sandbox_id));
+            let since = record.as_ref().map(|record| record.updated_at);
+            let (from, phase) = match (record.map(|record| record.state), interrupted) {
+                (Some(state @ (LifecycleState::Pausing | LifecycleState::Resuming)), interrupted) => (state, interrupted.and_then(|(_, phase)| phase)),
+                (_, Some(interrupted)) => interrupted,
+                _ => continue,
+            };
+            let Some(action) = self.config().startup_recovery.action_for(from) else { continue };
+
+            let resume_progress = match persistence.load_restore_checkpoint(&sandbox_id).await {
+                Ok(progress) => progress.map(|progress| progress.completed).unwrap_or_default(),
+                Err(e) => {
+                    warn!("Failed to load restore checkpoint of sandbox {}: {}", sandbox_id, e);
+                    BTreeSet::new()
+                }
+            };
+            let crashed = report.crash.as_ref().is_some_and(|crash| crash.affected.contains(&sandbox_id));
+            let error = recovering(self.apply_recovery(&sandbox_id, from, action, since)).await.err().map(|e| e.to_string());
+            match &error {
+                Some(e) => warn!("Failed to {:?} sandbox {} left {:?}: {}", action, sandbox_id, from, e),
+                None => info!(target: "audit", sandbox_id = sandbox_id.as_str(), operation = "recover"; "Recovered sandbox {} left {:?} with {:?}", sandbox_id, from, action),
+            }
+            self.events().publish(&sandbox_id, EventPayload::Recovery { from, action, error: error.clone() });
+            report.decisions.push(RecoveryDecision {
+                sandbox_id,
+                from,
+                phase,
+                action,
+                crashed,
+                resume_progress,
+                error,
+            });
+        }
+
+        if report.crash.is_some() {
+            if let Err(e) = persistence.clear_crash_dump().await {
+                warn!("Failed to clear crash dump: {}", e);
+            }
+        }
+        info!(
+            "Startup recovery read {} sandboxes: {} recovered, {} failed",
+            report.scanned,
+            report.decisions.iter().filter(|decision| decision.error.is_none()).count(),
+            report.decisions.iter().filter(|decision| decision.error.is_some()).count()
+        );
+        Ok(report)
+    }
+
+    /// `since` is when the persisted lifecycle record was written
+    async fn apply_recovery(&self, sandbox_id: &str, from: LifecycleState, action: RecoveryAction, since: Option<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
+        match (from, action) {
+            (LifecycleState::Pausing, RecoveryAction::RollForward) => self.finish_pause(sandbox_id, since).await,
+            (LifecycleState::Pausing, RecoveryAction::RollBack) => {
+                self.set_lifecycle_state(sandbox_id, LifecycleState::Running).await;
+                Ok(())
+            }
+            (_, RecoveryAction::RollForward) => self.after_resume(sandbox_id).await.map(|_| ()),
+            (_, RecoveryAction::RollBack) => {
+                self.set_lifecycle_state(sandbox_id, LifecycleState::Paused).await;
+                Ok(())
+            }
+        }
+    }
+
+    /// Roll an interrupted pause forward without losing the snapshot: a commit that got
+    /// as far as persisting only needs the Paused record, and a restarted agent that
+    /// tracks no processes of the sandbox would replace the snapshot with an empty one,
+    /// so it rolls back instead
+    async fn finish_pause(&self, sandbox_id: &str, since: Option<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = match self.persistence_manager().load_snapshot_detailed(sandbox_id).await? {
+            SnapshotLoad::Loaded(snapshot) => Some(snapshot),
+            SnapshotLoad::Missing | SnapshotLoad::Stale => None,
+        };
+        if let Some(snapshot) = &snapshot {
+            if since.is_some_and(|since| snapshot.timestamp >= since) {
+                self.set_lifecycle_state(sandbox_id, LifecycleState::Paused).await;
+                return Ok(());
+            }
+            if self.process_manager().list_live_processes(sandbox_id).await?.is_empty() {
+                self.set_lifecycle_state(sandbox_id, LifecycleState::Running).await;
+                return Err(format!("no tracked processes to pause sandbox {} with; rolled back to keep its snapshot", sandbox_id).into());
+            }
+        }
+        // The pause was asked for before the crash, so protection no longer applies
+        let options = PauseOptions { force: true, ..Default::default() };
+        self.prepare_pause_with(sandbox_id, options).await.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_gate_holds_work_but_not_recovery() {
+        let gate = Arc::new(StartupGate::new(false, Duration::from_secs(60)));
+        recovering(gate.wait_open()).await.unwrap();
+
+        let waiting = tokio::spawn({
+            let gate = gate.clone();
+            async move { gate.wait_open().await.unwrap() }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        gate.open();
+        waiting.await.unwrap();
+        assert!(gate.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_gate_fails_fast_when_recovery_never_runs() {
+        let gate = StartupGate::new(false, Duration::from_millis(20));
+        let err = gate.wait_open().await.unwrap_err();
+        assert!(matches!(err, SandboxError::Timeout(_)));
+    }
+}
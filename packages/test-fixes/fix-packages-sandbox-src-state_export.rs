@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::AutoPauseManager;
+use crate::lifecycle::LifecycleState;
+use crate::process::ProcessState;
+use crate::registry::PriorityClass;
+
+/// The agent's view of one sandbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxStateView {
+    pub sandbox_id: String,
+    pub lifecycle: LifecycleState,
+    pub priority: PriorityClass,
+    pub process_count: usize,
+    pub running_count: usize,
+    pub last_snapshot_at: Option<DateTime<Utc>>,
+    /// Operations currently holding the sandbox's snapshot (resume, export, ...)
+    pub active_pins: usize,
+    /// A pause has been prepared and awaits commit or abort
+    pub pause_pending: bool,
+}
+
+/// Full per-host state document for orchestrator reconciliation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateExport {
+    pub generated_at: DateTime<Utc>,
+    pub sandboxes: Vec<SandboxStateView>,
+}
+
+impl StateExport {
+    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl AutoPauseManager {
+    /// Export every sandbox the agent knows about, sorted by ID. That includes sandboxes
+    /// only the store has, such as paused ones from before a restart.
+    pub async fn export_state(&self) -> StateExport {
+        let mut sandbox_ids: BTreeSet<String> = self.known_sandbox_ids().into_iter().map(String::from).collect();
+        sandbox_ids.extend(self.process_manager().sandbox_ids().await.into_iter().map(String::from));
+        sandbox_ids.extend(self.registry().list().into_iter().map(|e| e.sandbox_id));
+        match self.persistence_manager().stored_sandbox_ids().await {
+            Ok(stored) => sandbox_ids.extend(stored),
+            Err(e) => warn!("Exporting without the sandboxes only the store has: {}", e),
+        }
+
+        let mut sandboxes = Vec::with_capacity(sandbox_ids.len());
+        for sandbox_id in sandbox_ids {
+            let processes = self.process_manager().list_processes(&sandbox_id).await.unwrap_or_default();
+            let persistence = self.persistence_manager();
+
+            sandboxes.push(SandboxStateView {
+                lifecycle: self.lifecycle_state(&sandbox_id).await,
+                priority: self.registry().priority(&sandbox_id),
+                process_count: processes.len(),
+                running_count: processes.iter().filter(|p| p.state == ProcessState::Running).count(),
+                last_snapshot_at: persistence.snapshot_modified_at(&sandbox_id),
+                active_pins: persistence.pin_count(&sandbox_id),
+                pause_pending: self.is_pause_pending(&sandbox_id),
+                sandbox_id,
+            });
+        }
+
+        StateExport {
+            generated_at: Utc::now(),
+            sandboxes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auto_pause::AutoPauseConfig;
+    use crate::bench::FakeBackend;
+    use crate::state_snapshot::StateSnapshot;
+
+    #[tokio::test]
+    async fn test_export_includes_sandboxes_only_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backend, _exits) = FakeBackend::new([]);
+        let manager = AutoPauseManager::with_backend(AutoPauseConfig::default(), Box::new(backend));
+        let persistence = manager.persistence_manager();
+        persistence.set_sandbox_base_dir("paused", dir.path().to_path_buf());
+        persistence.save_snapshot(&StateSnapshot::new("paused".to_string())).await.unwrap();
+        persistence.save_lifecycle("paused", LifecycleState::Paused).await.unwrap();
+        // Registered but never stored
+        persistence.set_sandbox_base_dir("unknown", dir.path().to_path_buf());
+
+        let export = manager.export_state().await;
+        assert_eq!(export.sandboxes.iter().map(|s| s.sandbox_id.as_str()).collect::<Vec<_>>(), ["paused"]);
+        let paused = &export.sandboxes[0];
+        assert_eq!(paused.lifecycle, LifecycleState::Paused);
+        assert_eq!(paused.process_count, 0);
+        assert!(paused.last_snapshot_at.is_some());
+    }
+}
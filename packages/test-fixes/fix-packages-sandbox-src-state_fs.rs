@@ -0,0 +1,390 @@
+use std::collections::BTreeSet;
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auto_pause::AutoPauseManager;
+use crate::events::EventPayload;
+use crate::process::ProcessInfo;
+use crate::process_tree::TreeFormat;
+
+/// Files in every sandbox directory
+const SANDBOX_FILES: [&str; 5] = ["state", "processes", "processes.json", "process_tree", "last_pause.json"];
+/// Files at the root, next to the sandbox directories
+const ROOT_FILES: [&str; 1] = ["config.json"];
+
+/// Where the agent mounts the tree by default
+pub const DEFAULT_MOUNTPOINT: &str = "/run/e2b/state";
+
+/// How long the list of sandboxes is reused between lifecycle events, which refresh it
+/// at once; sandboxes that start being tracked without one show up after this
+const SANDBOXES_TTL: Duration = Duration::from_secs(2);
+
+/// Sandboxes as last exported; None once stale
+#[derive(Default)]
+struct SandboxCache {
+    ids: BTreeSet<String>,
+    refreshed: Option<Instant>,
+}
+
+/// An entry of a virtual directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// Live agent state as a read-only tree, rendered from the managers on every read
+/// except the list of sandboxes, which is cached:
+///
+/// ```text
+/// config.json
+/// <sandbox_id>/state            lifecycle state
+/// <sandbox_id>/processes        ps-style table
+/// <sandbox_id>/processes.json
+/// <sandbox_id>/process_tree     hierarchy with states and memory
+/// <sandbox_id>/last_pause.json  report of the last pause this agent ran, if any
+/// ```
+#[derive(Clone)]
+pub struct StateTree {
+    manager: Arc<AutoPauseManager>,
+    sandboxes: Arc<Mutex<SandboxCache>>,
+}
+
+impl StateTree {
+    /// Must be called within a tokio runtime: a task marks the cached sandbox list
+    /// stale on every lifecycle event, for as long as the tree lives
+    pub fn new(manager: Arc<AutoPauseManager>) -> Self {
+        let sandboxes = Arc::new(Mutex::new(SandboxCache::default()));
+        tokio::spawn(invalidate_on_lifecycle(manager.clone(), Arc::downgrade(&sandboxes)));
+        Self { manager, sandboxes }
+    }
+
+    /// Entries of the directory at `path` (relative to the mount), or None if there is none
+    pub async fn list(&self, path: &Path) -> Option<Vec<StateEntry>> {
+        let parts = split(path)?;
+        match parts.as_slice() {
+            [] => {
+                let dirs = self.sandbox_ids().await.into_iter().map(|name| StateEntry { name, is_dir: true });
+                let files = ROOT_FILES.iter().map(|name| StateEntry { name: name.to_string(), is_dir: false });
+                Some(files.chain(dirs).collect())
+            }
+            [sandbox_id] if self.exists(sandbox_id).await => Some(SANDBOX_FILES.iter().map(|name| StateEntry { name: name.to_string(), is_dir: false }).collect()),
+            _ => None,
+        }
+    }
+
+    /// Whether `path` is a directory, a file, or missing (None)
+    pub async fn kind(&self, path: &Path) -> Option<bool> {
+        let parts = split(path)?;
+        match parts.as_slice() {
+            [] => Some(true),
+            [name] if ROOT_FILES.contains(name) => Some(false),
+            [sandbox_id] => self.exists(sandbox_id).await.then_some(true),
+            [sandbox_id, file] => (SANDBOX_FILES.contains(file) && self.exists(sandbox_id).await).then_some(false),
+            _ => None,
+        }
+    }
+
+    /// Contents of the file at `path`, rendered now
+    pub async fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        let parts = split(path)?;
+        let text = match parts.as_slice() {
+            ["config.json"] => serde_json::to_string_pretty(self.manager.config()).ok()?,
+            [sandbox_id, file] if self.exists(sandbox_id).await => match *file {
+                "state" => format!("{:?}\n", self.manager.lifecycle_state(sandbox_id).await).to_lowercase(),
+                "processes" => render_processes(&self.manager.process_manager().list_processes(sandbox_id).await.unwrap_or_default()),
+                "processes.json" => serde_json::to_string_pretty(&self.manager.process_manager().list_processes(sandbox_id).await.unwrap_or_default()).ok()?,
+                "process_tree" => self.manager.render_process_tree(sandbox_id, TreeFormat::Ascii).await.ok()?,
+                "last_pause.json" => serde_json::to_string_pretty(&self.manager.last_pause_report(sandbox_id)).ok()?,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(text.into_bytes())
+    }
+
+    async fn exists(&self, sandbox_id: &str) -> bool {
+        self.sandbox_ids().await.contains(sandbox_id)
+    }
+
+    async fn sandbox_ids(&self) -> BTreeSet<String> {
+        {
+            let cache = self.sandboxes.lock().unwrap();
+            if cache.refreshed.is_some_and(|at| at.elapsed() < SANDBOXES_TTL) {
+                return cache.ids.clone();
+            }
+        }
+        let ids: BTreeSet<String> = self.manager.export_state().await.sandboxes.into_iter().map(|s| s.sandbox_id).collect();
+        *self.sandboxes.lock().unwrap() = SandboxCache { ids: ids.clone(), refreshed: Some(Instant::now()) };
+        ids
+    }
+}
+
+async fn invalidate_on_lifecycle(manager: Arc<AutoPauseManager>, sandboxes: Weak<Mutex<SandboxCache>>) {
+    let mut events = manager.subscribe_events();
+    drop(manager);
+    loop {
+        let stale = match events.recv().await {
+            Ok(event) => matches!(event.payload, EventPayload::Lifecycle { .. }),
+            Err(RecvError::Lagged(_)) => true,
+            Err(RecvError::Closed) => return,
+        };
+        let Some(sandboxes) = sandboxes.upgrade() else {
+            return;
+        };
+        if stale {
+            sandboxes.lock().unwrap().refreshed = None;
+        }
+    }
+}
+
+/// A mounted state tree; unmounted when dropped
+#[cfg(feature = "fuse")]
+pub type StateFsSession = fuser::BackgroundSession;
+#[cfg(not(feature = "fuse"))]
+pub type StateFsSession = ();
+
+impl AutoPauseManager {
+    /// Mount the live-state tree at `state_fs`, if set. Must be called within a tokio
+    /// runtime; a failed mount is logged and leaves the agent running without it.
+    pub fn mount_state_fs(self: &Arc<Self>) -> Option<StateFsSession> {
+        let mountpoint = self.config().state_fs.clone()?;
+        match mount(StateTree::new(self.clone()), &mountpoint) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                warn!("Failed to mount the state filesystem at {}: {}", mountpoint.display(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Path components as strings; None for anything but plain names
+fn split(path: &Path) -> Option<Vec<&str>> {
+    path.components()
+        .filter(|c| !matches!(c, Component::RootDir | Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// One line per process, like `ps`
+pub fn render_processes(processes: &[ProcessInfo]) -> String {
+    let mut out = format!("{:>7} {:<10} {:>7} {:<20} {}\n", "PID", "STATE", "THREADS", "STARTED", "COMMAND");
+    for p in processes {
+        let state = format!("{:?}", p.state).to_lowercase();
+        out.push_str(&format!("{:>7} {:<10} {:>7} {:<20} {}\n", p.pid, state, p.thread_count, p.start_time.format("%Y-%m-%d %H:%M:%S"), p.cmd));
+    }
+    out
+}
+
+/// Mount `tree` at `mountpoint` until the returned session is dropped
+#[cfg(feature = "fuse")]
+pub fn mount(tree: StateTree, mountpoint: &Path) -> std::io::Result<StateFsSession> {
+    std::fs::create_dir_all(mountpoint)?;
+    let options = [fuser::MountOption::RO, fuser::MountOption::FSName("e2b-state".to_string()), fuser::MountOption::AutoUnmount];
+    fuser::spawn_mount2(fuse::StateFs::new(tree, tokio::runtime::Handle::current()), mountpoint, &options)
+}
+
+#[cfg(not(feature = "fuse"))]
+pub fn mount(_tree: StateTree, _mountpoint: &Path) -> std::io::Result<StateFsSession> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "the state filesystem is not available in this build"))
+}
+
+#[cfg(feature = "fuse")]
+mod fuse {
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    use fuser::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyOpen, Request};
+    use tokio::runtime::Handle;
+
+    use super::StateTree;
+
+    /// Attributes are not cached: the contents change with every read
+    const TTL: Duration = Duration::ZERO;
+    const ROOT_INO: u64 = 1;
+
+    pub struct StateFs {
+        tree: StateTree,
+        runtime: Handle,
+        /// Inode of every path looked up so far; index + 1 is the inode
+        paths: Vec<PathBuf>,
+        inodes: HashMap<PathBuf, u64>,
+        /// Contents rendered at open, so one read of a file sees one version of it
+        open: HashMap<u64, Vec<u8>>,
+        next_fh: u64,
+    }
+
+    impl StateFs {
+        pub fn new(tree: StateTree, runtime: Handle) -> Self {
+            Self {
+                tree,
+                runtime,
+                paths: vec![PathBuf::new()],
+                inodes: HashMap::from([(PathBuf::new(), ROOT_INO)]),
+                open: HashMap::new(),
+                next_fh: 1,
+            }
+        }
+
+        fn inode(&mut self, path: PathBuf) -> u64 {
+            if let Some(ino) = self.inodes.get(&path) {
+                return *ino;
+            }
+            self.paths.push(path.clone());
+            let ino = self.paths.len() as u64;
+            self.inodes.insert(path, ino);
+            ino
+        }
+
+        fn path(&self, ino: u64) -> Option<PathBuf> {
+            self.paths.get(ino.checked_sub(1)? as usize).cloned()
+        }
+
+        fn attr(ino: u64, is_dir: bool) -> FileAttr {
+            let now = SystemTime::now();
+            FileAttr {
+                ino,
+                size: 0,
+                blocks: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                crtime: now,
+                kind: if is_dir { FileType::Directory } else { FileType::RegularFile },
+                perm: if is_dir { 0o555 } else { 0o444 },
+                nlink: if is_dir { 2 } else { 1 },
+                uid: 0,
+                gid: 0,
+                rdev: 0,
+                blksize: 4096,
+                flags: 0,
+            }
+        }
+    }
+
+    impl Filesystem for StateFs {
+        fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+            let Some(path) = self.path(parent).map(|p| p.join(name)) else {
+                return reply.error(libc::ENOENT);
+            };
+            match self.runtime.block_on(self.tree.kind(&path)) {
+                Some(is_dir) => {
+                    let ino = self.inode(path);
+                    reply.entry(&TTL, &Self::attr(ino, is_dir), 0);
+                }
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+            let kind = self.path(ino).and_then(|path| self.runtime.block_on(self.tree.kind(&path)));
+            match kind {
+                Some(is_dir) => reply.attr(&TTL, &Self::attr(ino, is_dir)),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+            let contents = self.path(ino).and_then(|path| self.runtime.block_on(self.tree.read(&path)));
+            let Some(contents) = contents else {
+                return reply.error(libc::ENOENT);
+            };
+            let fh = self.next_fh;
+            self.next_fh += 1;
+            self.open.insert(fh, contents);
+            // Sizes are unknown until rendered, so reads bypass the page cache like /proc
+            reply.opened(fh, fuser::consts::FOPEN_DIRECT_IO);
+        }
+
+        fn read(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, offset: i64, size: u32, _flags: i32, _lock: Option<u64>, reply: ReplyData) {
+            let Some(contents) = self.open.get(&fh) else {
+                return reply.error(libc::EBADF);
+            };
+            let start = (offset.max(0) as usize).min(contents.len());
+            let end = (start + size as usize).min(contents.len());
+            reply.data(&contents[start..end]);
+        }
+
+        fn release(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _flags: i32, _lock: Option<u64>, _flush: bool, reply: ReplyEmpty) {
+            self.open.remove(&fh);
+            reply.ok();
+        }
+
+        fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+            let Some(path) = self.path(ino) else {
+                return reply.error(libc::ENOENT);
+            };
+            let Some(entries) = self.runtime.block_on(self.tree.list(&path)) else {
+                return reply.error(libc::ENOTDIR);
+            };
+            let parent = path.parent().map(|p| self.inode(p.to_path_buf())).unwrap_or(ROOT_INO);
+            let mut all = vec![(ino, FileType::Directory, ".".to_string()), (parent, FileType::Directory, "..".to_string())];
+            for entry in entries {
+                let kind = if entry.is_dir { FileType::Directory } else { FileType::RegularFile };
+                all.push((self.inode(path.join(&entry.name)), kind, entry.name));
+            }
+            for (i, (ino, kind, name)) in all.into_iter().enumerate().skip(offset as usize) {
+                if reply.add(ino, (i + 1) as i64, kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use crate::lifecycle::LifecycleState;
+    use crate::process::ProcessState;
+    use crate::test_support::process_info;
+
+    #[test]
+    fn test_split_and_render() {
+        assert_eq!(split(Path::new("/sbx/state")), Some(vec!["sbx", "state"]));
+        assert_eq!(split(Path::new("")), Some(vec![]));
+        assert_eq!(split(Path::new("sbx/../etc")), None);
+
+        let process: ProcessInfo = serde_json::from_value(serde_json::json!({
+            "pid": 42,
+            "name": "python",
+            "cmd": "python app.py",
+            "start_time": Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            "state": ProcessState::Running,
+            "thread_count": 3,
+        }))
+        .unwrap();
+        let table = render_processes(&[process]);
+        assert_eq!(table.lines().nth(1).unwrap(), "     42 running          3 2024-05-01 12:00:00  python app.py");
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_list_is_cached_until_a_lifecycle_event() {
+        let manager = Arc::new(AutoPauseManager::new(Default::default()));
+        let tree = StateTree::new(manager.clone());
+        assert!(!tree.exists("sbx").await);
+
+        // Tracked without a lifecycle event: not seen until the cache expires
+        manager.process_manager().add_process("sbx", process_info(4_200_001, "web")).await.unwrap();
+        assert!(!tree.exists("sbx").await);
+
+        manager.set_lifecycle_state("sbx", LifecycleState::Running).await;
+        for _ in 0..100 {
+            if tree.exists("sbx").await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the lifecycle event did not refresh the sandbox list");
+    }
+}
@@ -1,32 +1,189 @@
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
+use crate::cgroup::CgroupLimits;
+use crate::launch_hints::LaunchContext;
+use crate::process::{OomEvent, ProcessExit, SessionRef};
+use crate::sandbox_id::SandboxId;
+use crate::sessions::SessionSummary;
+
+/// Summary of a process's open file descriptors at snapshot time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FdSummary {
+    pub files: u32,
+    pub sockets: u32,
+    pub pipes: u32,
+    pub other: u32,
+    /// Regular files held open, excluding /dev and /proc (capped)
+    pub notable_paths: Vec<String>,
+    /// TCP ports the process was listening on
+    pub listening_ports: Vec<u16>,
+}
+
+impl FdSummary {
+    /// Whether the process held resources that cannot survive a restart
+    pub fn has_non_restorable(&self) -> bool {
+        self.sockets > 0 || self.pipes > 0
+    }
+}
+
+/// A POSIX/flock lock held by a process at snapshot time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileLock {
+    /// POSIX, FLOCK or OFDLCK
+    pub kind: String,
+    /// READ or WRITE
+    pub access: String,
+    /// Device and inode as reported by /proc/locks (`maj:min:inode`)
+    pub dev_inode: String,
+    pub inode: u64,
+    pub path: Option<String>,
+}
+
 /// Persisted process information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersistedProcess {
     pub pid: i32,
     pub name: String,
     pub cmd: String,
+    /// Argument vector as passed to exec; empty in snapshots taken before it was captured
+    #[serde(default)]
+    pub argv: Vec<String>,
     pub start_time: DateTime<Utc>,
     pub state: String, // "running", "suspended", "terminated"
+    #[serde(default)]
+    pub thread_count: u32,
+    #[serde(default)]
+    pub child_count: u32,
+    /// Parent PID at snapshot time, for drawing the process tree
+    #[serde(default)]
+    pub ppid: Option<i32>,
+    #[serde(default)]
+    pub fds: Option<FdSummary>,
+    #[serde(default)]
+    pub locks: Vec<FileLock>,
+    #[serde(default)]
+    pub exit: Option<ProcessExit>,
+    #[serde(default)]
+    pub ended_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub session: Option<SessionRef>,
+    /// Executable, cwd, interpreter and PATH at snapshot time, for relaunching
+    #[serde(default)]
+    pub launch: Option<LaunchContext>,
+    /// Real UID at snapshot time; a survivor is only adopted when it runs as the same user
+    #[serde(default)]
+    pub uid: Option<u32>,
+    /// `argv` is known to be incomplete, e.g. read from a /proc cmdline the kernel
+    /// cut at one page; such a process is never relaunched
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub argv_truncated: bool,
+}
+
+/// Size limits applied to a snapshot before it is written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotCaps {
+    /// Longest command line kept, in bytes
+    pub max_cmd_bytes: usize,
+    /// Labels kept per process, and the longest label value
+    pub max_labels: usize,
+    pub max_label_bytes: usize,
+    /// Open paths and locks kept per process
+    pub max_notable_paths: usize,
+    pub max_locks: usize,
+    /// Serialized size of all processes together; processes past it are dropped,
+    /// terminated ones first
+    pub max_snapshot_bytes: usize,
+}
+
+impl Default for SnapshotCaps {
+    fn default() -> Self {
+        Self {
+            max_cmd_bytes: 16 * 1024,
+            max_labels: 64,
+            max_label_bytes: 4 * 1024,
+            max_notable_paths: 256,
+            max_locks: 256,
+            max_snapshot_bytes: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// Records that a field was cut to fit the snapshot caps, so restore and
+/// tooling know the data is incomplete
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Truncation {
+    /// None for snapshot-wide truncation
+    pub pid: Option<i32>,
+    /// `cmd`, `labels`, `labels.<key>`, `notable_paths`, `locks` or `processes`
+    pub field: String,
+    /// Size before and after, in bytes for strings and entries for lists
+    pub original: usize,
+    pub kept: usize,
+}
+
+/// Cut `value` to at most `max` bytes on a char boundary; returns the original length if cut
+fn truncate_str(value: &mut String, max: usize) -> Option<usize> {
+    if value.len() <= max {
+        return None;
+    }
+    let original = value.len();
+    let mut end = max;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value.truncate(end);
+    Some(original)
 }
 
 /// Complete state snapshot for a sandbox
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StateSnapshot {
-    pub sandbox_id: String,
+    pub sandbox_id: SandboxId,
     pub timestamp: DateTime<Utc>,
     pub processes: Vec<PersistedProcess>,
+    /// Login sessions of the sandbox at snapshot time
+    #[serde(default)]
+    pub sessions: Vec<SessionSummary>,
+    /// Limits the sandbox cgroup had before a soft pause clamped them
+    #[serde(default)]
+    pub cgroup_limits: Option<CgroupLimits>,
+    /// Recent OOM kills in the sandbox
+    #[serde(default)]
+    pub oom_events: Vec<OomEvent>,
+    /// Fields cut short by the snapshot caps
+    #[serde(default)]
+    pub truncations: Vec<Truncation>,
+    /// User the sandbox belonged to when it was snapshotted
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Team the sandbox belonged to when it was snapshotted
+    #[serde(default)]
+    pub team: Option<String>,
+    /// Processes a Freeze policy stopped during the pause; resume continues them
+    #[serde(default)]
+    pub frozen: Vec<i32>,
 }
 
 impl StateSnapshot {
     /// Create a new state snapshot
-    pub fn new(sandbox_id: String) -> Self {
+    pub fn new(sandbox_id: impl Into<SandboxId>) -> Self {
         Self {
-            sandbox_id,
+            sandbox_id: sandbox_id.into(),
             timestamp: Utc::now(),
             processes: Vec::new(),
+            sessions: Vec::new(),
+            cgroup_limits: None,
+            oom_events: Vec::new(),
+            truncations: Vec::new(),
+            owner: None,
+            team: None,
+            frozen: Vec::new(),
         }
     }
 
@@ -35,7 +192,45 @@ impl StateSnapshot {
         self.processes.push(process);
     }
 
-    /// Get the snapshot file path
+    /// Copy this snapshot for a different sandbox. Sandbox-scoped fields
+    /// (ID, paths, launch context and commands that mention the source ID) are rewritten,
+    /// locks are dropped and every process is marked terminated until relaunched.
+    /// Only path components that are exactly the source ID are rewritten.
+    pub fn clone_for(&self, new_sandbox_id: &str) -> Self {
+        let rewrite = |value: &str| replace_path_component(value, self.sandbox_id.as_str(), new_sandbox_id);
+        let processes = self
+            .processes
+            .iter()
+            .map(|p| {
+                let mut process = p.clone();
+                process.cmd = rewrite(&p.cmd);
+                process.argv = p.argv.iter().map(|arg| rewrite(arg)).collect();
+                process.state = "terminated".to_string();
+                process.locks.clear();
+                if let Some(fds) = process.fds.as_mut() {
+                    fds.notable_paths = fds.notable_paths.iter().map(|path| rewrite(path)).collect();
+                }
+                process.launch = p.launch.as_ref().map(|launch| launch.rewrite_paths(rewrite));
+                process
+            })
+            .collect();
+
+        Self {
+            sandbox_id: SandboxId::new(new_sandbox_id),
+            timestamp: Utc::now(),
+            processes,
+            sessions: self.sessions.clone(),
+            cgroup_limits: None,
+            oom_events: Vec::new(),
+            truncations: self.truncations.clone(),
+            owner: self.owner.clone(),
+            team: self.team.clone(),
+            // The clone relaunches its processes; nothing of it is stopped
+            frozen: Vec::new(),
+        }
+    }
+
+    /// Get the snapshot file path in the legacy flat layout
     pub fn get_snapshot_path(&self, base_dir: &PathBuf) -> PathBuf {
         base_dir.join(format!("{}.snapshot.json", self.sandbox_id))
     }
@@ -50,6 +245,74 @@ impl StateSnapshot {
         Ok(serde_json::from_str(json)?)
     }
 
+    /// Cut oversized fields and, if the processes together are still too large, drop
+    /// processes until they fit. Every cut is recorded in `truncations`.
+    pub fn apply_caps(&mut self, caps: &SnapshotCaps) {
+        let mut cut = Vec::new();
+        for process in &mut self.processes {
+            let pid = Some(process.pid);
+            let mut record = |field: String, original: usize, kept: usize| cut.push(Truncation { pid, field, original, kept });
+
+            // argv is kept whole: a cut one cannot be exec'd. Only the display command is capped.
+            if let Some(original) = truncate_str(&mut process.cmd, caps.max_cmd_bytes) {
+                record("cmd".to_string(), original, process.cmd.len());
+            }
+            if process.labels.len() > caps.max_labels {
+                let original = process.labels.len();
+                let dropped: Vec<String> = process.labels.keys().skip(caps.max_labels).cloned().collect();
+                for key in dropped {
+                    process.labels.remove(&key);
+                }
+                record("labels".to_string(), original, caps.max_labels);
+            }
+            for (key, value) in process.labels.iter_mut() {
+                if let Some(original) = truncate_str(value, caps.max_label_bytes) {
+                    record(format!("labels.{}", key), original, value.len());
+                }
+            }
+            if let Some(fds) = process.fds.as_mut() {
+                if fds.notable_paths.len() > caps.max_notable_paths {
+                    record("notable_paths".to_string(), fds.notable_paths.len(), caps.max_notable_paths);
+                    fds.notable_paths.truncate(caps.max_notable_paths);
+                }
+            }
+            if process.locks.len() > caps.max_locks {
+                record("locks".to_string(), process.locks.len(), caps.max_locks);
+                process.locks.truncate(caps.max_locks);
+            }
+        }
+
+        let sizes: Vec<usize> = self
+            .processes
+            .iter()
+            .map(|p| serde_json::to_vec(p).map(|json| json.len()).unwrap_or(0))
+            .collect();
+        let mut total: usize = sizes.iter().sum();
+        if total > caps.max_snapshot_bytes {
+            let original = self.processes.len();
+            // Drop terminated processes first, then the latest-tracked ones
+            let mut order: Vec<usize> = (0..original).collect();
+            order.sort_by_key(|&i| (self.processes[i].state != "terminated", std::cmp::Reverse(i)));
+            let mut keep = vec![true; original];
+            for i in order {
+                if total <= caps.max_snapshot_bytes {
+                    break;
+                }
+                keep[i] = false;
+                total -= sizes[i];
+            }
+            let mut keep = keep.into_iter();
+            self.processes.retain(|_| keep.next().unwrap_or(true));
+            cut.push(Truncation {
+                pid: None,
+                field: "processes".to_string(),
+                original,
+                kept: self.processes.len(),
+            });
+        }
+        self.truncations.extend(cut);
+    }
+
     /// Check if this snapshot is stale (older than 24 hours)
     pub fn is_stale(&self) -> bool {
         let max_age = chrono::Duration::hours(24);
@@ -57,22 +320,43 @@ impl StateSnapshot {
     }
 }
 
+/// `value` with every path component that is exactly `from` replaced by `to`.
+/// Components end at `/`, whitespace, `=` and `:`, so commands and PATH lists work
+/// too, and names that merely contain `from` are left alone.
+fn replace_path_component(value: &str, from: &str, to: &str) -> String {
+    if from.is_empty() {
+        return value.to_string();
+    }
+    let is_boundary = |c: Option<char>| match c {
+        None => true,
+        Some(c) => matches!(c, '/' | '=' | ':') || c.is_whitespace(),
+    };
+    let mut rewritten = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut previous = None;
+    while let Some(at) = rest.find(from) {
+        let before = rest[..at].chars().next_back().or(previous);
+        let after = rest[at + from.len()..].chars().next();
+        rewritten.push_str(&rest[..at]);
+        rewritten.push_str(if is_boundary(before) && is_boundary(after) { to } else { from });
+        previous = from.chars().next_back();
+        rest = &rest[at + from.len()..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use chrono::TimeZone;
+    use crate::test_support::persisted_process;
 
     #[test]
     fn test_state_snapshot_serialization() {
         let mut snapshot = StateSnapshot::new("test-sandbox".to_string());
         
-        let process = PersistedProcess {
-            pid: 1234,
-            name: "test-process".to_string(),
-            cmd: "test-command".to_string(),
-            start_time: Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap(),
-            state: "running".to_string(),
-        };
+        let process = persisted_process(1234, &["test-command"], Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap());
         
         snapshot.add_process(process);
         
@@ -84,6 +368,109 @@ mod tests {
         assert_eq!(restored.processes[0].pid, 1234);
     }
 
+    #[test]
+    fn test_caps_truncate_and_record() {
+        let mut snapshot = StateSnapshot::new("capped".to_string());
+        for pid in 1..=3 {
+            snapshot.add_process(PersistedProcess {
+                cmd: "é".repeat(100),
+                argv: Vec::new(),
+                state: if pid == 1 { "terminated" } else { "running" }.to_string(),
+                labels: (0..5).map(|i| (format!("k{}", i), "v".to_string())).collect(),
+                ..persisted_process(pid, &["worker"], Utc.with_ymd_and_hms(2023, 1, 1, 12, 0, 0).unwrap())
+            });
+        }
+        let caps = SnapshotCaps {
+            max_cmd_bytes: 11,
+            max_labels: 2,
+            max_snapshot_bytes: 400,
+            ..SnapshotCaps::default()
+        };
+        snapshot.apply_caps(&caps);
+
+        let survivor = &snapshot.processes[0];
+        assert_eq!(survivor.cmd.len(), 10);
+        assert_eq!(survivor.labels.len(), 2);
+        assert_eq!(snapshot.processes.iter().map(|p| p.pid).collect::<Vec<_>>(), vec![2]);
+        assert!(snapshot.truncations.contains(&Truncation { pid: Some(2), field: "cmd".to_string(), original: 200, kept: 10 }));
+        assert_eq!(snapshot.truncations.last().unwrap().field, "processes");
+    }
+
+    #[test]
+    fn test_caps_keep_argv_whole() {
+        let mut snapshot = StateSnapshot::new("capped".to_string());
+        let long = "x".repeat(64);
+        snapshot.add_process(persisted_process(1, &["worker", &long, &long], Utc::now()));
+        snapshot.apply_caps(&SnapshotCaps { max_cmd_bytes: 16, ..SnapshotCaps::default() });
+
+        let process = &snapshot.processes[0];
+        assert_eq!(process.argv.len(), 3);
+        assert_eq!(process.cmd.len(), 16);
+        assert!(!process.argv_truncated);
+    }
+
+    #[test]
+    fn test_snapshot_without_fd_inventory_still_loads() {
+        let json = r#"{
+            "sandbox_id": "test-sandbox",
+            "timestamp": "2023-01-01T12:00:00Z",
+            "processes": [
+                {"pid": 1, "name": "sh", "cmd": "sh", "start_time": "2023-01-01T12:00:00Z", "state": "running"}
+            ]
+        }"#;
+
+        let snapshot = StateSnapshot::from_json(json).unwrap();
+        assert!(snapshot.processes[0].fds.is_none());
+    }
+
+    #[test]
+    fn test_clone_for_rewrites_sandbox_scoped_fields() {
+        let mut snapshot = StateSnapshot::new("sbx-a".to_string());
+        let mut process = persisted_process(42, &["server", "--data", "/home/user/sbx-a"], Utc::now());
+        process.launch = Some(LaunchContext {
+            exe: Some("/home/user/sbx-a/venv/bin/python3".to_string()),
+            cwd: Some("/home/user/sbx-a".to_string()),
+            interpreter: Some("python".to_string()),
+            venv: Some("/home/user/sbx-a/venv".to_string()),
+            path: Some("/home/user/sbx-a/venv/bin:/usr/bin".to_string()),
+        });
+        snapshot.add_process(process);
+
+        let cloned = snapshot.clone_for("sbx-b");
+        assert_eq!(cloned.sandbox_id, "sbx-b");
+        assert_eq!(cloned.processes[0].cmd, "server --data /home/user/sbx-b");
+        assert_eq!(cloned.processes[0].argv, vec!["server", "--data", "/home/user/sbx-b"]);
+        assert_eq!(cloned.processes[0].state, "terminated");
+        let launch = cloned.processes[0].launch.as_ref().unwrap();
+        assert_eq!(launch.exe.as_deref(), Some("/home/user/sbx-b/venv/bin/python3"));
+        assert_eq!(launch.cwd.as_deref(), Some("/home/user/sbx-b"));
+        assert_eq!(launch.venv.as_deref(), Some("/home/user/sbx-b/venv"));
+        assert_eq!(launch.path.as_deref(), Some("/home/user/sbx-b/venv/bin:/usr/bin"));
+        assert_eq!(snapshot.processes[0].cmd, "server --data /home/user/sbx-a");
+    }
+
+    #[test]
+    fn test_clone_for_rewrites_whole_path_components_only() {
+        let mut snapshot = StateSnapshot::new("sbx-a".to_string());
+        let argv = ["server", "--data=/home/user/sbx-a/db", "--peer", "/home/user/sbx-ab", "/srv/old-sbx-a"];
+        let mut process = persisted_process(42, &argv, Utc::now());
+        process.launch = Some(LaunchContext {
+            exe: Some("/opt/sbx-a.bin".to_string()),
+            cwd: Some("/home/user/sbx-a".to_string()),
+            path: Some("/home/sbx-a/bin:/opt/sbx-abc/bin".to_string()),
+            ..LaunchContext::default()
+        });
+        snapshot.add_process(process);
+
+        let cloned = snapshot.clone_for("sbx-b");
+        assert_eq!(cloned.processes[0].argv, vec!["server", "--data=/home/user/sbx-b/db", "--peer", "/home/user/sbx-ab", "/srv/old-sbx-a"]);
+        assert_eq!(cloned.processes[0].cmd, "server --data=/home/user/sbx-b/db --peer /home/user/sbx-ab /srv/old-sbx-a");
+        let launch = cloned.processes[0].launch.as_ref().unwrap();
+        assert_eq!(launch.exe.as_deref(), Some("/opt/sbx-a.bin"));
+        assert_eq!(launch.cwd.as_deref(), Some("/home/user/sbx-b"));
+        assert_eq!(launch.path.as_deref(), Some("/home/sbx-b/bin:/opt/sbx-abc/bin"));
+    }
+
     #[test]
     fn test_stale_snapshot_detection() {
         let mut snapshot = StateSnapshot::new("test-sandbox".to_string());
@@ -0,0 +1,287 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::auto_pause::PauseStrategy;
+use crate::lifecycle::LifecycleState;
+use crate::remote::constant_time_eq;
+use crate::sandbox_id::SandboxId;
+
+/// Points kept per sandbox; older ones are dropped first
+const MAX_POINTS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum TimelineEvent {
+    State { state: LifecycleState },
+    Pause { duration_ms: u64, strategy: PauseStrategy },
+    Resume { duration_ms: u64 },
+    Snapshot { bytes: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelinePoint {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: TimelineEvent,
+}
+
+/// Pre-aggregated lifecycle data of one sandbox, ready to chart
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SandboxStats {
+    pub sandbox_id: String,
+    pub state: Option<LifecycleState>,
+    pub pauses: u64,
+    pub resumes: u64,
+    pub last_pause_ms: Option<u64>,
+    pub last_resume_ms: Option<u64>,
+    pub last_snapshot_bytes: Option<u64>,
+    pub timeline: Vec<TimelinePoint>,
+}
+
+/// Bounded per-sandbox timelines of state changes, durations and snapshot sizes
+#[derive(Default)]
+pub struct StatsStore {
+    timelines: Mutex<HashMap<SandboxId, VecDeque<TimelinePoint>>>,
+}
+
+impl StatsStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, sandbox_id: &str, event: TimelineEvent) {
+        let mut timelines = self.timelines.lock().unwrap();
+        let timeline = timelines.entry(SandboxId::new(sandbox_id)).or_default();
+        if timeline.len() == MAX_POINTS {
+            timeline.pop_front();
+        }
+        timeline.push_back(TimelinePoint { at: Utc::now(), event });
+    }
+
+    /// Forget a sandbox that no longer exists
+    pub fn remove(&self, sandbox_id: &str) {
+        self.timelines.lock().unwrap().remove(sandbox_id);
+    }
+
+    pub fn sandbox(&self, sandbox_id: &str) -> Option<SandboxStats> {
+        let timelines = self.timelines.lock().unwrap();
+        timelines.get(sandbox_id).map(|timeline| aggregate(sandbox_id, timeline))
+    }
+
+    pub fn all(&self) -> Vec<SandboxStats> {
+        let timelines = self.timelines.lock().unwrap();
+        let mut stats: Vec<SandboxStats> = timelines.iter().map(|(id, timeline)| aggregate(id, timeline)).collect();
+        stats.sort_by(|a, b| a.sandbox_id.cmp(&b.sandbox_id));
+        stats
+    }
+}
+
+fn aggregate(sandbox_id: &str, timeline: &VecDeque<TimelinePoint>) -> SandboxStats {
+    let mut stats = SandboxStats {
+        sandbox_id: sandbox_id.to_string(),
+        timeline: timeline.iter().cloned().collect(),
+        ..SandboxStats::default()
+    };
+    for point in timeline {
+        match &point.event {
+            TimelineEvent::State { state } => stats.state = Some(*state),
+            TimelineEvent::Pause { duration_ms, .. } => {
+                stats.pauses += 1;
+                stats.last_pause_ms = Some(*duration_ms);
+            }
+            TimelineEvent::Resume { duration_ms } => {
+                stats.resumes += 1;
+                stats.last_resume_ms = Some(*duration_ms);
+            }
+            TimelineEvent::Snapshot { bytes } => stats.last_snapshot_bytes = Some(*bytes),
+        }
+    }
+    stats
+}
+
+/// Serves `GET /stats` and `GET /stats/<sandbox_id>` as JSON. Without a token it
+/// only binds to loopback addresses.
+pub struct StatsServer {
+    store: Arc<StatsStore>,
+    addr: SocketAddr,
+    token: Option<String>,
+}
+
+impl StatsServer {
+    pub fn new(store: Arc<StatsStore>, addr: SocketAddr) -> Self {
+        Self { store, addr, token: None }
+    }
+
+    /// Require `Authorization: Bearer <token>` on every request
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub async fn spawn(self) -> Result<JoinHandle<()>, Box<dyn std::error::Error>> {
+        match &self.token {
+            Some(token) if token.is_empty() => return Err("stats server token is empty".into()),
+            None if !self.addr.ip().is_loopback() => {
+                return Err(format!("refusing to serve stats on {} without a token; bind to localhost or set one", self.addr).into());
+            }
+            _ => {}
+        }
+        let listener = TcpListener::bind(self.addr).await?;
+        info!("Serving sandbox stats on http://{}/stats", listener.local_addr()?);
+        let store = self.store;
+        let token: Option<Arc<str>> = self.token.map(Into::into);
+        Ok(tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept stats connection: {}", e);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let store = store.clone();
+                let token = token.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, &store, token.as_deref()).await {
+                        debug!("Stats request from {} failed: {}", peer, e);
+                    }
+                });
+            }
+        }))
+    }
+}
+
+async fn handle(stream: TcpStream, store: &StatsStore, token: Option<&str>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // Read the headers up to the blank line; requests have no body
+    let mut bearer = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 2 {
+        bearer = bearer.or_else(|| bearer_token(&header));
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let authorized = token.is_none_or(|token| bearer.is_some_and(|bearer| constant_time_eq(bearer.as_bytes(), token.as_bytes())));
+    let (status, body) = match (method, route(path)) {
+        _ if !authorized => ("401 Unauthorized", Ok(r#"{"error":"missing or wrong bearer token"}"#.to_string())),
+        ("GET", Some(None)) => ("200 OK", serde_json::to_string(&store.all())),
+        ("GET", Some(Some(sandbox_id))) => match store.sandbox(sandbox_id) {
+            Some(stats) => ("200 OK", serde_json::to_string(&stats)),
+            None => ("404 Not Found", Ok(r#"{"error":"unknown sandbox"}"#.to_string())),
+        },
+        ("GET", None) => ("404 Not Found", Ok(r#"{"error":"not found"}"#.to_string())),
+        _ => ("405 Method Not Allowed", Ok(r#"{"error":"method not allowed"}"#.to_string())),
+    };
+    let body = body.unwrap_or_else(|e| format!(r#"{{"error":"{}"}}"#, e));
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let mut stream = reader.into_inner();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Token of an `Authorization: Bearer` header line
+fn bearer_token(header: &str) -> Option<String> {
+    let (name, value) = header.split_once(':')?;
+    if !name.trim().eq_ignore_ascii_case("authorization") {
+        return None;
+    }
+    value.trim().strip_prefix("Bearer ").map(|token| token.to_string())
+}
+
+/// `Some(None)` for the overview, `Some(Some(id))` for one sandbox
+fn route(path: &str) -> Option<Option<&str>> {
+    let path = path.split('?').next().unwrap_or(path).trim_end_matches('/');
+    match path.strip_prefix("/stats") {
+        Some("") => Some(None),
+        Some(rest) => rest.strip_prefix('/').filter(|id| !id.is_empty() && !id.contains('/')).map(Some),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregates_timeline() {
+        let store = StatsStore::new();
+        store.record("sbx", TimelineEvent::State { state: LifecycleState::Pausing });
+        store.record("sbx", TimelineEvent::Snapshot { bytes: 2048 });
+        store.record("sbx", TimelineEvent::Pause { duration_ms: 120, strategy: PauseStrategy::Persist });
+        store.record("sbx", TimelineEvent::State { state: LifecycleState::Paused });
+
+        let stats = store.sandbox("sbx").unwrap();
+        assert_eq!(stats.state, Some(LifecycleState::Paused));
+        assert_eq!(stats.pauses, 1);
+        assert_eq!(stats.last_pause_ms, Some(120));
+        assert_eq!(stats.last_snapshot_bytes, Some(2048));
+        assert_eq!(stats.timeline.len(), 4);
+        assert!(store.sandbox("other").is_none());
+    }
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("/stats"), Some(None));
+        assert_eq!(route("/stats/?pretty"), Some(None));
+        assert_eq!(route("/stats/sbx-1"), Some(Some("sbx-1")));
+        assert_eq!(route("/metrics"), None);
+        assert_eq!(route("/stats/a/b"), None);
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Authorization: Bearer s3cret\r\n"), Some("s3cret".to_string()));
+        assert_eq!(bearer_token("authorization:Bearer s3cret\r\n"), Some("s3cret".to_string()));
+        assert_eq!(bearer_token("Authorization: Basic czNjcmV0\r\n"), None);
+        assert_eq!(bearer_token("Host: localhost\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_requires_token_off_loopback() {
+        let store = Arc::new(StatsStore::new());
+        assert!(StatsServer::new(store.clone(), "0.0.0.0:0".parse().unwrap()).spawn().await.is_err());
+        assert!(StatsServer::new(store.clone(), "127.0.0.1:0".parse().unwrap()).with_token("").spawn().await.is_err());
+        StatsServer::new(store, "127.0.0.1:0".parse().unwrap()).spawn().await.unwrap().abort();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_wrong_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let store = StatsStore::new();
+        store.record("sbx", TimelineEvent::Snapshot { bytes: 1 });
+        let server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                handle(stream, &store, Some("s3cret")).await.unwrap();
+            }
+        });
+
+        for (header, status) in [("Authorization: Bearer guess", "401"), ("Authorization: Bearer s3cret", "200")] {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(format!("GET /stats/sbx HTTP/1.1\r\n{}\r\n\r\n", header).as_bytes()).await.unwrap();
+            let mut response = String::new();
+            tokio::io::AsyncReadExt::read_to_string(&mut stream, &mut response).await.unwrap();
+            assert!(response.starts_with(&format!("HTTP/1.1 {}", status)), "{}", response);
+        }
+        server.await.unwrap();
+    }
+}
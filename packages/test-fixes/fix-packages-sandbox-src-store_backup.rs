@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+use crate::quarantine::QUARANTINE_DIR;
+
+/// Name of the manifest stored as the first entry of every store archive
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Prefix of the staging directory a restore unpacks into
+const STAGING_PREFIX: &str = ".restore-";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the store's base directory
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// Contents of a store archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreManifest {
+    pub created_at: DateTime<Utc>,
+    pub sandboxes: Vec<String>,
+    pub files: Vec<ManifestEntry>,
+}
+
+impl StoreManifest {
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes).sum()
+    }
+}
+
+/// Files to archive: everything under `base` except quarantine, restore staging and
+/// half-written temp files, as paths relative to `base`
+pub(crate) fn collect_files(base: &Path, dir: &Path, out: &mut Vec<ManifestEntry>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("reading {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if dir == base && (name == QUARANTINE_DIR || name.starts_with(STAGING_PREFIX)) {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(|e| format!("reading {}: {}", path.display(), e))?;
+        if metadata.is_dir() {
+            collect_files(base, &path, out)?;
+        } else if metadata.is_file() && path.extension().is_none_or(|ext| ext != "tmp") {
+            let relative = path.strip_prefix(base).map_err(|e| e.to_string())?;
+            out.push(ManifestEntry {
+                path: relative.to_string_lossy().into_owned(),
+                bytes: metadata.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn write_archive(base: &Path, dest: &Path) -> Result<StoreManifest, String> {
+    let mut files = Vec::new();
+    if base.exists() {
+        collect_files(base, base, &mut files)?;
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut sandboxes: Vec<String> = files
+        .iter()
+        .filter_map(|f| Path::new(&f.path).components().next())
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    sandboxes.dedup();
+    let manifest = StoreManifest {
+        created_at: Utc::now(),
+        sandboxes,
+        files,
+    };
+
+    // Build next to the destination and rename, so a failed backup leaves no partial archive
+    let temp = dest.with_extension("tmp");
+    if let Err(e) = build_archive(base, &temp, &manifest).and_then(|()| Ok(fs::rename(&temp, dest)?)) {
+        let _ = fs::remove_file(&temp);
+        return Err(format!("writing {}: {}", dest.display(), e));
+    }
+    Ok(manifest)
+}
+
+fn build_archive(base: &Path, path: &Path, manifest: &StoreManifest) -> Result<(), Box<dyn std::error::Error>> {
+    let mut builder = tar::Builder::new(File::create(path)?);
+    let json = serde_json::to_vec_pretty(manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at.timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_FILE, json.as_slice())?;
+    for file in &manifest.files {
+        builder.append_path_with_name(base.join(&file.path), &file.path)?;
+    }
+    builder.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+fn read_archive(src: &Path, base: &Path) -> Result<StoreManifest, String> {
+    let staging = base.join(format!("{}{}", STAGING_PREFIX, Utc::now().format("%Y%m%dT%H%M%S%f")));
+    let result = unpack_and_swap(src, base, &staging);
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn unpack_and_swap(src: &Path, base: &Path, staging: &Path) -> Result<StoreManifest, String> {
+    fs::create_dir_all(staging).map_err(|e| format!("creating {}: {}", staging.display(), e))?;
+    let archive = File::open(src).map_err(|e| format!("opening {}: {}", src.display(), e))?;
+    // `unpack` refuses entries that would land outside the staging directory
+    tar::Archive::new(archive)
+        .unpack(staging)
+        .map_err(|e| format!("unpacking {}: {}", src.display(), e))?;
+
+    let manifest_path = staging.join(MANIFEST_FILE);
+    let json = fs::read_to_string(&manifest_path).map_err(|e| format!("{} has no manifest: {}", src.display(), e))?;
+    let manifest: StoreManifest = serde_json::from_str(&json).map_err(|e| format!("invalid manifest in {}: {}", src.display(), e))?;
+    for file in &manifest.files {
+        let bytes = fs::metadata(staging.join(&file.path)).map(|m| m.len()).ok();
+        if bytes != Some(file.bytes) {
+            return Err(format!("archive {} is incomplete: {} does not match the manifest", src.display(), file.path));
+        }
+    }
+    fs::remove_file(&manifest_path).map_err(|e| e.to_string())?;
+
+    // Everything checked out; replace what the archive covers, leave other sandboxes alone
+    for entry in fs::read_dir(staging).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let target = base.join(entry.file_name());
+        if target.is_dir() {
+            fs::remove_dir_all(&target).map_err(|e| format!("replacing {}: {}", target.display(), e))?;
+        } else if target.exists() {
+            fs::remove_file(&target).map_err(|e| format!("replacing {}: {}", target.display(), e))?;
+        }
+        fs::rename(entry.path(), &target).map_err(|e| format!("restoring {}: {}", target.display(), e))?;
+    }
+    Ok(manifest)
+}
+
+impl PersistenceManager {
+    /// Write every snapshot, lifecycle record and template under the base dir to a
+    /// tar archive at `dest`, with a manifest as its first entry. Storage calls wait
+    /// until the archive is written, so it is a consistent point-in-time copy.
+    /// Sandboxes stored under a custom base dir are not included.
+    pub async fn backup_store(&self, dest: &Path) -> Result<StoreManifest, Box<dyn std::error::Error>> {
+        let _store = self.lock_store().await;
+        let (base, dest) = (self.get_base_dir().to_path_buf(), dest.to_path_buf());
+        let manifest = blocking::run("store_backup", move || write_archive(&base, &dest)).await?;
+        info!(
+            "Backed up {} sandboxes ({} files, {} bytes)",
+            manifest.sandboxes.len(),
+            manifest.files.len(),
+            manifest.total_bytes()
+        );
+        Ok(manifest)
+    }
+
+    /// Restore an archive written by `backup_store` into the base dir. The archive is
+    /// unpacked and checked against its manifest before anything live is touched;
+    /// sandboxes it contains replace their current state, others are kept.
+    pub async fn restore_store(&self, src: &Path) -> Result<StoreManifest, Box<dyn std::error::Error>> {
+        self.check_writable("store restore")?;
+        let _store = self.lock_store().await;
+        let (base, src) = (self.get_base_dir().to_path_buf(), src.to_path_buf());
+        let manifest = blocking::run("store_restore", move || {
+            fs::create_dir_all(&base).map_err(|e| format!("creating {}: {}", base.display(), e))?;
+            read_archive(&src, &base)
+        })
+        .await?;
+        info!("Restored {} sandboxes from backup taken at {}", manifest.sandboxes.len(), manifest.created_at);
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecycleState;
+    use crate::state_snapshot::StateSnapshot;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_backup_and_restore_round_trip() {
+        let source_dir = TempDir::new().unwrap();
+        let source = PersistenceManager::with_base_dir(source_dir.path().to_path_buf());
+        for id in ["a", "b"] {
+            source.save_snapshot(&StateSnapshot::new(id.to_string())).await.unwrap();
+        }
+        source.save_lifecycle("a", LifecycleState::Paused).await.unwrap();
+
+        let archive_dir = TempDir::new().unwrap();
+        let archive = archive_dir.path().join("store.tar");
+        let manifest = source.backup_store(&archive).await.unwrap();
+        assert_eq!(manifest.sandboxes, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(manifest.files.len(), 3);
+
+        let target_dir = TempDir::new().unwrap();
+        let target = PersistenceManager::with_base_dir(target_dir.path().to_path_buf());
+        target.save_snapshot(&StateSnapshot::new("local".to_string())).await.unwrap();
+        target.restore_store(&archive).await.unwrap();
+
+        assert!(target.load_snapshot("a").await.unwrap().is_some());
+        assert_eq!(target.load_lifecycle("a").await.unwrap().unwrap().state, LifecycleState::Paused);
+        assert!(target.snapshot_exists("b"));
+        assert!(target.snapshot_exists("local"));
+        assert_eq!(target.list_sandbox_dirs().await.unwrap().len(), 3);
+    }
+}
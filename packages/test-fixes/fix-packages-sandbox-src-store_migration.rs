@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::blocking;
+use crate::object_store::{ObjectStore, S3Config};
+use crate::persistence::PersistenceManager;
+use crate::store_backup::{collect_files, ManifestEntry};
+
+/// Which of the two stores of a bucket migration is the new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationDirection {
+    /// From the local store to the bucket
+    ToRemote,
+    /// From the bucket to the local store
+    FromRemote,
+}
+
+/// A migration between the local store and a bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteMigrationConfig {
+    pub s3: S3Config,
+    pub direction: MigrationDirection,
+}
+
+/// The store on the other side of a migration
+pub enum StoreMigration {
+    /// Another store directory, which is the old store
+    Local(Box<PersistenceManager>),
+    Remote { store: Arc<dyn ObjectStore>, direction: MigrationDirection },
+}
+
+/// Outcome of backfilling the new store from the old one
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MigrationReport {
+    /// Files found in the old store
+    pub files: usize,
+    /// Files copied into the new store and verified
+    pub copied: usize,
+    /// Files already present in the new store with the same checksum
+    pub unchanged: usize,
+    /// Files whose content differs between the stores; the new store's copy is kept
+    pub diverged: Vec<String>,
+    /// Files that could not be copied or did not verify after copying
+    pub failed: Vec<String>,
+}
+
+impl MigrationReport {
+    /// Every file in the old store is now in the new one
+    pub fn ready_for_cutover(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+fn checksum(path: &Path) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| format!("reading {}: {}", path.display(), e))?;
+    Ok(digest(&bytes))
+}
+
+fn digest(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn local_files(base: &Path) -> Result<Vec<ManifestEntry>, String> {
+    let mut files = Vec::new();
+    if base.exists() {
+        collect_files(base, base, &mut files)?;
+    }
+    Ok(files)
+}
+
+fn backfill(old_base: &Path, new_base: &Path) -> Result<MigrationReport, String> {
+    let files = local_files(old_base)?;
+    let mut report = MigrationReport {
+        files: files.len(),
+        ..MigrationReport::default()
+    };
+
+    for file in files {
+        let (source, target) = (old_base.join(&file.path), new_base.join(&file.path));
+        let expected = match checksum(&source) {
+            Ok(sum) => sum,
+            Err(e) => {
+                warn!("Skipping {} in migration: {}", file.path, e);
+                report.failed.push(file.path);
+                continue;
+            }
+        };
+        if target.exists() {
+            match checksum(&target) {
+                Ok(sum) if sum == expected => report.unchanged += 1,
+                Ok(_) => report.diverged.push(file.path),
+                Err(e) => {
+                    warn!("Could not verify {} in the new store: {}", file.path, e);
+                    report.failed.push(file.path);
+                }
+            }
+            continue;
+        }
+
+        let temp = target.with_extension("tmp");
+        let copied = target
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::copy(&source, &temp))
+            .and_then(|_| fs::rename(&temp, &target));
+        match copied.map_err(|e| e.to_string()).and_then(|()| checksum(&target)) {
+            Ok(sum) if sum == expected => report.copied += 1,
+            Ok(_) => {
+                warn!("Checksum mismatch after copying {}", file.path);
+                let _ = fs::remove_file(&target);
+                report.failed.push(file.path);
+            }
+            Err(e) => {
+                warn!("Failed to copy {} to the new store: {}", file.path, e);
+                let _ = fs::remove_file(&temp);
+                report.failed.push(file.path);
+            }
+        }
+    }
+    Ok(report)
+}
+
+enum Backfilled {
+    Copied,
+    Unchanged,
+    Diverged,
+}
+
+/// Store paths use `/` in a bucket
+fn object_key(path: &str) -> String {
+    Path::new(path).components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// Backfill the bucket from the local store
+async fn backfill_to_remote(local_base: PathBuf, store: &dyn ObjectStore) -> Result<MigrationReport, Box<dyn std::error::Error>> {
+    let files = {
+        let base = local_base.clone();
+        blocking::run("store_migration", move || local_files(&base)).await?
+    };
+    let mut report = MigrationReport {
+        files: files.len(),
+        ..MigrationReport::default()
+    };
+
+    for file in files {
+        let key = object_key(&file.path);
+        let bytes = match tokio::fs::read(local_base.join(&file.path)).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("Skipping {} in migration: {}", file.path, e);
+                report.failed.push(file.path);
+                continue;
+            }
+        };
+        let expected = digest(&bytes);
+        match store.get(&key).await {
+            Ok(Some(existing)) if digest(&existing) == expected => {
+                report.unchanged += 1;
+                continue;
+            }
+            Ok(Some(_)) => {
+                report.diverged.push(file.path);
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Could not verify {} in {}: {}", file.path, store.describe(), e);
+                report.failed.push(file.path);
+                continue;
+            }
+        }
+
+        let copied = match store.put(&key, bytes).await {
+            Ok(()) => store.get(&key).await,
+            Err(e) => Err(e),
+        };
+        match copied {
+            Ok(Some(copy)) if digest(&copy) == expected => report.copied += 1,
+            Ok(_) => {
+                warn!("Checksum mismatch after copying {} to {}", file.path, store.describe());
+                let _ = store.delete(&key).await;
+                report.failed.push(file.path);
+            }
+            Err(e) => {
+                warn!("Failed to copy {} to {}: {}", file.path, store.describe(), e);
+                report.failed.push(file.path);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Backfill the local store from the bucket
+async fn backfill_from_remote(store: &dyn ObjectStore, local_base: PathBuf) -> Result<MigrationReport, Box<dyn std::error::Error>> {
+    let keys = store.list().await?;
+    let mut report = MigrationReport {
+        files: keys.len(),
+        ..MigrationReport::default()
+    };
+
+    for key in keys {
+        // Keys come from the bucket, so they are not trusted to stay under the base dir
+        let target = match Path::new(&key).components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+            true => local_base.join(&key),
+            false => {
+                warn!("Skipping {} in {}: not a store path", key, store.describe());
+                report.failed.push(key);
+                continue;
+            }
+        };
+        let bytes = match store.get(&key).await {
+            Ok(Some(bytes)) => bytes,
+            // Removed since it was listed
+            Ok(None) => {
+                report.files -= 1;
+                continue;
+            }
+            Err(e) => {
+                warn!("Skipping {} in migration: {}", key, e);
+                report.failed.push(key);
+                continue;
+            }
+        };
+        let expected = digest(&bytes);
+        let copied = blocking::run("store_migration", move || -> Result<Backfilled, String> {
+            if target.exists() {
+                return checksum(&target).map(|sum| if sum == expected { Backfilled::Unchanged } else { Backfilled::Diverged });
+            }
+            let temp = target.with_extension("tmp");
+            let written = target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&temp, &bytes))
+                .and_then(|()| fs::rename(&temp, &target));
+            if let Err(e) = written {
+                let _ = fs::remove_file(&temp);
+                return Err(e.to_string());
+            }
+            match checksum(&target)? == expected {
+                true => Ok(Backfilled::Copied),
+                false => {
+                    let _ = fs::remove_file(&target);
+                    Err("checksum mismatch after copying".to_string())
+                }
+            }
+        })
+        .await;
+        match copied {
+            Ok(Backfilled::Copied) => report.copied += 1,
+            Ok(Backfilled::Diverged) => report.diverged.push(key),
+            Ok(Backfilled::Unchanged) => report.unchanged += 1,
+            Err(e) => {
+                warn!("Failed to copy {} from {}: {}", key, store.describe(), e);
+                report.failed.push(key);
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl PersistenceManager {
+    /// Copy everything in the old store of the migration that the new one lacks,
+    /// verifying each copy by SHA-256. Files present in both stores with different
+    /// content are left as they are in the new store and listed as diverged. Storage
+    /// calls wait until the backfill is done. Fails unless the manager is in migration mode.
+    pub async fn migrate_store(&self) -> Result<MigrationReport, Box<dyn std::error::Error>> {
+        let Some(migration) = self.migration() else {
+            return Err("not in migration mode: no migration source configured".into());
+        };
+        self.check_writable("store migration")?;
+        let _store = self.lock_store().await;
+        let base = self.get_base_dir().to_path_buf();
+        let report = match migration {
+            StoreMigration::Local(source) => {
+                let old_base = source.get_base_dir().to_path_buf();
+                blocking::run("store_migration", move || backfill(&old_base, &base)).await?
+            }
+            StoreMigration::Remote { store, direction: MigrationDirection::ToRemote } => backfill_to_remote(base, store.as_ref()).await?,
+            StoreMigration::Remote { store, direction: MigrationDirection::FromRemote } => backfill_from_remote(store.as_ref(), base).await?,
+        };
+        info!(
+            "Store migration checked {} files: {} copied, {} unchanged, {} diverged, {} failed",
+            report.files,
+            report.copied,
+            report.unchanged,
+            report.diverged.len(),
+            report.failed.len()
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecycleState;
+    use crate::state_snapshot::StateSnapshot;
+    use crate::test_support::MemoryStore;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_dual_write_and_backfill() {
+        let (old_dir, new_dir) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let old = PersistenceManager::with_base_dir(old_dir.path().to_path_buf());
+        old.save_snapshot(&StateSnapshot::new("legacy".to_string())).await.unwrap();
+
+        let manager = PersistenceManager::with_base_dir(new_dir.path().to_path_buf())
+            .with_migration_source(PersistenceManager::with_base_dir(old_dir.path().to_path_buf()));
+
+        // Reads fall back to the old store, writes land in both
+        assert!(manager.load_snapshot("legacy").await.unwrap().is_some());
+        assert!(!manager.snapshot_exists("legacy"));
+        manager.save_lifecycle("fresh", LifecycleState::Paused).await.unwrap();
+        assert!(old.load_lifecycle("fresh").await.unwrap().is_some());
+
+        let report = manager.migrate_store().await.unwrap();
+        assert!(report.ready_for_cutover());
+        assert_eq!((report.files, report.copied, report.unchanged), (2, 1, 1));
+        assert!(manager.snapshot_exists("legacy"));
+        assert!(PersistenceManager::with_base_dir(new_dir.path().to_path_buf())
+            .load_snapshot("legacy")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_migration_to_a_bucket() {
+        let dir = TempDir::new().unwrap();
+        PersistenceManager::with_base_dir(dir.path().to_path_buf())
+            .save_snapshot(&StateSnapshot::new("legacy".to_string()))
+            .await
+            .unwrap();
+        let bucket = Arc::new(MemoryStore::default());
+        let manager = PersistenceManager::with_base_dir(dir.path().to_path_buf()).with_remote_migration(bucket.clone(), MigrationDirection::ToRemote);
+
+        // Writes land in both stores, under the same paths
+        manager.save_lifecycle("fresh", LifecycleState::Paused).await.unwrap();
+        assert!(bucket.objects.lock().unwrap().contains_key("fresh/lifecycle.json"));
+        assert!(manager.load_lifecycle("fresh").await.unwrap().is_some());
+
+        // The bucket is read first; the local store fills in what it lacks
+        assert!(manager.load_snapshot("legacy").await.unwrap().is_some());
+        let mut newer = StateSnapshot::new("legacy".to_string());
+        newer.timestamp += chrono::Duration::seconds(1);
+        let json = newer.to_json().unwrap().into_bytes();
+        bucket.objects.lock().unwrap().insert("legacy/snapshots/snapshot.json".to_string(), json);
+        assert_eq!(manager.load_snapshot("legacy").await.unwrap().unwrap().timestamp, newer.timestamp);
+        bucket.objects.lock().unwrap().remove("legacy/snapshots/snapshot.json");
+
+        let report = manager.migrate_store().await.unwrap();
+        assert!(report.ready_for_cutover());
+        // Snapshot and its checksum copied; the lifecycle record was already there
+        assert_eq!((report.files, report.copied, report.unchanged), (3, 2, 1));
+        assert!(bucket.objects.lock().unwrap().contains_key("legacy/snapshots/snapshot.sha256"));
+
+        manager.remove_snapshot("legacy").await.unwrap();
+        assert!(!bucket.objects.lock().unwrap().keys().any(|key| key.starts_with("legacy/")));
+    }
+
+    #[tokio::test]
+    async fn test_migration_from_a_bucket() {
+        let bucket = Arc::new(MemoryStore::default());
+        let old_dir = TempDir::new().unwrap();
+        let old = PersistenceManager::with_base_dir(old_dir.path().to_path_buf()).with_remote_migration(bucket.clone(), MigrationDirection::ToRemote);
+        old.save_snapshot(&StateSnapshot::new("legacy".to_string())).await.unwrap();
+        old.save_lifecycle("legacy", LifecycleState::Paused).await.unwrap();
+
+        let new_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(new_dir.path().to_path_buf()).with_remote_migration(bucket.clone(), MigrationDirection::FromRemote);
+        assert!(manager.load_snapshot("legacy").await.unwrap().is_some());
+        assert_eq!(manager.load_lifecycle("legacy").await.unwrap().unwrap().state, LifecycleState::Paused);
+
+        // A key that would land outside the store is refused, not written
+        bucket.objects.lock().unwrap().insert("../escape.json".to_string(), b"{}".to_vec());
+        let report = manager.migrate_store().await.unwrap();
+        assert_eq!((report.files, report.copied), (4, 3));
+        assert_eq!(report.failed, ["../escape.json"]);
+        assert!(!report.ready_for_cutover());
+        assert!(!new_dir.path().parent().unwrap().join("escape.json").exists());
+        assert!(manager.snapshot_exists("legacy"));
+    }
+}
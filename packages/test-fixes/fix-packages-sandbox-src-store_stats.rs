@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::AutoPauseManager;
+use crate::blocking;
+use crate::metrics;
+use crate::persistence::{PersistenceManager, SandboxLayout, BACKEND};
+use crate::store_backup::collect_files;
+
+/// Bucket for sandboxes the registry has no tenant for
+pub const UNASSIGNED_TENANT: &str = "unassigned";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TenantStoreStats {
+    pub sandboxes: usize,
+    pub snapshots: usize,
+    pub bytes: u64,
+}
+
+/// Whether the store's backing storage is usable
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackendHealth {
+    pub backend: String,
+    /// A probe file could be written and removed
+    pub writable: bool,
+    /// Space left on the filesystem holding the store
+    pub free_bytes: Option<u64>,
+    /// Dual-writing to an old store during a migration
+    pub migrating: bool,
+    pub error: Option<String>,
+}
+
+/// Size and age of everything in the snapshot store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StoreStats {
+    pub collected_at: Option<DateTime<Utc>>,
+    /// Sandbox directories, with or without a snapshot
+    pub sandboxes: usize,
+    pub snapshots: usize,
+    pub lifecycle_records: usize,
+    /// Every file in the store, templates included
+    pub total_bytes: u64,
+    /// Write times of the oldest and newest snapshot
+    pub oldest_snapshot: Option<DateTime<Utc>>,
+    pub newest_snapshot: Option<DateTime<Utc>>,
+    pub tenants: BTreeMap<String, TenantStoreStats>,
+    pub backend: BackendHealth,
+}
+
+struct SandboxUsage {
+    sandbox_id: String,
+    bytes: u64,
+    snapshot_at: Option<DateTime<Utc>>,
+    has_lifecycle: bool,
+}
+
+/// Per-sandbox usage plus the bytes of everything else (templates)
+fn scan(base: &Path) -> Result<(Vec<SandboxUsage>, u64), String> {
+    let mut files = Vec::new();
+    if base.exists() {
+        collect_files(base, base, &mut files)?;
+    }
+    let mut per_sandbox: BTreeMap<String, u64> = BTreeMap::new();
+    let mut other_bytes = 0;
+    for file in files {
+        let top = Path::new(&file.path).components().next().map(|c| c.as_os_str().to_string_lossy().into_owned());
+        match top {
+            Some(name) if !name.starts_with('.') && file.path.contains(std::path::MAIN_SEPARATOR) => {
+                *per_sandbox.entry(name).or_default() += file.bytes;
+            }
+            _ => other_bytes += file.bytes,
+        }
+    }
+
+    let usage = per_sandbox
+        .into_iter()
+        .map(|(sandbox_id, bytes)| {
+            let layout = SandboxLayout::new(base, &sandbox_id);
+            let snapshot_at = fs::metadata(layout.snapshot_file()).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from);
+            SandboxUsage {
+                has_lifecycle: layout.lifecycle_file().exists(),
+                sandbox_id,
+                bytes,
+                snapshot_at,
+            }
+        })
+        .collect();
+    Ok((usage, other_bytes))
+}
+
+fn probe_backend(base: &Path) -> BackendHealth {
+    let mut health = BackendHealth {
+        backend: BACKEND.to_string(),
+        ..BackendHealth::default()
+    };
+    // Ends in .tmp so backups never pick it up
+    let probe = base.join(".health-probe.tmp");
+    match fs::create_dir_all(base).and_then(|()| fs::write(&probe, b"ok")).and_then(|()| fs::remove_file(&probe)) {
+        Ok(()) => health.writable = true,
+        Err(e) => health.error = Some(format!("{}: {}", base.display(), e)),
+    }
+    // statvfs field widths differ between platforms
+    #[cfg(unix)]
+    #[allow(clippy::unnecessary_cast)]
+    {
+        health.free_bytes = nix::sys::statvfs::statvfs(base)
+            .ok()
+            .map(|stat| stat.blocks_available() as u64 * stat.fragment_size() as u64);
+    }
+    health
+}
+
+impl PersistenceManager {
+    /// Counts, sizes and snapshot ages for the store under the base dir, grouped by
+    /// `tenant_of`, plus a writability probe of the backing storage. Sandboxes under
+    /// a custom base dir are not counted.
+    pub async fn store_stats(&self, tenant_of: impl Fn(&str) -> Option<String>) -> Result<StoreStats, Box<dyn std::error::Error>> {
+        let base = self.get_base_dir().to_path_buf();
+        let (usage, other_bytes, mut backend) = blocking::run("store_stats", move || {
+            scan(&base).map(|(usage, other_bytes)| (usage, other_bytes, probe_backend(&base)))
+        })
+        .await?;
+        backend.migrating = self.migration().is_some();
+
+        let mut stats = StoreStats {
+            collected_at: Some(Utc::now()),
+            sandboxes: usage.len(),
+            total_bytes: other_bytes,
+            backend,
+            ..StoreStats::default()
+        };
+        for sandbox in usage {
+            stats.total_bytes += sandbox.bytes;
+            stats.lifecycle_records += usize::from(sandbox.has_lifecycle);
+            let tenant = tenant_of(&sandbox.sandbox_id).unwrap_or_else(|| UNASSIGNED_TENANT.to_string());
+            let tenant = stats.tenants.entry(tenant).or_default();
+            tenant.sandboxes += 1;
+            tenant.bytes += sandbox.bytes;
+            if let Some(at) = sandbox.snapshot_at {
+                stats.snapshots += 1;
+                tenant.snapshots += 1;
+                stats.oldest_snapshot = Some(stats.oldest_snapshot.map_or(at, |oldest| oldest.min(at)));
+                stats.newest_snapshot = Some(stats.newest_snapshot.map_or(at, |newest| newest.max(at)));
+            }
+        }
+        Ok(stats)
+    }
+}
+
+impl AutoPauseManager {
+    /// Store statistics broken down by the tenants in the sandbox registry; also
+    /// published as the store size gauges
+    pub async fn store_stats(&self) -> Result<StoreStats, Box<dyn std::error::Error>> {
+        let registry = self.registry().clone();
+        let stats = self.persistence_manager().store_stats(|sandbox_id| registry.tenant(sandbox_id)).await?;
+        metrics::record_store_stats(BACKEND, stats.snapshots, stats.total_bytes);
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecycleState;
+    use crate::state_snapshot::StateSnapshot;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_store_stats_by_tenant() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        for id in ["a1", "a2", "b1"] {
+            manager.save_snapshot(&StateSnapshot::new(id.to_string())).await.unwrap();
+        }
+        manager.save_lifecycle("idle", LifecycleState::Running).await.unwrap();
+
+        let stats = manager
+            .store_stats(|id| id.starts_with('a').then(|| "acme".to_string()))
+            .await
+            .unwrap();
+        assert_eq!((stats.sandboxes, stats.snapshots, stats.lifecycle_records), (4, 3, 1));
+        assert!(stats.oldest_snapshot <= stats.newest_snapshot);
+        assert_eq!(stats.tenants["acme"].snapshots, 2);
+        assert_eq!(stats.tenants[UNASSIGNED_TENANT].sandboxes, 2);
+        assert!(stats.backend.writable);
+        assert!(stats.total_bytes > 0);
+    }
+}
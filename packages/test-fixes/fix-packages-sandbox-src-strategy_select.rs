@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+
+use crate::auto_pause::PauseStrategy;
+use crate::blocking;
+use crate::cost_estimate::StrategyCost;
+use crate::persistence::PersistenceManager;
+use crate::process::ProcessInfo;
+use crate::registry::PriorityClass;
+
+const DECISION_FILE: &str = "strategy.json";
+
+/// Thresholds for the `auto` strategy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoStrategyConfig {
+    /// Process names that mark a database, whose state is persisted rather than risked
+    pub database_names: Vec<String>,
+    /// Largest total resident memory, in MiB, still kept running under throttle (default: 512)
+    pub throttle_max_memory_mb: u64,
+    /// Most processes still kept running under throttle (default: 8)
+    pub throttle_max_processes: usize,
+    /// Pause and resume durations, by past estimates, above which throttle and persist
+    /// give way to each other (default: none)
+    pub pause_budget_ms: Option<u64>,
+    pub resume_budget_ms: Option<u64>,
+    /// Priority class of each tenant's sandboxes; a sandbox is treated as the higher
+    /// of its own class and its tenant's
+    pub tenant_tiers: HashMap<String, PriorityClass>,
+}
+
+impl Default for AutoStrategyConfig {
+    fn default() -> Self {
+        Self {
+            database_names: ["postgres", "mysqld", "mariadbd", "mongod", "redis-server", "clickhouse-server", "etcd"].map(String::from).to_vec(),
+            throttle_max_memory_mb: 512,
+            throttle_max_processes: 8,
+            pause_budget_ms: None,
+            resume_budget_ms: None,
+            tenant_tiers: HashMap::new(),
+        }
+    }
+}
+
+impl AutoStrategyConfig {
+    /// Priority the heuristics use for a sandbox of class `priority` owned by `tenant`
+    pub fn priority(&self, priority: PriorityClass, tenant: Option<&str>) -> PriorityClass {
+        let tier = tenant.and_then(|tenant| self.tenant_tiers.get(tenant)).copied().unwrap_or_default();
+        priority.max(tier)
+    }
+}
+
+/// What the heuristics look at, gathered when the pause is prepared
+#[derive(Debug, Clone, Default)]
+pub struct Workload {
+    pub processes: usize,
+    pub memory_bytes: u64,
+    /// Names of the database processes found
+    pub databases: Vec<String>,
+    /// PIDs with a GPU device open
+    pub gpu_pids: Vec<i32>,
+    pub priority: PriorityClass,
+    /// Estimated cost of each strategy from past pauses
+    pub costs: Vec<StrategyCost>,
+}
+
+impl Workload {
+    /// Inspect the live processes of a sandbox of the given priority class
+    pub fn inspect(config: &AutoStrategyConfig, processes: &[ProcessInfo], priority: PriorityClass) -> Self {
+        let databases = processes
+            .iter()
+            .filter(|p| config.database_names.iter().any(|name| p.name == *name))
+            .map(|p| p.name.clone())
+            .collect();
+        Self {
+            processes: processes.len(),
+            memory_bytes: processes.iter().filter_map(|p| rss_bytes(p.pid)).sum(),
+            databases,
+            gpu_pids: processes.iter().filter(|p| uses_gpu(p.pid)).map(|p| p.pid).collect(),
+            priority,
+            costs: Vec::new(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn rss_bytes(pid: i32) -> Option<u64> {
+    crate::procfs::rss_bytes(pid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss_bytes(_pid: i32) -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn uses_gpu(pid: i32) -> bool {
+    crate::procfs::uses_gpu(pid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn uses_gpu(_pid: i32) -> bool {
+    false
+}
+
+/// The strategy picked for one pause and why, recorded in the pause report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StrategyDecision {
+    pub strategy: PauseStrategy,
+    pub reason: String,
+    pub decided_at: DateTime<Utc>,
+}
+
+impl StrategyDecision {
+    /// A strategy set in the configuration rather than picked
+    pub fn configured(strategy: PauseStrategy) -> Self {
+        Self::new(strategy, "configured")
+    }
+
+    fn new(strategy: PauseStrategy, reason: impl Into<String>) -> Self {
+        Self {
+            strategy,
+            reason: reason.into(),
+            decided_at: Utc::now(),
+        }
+    }
+}
+
+/// Pick a strategy for a workload; the first rule that applies wins:
+///
+/// 1. No live processes: kill, there is nothing to keep.
+/// 2. Databases or GPU users: persist, a restart would lose state or be slow to rebuild.
+/// 3. High and critical priority: persist, so the sandbox comes back as it was.
+/// 4. Low priority: kill, the cheapest pause.
+/// 5. Small enough to keep running: throttle, which resumes instantly.
+/// 6. Otherwise persist.
+///
+/// When past pauses put a persist or throttle choice over the pause or resume budget
+/// and the other one within it, the other one is picked; throttle only when the
+/// workload is within `throttle_max_memory_mb` and `throttle_max_processes`.
+pub fn select(config: &AutoStrategyConfig, workload: &Workload) -> StrategyDecision {
+    let preferred = preferred(config, workload);
+    if !matches!(preferred.strategy, PauseStrategy::Throttle | PauseStrategy::Persist) {
+        return preferred;
+    }
+    let Some(over) = over_budget(config, workload, preferred.strategy) else {
+        return preferred;
+    };
+    let alternative = if preferred.strategy == PauseStrategy::Throttle { PauseStrategy::Persist } else { PauseStrategy::Throttle };
+    if alternative == PauseStrategy::Throttle && !fits_throttle(config, workload) {
+        return preferred;
+    }
+    let known = workload.costs.iter().any(|cost| cost.strategy == alternative && cost.samples > 0);
+    if known && over_budget(config, workload, alternative).is_none() {
+        return StrategyDecision::new(alternative, format!("{}, but {}", preferred.reason, over));
+    }
+    preferred
+}
+
+/// Why past pauses put `strategy` over budget, if they do
+fn over_budget(config: &AutoStrategyConfig, workload: &Workload, strategy: PauseStrategy) -> Option<String> {
+    let cost = workload.costs.iter().find(|cost| cost.strategy == strategy)?;
+    let name = format!("{:?}", strategy).to_lowercase();
+    if let (Some(expected), Some(budget)) = (cost.pause_ms, config.pause_budget_ms) {
+        if expected > budget {
+            return Some(format!("{} pauses are expected to take {}ms, over the {}ms budget", name, expected, budget));
+        }
+    }
+    if let (Some(expected), Some(budget)) = (cost.resume_ms, config.resume_budget_ms) {
+        if expected > budget {
+            return Some(format!("{} resumes are expected to take {}ms, over the {}ms budget", name, expected, budget));
+        }
+    }
+    None
+}
+
+fn fits_throttle(config: &AutoStrategyConfig, workload: &Workload) -> bool {
+    workload.memory_bytes / (1024 * 1024) <= config.throttle_max_memory_mb && workload.processes <= config.throttle_max_processes
+}
+
+fn preferred(config: &AutoStrategyConfig, workload: &Workload) -> StrategyDecision {
+    let memory_mb = workload.memory_bytes / (1024 * 1024);
+    if workload.processes == 0 {
+        return StrategyDecision::new(PauseStrategy::Kill, "no live processes");
+    }
+    if !workload.databases.is_empty() {
+        return StrategyDecision::new(PauseStrategy::Persist, format!("runs a database ({})", workload.databases.join(", ")));
+    }
+    if !workload.gpu_pids.is_empty() {
+        return StrategyDecision::new(PauseStrategy::Persist, format!("processes {:?} use a GPU", workload.gpu_pids));
+    }
+    if workload.priority >= PriorityClass::High {
+        return StrategyDecision::new(PauseStrategy::Persist, format!("{:?} priority", workload.priority));
+    }
+    if workload.priority == PriorityClass::Low {
+        return StrategyDecision::new(PauseStrategy::Kill, "low priority");
+    }
+    if fits_throttle(config, workload) {
+        return StrategyDecision::new(PauseStrategy::Throttle, format!("{} processes using {} MiB, small enough to keep running", workload.processes, memory_mb));
+    }
+    StrategyDecision::new(PauseStrategy::Persist, format!("{} processes using {} MiB", workload.processes, memory_mb))
+}
+
+impl PersistenceManager {
+    /// Record the strategy a sandbox was paused with, so its resume matches
+    pub async fn save_strategy_decision(&self, sandbox_id: &str, decision: &StrategyDecision) -> Result<(), Box<dyn std::error::Error>> {
+        let root = self.layout(sandbox_id).root().to_path_buf();
+        let json = serde_json::to_string(decision)?;
+        self.bounded_write("strategy save", sandbox_id, async {
+            async_fs::create_dir_all(&root).await?;
+            let file_path = root.join(DECISION_FILE);
+            let temp_path = file_path.with_extension("tmp");
+            async_fs::write(&temp_path, json).await?;
+            async_fs::rename(&temp_path, &file_path).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn load_strategy_decision(&self, sandbox_id: &str) -> Result<Option<StrategyDecision>, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join(DECISION_FILE);
+        self.bounded("strategy load", sandbox_id, async {
+            if !blocking::path_exists(&file_path).await {
+                return Ok(None);
+            }
+            let json = async_fs::read_to_string(&file_path).await?;
+            Ok(Some(serde_json::from_str(&json)?))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cost_estimate::CostSource;
+
+    #[test]
+    fn test_tenant_tier_raises_priority() {
+        let config = AutoStrategyConfig {
+            tenant_tiers: HashMap::from([("acme".to_string(), PriorityClass::Critical), ("free".to_string(), PriorityClass::Low)]),
+            ..Default::default()
+        };
+        assert_eq!(config.priority(PriorityClass::Normal, Some("acme")), PriorityClass::Critical);
+        assert_eq!(config.priority(PriorityClass::High, Some("free")), PriorityClass::High);
+        assert_eq!(config.priority(PriorityClass::Low, Some("unknown")), PriorityClass::Normal);
+        assert_eq!(config.priority(PriorityClass::Low, None), PriorityClass::Normal);
+    }
+
+    #[test]
+    fn test_select_rules_in_order() {
+        let config = AutoStrategyConfig::default();
+        let small = Workload {
+            processes: 3,
+            memory_bytes: 100 * 1024 * 1024,
+            ..Default::default()
+        };
+        assert_eq!(select(&config, &Workload::default()).strategy, PauseStrategy::Kill);
+        assert_eq!(select(&config, &small).strategy, PauseStrategy::Throttle);
+
+        let large = Workload { memory_bytes: 4096 * 1024 * 1024, ..small.clone() };
+        assert_eq!(select(&config, &large).strategy, PauseStrategy::Persist);
+        let low = Workload { priority: PriorityClass::Low, ..large.clone() };
+        assert_eq!(select(&config, &low).strategy, PauseStrategy::Kill);
+
+        // A database outranks the priority class
+        let database = Workload { databases: vec!["postgres".to_string()], ..low };
+        let decision = select(&config, &database);
+        assert_eq!(decision.strategy, PauseStrategy::Persist);
+        assert_eq!(decision.reason, "runs a database (postgres)");
+    }
+
+    #[test]
+    fn test_budget_overrides_soft_choice() {
+        let config = AutoStrategyConfig { resume_budget_ms: Some(1000), ..Default::default() };
+        let cost = |strategy, resume_ms| StrategyCost {
+            strategy,
+            pause_ms: Some(100),
+            snapshot_bytes: None,
+            resume_ms: Some(resume_ms),
+            samples: 3,
+            source: CostSource::Sandbox,
+        };
+        let high = Workload {
+            processes: 4,
+            memory_bytes: 100 * 1024 * 1024,
+            priority: PriorityClass::High,
+            costs: vec![cost(PauseStrategy::Persist, 5000), cost(PauseStrategy::Throttle, 200)],
+            ..Default::default()
+        };
+        let decision = select(&config, &high);
+        assert_eq!(decision.strategy, PauseStrategy::Throttle);
+        assert!(decision.reason.ends_with("persist resumes are expected to take 5000ms, over the 1000ms budget"));
+
+        // Too large to keep running: the budget does not override the throttle limits
+        let large = Workload { processes: 20, memory_bytes: 4096 * 1024 * 1024, priority: PriorityClass::Normal, ..high.clone() };
+        assert_eq!(select(&config, &large).strategy, PauseStrategy::Persist);
+
+        // Without history for the alternative the preferred strategy stands
+        let unknown = Workload { costs: vec![cost(PauseStrategy::Persist, 5000)], ..high };
+        assert_eq!(select(&config, &unknown).strategy, PauseStrategy::Persist);
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::fs as async_fs;
+use tokio::process::Command;
+
+use crate::blocking;
+use crate::persistence::PersistenceManager;
+use crate::process;
+
+/// Where per-user runtime directories live; a user manager is running when `<dir>/<uid>/systemd` exists
+const USER_RUNTIME_DIR: &str = "/run/user";
+
+/// Label set on tracked processes that run under a systemd user service
+pub const UNIT_LABEL: &str = "systemd.unit";
+
+/// Capture of systemd user services across a pause
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SystemdUserConfig {
+    pub enabled: bool,
+}
+
+/// A user service and its state at pause time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnitState {
+    pub name: String,
+    pub active_state: String,
+    pub sub_state: String,
+}
+
+/// The services of one user's systemd instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserUnits {
+    pub user: String,
+    pub uid: u32,
+    pub units: Vec<UnitState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemdUnitsCapture {
+    pub captured_at: DateTime<Utc>,
+    pub users: Vec<UserUnits>,
+}
+
+/// A unit that could not be started again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitFailure {
+    pub user: String,
+    pub unit: String,
+    pub error: String,
+}
+
+/// What restoring user services did
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemdUnitsRestore {
+    /// `user/unit` of every unit that was started
+    pub started: Vec<String>,
+    pub failed: Vec<UnitFailure>,
+}
+
+/// Enumerate the services of every running systemd user instance
+pub async fn capture() -> Result<SystemdUnitsCapture, Box<dyn std::error::Error>> {
+    let mut users = Vec::new();
+    let runtime_dir = Path::new(USER_RUNTIME_DIR);
+    if blocking::path_exists(runtime_dir).await {
+        let mut entries = async_fs::read_dir(runtime_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(uid) = entry.file_name().to_string_lossy().parse::<u32>() else {
+                continue;
+            };
+            if !blocking::path_exists(&entry.path().join("systemd")).await {
+                continue;
+            }
+            // getpwuid may go through NSS
+            let Some(user) = blocking::run("user_name", move || user_name(uid)).await else {
+                continue;
+            };
+            match list_units(&user).await {
+                Ok(units) => users.push(UserUnits { user, uid, units }),
+                Err(e) => warn!("Failed to list systemd user units of {}: {}", user, e),
+            }
+        }
+    }
+    Ok(SystemdUnitsCapture {
+        captured_at: Utc::now(),
+        users,
+    })
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> Option<String> {
+    nix::unistd::User::from_uid(nix::unistd::Uid::from_raw(uid)).ok().flatten().map(|user| user.name)
+}
+
+#[cfg(not(unix))]
+fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+async fn systemctl(user: &str, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let machine = format!("{}@", user);
+    let output = process::helper_output(Command::new("systemctl").args(["--user", "--machine", &machine, "--no-pager"]).args(args)).await?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn list_units(user: &str) -> Result<Vec<UnitState>, Box<dyn std::error::Error>> {
+    let output = systemctl(user, &["list-units", "--type=service", "--all", "--plain", "--no-legend"]).await?;
+    Ok(output.lines().filter_map(parse_unit_line).collect())
+}
+
+/// Parse `name load active sub description...` from `systemctl list-units --plain --no-legend`
+fn parse_unit_line(line: &str) -> Option<UnitState> {
+    let mut fields = line.split_whitespace();
+    let name = fields.next()?.to_string();
+    let _load = fields.next()?;
+    Some(UnitState {
+        name,
+        active_state: fields.next()?.to_string(),
+        sub_state: fields.next()?.to_string(),
+    })
+}
+
+/// Start every unit that was active at pause and is not active now
+pub async fn restore(capture: &SystemdUnitsCapture) -> SystemdUnitsRestore {
+    let mut result = SystemdUnitsRestore::default();
+    for user in &capture.users {
+        let current = list_units(&user.user).await.unwrap_or_default();
+        for unit in user.units.iter().filter(|u| u.active_state == "active") {
+            let still_active = current.iter().any(|c| c.name == unit.name && c.active_state == "active");
+            if still_active {
+                continue;
+            }
+            match systemctl(&user.user, &["start", &unit.name]).await {
+                Ok(_) => result.started.push(format!("{}/{}", user.user, unit.name)),
+                Err(e) => result.failed.push(UnitFailure {
+                    user: user.user.clone(),
+                    unit: unit.name.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+    }
+    info!(
+        "Restored systemd user services: {} started, {} failed",
+        result.started.len(),
+        result.failed.len()
+    );
+    result
+}
+
+/// Name of the systemd user service a process runs under, from its cgroup
+/// (`.../user@1000.service/app.slice/foo.service`)
+pub fn user_unit_of(cgroup: &str) -> Option<String> {
+    let path = cgroup.lines().find_map(|line| line.strip_prefix("0::"))?;
+    let (_, below_manager) = path.split_once(".service/")?;
+    below_manager
+        .split('/')
+        .filter(|part| part.ends_with(".service"))
+        .last()
+        .map(String::from)
+}
+
+impl PersistenceManager {
+    pub async fn save_systemd_units(&self, sandbox_id: &str, capture: &SystemdUnitsCapture) -> Result<(), Box<dyn std::error::Error>> {
+        let root = self.layout(sandbox_id).root().to_path_buf();
+        let json = serde_json::to_string(capture)?;
+        self.bounded_write("systemd units save", sandbox_id, async {
+            async_fs::create_dir_all(&root).await?;
+            let file_path = root.join("systemd_units.json");
+            let temp_path = file_path.with_extension("tmp");
+            async_fs::write(&temp_path, json).await?;
+            async_fs::rename(&temp_path, &file_path).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn load_systemd_units(&self, sandbox_id: &str) -> Result<Option<SystemdUnitsCapture>, Box<dyn std::error::Error>> {
+        let file_path = self.layout(sandbox_id).root().join("systemd_units.json");
+        self.bounded("systemd units load", sandbox_id, async {
+            if !blocking::path_exists(&file_path).await {
+                return Ok(None);
+            }
+            let json = async_fs::read_to_string(&file_path).await?;
+            Ok(Some(serde_json::from_str(&json)?))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unit_line() {
+        let unit = parse_unit_line("jupyter.service loaded active running Jupyter Notebook").unwrap();
+        assert_eq!(unit.name, "jupyter.service");
+        assert_eq!(unit.active_state, "active");
+        assert_eq!(unit.sub_state, "running");
+        assert!(parse_unit_line("broken").is_none());
+    }
+
+    #[test]
+    fn test_user_unit_of_cgroup() {
+        let cgroup = "0::/user.slice/user-1000.slice/user@1000.service/app.slice/jupyter.service\n";
+        assert_eq!(user_unit_of(cgroup).as_deref(), Some("jupyter.service"));
+        assert_eq!(user_unit_of("0::/user.slice/user-1000.slice/session-3.scope\n"), None);
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use chrono::Utc;
+use log::warn;
+use serde::{Serialize, Deserialize};
+
+use crate::alarms::{Alarm, AlarmPhase, PhaseTimings};
+use crate::history::HistoryStore;
+use crate::stats::{TimelineEvent, TimelinePoint};
+
+/// When to flag a template whose operations got slower
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TemplateAnomalyConfig {
+    pub enabled: bool,
+    /// How many times the baseline median the recent median must reach (default: 10)
+    pub factor: f64,
+    /// Latest operations whose median is compared against the baseline (default: 3)
+    pub recent: usize,
+    /// Earlier operations needed before a baseline is trusted (default: 5)
+    pub min_baseline: usize,
+    /// Operations kept per template and phase (default: 100)
+    pub max_samples: usize,
+}
+
+impl Default for TemplateAnomalyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            factor: 10.0,
+            recent: 3,
+            min_baseline: 5,
+            max_samples: 100,
+        }
+    }
+}
+
+/// Operation metrics of every sandbox restored from one template
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TemplateMetrics {
+    pub template: String,
+    pub pauses: usize,
+    pub resumes: usize,
+    pub pause_median_ms: Option<u64>,
+    pub resume_median_ms: Option<u64>,
+    /// Phases whose recent operations are currently flagged
+    pub anomalous: Vec<AlarmPhase>,
+}
+
+#[derive(Default)]
+struct Series {
+    durations: VecDeque<u64>,
+    /// Set while the recent median is over the limit, so a regression alarms once
+    flagged: bool,
+}
+
+/// Durations by template and phase, seeded from the history store
+pub struct TemplateMonitor {
+    config: TemplateAnomalyConfig,
+    series: Mutex<HashMap<(String, AlarmPhase), Series>>,
+}
+
+impl TemplateMonitor {
+    pub fn new(config: TemplateAnomalyConfig) -> Self {
+        Self {
+            config,
+            series: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Load the recorded pauses and resumes of every template, oldest first
+    pub fn seed(&self, history: &HistoryStore) {
+        for template in history.templates() {
+            let mut points: Vec<TimelinePoint> = history.template_history(&template).into_iter().flat_map(|(_, timeline)| timeline).collect();
+            points.sort_by_key(|point| point.at);
+            for point in points {
+                if let Some((phase, duration_ms)) = operation(&point.event) {
+                    self.push(&template, phase, duration_ms);
+                }
+            }
+        }
+    }
+
+    /// Add one operation of a sandbox restored from `template`. Returns an alarm when this
+    /// operation tips the recent median over `factor` times the baseline median.
+    pub fn observe(&self, template: &str, sandbox_id: &str, event: &TimelineEvent) -> Option<Alarm> {
+        let (phase, duration_ms) = operation(event)?;
+        let (recent, baseline) = self.push(template, phase, duration_ms)?;
+        let threshold_ms = (baseline as f64 * self.config.factor) as u64;
+        warn!(
+            "{:?} of template {} regressed: recent median {}ms against a baseline of {}ms",
+            phase, template, recent, baseline
+        );
+        Some(Alarm {
+            sandbox_id: sandbox_id.to_string(),
+            phase,
+            elapsed_ms: recent,
+            threshold_ms,
+            phase_timings: PhaseTimings::from([("baseline_median".to_string(), baseline), ("latest".to_string(), duration_ms)]),
+            timestamp: Utc::now(),
+            stuck: false,
+            template: Some(template.to_string()),
+        })
+    }
+
+    /// Record a duration; the recent and baseline medians when the series just became anomalous
+    fn push(&self, template: &str, phase: AlarmPhase, duration_ms: u64) -> Option<(u64, u64)> {
+        let mut series = self.series.lock().unwrap();
+        let entry = series.entry((template.to_string(), phase)).or_default();
+        if entry.durations.len() >= self.config.max_samples.max(self.config.recent + self.config.min_baseline) {
+            entry.durations.pop_front();
+        }
+        entry.durations.push_back(duration_ms);
+        let split = entry.durations.len().checked_sub(self.config.recent.max(1))?;
+        if split < self.config.min_baseline {
+            return None;
+        }
+        let (baseline, recent) = entry.durations.make_contiguous().split_at(split);
+        let (baseline, recent) = (median(baseline), median(recent));
+        let anomalous = recent as f64 >= baseline.max(1) as f64 * self.config.factor;
+        let newly = anomalous && !entry.flagged;
+        entry.flagged = anomalous;
+        newly.then_some((recent, baseline))
+    }
+
+    /// Metrics of every template seen, by name
+    pub fn metrics(&self) -> Vec<TemplateMetrics> {
+        let series = self.series.lock().unwrap();
+        let mut metrics: HashMap<&str, TemplateMetrics> = HashMap::new();
+        for ((template, phase), entry) in series.iter() {
+            let m = metrics.entry(template.as_str()).or_insert_with(|| TemplateMetrics { template: template.clone(), ..Default::default() });
+            let durations: Vec<u64> = entry.durations.iter().copied().collect();
+            match phase {
+                AlarmPhase::Pause => {
+                    m.pauses = durations.len();
+                    m.pause_median_ms = (!durations.is_empty()).then(|| median(&durations));
+                }
+                AlarmPhase::Resume => {
+                    m.resumes = durations.len();
+                    m.resume_median_ms = (!durations.is_empty()).then(|| median(&durations));
+                }
+                AlarmPhase::SnapshotSave => {}
+            }
+            if entry.flagged {
+                m.anomalous.push(*phase);
+            }
+        }
+        let mut metrics: Vec<TemplateMetrics> = metrics.into_values().collect();
+        metrics.sort_by(|a, b| a.template.cmp(&b.template));
+        metrics
+    }
+}
+
+fn operation(event: &TimelineEvent) -> Option<(AlarmPhase, u64)> {
+    match event {
+        TimelineEvent::Pause { duration_ms, .. } => Some((AlarmPhase::Pause, *duration_ms)),
+        TimelineEvent::Resume { duration_ms } => Some((AlarmPhase::Resume, *duration_ms)),
+        _ => None,
+    }
+}
+
+fn median(values: &[u64]) -> u64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regression_alarms_once() {
+        let monitor = TemplateMonitor::new(TemplateAnomalyConfig::default());
+        let resume = |duration_ms| TimelineEvent::Resume { duration_ms };
+        for ms in [100, 120, 90, 110, 100, 95, 105] {
+            assert!(monitor.observe("python", "a", &resume(ms)).is_none());
+        }
+        // One slow resume moves the median of the last three only once a second follows
+        assert!(monitor.observe("python", "b", &resume(1500)).is_none());
+        let alarm = monitor.observe("python", "c", &resume(1600)).unwrap();
+        assert_eq!((alarm.phase, alarm.elapsed_ms, alarm.template.as_deref()), (AlarmPhase::Resume, 1500, Some("python")));
+        assert!(monitor.observe("python", "d", &resume(1700)).is_none());
+
+        let metrics = monitor.metrics();
+        assert_eq!(metrics[0].resumes, 10);
+        assert_eq!(metrics[0].anomalous, vec![AlarmPhase::Resume]);
+    }
+}
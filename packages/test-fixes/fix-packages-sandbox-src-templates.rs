@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::fs as async_fs;
+use log::info;
+
+use crate::blocking;
+use crate::persistence::{PersistenceManager, SnapshotLoad};
+use crate::state_snapshot::StateSnapshot;
+
+/// Directory under the snapshot base holding templates
+pub const TEMPLATES_DIR: &str = ".templates";
+
+/// Template names become directory names, so only `[A-Za-z0-9_-]+` is accepted
+fn validate_name(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("Invalid template name {:?}: only letters, digits, '_' and '-' are allowed", name).into());
+    }
+    Ok(())
+}
+
+/// Holds a template open; the template cannot be removed until every lease is dropped
+pub struct TemplateLease {
+    name: String,
+    refs: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Drop for TemplateLease {
+    fn drop(&mut self) {
+        let mut refs = self.refs.lock().unwrap();
+        if let Some(count) = refs.get_mut(&self.name) {
+            *count -= 1;
+            if *count == 0 {
+                refs.remove(&self.name);
+            }
+        }
+    }
+}
+
+/// Reusable snapshots that many fresh sandboxes can be restored from.
+/// Layout: `<dir>/<name>/{snapshot.json,payloads}`. Templates are never
+/// modified by a restore; each restore works on its own copy.
+pub struct TemplateStore {
+    dir: PathBuf,
+    refs: Arc<Mutex<HashMap<String, usize>>>, // template name -> active leases
+}
+
+impl TemplateStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            refs: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn snapshot_file(&self, name: &str) -> PathBuf {
+        self.dir.join(name).join("snapshot.json")
+    }
+
+    /// Directory reserved for the template's filesystem payloads
+    pub fn payloads_dir(&self, name: &str) -> PathBuf {
+        self.dir.join(name).join("payloads")
+    }
+
+    /// Store `snapshot` as template `name`, replacing any unused template of that name
+    pub async fn create(&self, name: &str, snapshot: &StateSnapshot) -> Result<(), Box<dyn std::error::Error>> {
+        validate_name(name)?;
+        if self.ref_count(name) > 0 {
+            return Err(format!("Template {} is in use", name).into());
+        }
+
+        async_fs::create_dir_all(self.payloads_dir(name)).await?;
+        let file_path = self.snapshot_file(name);
+        let temp_path = file_path.with_extension("tmp");
+        async_fs::write(&temp_path, snapshot.to_json()?).await?;
+        async_fs::rename(&temp_path, &file_path).await?;
+
+        info!("Stored template {} from sandbox {}", name, snapshot.sandbox_id);
+        Ok(())
+    }
+
+    /// Lease a template and read its snapshot. Templates do not go stale.
+    pub async fn acquire(&self, name: &str) -> Result<(TemplateLease, StateSnapshot), Box<dyn std::error::Error>> {
+        validate_name(name)?;
+        *self.refs.lock().unwrap().entry(name.to_string()).or_insert(0) += 1;
+        let lease = TemplateLease {
+            name: name.to_string(),
+            refs: Arc::clone(&self.refs),
+        };
+
+        let json = async_fs::read_to_string(self.snapshot_file(name)).await?;
+        let snapshot = StateSnapshot::from_json(&json)?;
+        Ok((lease, snapshot))
+    }
+
+    /// Number of restores currently using the template
+    pub fn ref_count(&self, name: &str) -> usize {
+        self.refs.lock().unwrap().get(name).copied().unwrap_or(0)
+    }
+
+    /// Names of all stored templates
+    pub async fn list(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut names = Vec::new();
+        if !blocking::path_exists(&self.dir).await {
+            return Ok(names);
+        }
+
+        let mut entries = async_fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Delete a template; refused while any lease is held
+    pub async fn remove(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        validate_name(name)?;
+        if self.ref_count(name) > 0 {
+            return Err(format!("Template {} is in use", name).into());
+        }
+        async_fs::remove_dir_all(self.dir.join(name)).await?;
+        info!("Removed template {}", name);
+        Ok(())
+    }
+}
+
+impl PersistenceManager {
+    /// Mark a sandbox's current snapshot as reusable template `name`
+    pub async fn create_template(&self, sandbox_id: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.check_writable("template creation")?;
+        let _pin = self.pin_snapshot(sandbox_id);
+        match self.load_snapshot_detailed(sandbox_id).await? {
+            SnapshotLoad::Loaded(snapshot) => self.templates().create(name, &snapshot).await,
+            SnapshotLoad::Missing | SnapshotLoad::Stale => {
+                Err(format!("No usable snapshot for sandbox {}", sandbox_id).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_template_in_use_cannot_be_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = PersistenceManager::with_base_dir(temp_dir.path().to_path_buf());
+        manager.save_snapshot(&StateSnapshot::new("source".to_string())).await.unwrap();
+        manager.create_template("source", "python-base").await.unwrap();
+
+        let (lease, snapshot) = manager.templates().acquire("python-base").await.unwrap();
+        assert_eq!(snapshot.sandbox_id, "source");
+        assert_eq!(manager.templates().ref_count("python-base"), 1);
+        assert!(manager.templates().remove("python-base").await.is_err());
+
+        drop(lease);
+        manager.templates().remove("python-base").await.unwrap();
+        assert!(manager.templates().list().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_template_names_are_validated() {
+        let temp_dir = TempDir::new().unwrap();
+        let templates = TemplateStore::new(temp_dir.path().join(TEMPLATES_DIR));
+        for name in ["", "..", "../escape", "a/b", ".hidden", "with space", "tab\t"] {
+            assert!(templates.create(name, &StateSnapshot::new("source")).await.is_err(), "{:?}", name);
+            assert!(templates.acquire(name).await.is_err(), "{:?}", name);
+            assert!(templates.remove(name).await.is_err(), "{:?}", name);
+        }
+        assert_eq!(templates.ref_count(".."), 0);
+        templates.create("node_20-slim", &StateSnapshot::new("source")).await.unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use log::warn;
+use serde::{Serialize, Deserialize};
+
+use crate::error::SandboxError;
+use crate::metrics;
+
+/// Window `max_per_minute` is counted over
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Limits on the pause/resume operations of one tenant's sandboxes; unset means unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantQuota {
+    /// Operations running at once
+    pub max_concurrent: Option<usize>,
+    /// Operations started within any minute
+    pub max_per_minute: Option<u32>,
+}
+
+/// Per-tenant quotas, so one tenant cannot take every pause worker on a shared host
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TenantQuotasConfig {
+    /// Quota of tenants without an entry in `tenants`
+    pub default: TenantQuota,
+    pub tenants: HashMap<String, TenantQuota>,
+}
+
+#[derive(Default)]
+struct TenantUsage {
+    in_flight: usize,
+    started: VecDeque<Instant>,
+}
+
+/// Admits or rejects operations against their tenant's quota. Over-quota operations
+/// are rejected rather than queued, so they never hold a worker slot while waiting.
+pub struct TenantQuotas {
+    config: TenantQuotasConfig,
+    usage: Arc<Mutex<HashMap<String, TenantUsage>>>,
+}
+
+/// Counts against the tenant's concurrency quota until dropped
+pub struct QuotaPermit {
+    tenant: String,
+    usage: Arc<Mutex<HashMap<String, TenantUsage>>>,
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        if let Some(usage) = self.usage.lock().unwrap().get_mut(&self.tenant) {
+            usage.in_flight = usage.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+impl TenantQuotas {
+    pub fn new(config: TenantQuotasConfig) -> Self {
+        Self {
+            config,
+            usage: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn quota(&self, tenant: &str) -> &TenantQuota {
+        self.config.tenants.get(tenant).unwrap_or(&self.config.default)
+    }
+
+    /// Admit an operation for `tenant`, or fail with `QuotaExceeded`
+    pub fn try_acquire(&self, tenant: &str) -> Result<QuotaPermit, SandboxError> {
+        let quota = self.quota(tenant);
+        let now = Instant::now();
+        let mut all = self.usage.lock().unwrap();
+        let usage = all.entry(tenant.to_string()).or_default();
+        while usage.started.front().is_some_and(|started| now.duration_since(*started) >= RATE_WINDOW) {
+            usage.started.pop_front();
+        }
+
+        let rejection = if quota.max_concurrent.is_some_and(|max| usage.in_flight >= max) {
+            Some(("concurrency", format!("{} operations already running", usage.in_flight)))
+        } else if quota.max_per_minute.is_some_and(|max| usage.started.len() >= max as usize) {
+            Some(("rate", format!("{} operations started in the last minute", usage.started.len())))
+        } else {
+            None
+        };
+        if let Some((kind, reason)) = rejection {
+            warn!("Rejecting operation of tenant {}: {}", tenant, reason);
+            metrics::record_quota_rejection(tenant, kind);
+            return Err(SandboxError::QuotaExceeded { tenant: tenant.to_string(), reason });
+        }
+
+        usage.in_flight += 1;
+        if quota.max_per_minute.is_some() {
+            usage.started.push_back(now);
+        }
+        Ok(QuotaPermit {
+            tenant: tenant.to_string(),
+            usage: Arc::clone(&self.usage),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    #[test]
+    fn test_concurrency_and_rate_quotas() {
+        let quotas = TenantQuotas::new(TenantQuotasConfig {
+            default: TenantQuota { max_concurrent: Some(2), max_per_minute: None },
+            tenants: HashMap::from([("noisy".to_string(), TenantQuota { max_concurrent: None, max_per_minute: Some(3) })]),
+        });
+
+        let first = quotas.try_acquire("acme").unwrap();
+        let _second = quotas.try_acquire("acme").unwrap();
+        let rejected = quotas.try_acquire("acme").err().unwrap();
+        assert_eq!(rejected.code(), ErrorCode::QuotaExceeded);
+        assert!(rejected.is_retriable());
+        drop(first);
+        let _third = quotas.try_acquire("acme").unwrap();
+
+        // Finished operations still count towards the rate
+        for _ in 0..3 {
+            drop(quotas.try_acquire("noisy").unwrap());
+        }
+        assert!(quotas.try_acquire("noisy").is_err());
+        // Other tenants are unaffected
+        assert!(quotas.try_acquire("quiet").is_ok());
+    }
+}
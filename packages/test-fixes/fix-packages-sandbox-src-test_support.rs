@@ -0,0 +1,92 @@
+#![cfg(test)]
+
+//! Fixtures shared by the unit tests
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+
+use crate::object_store::ObjectStore;
+use crate::process::{ProcessInfo, ProcessState};
+use crate::state_snapshot::PersistedProcess;
+
+/// A running snapshot entry launched with `argv`
+pub fn persisted_process(pid: i32, argv: &[&str], started: DateTime<Utc>) -> PersistedProcess {
+    PersistedProcess {
+        pid,
+        name: argv.first().copied().unwrap_or_default().to_string(),
+        cmd: argv.join(" "),
+        argv: argv.iter().map(|arg| arg.to_string()).collect(),
+        start_time: started,
+        state: "running".to_string(),
+        thread_count: 1,
+        child_count: 0,
+        ppid: None,
+        fds: None,
+        locks: Vec::new(),
+        exit: None,
+        ended_at: None,
+        labels: BTreeMap::new(),
+        session: None,
+        launch: None,
+        uid: Some(1000),
+        argv_truncated: false,
+    }
+}
+
+/// A running tracked process started just now
+pub fn process_info(pid: i32, name: &str) -> ProcessInfo {
+    ProcessInfo {
+        pid,
+        name: name.to_string(),
+        cmd: name.to_string(),
+        argv: Vec::new(),
+        start_time: Utc::now(),
+        state: ProcessState::Running,
+        thread_count: 1,
+        child_count: 0,
+        exit: None,
+        ended_at: None,
+        labels: BTreeMap::new(),
+        session: None,
+    }
+}
+
+pub fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+/// A bucket held in memory
+#[derive(Default)]
+pub struct MemoryStore {
+    pub objects: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl ObjectStore for MemoryStore {
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<Option<Vec<u8>>, String>> {
+        Box::pin(async move { Ok(self.objects.lock().unwrap().get(key).cloned()) })
+    }
+
+    fn put<'a>(&'a self, key: &'a str, bytes: Vec<u8>) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            self.objects.lock().unwrap().insert(key.to_string(), bytes);
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<String>, String>> {
+        Box::pin(async move { Ok(self.objects.lock().unwrap().keys().cloned().collect()) })
+    }
+
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use log::{debug, info};
+use serde::{Serialize, Deserialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::host_load::{self, HostLoad};
+
+/// A concurrency limit that can be raised or lowered while in use
+pub struct Throttle {
+    name: &'static str,
+    semaphore: Arc<Semaphore>,
+    limit: AtomicUsize,
+    min: usize,
+    max: usize,
+}
+
+impl Throttle {
+    pub fn new(name: &'static str, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            name,
+            semaphore: Arc::new(Semaphore::new(max)),
+            limit: AtomicUsize::new(max),
+            min,
+            max,
+        }
+    }
+
+    /// Wait for a slot; the slot is released when the permit is dropped
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        Arc::clone(&self.semaphore)
+            .acquire_owned()
+            .await
+            .expect("throttle semaphore is never closed")
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    /// Slots free right now
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+
+    /// Halve the limit (not below `min`). Slots currently in use are only taken
+    /// away once released, so the limit may drop over several calls.
+    pub fn shrink(&self) {
+        let current = self.limit();
+        let target = (current / 2).max(self.min);
+        if target < current {
+            let removed = self.semaphore.forget_permits(current - target);
+            self.limit.fetch_sub(removed, Ordering::SeqCst);
+            debug!("Throttle {} shrunk to {}", self.name, current - removed);
+        }
+    }
+
+    /// Raise the limit by one (not above `max`)
+    pub fn grow(&self) {
+        if self.limit() < self.max {
+            self.semaphore.add_permits(1);
+            let limit = self.limit.fetch_add(1, Ordering::SeqCst) + 1;
+            debug!("Throttle {} grew to {}", self.name, limit);
+        }
+    }
+}
+
+/// Host load levels that drive throttling
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThrottleConfig {
+    /// Sampling period in milliseconds (default: 2000)
+    pub interval_ms: u64,
+    /// Shrink when the 1-minute load per CPU exceeds this (default: 1.5)
+    pub high_load_per_cpu: f64,
+    /// Shrink when CPU pressure (PSI avg10, %) exceeds this (default: 40)
+    pub high_cpu_pressure: f64,
+    /// Shrink when I/O pressure (PSI avg10, %) exceeds this (default: 30)
+    pub high_io_pressure: f64,
+    /// Grow only when every indicator is below this fraction of its threshold (default: 0.5)
+    pub low_water_ratio: f64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 2000,
+            high_load_per_cpu: 1.5,
+            high_cpu_pressure: 40.0,
+            high_io_pressure: 30.0,
+            low_water_ratio: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Adjustment {
+    Shrink,
+    Grow,
+    Hold,
+}
+
+fn decide(load: &HostLoad, config: &ThrottleConfig) -> Adjustment {
+    let ratios = [
+        load.load_per_cpu / config.high_load_per_cpu,
+        load.cpu_pressure.unwrap_or(0.0) / config.high_cpu_pressure,
+        load.io_pressure.unwrap_or(0.0) / config.high_io_pressure,
+    ];
+    let worst = ratios.iter().cloned().fold(0.0, f64::max);
+
+    if worst > 1.0 {
+        Adjustment::Shrink
+    } else if worst < config.low_water_ratio {
+        Adjustment::Grow
+    } else {
+        Adjustment::Hold
+    }
+}
+
+/// Watches host load and adjusts the limits of pause/resume workers and persistence I/O
+pub struct ThrottleController {
+    config: ThrottleConfig,
+    throttles: Vec<Arc<Throttle>>,
+}
+
+impl ThrottleController {
+    pub fn new(config: ThrottleConfig, throttles: Vec<Arc<Throttle>>) -> Self {
+        Self { config, throttles }
+    }
+
+    /// Run the controller in the background
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_millis(self.config.interval_ms));
+            loop {
+                ticker.tick().await;
+                self.adjust_once();
+            }
+        })
+    }
+
+    /// Sample host load and apply one adjustment step
+    pub fn adjust_once(&self) {
+        let Some(load) = host_load::sample() else {
+            return;
+        };
+
+        match decide(&load, &self.config) {
+            Adjustment::Shrink => {
+                info!("Host under load ({:?}), throttling pause/resume work", load);
+                self.throttles.iter().for_each(|t| t.shrink());
+            }
+            Adjustment::Grow => self.throttles.iter().for_each(|t| t.grow()),
+            Adjustment::Hold => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decide_thresholds() {
+        let config = ThrottleConfig::default();
+        let busy = HostLoad { load_per_cpu: 0.2, cpu_pressure: Some(5.0), io_pressure: Some(45.0) };
+        let moderate = HostLoad { load_per_cpu: 1.0, cpu_pressure: None, io_pressure: None };
+        let idle = HostLoad { load_per_cpu: 0.1, cpu_pressure: Some(1.0), io_pressure: Some(1.0) };
+
+        assert_eq!(decide(&busy, &config), Adjustment::Shrink);
+        assert_eq!(decide(&moderate, &config), Adjustment::Hold);
+        assert_eq!(decide(&idle, &config), Adjustment::Grow);
+    }
+
+    #[tokio::test]
+    async fn test_throttle_shrink_respects_permits_in_use() {
+        let throttle = Throttle::new("test", 1, 8);
+        let held: Vec<_> = futures::future::join_all((0..6).map(|_| throttle.acquire())).await;
+
+        // Only the two idle slots can be taken away right now
+        throttle.shrink();
+        assert_eq!(throttle.limit(), 6);
+
+        drop(held);
+        throttle.shrink();
+        assert_eq!(throttle.limit(), 3);
+
+        throttle.grow();
+        assert_eq!(throttle.limit(), 4);
+    }
+}
@@ -0,0 +1,167 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::auto_pause::AutoPauseManager;
+use crate::process::ProcessExit;
+use crate::stats::TimelineEvent;
+
+/// Where a timeline entry came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimelineSource {
+    Audit,
+    Lifecycle,
+    Process,
+    Oom,
+    Snapshot,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub at: DateTime<Utc>,
+    pub source: TimelineSource,
+    pub summary: String,
+}
+
+/// An audit record (`target: "audit"`) as exported from the log sink
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub at: DateTime<Utc>,
+    pub sandbox_id: String,
+    pub operation: String,
+    pub message: String,
+}
+
+/// Parse audit records from `journalctl -o json` output, one object per line;
+/// lines that are not audit records are skipped
+pub fn audit_from_journal_json(lines: &str) -> Vec<AuditEntry> {
+    lines
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|record| {
+            let field = |name: &str| record.get(name).and_then(|v| v.as_str()).map(String::from);
+            let micros: i64 = field("__REALTIME_TIMESTAMP")?.parse().ok()?;
+            Some(AuditEntry {
+                at: Utc.timestamp_micros(micros).single()?,
+                sandbox_id: field("SANDBOX_ID")?,
+                operation: field("OPERATION")?,
+                message: field("MESSAGE").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+impl AutoPauseManager {
+    /// Everything known about a sandbox in chronological order: recorded lifecycle
+    /// transitions, process starts and exits, OOM kills, the current snapshot, and
+    /// any `audit` records the caller exported from the log sink
+    pub async fn timeline(&self, sandbox_id: &str, audit: &[AuditEntry]) -> Result<Vec<TimelineEntry>, Box<dyn std::error::Error>> {
+        let mut entries = Vec::new();
+
+        entries.extend(audit.iter().filter(|a| a.sandbox_id == sandbox_id).map(|a| TimelineEntry {
+            at: a.at,
+            source: TimelineSource::Audit,
+            summary: format!("{}: {}", a.operation, a.message),
+        }));
+
+        for point in self.stats().sandbox(sandbox_id).map(|s| s.timeline).unwrap_or_default() {
+            let (source, summary) = match point.event {
+                TimelineEvent::State { state } => (TimelineSource::Lifecycle, format!("state {:?}", state)),
+                TimelineEvent::Pause { duration_ms, strategy } => (TimelineSource::Lifecycle, format!("paused ({:?}) in {}ms", strategy, duration_ms)),
+                TimelineEvent::Resume { duration_ms } => (TimelineSource::Lifecycle, format!("resumed in {}ms", duration_ms)),
+                TimelineEvent::Snapshot { bytes } => (TimelineSource::Snapshot, format!("snapshot written ({} bytes)", bytes)),
+            };
+            entries.push(TimelineEntry { at: point.at, source, summary });
+        }
+        // After a restart the in-memory timeline is empty; the persisted state still tells the latest transition
+        if !entries.iter().any(|e| e.source == TimelineSource::Lifecycle) {
+            if let Some(record) = self.persistence_manager().load_lifecycle(sandbox_id).await? {
+                entries.push(TimelineEntry {
+                    at: record.updated_at,
+                    source: TimelineSource::Lifecycle,
+                    summary: format!("state {:?}", record.state),
+                });
+            }
+        }
+
+        for process in self.process_manager().list_processes(sandbox_id).await? {
+            entries.push(TimelineEntry {
+                at: process.start_time,
+                source: TimelineSource::Process,
+                summary: format!("started {} ({})", process.pid, process.name),
+            });
+            if let Some(ended_at) = process.ended_at {
+                let how = match &process.exit {
+                    Some(ProcessExit::Code(code)) => format!("exited with code {}", code),
+                    Some(ProcessExit::Signal(signal)) => format!("killed by {}", signal),
+                    Some(ProcessExit::OomKilled) => "OOM-killed".to_string(),
+                    None => "terminated".to_string(),
+                };
+                entries.push(TimelineEntry {
+                    at: ended_at,
+                    source: TimelineSource::Process,
+                    summary: format!("{} ({}) {}", process.pid, process.name, how),
+                });
+            }
+        }
+
+        for event in self.process_manager().oom_events(sandbox_id).await {
+            entries.push(TimelineEntry {
+                at: event.at,
+                source: TimelineSource::Oom,
+                summary: format!("{} OOM kills, pids {:?}", event.kills, event.pids()),
+            });
+        }
+
+        if let Some(snapshot) = self.persistence_manager().load_snapshot(sandbox_id).await? {
+            entries.push(TimelineEntry {
+                at: snapshot.timestamp,
+                source: TimelineSource::Snapshot,
+                summary: format!("snapshot taken with {} processes", snapshot.processes.len()),
+            });
+        }
+
+        // Stable, so entries at the same instant keep their source order
+        entries.sort_by_key(|e| e.at);
+        Ok(entries)
+    }
+}
+
+/// Plain-text rendering, one entry per line
+pub fn render(entries: &[TimelineEntry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        let source = format!("{:?}", entry.source).to_lowercase();
+        out.push_str(&format!("{}  {:<9}  {}\n", entry.at.format("%Y-%m-%d %H:%M:%S%.3f"), source, entry.summary));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_from_journal_json() {
+        let lines = concat!(
+            r#"{"__REALTIME_TIMESTAMP":"1700000000000000","SANDBOX_ID":"sbx","OPERATION":"pause","MESSAGE":"Paused sandbox sbx"}"#,
+            "\n",
+            r#"{"__REALTIME_TIMESTAMP":"1700000000000001","MESSAGE":"unrelated"}"#,
+            "\nnot json\n"
+        );
+        let audit = audit_from_journal_json(lines);
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].operation, "pause");
+        assert_eq!(audit[0].at.timestamp(), 1_700_000_000);
+    }
+
+    #[test]
+    fn test_render() {
+        let entries = vec![TimelineEntry {
+            at: Utc.timestamp_opt(0, 0).unwrap(),
+            source: TimelineSource::Oom,
+            summary: "1 OOM kills".to_string(),
+        }];
+        assert_eq!(render(&entries), "1970-01-01 00:00:00.000  oom        1 OOM kills\n");
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use serde_json::Value;
+
+use crate::events::{Event, EventPayload};
+use crate::operations::OperationInfo;
+use crate::state_export::{SandboxStateView, StateExport};
+
+/// Recent events shown at the bottom of the dashboard
+const MAX_EVENTS: usize = 200;
+
+/// What `sandboxctl top` shows, updated from the event subscription and periodic refreshes
+#[derive(Default)]
+pub struct Dashboard {
+    pub sandboxes: Vec<SandboxStateView>,
+    pub operations: Vec<OperationInfo>,
+    pub events: VecDeque<Event>,
+    /// Row selected in the sandbox table; events are filtered to it when set
+    pub selected: Option<usize>,
+    /// A lifecycle change arrived since the last refresh of the sandbox table
+    stale: bool,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the sandbox and operation tables
+    pub fn refresh(&mut self, state: StateExport, operations: Vec<OperationInfo>) {
+        let selected_id = self.selected_sandbox().map(str::to_string);
+        self.sandboxes = state.sandboxes;
+        self.operations = operations;
+        self.selected = selected_id.and_then(|id| self.sandboxes.iter().position(|s| s.sandbox_id == id));
+        self.stale = false;
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        if matches!(event.payload, EventPayload::Lifecycle { .. } | EventPayload::Audit { .. } | EventPayload::OperationFailed { .. } | EventPayload::Approval { .. } | EventPayload::Recovery { .. }) {
+            self.stale = true;
+        }
+        if self.events.len() >= MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Whether the tables should be refreshed before the next tick
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn selected_sandbox(&self) -> Option<&str> {
+        self.selected.and_then(|i| self.sandboxes.get(i)).map(|s| s.sandbox_id.as_str())
+    }
+
+    /// Move the selection by `delta` rows; moving above the first row clears it
+    pub fn move_selection(&mut self, delta: isize) {
+        if self.sandboxes.is_empty() {
+            self.selected = None;
+            return;
+        }
+        let next = self.selected.map_or(0, |i| i as isize + delta);
+        self.selected = if next < 0 { None } else { Some((next as usize).min(self.sandboxes.len() - 1)) };
+    }
+
+    /// Events of the selected sandbox, or of all sandboxes, newest first
+    pub fn visible_events(&self) -> impl Iterator<Item = &Event> {
+        let selected = self.selected_sandbox();
+        self.events.iter().rev().filter(move |event| selected.is_none_or(|id| event.sandbox_id == id))
+    }
+}
+
+/// One line per event: time, sandbox, type and the payload fields
+pub fn describe(event: &Event) -> String {
+    let fields = match serde_json::to_value(&event.payload) {
+        Ok(Value::Object(mut tagged)) => match tagged.remove("payload") {
+            Some(Value::Object(payload)) => payload.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(" "),
+            Some(other) => other.to_string(),
+            None => String::new(),
+        },
+        _ => String::new(),
+    };
+    format!("{} {} {} {}", event.timestamp.format("%H:%M:%S"), event.sandbox_id, event.event_type(), fields).trim_end().to_string()
+}
+
+#[cfg(feature = "tui")]
+pub use self::terminal::run_top;
+
+#[cfg(feature = "tui")]
+mod terminal {
+    use std::io;
+    use std::time::Duration;
+    use crossterm::event::{Event as TermEvent, EventStream, KeyCode, KeyEventKind};
+    use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+    use crossterm::ExecutableCommand;
+    use futures::StreamExt;
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::layout::{Constraint, Direction, Layout};
+    use ratatui::style::{Modifier, Style};
+    use ratatui::widgets::{Block, Borders, List, ListItem, Row, Table, TableState};
+    use ratatui::{Frame, Terminal};
+
+    use super::{describe, Dashboard};
+    use crate::remote::AgentClient;
+
+    /// Raw mode and the alternate screen, left again however `run_top` returns
+    struct TerminalGuard;
+
+    impl TerminalGuard {
+        fn enter() -> io::Result<Self> {
+            enable_raw_mode()?;
+            // Made before switching screens, so a failed switch still leaves raw mode
+            let guard = Self;
+            io::stdout().execute(EnterAlternateScreen)?;
+            Ok(guard)
+        }
+    }
+
+    impl Drop for TerminalGuard {
+        fn drop(&mut self) {
+            let _ = io::stdout().execute(LeaveAlternateScreen);
+            let _ = disable_raw_mode();
+        }
+    }
+
+    /// Run the dashboard against a local or remote agent until `q` or Esc is pressed,
+    /// refreshing the tables every `tick` and whenever an event changes them
+    pub async fn run_top(agent: &dyn AgentClient, tick: Duration) -> io::Result<()> {
+        let mut events = agent.subscribe_events(None, 0).await.map_err(io::Error::other)?;
+        let mut dashboard = Dashboard::new();
+        refresh(agent, &mut dashboard).await?;
+
+        let _guard = TerminalGuard::enter()?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        let mut keys = EventStream::new();
+        let mut interval = tokio::time::interval(tick.max(Duration::from_millis(100)));
+        loop {
+            terminal.draw(|frame| draw(frame, &dashboard))?;
+            tokio::select! {
+                key = keys.next() => match key {
+                    Some(Ok(key)) => {
+                        if handle_key(&mut dashboard, key) {
+                            return Ok(());
+                        }
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                },
+                received = events.next() => match received {
+                    Some(Ok(event)) => dashboard.push_event(event),
+                    // An event this build cannot decode, e.g. from a newer agent
+                    Some(Err(_)) => {}
+                    None => return Ok(()),
+                },
+                _ = interval.tick() => refresh(agent, &mut dashboard).await?,
+            }
+            if dashboard.is_stale() {
+                refresh(agent, &mut dashboard).await?;
+            }
+        }
+    }
+
+    async fn refresh(agent: &dyn AgentClient, dashboard: &mut Dashboard) -> io::Result<()> {
+        let state = agent.export_state().await.map_err(io::Error::other)?;
+        let operations = agent.list_operations().await.map_err(io::Error::other)?;
+        dashboard.refresh(state, operations);
+        Ok(())
+    }
+
+    /// Apply a key press; true when the user asked to quit
+    fn handle_key(dashboard: &mut Dashboard, event: TermEvent) -> bool {
+        let TermEvent::Key(key) = event else {
+            return false;
+        };
+        if key.kind != KeyEventKind::Press {
+            return false;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Down | KeyCode::Char('j') => dashboard.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => dashboard.move_selection(-1),
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(frame: &mut Frame, dashboard: &Dashboard) {
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(45), Constraint::Length(8), Constraint::Min(5)])
+            .split(frame.area());
+
+        let sandboxes = dashboard.sandboxes.iter().map(|s| {
+            Row::new(vec![
+                s.sandbox_id.clone(),
+                format!("{:?}", s.lifecycle).to_lowercase(),
+                format!("{:?}", s.priority).to_lowercase(),
+                format!("{}/{}", s.running_count, s.process_count),
+                if s.pause_pending { "prepared".to_string() } else { String::new() },
+            ])
+        });
+        let table = Table::new(sandboxes, [Constraint::Percentage(40), Constraint::Length(10), Constraint::Length(10), Constraint::Length(10), Constraint::Length(10)])
+            .header(Row::new(vec!["SANDBOX", "STATE", "PRIORITY", "RUNNING", "PAUSE"]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(Block::default().borders(Borders::ALL).title(format!(" Sandboxes ({}) ", dashboard.sandboxes.len())))
+            .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::default().with_selected(dashboard.selected);
+        frame.render_stateful_widget(table, areas[0], &mut state);
+
+        let operations = dashboard.operations.iter().map(|op| {
+            Row::new(vec![op.id.to_string(), op.sandbox_id.clone(), format!("{:?}", op.kind).to_lowercase(), op.phase.clone(), format!("{} ms", op.elapsed_ms)])
+        });
+        let table = Table::new(operations, [Constraint::Length(6), Constraint::Percentage(40), Constraint::Length(8), Constraint::Percentage(30), Constraint::Length(12)])
+            .header(Row::new(vec!["ID", "SANDBOX", "KIND", "PHASE", "ELAPSED"]).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(Block::default().borders(Borders::ALL).title(" In-flight operations "));
+        frame.render_widget(table, areas[1]);
+
+        let title = match dashboard.selected_sandbox() {
+            Some(id) => format!(" Events of {} (q quits, up/down selects) ", id),
+            None => " Events (q quits, up/down selects) ".to_string(),
+        };
+        let events: Vec<ListItem> = dashboard.visible_events().take(areas[2].height as usize).map(|event| ListItem::new(describe(event))).collect();
+        frame.render_widget(List::new(events).block(Block::default().borders(Borders::ALL).title(title)), areas[2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::lifecycle::LifecycleState;
+
+    fn view(id: &str) -> SandboxStateView {
+        SandboxStateView {
+            sandbox_id: id.to_string(),
+            lifecycle: LifecycleState::default(),
+            priority: Default::default(),
+            process_count: 0,
+            running_count: 0,
+            last_snapshot_at: None,
+            active_pins: 0,
+            pause_pending: false,
+        }
+    }
+
+    #[test]
+    fn test_selection_filters_events_and_survives_refresh() {
+        let mut dashboard = Dashboard::new();
+        let state = |ids: &[&str]| StateExport { generated_at: Utc::now(), sandboxes: ids.iter().map(|id| view(id)).collect() };
+        dashboard.refresh(state(&["a", "b"]), Vec::new());
+        dashboard.push_event(Event::new("a", EventPayload::Lifecycle { state: LifecycleState::Paused }));
+        dashboard.push_event(Event::new("b", EventPayload::Audit { operation: "pause".to_string(), duration_ms: 12, warnings: 0, owner: None, team: None }));
+        assert!(dashboard.is_stale());
+        assert_eq!(dashboard.visible_events().count(), 2);
+
+        dashboard.move_selection(1);
+        dashboard.move_selection(1);
+        assert_eq!(dashboard.selected_sandbox(), Some("b"));
+        let events: Vec<String> = dashboard.visible_events().map(describe).collect();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].ends_with("b audit duration_ms=12 operation=\"pause\" warnings=0"));
+
+        // A new sandbox sorted before the selected one keeps the selection on "b"
+        dashboard.refresh(state(&["0", "a", "b"]), Vec::new());
+        assert_eq!(dashboard.selected_sandbox(), Some("b"));
+        assert!(!dashboard.is_stale());
+    }
+}
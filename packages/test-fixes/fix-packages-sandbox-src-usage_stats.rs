@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use crate::auto_pause::PauseStrategy;
+
+/// Opt-in aggregate usage statistics. Only counts and durations are kept:
+/// no sandbox IDs, process names or anything else identifying a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageStatsConfig {
+    pub enabled: bool,
+    /// How often a summary is produced (default: one day)
+    pub summary_interval_secs: u64,
+    /// Summaries are appended here as JSON lines when set
+    pub output_path: Option<PathBuf>,
+}
+
+impl Default for UsageStatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            summary_interval_secs: 86_400,
+            output_path: None,
+        }
+    }
+}
+
+/// Aggregates over one summary period
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub pauses: u64,
+    pub pause_failures: u64,
+    pub resumes: u64,
+    pub resume_failures: u64,
+    pub pause_p50_ms: Option<u64>,
+    pub pause_p95_ms: Option<u64>,
+    pub resume_p50_ms: Option<u64>,
+    pub resume_p95_ms: Option<u64>,
+    /// Successful pauses per strategy
+    pub strategies: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+struct Period {
+    start: Option<DateTime<Utc>>,
+    pause_ms: Vec<u64>,
+    pause_failures: u64,
+    resume_ms: Vec<u64>,
+    resume_failures: u64,
+    strategies: BTreeMap<String, u64>,
+}
+
+/// Accumulates the current period in memory
+pub struct UsageStats {
+    period: Mutex<Period>,
+    summaries: broadcast::Sender<UsageSummary>,
+}
+
+impl UsageStats {
+    pub fn new() -> Self {
+        let (summaries, _) = broadcast::channel(16);
+        Self {
+            period: Mutex::new(Period {
+                start: Some(Utc::now()),
+                ..Period::default()
+            }),
+            summaries,
+        }
+    }
+
+    pub fn record_pause(&self, strategy: PauseStrategy, duration: Duration) {
+        let mut period = self.period.lock().unwrap();
+        period.pause_ms.push(duration.as_millis() as u64);
+        let name = format!("{:?}", strategy).to_lowercase();
+        *period.strategies.entry(name).or_default() += 1;
+    }
+
+    pub fn record_pause_failure(&self) {
+        self.period.lock().unwrap().pause_failures += 1;
+    }
+
+    pub fn record_resume(&self, duration: Duration) {
+        self.period.lock().unwrap().resume_ms.push(duration.as_millis() as u64);
+    }
+
+    pub fn record_resume_failure(&self) {
+        self.period.lock().unwrap().resume_failures += 1;
+    }
+
+    /// Receive each summary as it is produced
+    pub fn subscribe(&self) -> broadcast::Receiver<UsageSummary> {
+        self.summaries.subscribe()
+    }
+
+    /// Close the current period and start a new one
+    pub fn take_summary(&self) -> UsageSummary {
+        let now = Utc::now();
+        let mut period = std::mem::replace(
+            &mut *self.period.lock().unwrap(),
+            Period {
+                start: Some(now),
+                ..Period::default()
+            },
+        );
+        period.pause_ms.sort_unstable();
+        period.resume_ms.sort_unstable();
+        UsageSummary {
+            period_start: period.start,
+            period_end: Some(now),
+            pauses: period.pause_ms.len() as u64,
+            pause_failures: period.pause_failures,
+            resumes: period.resume_ms.len() as u64,
+            resume_failures: period.resume_failures,
+            pause_p50_ms: percentile(&period.pause_ms, 50),
+            pause_p95_ms: percentile(&period.pause_ms, 95),
+            resume_p50_ms: percentile(&period.resume_ms, 50),
+            resume_p95_ms: percentile(&period.resume_ms, 95),
+            strategies: period.strategies,
+        }
+    }
+
+    /// Produce a summary every interval, broadcasting it and appending it to `output_path`
+    pub fn spawn(self: Arc<Self>, config: UsageStatsConfig) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.summary_interval_secs.max(1)));
+            // The first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let summary = self.take_summary();
+                info!("Usage summary: {} pauses, {} resumes", summary.pauses, summary.resumes);
+                if let Some(path) = &config.output_path {
+                    if let Err(e) = append_summary(path, &summary).await {
+                        warn!("Failed to write usage summary to {}: {}", path.display(), e);
+                    }
+                }
+                // No subscribers is fine
+                let _ = self.summaries.send(summary);
+            }
+        })
+    }
+}
+
+impl Default for UsageStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn append_summary(path: &PathBuf, summary: &UsageSummary) -> Result<(), Box<dyn std::error::Error>> {
+    let mut line = serde_json::to_string(summary)?;
+    line.push('\n');
+    let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_aggregates_and_resets() {
+        let stats = UsageStats::new();
+        for ms in 1..=20 {
+            stats.record_pause(PauseStrategy::Kill, Duration::from_millis(ms * 10));
+        }
+        stats.record_pause(PauseStrategy::Persist, Duration::from_millis(5));
+        stats.record_pause_failure();
+        stats.record_resume(Duration::from_millis(40));
+
+        let summary = stats.take_summary();
+        assert_eq!(summary.pauses, 21);
+        assert_eq!(summary.pause_failures, 1);
+        assert_eq!(summary.resumes, 1);
+        assert_eq!(summary.pause_p95_ms, Some(190));
+        assert_eq!(summary.resume_p50_ms, Some(40));
+        assert_eq!(summary.strategies.get("kill"), Some(&20));
+        assert_eq!(summary.strategies.get("persist"), Some(&1));
+
+        let next = stats.take_summary();
+        assert_eq!(next.pauses, 0);
+        assert_eq!(next.pause_p95_ms, None);
+    }
+}
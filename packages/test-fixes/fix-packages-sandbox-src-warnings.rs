@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+
+use crate::chaos::Chaos;
+use crate::events::{EventBus, EventPayload};
+use crate::sandbox_id::SandboxId;
+
+/// Non-fatal conditions callers may want to react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// A snapshot was too old to use and has been deleted
+    StaleSnapshotRemoved,
+    /// Signalling a process group failed (e.g. EPERM)
+    SignalFailed,
+    /// A restored process is no longer running
+    ProcessMissing,
+    /// A restored PID now belongs to a different program
+    ProcessReplaced,
+    /// A lock held before the pause is now held by another process
+    LockConflict,
+    /// A process keeps crashing and being restarted
+    CrashLoop,
+    /// A health probe did not pass after resume
+    ProbeFailed,
+    /// A lifecycle hook failed or timed out
+    HookFailed,
+    /// A resume was cancelled after processes were restored; later phases were skipped
+    ResumeCancelled,
+    /// A stored snapshot or lifecycle file is unreadable or damaged
+    SnapshotCorrupted,
+    /// A feature flag is on for the sandbox but this agent cannot do what it asks
+    FeatureUnavailable,
+}
+
+/// A structured warning attached to operation results and broadcast to subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub sandbox_id: SandboxId,
+    pub pid: Option<i32>,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, sandbox_id: &str, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            sandbox_id: SandboxId::new(sandbox_id),
+            pid: None,
+            message: message.into(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn with_pid(mut self, pid: i32) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+}
+
+/// Logs, broadcasts and collects warnings
+pub struct WarningSink {
+    sender: broadcast::Sender<Warning>,
+    chaos: Option<Arc<Chaos>>,
+    events: Option<EventBus>,
+}
+
+impl WarningSink {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(256);
+        Self { sender, chaos: None, events: None }
+    }
+
+    /// Also publish every broadcast warning as an event
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Drop broadcasts at the configured chaos rate; warnings are still logged and collected
+    pub fn with_chaos(mut self, chaos: Arc<Chaos>) -> Self {
+        self.chaos = Some(chaos);
+        self
+    }
+
+    /// Receive every warning emitted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Warning> {
+        self.sender.subscribe()
+    }
+
+    /// Log and broadcast a warning, then record it in the operation's result
+    pub fn emit(&self, warning: Warning, collected: &mut Vec<Warning>) {
+        self.publish(warning.clone());
+        collected.push(warning);
+    }
+
+    /// Log and broadcast a warning raised outside of any operation
+    pub fn publish(&self, warning: Warning) {
+        warn!("[{}] {}", warning.sandbox_id, warning.message);
+        if self.chaos.as_ref().map_or(false, |chaos| chaos.drop_event()) {
+            warn!("chaos: dropping broadcast of warning for sandbox {}", warning.sandbox_id);
+            return;
+        }
+        if let Some(events) = &self.events {
+            events.publish(&warning.sandbox_id, EventPayload::Warning(warning.clone()));
+        }
+        // No subscribers is fine; the warning is still logged
+        let _ = self.sender.send(warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emit_collects_broadcasts_and_publishes() {
+        let events = EventBus::new();
+        let sink = WarningSink::new().with_events(events.clone());
+        let mut receiver = sink.subscribe();
+        let mut collected = Vec::new();
+
+        sink.emit(Warning::new(WarningKind::SignalFailed, "sbx", "EPERM").with_pid(7), &mut collected);
+        sink.publish(Warning::new(WarningKind::CrashLoop, "sbx", "restarted 5 times"));
+
+        // Only the operation's own warning lands in its result
+        assert_eq!(collected.len(), 1);
+        assert_eq!((collected[0].kind, collected[0].pid), (WarningKind::SignalFailed, Some(7)));
+        assert_eq!(receiver.try_recv().unwrap().kind, WarningKind::SignalFailed);
+        assert_eq!(receiver.try_recv().unwrap().kind, WarningKind::CrashLoop);
+        let published: Vec<WarningKind> = events
+            .recent("sbx")
+            .into_iter()
+            .filter_map(|event| match event.payload {
+                EventPayload::Warning(warning) => Some(warning.kind),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(published, [WarningKind::SignalFailed, WarningKind::CrashLoop]);
+    }
+}
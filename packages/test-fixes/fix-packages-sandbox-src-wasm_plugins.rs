@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::BoxFuture;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use wasmtime::{Caller, Engine, Instance, Linker, Module, Store};
+
+use crate::blocking;
+use crate::hooks::{HookContext, HookPhase, LifecycleHook};
+use crate::process::ProcessInfo;
+
+/// A plugin to load at startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmPluginConfig {
+    pub path: PathBuf,
+    /// Instructions a single call may execute (default: 10M)
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    /// Wall-clock time a single call may take, however little fuel it burns, e.g.
+    /// while a host call blocks (default: 100)
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_fuel() -> u64 {
+    10_000_000
+}
+
+fn default_timeout_ms() -> u64 {
+    100
+}
+
+/// How often the engine's epoch advances; call timeouts are rounded up to it
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// The view of a process handed to `spare`
+#[derive(Debug, Serialize)]
+struct ProcessView<'a> {
+    pid: i32,
+    name: &'a str,
+    cmd: &'a str,
+    state: &'a str,
+    labels: &'a BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct HookView<'a> {
+    sandbox_id: &'a str,
+    phase: HookPhase,
+}
+
+struct PluginState {
+    name: String,
+}
+
+/// A tenant-supplied policy compiled to WebAssembly.
+///
+/// Plugins get no WASI, only an `env.log(ptr, len)` import. They export `memory`,
+/// `alloc(len) -> ptr` and any of `spare(ptr, len) -> i32` (process JSON in, non-zero
+/// spares it) and `on_hook(ptr, len) -> i32` (`{sandbox_id, phase}` in, non-zero rejects
+/// the phase). Each call gets a fresh store, a fuel budget and a time limit.
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    /// Epoch ticks a call may run for
+    deadline_ticks: u64,
+}
+
+impl WasmPlugin {
+    pub fn load(config: &WasmPluginConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let name = config
+            .path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| config.path.display().to_string());
+        let plugin = Self::from_bytes(&name, &std::fs::read(&config.path)?, config.fuel, Duration::from_millis(config.timeout_ms))?;
+        info!("Loaded WASM plugin {} from {}", name, config.path.display());
+        Ok(plugin)
+    }
+
+    /// Compile a plugin from WASM binary or text
+    pub fn from_bytes(name: &str, bytes: &[u8], fuel: u64, timeout: Duration) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        engine_config.epoch_interruption(true);
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::new(&engine, bytes)?;
+        spawn_epoch_ticker(&engine)?;
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            module,
+            fuel,
+            deadline_ticks: timeout.as_millis().div_ceil(EPOCH_TICK.as_millis()).max(1) as u64,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn exports(&self, export: &str) -> bool {
+        self.module.get_export(export).is_some()
+    }
+
+    /// Instantiate in a fresh store and call `export` with `input` as JSON
+    fn call(&self, export: &str, input: &[u8]) -> Result<i32, String> {
+        let mut linker: Linker<PluginState> = Linker::new(&self.engine);
+        linker
+            .func_wrap("env", "log", |mut caller: Caller<'_, PluginState>, ptr: i32, len: i32| {
+                let Some(memory) = caller.get_export("memory").and_then(|e| e.into_memory()) else {
+                    return;
+                };
+                let mut buf = vec![0u8; len.max(0) as usize];
+                if memory.read(&caller, ptr as usize, &mut buf).is_ok() {
+                    info!("[plugin {}] {}", caller.data().name, String::from_utf8_lossy(&buf));
+                }
+            })
+            .map_err(|e| e.to_string())?;
+
+        let mut store = Store::new(&self.engine, PluginState { name: self.name.clone() });
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        store.set_epoch_deadline(self.deadline_ticks);
+        let instance: Instance = linker.instantiate(&mut store, &self.module).map_err(|e| e.to_string())?;
+        let memory = instance.get_memory(&mut store, "memory").ok_or("plugin exports no memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc").map_err(|e| e.to_string())?;
+        let entry = instance.get_typed_func::<(i32, i32), i32>(&mut store, export).map_err(|e| e.to_string())?;
+
+        let len = input.len() as i32;
+        let ptr = alloc.call(&mut store, len).map_err(|e| e.to_string())?;
+        memory.write(&mut store, ptr as usize, input).map_err(|e| e.to_string())?;
+        entry.call(&mut store, (ptr, len)).map_err(|e| e.to_string())
+    }
+
+    /// Whether the plugin wants `process` left running; plugins without `spare` spare nothing
+    pub fn spare(&self, process: &ProcessInfo) -> Result<bool, String> {
+        if !self.exports("spare") {
+            return Ok(false);
+        }
+        let state = format!("{:?}", process.state).to_lowercase();
+        let view = ProcessView {
+            pid: process.pid,
+            name: &process.name,
+            cmd: &process.cmd,
+            state: &state,
+            labels: &process.labels,
+        };
+        let input = serde_json::to_vec(&view).map_err(|e| e.to_string())?;
+        Ok(self.call("spare", &input)? != 0)
+    }
+
+    /// Ask the plugin whether a lifecycle phase may go ahead
+    pub fn on_hook(&self, context: &HookContext) -> Result<(), String> {
+        if !self.exports("on_hook") {
+            return Ok(());
+        }
+        let view = HookView {
+            sandbox_id: &context.sandbox_id,
+            phase: context.phase,
+        };
+        let input = serde_json::to_vec(&view).map_err(|e| e.to_string())?;
+        match self.call("on_hook", &input)? {
+            0 => Ok(()),
+            code => Err(format!("plugin {} rejected {:?} with code {}", self.name, context.phase, code)),
+        }
+    }
+}
+
+/// Advance the epoch of `engine` every `EPOCH_TICK` until the engine is dropped
+fn spawn_epoch_ticker(engine: &Engine) -> std::io::Result<()> {
+    let engine = engine.weak();
+    std::thread::Builder::new().name("wasm-epoch".to_string()).spawn(move || {
+        while let Some(engine) = engine.upgrade() {
+            engine.increment_epoch();
+            drop(engine);
+            std::thread::sleep(EPOCH_TICK);
+        }
+    })?;
+    Ok(())
+}
+
+/// All loaded plugins
+#[derive(Default)]
+pub struct PluginHost {
+    plugins: Vec<Arc<WasmPlugin>>,
+}
+
+impl PluginHost {
+    /// Load the configured plugins; one that fails to load is skipped with a warning
+    pub fn load(configs: &[WasmPluginConfig]) -> Self {
+        let plugins = configs
+            .iter()
+            .filter_map(|config| match WasmPlugin::load(config) {
+                Ok(plugin) => Some(Arc::new(plugin)),
+                Err(e) => {
+                    warn!("Failed to load WASM plugin {}: {}", config.path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Self { plugins }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn plugins(&self) -> &[Arc<WasmPlugin>] {
+        &self.plugins
+    }
+
+    /// Whether any plugin spares `process`, asked on the blocking pool. A failing
+    /// plugin, or one that runs out of fuel or time, spares nothing.
+    pub async fn spare(&self, process: &ProcessInfo) -> bool {
+        if self.plugins.is_empty() {
+            return false;
+        }
+        let plugins = self.plugins.clone();
+        let process = process.clone();
+        blocking::run("wasm_spare", move || {
+            plugins.iter().any(|plugin| match plugin.spare(&process) {
+                Ok(spare) => spare,
+                Err(e) => {
+                    warn!("WASM plugin {} failed on process {}: {}", plugin.name(), process.pid, e);
+                    false
+                }
+            })
+        })
+        .await
+    }
+}
+
+/// Runs a plugin's `on_hook` on the blocking pool
+pub struct PluginHook {
+    plugin: Arc<WasmPlugin>,
+}
+
+impl PluginHook {
+    pub fn new(plugin: Arc<WasmPlugin>) -> Self {
+        Self { plugin }
+    }
+}
+
+impl LifecycleHook for PluginHook {
+    fn run<'a>(&'a self, context: &'a HookContext) -> BoxFuture<'a, Result<(), String>> {
+        let plugin = self.plugin.clone();
+        let context = context.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || plugin.on_hook(&context))
+                .await
+                .map_err(|e| e.to_string())?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    const SPARE_ALL: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) i32.const 0)
+        (func (export "spare") (param i32 i32) (result i32) i32.const 1)
+        (func (export "on_hook") (param i32 i32) (result i32) (loop br 0) i32.const 0))"#;
+
+    fn process() -> ProcessInfo {
+        ProcessInfo {
+            cmd: "worker --once".to_string(),
+            ..test_support::process_info(7, "worker")
+        }
+    }
+
+    #[test]
+    fn test_plugin_spares_process() {
+        let plugin = WasmPlugin::from_bytes("spare_all", SPARE_ALL.as_bytes(), default_fuel(), Duration::from_millis(default_timeout_ms())).unwrap();
+        assert_eq!(plugin.spare(&process()), Ok(true));
+    }
+
+    #[test]
+    fn test_fuel_stops_runaway_plugin() {
+        let plugin = WasmPlugin::from_bytes("spare_all", SPARE_ALL.as_bytes(), 10_000, Duration::from_secs(60)).unwrap();
+        let context = HookContext {
+            sandbox_id: "sbx".to_string(),
+            phase: HookPhase::PrePause,
+        };
+        assert!(plugin.on_hook(&context).is_err());
+    }
+
+    #[test]
+    fn test_timeout_stops_runaway_plugin() {
+        let plugin = WasmPlugin::from_bytes("spare_all", SPARE_ALL.as_bytes(), u64::MAX, Duration::from_millis(50)).unwrap();
+        let context = HookContext {
+            sandbox_id: "sbx".to_string(),
+            phase: HookPhase::PrePause,
+        };
+        let started = std::time::Instant::now();
+        assert!(plugin.on_hook(&context).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
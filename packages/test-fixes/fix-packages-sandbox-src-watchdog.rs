@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Serialize, Deserialize};
+use tokio::task::JoinHandle;
+
+use crate::alarms::{Alarm, AlarmPhase, PhaseTimings};
+use crate::auto_pause::AutoPauseManager;
+use crate::operations::{OperationId, OperationInfo, OperationKind};
+
+/// Expected duration of an operation kind without an SLO threshold or configured value
+const DEFAULT_EXPECTED_MS: u64 = 60_000;
+
+/// What the watchdog does about a stuck operation besides alarming
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StuckPolicy {
+    /// Raise an alarm and capture diagnostics only
+    #[default]
+    Alert,
+    /// Also cancel the operation at its next checkpoint
+    Cancel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchdogConfig {
+    /// How often in-flight operations are checked (default: 5000)
+    pub interval_ms: u64,
+    /// An operation is stuck once it has run this many times its expected duration (default: 3)
+    pub multiplier: f64,
+    /// Expected pause duration; defaults to the pause SLO threshold
+    pub expected_pause_ms: Option<u64>,
+    /// Expected resume duration; defaults to the resume SLO threshold
+    pub expected_resume_ms: Option<u64>,
+    pub on_stuck: StuckPolicy,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 5000,
+            multiplier: 3.0,
+            expected_pause_ms: None,
+            expected_resume_ms: None,
+            on_stuck: StuckPolicy::default(),
+        }
+    }
+}
+
+/// Diagnostic state captured when an operation is flagged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StuckOperation {
+    pub operation: OperationInfo,
+    pub expected_ms: u64,
+    pub detected_at: DateTime<Utc>,
+    /// Backtraces of every runtime task; only available in builds with tokio's task dumps enabled
+    pub task_dump: Option<String>,
+    pub cancelled: bool,
+}
+
+/// Whether an operation has outrun `multiplier` times its expected duration
+fn is_stuck(operation: &OperationInfo, expected_ms: u64, multiplier: f64) -> bool {
+    operation.elapsed_ms as f64 > expected_ms as f64 * multiplier
+}
+
+/// Flags pauses and resumes running far longer than expected, once per operation
+pub struct Watchdog {
+    manager: Arc<AutoPauseManager>,
+    config: WatchdogConfig,
+    flagged: HashSet<OperationId>,
+}
+
+impl Watchdog {
+    /// Fails on a zero interval or a multiplier that is not positive
+    pub fn new(manager: Arc<AutoPauseManager>, config: WatchdogConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if config.interval_ms == 0 {
+            return Err("watchdog interval_ms must be at least 1".into());
+        }
+        if !(config.multiplier > 0.0) {
+            return Err(format!("watchdog multiplier must be greater than 0, got {}", config.multiplier).into());
+        }
+        Ok(Self {
+            manager,
+            config,
+            flagged: HashSet::new(),
+        })
+    }
+
+    /// Run the watchdog in the background
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_millis(self.config.interval_ms));
+            loop {
+                ticker.tick().await;
+                self.check_once().await;
+            }
+        })
+    }
+
+    fn expected_ms(&self, kind: OperationKind) -> u64 {
+        let slo = self.manager.slo();
+        let expected = match kind {
+            OperationKind::Pause => self.config.expected_pause_ms.or(slo.pause_max_ms),
+            OperationKind::Resume => self.config.expected_resume_ms.or(slo.resume_max_ms),
+        };
+        expected.unwrap_or(DEFAULT_EXPECTED_MS)
+    }
+
+    /// Check in-flight operations; returns the ones newly found stuck
+    pub async fn check_once(&mut self) -> Vec<StuckOperation> {
+        let operations = self.manager.list_operations();
+        self.flagged.retain(|id| operations.iter().any(|op| op.id == *id));
+
+        let mut stuck = Vec::new();
+        for operation in operations {
+            let expected_ms = self.expected_ms(operation.kind);
+            if self.flagged.contains(&operation.id) || !is_stuck(&operation, expected_ms, self.config.multiplier) {
+                continue;
+            }
+            self.flagged.insert(operation.id);
+
+            let cancelled = self.config.on_stuck == StuckPolicy::Cancel && self.manager.cancel_operation(operation.id);
+            let report = StuckOperation {
+                expected_ms,
+                detected_at: Utc::now(),
+                task_dump: task_dump().await,
+                cancelled,
+                operation,
+            };
+            self.raise(&report);
+            stuck.push(report);
+        }
+        stuck
+    }
+
+    fn raise(&self, report: &StuckOperation) {
+        let operation = &report.operation;
+        error!(
+            "{:?} of sandbox {} (operation {}) stuck in phase {} for {}ms, {}ms in total against {}ms expected{}",
+            operation.kind,
+            operation.sandbox_id,
+            operation.id,
+            operation.phase,
+            operation.last_progress_ms,
+            operation.elapsed_ms,
+            report.expected_ms,
+            if report.cancelled { "; cancelling" } else { "" }
+        );
+        if let Some(dump) = &report.task_dump {
+            info!("Task dump for stuck operation {}:\n{}", operation.id, dump);
+        }
+        self.manager.raise_alarm(Alarm {
+            sandbox_id: operation.sandbox_id.clone(),
+            phase: match operation.kind {
+                OperationKind::Pause => AlarmPhase::Pause,
+                OperationKind::Resume => AlarmPhase::Resume,
+            },
+            elapsed_ms: operation.elapsed_ms,
+            threshold_ms: (report.expected_ms as f64 * self.config.multiplier) as u64,
+            phase_timings: PhaseTimings::from([(operation.phase.clone(), operation.last_progress_ms)]),
+            timestamp: report.detected_at,
+            stuck: true,
+            template: None,
+        });
+    }
+}
+
+impl AutoPauseManager {
+    /// Start the configured watchdog, if any
+    pub fn spawn_watchdog(self: &Arc<Self>) -> Result<Option<JoinHandle<()>>, Box<dyn std::error::Error>> {
+        let Some(config) = self.config().watchdog.clone() else {
+            return Ok(None);
+        };
+        let task = Watchdog::new(self.clone(), config)?.spawn();
+        self.track_task("watchdog", &task);
+        Ok(Some(task))
+    }
+}
+
+/// Backtraces of every task on the current runtime, when tokio was built with task dumps
+async fn task_dump() -> Option<String> {
+    #[cfg(all(tokio_unstable, tokio_taskdump))]
+    {
+        let dump = tokio::time::timeout(Duration::from_secs(1), tokio::runtime::Handle::current().dump()).await.ok()?;
+        let traces: Vec<String> = dump.tasks().iter().map(|task| task.trace().to_string()).collect();
+        Some(traces.join("\n\n"))
+    }
+    #[cfg(not(all(tokio_unstable, tokio_taskdump)))]
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_zero_interval_is_rejected() {
+        let manager = Arc::new(AutoPauseManager::new(Default::default()));
+        let config = WatchdogConfig { interval_ms: 0, ..Default::default() };
+        assert!(Watchdog::new(manager.clone(), config).is_err());
+        let config = WatchdogConfig { multiplier: 0.0, ..Default::default() };
+        assert!(Watchdog::new(manager.clone(), config).is_err());
+        assert!(Watchdog::new(manager, WatchdogConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_stuck_after_multiple_of_expected() {
+        let operation = OperationInfo {
+            id: 1,
+            sandbox_id: "sbx".to_string(),
+            kind: OperationKind::Resume,
+            phase: "restore".to_string(),
+            started_at: Utc::now(),
+            elapsed_ms: 2500,
+            last_progress_ms: 2000,
+            deadline_in_ms: None,
+            cancelled: false,
+        };
+        assert!(!is_stuck(&operation, 1000, 3.0));
+        assert!(is_stuck(&operation, 1000, 2.0));
+    }
+}
@@ -0,0 +1,641 @@
+use std::collections::BTreeMap;
+use chrono::{DateTime, Utc};
+use prost::Message;
+use serde_json::Value;
+
+use crate::auto_pause::{self, RestoreStatus as InternalRestoreStatus};
+use crate::events;
+use crate::lifecycle::LifecycleState as InternalLifecycleState;
+use crate::operations::{self, OperationKind as InternalOperationKind};
+use crate::process::{self, ProcessState as InternalProcessState};
+use crate::process_tree::TreeFormat as InternalTreeFormat;
+use crate::registry::PriorityClass as InternalPriorityClass;
+use crate::state_export::{self, SandboxStateView};
+use crate::state_snapshot::{self, PersistedProcess as InternalProcess, StateSnapshot as InternalSnapshot};
+use crate::warnings::{self, WarningKind as InternalWarningKind};
+
+// Generated from proto/sandbox.proto by build.rs
+tonic::include_proto!("sandbox.v1");
+
+fn millis(at: &DateTime<Utc>) -> i64 {
+    at.timestamp_millis()
+}
+
+fn from_millis(ms: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp_millis(ms).unwrap_or_default()
+}
+
+impl From<&InternalSnapshot> for StateSnapshot {
+    fn from(snapshot: &InternalSnapshot) -> Self {
+        Self {
+            sandbox_id: snapshot.sandbox_id.to_string(),
+            timestamp_ms: millis(&snapshot.timestamp),
+            processes: snapshot.processes.iter().map(PersistedProcess::from).collect(),
+            truncations: snapshot
+                .truncations
+                .iter()
+                .map(|t| Truncation {
+                    pid: t.pid,
+                    field: t.field.clone(),
+                    original: t.original as u64,
+                    kept: t.kept as u64,
+                })
+                .collect(),
+            owner: snapshot.owner.clone(),
+            team: snapshot.team.clone(),
+            frozen: snapshot.frozen.clone(),
+        }
+    }
+}
+
+impl From<&InternalProcess> for PersistedProcess {
+    fn from(process: &InternalProcess) -> Self {
+        Self {
+            pid: process.pid,
+            name: process.name.clone(),
+            cmd: process.cmd.clone(),
+            argv: process.argv.clone(),
+            start_time_ms: millis(&process.start_time),
+            state: process.state.clone(),
+            thread_count: process.thread_count,
+            child_count: process.child_count,
+            fds: process.fds.as_ref().map(|fds| FdSummary {
+                files: fds.files,
+                sockets: fds.sockets,
+                pipes: fds.pipes,
+                other: fds.other,
+                notable_paths: fds.notable_paths.clone(),
+                listening_ports: fds.listening_ports.iter().map(|&port| u32::from(port)).collect(),
+            }),
+            locks: process
+                .locks
+                .iter()
+                .map(|lock| FileLock {
+                    kind: lock.kind.clone(),
+                    access: lock.access.clone(),
+                    dev_inode: lock.dev_inode.clone(),
+                    inode: lock.inode,
+                    path: lock.path.clone(),
+                })
+                .collect(),
+            ended_at_ms: process.ended_at.as_ref().map(millis),
+            labels: process.labels.clone(),
+            ppid: process.ppid,
+            uid: process.uid,
+            argv_truncated: process.argv_truncated,
+        }
+    }
+}
+
+/// Fields the proto does not carry (exit status, session, launch context, sessions,
+/// cgroup limits, OOM events) come back empty
+impl From<StateSnapshot> for InternalSnapshot {
+    fn from(snapshot: StateSnapshot) -> Self {
+        let mut internal = InternalSnapshot::new(snapshot.sandbox_id);
+        internal.timestamp = from_millis(snapshot.timestamp_ms);
+        internal.processes = snapshot.processes.into_iter().map(InternalProcess::from).collect();
+        internal.truncations = snapshot
+            .truncations
+            .into_iter()
+            .map(|t| state_snapshot::Truncation {
+                pid: t.pid,
+                field: t.field,
+                original: t.original as usize,
+                kept: t.kept as usize,
+            })
+            .collect();
+        internal.owner = snapshot.owner;
+        internal.team = snapshot.team;
+        internal.frozen = snapshot.frozen;
+        internal
+    }
+}
+
+impl From<PersistedProcess> for InternalProcess {
+    fn from(process: PersistedProcess) -> Self {
+        Self {
+            pid: process.pid,
+            name: process.name,
+            cmd: process.cmd,
+            argv: process.argv,
+            start_time: from_millis(process.start_time_ms),
+            state: process.state,
+            thread_count: process.thread_count,
+            child_count: process.child_count,
+            ppid: process.ppid,
+            fds: process.fds.map(|fds| state_snapshot::FdSummary {
+                files: fds.files,
+                sockets: fds.sockets,
+                pipes: fds.pipes,
+                other: fds.other,
+                notable_paths: fds.notable_paths,
+                // Out-of-range ports can only come from a broken writer
+                listening_ports: fds.listening_ports.into_iter().filter_map(|port| u16::try_from(port).ok()).collect(),
+            }),
+            locks: process
+                .locks
+                .into_iter()
+                .map(|lock| state_snapshot::FileLock {
+                    kind: lock.kind,
+                    access: lock.access,
+                    dev_inode: lock.dev_inode,
+                    inode: lock.inode,
+                    path: lock.path,
+                })
+                .collect(),
+            exit: None,
+            ended_at: process.ended_at_ms.map(from_millis),
+            labels: process.labels,
+            session: None,
+            launch: None,
+            uid: process.uid,
+            argv_truncated: process.argv_truncated,
+        }
+    }
+}
+
+impl From<InternalWarningKind> for WarningKind {
+    fn from(kind: InternalWarningKind) -> Self {
+        match kind {
+            InternalWarningKind::StaleSnapshotRemoved => WarningKind::StaleSnapshotRemoved,
+            InternalWarningKind::SignalFailed => WarningKind::SignalFailed,
+            InternalWarningKind::ProcessMissing => WarningKind::ProcessMissing,
+            InternalWarningKind::ProcessReplaced => WarningKind::ProcessReplaced,
+            InternalWarningKind::LockConflict => WarningKind::LockConflict,
+            InternalWarningKind::CrashLoop => WarningKind::CrashLoop,
+            InternalWarningKind::ProbeFailed => WarningKind::ProbeFailed,
+            InternalWarningKind::HookFailed => WarningKind::HookFailed,
+            InternalWarningKind::ResumeCancelled => WarningKind::ResumeCancelled,
+            InternalWarningKind::SnapshotCorrupted => WarningKind::SnapshotCorrupted,
+            InternalWarningKind::FeatureUnavailable => WarningKind::FeatureUnavailable,
+        }
+    }
+}
+
+impl From<&warnings::Warning> for Warning {
+    fn from(warning: &warnings::Warning) -> Self {
+        Self {
+            kind: WarningKind::from(warning.kind) as i32,
+            sandbox_id: warning.sandbox_id.to_string(),
+            pid: warning.pid,
+            message: warning.message.clone(),
+            timestamp_ms: millis(&warning.timestamp),
+        }
+    }
+}
+
+impl From<&auto_pause::PauseResult> for PauseResult {
+    fn from(result: &auto_pause::PauseResult) -> Self {
+        Self {
+            sandbox_id: result.sandbox_id.clone(),
+            warnings: result.warnings.iter().map(Warning::from).collect(),
+            deadline_exceeded: result.deadline_exceeded,
+            remaining_pids: result.remaining_pids.clone(),
+        }
+    }
+}
+
+impl From<InternalRestoreStatus> for RestoreStatus {
+    fn from(status: InternalRestoreStatus) -> Self {
+        match status {
+            InternalRestoreStatus::Verified => RestoreStatus::Verified,
+            InternalRestoreStatus::Missing => RestoreStatus::Missing,
+            InternalRestoreStatus::Replaced => RestoreStatus::Replaced,
+            InternalRestoreStatus::Adopted => RestoreStatus::Adopted,
+        }
+    }
+}
+
+impl From<&auto_pause::ResumeReport> for ResumeReport {
+    fn from(report: &auto_pause::ResumeReport) -> Self {
+        Self {
+            sandbox_id: report.sandbox_id.clone(),
+            restored: report
+                .restored
+                .iter()
+                .map(|p| RestoredProcess {
+                    pid: p.pid,
+                    name: p.name.clone(),
+                    status: RestoreStatus::from(p.status) as i32,
+                    adopted_pid: p.adopted_pid,
+                })
+                .collect(),
+            warnings: report.warnings.iter().map(Warning::from).collect(),
+        }
+    }
+}
+
+// Replies of the agent API decode back into the library types, so remote and local
+// agents give the same results. Enum values this build does not know are an error.
+
+fn unknown(what: &str, value: i32) -> String {
+    format!("agent sent unknown {} {}", what, value)
+}
+
+impl TryFrom<Warning> for warnings::Warning {
+    type Error = String;
+
+    fn try_from(warning: Warning) -> Result<Self, String> {
+        let kind = match WarningKind::try_from(warning.kind).ok() {
+            Some(WarningKind::StaleSnapshotRemoved) => InternalWarningKind::StaleSnapshotRemoved,
+            Some(WarningKind::SignalFailed) => InternalWarningKind::SignalFailed,
+            Some(WarningKind::ProcessMissing) => InternalWarningKind::ProcessMissing,
+            Some(WarningKind::ProcessReplaced) => InternalWarningKind::ProcessReplaced,
+            Some(WarningKind::LockConflict) => InternalWarningKind::LockConflict,
+            Some(WarningKind::CrashLoop) => InternalWarningKind::CrashLoop,
+            Some(WarningKind::ProbeFailed) => InternalWarningKind::ProbeFailed,
+            Some(WarningKind::HookFailed) => InternalWarningKind::HookFailed,
+            Some(WarningKind::ResumeCancelled) => InternalWarningKind::ResumeCancelled,
+            Some(WarningKind::SnapshotCorrupted) => InternalWarningKind::SnapshotCorrupted,
+            Some(WarningKind::FeatureUnavailable) => InternalWarningKind::FeatureUnavailable,
+            Some(WarningKind::Unspecified) | None => return Err(unknown("warning kind", warning.kind)),
+        };
+        let mut converted = warnings::Warning::new(kind, &warning.sandbox_id, warning.message);
+        converted.pid = warning.pid;
+        converted.timestamp = from_millis(warning.timestamp_ms);
+        Ok(converted)
+    }
+}
+
+fn decode_warnings(warnings: Vec<Warning>) -> Result<Vec<warnings::Warning>, String> {
+    warnings.into_iter().map(warnings::Warning::try_from).collect()
+}
+
+impl TryFrom<PauseResult> for auto_pause::PauseResult {
+    type Error = String;
+
+    fn try_from(result: PauseResult) -> Result<Self, String> {
+        Ok(Self {
+            sandbox_id: result.sandbox_id,
+            warnings: decode_warnings(result.warnings)?,
+            deadline_exceeded: result.deadline_exceeded,
+            remaining_pids: result.remaining_pids,
+            strategy: None,
+        })
+    }
+}
+
+/// Only the fields the wire message carries; the rest stay empty
+impl TryFrom<ResumeReport> for auto_pause::ResumeReport {
+    type Error = String;
+
+    fn try_from(report: ResumeReport) -> Result<Self, String> {
+        let restored = report
+            .restored
+            .into_iter()
+            .map(|p| {
+                let status = match RestoreStatus::try_from(p.status).ok() {
+                    Some(RestoreStatus::Verified) => InternalRestoreStatus::Verified,
+                    Some(RestoreStatus::Missing) => InternalRestoreStatus::Missing,
+                    Some(RestoreStatus::Replaced) => InternalRestoreStatus::Replaced,
+                    Some(RestoreStatus::Adopted) => InternalRestoreStatus::Adopted,
+                    Some(RestoreStatus::Unspecified) | None => return Err(unknown("restore status", p.status)),
+                };
+                Ok(auto_pause::RestoredProcess {
+                    pid: p.pid,
+                    name: p.name,
+                    status,
+                    adopted_pid: p.adopted_pid,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            sandbox_id: report.sandbox_id,
+            restored,
+            warnings: decode_warnings(report.warnings)?,
+            ..Default::default()
+        })
+    }
+}
+
+impl From<InternalLifecycleState> for LifecycleState {
+    fn from(state: InternalLifecycleState) -> Self {
+        match state {
+            InternalLifecycleState::Running => LifecycleState::Running,
+            InternalLifecycleState::Pausing => LifecycleState::Pausing,
+            InternalLifecycleState::Paused => LifecycleState::Paused,
+            InternalLifecycleState::Resuming => LifecycleState::Resuming,
+        }
+    }
+}
+
+impl From<InternalPriorityClass> for PriorityClass {
+    fn from(priority: InternalPriorityClass) -> Self {
+        match priority {
+            InternalPriorityClass::Low => PriorityClass::Low,
+            InternalPriorityClass::Normal => PriorityClass::Normal,
+            InternalPriorityClass::High => PriorityClass::High,
+            InternalPriorityClass::Critical => PriorityClass::Critical,
+        }
+    }
+}
+
+impl From<&state_export::StateExport> for StateExport {
+    fn from(export: &state_export::StateExport) -> Self {
+        Self {
+            generated_at_ms: millis(&export.generated_at),
+            sandboxes: export
+                .sandboxes
+                .iter()
+                .map(|sandbox| SandboxState {
+                    sandbox_id: sandbox.sandbox_id.clone(),
+                    lifecycle: LifecycleState::from(sandbox.lifecycle) as i32,
+                    priority: PriorityClass::from(sandbox.priority) as i32,
+                    process_count: sandbox.process_count as u32,
+                    running_count: sandbox.running_count as u32,
+                    last_snapshot_at_ms: sandbox.last_snapshot_at.as_ref().map(millis),
+                    active_pins: sandbox.active_pins as u32,
+                    pause_pending: sandbox.pause_pending,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<StateExport> for state_export::StateExport {
+    type Error = String;
+
+    fn try_from(export: StateExport) -> Result<Self, String> {
+        let sandboxes = export
+            .sandboxes
+            .into_iter()
+            .map(|sandbox| {
+                let lifecycle = match LifecycleState::try_from(sandbox.lifecycle).ok() {
+                    Some(LifecycleState::Running) => InternalLifecycleState::Running,
+                    Some(LifecycleState::Pausing) => InternalLifecycleState::Pausing,
+                    Some(LifecycleState::Paused) => InternalLifecycleState::Paused,
+                    Some(LifecycleState::Resuming) => InternalLifecycleState::Resuming,
+                    Some(LifecycleState::Unspecified) | None => return Err(unknown("lifecycle state", sandbox.lifecycle)),
+                };
+                let priority = match PriorityClass::try_from(sandbox.priority).ok() {
+                    Some(PriorityClass::Low) => InternalPriorityClass::Low,
+                    Some(PriorityClass::Normal) => InternalPriorityClass::Normal,
+                    Some(PriorityClass::High) => InternalPriorityClass::High,
+                    Some(PriorityClass::Critical) => InternalPriorityClass::Critical,
+                    Some(PriorityClass::Unspecified) | None => return Err(unknown("priority class", sandbox.priority)),
+                };
+                Ok(SandboxStateView {
+                    sandbox_id: sandbox.sandbox_id,
+                    lifecycle,
+                    priority,
+                    process_count: sandbox.process_count as usize,
+                    running_count: sandbox.running_count as usize,
+                    last_snapshot_at: sandbox.last_snapshot_at_ms.map(from_millis),
+                    active_pins: sandbox.active_pins as usize,
+                    pause_pending: sandbox.pause_pending,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            generated_at: from_millis(export.generated_at_ms),
+            sandboxes,
+        })
+    }
+}
+
+impl From<&[operations::OperationInfo]> for OperationList {
+    fn from(operations: &[operations::OperationInfo]) -> Self {
+        Self {
+            operations: operations
+                .iter()
+                .map(|op| OperationInfo {
+                    id: op.id,
+                    sandbox_id: op.sandbox_id.clone(),
+                    kind: match op.kind {
+                        InternalOperationKind::Pause => OperationKind::Pause,
+                        InternalOperationKind::Resume => OperationKind::Resume,
+                    } as i32,
+                    phase: op.phase.clone(),
+                    started_at_ms: millis(&op.started_at),
+                    elapsed_ms: op.elapsed_ms,
+                    last_progress_ms: op.last_progress_ms,
+                    deadline_in_ms: op.deadline_in_ms,
+                    cancelled: op.cancelled,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<OperationList> for Vec<operations::OperationInfo> {
+    type Error = String;
+
+    fn try_from(list: OperationList) -> Result<Self, String> {
+        list.operations
+            .into_iter()
+            .map(|op| {
+                let kind = match OperationKind::try_from(op.kind).ok() {
+                    Some(OperationKind::Pause) => InternalOperationKind::Pause,
+                    Some(OperationKind::Resume) => InternalOperationKind::Resume,
+                    Some(OperationKind::Unspecified) | None => return Err(unknown("operation kind", op.kind)),
+                };
+                Ok(operations::OperationInfo {
+                    id: op.id,
+                    sandbox_id: op.sandbox_id,
+                    kind,
+                    phase: op.phase,
+                    started_at: from_millis(op.started_at_ms),
+                    elapsed_ms: op.elapsed_ms,
+                    last_progress_ms: op.last_progress_ms,
+                    deadline_in_ms: op.deadline_in_ms,
+                    cancelled: op.cancelled,
+                })
+            })
+            .collect()
+    }
+}
+
+impl From<&[process::ProcessInfo]> for ProcessList {
+    fn from(processes: &[process::ProcessInfo]) -> Self {
+        Self {
+            processes: processes
+                .iter()
+                .map(|p| ProcessInfo {
+                    pid: p.pid,
+                    name: p.name.clone(),
+                    cmd: p.cmd.clone(),
+                    argv: p.argv.clone(),
+                    start_time_ms: millis(&p.start_time),
+                    state: match p.state {
+                        InternalProcessState::Running => ProcessState::Running,
+                        InternalProcessState::Suspended => ProcessState::Suspended,
+                        InternalProcessState::Terminated => ProcessState::Terminated,
+                    } as i32,
+                    thread_count: p.thread_count,
+                    child_count: p.child_count,
+                    ended_at_ms: p.ended_at.as_ref().map(millis),
+                    labels: p.labels.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<ProcessList> for Vec<process::ProcessInfo> {
+    type Error = String;
+
+    fn try_from(list: ProcessList) -> Result<Self, String> {
+        list.processes
+            .into_iter()
+            .map(|p| {
+                let state = match ProcessState::try_from(p.state).ok() {
+                    Some(ProcessState::Running) => InternalProcessState::Running,
+                    Some(ProcessState::Suspended) => InternalProcessState::Suspended,
+                    Some(ProcessState::Terminated) => InternalProcessState::Terminated,
+                    Some(ProcessState::Unspecified) | None => return Err(unknown("process state", p.state)),
+                };
+                Ok(process::ProcessInfo {
+                    pid: p.pid,
+                    name: p.name,
+                    cmd: p.cmd,
+                    argv: p.argv,
+                    start_time: from_millis(p.start_time_ms),
+                    state,
+                    thread_count: p.thread_count,
+                    child_count: p.child_count,
+                    exit: None,
+                    ended_at: p.ended_at_ms.map(from_millis),
+                    labels: p.labels,
+                    session: None,
+                })
+            })
+            .collect()
+    }
+}
+
+impl From<InternalTreeFormat> for TreeFormat {
+    fn from(format: InternalTreeFormat) -> Self {
+        match format {
+            InternalTreeFormat::Ascii => TreeFormat::Ascii,
+            InternalTreeFormat::Dot => TreeFormat::Dot,
+        }
+    }
+}
+
+impl TryFrom<i32> for InternalTreeFormat {
+    type Error = String;
+
+    fn try_from(format: i32) -> Result<Self, String> {
+        match TreeFormat::try_from(format).ok() {
+            Some(TreeFormat::Ascii) => Ok(InternalTreeFormat::Ascii),
+            Some(TreeFormat::Dot) => Ok(InternalTreeFormat::Dot),
+            Some(TreeFormat::Unspecified) | None => Err(unknown("tree format", format)),
+        }
+    }
+}
+
+impl From<&events::Event> for Event {
+    fn from(event: &events::Event) -> Self {
+        // Serialized as {"event_type": ..., "payload": ...}; the type has its own field
+        let payload = serde_json::to_value(&event.payload).ok().and_then(|mut value| value.get_mut("payload").map(Value::take));
+        Self {
+            schema_version: event.schema_version,
+            seq: event.seq,
+            timestamp_ms: millis(&event.timestamp),
+            sandbox_id: event.sandbox_id.clone(),
+            event_type: event.event_type().to_string(),
+            payload_json: payload.unwrap_or(Value::Null).to_string(),
+        }
+    }
+}
+
+/// Event types this build does not know are an error
+impl TryFrom<Event> for events::Event {
+    type Error = String;
+
+    fn try_from(event: Event) -> Result<Self, String> {
+        let payload: Value = serde_json::from_str(&event.payload_json).map_err(|e| format!("payload of event {} is not JSON: {}", event.seq, e))?;
+        let tagged = serde_json::json!({ "event_type": event.event_type, "payload": payload });
+        let payload = serde_json::from_value(tagged).map_err(|e| format!("{} event {}: {}", event.event_type, event.seq, e))?;
+        Ok(Self {
+            schema_version: event.schema_version,
+            seq: event.seq,
+            timestamp: from_millis(event.timestamp_ms),
+            sandbox_id: event.sandbox_id,
+            payload,
+        })
+    }
+}
+
+/// Snapshot in the protobuf wire format
+pub fn encode_snapshot(snapshot: &InternalSnapshot) -> Vec<u8> {
+    StateSnapshot::from(snapshot).encode_to_vec()
+}
+
+pub fn decode_snapshot(bytes: &[u8]) -> Result<InternalSnapshot, prost::DecodeError> {
+    StateSnapshot::decode(bytes).map(InternalSnapshot::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut snapshot = InternalSnapshot::new("sbx");
+        snapshot.processes.push(InternalProcess {
+            pid: 42,
+            name: "node".to_string(),
+            cmd: "node server.js".to_string(),
+            argv: vec!["node".to_string(), "server.js".to_string()],
+            start_time: from_millis(1_700_000_000_000),
+            state: "running".to_string(),
+            thread_count: 11,
+            child_count: 0,
+            ppid: None,
+            fds: Some(state_snapshot::FdSummary {
+                listening_ports: vec![3000],
+                ..Default::default()
+            }),
+            locks: Vec::new(),
+            exit: None,
+            ended_at: None,
+            labels: BTreeMap::from([("role".to_string(), "web".to_string())]),
+            session: None,
+            launch: None,
+            uid: Some(1000),
+            argv_truncated: false,
+        });
+
+        let decoded = decode_snapshot(&encode_snapshot(&snapshot)).unwrap();
+        assert_eq!(decoded.sandbox_id, snapshot.sandbox_id);
+        assert_eq!(decoded.timestamp.timestamp_millis(), snapshot.timestamp.timestamp_millis());
+        let process = &decoded.processes[0];
+        assert_eq!((process.pid, process.argv.len(), process.thread_count), (42, 2, 11));
+        assert_eq!(process.start_time, from_millis(1_700_000_000_000));
+        assert_eq!(process.fds.as_ref().unwrap().listening_ports, vec![3000]);
+        assert_eq!(process.labels["role"], "web");
+        assert_eq!(process.uid, Some(1000));
+    }
+
+    /// Pins the encoding so a renumbered field fails here before it breaks other languages
+    #[test]
+    fn test_warning_encoding_is_stable() {
+        let warning = Warning {
+            kind: WarningKind::ProcessMissing as i32,
+            sandbox_id: "s".to_string(),
+            pid: Some(7),
+            message: String::new(),
+            timestamp_ms: 1,
+        };
+        assert_eq!(warning.encode_to_vec(), vec![0x08, 0x03, 0x12, 0x01, b's', 0x18, 0x07, 0x28, 0x01]);
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        let mut event = events::Event::new("sbx", events::EventPayload::OperationFailed {
+            operation: "pause".to_string(),
+            error: "timed out".to_string(),
+        });
+        event.seq = 7;
+        let wire = Event::from(&event);
+        assert_eq!(wire.event_type, "operation_failed");
+        let payload: Value = serde_json::from_str(&wire.payload_json).unwrap();
+        assert_eq!(payload, serde_json::json!({ "operation": "pause", "error": "timed out" }));
+
+        let decoded = events::Event::try_from(wire.clone()).unwrap();
+        assert_eq!(decoded.seq, 7);
+        assert!(matches!(decoded.payload, events::EventPayload::OperationFailed { error, .. } if error == "timed out"));
+        assert!(events::Event::try_from(Event { event_type: "from_the_future".to_string(), ..wire }).is_err());
+    }
+}